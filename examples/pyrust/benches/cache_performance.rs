@@ -1,9 +1,10 @@
 use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
 use pyrust::ast::{Expression, Program, Statement};
 use pyrust::bytecode::Bytecode;
-use pyrust::cache::CompilationCache;
+use pyrust::cache::{CompilationCache, ShardedCache};
 use pyrust::compiler::compile;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 /// Helper to create a simple bytecode for testing
 fn create_bytecode(value: i64) -> Bytecode {
@@ -256,6 +257,124 @@ fn bench_realistic_workload(c: &mut Criterion) {
     group.finish();
 }
 
+/// Benchmark: Lookups from many threads, one lock vs. sharded locks
+///
+/// Each thread looks up a rotating set of programs, as daemon connections
+/// would. Times are for the whole batch, so lower means higher throughput.
+fn bench_concurrent_lookups(c: &mut Criterion) {
+    const THREADS: usize = 8;
+    const LOOKUPS: usize = 2_000;
+    const PROGRAMS: usize = 64;
+
+    let mut group = c.benchmark_group("concurrent_lookups");
+    group.sample_size(20);
+    group.measurement_time(Duration::from_secs(5));
+
+    let codes: Vec<String> = (0..PROGRAMS).map(|i| format!("x = {}", i)).collect();
+
+    // Run `lookup` for every code, LOOKUPS times per thread, and time it
+    let run = |iters: u64, lookup: &(dyn Fn(&str) + Sync)| {
+        let started = Instant::now();
+        for _ in 0..iters {
+            std::thread::scope(|scope| {
+                for thread in 0..THREADS {
+                    let codes = &codes;
+                    scope.spawn(move || {
+                        for i in 0..LOOKUPS {
+                            lookup(&codes[(thread + i) % PROGRAMS]);
+                        }
+                    });
+                }
+            });
+        }
+        started.elapsed()
+    };
+
+    let single = Mutex::new(CompilationCache::new(1000));
+    let sharded = ShardedCache::new(16, || CompilationCache::new(1000));
+    for (i, code) in codes.iter().enumerate() {
+        let bytecode = Arc::new(create_bytecode(i as i64));
+        single
+            .lock()
+            .unwrap()
+            .insert(code.clone(), Arc::clone(&bytecode));
+        sharded.insert(code.clone(), bytecode);
+    }
+
+    group.bench_function("single_mutex", |b| {
+        b.iter_custom(|iters| {
+            run(iters, &|code| {
+                black_box(single.lock().unwrap().get(code));
+            })
+        });
+    });
+    group.bench_function("sharded_16", |b| {
+        b.iter_custom(|iters| {
+            run(iters, &|code| {
+                black_box(sharded.get(code));
+            })
+        });
+    });
+
+    group.finish();
+}
+
+/// Benchmark every thread looking up the same program
+///
+/// The worst case for a locked cache: all lookups go to one lock, where the
+/// sharded cache serves them from its lock-free snapshot.
+fn bench_hot_program_contention(c: &mut Criterion) {
+    const THREADS: usize = 8;
+    const LOOKUPS: usize = 2_000;
+
+    let mut group = c.benchmark_group("hot_program_contention");
+    group.sample_size(20);
+    group.measurement_time(Duration::from_secs(5));
+
+    let code = "x = 1";
+    let run = |iters: u64, lookup: &(dyn Fn(&str) + Sync)| {
+        let started = Instant::now();
+        for _ in 0..iters {
+            std::thread::scope(|scope| {
+                for _ in 0..THREADS {
+                    scope.spawn(|| {
+                        for _ in 0..LOOKUPS {
+                            lookup(code);
+                        }
+                    });
+                }
+            });
+        }
+        started.elapsed()
+    };
+
+    let bytecode = Arc::new(create_bytecode(1));
+    let single = Mutex::new(CompilationCache::new(1000));
+    single
+        .lock()
+        .unwrap()
+        .insert(code.to_string(), Arc::clone(&bytecode));
+    let sharded = ShardedCache::new(16, || CompilationCache::new(1000));
+    sharded.insert(code.to_string(), bytecode);
+
+    group.bench_function("single_mutex", |b| {
+        b.iter_custom(|iters| {
+            run(iters, &|code| {
+                black_box(single.lock().unwrap().get(code));
+            })
+        });
+    });
+    group.bench_function("sharded_lock_free", |b| {
+        b.iter_custom(|iters| {
+            run(iters, &|code| {
+                black_box(sharded.get(code));
+            })
+        });
+    });
+
+    group.finish();
+}
+
 // Configure Criterion with sample_size(1000) and measurement_time(10s) to reduce CV below 10% threshold
 criterion_group! {
     name = benches;
@@ -272,7 +391,9 @@ criterion_group! {
         bench_hash_computation,
         bench_insert_performance,
         bench_stats_computation,
-        bench_realistic_workload
+        bench_realistic_workload,
+        bench_concurrent_lookups,
+        bench_hot_program_contention
 }
 
 criterion_main!(benches);
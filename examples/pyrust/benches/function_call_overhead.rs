@@ -1,5 +1,5 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use pyrust::execute_python;
+use pyrust::{compiler, execute_python, lexer, parser, vm::VM};
 
 /// Benchmark direct arithmetic: 10 + 20
 /// This is the baseline for measuring function call overhead
@@ -203,6 +203,25 @@ fn recursive_function_call(c: &mut Criterion) {
     });
 }
 
+/// Benchmark calls on one reused VM with precompiled bytecode
+/// Isolates call/return cost from compilation and VM construction
+fn reused_vm_calls(c: &mut Criterion) {
+    let tokens = lexer::lex(
+        "def double(x):\n    return x * 2\ndef quad(x):\n    return double(double(x))\nquad(1)\nquad(2)\nquad(3)\nquad(4)",
+    )
+    .unwrap();
+    let ast = parser::parse(tokens).unwrap();
+    let bytecode = compiler::compile(&ast).unwrap();
+    let mut vm = VM::new();
+
+    c.bench_function("reused_vm_calls", |b| {
+        b.iter(|| {
+            vm.reset_execution_state();
+            black_box(vm.execute(black_box(&bytecode)))
+        });
+    });
+}
+
 // Configure Criterion with sample_size(1000) and measurement_time(10s) to reduce CV below 10% threshold
 criterion_group! {
     name = benches;
@@ -225,7 +244,8 @@ criterion_group! {
         simple_return_overhead,
         function_definition_only,
         function_call_in_assignment,
-        recursive_function_call
+        recursive_function_call,
+        reused_vm_calls
 }
 
 // Separate group for function_with_all_operators with measurement_time(15s)
@@ -2,6 +2,12 @@
 //!
 //! Pure data structures optimized for arena allocation.
 //! Represents the parsed structure of Python-like source code.
+//! Rewrites over these nodes live in [`simplify`]; [`Program::pretty`]
+//! prints them as an indented tree.
+
+pub mod simplify;
+
+use std::fmt::Write;
 
 /// Root AST node containing a list of statements
 #[derive(Debug, Clone, PartialEq)]
@@ -9,11 +15,54 @@ pub struct Program {
     pub statements: Vec<Statement>,
 }
 
+/// Position of a token in the source text
+///
+/// Line and column are 1-indexed, matching [`crate::lexer::Token`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SourcePos {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Source extent of an expression, mirroring the expression's shape
+///
+/// `end` is exclusive and parentheses around the whole expression are not
+/// included. `children` follow the node's operands in order: left then right
+/// for a binary operation, the operand of a unary one, or a call's arguments.
+/// Literals and variables have no children.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExprSpan {
+    pub start: SourcePos,
+    pub end: SourcePos,
+    pub children: Vec<ExprSpan>,
+}
+
+/// Where a statement starts, plus the positions of its body statements
+///
+/// [`crate::parser::parse_with_positions`] returns one entry per statement of
+/// the [`Program`], in the same order; `body` mirrors a function's body the
+/// same way and is empty for every other statement. `value` is the span of
+/// the statement's expression (the assigned, printed, or returned value), if
+/// it has one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatementPos {
+    pub pos: SourcePos,
+    pub body: Vec<StatementPos>,
+    pub value: Option<ExprSpan>,
+}
+
 /// Statement variants in the language
 #[derive(Debug, Clone, PartialEq)]
 pub enum Statement {
     /// Variable assignment: `name = expression`
     Assignment { name: String, value: Expression },
+    /// Chained assignment: `a = b = expression`
+    ///
+    /// The value is evaluated once and stored into each target, left to right.
+    MultiAssignment {
+        targets: Vec<String>,
+        value: Expression,
+    },
     /// Print statement: `print(expression)`
     Print { value: Expression },
     /// Expression statement: standalone expression
@@ -92,6 +141,18 @@ impl BinaryOperator {
             | BinaryOperator::Mod => 2,
         }
     }
+
+    /// Returns the operator's source symbol
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOperator::Add => "+",
+            BinaryOperator::Sub => "-",
+            BinaryOperator::Mul => "*",
+            BinaryOperator::Div => "/",
+            BinaryOperator::FloorDiv => "//",
+            BinaryOperator::Mod => "%",
+        }
+    }
 }
 
 /// Unary operators for future extensions
@@ -107,6 +168,104 @@ pub enum UnaryOperator {
     Pos,
 }
 
+impl UnaryOperator {
+    /// Returns the operator's source symbol
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            UnaryOperator::Neg => "-",
+            UnaryOperator::Pos => "+",
+        }
+    }
+}
+
+impl Program {
+    /// The tree as indented text, one node per line
+    ///
+    /// Each node is named after its variant, followed by what it holds
+    /// besides child nodes, with its children indented below it. Operands
+    /// are shown exactly as parsed, so the nesting of binary operations
+    /// shows how precedence grouped them.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let tokens = pyrust::lexer::lex("1 + 2 * 3").unwrap();
+    /// let program = pyrust::parser::parse(tokens).unwrap();
+    /// assert_eq!(
+    ///     program.pretty(),
+    ///     "Program\n  Expression\n    BinaryOp +\n      Integer 1\n      BinaryOp *\n        Integer 2\n        Integer 3\n"
+    /// );
+    /// ```
+    pub fn pretty(&self) -> String {
+        let mut out = String::from("Program\n");
+        for statement in &self.statements {
+            write_statement(&mut out, statement, 1);
+        }
+        out
+    }
+}
+
+fn write_statement(out: &mut String, statement: &Statement, depth: usize) {
+    let indent = "  ".repeat(depth);
+    match statement {
+        Statement::Assignment { name, value } => {
+            let _ = writeln!(out, "{}Assignment {}", indent, name);
+            write_expression(out, value, depth + 1);
+        }
+        Statement::MultiAssignment { targets, value } => {
+            let _ = writeln!(out, "{}MultiAssignment {}", indent, targets.join(", "));
+            write_expression(out, value, depth + 1);
+        }
+        Statement::Print { value } => {
+            let _ = writeln!(out, "{}Print", indent);
+            write_expression(out, value, depth + 1);
+        }
+        Statement::Expression { value } => {
+            let _ = writeln!(out, "{}Expression", indent);
+            write_expression(out, value, depth + 1);
+        }
+        Statement::FunctionDef { name, params, body } => {
+            let _ = writeln!(out, "{}FunctionDef {}({})", indent, name, params.join(", "));
+            for statement in body {
+                write_statement(out, statement, depth + 1);
+            }
+        }
+        Statement::Return { value } => {
+            let _ = writeln!(out, "{}Return", indent);
+            if let Some(value) = value {
+                write_expression(out, value, depth + 1);
+            }
+        }
+    }
+}
+
+fn write_expression(out: &mut String, expression: &Expression, depth: usize) {
+    let indent = "  ".repeat(depth);
+    match expression {
+        Expression::Integer(value) => {
+            let _ = writeln!(out, "{}Integer {}", indent, value);
+        }
+        Expression::Variable(name) => {
+            let _ = writeln!(out, "{}Variable {}", indent, name);
+        }
+        Expression::BinaryOp { left, op, right } => {
+            let _ = writeln!(out, "{}BinaryOp {}", indent, op.symbol());
+            write_expression(out, left, depth + 1);
+            write_expression(out, right, depth + 1);
+        }
+        Expression::UnaryOp { op, operand } => {
+            let _ = writeln!(out, "{}UnaryOp {}", indent, op.symbol());
+            write_expression(out, operand, depth + 1);
+        }
+        Expression::Call { name, args } => {
+            let _ = writeln!(out, "{}Call {}", indent, name);
+            for arg in args {
+                write_expression(out, arg, depth + 1);
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -976,4 +1135,38 @@ mod tests {
         let cloned = call.clone();
         assert_eq!(call, cloned);
     }
+
+    #[test]
+    fn test_operator_symbols() {
+        assert_eq!(BinaryOperator::Add.symbol(), "+");
+        assert_eq!(BinaryOperator::FloorDiv.symbol(), "//");
+        assert_eq!(BinaryOperator::Mod.symbol(), "%");
+        assert_eq!(UnaryOperator::Neg.symbol(), "-");
+        assert_eq!(UnaryOperator::Pos.symbol(), "+");
+    }
+
+    #[test]
+    fn test_pretty_prints_every_node() {
+        let code =
+            "def f(a, b):\n    return -a // b\nx = y = f(7, 2)\nprint(x)\ndef g():\n    return\n";
+        let program = crate::parser::parse(crate::lexer::lex(code).unwrap()).unwrap();
+        let expected = "\
+Program
+  FunctionDef f(a, b)
+    Return
+      BinaryOp //
+        UnaryOp -
+          Variable a
+        Variable b
+  MultiAssignment x, y
+    Call f
+      Integer 7
+      Integer 2
+  Print
+    Variable x
+  FunctionDef g()
+    Return
+";
+        assert_eq!(program.pretty(), expected);
+    }
 }
@@ -0,0 +1,259 @@
+//! Expression rewrites shared by the compiler and source tooling
+//!
+//! - [`constant_value`] evaluates an expression built from literals (and,
+//!   optionally, variables with known values) using the VM's own arithmetic.
+//!   The compiler's constant folding and the division-by-zero lint both use
+//!   it, so they agree with each other and with the runtime.
+//! - [`simplify`] folds every literal subexpression and removes double
+//!   negations, for tools that display or compare expressions.
+//! - [`to_source`] prints an expression with only the parentheses its
+//!   structure needs, so differently parenthesized inputs print the same.
+//!
+//! Folding never hides an error: a subexpression that would raise at runtime,
+//! such as `1 / 0`, is left as it is. Removing a double negation does not
+//! have that guarantee, since `-(-x)` raises when `x` is `None` or the
+//! smallest integer while `x` does not. That is why the compiler only folds.
+//!
+//! # Example
+//!
+//! ```
+//! use pyrust::ast::simplify::{simplify, to_source};
+//! use pyrust::{lexer, parser};
+//!
+//! let program = parser::parse(lexer::lex("-(-(x)) * ((2 + 3) * y)").unwrap()).unwrap();
+//! let pyrust::ast::Statement::Expression { value } = &program.statements[0] else {
+//!     unreachable!()
+//! };
+//!
+//! assert_eq!(to_source(value), "--x * ((2 + 3) * y)");
+//! assert_eq!(to_source(&simplify(value)), "x * (5 * y)");
+//! ```
+
+use super::{BinaryOperator, Expression, UnaryOperator};
+use crate::value::Value;
+
+/// Value of `expr` if it is built only from integer literals and variables
+/// that `lookup` knows, and evaluates without raising
+pub fn constant_value(expr: &Expression, lookup: &dyn Fn(&str) -> Option<i64>) -> Option<i64> {
+    let value = match expr {
+        Expression::Integer(value) => return Some(*value),
+        Expression::Variable(name) => return lookup(name),
+        Expression::BinaryOp { left, op, right } => {
+            let left = Value::Integer(constant_value(left, lookup)?);
+            let right = Value::Integer(constant_value(right, lookup)?);
+            left.binary_op(*op, &right).ok()?
+        }
+        Expression::UnaryOp { op, operand } => Value::Integer(constant_value(operand, lookup)?)
+            .unary_op(*op)
+            .ok()?,
+        Expression::Call { .. } => return None,
+    };
+    match value {
+        Value::Integer(value) => Some(value),
+        _ => None,
+    }
+}
+
+/// Whether `op` raises when its right operand is zero
+pub fn is_division(op: BinaryOperator) -> bool {
+    matches!(
+        op,
+        BinaryOperator::Div | BinaryOperator::FloorDiv | BinaryOperator::Mod
+    )
+}
+
+/// Value of `expr` if it is built only from integer literals
+pub fn literal_value(expr: &Expression) -> Option<i64> {
+    constant_value(expr, &|_| None)
+}
+
+/// Fold literal subexpressions and remove double negations
+///
+/// `-(-x)` becomes `x`, and `2 * 3 + y` becomes `6 + y`. See the module
+/// docs for when the result can behave differently from `expr`.
+pub fn simplify(expr: &Expression) -> Expression {
+    if let Some(value) = literal_value(expr) {
+        return Expression::Integer(value);
+    }
+    match expr {
+        Expression::Integer(_) | Expression::Variable(_) => expr.clone(),
+        Expression::BinaryOp { left, op, right } => Expression::BinaryOp {
+            left: Box::new(simplify(left)),
+            op: *op,
+            right: Box::new(simplify(right)),
+        },
+        Expression::UnaryOp { op, operand } => match (op, simplify(operand)) {
+            (
+                UnaryOperator::Neg,
+                Expression::UnaryOp {
+                    op: UnaryOperator::Neg,
+                    operand: inner,
+                },
+            ) => *inner,
+            (op, operand) => Expression::UnaryOp {
+                op: *op,
+                operand: Box::new(operand),
+            },
+        },
+        Expression::Call { name, args } => Expression::Call {
+            name: name.clone(),
+            args: args.iter().map(simplify).collect(),
+        },
+    }
+}
+
+/// Print `expr` as source, with the fewest parentheses that keep its shape
+///
+/// Operators are surrounded by single spaces and arguments separated by
+/// `", "`. Parsing the result gives back an equivalent expression.
+pub fn to_source(expr: &Expression) -> String {
+    let mut out = String::new();
+    write_expression(expr, &mut out);
+    out
+}
+
+fn write_expression(expr: &Expression, out: &mut String) {
+    match expr {
+        // The literal for the smallest integer does not fit in an i64
+        Expression::Integer(i64::MIN) => out.push_str("(-9223372036854775807 - 1)"),
+        Expression::Integer(value) => out.push_str(&value.to_string()),
+        Expression::Variable(name) => out.push_str(name),
+        Expression::BinaryOp { left, op, right } => {
+            // Operators are left-associative, so a right operand of the same
+            // precedence needs parentheses and a left one does not
+            write_operand(left, out, |inner| inner < op.precedence());
+            out.push(' ');
+            out.push_str(op.symbol());
+            out.push(' ');
+            write_operand(right, out, |inner| inner <= op.precedence());
+        }
+        Expression::UnaryOp { op, operand } => {
+            out.push_str(op.symbol());
+            // A unary operator applies to a single primary, so any binary
+            // operand, or a negative literal that would merge with the sign,
+            // needs parentheses
+            let parenthesize = match operand.as_ref() {
+                Expression::BinaryOp { .. } => true,
+                Expression::Integer(value) => *value < 0,
+                _ => false,
+            };
+            write_wrapped(operand, out, parenthesize);
+        }
+        Expression::Call { name, args } => {
+            out.push_str(name);
+            out.push('(');
+            for (index, arg) in args.iter().enumerate() {
+                if index > 0 {
+                    out.push_str(", ");
+                }
+                write_expression(arg, out);
+            }
+            out.push(')');
+        }
+    }
+}
+
+/// Write a binary operand, parenthesized if `needs_parens` holds for the
+/// precedence of its own operator
+fn write_operand(expr: &Expression, out: &mut String, needs_parens: impl Fn(u8) -> bool) {
+    let parenthesize = match expr {
+        Expression::BinaryOp { op, .. } => needs_parens(op.precedence()),
+        _ => false,
+    };
+    write_wrapped(expr, out, parenthesize);
+}
+
+fn write_wrapped(expr: &Expression, out: &mut String, parenthesize: bool) {
+    if parenthesize {
+        out.push('(');
+        write_expression(expr, out);
+        out.push(')');
+    } else {
+        write_expression(expr, out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Statement;
+    use crate::{lexer, parser};
+
+    fn parse_expr(source: &str) -> Expression {
+        let program = parser::parse(lexer::lex(source).unwrap()).unwrap();
+        match program.statements.into_iter().next() {
+            Some(Statement::Expression { value }) => value,
+            other => panic!("expected an expression statement, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_constant_value() {
+        assert_eq!(literal_value(&parse_expr("2 * (3 + 4) - -1")), Some(15));
+        assert_eq!(literal_value(&parse_expr("1 + x")), None);
+        assert_eq!(literal_value(&parse_expr("1 / 0")), None);
+        assert_eq!(literal_value(&parse_expr("9223372036854775807 + 1")), None);
+
+        let lookup = |name: &str| (name == "x").then_some(10);
+        assert_eq!(constant_value(&parse_expr("x % 3"), &lookup), Some(1));
+        assert_eq!(constant_value(&parse_expr("x + y"), &lookup), None);
+    }
+
+    #[test]
+    fn test_simplify() {
+        let cases = [
+            ("-(-x)", "x"),
+            ("--(a + 1)", "a + 1"),
+            ("-(-(-x))", "-x"),
+            ("+(-x)", "+-x"),
+            ("f(2 * 3, -(-y)) + 10 // 3", "f(6, y) + 3"),
+            // Folding would raise, so the division stays
+            ("x + 1 / 0", "x + 1 / 0"),
+            ("(1 + 2) * x", "3 * x"),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(
+                to_source(&simplify(&parse_expr(source))),
+                expected,
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn test_to_source_uses_minimal_parentheses() {
+        let cases = [
+            ("((a + b)) + (c)", "a + b + c"),
+            ("a + (b + c)", "a + (b + c)"),
+            ("a - (b * c)", "a - b * c"),
+            ("(a - b) * c", "(a - b) * c"),
+            ("a // (b % c)", "a // (b % c)"),
+            ("-(a + b) * -c", "-(a + b) * -c"),
+            ("f((a), (b + 1) * 2)", "f(a, (b + 1) * 2)"),
+        ];
+        for (source, expected) in cases {
+            let expr = parse_expr(source);
+            let printed = to_source(&expr);
+            assert_eq!(printed, expected, "{}", source);
+            assert_eq!(parse_expr(&printed), expr, "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_to_source_of_folded_negative_literals() {
+        assert_eq!(to_source(&simplify(&parse_expr("-(2 + 3)"))), "-5");
+        let negated = Expression::UnaryOp {
+            op: UnaryOperator::Neg,
+            operand: Box::new(Expression::Integer(-5)),
+        };
+        assert_eq!(to_source(&negated), "-(-5)");
+
+        let smallest = simplify(&parse_expr("-9223372036854775807 - 1"));
+        assert_eq!(smallest, Expression::Integer(i64::MIN));
+        assert_eq!(
+            literal_value(&parse_expr(&to_source(&smallest))),
+            Some(i64::MIN)
+        );
+    }
+}
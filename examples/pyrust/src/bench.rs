@@ -0,0 +1,168 @@
+//! Repeated timed runs of a program, for `pyrust --bench`
+//!
+//! [`run`] compiles and executes a program a given number of times, timing
+//! the two phases of every run separately, and sums the timings up in a
+//! [`BenchReport`] by their minimum, median and 95th percentile. Each run
+//! compiles the source from scratch, as a first run does. A warmed benchmark
+//! instead compiles the program into the global cache before timing, so that
+//! every run looks it up there, as repeated daemon requests do.
+//!
+//! # Example
+//!
+//! ```
+//! let report = pyrust::bench::run("x = 6\nx * 7", 5, false).unwrap();
+//! assert_eq!(report.runs, 5);
+//! assert!(report.total.min <= report.total.p95);
+//! print!("{}", report.format_table());
+//! ```
+
+use crate::error::PyRustError;
+use std::fmt::Write;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Minimum, median and 95th percentile of one phase's timings
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub min: Duration,
+    pub median: Duration,
+    pub p95: Duration,
+}
+
+impl Summary {
+    /// Summarize `samples` with nearest-rank percentiles, all zero if empty
+    pub fn of(samples: &[Duration]) -> Self {
+        let mut sorted = samples.to_vec();
+        sorted.sort_unstable();
+        let Some(&min) = sorted.first() else {
+            return Self::default();
+        };
+        let rank = |percent: usize| sorted[(sorted.len() * percent).div_ceil(100) - 1];
+        Self {
+            min,
+            median: rank(50),
+            p95: rank(95),
+        }
+    }
+}
+
+/// Timings of a benchmark, split into compiling and executing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BenchReport {
+    /// Number of timed runs
+    pub runs: usize,
+    /// Whether the program was cached before the timed runs
+    pub warmed: bool,
+    /// Lexing, parsing and compiling, or only the cache lookup when warmed
+    pub compile: Summary,
+    /// Running the bytecode on a fresh VM and formatting its output
+    pub execute: Summary,
+    /// Both phases of each run
+    pub total: Summary,
+}
+
+impl BenchReport {
+    /// Format as a table with one row per phase, each time in the unit that
+    /// suits it (`ns`, `µs`, `ms` or `s`)
+    pub fn format_table(&self) -> String {
+        let mut out = String::new();
+        let how = if self.warmed {
+            "cached before timing"
+        } else {
+            "compiled every run"
+        };
+        let _ = writeln!(out, "{} runs, {}", self.runs, how);
+        let _ = writeln!(out, "{:<9}{:>12}{:>12}{:>12}", "", "min", "median", "p95");
+        for (phase, summary) in [
+            ("compile", self.compile),
+            ("execute", self.execute),
+            ("total", self.total),
+        ] {
+            let _ = writeln!(
+                out,
+                "{:<9}{:>12}{:>12}{:>12}",
+                phase,
+                format!("{:.1?}", summary.min),
+                format!("{:.1?}", summary.median),
+                format!("{:.1?}", summary.p95)
+            );
+        }
+        out
+    }
+}
+
+/// Compile and execute `code` `runs` times, timing each run
+///
+/// With `warm`, the program is compiled into the global cache and run once
+/// before the timed runs, which then take it from the cache. Every run gets
+/// a fresh VM, created outside the timings; print output is discarded.
+/// Fails with the first error a run raises.
+pub fn run(code: &str, runs: usize, warm: bool) -> Result<BenchReport, PyRustError> {
+    if warm {
+        let (bytecode, _) = crate::compile_cached_global(code)?;
+        crate::execute_compiled_on(&mut crate::new_vm(), &bytecode, code)?;
+    }
+
+    let mut compile = Vec::with_capacity(runs);
+    let mut execute = Vec::with_capacity(runs);
+    let mut total = Vec::with_capacity(runs);
+    for _ in 0..runs {
+        let mut vm = crate::new_vm();
+        let started = Instant::now();
+        let bytecode = if warm {
+            crate::compile_cached_global(code)?.0
+        } else {
+            Arc::new(crate::compile_python(code)?)
+        };
+        let compiled = Instant::now();
+        crate::execute_compiled_on(&mut vm, &bytecode, code)?;
+        let finished = Instant::now();
+        compile.push(compiled - started);
+        execute.push(finished - compiled);
+        total.push(finished - started);
+    }
+
+    Ok(BenchReport {
+        runs,
+        warmed: warm,
+        compile: Summary::of(&compile),
+        execute: Summary::of(&execute),
+        total: Summary::of(&total),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_uses_nearest_rank() {
+        let samples: Vec<Duration> = (1..=20).rev().map(Duration::from_millis).collect();
+        let summary = Summary::of(&samples);
+        assert_eq!(summary.min, Duration::from_millis(1));
+        assert_eq!(summary.median, Duration::from_millis(10));
+        assert_eq!(summary.p95, Duration::from_millis(19));
+
+        let single = Summary::of(&[Duration::from_millis(3)]);
+        assert_eq!(single.median, Duration::from_millis(3));
+        assert_eq!(single.p95, Duration::from_millis(3));
+        assert_eq!(Summary::of(&[]), Summary::default());
+    }
+
+    #[test]
+    fn test_run_times_every_run() {
+        let report = run("def f(n):\n    return n * 2\nprint(f(21))", 7, true).unwrap();
+        assert_eq!(report.runs, 7);
+        assert!(report.warmed);
+        for summary in [report.compile, report.execute, report.total] {
+            assert!(summary.min <= summary.median && summary.median <= summary.p95);
+        }
+        assert!(report.total.min >= report.execute.min);
+
+        let table = report.format_table();
+        assert!(table.starts_with("7 runs, cached before timing\n"));
+        assert!(table.contains("\ncompile "));
+
+        assert!(run("1 / 0", 3, false).is_err());
+    }
+}
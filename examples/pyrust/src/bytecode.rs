@@ -3,7 +3,7 @@
 //! Defines compact bytecode instruction format with 8 instruction types.
 //! Target: 8-16 bytes per instruction.
 
-use crate::ast::{BinaryOperator, UnaryOperator};
+use crate::ast::{BinaryOperator, SourcePos, UnaryOperator};
 
 /// Compact bytecode instruction for register-based VM
 #[derive(Debug, Clone, PartialEq)]
@@ -56,18 +56,15 @@ pub enum Instruction {
     /// Halt execution
     Halt,
 
-    /// Define a function
-    /// Args: name_index, param_count, body_start, body_len, max_register_used
-    DefineFunction {
-        name_index: usize,
-        param_count: u8,
-        body_start: usize,
-        body_len: usize,
-        max_register_used: u8,
-    },
+    /// Define a function, making calls by its name run the given chunk
+    /// Args: chunk_index (into [`Bytecode::functions`])
+    DefineFunction { chunk_index: usize },
 
     /// Call a function
     /// Args: name_index, arg_count, first_arg_reg, dest_reg
+    ///
+    /// Argument `i` is copied from `first_arg_reg + i` into register `i` of
+    /// the callee's window, which is where the body reads parameter `i`.
     Call {
         name_index: usize,
         arg_count: u8,
@@ -81,8 +78,31 @@ pub enum Instruction {
         has_value: bool,
         src_reg: Option<u8>,
     },
+
+    /// Unconditionally continue execution at target
+    /// Args: target (instruction index within the current chunk)
+    Jump { target: usize },
+
+    /// Jump to target if the condition register is falsy
+    /// Args: cond_reg, target (instruction index within the current chunk)
+    JumpIfFalse { cond_reg: u8, target: usize },
+
+    /// Jump to target if the condition register is truthy
+    /// Args: cond_reg, target (instruction index within the current chunk)
+    JumpIfTrue { cond_reg: u8, target: usize },
+
+    /// Copy a register: dest_reg = src_reg
+    /// Args: dest_reg, src_reg
+    Move { dest_reg: u8, src_reg: u8 },
 }
 
+/// Forward-declarable jump destination created by [`BytecodeBuilder::new_label`]
+///
+/// Jumps may be emitted to a label before it is bound; their targets are
+/// patched when [`BytecodeBuilder::bind_label`] is called.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Label(usize);
+
 /// Compiler metadata tracking register usage
 #[derive(Debug, Clone, PartialEq)]
 pub struct CompilerMetadata {
@@ -90,16 +110,64 @@ pub struct CompilerMetadata {
     pub max_register_used: u8,
 }
 
+/// Line table entry: instructions from `start` onward come from `pos`
+///
+/// An entry covers every instruction up to the next entry's `start`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineTableEntry {
+    /// Index of the first instruction covered, within its chunk
+    pub start: usize,
+    /// Source position of the expression or statement those instructions were
+    /// compiled from
+    pub pos: SourcePos,
+    /// Where that expression ends (exclusive); equal to `pos` when only its
+    /// start is known
+    pub end: SourcePos,
+}
+
+/// A function body, compiled as its own unit of code
+///
+/// Jump targets and line table entries inside a chunk are relative to the
+/// chunk's first instruction, so chunks can be built, verified, and moved
+/// independently of each other and of the main code.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionChunk {
+    /// Index of the function's name in [`Bytecode::var_names`]
+    pub name_index: usize,
+    /// Number of parameters, passed in registers `0..param_count`
+    pub param_count: u8,
+    /// Highest register the body uses
+    pub max_register_used: u8,
+    /// The body, ending in a `Return` on every path
+    pub instructions: Vec<Instruction>,
+    /// Source positions of the body, sorted by `start`
+    pub line_table: Vec<LineTableEntry>,
+}
+
 /// Complete bytecode program with constant and variable pools
+///
+/// The main code is `instructions`; each function body is a separate
+/// [`FunctionChunk`]. Where a single number has to identify an instruction
+/// (the VM's instruction pointer, error locations, breakpoints), it is a
+/// *code address*: the main code is numbered from 0, followed by each chunk
+/// in the order of `functions`.
+///
+/// Compilation is deterministic: both pools are filled in order of first use
+/// and the compiler's interned IDs follow the same order, so compiling the
+/// same source with the same options always yields identical bytecode (and
+/// identical `.pybc` bytes).
 #[derive(Debug, Clone, PartialEq)]
 pub struct Bytecode {
-    /// Instruction sequence
+    /// Main code, run from its first instruction
     pub instructions: Vec<Instruction>,
 
-    /// Constant pool for integer literals
+    /// Function bodies, referenced by `DefineFunction`
+    pub functions: Vec<FunctionChunk>,
+
+    /// Constant pool for integer literals, in order of first use
     pub constants: Vec<i64>,
 
-    /// Variable name pool for identifiers
+    /// Variable name pool for identifiers, in order of first use
     pub var_names: Vec<String>,
 
     /// Variable ID pool parallel to var_names for interned IDs
@@ -107,6 +175,223 @@ pub struct Bytecode {
 
     /// Compiler metadata
     pub metadata: CompilerMetadata,
+
+    /// Source positions of the main code, sorted by `start`; empty when
+    /// positions are unknown
+    pub line_table: Vec<LineTableEntry>,
+}
+
+impl Bytecode {
+    /// Number of instructions in the main code and every chunk together
+    pub fn code_len(&self) -> usize {
+        self.instructions.len()
+            + self
+                .functions
+                .iter()
+                .map(|chunk| chunk.instructions.len())
+                .sum::<usize>()
+    }
+
+    /// Approximate number of bytes the program occupies in memory
+    ///
+    /// Counts the struct itself and the allocated capacity of every pool,
+    /// chunk and line table, but not allocator overhead.
+    pub fn memory_footprint(&self) -> usize {
+        use std::mem::size_of;
+        let code = |instructions: &Vec<Instruction>, line_table: &Vec<LineTableEntry>| {
+            instructions.capacity() * size_of::<Instruction>()
+                + line_table.capacity() * size_of::<LineTableEntry>()
+        };
+        size_of::<Self>()
+            + code(&self.instructions, &self.line_table)
+            + self.functions.capacity() * size_of::<FunctionChunk>()
+            + self
+                .functions
+                .iter()
+                .map(|chunk| code(&chunk.instructions, &chunk.line_table))
+                .sum::<usize>()
+            + self.constants.capacity() * size_of::<i64>()
+            + self.var_names.capacity() * size_of::<String>()
+            + self.var_names.iter().map(String::capacity).sum::<usize>()
+            + self.var_ids.capacity() * size_of::<u32>()
+    }
+
+    /// Code address of the first instruction of `functions[index]`
+    pub fn chunk_start(&self, index: usize) -> usize {
+        self.instructions.len()
+            + self.functions[..index]
+                .iter()
+                .map(|chunk| chunk.instructions.len())
+                .sum::<usize>()
+    }
+
+    /// Chunk holding code address `address`, and the offset within it
+    ///
+    /// The chunk is `None` for the main code and `Some(index)` for
+    /// `functions[index]`. Returns None past the end of the code.
+    pub fn locate(&self, address: usize) -> Option<(Option<usize>, usize)> {
+        if address < self.instructions.len() {
+            return Some((None, address));
+        }
+        let mut offset = address - self.instructions.len();
+        for (index, chunk) in self.functions.iter().enumerate() {
+            if offset < chunk.instructions.len() {
+                return Some((Some(index), offset));
+            }
+            offset -= chunk.instructions.len();
+        }
+        None
+    }
+
+    /// Name of the function whose body holds code address `address`
+    ///
+    /// Returns None in the main code and past the end of the code.
+    pub fn function_name(&self, address: usize) -> Option<&str> {
+        let (chunk, _) = self.locate(address)?;
+        let chunk = &self.functions[chunk?];
+        self.var_names.get(chunk.name_index).map(String::as_str)
+    }
+
+    /// Instruction at code address `address`
+    pub fn instruction(&self, address: usize) -> Option<&Instruction> {
+        let (chunk, offset) = self.locate(address)?;
+        self.chunk_code(chunk).get(offset)
+    }
+
+    /// Every instruction in code address order
+    pub fn code(&self) -> impl Iterator<Item = &Instruction> {
+        self.instructions.iter().chain(
+            self.functions
+                .iter()
+                .flat_map(|chunk| chunk.instructions.iter()),
+        )
+    }
+
+    /// Instructions of the main code (`None`) or of a function chunk
+    ///
+    /// A chunk index past the end yields no instructions.
+    pub fn chunk_code(&self, chunk: Option<usize>) -> &[Instruction] {
+        match chunk {
+            None => &self.instructions,
+            Some(index) => self
+                .functions
+                .get(index)
+                .map_or(&[], |chunk| &chunk.instructions),
+        }
+    }
+
+    /// Source position of the expression that produced the instruction at
+    /// code address `address`
+    ///
+    /// Returns None for instructions emitted before any position was recorded
+    /// (such as function definitions) or when the bytecode has no line table.
+    pub fn source_position(&self, address: usize) -> Option<SourcePos> {
+        self.source_span(address).map(|(pos, _)| pos)
+    }
+
+    /// Start and exclusive end of the expression that produced the
+    /// instruction at code address `address`
+    ///
+    /// Both are the same position when only the start was recorded; see
+    /// [`Bytecode::source_position`].
+    pub fn source_span(&self, address: usize) -> Option<(SourcePos, SourcePos)> {
+        let (chunk, offset) = self.locate(address)?;
+        let line_table = match chunk {
+            None => &self.line_table,
+            Some(index) => &self.functions[index].line_table,
+        };
+        let covering = line_table.partition_point(|entry| entry.start <= offset);
+        covering
+            .checked_sub(1)
+            .map(|entry| (line_table[entry].pos, line_table[entry].end))
+    }
+
+    /// Render the instruction at code address `address` with pool operands
+    /// resolved
+    ///
+    /// Constants and names are looked up in the pools, so the text reads like
+    /// `LoadVar r1, x` rather than raw indices. Out-of-range pool indices are
+    /// shown as `?`.
+    pub fn describe_instruction(&self, address: usize) -> String {
+        let name = |i: usize| self.var_names.get(i).map_or("?", |n| n.as_str());
+        let Some(instruction) = self.instruction(address) else {
+            return "<out of bounds>".to_string();
+        };
+
+        match instruction {
+            Instruction::LoadConst {
+                dest_reg,
+                const_index,
+            } => match self.constants.get(*const_index) {
+                Some(value) => format!("LoadConst r{}, {}", dest_reg, value),
+                None => format!("LoadConst r{}, ?", dest_reg),
+            },
+            Instruction::LoadVar {
+                dest_reg,
+                var_name_index,
+                ..
+            } => format!("LoadVar r{}, {}", dest_reg, name(*var_name_index)),
+            Instruction::StoreVar {
+                var_name_index,
+                src_reg,
+                ..
+            } => format!("StoreVar {}, r{}", name(*var_name_index), src_reg),
+            Instruction::BinaryOp {
+                dest_reg,
+                left_reg,
+                op,
+                right_reg,
+            } => format!(
+                "BinaryOp r{}, r{} {} r{}",
+                dest_reg,
+                left_reg,
+                op.symbol(),
+                right_reg
+            ),
+            Instruction::UnaryOp {
+                dest_reg,
+                op,
+                operand_reg,
+            } => format!("UnaryOp r{}, {}r{}", dest_reg, op.symbol(), operand_reg),
+            Instruction::Print { src_reg } => format!("Print r{}", src_reg),
+            Instruction::SetResult { src_reg } => format!("SetResult r{}", src_reg),
+            Instruction::Halt => "Halt".to_string(),
+            Instruction::DefineFunction { chunk_index } => match self.functions.get(*chunk_index) {
+                Some(chunk) => format!(
+                    "DefineFunction {}/{}, chunk {}",
+                    name(chunk.name_index),
+                    chunk.param_count,
+                    chunk_index
+                ),
+                None => format!("DefineFunction ?, chunk {}", chunk_index),
+            },
+            Instruction::Call {
+                name_index,
+                arg_count,
+                first_arg_reg,
+                dest_reg,
+            } => format!(
+                "Call r{}, {}({} args from r{})",
+                dest_reg,
+                name(*name_index),
+                arg_count,
+                first_arg_reg
+            ),
+            Instruction::Return {
+                src_reg: Some(src_reg),
+                ..
+            } => format!("Return r{}", src_reg),
+            Instruction::Return { src_reg: None, .. } => "Return".to_string(),
+            Instruction::Jump { target } => format!("Jump {}", target),
+            Instruction::JumpIfFalse { cond_reg, target } => {
+                format!("JumpIfFalse r{}, {}", cond_reg, target)
+            }
+            Instruction::JumpIfTrue { cond_reg, target } => {
+                format!("JumpIfTrue r{}, {}", cond_reg, target)
+            }
+            Instruction::Move { dest_reg, src_reg } => format!("Move r{}, r{}", dest_reg, src_reg),
+        }
+    }
 }
 
 /// Builder for constructing bytecode with automatic pooling
@@ -115,17 +400,22 @@ pub struct BytecodeBuilder {
     constants: Vec<i64>,
     var_names: Vec<String>,
     var_ids: Vec<u32>,
+    /// Function chunks declared so far
+    functions: Vec<FunctionChunk>,
+    /// Chunk being emitted into, with the main code set aside until it ends
+    current_function: Option<(usize, Vec<Instruction>, Vec<LineTableEntry>)>,
+    /// Bound position of each label within its chunk (None until bound)
+    labels: Vec<Option<usize>>,
+    /// Jumps awaiting a label binding: (instruction index in builder, label)
+    pending_jumps: Vec<(usize, Label)>,
+    /// Source positions recorded with [`BytecodeBuilder::set_position`]
+    line_table: Vec<LineTableEntry>,
 }
 
 impl BytecodeBuilder {
     /// Create a new bytecode builder
     pub fn new() -> Self {
-        Self {
-            instructions: Vec::new(),
-            constants: Vec::new(),
-            var_names: Vec::new(),
-            var_ids: Vec::new(),
-        }
+        Self::with_pools(Vec::new(), Vec::new(), Vec::new())
     }
 
     /// Add or reuse a constant in the pool, returning its index
@@ -153,12 +443,6 @@ impl BytecodeBuilder {
         index
     }
 
-    /// Ensure a variable name is in the pool (for parameter registration)
-    /// Returns the index of the var_name in the pool
-    pub fn ensure_var_name(&mut self, name: &str, var_id: u32) -> usize {
-        self.add_var_name(name, var_id)
-    }
-
     /// Emit LoadConst instruction
     pub fn emit_load_const(&mut self, dest_reg: u8, value: i64) {
         let const_index = self.add_constant(value);
@@ -204,6 +488,12 @@ impl BytecodeBuilder {
         });
     }
 
+    /// Emit Move instruction
+    pub fn emit_move(&mut self, dest_reg: u8, src_reg: u8) {
+        self.instructions
+            .push(Instruction::Move { dest_reg, src_reg });
+    }
+
     /// Emit UnaryOp instruction
     pub fn emit_unary_op(&mut self, dest_reg: u8, op: UnaryOperator, operand_reg: u8) {
         self.instructions.push(Instruction::UnaryOp {
@@ -223,24 +513,24 @@ impl BytecodeBuilder {
         self.instructions.push(Instruction::SetResult { src_reg });
     }
 
-    /// Emit DefineFunction instruction
-    pub fn emit_define_function(
-        &mut self,
-        name: &str,
-        var_id: u32,
-        param_count: u8,
-        body_start: usize,
-        body_len: usize,
-        max_register_used: u8,
-    ) {
+    /// Emit DefineFunction for a new, empty function chunk
+    ///
+    /// Returns the chunk's index; its body is emitted later between
+    /// [`BytecodeBuilder::begin_function`] and
+    /// [`BytecodeBuilder::end_function`].
+    pub fn emit_define_function(&mut self, name: &str, var_id: u32, param_count: u8) -> usize {
         let name_index = self.add_var_name(name, var_id);
-        self.instructions.push(Instruction::DefineFunction {
+        let chunk_index = self.functions.len();
+        self.functions.push(FunctionChunk {
             name_index,
             param_count,
-            body_start,
-            body_len,
-            max_register_used,
+            max_register_used: 0,
+            instructions: Vec::new(),
+            line_table: Vec::new(),
         });
+        self.instructions
+            .push(Instruction::DefineFunction { chunk_index });
+        chunk_index
     }
 
     /// Emit Call instruction
@@ -267,19 +557,88 @@ impl BytecodeBuilder {
             .push(Instruction::Return { has_value, src_reg });
     }
 
+    /// Emit Halt instruction
+    pub fn emit_halt(&mut self) {
+        self.instructions.push(Instruction::Halt);
+    }
+
+    /// Send everything emitted from now on to function chunk `index`
+    ///
+    /// The main code is set aside until [`BytecodeBuilder::end_function`].
+    /// Labels belong to the chunk they are bound in, so none may be pending.
+    ///
+    /// # Panics
+    /// Panics if another chunk is still open or a jump is waiting for a label.
+    pub fn begin_function(&mut self, index: usize) {
+        assert!(
+            self.current_function.is_none(),
+            "function chunk {} begun inside another chunk",
+            index
+        );
+        self.assert_no_pending_jumps();
+        self.labels.clear();
+        let main = std::mem::take(&mut self.instructions);
+        let main_line_table = std::mem::take(&mut self.line_table);
+        self.current_function = Some((index, main, main_line_table));
+    }
+
+    /// Finish the chunk opened by [`BytecodeBuilder::begin_function`] and go
+    /// back to emitting main code
+    ///
+    /// # Panics
+    /// Panics if no chunk is open or a jump is waiting for a label.
+    pub fn end_function(&mut self, max_register_used: u8) {
+        let (index, main, main_line_table) = self
+            .current_function
+            .take()
+            .expect("end_function without begin_function");
+        self.assert_no_pending_jumps();
+        self.labels.clear();
+        let chunk = &mut self.functions[index];
+        chunk.instructions = std::mem::replace(&mut self.instructions, main);
+        chunk.line_table = std::mem::replace(&mut self.line_table, main_line_table);
+        chunk.max_register_used = max_register_used;
+    }
+
+    fn assert_no_pending_jumps(&self) {
+        if let Some((_, label)) = self.pending_jumps.first() {
+            panic!("jump to unbound label {}", label.0);
+        }
+    }
+
     /// Build final bytecode, automatically appending Halt instruction
+    ///
+    /// # Panics
+    /// Panics if a jump was emitted to a label that was never bound, or a
+    /// function chunk was begun but not ended.
     pub fn build(mut self) -> Bytecode {
-        // Automatically append Halt instruction
-        self.instructions.push(Instruction::Halt);
+        self.emit_halt();
+        self.finish()
+    }
+
+    /// Build final bytecode exactly as emitted, for callers that place their
+    /// own Halt
+    ///
+    /// # Panics
+    /// Panics if a jump was emitted to a label that was never bound, or a
+    /// function chunk was begun but not ended.
+    pub fn finish(self) -> Bytecode {
+        self.assert_no_pending_jumps();
+        assert!(
+            self.current_function.is_none(),
+            "function chunk was never ended"
+        );
 
         Bytecode {
             instructions: self.instructions,
+            functions: self.functions,
             constants: self.constants,
             var_names: self.var_names,
             var_ids: self.var_ids,
             metadata: CompilerMetadata {
                 max_register_used: 0, // Will be set by compiler
             },
+            line_table: self.line_table,
         }
     }
 
@@ -305,6 +664,105 @@ impl BytecodeBuilder {
             constants,
             var_names,
             var_ids,
+            functions: Vec::new(),
+            current_function: None,
+            labels: Vec::new(),
+            pending_jumps: Vec::new(),
+            line_table: Vec::new(),
+        }
+    }
+
+    /// Attribute the instructions emitted from now on to `pos`
+    pub fn set_position(&mut self, pos: SourcePos) {
+        self.set_span(pos, pos);
+    }
+
+    /// Attribute the instructions emitted from now on to the code from `pos`
+    /// up to `end` (exclusive)
+    pub fn set_span(&mut self, pos: SourcePos, end: SourcePos) {
+        let start = self.instructions.len();
+        match self.line_table.last_mut() {
+            // Nothing emitted since the last position, so replace it
+            Some(last) if last.start == start => (last.pos, last.end) = (pos, end),
+            Some(last) if (last.pos, last.end) == (pos, end) => {}
+            _ => self.line_table.push(LineTableEntry { start, pos, end }),
+        }
+    }
+
+    /// Get the line table recorded so far (for compiler use)
+    pub fn line_table(&self) -> &[LineTableEntry] {
+        &self.line_table
+    }
+
+    /// Create a new unbound label
+    pub fn new_label(&mut self) -> Label {
+        self.labels.push(None);
+        Label(self.labels.len() - 1)
+    }
+
+    /// Bind a label to the position of the next emitted instruction
+    ///
+    /// Patches every jump already emitted to this label.
+    ///
+    /// # Panics
+    /// Panics if the label is already bound.
+    pub fn bind_label(&mut self, label: Label) {
+        assert!(
+            self.labels[label.0].is_none(),
+            "label {} bound twice",
+            label.0
+        );
+        let target = self.instructions.len();
+        self.labels[label.0] = Some(target);
+
+        let instructions = &mut self.instructions;
+        self.pending_jumps.retain(|&(index, pending)| {
+            if pending != label {
+                return true;
+            }
+            Self::patch_jump(&mut instructions[index], target);
+            false
+        });
+    }
+
+    /// Emit Jump instruction to a label
+    pub fn emit_jump(&mut self, label: Label) {
+        let target = self.resolve_label(label);
+        self.instructions.push(Instruction::Jump { target });
+    }
+
+    /// Emit JumpIfFalse instruction to a label
+    pub fn emit_jump_if_false(&mut self, cond_reg: u8, label: Label) {
+        let target = self.resolve_label(label);
+        self.instructions
+            .push(Instruction::JumpIfFalse { cond_reg, target });
+    }
+
+    /// Emit JumpIfTrue instruction to a label
+    pub fn emit_jump_if_true(&mut self, cond_reg: u8, label: Label) {
+        let target = self.resolve_label(label);
+        self.instructions
+            .push(Instruction::JumpIfTrue { cond_reg, target });
+    }
+
+    /// Return the label's target, or record the next instruction for patching
+    fn resolve_label(&mut self, label: Label) -> usize {
+        match self.labels[label.0] {
+            Some(target) => target,
+            None => {
+                self.pending_jumps.push((self.instructions.len(), label));
+                usize::MAX
+            }
+        }
+    }
+
+    /// Overwrite the target of a jump instruction
+    fn patch_jump(instruction: &mut Instruction, new_target: usize) {
+        match instruction {
+            Instruction::Jump { target }
+            | Instruction::JumpIfFalse { target, .. }
+            | Instruction::JumpIfTrue { target, .. } => *target = new_target,
+            other => unreachable!("patching non-jump instruction {:?}", other),
         }
     }
 }
@@ -409,23 +867,8 @@ mod tests {
         assert_eq!(halt, Instruction::Halt);
 
         // Test DefineFunction instruction
-        let def_func = Instruction::DefineFunction {
-            name_index: 0,
-            param_count: 2,
-            body_start: 10,
-            body_len: 5,
-            max_register_used: 3,
-        };
-        assert_eq!(
-            def_func,
-            Instruction::DefineFunction {
-                name_index: 0,
-                param_count: 2,
-                body_start: 10,
-                body_len: 5,
-                max_register_used: 3,
-            }
-        );
+        let def_func = Instruction::DefineFunction { chunk_index: 3 };
+        assert_eq!(def_func, Instruction::DefineFunction { chunk_index: 3 });
 
         // Test Call instruction
         let call = Instruction::Call {
@@ -827,27 +1270,20 @@ mod tests {
     #[test]
     fn test_emit_define_function_basic() {
         let mut builder = BytecodeBuilder::new();
-        builder.emit_define_function("foo", 1, 2, 0, 10, 0);
+        assert_eq!(builder.emit_define_function("foo", 1, 2), 0);
 
         let bytecode = builder.build();
 
         assert_eq!(bytecode.instructions.len(), 2); // DefineFunction + Halt
-        match &bytecode.instructions[0] {
-            Instruction::DefineFunction {
-                name_index,
-                param_count,
-                body_start,
-                body_len,
-                max_register_used,
-            } => {
-                assert_eq!(*name_index, 0);
-                assert_eq!(*param_count, 2);
-                assert_eq!(*body_start, 0);
-                assert_eq!(*body_len, 10);
-                assert_eq!(*max_register_used, 0);
-            }
-            _ => panic!("Expected DefineFunction instruction"),
-        }
+        assert_eq!(
+            bytecode.instructions[0],
+            Instruction::DefineFunction { chunk_index: 0 }
+        );
+        let chunk = &bytecode.functions[0];
+        assert_eq!(chunk.name_index, 0);
+        assert_eq!(chunk.param_count, 2);
+        assert_eq!(chunk.max_register_used, 0);
+        assert!(chunk.instructions.is_empty());
 
         // Function name should be in var_names pool
         assert_eq!(bytecode.var_names.len(), 1);
@@ -921,10 +1357,10 @@ mod tests {
         let mut builder = BytecodeBuilder::new();
 
         // Define and call same function multiple times
-        builder.emit_define_function("foo", 1, 0, 0, 5, 0);
+        builder.emit_define_function("foo", 1, 0);
         builder.emit_call("foo", 1, 0, 0, 1);
         builder.emit_call("foo", 1, 0, 0, 2); // Duplicate name
-        builder.emit_define_function("bar", 2, 1, 5, 10, 1);
+        builder.emit_define_function("bar", 2, 1);
         builder.emit_call("foo", 1, 0, 0, 3); // Another duplicate
 
         let bytecode = builder.build();
@@ -935,10 +1371,7 @@ mod tests {
         assert_eq!(bytecode.var_names[1], "bar");
 
         // Verify deduplication worked
-        match &bytecode.instructions[0] {
-            Instruction::DefineFunction { name_index, .. } => assert_eq!(*name_index, 0),
-            _ => panic!("Expected DefineFunction"),
-        }
+        assert_eq!(bytecode.functions[0].name_index, 0);
         match &bytecode.instructions[1] {
             Instruction::Call { name_index, .. } => assert_eq!(*name_index, 0),
             _ => panic!("Expected Call"),
@@ -947,10 +1380,11 @@ mod tests {
             Instruction::Call { name_index, .. } => assert_eq!(*name_index, 0), // Reused index
             _ => panic!("Expected Call"),
         }
-        match &bytecode.instructions[3] {
-            Instruction::DefineFunction { name_index, .. } => assert_eq!(*name_index, 1),
-            _ => panic!("Expected DefineFunction"),
-        }
+        assert_eq!(
+            bytecode.instructions[3],
+            Instruction::DefineFunction { chunk_index: 1 }
+        );
+        assert_eq!(bytecode.functions[1].name_index, 1);
         match &bytecode.instructions[4] {
             Instruction::Call { name_index, .. } => assert_eq!(*name_index, 0), // Reused index
             _ => panic!("Expected Call"),
@@ -960,31 +1394,25 @@ mod tests {
     #[test]
     fn test_function_with_zero_params() {
         let mut builder = BytecodeBuilder::new();
-        builder.emit_define_function("no_params", 1, 0, 0, 1, 0);
+        builder.emit_define_function("no_params", 1, 0);
 
         let bytecode = builder.build();
 
-        match &bytecode.instructions[0] {
-            Instruction::DefineFunction { param_count, .. } => {
-                assert_eq!(*param_count, 0);
-            }
-            _ => panic!("Expected DefineFunction"),
-        }
+        assert_eq!(bytecode.functions[0].param_count, 0);
     }
 
     #[test]
     fn test_function_with_max_params() {
         let mut builder = BytecodeBuilder::new();
-        builder.emit_define_function("many_params", 1, 255, 0, 1, 254); // u8 max
+        let chunk = builder.emit_define_function("many_params", 1, 255); // u8 max
+        builder.begin_function(chunk);
+        builder.emit_return(false, None);
+        builder.end_function(254);
 
         let bytecode = builder.build();
 
-        match &bytecode.instructions[0] {
-            Instruction::DefineFunction { param_count, .. } => {
-                assert_eq!(*param_count, 255);
-            }
-            _ => panic!("Expected DefineFunction"),
-        }
+        assert_eq!(bytecode.functions[0].param_count, 255);
+        assert_eq!(bytecode.functions[0].max_register_used, 254);
     }
 
     #[test]
@@ -1023,18 +1451,22 @@ mod tests {
         let mut builder = BytecodeBuilder::new();
 
         // Define function
-        builder.emit_define_function("add", 1, 2, 0, 5, 3);
+        let chunk = builder.emit_define_function("add", 1, 2);
 
         // Call function
         builder.emit_load_const(0, 10);
         builder.emit_load_const(1, 20);
         builder.emit_call("add", 1, 2, 0, 2);
         builder.emit_store_var("result", 2, 2);
+        builder.emit_halt();
 
-        // Return from function (would be in body)
-        builder.emit_return(true, Some(3));
+        // Body goes to its own chunk
+        builder.begin_function(chunk);
+        builder.emit_binary_op(2, 0, BinaryOperator::Add, 1);
+        builder.emit_return(true, Some(2));
+        builder.end_function(2);
 
-        let bytecode = builder.build();
+        let bytecode = builder.finish();
 
         // Verify structure
         assert!(matches!(
@@ -1054,11 +1486,13 @@ mod tests {
             bytecode.instructions[4],
             Instruction::StoreVar { .. }
         ));
-        assert!(matches!(
-            bytecode.instructions[5],
-            Instruction::Return { .. }
-        ));
-        assert_eq!(bytecode.instructions[6], Instruction::Halt);
+        assert_eq!(bytecode.instructions[5], Instruction::Halt);
+        assert_eq!(bytecode.instructions.len(), 6);
+
+        let body = &bytecode.functions[0];
+        assert_eq!(body.max_register_used, 2);
+        assert!(matches!(body.instructions[1], Instruction::Return { .. }));
+        assert_eq!(bytecode.code_len(), 8);
 
         // Verify pools
         assert_eq!(bytecode.constants.len(), 2);
@@ -1074,8 +1508,8 @@ mod tests {
     fn test_nested_function_definitions() {
         let mut builder = BytecodeBuilder::new();
 
-        builder.emit_define_function("outer", 1, 1, 0, 10, 1);
-        builder.emit_define_function("inner", 2, 2, 10, 5, 2);
+        builder.emit_define_function("outer", 1, 1);
+        builder.emit_define_function("inner", 2, 2);
         builder.emit_call("inner", 2, 2, 0, 1);
         builder.emit_return(true, Some(1));
         builder.emit_call("outer", 1, 1, 0, 0);
@@ -1109,7 +1543,7 @@ mod tests {
         let mut builder = BytecodeBuilder::new();
 
         // Both functions and variables use var_names pool (all use same ID 1 for "x")
-        builder.emit_define_function("x", 1, 0, 0, 1, 3);
+        builder.emit_define_function("x", 1, 0);
         builder.emit_store_var("x", 1, 1); // Same name as function
         builder.emit_call("x", 1, 0, 0, 2);
         builder.emit_load_var(3, "x", 1);
@@ -1121,10 +1555,7 @@ mod tests {
         assert_eq!(bytecode.var_names[0], "x");
 
         // All instructions should reference same index
-        match &bytecode.instructions[0] {
-            Instruction::DefineFunction { name_index, .. } => assert_eq!(*name_index, 0),
-            _ => panic!("Expected DefineFunction"),
-        }
+        assert_eq!(bytecode.functions[0].name_index, 0);
         match &bytecode.instructions[1] {
             Instruction::StoreVar { var_name_index, .. } => assert_eq!(*var_name_index, 0),
             _ => panic!("Expected StoreVar"),
@@ -1142,27 +1573,20 @@ mod tests {
     #[test]
     fn test_empty_function_body() {
         let mut builder = BytecodeBuilder::new();
-        builder.emit_define_function("empty", 1, 0, 0, 0, 0); // Zero-length body
+        let chunk = builder.emit_define_function("empty", 1, 0);
+        // Zero-length body
+        builder.begin_function(chunk);
+        builder.end_function(0);
 
         let bytecode = builder.build();
 
-        match &bytecode.instructions[0] {
-            Instruction::DefineFunction { body_len, .. } => {
-                assert_eq!(*body_len, 0);
-            }
-            _ => panic!("Expected DefineFunction"),
-        }
+        assert!(bytecode.functions[0].instructions.is_empty());
+        assert_eq!(bytecode.code_len(), 2);
     }
 
     #[test]
     fn test_function_instruction_clone() {
-        let inst1 = Instruction::DefineFunction {
-            name_index: 0,
-            param_count: 2,
-            body_start: 5,
-            body_len: 10,
-            max_register_used: 3,
-        };
+        let inst1 = Instruction::DefineFunction { chunk_index: 5 };
         let cloned1 = inst1.clone();
         assert_eq!(inst1, cloned1);
 
@@ -1182,4 +1606,180 @@ mod tests {
         let cloned3 = inst3.clone();
         assert_eq!(inst3, cloned3);
     }
+
+    #[test]
+    fn test_forward_label_is_patched() {
+        let mut builder = BytecodeBuilder::new();
+        let end = builder.new_label();
+        builder.emit_jump(end);
+        builder.emit_jump_if_false(2, end);
+        builder.emit_load_const(0, 1);
+        builder.bind_label(end);
+        let bytecode = builder.build();
+
+        assert_eq!(bytecode.instructions[0], Instruction::Jump { target: 3 });
+        assert_eq!(
+            bytecode.instructions[1],
+            Instruction::JumpIfFalse {
+                cond_reg: 2,
+                target: 3
+            }
+        );
+        assert_eq!(bytecode.instructions[3], Instruction::Halt);
+    }
+
+    #[test]
+    fn test_backward_label_resolves_immediately() {
+        let mut builder = BytecodeBuilder::new();
+        builder.emit_load_const(0, 1);
+        let top = builder.new_label();
+        builder.bind_label(top);
+        builder.emit_jump_if_true(0, top);
+
+        assert_eq!(
+            builder.instructions()[1],
+            Instruction::JumpIfTrue {
+                cond_reg: 0,
+                target: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_function_chunks_use_local_targets_and_positions() {
+        let pos = |line| SourcePos { line, column: 1 };
+        let mut builder = BytecodeBuilder::new();
+        let chunk = builder.emit_define_function("f", 0, 1);
+        builder.set_position(pos(1));
+        builder.emit_load_const(0, 1);
+        builder.emit_halt();
+
+        builder.begin_function(chunk);
+        let end = builder.new_label();
+        builder.set_position(pos(3));
+        builder.emit_jump_if_false(0, end);
+        builder.emit_load_const(0, 2);
+        builder.bind_label(end);
+        builder.emit_return(true, Some(0));
+        builder.end_function(0);
+        builder.emit_print(0);
+        let bytecode = builder.finish();
+
+        // The main code picks up where it left off
+        assert_eq!(bytecode.instructions.len(), 4);
+        assert_eq!(bytecode.instructions[3], Instruction::Print { src_reg: 0 });
+
+        let body = &bytecode.functions[0].instructions;
+        assert_eq!(
+            body[0],
+            Instruction::JumpIfFalse {
+                cond_reg: 0,
+                target: 2
+            }
+        );
+        assert_eq!(bytecode.functions[0].line_table[0].start, 0);
+
+        // Code addresses continue past the main code
+        assert_eq!(bytecode.chunk_start(0), 4);
+        assert_eq!(bytecode.locate(5), Some((Some(0), 1)));
+        assert_eq!(bytecode.locate(7), None);
+        assert_eq!(bytecode.instruction(6), Some(&body[2]));
+        assert_eq!(bytecode.source_position(3), Some(pos(1)));
+        assert_eq!(bytecode.source_position(4), Some(pos(3)));
+        assert_eq!(
+            bytecode.describe_instruction(0),
+            "DefineFunction f/1, chunk 0"
+        );
+        assert_eq!(bytecode.code().count(), bytecode.code_len());
+    }
+
+    #[test]
+    #[should_panic(expected = "never ended")]
+    fn test_finish_rejects_open_function_chunk() {
+        let mut builder = BytecodeBuilder::new();
+        let chunk = builder.emit_define_function("f", 0, 0);
+        builder.begin_function(chunk);
+        builder.finish();
+    }
+
+    #[test]
+    #[should_panic(expected = "jump to unbound label")]
+    fn test_build_rejects_unbound_label() {
+        let mut builder = BytecodeBuilder::new();
+        let label = builder.new_label();
+        builder.emit_jump(label);
+        builder.build();
+    }
+
+    #[test]
+    #[should_panic(expected = "bound twice")]
+    fn test_label_cannot_be_bound_twice() {
+        let mut builder = BytecodeBuilder::new();
+        let label = builder.new_label();
+        builder.bind_label(label);
+        builder.bind_label(label);
+    }
+
+    #[test]
+    fn test_describe_instruction_resolves_pools() {
+        let mut builder = BytecodeBuilder::new();
+        builder.emit_load_const(0, -7);
+        builder.emit_store_var("total", 40, 0);
+        builder.emit_unary_op(1, UnaryOperator::Neg, 0);
+        builder.emit_call("f", 41, 2, 0, 3);
+        builder.emit_move(4, 3);
+        builder.emit_return(false, None);
+        let bytecode = builder.build();
+
+        assert_eq!(bytecode.describe_instruction(0), "LoadConst r0, -7");
+        assert_eq!(bytecode.describe_instruction(1), "StoreVar total, r0");
+        assert_eq!(bytecode.describe_instruction(2), "UnaryOp r1, -r0");
+        assert_eq!(
+            bytecode.describe_instruction(3),
+            "Call r3, f(2 args from r0)"
+        );
+        assert_eq!(bytecode.describe_instruction(4), "Move r4, r3");
+        assert_eq!(bytecode.describe_instruction(5), "Return");
+        assert_eq!(bytecode.describe_instruction(6), "Halt");
+        assert_eq!(bytecode.describe_instruction(7), "<out of bounds>");
+    }
+
+    #[test]
+    fn test_set_position_builds_line_table() {
+        let pos = |line| SourcePos { line, column: 1 };
+        let mut builder = BytecodeBuilder::new();
+        builder.set_position(pos(1));
+        builder.emit_load_const(0, 1);
+        builder.emit_load_const(1, 2);
+        builder.set_position(pos(2));
+        // Superseded before anything was emitted for it
+        builder.set_position(pos(3));
+        builder.emit_print(0);
+        builder.set_position(pos(3));
+        builder.emit_print(1);
+        let bytecode = builder.build();
+
+        assert_eq!(bytecode.line_table.len(), 2);
+        assert_eq!(bytecode.source_position(1), Some(pos(1)));
+        assert_eq!(bytecode.source_position(2), Some(pos(3)));
+        assert_eq!(bytecode.source_position(3), Some(pos(3)));
+        assert_eq!(bytecode.source_span(3), Some((pos(3), pos(3))));
+    }
+
+    #[test]
+    fn test_set_span_keeps_the_end() {
+        let pos = |column| SourcePos { line: 1, column };
+        let mut builder = BytecodeBuilder::new();
+        builder.set_span(pos(5), pos(10));
+        builder.emit_load_const(0, 1);
+        // Same start, wider expression
+        builder.set_span(pos(5), pos(14));
+        builder.emit_print(0);
+        let bytecode = builder.build();
+
+        assert_eq!(bytecode.line_table.len(), 2);
+        assert_eq!(bytecode.source_span(0), Some((pos(5), pos(10))));
+        assert_eq!(bytecode.source_span(1), Some((pos(5), pos(14))));
+        assert_eq!(bytecode.source_position(1), Some(pos(5)));
+    }
 }
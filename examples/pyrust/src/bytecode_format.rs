@@ -0,0 +1,821 @@
+//! Stable binary format for compiled bytecode
+//!
+//! [`Bytecode::to_bytes`] writes a program to a self-contained byte buffer and
+//! [`Bytecode::from_bytes`] reads it back, so scripts can be compiled ahead of
+//! time (`pyrust --compile script.py -o script.pybc`) or cached on disk.
+//!
+//! # Format Specification
+//!
+//! All integers are big-endian. Every `usize` field of an instruction is
+//! stored as a `u32`.
+//!
+//! ```text
+//! [4 bytes magic "PYBC"][u16 version]
+//! [u8 max_register_used]
+//! [u32 count][i64 constant]*
+//! [u32 count]([u32 length][UTF-8 name][u32 var_id])*
+//! [u32 count][u8 opcode, operands...]*
+//! [u32 count]([u32 start][u32 line][u32 column][u32 end line][u32 end column])*
+//! [u32 count]([u32 name_index][u8 param_count][u8 max_register_used]
+//!             [u32 count][u8 opcode, operands...]*
+//!             [u32 count]([u32 start][u32 line][u32 column]
+//!                         [u32 end line][u32 end column])*)*
+//! ```
+//!
+//! The sections are, in order: the constant pool, the variable name pool with
+//! each name's interned ID, the main instructions and their line table, and
+//! the function chunks, each with its own instructions and line table. Opcodes
+//! follow the declaration order of [`Instruction`], and operands follow each
+//! variant's field order. Operators are encoded by their declaration order in
+//! [`BinaryOperator`] and [`UnaryOperator`]. `Return` stores a flags byte
+//! (bit 0: `has_value`, bit 1: a source register follows) and then the
+//! register, if any.
+//!
+//! Readers reject any [`FORMAT_VERSION`] other than their own, so the layout
+//! may change freely as long as the version is bumped.
+//!
+//! # Example
+//!
+//! ```
+//! use pyrust::bytecode::Bytecode;
+//!
+//! let bytecode = pyrust::compile_python("x = 40\nprint(x + 2)").unwrap();
+//! let bytes = bytecode.to_bytes();
+//! assert_eq!(&bytes[..4], b"PYBC");
+//! assert_eq!(Bytecode::from_bytes(&bytes).unwrap(), bytecode);
+//! ```
+
+use crate::ast::{BinaryOperator, SourcePos, UnaryOperator};
+use crate::bytecode::{Bytecode, CompilerMetadata, FunctionChunk, Instruction, LineTableEntry};
+use crate::error_code::ErrorCode;
+use std::fmt;
+
+/// Bytes every serialized program starts with
+pub const FORMAT_MAGIC: [u8; 4] = *b"PYBC";
+
+/// Version of the layout written by [`Bytecode::to_bytes`]
+pub const FORMAT_VERSION: u16 = 4;
+
+/// Reasons a byte buffer is not a valid serialized program
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormatError {
+    /// The buffer does not start with [`FORMAT_MAGIC`]
+    BadMagic,
+    /// The buffer was written by a different format version
+    UnsupportedVersion { found: u16, expected: u16 },
+    /// The buffer ends in the middle of the named item
+    Truncated(&'static str),
+    /// A variable name is not valid UTF-8
+    InvalidUtf8(String),
+    /// Unknown instruction opcode
+    InvalidOpcode(u8),
+    /// Unknown binary or unary operator tag
+    InvalidOperator(u8),
+    /// Bytes left over after the last function chunk
+    TrailingBytes(usize),
+    /// The program decoded but failed [`Bytecode::verify`]
+    Malformed(String),
+    /// The bytecode file could not be read
+    Io(String),
+}
+
+impl FormatError {
+    /// Stable code of the kind of failure
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            FormatError::BadMagic => ErrorCode::NotBytecode,
+            FormatError::UnsupportedVersion { .. } => ErrorCode::UnsupportedVersion,
+            FormatError::Truncated(_)
+            | FormatError::InvalidUtf8(_)
+            | FormatError::InvalidOpcode(_)
+            | FormatError::InvalidOperator(_)
+            | FormatError::TrailingBytes(_) => ErrorCode::CorruptBytecode,
+            FormatError::Malformed(_) => ErrorCode::MalformedBytecode,
+            FormatError::Io(_) => ErrorCode::UnreadableFile,
+        }
+    }
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatError::BadMagic => write!(f, "Not a pyrust bytecode file"),
+            FormatError::UnsupportedVersion { found, expected } => write!(
+                f,
+                "Unsupported bytecode format version {} (expected {}); recompile the source",
+                found, expected
+            ),
+            FormatError::Truncated(item) => write!(f, "Truncated bytecode: incomplete {}", item),
+            FormatError::InvalidUtf8(msg) => write!(f, "Invalid UTF-8: {}", msg),
+            FormatError::InvalidOpcode(opcode) => write!(f, "Invalid opcode: {}", opcode),
+            FormatError::InvalidOperator(tag) => write!(f, "Invalid operator: {}", tag),
+            FormatError::TrailingBytes(count) => {
+                write!(f, "{} unexpected bytes after function chunks", count)
+            }
+            FormatError::Malformed(msg) => write!(f, "Malformed bytecode: {}", msg),
+            FormatError::Io(msg) => write!(f, "Cannot read bytecode file: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for FormatError {}
+
+impl Bytecode {
+    /// Serialize the program in the stable binary format
+    ///
+    /// # Panics
+    ///
+    /// Panics if a pool, the instruction list or an index does not fit in a
+    /// `u32`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Writer(Vec::with_capacity(64 + self.instructions.len() * 8));
+        out.0.extend_from_slice(&FORMAT_MAGIC);
+        out.0.extend_from_slice(&FORMAT_VERSION.to_be_bytes());
+        out.u8(self.metadata.max_register_used);
+
+        out.len(self.constants.len());
+        for constant in &self.constants {
+            out.0.extend_from_slice(&constant.to_be_bytes());
+        }
+
+        out.len(self.var_names.len());
+        for (index, name) in self.var_names.iter().enumerate() {
+            out.len(name.len());
+            out.0.extend_from_slice(name.as_bytes());
+            out.u32(self.var_ids.get(index).copied().unwrap_or(0));
+        }
+
+        out.code(&self.instructions, &self.line_table);
+
+        out.len(self.functions.len());
+        for chunk in &self.functions {
+            out.len(chunk.name_index);
+            out.u8(chunk.param_count);
+            out.u8(chunk.max_register_used);
+            out.code(&chunk.instructions, &chunk.line_table);
+        }
+
+        out.0
+    }
+
+    /// Deserialize a program written by [`Bytecode::to_bytes`]
+    ///
+    /// Only the layout is checked; call [`Bytecode::verify`] before running
+    /// bytecode that did not come straight from the compiler.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Bytecode, FormatError> {
+        let mut input = Reader { bytes, pos: 0 };
+        if input.take(4, "header")? != FORMAT_MAGIC {
+            return Err(FormatError::BadMagic);
+        }
+        let version = u16::from_be_bytes([input.u8("header")?, input.u8("header")?]);
+        if version != FORMAT_VERSION {
+            return Err(FormatError::UnsupportedVersion {
+                found: version,
+                expected: FORMAT_VERSION,
+            });
+        }
+        let max_register_used = input.u8("header")?;
+
+        let count = input.len("constant pool")?;
+        let mut constants = Vec::with_capacity(count.min(bytes.len() / 8));
+        for _ in 0..count {
+            let raw = input.take(8, "constant pool")?;
+            constants.push(i64::from_be_bytes(raw.try_into().unwrap()));
+        }
+
+        let count = input.len("variable names")?;
+        let mut var_names = Vec::with_capacity(count.min(bytes.len() / 8));
+        let mut var_ids = Vec::with_capacity(count.min(bytes.len() / 8));
+        for _ in 0..count {
+            let length = input.len("variable names")?;
+            let raw = input.take(length, "variable names")?;
+            let name =
+                std::str::from_utf8(raw).map_err(|e| FormatError::InvalidUtf8(e.to_string()))?;
+            var_names.push(name.to_string());
+            var_ids.push(input.u32("variable names")?);
+        }
+
+        let (instructions, line_table) = input.code()?;
+
+        let count = input.len("function chunks")?;
+        let mut functions = Vec::with_capacity(count.min(bytes.len() / 14));
+        for _ in 0..count {
+            let name_index = input.len("function chunks")?;
+            let param_count = input.u8("function chunks")?;
+            let max_register_used = input.u8("function chunks")?;
+            let (instructions, line_table) = input.code()?;
+            functions.push(FunctionChunk {
+                name_index,
+                param_count,
+                max_register_used,
+                instructions,
+                line_table,
+            });
+        }
+
+        let remaining = bytes.len() - input.pos;
+        if remaining > 0 {
+            return Err(FormatError::TrailingBytes(remaining));
+        }
+
+        Ok(Bytecode {
+            instructions,
+            functions,
+            constants,
+            var_names,
+            var_ids,
+            metadata: CompilerMetadata { max_register_used },
+            line_table,
+        })
+    }
+}
+
+impl Bytecode {
+    /// Check that every operand refers to something that exists
+    ///
+    /// Pool indices must be in range, each `var_id` must match the ID recorded
+    /// for its name, function definitions must refer to existing chunks, jump
+    /// targets must lie inside their own chunk, and every line table must be
+    /// sorted. The VM would otherwise only notice some of these mid-run, after
+    /// side effects.
+    ///
+    /// Errors name the offending instruction by its code address.
+    pub fn verify(&self) -> Result<(), FormatError> {
+        if self.var_ids.len() != self.var_names.len() {
+            return Err(FormatError::Malformed(format!(
+                "{} variable IDs for {} names",
+                self.var_ids.len(),
+                self.var_names.len()
+            )));
+        }
+
+        self.verify_code(0, &self.instructions, &self.line_table)?;
+        for (index, chunk) in self.functions.iter().enumerate() {
+            if chunk.name_index >= self.var_names.len() {
+                return Err(FormatError::Malformed(format!(
+                    "function chunk {}: name index {} out of range",
+                    index, chunk.name_index
+                )));
+            }
+            self.verify_code(
+                self.chunk_start(index),
+                &chunk.instructions,
+                &chunk.line_table,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Check one chunk's instructions, which start at code address `base`
+    fn verify_code(
+        &self,
+        base: usize,
+        code: &[Instruction],
+        line_table: &[LineTableEntry],
+    ) -> Result<(), FormatError> {
+        let malformed = |index: usize, msg: String| {
+            FormatError::Malformed(format!("instruction {}: {}", base + index, msg))
+        };
+        let name = |index: usize, name_index: usize| {
+            if name_index < self.var_names.len() {
+                Ok(())
+            } else {
+                Err(malformed(
+                    index,
+                    format!("name index {} out of range", name_index),
+                ))
+            }
+        };
+        let variable = |index: usize, name_index: usize, var_id: u32| {
+            name(index, name_index)?;
+            if self.var_ids[name_index] == var_id {
+                Ok(())
+            } else {
+                Err(malformed(
+                    index,
+                    format!("variable ID {} does not match its name", var_id),
+                ))
+            }
+        };
+        let target = |index: usize, target: usize| {
+            if target < code.len() {
+                Ok(())
+            } else {
+                Err(malformed(
+                    index,
+                    format!("jump target {} out of range", target),
+                ))
+            }
+        };
+
+        for (index, instruction) in code.iter().enumerate() {
+            match *instruction {
+                Instruction::LoadConst { const_index, .. }
+                    if const_index >= self.constants.len() =>
+                {
+                    return Err(malformed(
+                        index,
+                        format!("constant index {} out of range", const_index),
+                    ));
+                }
+                Instruction::LoadVar {
+                    var_name_index,
+                    var_id,
+                    ..
+                }
+                | Instruction::StoreVar {
+                    var_name_index,
+                    var_id,
+                    ..
+                } => variable(index, var_name_index, var_id)?,
+                Instruction::Call { name_index, .. } => name(index, name_index)?,
+                Instruction::DefineFunction { chunk_index }
+                    if chunk_index >= self.functions.len() =>
+                {
+                    return Err(malformed(
+                        index,
+                        format!("function chunk {} out of range", chunk_index),
+                    ));
+                }
+                Instruction::Jump { target: to }
+                | Instruction::JumpIfFalse { target: to, .. }
+                | Instruction::JumpIfTrue { target: to, .. } => target(index, to)?,
+                _ => {}
+            }
+        }
+
+        if !line_table.is_sorted_by_key(|entry| entry.start) {
+            return Err(FormatError::Malformed(
+                "line table is not sorted".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Append-only encoder for the format
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_be_bytes());
+    }
+
+    /// Write a count or index, which the format stores as a `u32`
+    fn len(&mut self, value: usize) {
+        self.u32(u32::try_from(value).expect("bytecode too large to serialize"));
+    }
+
+    /// Write a chunk's instructions followed by its line table
+    fn code(&mut self, instructions: &[Instruction], line_table: &[LineTableEntry]) {
+        self.len(instructions.len());
+        for instruction in instructions {
+            self.instruction(instruction);
+        }
+
+        self.len(line_table.len());
+        for entry in line_table {
+            self.len(entry.start);
+            self.len(entry.pos.line);
+            self.len(entry.pos.column);
+            self.len(entry.end.line);
+            self.len(entry.end.column);
+        }
+    }
+
+    fn instruction(&mut self, instruction: &Instruction) {
+        match *instruction {
+            Instruction::LoadConst {
+                dest_reg,
+                const_index,
+            } => {
+                self.u8(0);
+                self.u8(dest_reg);
+                self.len(const_index);
+            }
+            Instruction::LoadVar {
+                dest_reg,
+                var_name_index,
+                var_id,
+            } => {
+                self.u8(1);
+                self.u8(dest_reg);
+                self.len(var_name_index);
+                self.u32(var_id);
+            }
+            Instruction::StoreVar {
+                var_name_index,
+                var_id,
+                src_reg,
+            } => {
+                self.u8(2);
+                self.len(var_name_index);
+                self.u32(var_id);
+                self.u8(src_reg);
+            }
+            Instruction::BinaryOp {
+                dest_reg,
+                left_reg,
+                op,
+                right_reg,
+            } => {
+                self.u8(3);
+                self.u8(dest_reg);
+                self.u8(left_reg);
+                self.u8(binary_operator_tag(op));
+                self.u8(right_reg);
+            }
+            Instruction::UnaryOp {
+                dest_reg,
+                op,
+                operand_reg,
+            } => {
+                self.u8(4);
+                self.u8(dest_reg);
+                self.u8(unary_operator_tag(op));
+                self.u8(operand_reg);
+            }
+            Instruction::Print { src_reg } => {
+                self.u8(5);
+                self.u8(src_reg);
+            }
+            Instruction::SetResult { src_reg } => {
+                self.u8(6);
+                self.u8(src_reg);
+            }
+            Instruction::Halt => self.u8(7),
+            Instruction::DefineFunction { chunk_index } => {
+                self.u8(8);
+                self.len(chunk_index);
+            }
+            Instruction::Call {
+                name_index,
+                arg_count,
+                first_arg_reg,
+                dest_reg,
+            } => {
+                self.u8(9);
+                self.len(name_index);
+                self.u8(arg_count);
+                self.u8(first_arg_reg);
+                self.u8(dest_reg);
+            }
+            Instruction::Return { has_value, src_reg } => {
+                self.u8(10);
+                self.u8(u8::from(has_value) | if src_reg.is_some() { 2 } else { 0 });
+                if let Some(src_reg) = src_reg {
+                    self.u8(src_reg);
+                }
+            }
+            Instruction::Jump { target } => {
+                self.u8(11);
+                self.len(target);
+            }
+            Instruction::JumpIfFalse { cond_reg, target } => {
+                self.u8(12);
+                self.u8(cond_reg);
+                self.len(target);
+            }
+            Instruction::JumpIfTrue { cond_reg, target } => {
+                self.u8(13);
+                self.u8(cond_reg);
+                self.len(target);
+            }
+            Instruction::Move { dest_reg, src_reg } => {
+                self.u8(14);
+                self.u8(dest_reg);
+                self.u8(src_reg);
+            }
+        }
+    }
+}
+
+/// Bounds-checked decoder for the format
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    /// Consume the next `count` bytes, which belong to `item`
+    fn take(&mut self, count: usize, item: &'static str) -> Result<&'a [u8], FormatError> {
+        let end = self
+            .pos
+            .checked_add(count)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or(FormatError::Truncated(item))?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn u8(&mut self, item: &'static str) -> Result<u8, FormatError> {
+        Ok(self.take(1, item)?[0])
+    }
+
+    fn u32(&mut self, item: &'static str) -> Result<u32, FormatError> {
+        let raw = self.take(4, item)?;
+        Ok(u32::from_be_bytes(raw.try_into().unwrap()))
+    }
+
+    fn len(&mut self, item: &'static str) -> Result<usize, FormatError> {
+        Ok(self.u32(item)? as usize)
+    }
+
+    /// Read a chunk's instructions and line table
+    fn code(&mut self) -> Result<(Vec<Instruction>, Vec<LineTableEntry>), FormatError> {
+        let remaining = self.bytes.len() - self.pos;
+        let count = self.len("instructions")?;
+        let mut instructions = Vec::with_capacity(count.min(remaining));
+        for _ in 0..count {
+            instructions.push(self.instruction()?);
+        }
+
+        let count = self.len("line table")?;
+        let mut line_table = Vec::with_capacity(count.min(remaining / 20));
+        for _ in 0..count {
+            let start = self.len("line table")?;
+            let line = self.len("line table")?;
+            let column = self.len("line table")?;
+            let end_line = self.len("line table")?;
+            let end_column = self.len("line table")?;
+            line_table.push(LineTableEntry {
+                start,
+                pos: SourcePos { line, column },
+                end: SourcePos {
+                    line: end_line,
+                    column: end_column,
+                },
+            });
+        }
+        Ok((instructions, line_table))
+    }
+
+    fn instruction(&mut self) -> Result<Instruction, FormatError> {
+        const ITEM: &str = "instructions";
+        let instruction = match self.u8(ITEM)? {
+            0 => Instruction::LoadConst {
+                dest_reg: self.u8(ITEM)?,
+                const_index: self.len(ITEM)?,
+            },
+            1 => Instruction::LoadVar {
+                dest_reg: self.u8(ITEM)?,
+                var_name_index: self.len(ITEM)?,
+                var_id: self.u32(ITEM)?,
+            },
+            2 => Instruction::StoreVar {
+                var_name_index: self.len(ITEM)?,
+                var_id: self.u32(ITEM)?,
+                src_reg: self.u8(ITEM)?,
+            },
+            3 => Instruction::BinaryOp {
+                dest_reg: self.u8(ITEM)?,
+                left_reg: self.u8(ITEM)?,
+                op: binary_operator(self.u8(ITEM)?)?,
+                right_reg: self.u8(ITEM)?,
+            },
+            4 => Instruction::UnaryOp {
+                dest_reg: self.u8(ITEM)?,
+                op: unary_operator(self.u8(ITEM)?)?,
+                operand_reg: self.u8(ITEM)?,
+            },
+            5 => Instruction::Print {
+                src_reg: self.u8(ITEM)?,
+            },
+            6 => Instruction::SetResult {
+                src_reg: self.u8(ITEM)?,
+            },
+            7 => Instruction::Halt,
+            8 => Instruction::DefineFunction {
+                chunk_index: self.len(ITEM)?,
+            },
+            9 => Instruction::Call {
+                name_index: self.len(ITEM)?,
+                arg_count: self.u8(ITEM)?,
+                first_arg_reg: self.u8(ITEM)?,
+                dest_reg: self.u8(ITEM)?,
+            },
+            10 => {
+                let flags = self.u8(ITEM)?;
+                let src_reg = if flags & 2 != 0 {
+                    Some(self.u8(ITEM)?)
+                } else {
+                    None
+                };
+                Instruction::Return {
+                    has_value: flags & 1 != 0,
+                    src_reg,
+                }
+            }
+            11 => Instruction::Jump {
+                target: self.len(ITEM)?,
+            },
+            12 => Instruction::JumpIfFalse {
+                cond_reg: self.u8(ITEM)?,
+                target: self.len(ITEM)?,
+            },
+            13 => Instruction::JumpIfTrue {
+                cond_reg: self.u8(ITEM)?,
+                target: self.len(ITEM)?,
+            },
+            14 => Instruction::Move {
+                dest_reg: self.u8(ITEM)?,
+                src_reg: self.u8(ITEM)?,
+            },
+            opcode => return Err(FormatError::InvalidOpcode(opcode)),
+        };
+        Ok(instruction)
+    }
+}
+
+fn binary_operator_tag(op: BinaryOperator) -> u8 {
+    match op {
+        BinaryOperator::Add => 0,
+        BinaryOperator::Sub => 1,
+        BinaryOperator::Mul => 2,
+        BinaryOperator::Div => 3,
+        BinaryOperator::FloorDiv => 4,
+        BinaryOperator::Mod => 5,
+    }
+}
+
+fn binary_operator(tag: u8) -> Result<BinaryOperator, FormatError> {
+    Ok(match tag {
+        0 => BinaryOperator::Add,
+        1 => BinaryOperator::Sub,
+        2 => BinaryOperator::Mul,
+        3 => BinaryOperator::Div,
+        4 => BinaryOperator::FloorDiv,
+        5 => BinaryOperator::Mod,
+        _ => return Err(FormatError::InvalidOperator(tag)),
+    })
+}
+
+fn unary_operator_tag(op: UnaryOperator) -> u8 {
+    match op {
+        UnaryOperator::Neg => 0,
+        UnaryOperator::Pos => 1,
+    }
+}
+
+fn unary_operator(tag: u8) -> Result<UnaryOperator, FormatError> {
+    Ok(match tag {
+        0 => UnaryOperator::Neg,
+        1 => UnaryOperator::Pos,
+        _ => return Err(FormatError::InvalidOperator(tag)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode::BytecodeBuilder;
+
+    fn compile(source: &str) -> Bytecode {
+        crate::compile_python(source).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip_preserves_program() {
+        let bytecode = compile(
+            "def add(a, b):\n    return a + b\n\ndef nothing():\n    return\n\nx = -7\nprint(add(x, 100) // 3 % 5)\nnothing()\nadd(x, 1)",
+        );
+        assert!(!bytecode.line_table.is_empty());
+
+        let decoded = Bytecode::from_bytes(&bytecode.to_bytes()).unwrap();
+        assert_eq!(decoded, bytecode);
+
+        let mut vm = crate::vm::VM::new();
+        let result = vm.execute(&decoded).unwrap();
+        assert_eq!(vm.format_output(result), "1\n-6");
+    }
+
+    #[test]
+    fn test_round_trip_covers_every_instruction() {
+        let mut builder = BytecodeBuilder::new();
+        let done = builder.new_label();
+        builder.emit_load_const(0, i64::MIN);
+        builder.emit_unary_op(1, UnaryOperator::Pos, 0);
+        builder.emit_move(2, 1);
+        builder.emit_jump_if_true(1, done);
+        builder.emit_jump_if_false(1, done);
+        builder.emit_jump(done);
+        builder.bind_label(done);
+        builder.emit_return(true, Some(1));
+        builder.emit_return(false, None);
+        let bytecode = builder.build();
+
+        assert_eq!(Bytecode::from_bytes(&bytecode.to_bytes()), Ok(bytecode));
+    }
+
+    #[test]
+    fn test_rejects_foreign_and_newer_files() {
+        let mut bytes = compile("1").to_bytes();
+        bytes[4..6].copy_from_slice(&[0xFF; 2]);
+        assert_eq!(
+            Bytecode::from_bytes(&bytes),
+            Err(FormatError::UnsupportedVersion {
+                found: 0xFFFF,
+                expected: FORMAT_VERSION
+            })
+        );
+
+        bytes[0] = b'X';
+        assert_eq!(Bytecode::from_bytes(&bytes), Err(FormatError::BadMagic));
+    }
+
+    #[test]
+    fn test_truncated_input_is_an_error_not_a_panic() {
+        let bytes = compile("def f(a):\n    return a * 2\nprint(f(21))").to_bytes();
+        for cut in 0..bytes.len() {
+            assert!(
+                matches!(
+                    Bytecode::from_bytes(&bytes[..cut]),
+                    Err(FormatError::Truncated(_) | FormatError::BadMagic)
+                ),
+                "prefix of {} bytes decoded",
+                cut
+            );
+        }
+
+        let mut padded = bytes.clone();
+        padded.push(0);
+        assert_eq!(
+            Bytecode::from_bytes(&padded),
+            Err(FormatError::TrailingBytes(1))
+        );
+    }
+
+    #[test]
+    fn test_verify_accepts_compiled_programs() {
+        let bytecode = compile("def f(a):\n    return a - 1\nx = f(3)\nprint(x)");
+        assert_eq!(bytecode.verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_verify_rejects_dangling_operands() {
+        let valid = compile("def f(a):\n    return a\nx = 1\nf(x)");
+        let find =
+            |pred: fn(&Instruction) -> bool| valid.instructions.iter().position(pred).unwrap();
+
+        let mut bytecode = valid.clone();
+        let index = find(|i| matches!(i, Instruction::LoadConst { .. }));
+        bytecode.instructions[index] = Instruction::LoadConst {
+            dest_reg: 0,
+            const_index: 99,
+        };
+        assert!(
+            matches!(bytecode.verify(), Err(FormatError::Malformed(m)) if m.contains("constant index 99"))
+        );
+
+        let mut bytecode = valid.clone();
+        let index = find(|i| matches!(i, Instruction::StoreVar { .. }));
+        if let Instruction::StoreVar { var_id, .. } = &mut bytecode.instructions[index] {
+            *var_id = u32::MAX;
+        }
+        assert!(
+            matches!(bytecode.verify(), Err(FormatError::Malformed(m)) if m.contains("does not match"))
+        );
+
+        let mut bytecode = valid.clone();
+        let index = find(|i| matches!(i, Instruction::DefineFunction { .. }));
+        bytecode.instructions[index] = Instruction::DefineFunction { chunk_index: 7 };
+        assert!(
+            matches!(bytecode.verify(), Err(FormatError::Malformed(m)) if m.contains("function chunk 7"))
+        );
+
+        let mut bytecode = valid.clone();
+        bytecode.functions[0].name_index = 99;
+        assert!(
+            matches!(bytecode.verify(), Err(FormatError::Malformed(m)) if m.contains("name index 99"))
+        );
+
+        let mut bytecode = valid.clone();
+        bytecode
+            .instructions
+            .push(Instruction::Jump { target: 1000 });
+        assert!(
+            matches!(bytecode.verify(), Err(FormatError::Malformed(m)) if m.contains("jump target 1000"))
+        );
+
+        // Targets are local, so a jump past the end of its own chunk is
+        // rejected even when the address exists in another
+        let mut bytecode = valid;
+        let main_len = bytecode.instructions.len();
+        bytecode.functions[0]
+            .instructions
+            .insert(0, Instruction::Jump { target: 5 });
+        assert!(bytecode.code_len() > 5);
+        assert_eq!(
+            bytecode.verify(),
+            Err(FormatError::Malformed(format!(
+                "instruction {}: jump target 5 out of range",
+                main_len
+            )))
+        );
+    }
+}
@@ -1,16 +1,80 @@
-//! Compilation cache with LRU eviction
+//! Compilation cache with pluggable eviction
 //!
 //! Provides in-memory caching of compiled bytecode with SipHash-based collision detection.
 //! Designed for <50μs cache hit latency and <10MB memory footprint for 1000 entries.
-
+//!
+//! Programs are keyed by their tokens and the lines they are on rather than
+//! by the raw source, so copies that differ only in spacing within lines or
+//! in trailing blank lines share an entry.
+//!
+//! Eviction is bounded by entry count and, optionally, by bytes
+//! ([`CompilationCache::with_max_bytes`]): each entry is charged its key
+//! plus [`Bytecode::memory_footprint`], and entries are evicted until a new
+//! one fits. A program larger than the whole budget is not cached in memory.
+//! Which entries go is up to the [`EvictionPolicy`]
+//! ([`CompilationCache::with_policy`]), least recently used by default.
+//!
+//! An optional disk tier ([`CompilationCache::with_disk_dir`]) keeps every
+//! inserted program as a file named by a hash of its key, in the
+//! [`crate::bytecode_format`] encoding. It outlives the process, so a freshly
+//! started daemon or a repeated CLI run of the same script loads the bytecode
+//! instead of compiling it. Each file also stores the full key, so a hash
+//! collision is a miss like in memory; unreadable, stale or corrupted files
+//! are misses too, and are overwritten on the next insert. Files record the
+//! pyrust and bytecode format versions that wrote them, so after an upgrade
+//! old entries are recompiled rather than decoded.
+//!
+//! With a time to live ([`CompilationCache::with_ttl`]), an entry older
+//! than the TTL is dropped when it is next looked up, and the program is
+//! compiled afresh. Age counts from when the program was compiled: a disk
+//! entry's age is that of its file, and it keeps that age when loaded into
+//! memory. This bounds how long a long-running daemon can serve bytecode from
+//! an older compiler or older settings.
+//!
+//! A shared cache file ([`CompilationCache::with_shared_file`]) holds the
+//! programs of every process in one file that each maps into memory, so CLI
+//! runs in a shell loop skip compiling without a daemon or a file per
+//! program; see [`SharedFile`]. It is consulted after memory and before the
+//! disk tier, and the TTL applies to it too.
+//!
+//! A [`CompilationCache`] takes `&mut self`; [`ShardedCache`] spreads
+//! programs over several of them, each behind its own lock, for use from
+//! many threads, and serves hits on programs in memory without locking at
+//! all. The global cache the daemon uses is sharded.
+//!
+//! [`CompilationCache::from_env`] reads the byte budget from
+//! `PYRUST_CACHE_MAX_BYTES`, the TTL from `PYRUST_CACHE_TTL_SECS`, the
+//! eviction policy from `PYRUST_CACHE_POLICY`, and enables the disk tier when
+//! `PYRUST_CACHE_DIR` names a directory, or when `PYRUST_DISK_CACHE=1`, in
+//! which case files go under [`default_disk_dir`]. `PYRUST_SHARED_CACHE`
+//! names the shared cache file, or with `1` uses [`shared::DEFAULT_FILE_NAME`]
+//! under [`default_disk_dir`].
+
+use std::borrow::Cow;
 use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
+use std::fs;
 use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 
 use crate::bytecode::Bytecode;
+use crate::lexer::{self, TokenKind};
+
+pub mod eviction;
+mod sharded;
+pub mod shared;
+
+pub use eviction::{policy_from_name, EntryInfo, EvictionPolicy};
+pub use sharded::ShardedCache;
+pub use shared::SharedFile;
 
-/// LRU cache for compiled bytecode
+/// Extension of disk cache entries
+const DISK_EXTENSION: &str = "pyc-cache";
+
+/// Bounded cache for compiled bytecode
 /// Uses HashMap for O(1) lookup + collision detection via full source storage
 pub struct CompilationCache {
     /// Map from source code hash to cached entry
@@ -19,24 +83,74 @@ pub struct CompilationCache {
     /// Maximum number of entries
     capacity: usize,
 
-    /// Monotonic timestamp for LRU tracking
+    /// Maximum total size of the entries in bytes (None = unbounded)
+    max_bytes: Option<usize>,
+
+    /// Total size of the entries in bytes
+    bytes: usize,
+
+    /// Logical clock, ticking on every insert and hit
     timestamp: u64,
 
+    /// Bumped whenever an entry is added to or removed from memory
+    generation: u64,
+
+    /// Keys of the entries dropped from memory since last taken, kept only
+    /// for [`ShardedCache`]'s lock-free snapshots
+    dropped: Option<Vec<String>>,
+
+    /// Chooses which entries to evict
+    policy: Box<dyn EvictionPolicy>,
+
+    /// Directory of the disk tier (None = memory only)
+    disk_dir: Option<PathBuf>,
+
+    /// File shared with other processes (None = not used)
+    shared: Option<SharedFile>,
+
+    /// Age after which entries are recompiled (None = never)
+    ttl: Option<Duration>,
+
     /// Statistics
     hits: usize,
     misses: usize,
+    disk_hits: usize,
+    shared_hits: usize,
+    expired: usize,
+    evictions: usize,
+
+    /// Compile time of every program served from memory, summed per hit
+    compile_time_saved: Duration,
 }
 
 /// Cached bytecode entry with full source for collision detection
 struct CacheEntry {
-    /// Full source code (for collision detection per PRD Risk R3)
-    source: String,
+    /// Full cache key (for collision detection per PRD Risk R3)
+    key: String,
 
     /// Compiled bytecode (Arc for cheap cloning)
     bytecode: Arc<Bytecode>,
 
+    /// Logical time of insertion
+    created: u64,
+
     /// Last access timestamp
     last_access: u64,
+
+    /// Hits since insertion
+    accesses: u64,
+
+    /// Bytes charged against the budget
+    size: usize,
+
+    /// When the bytecode was compiled, for TTL expiry
+    inserted: Instant,
+
+    /// When the entry was last inserted or hit
+    last_used: Instant,
+
+    /// Time it took to compile the program; zero when not known
+    compile_time: Duration,
 }
 
 impl CompilationCache {
@@ -46,102 +160,494 @@ impl CompilationCache {
         CompilationCache {
             entries: HashMap::new(),
             capacity,
+            max_bytes: None,
+            bytes: 0,
             timestamp: 0,
+            generation: 0,
+            dropped: None,
+            policy: Box::new(eviction::Lru),
+            disk_dir: None,
+            shared: None,
+            ttl: None,
             hits: 0,
             misses: 0,
+            disk_hits: 0,
+            shared_hits: 0,
+            expired: 0,
+            evictions: 0,
+            compile_time_saved: Duration::ZERO,
         }
     }
 
     /// Create cache with capacity from environment variable
     /// PYRUST_CACHE_SIZE controls capacity (default: 1000)
+    /// PYRUST_CACHE_MAX_BYTES bounds the memory tier's size (default: unbounded)
+    /// PYRUST_CACHE_TTL_SECS expires entries after that many seconds (default: never)
+    /// PYRUST_CACHE_POLICY names the eviction policy (default: lru)
+    /// PYRUST_CACHE_DIR or PYRUST_DISK_CACHE=1 enable the disk tier
+    /// PYRUST_SHARED_CACHE names the shared cache file, or 1 for the default one
     pub fn from_env() -> Self {
         let capacity = std::env::var("PYRUST_CACHE_SIZE")
             .ok()
             .and_then(|s| s.parse().ok())
             .unwrap_or(1000);
-        Self::new(capacity)
+        let mut cache = Self::new(capacity);
+        if let Some(max_bytes) = std::env::var("PYRUST_CACHE_MAX_BYTES")
+            .ok()
+            .and_then(|s| s.parse().ok())
+        {
+            cache = cache.with_max_bytes(max_bytes);
+        }
+        if let Some(secs) = std::env::var("PYRUST_CACHE_TTL_SECS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+        {
+            cache = cache.with_ttl(Duration::from_secs(secs));
+        }
+        if let Some(policy) = std::env::var("PYRUST_CACHE_POLICY")
+            .ok()
+            .and_then(|name| policy_from_name(&name))
+        {
+            cache.policy = policy;
+        }
+        let shared = match std::env::var_os("PYRUST_SHARED_CACHE") {
+            Some(value) if value == "1" => {
+                default_disk_dir().map(|dir| dir.join(shared::DEFAULT_FILE_NAME))
+            }
+            Some(path) if !path.is_empty() => Some(PathBuf::from(path)),
+            _ => None,
+        };
+        if let Some(path) = shared {
+            cache = cache.with_shared_file(path);
+        }
+
+        let disk_dir = match std::env::var_os("PYRUST_CACHE_DIR") {
+            Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+            _ if std::env::var("PYRUST_DISK_CACHE").is_ok_and(|v| v == "1") => default_disk_dir(),
+            _ => None,
+        };
+        match disk_dir {
+            Some(dir) => cache.with_disk_dir(dir),
+            None => cache,
+        }
+    }
+
+    /// Evict entries whenever their total size would exceed `max_bytes`
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self.evict_to_fit(0);
+        self
+    }
+
+    /// Evict entries in the order `policy` ranks them
+    pub fn with_policy(mut self, policy: impl EvictionPolicy + 'static) -> Self {
+        self.policy = Box::new(policy);
+        self
+    }
+
+    /// Recompile programs once their bytecode is `ttl` old
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Shrink the capacity and byte budget to a `shards`th, rounding up
+    fn divide(mut self, shards: usize) -> Self {
+        self.capacity = self.capacity.div_ceil(shards);
+        self.max_bytes = self.max_bytes.map(|max_bytes| max_bytes.div_ceil(shards));
+        self
+    }
+
+    /// Whether bytecode compiled `age` ago is past the TTL
+    fn is_expired(&self, age: Duration) -> bool {
+        self.ttl.is_some_and(|ttl| age >= ttl)
+    }
+
+    /// Also keep entries on disk under `dir`, created on first insert
+    pub fn with_disk_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.disk_dir = Some(dir.into());
+        self
+    }
+
+    /// Directory of the disk tier, if enabled
+    pub fn disk_dir(&self) -> Option<&Path> {
+        self.disk_dir.as_deref()
+    }
+
+    /// Also keep entries in the cache file at `path`, shared with other
+    /// processes and created on first insert
+    pub fn with_shared_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.shared = Some(SharedFile::new(path));
+        self
+    }
+
+    /// Path of the shared cache file, if enabled
+    pub fn shared_file(&self) -> Option<&Path> {
+        self.shared.as_ref().map(SharedFile::path)
     }
 
     /// Get bytecode from cache
     /// Returns Some(Arc<Bytecode>) on hit, None on miss
+    ///
+    /// Falls back to the shared file, then the disk tier, on a memory miss; a
+    /// program found there is kept in memory for later lookups.
     pub fn get(&mut self, code: &str) -> Option<Arc<Bytecode>> {
-        let hash = Self::hash_code(code);
+        self.get_key(&cache_key(code))
+    }
 
-        if let Some(entry) = self.entries.get_mut(&hash) {
-            // COLLISION DETECTION: verify full source matches (PRD Risk R3)
-            if entry.source == code {
-                self.hits += 1;
+    /// [`CompilationCache::get`] for a key already made by [`cache_key`]
+    fn get_key(&mut self, key: &str) -> Option<Arc<Bytecode>> {
+        if let Some(bytecode) = self.get_memory(key) {
+            self.hits += 1;
+            return Some(bytecode);
+        }
+
+        if let Some((bytecode, age)) = self.read_shared(key) {
+            self.hits += 1;
+            self.shared_hits += 1;
+            let bytecode = Arc::new(bytecode);
+            self.insert_memory(key.to_string(), Arc::clone(&bytecode), age, Duration::ZERO);
+            return Some(bytecode);
+        }
+
+        if let Some((bytecode, age)) = self.read_disk(key) {
+            self.hits += 1;
+            self.disk_hits += 1;
+            let bytecode = Arc::new(bytecode);
+            self.insert_memory(key.to_string(), Arc::clone(&bytecode), age, Duration::ZERO);
+            return Some(bytecode);
+        }
 
+        self.misses += 1;
+        None
+    }
+
+    /// Look up `key` in memory, dropping it if it has expired
+    fn get_memory(&mut self, key: &str) -> Option<Arc<Bytecode>> {
+        let hash = Self::hash_code(key);
+
+        let expired = self
+            .entries
+            .get(&hash)
+            .is_some_and(|entry| entry.key == key && self.is_expired(entry.inserted.elapsed()));
+        if expired {
+            if let Some(entry) = self.entries.remove(&hash) {
+                self.forget(entry);
+            }
+            self.expired += 1;
+            return None;
+        }
+
+        if let Some(entry) = self.entries.get_mut(&hash) {
+            // COLLISION DETECTION: verify full key matches (PRD Risk R3)
+            if entry.key == key {
                 // Update LRU timestamp (no need to update lru_order vector)
                 self.timestamp += 1;
                 entry.last_access = self.timestamp;
+                entry.accesses += 1;
+                entry.last_used = Instant::now();
+                self.compile_time_saved += entry.compile_time;
 
                 return Some(Arc::clone(&entry.bytecode));
             } else {
-                // Hash collision: different source with same hash
+                // Hash collision: different key with same hash
                 // Treat as miss (rare, acceptable to recompile)
-                self.misses += 1;
                 return None;
             }
         }
 
-        self.misses += 1;
         None
     }
 
     /// Insert compiled bytecode into cache
-    /// Evicts LRU entry if capacity exceeded
+    /// Evicts an entry if capacity exceeded
+    ///
+    /// With a disk tier or shared file the program is also written there.
+    /// Write failures are ignored: those only ever save work.
     pub fn insert(&mut self, code: String, bytecode: Arc<Bytecode>) {
+        self.insert_timed(code, bytecode, Duration::ZERO);
+    }
+
+    /// [`CompilationCache::insert`] for bytecode that took `compile_time` to
+    /// compile
+    ///
+    /// Each later hit on the entry adds `compile_time` to
+    /// [`CacheStats::compile_time_saved`].
+    pub fn insert_timed(&mut self, code: String, bytecode: Arc<Bytecode>, compile_time: Duration) {
+        self.insert_key(owned_cache_key(code), bytecode, compile_time);
+    }
+
+    /// [`CompilationCache::insert_timed`] for a key already made by
+    /// [`cache_key`]
+    fn insert_key(&mut self, key: String, bytecode: Arc<Bytecode>, compile_time: Duration) {
+        if let Some(shared) = &self.shared {
+            let _ = shared.insert(&key, &bytecode);
+        }
+        if self.disk_dir.is_some() {
+            self.write_disk(&key, &bytecode);
+        }
+        self.insert_memory(key, bytecode, Duration::ZERO, compile_time);
+    }
+
+    /// Insert into memory only, for bytecode compiled `age` ago
+    fn insert_memory(
+        &mut self,
+        key: String,
+        bytecode: Arc<Bytecode>,
+        age: Duration,
+        compile_time: Duration,
+    ) {
         // Don't insert if capacity is zero
         if self.capacity == 0 {
             return;
         }
 
-        let hash = Self::hash_code(&code);
+        let hash = Self::hash_code(&key);
 
         // Check if already cached (update)
-        if self.entries.contains_key(&hash) {
-            self.entries.remove(&hash);
+        if let Some(old) = self.entries.remove(&hash) {
+            self.forget(old);
+        }
+
+        let size = key.len() + bytecode.memory_footprint();
+        if self.max_bytes.is_some_and(|max_bytes| size > max_bytes) {
+            // Evicting everything would still not make room
+            return;
         }
 
         // Check capacity and evict if needed
         if self.entries.len() >= self.capacity {
-            self.evict_lru();
+            self.evict_one();
         }
+        self.evict_to_fit(size);
 
         // Insert entry
         self.timestamp += 1;
         let entry = CacheEntry {
-            source: code,
+            key,
             bytecode,
+            created: self.timestamp,
             last_access: self.timestamp,
+            accesses: 0,
+            size,
+            inserted: Instant::now().checked_sub(age).unwrap_or_else(Instant::now),
+            last_used: Instant::now(),
+            compile_time,
         };
 
+        self.bytes += size;
         self.entries.insert(hash, entry);
+        self.generation += 1;
+    }
+
+    /// Count `hits` hits on `key` made elsewhere, the last of them at
+    /// `last_used`
+    ///
+    /// Updates the entry's recency and hit count as [`CompilationCache::get`]
+    /// would have. Hits on an entry since dropped still count as hits.
+    fn record_hits(&mut self, key: &str, hits: u64, last_used: Instant) {
+        self.hits += hits as usize;
+        let hash = Self::hash_code(key);
+        if let Some(entry) = self.entries.get_mut(&hash).filter(|entry| entry.key == key) {
+            self.timestamp += 1;
+            entry.last_access = self.timestamp;
+            entry.accesses += hits;
+            entry.last_used = entry.last_used.max(last_used);
+            self.compile_time_saved += entry
+                .compile_time
+                .saturating_mul(u32::try_from(hits).unwrap_or(u32::MAX));
+        }
+    }
+
+    /// Key, bytecode and compile instant of every entry in memory
+    fn memory_entries(&self) -> impl Iterator<Item = (&str, &Arc<Bytecode>, Instant)> {
+        self.entries
+            .values()
+            .map(|entry| (entry.key.as_str(), &entry.bytecode, entry.inserted))
+    }
+
+    /// Evict entries until `incoming` more bytes fit the budget
+    fn evict_to_fit(&mut self, incoming: usize) {
+        let Some(max_bytes) = self.max_bytes else {
+            return;
+        };
+        while !self.entries.is_empty() && self.bytes + incoming > max_bytes {
+            self.evict_one();
+        }
     }
 
-    /// Evict least recently used entry
+    /// Evict the entry the policy ranks lowest
     /// O(n) but acceptable for 1000 entry capacity
-    fn evict_lru(&mut self) {
-        if self.entries.is_empty() {
+    fn evict_one(&mut self) {
+        let now = self.timestamp;
+        let victim = self
+            .entries
+            .iter()
+            .min_by_key(|(_, entry)| {
+                let info = EntryInfo {
+                    size: entry.size,
+                    inserted: entry.created,
+                    last_access: entry.last_access,
+                    accesses: entry.accesses,
+                };
+                (self.policy.rank(&info, now), entry.last_access)
+            })
+            .map(|(hash, _)| *hash);
+
+        if let Some(evicted) = victim.and_then(|hash| self.entries.remove(&hash)) {
+            self.evictions += 1;
+            self.forget(evicted);
+        }
+    }
+
+    /// Account for `entry`, just removed from memory
+    fn forget(&mut self, entry: CacheEntry) {
+        self.bytes -= entry.size;
+        self.generation += 1;
+        if let Some(dropped) = &mut self.dropped {
+            dropped.push(entry.key);
+        }
+    }
+
+    /// Path of the disk entry for `key`
+    ///
+    /// The name hashes only the key, so the entry an older release wrote for
+    /// a program is the one a newer release replaces.
+    fn disk_path(dir: &Path, key: &str) -> PathBuf {
+        let hash = fnv1a(&[key.as_bytes()]);
+        dir.join(format!("{:016x}.{}", hash, DISK_EXTENSION))
+    }
+
+    /// Load and verify the disk entry for `key`, with its age
+    ///
+    /// An entry is the [`entry_version`] as a big-endian `u64`, the key
+    /// length as a `u32`, the key, and the serialized bytecode. Entries from
+    /// another version, or expired, count as missing.
+    fn read_disk(&mut self, key: &str) -> Option<(Bytecode, Duration)> {
+        let path = Self::disk_path(self.disk_dir.as_deref()?, key);
+        let modified = fs::metadata(&path).and_then(|meta| meta.modified()).ok()?;
+        // A clock set backwards makes the file look new rather than old
+        let age = SystemTime::now()
+            .duration_since(modified)
+            .unwrap_or_default();
+        if self.is_expired(age) {
+            self.expired += 1;
+            return None;
+        }
+
+        let bytes = fs::read(&path).ok()?;
+        let (version, rest) = bytes.split_first_chunk::<8>()?;
+        if u64::from_be_bytes(*version) != entry_version() {
+            return None;
+        }
+        let (length, rest) = rest.split_first_chunk::<4>()?;
+        let (stored, program) = rest.split_at_checked(u32::from_be_bytes(*length) as usize)?;
+        if stored != key.as_bytes() {
+            return None;
+        }
+        let bytecode = Bytecode::from_bytes(program).ok()?;
+        bytecode.verify().ok()?;
+        Some((bytecode, age))
+    }
+
+    /// Load the shared file's entry for `key`, with its age, unless expired
+    fn read_shared(&mut self, key: &str) -> Option<(Bytecode, Duration)> {
+        let (bytecode, age) = self.shared.as_ref()?.get(key)?;
+        if self.is_expired(age) {
+            self.expired += 1;
+            return None;
+        }
+        Some((bytecode, age))
+    }
+
+    /// Write the disk entry for `key`, replacing any existing one atomically
+    fn write_disk(&self, key: &str, bytecode: &Bytecode) {
+        let Some(dir) = self.disk_dir.as_deref() else {
+            return;
+        };
+        let Ok(length) = u32::try_from(key.len()) else {
             return;
+        };
+        let mut bytes = Vec::with_capacity(12 + key.len());
+        bytes.extend_from_slice(&entry_version().to_be_bytes());
+        bytes.extend_from_slice(&length.to_be_bytes());
+        bytes.extend_from_slice(key.as_bytes());
+        bytes.extend_from_slice(&bytecode.to_bytes());
+
+        let path = Self::disk_path(dir, key);
+        // Readers must never see a partly written entry
+        let temp = path.with_extension(format!("{}.tmp", std::process::id()));
+        let written = fs::create_dir_all(dir)
+            .and_then(|_| fs::write(&temp, &bytes))
+            .and_then(|_| fs::rename(&temp, &path));
+        if written.is_err() {
+            let _ = fs::remove_file(&temp);
         }
+    }
 
-        // Find oldest entry by minimum last_access timestamp
-        let mut oldest_hash = 0u64;
-        let mut oldest_time = u64::MAX;
+    /// Drop the entry for `code` from memory and the disk tier
+    ///
+    /// Returns whether memory held it. The shared file keeps its copy until
+    /// it expires or is cleared.
+    pub fn remove(&mut self, code: &str) -> bool {
+        self.remove_key(&cache_key(code))
+    }
 
-        for (hash, entry) in &self.entries {
-            if entry.last_access < oldest_time {
-                oldest_time = entry.last_access;
-                oldest_hash = *hash;
-            }
+    /// [`CompilationCache::remove`] for a key already made by [`cache_key`]
+    fn remove_key(&mut self, key: &str) -> bool {
+        if let Some(dir) = self.disk_dir.as_deref() {
+            let _ = fs::remove_file(Self::disk_path(dir, key));
+        }
+        let hash = Self::hash_code(key);
+        match self.entries.get(&hash) {
+            Some(entry) if entry.key == key => {}
+            _ => return false,
         }
+        if let Some(entry) = self.entries.remove(&hash) {
+            self.forget(entry);
+        }
+        true
+    }
 
-        self.entries.remove(&oldest_hash);
+    /// Compile each script in `paths` and insert it, disk tier included
+    ///
+    /// Scripts are compiled as [`crate::execute_python`] would compile them,
+    /// and replace any entry already cached for them. A script that cannot
+    /// be read or compiled is reported and skipped.
+    pub fn warm(&mut self, paths: &[PathBuf]) -> WarmReport {
+        warm_with(paths, |code, bytecode, compile_time| {
+            self.insert_timed(code, bytecode, compile_time)
+        })
     }
 
-    /// Hash source code using DefaultHasher (SipHash 1-3)
+    /// Remove every entry of the disk tier and the shared file
+    ///
+    /// Returns the number of entries removed; a missing directory or file has
+    /// none. Entries in memory are kept; see [`CompilationCache::clear`].
+    pub fn clear_disk(&self) -> io::Result<usize> {
+        let mut removed = match &self.shared {
+            Some(shared) => shared.clear()?,
+            None => 0,
+        };
+        let Some(dir) = self.disk_dir.as_deref() else {
+            return Ok(removed);
+        };
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(removed),
+            Err(e) => return Err(e),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == DISK_EXTENSION) {
+                fs::remove_file(&path)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Hash a cache key using DefaultHasher (SipHash 1-3)
     /// Provides cryptographic-quality collision resistance
     fn hash_code(code: &str) -> u64 {
         let mut hasher = DefaultHasher::new();
@@ -154,8 +660,17 @@ impl CompilationCache {
         CacheStats {
             hits: self.hits,
             misses: self.misses,
+            disk_hits: self.disk_hits,
+            shared_hits: self.shared_hits,
+            expired: self.expired,
+            evictions: self.evictions,
+            compile_time_saved: self.compile_time_saved,
+            hottest: self.hottest(),
+            policy: self.policy.name(),
             size: self.entries.len(),
             capacity: self.capacity,
+            bytes: self.bytes,
+            max_bytes: self.max_bytes,
             hit_rate: if self.hits + self.misses > 0 {
                 self.hits as f64 / (self.hits + self.misses) as f64
             } else {
@@ -164,13 +679,343 @@ impl CompilationCache {
         }
     }
 
+    /// Summaries of the entries in memory, largest first
+    pub fn entries(&self) -> Vec<EntrySummary> {
+        let mut entries: Vec<_> = self
+            .entries
+            .iter()
+            .map(|(&hash, entry)| entry.summary(hash))
+            .collect();
+        sort_entries(&mut entries);
+        entries
+    }
+
+    /// Summaries of the [`TOP_ENTRIES`] most hit entries, most hit first
+    fn hottest(&self) -> Vec<EntrySummary> {
+        let mut hottest: Vec<_> = self.entries.iter().collect();
+        hottest.sort_by_key(|(&hash, entry)| (std::cmp::Reverse(entry.accesses), hash));
+        hottest.truncate(TOP_ENTRIES);
+        hottest
+            .into_iter()
+            .map(|(&hash, entry)| entry.summary(hash))
+            .collect()
+    }
+
     /// Clear all entries
+    ///
+    /// Only the memory tier is cleared; see [`CompilationCache::clear_disk`].
     pub fn clear(&mut self) {
         self.entries.clear();
+        self.generation += 1;
+        self.bytes = 0;
         self.timestamp = 0;
         self.hits = 0;
         self.misses = 0;
+        self.disk_hits = 0;
+        self.shared_hits = 0;
+        self.expired = 0;
+        self.evictions = 0;
+        self.compile_time_saved = Duration::ZERO;
+    }
+}
+
+impl CacheEntry {
+    fn summary(&self, hash: u64) -> EntrySummary {
+        EntrySummary {
+            hash,
+            preview: key_preview(&self.key),
+            size: self.size,
+            hits: self.accesses,
+            compile_time: self.compile_time,
+            age: self.inserted.elapsed(),
+            idle: self.last_used.elapsed(),
+        }
+    }
+}
+
+/// Key under which the bytecode for `code` is cached
+///
+/// Lists each token with its line and column, so sources that differ only
+/// in trailing spaces, or in blank lines at the end, share a key. Both are
+/// kept because the bytecode records them for runtime errors, which would
+/// otherwise point into the copy that was compiled rather than the one that
+/// ran. Source that does not lex is its own key.
+fn cache_key(code: &str) -> Cow<'_, str> {
+    let Ok(tokens) = lexer::lex(code) else {
+        return Cow::Borrowed(code);
+    };
+    let mut key = String::with_capacity(code.len());
+    for token in tokens {
+        match token.kind {
+            // Implied by the lines of the tokens around them
+            TokenKind::Newline | TokenKind::Eof => {}
+            // Empty, and placed wherever the block happens to close
+            TokenKind::Indent => key.push_str(">\n"),
+            TokenKind::Dedent => key.push_str("<\n"),
+            _ => {
+                key.push_str(&format!("{}:{} ", token.line, token.column));
+                key.push_str(token.text);
+                key.push('\n');
+            }
+        }
+    }
+    Cow::Owned(key)
+}
+
+/// [`cache_key`] of an owned source, reusing it when it is its own key
+fn owned_cache_key(code: String) -> String {
+    match cache_key(&code) {
+        Cow::Borrowed(_) => code,
+        Cow::Owned(key) => key,
+    }
+}
+
+/// Compile each script in `paths` and hand it to `insert`
+fn warm_with(
+    paths: &[PathBuf],
+    mut insert: impl FnMut(String, Arc<Bytecode>, Duration),
+) -> WarmReport {
+    let mut report = WarmReport::default();
+    for path in paths {
+        let compiled = fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|code| {
+                let start = Instant::now();
+                match crate::compile_python(&code) {
+                    Ok(bytecode) => Ok((code, bytecode, start.elapsed())),
+                    Err(e) => Err(e.to_string()),
+                }
+            });
+        match compiled {
+            Ok((code, bytecode, compile_time)) => {
+                insert(code, Arc::new(bytecode), compile_time);
+                report.compiled += 1;
+            }
+            Err(message) => report.failed.push((path.clone(), message)),
+        }
+    }
+    report
+}
+
+/// Version of the compiler that wrote a persisted entry
+///
+/// Hashes the crate version with [`crate::bytecode_format::FORMAT_VERSION`].
+/// Disk entries and the shared file record it, and an entry recorded with
+/// any other version is a miss that the next insert replaces, so bytecode
+/// from an older or newer pyrust is never decoded.
+fn entry_version() -> u64 {
+    fnv1a(&[
+        env!("CARGO_PKG_VERSION").as_bytes(),
+        b"\0",
+        &crate::bytecode_format::FORMAT_VERSION.to_be_bytes(),
+    ])
+}
+
+/// FNV-1a of `parts` in order
+///
+/// Used for what other processes read because, unlike `DefaultHasher`, its
+/// output is fixed across Rust versions.
+fn fnv1a(parts: &[&[u8]]) -> u64 {
+    parts
+        .iter()
+        .flat_map(|part| part.iter())
+        .fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+/// Default directory of the disk tier: `$XDG_CACHE_HOME/pyrust`, falling
+/// back to `~/.cache/pyrust`
+///
+/// Returns None when neither variable is set.
+pub fn default_disk_dir() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_CACHE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => {
+            PathBuf::from(std::env::var_os("HOME").filter(|home| !home.is_empty())?).join(".cache")
+        }
+    };
+    Some(base.join("pyrust"))
+}
+
+/// Characters of source shown in [`EntrySummary::preview`]
+const PREVIEW_CHARS: usize = 60;
+
+/// Description of one cached program, from [`CompilationCache::entries`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntrySummary {
+    /// Hash of the cache key
+    pub hash: u64,
+    /// Start of the program on one line, tokens separated by spaces and
+    /// lines by `"; "`
+    pub preview: String,
+    /// Bytes charged against the budget
+    pub size: usize,
+    /// Hits since the entry was inserted
+    pub hits: u64,
+    /// Time the program took to compile; zero for programs loaded from disk
+    /// or inserted without a time
+    pub compile_time: Duration,
+    /// Time since the program was compiled
+    pub age: Duration,
+    /// Time since the entry was last hit, or inserted
+    pub idle: Duration,
+}
+
+/// Entries kept in [`CacheStats`] for [`CacheStats::top_entries`]
+pub const TOP_ENTRIES: usize = 10;
+
+/// Sort summaries largest first, ties by hash so the order is stable
+fn sort_entries(entries: &mut [EntrySummary]) {
+    entries.sort_by_key(|entry| (std::cmp::Reverse(entry.size), entry.hash));
+}
+
+/// Keep the [`TOP_ENTRIES`] most hit of `entries`, most hit first
+fn keep_hottest(entries: &mut Vec<EntrySummary>) {
+    entries.sort_by_key(|entry| (std::cmp::Reverse(entry.hits), entry.hash));
+    entries.truncate(TOP_ENTRIES);
+}
+
+/// Table of `entries`, one per line under a header
+pub fn format_entries(entries: &[EntrySummary]) -> String {
+    let mut out = format!(
+        "{:<16}  {:>8}  {:>6}  {:>10}  {:>8}  {:>8}  SOURCE\n",
+        "HASH", "BYTES", "HITS", "COMPILE", "AGE", "IDLE"
+    );
+    for entry in entries {
+        out.push_str(&format!(
+            "{:016x}  {:>8}  {:>6}  {:>8}us  {:>7}s  {:>7}s  {}\n",
+            entry.hash,
+            entry.size,
+            entry.hits,
+            entry.compile_time.as_micros(),
+            entry.age.as_secs(),
+            entry.idle.as_secs(),
+            entry.preview
+        ));
+    }
+    out
+}
+
+/// Summary of `stats` with its hottest entries, as `--daemon-status` shows
+pub fn format_summary(stats: &CacheStats) -> String {
+    let mut out = format!(
+        "Cache: {} entries, {} hits, {} misses ({:.1}% hit rate)\n\
+         Compile time saved: {:.3}ms\n",
+        stats.size,
+        stats.hits,
+        stats.misses,
+        stats.hit_rate * 100.0,
+        stats.compile_time_saved.as_secs_f64() * 1000.0
+    );
+    let hottest = stats.top_entries(5);
+    if hottest.first().is_some_and(|entry| entry.hits > 0) {
+        out.push_str("Hottest entries:\n");
+        for entry in hottest.iter().take_while(|entry| entry.hits > 0) {
+            out.push_str(&format!(
+                "  {:>6} hits  {:>8}us  {}\n",
+                entry.hits,
+                entry.compile_time.as_micros(),
+                entry.preview
+            ));
+        }
+    }
+    out
+}
+
+/// Every counter of `stats` as `name value` lines, durations in seconds
+///
+/// `max_bytes` is left out for a cache bounded by entry count only.
+pub fn format_stats(stats: &CacheStats) -> String {
+    let mut out = format!(
+        "policy {}
+size {}
+capacity {}
+bytes {}
+",
+        stats.policy, stats.size, stats.capacity, stats.bytes
+    );
+    if let Some(max_bytes) = stats.max_bytes {
+        out.push_str(&format!(
+            "max_bytes {}
+",
+            max_bytes
+        ));
     }
+    out.push_str(&format!(
+        "hits {}
+misses {}
+disk_hits {}
+shared_hits {}
+expired {}
+evictions {}
+         hit_rate {}
+compile_time_saved_seconds {}
+",
+        stats.hits,
+        stats.misses,
+        stats.disk_hits,
+        stats.shared_hits,
+        stats.expired,
+        stats.evictions,
+        stats.hit_rate,
+        stats.compile_time_saved.as_secs_f64()
+    ));
+    out
+}
+
+/// Readable form of a key made by [`cache_key`], cut to [`PREVIEW_CHARS`]
+fn key_preview(key: &str) -> String {
+    // Each line of a token key is a block marker or a position and a token
+    let tokens: Option<Vec<_>> = key
+        .lines()
+        .filter(|entry| !matches!(*entry, ">" | "<"))
+        .map(|entry| {
+            let (position, text) = entry.split_once(' ')?;
+            let (line, _column) = position.split_once(':')?;
+            line.parse::<usize>().ok().map(|line| (line, text))
+        })
+        .collect();
+
+    let mut preview = String::new();
+    match tokens {
+        Some(tokens) => {
+            let mut current_line = None;
+            for (line, text) in tokens {
+                if current_line.is_some() {
+                    preview.push_str(if current_line == Some(line) {
+                        " "
+                    } else {
+                        "; "
+                    });
+                }
+                current_line = Some(line);
+                preview.push_str(text);
+            }
+        }
+        // Source that did not lex is its own key
+        None => {
+            let lines: Vec<_> = key
+                .lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty())
+                .collect();
+            preview = lines.join("; ");
+        }
+    }
+    match preview.char_indices().nth(PREVIEW_CHARS) {
+        Some((end, _)) => format!("{}...", &preview[..end]),
+        None => preview,
+    }
+}
+
+/// Outcome of [`CompilationCache::warm`]
+#[derive(Debug, Default)]
+pub struct WarmReport {
+    /// Scripts compiled and inserted
+    pub compiled: usize,
+    /// Scripts skipped, with why
+    pub failed: Vec<(PathBuf, String)>,
 }
 
 /// Cache statistics
@@ -178,11 +1023,39 @@ impl CompilationCache {
 pub struct CacheStats {
     pub hits: usize,
     pub misses: usize,
+    /// Hits served by the disk tier (included in `hits`)
+    pub disk_hits: usize,
+    /// Hits served by the shared cache file (included in `hits`)
+    pub shared_hits: usize,
+    /// Lookups that found an entry past its TTL (included in `misses`)
+    pub expired: usize,
+    /// Entries evicted to respect the capacity or byte budget
+    pub evictions: usize,
+    /// Compile time the memory tier's hits avoided
+    pub compile_time_saved: Duration,
+    /// The [`TOP_ENTRIES`] most hit entries, most hit first; see
+    /// [`CacheStats::top_entries`]
+    pub hottest: Vec<EntrySummary>,
+    /// Name of the eviction policy
+    pub policy: &'static str,
     pub size: usize,
     pub capacity: usize,
+    /// Total size of the cached entries, as charged against `max_bytes`
+    pub bytes: usize,
+    /// Byte budget (None = bounded by entry count only)
+    pub max_bytes: Option<usize>,
     pub hit_rate: f64,
 }
 
+impl CacheStats {
+    /// The `n` most hit entries, most hit first
+    ///
+    /// At most [`TOP_ENTRIES`] are kept, so fewer may be returned.
+    pub fn top_entries(&self, n: usize) -> &[EntrySummary] {
+        &self.hottest[..n.min(self.hottest.len())]
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -652,17 +1525,31 @@ mod tests {
         let code3 = "\n\n";
         let bytecode = create_bytecode_arc(0);
 
-        // Different whitespace should be cached separately
+        // None of them has a token, so they share an entry
         cache.insert(code1.to_string(), bytecode.clone());
         cache.insert(code2.to_string(), bytecode.clone());
         cache.insert(code3.to_string(), bytecode);
 
-        assert_eq!(cache.stats().size, 3);
+        assert_eq!(cache.stats().size, 1);
         assert!(cache.get(code1).is_some());
         assert!(cache.get(code2).is_some());
         assert!(cache.get(code3).is_some());
     }
 
+    #[test]
+    fn test_trailing_whitespace_shares_an_entry() {
+        let mut cache = CompilationCache::new(10);
+        cache.insert("x = 1 + 2\nprint(x)".to_string(), create_bytecode_arc(3));
+
+        assert!(cache.get("x = 1 + 2  \nprint(x)\t\n\n").is_some());
+        // Different tokens, or the same tokens in other places, are other
+        // programs: their runtime errors point elsewhere
+        assert!(cache.get("x = 1 + 3\nprint(x)").is_none());
+        assert!(cache.get("x=1+2\nprint(x)").is_none());
+        assert!(cache.get("\nx = 1 + 2\nprint(x)").is_none());
+        assert_eq!(cache.stats().size, 1);
+    }
+
     #[test]
     fn test_very_long_source_code() {
         // Edge case: very long source code
@@ -1053,4 +1940,378 @@ mod tests {
         assert_eq!(stats.misses, 3);
         assert!((stats.hit_rate - 0.625).abs() < 0.001); // 5/8 = 0.625
     }
+
+    #[test]
+    fn test_byte_budget_evicts_least_recently_used() {
+        // Entries with one-digit values all have the same size
+        let size = cache_key("x = 0").len() + create_bytecode(0).memory_footprint();
+
+        // Room for three entries, but not four
+        let mut cache = CompilationCache::new(100).with_max_bytes(size * 3 + size / 2);
+        for i in 0..3 {
+            cache.insert(format!("x = {}", i), create_bytecode_arc(i));
+        }
+        assert_eq!(cache.stats().size, 3);
+        assert_eq!(cache.stats().bytes, size * 3);
+
+        // Touch "x = 0" so "x = 1" is the least recently used
+        cache.get("x = 0");
+        cache.insert("x = 3".to_string(), create_bytecode_arc(3));
+
+        let stats = cache.stats();
+        assert_eq!(stats.size, 3);
+        assert!(stats.bytes <= stats.max_bytes.unwrap());
+        assert!(cache.get("x = 1").is_none());
+        assert!(cache.get("x = 0").is_some());
+        assert!(cache.get("x = 3").is_some());
+    }
+
+    #[test]
+    fn test_byte_budget_accounts_for_replaced_and_oversized_entries() {
+        let mut cache = CompilationCache::new(100).with_max_bytes(10_000);
+        cache.insert("42".to_string(), create_bytecode_arc(42));
+        let bytes = cache.stats().bytes;
+        assert!(bytes > 0);
+
+        // Replacing an entry does not count it twice
+        cache.insert("42".to_string(), create_bytecode_arc(42));
+        assert_eq!(cache.stats().bytes, bytes);
+
+        // A program bigger than the whole budget is not cached, and does
+        // not push anything else out
+        let big = "x = 1\n".repeat(2000);
+        cache.insert(big.clone(), create_bytecode_arc(1));
+        assert!(cache.get(&big).is_none());
+        assert!(cache.get("42").is_some());
+
+        // Shrinking the budget evicts right away
+        let cache = cache.with_max_bytes(bytes - 1);
+        assert_eq!(cache.stats().size, 0);
+        assert_eq!(cache.stats().bytes, 0);
+    }
+
+    #[test]
+    fn test_ttl_expires_entries() {
+        let mut cache = CompilationCache::new(10).with_ttl(Duration::ZERO);
+        cache.insert("a".to_string(), create_bytecode_arc(1));
+        assert!(cache.get("a").is_none());
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.expired), (0, 1, 1));
+        // The expired entry is dropped along with its bytes
+        assert_eq!((stats.size, stats.bytes), (0, 0));
+
+        let mut cache = CompilationCache::new(10).with_ttl(Duration::from_secs(3600));
+        cache.insert("a".to_string(), create_bytecode_arc(1));
+        assert!(cache.get("a").is_some());
+        assert_eq!(cache.stats().expired, 0);
+    }
+
+    #[test]
+    fn test_policies_choose_different_victims() {
+        // "a" is inserted first and used most, "b" is the largest, and "c"
+        // is the least recently used
+        let victim = |policy: Box<dyn EvictionPolicy>| {
+            let mut cache = CompilationCache::new(3);
+            cache.policy = policy;
+            cache.insert("a".to_string(), create_bytecode_arc(1));
+            let long = "x = x + 1\n".repeat(100);
+            cache.insert(
+                "b".to_string(),
+                Arc::new(crate::compile_python(&format!("x = 0\n{}print(x)", long)).unwrap()),
+            );
+            cache.insert("c".to_string(), create_bytecode_arc(3));
+            cache.get("a");
+            cache.get("a");
+            cache.get("b");
+            cache.insert("d".to_string(), create_bytecode_arc(4));
+
+            let stats = cache.stats();
+            assert_eq!((stats.size, stats.evictions), (3, 1));
+            ["a", "b", "c"]
+                .into_iter()
+                .find(|code| cache.get(code).is_none())
+                .unwrap()
+        };
+
+        assert_eq!(victim(Box::new(eviction::Lru)), "c");
+        assert_eq!(victim(Box::new(eviction::Fifo)), "a");
+        assert_eq!(victim(Box::new(eviction::SizeWeighted)), "b");
+        // "c" is the only entry never hit
+        assert_eq!(victim(Box::new(eviction::Lfu)), "c");
+    }
+
+    #[test]
+    fn test_policy_and_evictions_in_stats() {
+        let mut cache = CompilationCache::new(1).with_policy(eviction::Fifo);
+        assert_eq!(cache.stats().policy, "fifo");
+        for i in 0..4 {
+            cache.insert(format!("x = {}", i), create_bytecode_arc(i));
+        }
+        assert_eq!(cache.stats().evictions, 3);
+        cache.clear();
+        assert_eq!(cache.stats().evictions, 0);
+
+        assert_eq!(policy_from_name("lfu").unwrap().name(), "lfu");
+        assert!(policy_from_name("random").is_none());
+    }
+
+    #[test]
+    fn test_entries_describe_cached_programs() {
+        let mut cache = CompilationCache::new(10);
+        let small = "x = 1\nprint(x)";
+        let large = "y = 2\nz = y * (y + 1)\nprint(z)";
+        cache.insert(small.to_string(), create_bytecode_arc(1));
+        cache.insert(
+            large.to_string(),
+            Arc::new(crate::compile_python(large).unwrap()),
+        );
+        cache.get(small);
+        cache.get(small);
+
+        let entries = cache.entries();
+        assert_eq!(entries.len(), 2);
+        assert!(entries[0].size > entries[1].size);
+        assert_eq!(entries[0].preview, "y = 2; z = y * ( y + 1 ); print ( z )");
+        assert_eq!(entries[1].preview, "x = 1; print ( x )");
+        assert_eq!((entries[0].hits, entries[1].hits), (0, 2));
+        assert_eq!(
+            entries[1].hash,
+            CompilationCache::hash_code(&cache_key(small))
+        );
+
+        let table = format_entries(&entries);
+        assert_eq!(table.lines().count(), 3);
+        assert!(table
+            .lines()
+            .nth(2)
+            .unwrap()
+            .ends_with("x = 1; print ( x )"));
+    }
+
+    #[test]
+    fn test_top_entries_and_compile_time_saved() {
+        let mut cache = CompilationCache::new(20);
+        for i in 0..12 {
+            let code = format!("print({})", i);
+            cache.insert_timed(
+                code.clone(),
+                create_bytecode_arc(i),
+                Duration::from_millis(2),
+            );
+            for _ in 0..i {
+                cache.get(&code);
+            }
+        }
+        cache.insert("print(99)".to_string(), create_bytecode_arc(99));
+        cache.get("print(99)");
+
+        let stats = cache.stats();
+        // Untimed entries save nothing
+        assert_eq!(stats.compile_time_saved, Duration::from_millis(2 * 66));
+        assert_eq!(stats.hottest.len(), TOP_ENTRIES);
+        let top = stats.top_entries(3);
+        assert_eq!(
+            top.iter().map(|entry| entry.hits).collect::<Vec<_>>(),
+            vec![11, 10, 9]
+        );
+        assert_eq!(top[0].preview, "print ( 11 )");
+        assert_eq!(top[0].compile_time, Duration::from_millis(2));
+        assert!(top[0].idle <= top[0].age);
+        assert_eq!(stats.top_entries(50).len(), TOP_ENTRIES);
+
+        let summary = format_summary(&stats);
+        assert!(summary.contains("Compile time saved: 132.000ms\n"));
+        assert_eq!(summary.lines().filter(|l| l.contains(" hits ")).count(), 5);
+        let counters = format_stats(&stats);
+        assert!(counters.contains("\ncompile_time_saved_seconds 0.132\n"));
+        assert!(counters.contains(&format!("\nsize {}\n", stats.size)));
+
+        cache.clear();
+        assert_eq!(cache.stats().compile_time_saved, Duration::ZERO);
+        assert!(!format_summary(&cache.stats()).contains("Hottest"));
+    }
+
+    #[test]
+    fn test_key_preview_is_cut_and_handles_raw_keys() {
+        let long = format!("print({})", "1 + ".repeat(30) + "1");
+        let preview = key_preview(&cache_key(&long));
+        assert!(preview.ends_with("..."));
+        assert_eq!(preview.chars().count(), PREVIEW_CHARS + 3);
+
+        // Source that does not lex is shown as written
+        assert_eq!(key_preview(&cache_key("x = $\n  y")), "x = $; y");
+    }
+
+    /// Empty private directory for a disk tier test
+    fn disk_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("pyrust-cache-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_disk_tier_survives_a_new_cache() {
+        let dir = disk_dir("survives");
+        let code = "x = 6\nprint(x * 7)";
+        let bytecode = Arc::new(crate::compile_python(code).unwrap());
+
+        let mut first = CompilationCache::new(10).with_disk_dir(&dir);
+        first.insert(code.to_string(), Arc::clone(&bytecode));
+
+        // A fresh cache, as in a new process, loads the program from disk
+        let mut second = CompilationCache::new(10).with_disk_dir(&dir);
+        assert_eq!(second.get(code).as_deref(), Some(&*bytecode));
+        assert!(second.get("print(1)").is_none());
+        // The second lookup of `code` is served from memory
+        assert!(second.get(code).is_some());
+        let stats = second.stats();
+        assert_eq!((stats.hits, stats.disk_hits, stats.misses), (2, 1, 1));
+        assert_eq!(stats.size, 1);
+
+        assert_eq!(second.clear_disk().unwrap(), 1);
+        assert!(CompilationCache::new(10)
+            .with_disk_dir(&dir)
+            .get(code)
+            .is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_remove_drops_memory_and_disk_entries() {
+        let dir = disk_dir("remove");
+        let code = "x = 6\nprint(x * 7)";
+        let mut cache = CompilationCache::new(10).with_disk_dir(&dir);
+        cache.insert(code.to_string(), create_bytecode_arc(1));
+        cache.insert("x = 2".to_string(), create_bytecode_arc(2));
+
+        // Keyed like lookups, so trailing spaces do not matter
+        assert!(cache.remove("x = 6  \nprint(x * 7)\n"));
+        assert!(!cache.remove(code));
+        assert!(cache.get(code).is_none());
+        assert!(cache.get("x = 2").is_some());
+        assert_eq!(cache.stats().size, 1);
+        assert_eq!(cache.clear_disk().unwrap(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_disk_tier_rejects_bad_entries() {
+        let dir = disk_dir("bad-entries");
+        let code = "1 + 2";
+        let mut cache = CompilationCache::new(0).with_disk_dir(&dir);
+        cache.insert(code.to_string(), create_bytecode_arc(3));
+        let path = CompilationCache::disk_path(&dir, &cache_key(code));
+        let valid = fs::read(&path).unwrap();
+
+        // Another source under the same name, as after a hash collision
+        let mut collided = valid.clone();
+        collided[12] = b'9';
+        fs::write(&path, &collided).unwrap();
+        assert!(cache.get(code).is_none());
+
+        // An entry written by another version
+        let mut foreign = valid.clone();
+        foreign[..8].copy_from_slice(&(entry_version() ^ 1).to_be_bytes());
+        fs::write(&path, &foreign).unwrap();
+        assert!(cache.get(code).is_none());
+
+        // A truncated program
+        fs::write(&path, &valid[..valid.len() - 1]).unwrap();
+        assert!(cache.get(code).is_none());
+
+        // A program that decodes but fails verification
+        let mut bytecode = create_bytecode(3);
+        bytecode.constants.clear();
+        cache.insert(code.to_string(), Arc::new(bytecode));
+        assert!(cache.get(code).is_none());
+
+        // Inserting again repairs the entry
+        cache.insert(code.to_string(), create_bytecode_arc(3));
+        assert_eq!(cache.get(code).unwrap().constants, vec![3]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_memory_only_cache_never_touches_disk() {
+        let cache = CompilationCache::new(10);
+        assert!(cache.disk_dir().is_none());
+        assert_eq!(cache.clear_disk().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_ttl_applies_to_disk_entries() {
+        let dir = disk_dir("ttl");
+        let code = "print(1)";
+        CompilationCache::new(10)
+            .with_disk_dir(&dir)
+            .insert(code.to_string(), create_bytecode_arc(1));
+
+        let mut expired = CompilationCache::new(10)
+            .with_disk_dir(&dir)
+            .with_ttl(Duration::ZERO);
+        assert!(expired.get(code).is_none());
+        assert_eq!(expired.stats().expired, 1);
+
+        let mut fresh = CompilationCache::new(10)
+            .with_disk_dir(&dir)
+            .with_ttl(Duration::from_secs(3600));
+        assert!(fresh.get(code).is_some());
+        assert_eq!(fresh.stats().disk_hits, 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_shared_file_serves_other_caches() {
+        let dir = disk_dir("shared");
+        let file = dir.join(shared::DEFAULT_FILE_NAME);
+        let code = "x = 6\nprint(x * 7)";
+        let mut writer = CompilationCache::new(10).with_shared_file(&file);
+        writer.insert(code.to_string(), create_bytecode_arc(42));
+        assert!(file.exists());
+
+        // A cache in another process starts out empty
+        let mut reader = CompilationCache::new(10).with_shared_file(&file);
+        assert!(reader.get("x = 6  \nprint(x * 7)\n").is_some());
+        assert!(reader.get(code).is_some());
+        let stats = reader.stats();
+        assert_eq!((stats.hits, stats.shared_hits, stats.size), (2, 1, 1));
+
+        let mut expired = CompilationCache::new(10)
+            .with_shared_file(&file)
+            .with_ttl(Duration::ZERO);
+        assert!(expired.get(code).is_none());
+        assert_eq!(expired.stats().expired, 1);
+
+        assert_eq!(reader.clear_disk().unwrap(), 1);
+        assert!(!file.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_warm_compiles_scripts_into_the_disk_tier() {
+        let dir = disk_dir("warm");
+        let scripts = disk_dir("warm-scripts");
+        fs::create_dir_all(&scripts).unwrap();
+        let good = scripts.join("good.py");
+        let bad = scripts.join("bad.py");
+        fs::write(&good, "x = 6\nprint(x * 7)").unwrap();
+        fs::write(&bad, "print(").unwrap();
+
+        let report = CompilationCache::new(10).with_disk_dir(&dir).warm(&[
+            good,
+            bad.clone(),
+            scripts.join("missing.py"),
+        ]);
+        assert_eq!(report.compiled, 1);
+        assert_eq!(report.failed.len(), 2);
+        assert_eq!(report.failed[0].0, bad);
+
+        // A new cache finds the warmed script without compiling it
+        let mut cache = CompilationCache::new(10).with_disk_dir(&dir);
+        assert!(cache.get("x = 6\nprint(x * 7)").is_some());
+        assert_eq!(cache.stats().disk_hits, 1);
+        fs::remove_dir_all(&dir).unwrap();
+        fs::remove_dir_all(&scripts).unwrap();
+    }
 }
@@ -0,0 +1,114 @@
+//! Policies choosing which cache entry to evict
+//!
+//! When the cache is full, or over its byte budget, it asks its
+//! [`EvictionPolicy`] to rank every entry and evicts the lowest ranked one;
+//! ties go to the least recently used. Times are logical: the cache's clock
+//! ticks once per insert and per hit, so they order events without
+//! depending on how fast requests arrive.
+//!
+//! | Name   | Policy           | Evicts first                                 |
+//! |--------|------------------|----------------------------------------------|
+//! | `lru`  | [`Lru`]          | the least recently used entry                |
+//! | `lfu`  | [`Lfu`]          | the entry with the fewest hits               |
+//! | `fifo` | [`Fifo`]         | the oldest entry, however often it is used   |
+//! | `size` | [`SizeWeighted`] | large entries that have not been used lately |
+//!
+//! The names are those accepted by [`policy_from_name`] and the
+//! `PYRUST_CACHE_POLICY` environment variable.
+
+/// What a policy can see of a cache entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EntryInfo {
+    /// Bytes charged against the budget
+    pub size: usize,
+    /// Logical time of the entry's insertion
+    pub inserted: u64,
+    /// Logical time of the entry's last hit, or its insertion
+    pub last_access: u64,
+    /// Hits since insertion
+    pub accesses: u64,
+}
+
+/// Ranks cache entries for eviction
+pub trait EvictionPolicy: Send {
+    /// Name reported in [`crate::cache::CacheStats::policy`]
+    fn name(&self) -> &'static str;
+
+    /// Rank of `entry` at logical time `now`; the lowest is evicted first
+    fn rank(&self, entry: &EntryInfo, now: u64) -> u64;
+}
+
+/// Least recently used
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Lru;
+
+impl EvictionPolicy for Lru {
+    fn name(&self) -> &'static str {
+        "lru"
+    }
+
+    fn rank(&self, entry: &EntryInfo, _: u64) -> u64 {
+        entry.last_access
+    }
+}
+
+/// Least frequently used
+///
+/// Suits workloads with a stable set of hot programs: a burst of one-off
+/// programs cannot push them out, as it would under LRU.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Lfu;
+
+impl EvictionPolicy for Lfu {
+    fn name(&self) -> &'static str {
+        "lfu"
+    }
+
+    fn rank(&self, entry: &EntryInfo, _: u64) -> u64 {
+        entry.accesses
+    }
+}
+
+/// First in, first out
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Fifo;
+
+impl EvictionPolicy for Fifo {
+    fn name(&self) -> &'static str {
+        "fifo"
+    }
+
+    fn rank(&self, entry: &EntryInfo, _: u64) -> u64 {
+        entry.inserted
+    }
+}
+
+/// Idle time weighted by size
+///
+/// An entry's cost is its size times the ticks since it was last used, and
+/// the costliest goes first. Under a byte budget this frees more room per
+/// eviction than LRU, at the price of recompiling large programs more often.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SizeWeighted;
+
+impl EvictionPolicy for SizeWeighted {
+    fn name(&self) -> &'static str {
+        "size"
+    }
+
+    fn rank(&self, entry: &EntryInfo, now: u64) -> u64 {
+        let idle = now.saturating_sub(entry.last_access) + 1;
+        u64::MAX - (entry.size as u64).saturating_mul(idle)
+    }
+}
+
+/// The built-in policy called `name`, as listed in the module docs
+pub fn policy_from_name(name: &str) -> Option<Box<dyn EvictionPolicy>> {
+    match name {
+        "lru" => Some(Box::new(Lru)),
+        "lfu" => Some(Box::new(Lfu)),
+        "fifo" => Some(Box::new(Fifo)),
+        "size" => Some(Box::new(SizeWeighted)),
+        _ => None,
+    }
+}
@@ -0,0 +1,470 @@
+//! A compilation cache split across independently locked shards
+//!
+//! A single `Mutex<CompilationCache>` makes every daemon thread wait for
+//! whichever one is looking up or inserting. [`ShardedCache`] spreads
+//! programs over several caches by key hash, each behind its own lock, so
+//! threads only contend when their programs land in the same shard.
+//!
+//! Capacity and byte budget are divided evenly between the shards, and each
+//! evicts on its own, so a shard can evict while another still has room.
+//! Statistics are summed over the shards.
+//!
+//! # Lock-free Hits
+//!
+//! Even sharded, a program every connection runs keeps its shard's lock
+//! busy. So each shard also publishes a snapshot of the programs in its
+//! memory tier through an [`ArcSwap`]. A lookup tries the snapshot first and
+//! only takes the lock on a miss, to fall back to the shared file and disk
+//! tier, or to drop an expired entry.
+//!
+//! Rebuilding the snapshot costs a copy of the shard's index, so it is not
+//! done on every insert, which would make a stream of new programs pay for
+//! it each time. It is done when a locked lookup finds a program the
+//! snapshot lacks: one being run again. Entries dropped from memory are
+//! taken out of the snapshot as they go, by publishing a copy without them,
+//! so it never serves a program the cache no longer holds nor keeps its
+//! bytecode alive. Only programs that were run again are in the snapshot to
+//! be taken out, so a stream of new programs does not pay for the copy
+//! either.
+//!
+//! Hits on the snapshot are counted in atomics on its entries and recorded
+//! in the shard's cache the next time its lock is taken, in the order they
+//! happened, so eviction policies and statistics see them as if they had
+//! gone through the lock. A count of the hits not yet recorded lets the
+//! lock skip looking for them when there are none. A hit racing the removal
+//! of its entry may go uncounted.
+
+use super::{
+    cache_key, keep_hottest, owned_cache_key, sort_entries, warm_with, CacheStats,
+    CompilationCache, EntrySummary, WarmReport,
+};
+use crate::bytecode::Bytecode;
+use arc_swap::ArcSwap;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// Shards used by [`ShardedCache::from_env`] unless `PYRUST_CACHE_SHARDS` is set
+const DEFAULT_SHARDS: usize = 16;
+
+/// A thread-safe compilation cache made of [`CompilationCache`] shards
+pub struct ShardedCache {
+    shards: Box<[Shard]>,
+}
+
+/// One shard: a cache, and the snapshot of its memory tier that lookups
+/// read without locking
+struct Shard {
+    cache: Mutex<CompilationCache>,
+    /// Programs in `cache`'s memory tier, by key
+    hot: ArcSwap<HashMap<String, Arc<HotEntry>>>,
+    /// Generation of `cache` that `hot` was taken from
+    published: AtomicU64,
+    /// Hits on `hot` not yet recorded in `cache`
+    pending: AtomicU64,
+    /// Age past which `cache` recompiles programs
+    ttl: Option<Duration>,
+    /// Instant hit times are counted from
+    epoch: Instant,
+}
+
+/// A program in a shard's snapshot
+struct HotEntry {
+    bytecode: Arc<Bytecode>,
+    /// When the bytecode was compiled, for TTL expiry
+    compiled: Instant,
+    /// Hits not yet recorded in the shard's cache
+    pending: AtomicU64,
+    /// Nanoseconds from the shard's epoch to the latest hit
+    last_hit: AtomicU64,
+}
+
+impl ShardedCache {
+    /// Cache of `shards` shards, each made by `make`
+    ///
+    /// The capacity and byte budget of each cache `make` returns are divided
+    /// by the shard count, so together the shards hold what one of them
+    /// would have. At least one shard is made.
+    pub fn new(shards: usize, mut make: impl FnMut() -> CompilationCache) -> Self {
+        let count = shards.max(1);
+        Self {
+            shards: (0..count)
+                .map(|_| Shard::new(make().divide(count)))
+                .collect(),
+        }
+    }
+
+    /// Shards configured by [`CompilationCache::from_env`]
+    ///
+    /// PYRUST_CACHE_SHARDS sets the shard count (default: 16).
+    /// The other variables configure the cache as a whole: PYRUST_CACHE_SIZE,
+    /// for instance, is the total capacity across shards.
+    pub fn from_env() -> Self {
+        let shards = std::env::var("PYRUST_CACHE_SHARDS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(DEFAULT_SHARDS);
+        Self::new(shards, CompilationCache::from_env)
+    }
+
+    /// Number of shards
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Look up `code`; see [`CompilationCache::get`]
+    pub fn get(&self, code: &str) -> Option<Arc<Bytecode>> {
+        // Keyed before locking, so lexing the source does not hold the lock
+        let key = cache_key(code);
+        let shard = self.shard(&key);
+        shard.get_hot(&key).or_else(|| {
+            shard.locked(|cache| {
+                let bytecode = cache.get_key(&key);
+                // Found again: worth serving without the lock from now on
+                if bytecode.is_some() {
+                    shard.publish(cache);
+                }
+                bytecode
+            })
+        })
+    }
+
+    /// Insert `bytecode` for `code`; see [`CompilationCache::insert`]
+    pub fn insert(&self, code: String, bytecode: Arc<Bytecode>) {
+        self.insert_timed(code, bytecode, Duration::ZERO);
+    }
+
+    /// Insert with its compile time; see [`CompilationCache::insert_timed`]
+    pub fn insert_timed(&self, code: String, bytecode: Arc<Bytecode>, compile_time: Duration) {
+        let key = owned_cache_key(code);
+        self.shard(&key)
+            .locked(|cache| cache.insert_key(key, bytecode, compile_time));
+    }
+
+    /// Compile and insert scripts; see [`CompilationCache::warm`]
+    pub fn warm(&self, paths: &[PathBuf]) -> WarmReport {
+        warm_with(paths, |code, bytecode, compile_time| {
+            self.insert_timed(code, bytecode, compile_time)
+        })
+    }
+
+    /// Statistics summed over every shard
+    pub fn stats(&self) -> CacheStats {
+        let mut shards = self
+            .shards
+            .iter()
+            .map(|shard| shard.locked(|cache| cache.stats()));
+        let mut total = shards.next().expect("a sharded cache has a shard");
+        for stats in shards {
+            total.hits += stats.hits;
+            total.misses += stats.misses;
+            total.disk_hits += stats.disk_hits;
+            total.shared_hits += stats.shared_hits;
+            total.expired += stats.expired;
+            total.evictions += stats.evictions;
+            total.compile_time_saved += stats.compile_time_saved;
+            total.hottest.extend(stats.hottest);
+            total.size += stats.size;
+            total.capacity += stats.capacity;
+            total.bytes += stats.bytes;
+            total.max_bytes = total.max_bytes.zip(stats.max_bytes).map(|(a, b)| a + b);
+        }
+        keep_hottest(&mut total.hottest);
+        let lookups = total.hits + total.misses;
+        total.hit_rate = if lookups > 0 {
+            total.hits as f64 / lookups as f64
+        } else {
+            0.0
+        };
+        total
+    }
+
+    /// Summaries of the entries of every shard, largest first
+    pub fn entries(&self) -> Vec<EntrySummary> {
+        let mut entries: Vec<_> = self
+            .shards
+            .iter()
+            .flat_map(|shard| shard.locked(|cache| cache.entries()))
+            .collect();
+        sort_entries(&mut entries);
+        entries
+    }
+
+    /// Drop the entry for `code`; see [`CompilationCache::remove`]
+    pub fn remove(&self, code: &str) -> bool {
+        let key = cache_key(code);
+        self.shard(&key).locked(|cache| cache.remove_key(&key))
+    }
+
+    /// Clear every shard; see [`CompilationCache::clear`]
+    pub fn clear(&self) {
+        for shard in self.shards.iter() {
+            shard.locked(|cache| {
+                cache.clear();
+                shard.publish(cache);
+            });
+        }
+    }
+
+    /// Remove the disk tier's entries; see [`CompilationCache::clear_disk`]
+    ///
+    /// The shards share one directory and shared file, so clearing them
+    /// through any shard clears them for all.
+    pub fn clear_disk(&self) -> io::Result<usize> {
+        lock(&self.shards[0].cache).clear_disk()
+    }
+
+    /// Directory of the disk tier, if any
+    pub fn disk_dir(&self) -> Option<PathBuf> {
+        lock(&self.shards[0].cache)
+            .disk_dir()
+            .map(Path::to_path_buf)
+    }
+
+    fn shard(&self, key: &str) -> &Shard {
+        let index = CompilationCache::hash_code(key) % self.shards.len() as u64;
+        &self.shards[index as usize]
+    }
+}
+
+impl Shard {
+    fn new(mut cache: CompilationCache) -> Self {
+        cache.dropped = Some(Vec::new());
+        Self {
+            ttl: cache.ttl,
+            cache: Mutex::new(cache),
+            hot: ArcSwap::from_pointee(HashMap::new()),
+            published: AtomicU64::new(0),
+            pending: AtomicU64::new(0),
+            epoch: Instant::now(),
+        }
+    }
+
+    /// Look up `key` in the snapshot, without locking
+    ///
+    /// An expired entry is a miss here; the locked lookup drops it.
+    fn get_hot(&self, key: &str) -> Option<Arc<Bytecode>> {
+        let hot = self.hot.load();
+        let entry = hot.get(key)?;
+        if self.ttl.is_some_and(|ttl| entry.compiled.elapsed() >= ttl) {
+            return None;
+        }
+        let now = self.epoch.elapsed().as_nanos() as u64;
+        entry.last_hit.fetch_max(now, Ordering::Relaxed);
+        entry.pending.fetch_add(1, Ordering::Relaxed);
+        // After the entry's count, so a lock that sees this sees that
+        self.pending.fetch_add(1, Ordering::Release);
+        Some(Arc::clone(&entry.bytecode))
+    }
+
+    /// Run `f` on the locked cache, after recording the hits made on the
+    /// snapshot, and take the entries `f` dropped out of the snapshot
+    fn locked<T>(&self, f: impl FnOnce(&mut CompilationCache) -> T) -> T {
+        let mut cache = lock(&self.cache);
+        if self.pending.swap(0, Ordering::Acquire) > 0 {
+            self.record_hits(&mut cache, self.hot.load().iter());
+        }
+        let result = f(&mut cache);
+        let dropped = cache.dropped.as_mut().map(std::mem::take);
+        if let Some(dropped) = dropped.filter(|keys| !keys.is_empty()) {
+            self.prune(&mut cache, &dropped);
+        }
+        result
+    }
+
+    /// Publish a copy of the snapshot without the entries for `keys`, if it
+    /// holds any of them
+    ///
+    /// Taken after the locked operation, which may have published a
+    /// snapshot itself; hits that reached the entries are recorded.
+    fn prune(&self, cache: &mut CompilationCache, keys: &[String]) {
+        let old = self.hot.load_full();
+        if !keys.iter().any(|key| old.contains_key(key)) {
+            return;
+        }
+        let mut hot = HashMap::clone(&old);
+        let removed: Vec<_> = keys
+            .iter()
+            .filter_map(|key| hot.remove_entry(key))
+            .collect();
+        self.hot.store(Arc::new(hot));
+        self.record_hits(cache, removed.iter().map(|(key, entry)| (key, entry)));
+    }
+
+    /// Record the pending hits of `entries` in `cache`, oldest first
+    fn record_hits<'a>(
+        &self,
+        cache: &mut CompilationCache,
+        entries: impl Iterator<Item = (&'a String, &'a Arc<HotEntry>)>,
+    ) {
+        let mut hits: Vec<_> = entries
+            .filter_map(|(key, entry)| {
+                let count = entry.pending.swap(0, Ordering::Relaxed);
+                let last_hit = entry.last_hit.load(Ordering::Relaxed);
+                (count > 0).then_some((last_hit, key, count))
+            })
+            .collect();
+        hits.sort_unstable();
+        for (last_hit, key, count) in hits {
+            let last_used = self.epoch + Duration::from_nanos(last_hit);
+            cache.record_hits(key, count, last_used);
+        }
+    }
+
+    /// Replace the snapshot with the entries now in `cache`, unless it
+    /// already holds them
+    ///
+    /// Entries whose bytecode is unchanged carry over with their pending
+    /// hits; hits that reached the others before the swap are recorded.
+    fn publish(&self, cache: &mut CompilationCache) {
+        if cache.generation == self.published.load(Ordering::Relaxed) {
+            return;
+        }
+        let old = self.hot.load_full();
+        let hot: HashMap<_, _> = cache
+            .memory_entries()
+            .map(|(key, bytecode, compiled)| {
+                let entry = match old.get(key) {
+                    Some(entry) if Arc::ptr_eq(&entry.bytecode, bytecode) => Arc::clone(entry),
+                    _ => Arc::new(HotEntry {
+                        bytecode: Arc::clone(bytecode),
+                        compiled,
+                        pending: AtomicU64::new(0),
+                        last_hit: AtomicU64::new(0),
+                    }),
+                };
+                (key.to_string(), entry)
+            })
+            .collect();
+        let dropped: Vec<_> = old
+            .iter()
+            .filter(|(key, entry)| {
+                hot.get(*key)
+                    .is_none_or(|current| !Arc::ptr_eq(current, entry))
+            })
+            .collect();
+        self.hot.store(Arc::new(hot));
+        self.record_hits(cache, dropped.into_iter());
+        self.published.store(cache.generation, Ordering::Relaxed);
+    }
+}
+
+/// Lock a shard, recovering it if a thread panicked while holding it
+///
+/// Every operation leaves a shard consistent between statements that could
+/// panic, so the data is safe to keep using.
+fn lock(shard: &Mutex<CompilationCache>) -> MutexGuard<'_, CompilationCache> {
+    shard
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_shards_divide_capacity_and_sum_stats() {
+        let cache = ShardedCache::new(4, || CompilationCache::new(30).with_max_bytes(100_000));
+        assert_eq!(cache.shard_count(), 4);
+        let stats = cache.stats();
+        // Rounded up to 8 per shard
+        assert_eq!(stats.capacity, 32);
+        assert_eq!(stats.max_bytes, Some(100_000));
+
+        let bytecode = Arc::new(crate::compile_python("print(1)").unwrap());
+        for i in 0..6 {
+            cache.insert(format!("x = {}", i), Arc::clone(&bytecode));
+        }
+        assert!(cache.get("x = 3").is_some());
+        assert!(cache.get("x = 3  ").is_some());
+        assert!(cache.get("x = 9").is_none());
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.size), (2, 1, 6));
+        assert!(cache.remove("x = 5"));
+        assert!(cache.get("x = 5").is_none());
+        assert_eq!(cache.stats().size, 5);
+        cache.clear();
+        assert_eq!(cache.stats().size, 0);
+    }
+
+    #[test]
+    fn test_concurrent_access() {
+        let cache = Arc::new(ShardedCache::new(8, || CompilationCache::new(1000)));
+        let workers: Vec<_> = (0..4)
+            .map(|worker| {
+                let cache = Arc::clone(&cache);
+                thread::spawn(move || {
+                    for i in 0..50 {
+                        let code = format!("print({})", i);
+                        if cache.get(&code).is_none() {
+                            let bytecode = crate::compile_python(&code).unwrap();
+                            cache.insert(code, Arc::new(bytecode));
+                        }
+                    }
+                    worker
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+
+        let stats = cache.stats();
+        assert_eq!(stats.size, 50);
+        assert_eq!(stats.hits + stats.misses, 200);
+    }
+
+    #[test]
+    fn test_lock_free_hits_are_recorded() {
+        let cache = ShardedCache::new(1, || CompilationCache::new(2));
+        let bytecode = Arc::new(crate::compile_python("print(1)").unwrap());
+        cache.insert("x = 1".to_string(), Arc::clone(&bytecode));
+        cache.insert("x = 2".to_string(), Arc::clone(&bytecode));
+
+        // Served from the snapshot, then recorded before the next insert,
+        // which evicts the least recently used "x = 2"
+        for _ in 0..3 {
+            assert!(cache.get("x = 1").is_some());
+        }
+        cache.insert("x = 3".to_string(), bytecode);
+        assert!(cache.get("x = 1").is_some());
+        assert!(cache.get("x = 2").is_none());
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (4, 1, 1));
+    }
+
+    #[test]
+    fn test_evicted_programs_leave_the_snapshot() {
+        let cache = ShardedCache::new(1, || CompilationCache::new(1));
+        let bytecode = Arc::new(crate::compile_python("print(1)").unwrap());
+        cache.insert("x = 1".to_string(), Arc::clone(&bytecode));
+        // Found again, so published, then served from the snapshot
+        assert!(cache.get("x = 1").is_some());
+        assert!(cache.get("x = 1").is_some());
+
+        let other = Arc::new(crate::compile_python("print(2)").unwrap());
+        cache.insert("x = 2".to_string(), other);
+        assert!(cache.get("x = 1").is_none());
+        // Neither the cache nor its snapshot holds the evicted bytecode
+        assert_eq!(Arc::strong_count(&bytecode), 1);
+        assert_eq!(cache.stats().hits, 2);
+    }
+
+    #[test]
+    fn test_lock_free_hits_respect_ttl() {
+        let cache = ShardedCache::new(2, || CompilationCache::new(10).with_ttl(Duration::ZERO));
+        let bytecode = Arc::new(crate::compile_python("print(1)").unwrap());
+        cache.insert("x = 1".to_string(), bytecode);
+        assert!(cache.get("x = 1").is_none());
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.expired, stats.size), (0, 1, 0));
+    }
+}
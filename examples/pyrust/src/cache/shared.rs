@@ -0,0 +1,514 @@
+//! A single cache file that concurrent processes map into memory
+//!
+//! The disk tier costs a lookup an `open` and a `read` of its own file. A
+//! [`SharedFile`] instead keeps every program in one file that readers map
+//! read-only, so a short-lived CLI process run over and over from a shell
+//! loop finds its bytecode with a single mapping, and without a daemon.
+//!
+//! The file starts with a header and a fixed table of slots, followed by
+//! records appended one after the other:
+//!
+//! | Offset | Size       | Contents                                          |
+//! |--------|------------|---------------------------------------------------|
+//! | 0      | 8          | magic `PYRSHM01`                                  |
+//! | 8      | 4          | slot count                                        |
+//! | 12     | 4          | programs stored                                   |
+//! | 16     | 8          | version of the pyrust that wrote the file         |
+//! | 24     | 8          | offset at which the next record goes              |
+//! | 32     | 16 × slots | key hash and record offset per slot, 0 when free  |
+//!
+//! A record is the key length and program length as `u32`s, the Unix time at
+//! which it was written as a `u64`, the key, and the program in the
+//! [`crate::bytecode_format`] encoding. All integers are big-endian.
+//!
+//! Slots are probed linearly from the key's hash. Records are never moved or
+//! removed, so the file is read-mostly: once the slots or the size limit run
+//! out, further programs are simply not added until the file is cleared. A
+//! file written by another pyrust or bytecode format version is never read,
+//! and is started afresh by the next insert.
+//!
+//! Readers take a shared `flock` on the file and writers an exclusive one,
+//! so a reader never sees a half-written record, and since the file only
+//! grows while anyone has it locked, a mapping never outlives its pages.
+//! On Windows the locks are the platform's file locks, and readers read the
+//! file instead of mapping it.
+
+use super::{entry_version, fnv1a};
+use crate::bytecode::Bytecode;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+#[cfg(unix)]
+use std::os::unix::fs::FileExt;
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Name of the file under the default cache directory
+pub const DEFAULT_FILE_NAME: &str = "shared.pyc-shm";
+
+const MAGIC: &[u8; 8] = b"PYRSHM01";
+const HEADER_LEN: u64 = 32;
+const SLOT_LEN: u64 = 16;
+const RECORD_HEADER_LEN: usize = 16;
+
+/// Slots in a new file, and so the most programs it holds
+const DEFAULT_SLOTS: u32 = 4096;
+
+/// Size past which no more records are appended
+const DEFAULT_MAX_BYTES: u64 = 64 << 20;
+
+/// A cache file shared between processes; see the module docs
+#[derive(Debug, Clone)]
+pub struct SharedFile {
+    path: PathBuf,
+    slots: u32,
+    max_bytes: u64,
+}
+
+impl SharedFile {
+    /// Cache file at `path`, created on first insert
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            slots: DEFAULT_SLOTS,
+            max_bytes: DEFAULT_MAX_BYTES,
+        }
+    }
+
+    /// Number of slots a new file is laid out with
+    pub fn with_slots(mut self, slots: u32) -> Self {
+        self.slots = slots.max(1);
+        self
+    }
+
+    /// Stop appending once the file would grow past `max_bytes`
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Path of the file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Load and verify the program stored for `key`, with its age
+    ///
+    /// A missing, stale or corrupted file, or a record that fails to
+    /// verify, is a miss.
+    pub(super) fn get(&self, key: &str) -> Option<(Bytecode, Duration)> {
+        let file = File::open(&self.path).ok()?;
+        lock(&file, Lock::Shared).ok()?;
+        let len = file.metadata().ok()?.len();
+        let map = Mapping::new(&file, usize::try_from(len).ok()?).ok()?;
+        let record = find(map.bytes(), key)?;
+
+        let written = u64::from_be_bytes(record[8..16].try_into().ok()?);
+        let key_len = u32::from_be_bytes(record[0..4].try_into().ok()?) as usize;
+        let program = record.get(RECORD_HEADER_LEN + key_len..)?;
+        let bytecode = Bytecode::from_bytes(program).ok()?;
+        bytecode.verify().ok()?;
+        // A clock set backwards makes the record look new rather than old
+        let age = unix_secs().saturating_sub(written);
+        Some((bytecode, Duration::from_secs(age)))
+    }
+
+    /// Store `bytecode` under `key`
+    ///
+    /// A key already in the file gets a new record; its old one is left
+    /// unreachable. Does nothing once the slots or the size limit are used up.
+    pub(super) fn insert(&self, key: &str, bytecode: &Bytecode) -> io::Result<()> {
+        let program = bytecode.to_bytes();
+        let (Ok(key_len), Ok(program_len)) =
+            (u32::try_from(key.len()), u32::try_from(program.len()))
+        else {
+            return Ok(());
+        };
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&self.path)?;
+        lock(&file, Lock::Exclusive)?;
+
+        let mut header = [0; HEADER_LEN as usize];
+        let valid = file.metadata()?.len() >= HEADER_LEN
+            && file.read_exact_at(&mut header, 0).is_ok()
+            && header_is_current(&header);
+        if !valid {
+            header = new_header(self.slots);
+            file.set_len(0)?;
+            file.write_all_at(&header, 0)?;
+            file.set_len(data_start(self.slots))?;
+        }
+        let slots = u32::from_be_bytes(header[8..12].try_into().unwrap());
+        let count = u32::from_be_bytes(header[12..16].try_into().unwrap());
+        let end = u64::from_be_bytes(header[24..32].try_into().unwrap());
+
+        let hash = key_hash(key);
+        let mut free = None;
+        let mut replaced = false;
+        for probe in 0..u64::from(slots) {
+            let slot = hash.wrapping_add(probe) % u64::from(slots);
+            let mut entry = [0; SLOT_LEN as usize];
+            file.read_exact_at(&mut entry, HEADER_LEN + slot * SLOT_LEN)?;
+            let stored = u64::from_be_bytes(entry[..8].try_into().unwrap());
+            if stored == 0 {
+                free = Some(slot);
+                break;
+            }
+            if stored == hash {
+                let offset = u64::from_be_bytes(entry[8..].try_into().unwrap());
+                if self.record_key_is(&file, offset, key)? {
+                    free = Some(slot);
+                    replaced = true;
+                    break;
+                }
+            }
+        }
+        let Some(slot) = free else {
+            return Ok(());
+        };
+
+        let mut record = Vec::with_capacity(RECORD_HEADER_LEN + key.len() + program.len());
+        record.extend_from_slice(&key_len.to_be_bytes());
+        record.extend_from_slice(&program_len.to_be_bytes());
+        record.extend_from_slice(&unix_secs().to_be_bytes());
+        record.extend_from_slice(key.as_bytes());
+        record.extend_from_slice(&program);
+        let new_end = end + record.len() as u64;
+        if new_end > self.max_bytes {
+            return Ok(());
+        }
+
+        // The record lands before the slot points at it
+        file.write_all_at(&record, end)?;
+        let mut entry = [0; SLOT_LEN as usize];
+        entry[..8].copy_from_slice(&hash.to_be_bytes());
+        entry[8..].copy_from_slice(&end.to_be_bytes());
+        file.write_all_at(&entry, HEADER_LEN + slot * SLOT_LEN)?;
+        let count = if replaced { count } else { count + 1 };
+        file.write_all_at(&count.to_be_bytes(), 12)?;
+        file.write_all_at(&new_end.to_be_bytes(), 24)
+    }
+
+    /// Remove the file, returning how many programs it held
+    pub fn clear(&self) -> io::Result<usize> {
+        let count = match File::open(&self.path) {
+            Ok(file) => {
+                let mut header = [0; HEADER_LEN as usize];
+                match file.read_exact_at(&mut header, 0) {
+                    Ok(()) if header_is_current(&header) => {
+                        u32::from_be_bytes(header[12..16].try_into().unwrap()) as usize
+                    }
+                    _ => 0,
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e),
+        };
+        // Processes with the file mapped keep the unlinked copy until done
+        fs::remove_file(&self.path)?;
+        Ok(count)
+    }
+
+    fn record_key_is(&self, file: &File, offset: u64, key: &str) -> io::Result<bool> {
+        let mut header = [0; RECORD_HEADER_LEN];
+        file.read_exact_at(&mut header, offset)?;
+        if u32::from_be_bytes(header[..4].try_into().unwrap()) as usize != key.len() {
+            return Ok(false);
+        }
+        let mut stored = vec![0; key.len()];
+        file.read_exact_at(&mut stored, offset + RECORD_HEADER_LEN as u64)?;
+        Ok(stored == key.as_bytes())
+    }
+}
+
+/// The record stored for `key` in a mapped file, if any
+fn find<'a>(bytes: &'a [u8], key: &str) -> Option<&'a [u8]> {
+    let header = bytes.get(..HEADER_LEN as usize)?;
+    if !header_is_current(header) {
+        return None;
+    }
+    let slots = u64::from(u32::from_be_bytes(header[8..12].try_into().ok()?));
+    let hash = key_hash(key);
+    for probe in 0..slots {
+        let at = (HEADER_LEN + hash.wrapping_add(probe) % slots * SLOT_LEN) as usize;
+        let entry = bytes.get(at..at + SLOT_LEN as usize)?;
+        let stored = u64::from_be_bytes(entry[..8].try_into().ok()?);
+        if stored == 0 {
+            return None;
+        }
+        if stored != hash {
+            continue;
+        }
+        let offset = usize::try_from(u64::from_be_bytes(entry[8..].try_into().ok()?)).ok()?;
+        let record = bytes.get(offset..)?;
+        let key_len = u32::from_be_bytes(record.get(0..4)?.try_into().ok()?) as usize;
+        let program_len = u32::from_be_bytes(record.get(4..8)?.try_into().ok()?) as usize;
+        let record = record.get(..RECORD_HEADER_LEN + key_len + program_len)?;
+        if &record[RECORD_HEADER_LEN..RECORD_HEADER_LEN + key_len] == key.as_bytes() {
+            return Some(record);
+        }
+    }
+    None
+}
+
+fn new_header(slots: u32) -> [u8; HEADER_LEN as usize] {
+    let mut header = [0; HEADER_LEN as usize];
+    header[..8].copy_from_slice(MAGIC);
+    header[8..12].copy_from_slice(&slots.to_be_bytes());
+    header[16..24].copy_from_slice(&entry_version().to_be_bytes());
+    header[24..32].copy_from_slice(&data_start(slots).to_be_bytes());
+    header
+}
+
+fn header_is_current(header: &[u8]) -> bool {
+    header.len() >= HEADER_LEN as usize
+        && &header[..8] == MAGIC
+        && header[8..12] != [0; 4]
+        && header[16..24] == entry_version().to_be_bytes()
+}
+
+fn data_start(slots: u32) -> u64 {
+    HEADER_LEN + u64::from(slots) * SLOT_LEN
+}
+
+/// Slot hash of `key`; never 0, which marks a free slot
+fn key_hash(key: &str) -> u64 {
+    fnv1a(&[key.as_bytes()]).max(1)
+}
+
+fn unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+/// Lock taken on the file while it is used
+#[derive(Debug, Clone, Copy)]
+enum Lock {
+    /// Readers
+    Shared,
+    /// The writer
+    Exclusive,
+}
+
+/// `flock` `file`; the lock goes when the file is closed
+#[cfg(unix)]
+fn lock(file: &File, lock: Lock) -> io::Result<()> {
+    let operation = match lock {
+        Lock::Shared => libc::LOCK_SH,
+        Lock::Exclusive => libc::LOCK_EX,
+    };
+    // SAFETY: the descriptor is open for as long as `file` is borrowed
+    if unsafe { libc::flock(file.as_raw_fd(), operation) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Lock `file`; the lock goes when the file is closed
+#[cfg(windows)]
+fn lock(file: &File, lock: Lock) -> io::Result<()> {
+    match lock {
+        Lock::Shared => file.lock_shared(),
+        Lock::Exclusive => file.lock(),
+    }
+}
+
+/// Positioned reads and writes, as the Unix `FileExt` has them
+#[cfg(windows)]
+trait FileExt {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()>;
+    fn write_all_at(&self, buf: &[u8], offset: u64) -> io::Result<()>;
+}
+
+#[cfg(windows)]
+impl FileExt for File {
+    fn read_exact_at(&self, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+        use std::os::windows::fs::FileExt as _;
+        while !buf.is_empty() {
+            match self.seek_read(buf, offset)? {
+                0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                n => {
+                    let rest = buf;
+                    buf = &mut rest[n..];
+                    offset += n as u64;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write_all_at(&self, mut buf: &[u8], mut offset: u64) -> io::Result<()> {
+        use std::os::windows::fs::FileExt as _;
+        while !buf.is_empty() {
+            match self.seek_write(buf, offset)? {
+                0 => return Err(io::ErrorKind::WriteZero.into()),
+                n => {
+                    buf = &buf[n..];
+                    offset += n as u64;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A copy of a whole file, read rather than mapped
+#[cfg(windows)]
+struct Mapping {
+    bytes: Vec<u8>,
+}
+
+#[cfg(windows)]
+impl Mapping {
+    fn new(file: &File, len: usize) -> io::Result<Self> {
+        let mut bytes = vec![0; len];
+        file.read_exact_at(&mut bytes, 0)?;
+        Ok(Self { bytes })
+    }
+
+    fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+/// A read-only mapping of a whole file
+#[cfg(unix)]
+struct Mapping {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+#[cfg(unix)]
+impl Mapping {
+    fn new(file: &File, len: usize) -> io::Result<Self> {
+        if len == 0 {
+            return Ok(Self {
+                ptr: std::ptr::null_mut(),
+                len,
+            });
+        }
+        // SAFETY: the kernel picks a new address range, so no existing memory
+        // is affected; failure is reported as MAP_FAILED
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { ptr, len })
+    }
+
+    fn bytes(&self) -> &[u8] {
+        if self.len == 0 {
+            return &[];
+        }
+        // SAFETY: the mapping covers `len` readable bytes until dropped, and
+        // writers, which only append, are held off by the reader's lock
+        unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        if self.len > 0 {
+            // SAFETY: unmaps exactly the range mapped in `new`
+            unsafe {
+                libc::munmap(self.ptr, self.len);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("pyrust-shared-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir.join(DEFAULT_FILE_NAME)
+    }
+
+    #[test]
+    fn test_programs_round_trip_through_the_file() {
+        let path = temp_path("round_trip");
+        let shared = SharedFile::new(&path).with_slots(8);
+        let one = crate::compile_python("print(1)").unwrap();
+        let two = crate::compile_python("x = 2\nprint(x)").unwrap();
+        assert!(shared.get("one").is_none());
+
+        shared.insert("one", &one).unwrap();
+        shared.insert("two", &two).unwrap();
+        // Replacing a key leaves the count alone
+        shared.insert("one", &one).unwrap();
+
+        // Another handle, as another process would have
+        let other = SharedFile::new(&path);
+        let (bytecode, age) = other.get("two").unwrap();
+        assert_eq!(bytecode.to_bytes(), two.to_bytes());
+        assert!(age < Duration::from_secs(5));
+        assert!(other.get("one").is_some());
+        assert!(other.get("three").is_none());
+
+        assert_eq!(other.clear().unwrap(), 2);
+        assert!(shared.get("one").is_none());
+        assert_eq!(shared.clear().unwrap(), 0);
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_full_or_corrupted_files_are_misses() {
+        let path = temp_path("limits");
+        let bytecode = crate::compile_python("print(1)").unwrap();
+        let shared = SharedFile::new(&path).with_slots(2);
+        for key in ["a", "b", "c"] {
+            shared.insert(key, &bytecode).unwrap();
+        }
+        assert!(shared.get("b").is_some());
+        assert!(shared.get("c").is_none());
+
+        // Room for the slots but not a record
+        fs::remove_file(&path).unwrap();
+        let capped = SharedFile::new(&path)
+            .with_slots(2)
+            .with_max_bytes(data_start(2) + 8);
+        capped.insert("a", &bytecode).unwrap();
+        assert!(capped.get("a").is_none());
+
+        // A foreign file is a miss, and is replaced by the next insert
+        fs::write(&path, b"not a cache file").unwrap();
+        assert!(shared.get("a").is_none());
+        shared.insert("a", &bytecode).unwrap();
+        assert!(shared.get("a").is_some());
+
+        // So is one written by another version
+        let mut foreign = fs::read(&path).unwrap();
+        foreign[16..24].copy_from_slice(&(entry_version() ^ 1).to_be_bytes());
+        fs::write(&path, &foreign).unwrap();
+        assert!(shared.get("a").is_none());
+        shared.insert("b", &bytecode).unwrap();
+        assert!(shared.get("a").is_none());
+        assert!(shared.get("b").is_some());
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+}
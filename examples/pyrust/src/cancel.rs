@@ -0,0 +1,72 @@
+//! Cooperative cancellation of running executions
+//!
+//! A [`CancellationToken`] is a shared flag. Attach a clone to a VM with
+//! [`crate::vm::VM::set_cancellation_token`] (or pass it to
+//! [`crate::execute_python_cancellable`]) and call [`CancellationToken::cancel`]
+//! from any thread; the VM notices within a few hundred instructions and
+//! fails with an "execution cancelled" runtime error. Nothing is interrupted
+//! mid-instruction, so the VM stays usable afterwards.
+//!
+//! # Example
+//!
+//! ```
+//! use pyrust::cancel::CancellationToken;
+//!
+//! let token = CancellationToken::new();
+//! token.cancel();
+//!
+//! let err = pyrust::execute_python_cancellable("1 + 2", &token).unwrap_err();
+//! assert!(err.to_string().contains("execution cancelled"));
+//! ```
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Message of the runtime error raised when a token is cancelled
+pub const CANCELLED_MESSAGE: &str = "execution cancelled";
+
+/// Shared flag that asks running executions to stop
+///
+/// Clones share the same flag. Once cancelled, a token stays cancelled.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    flag: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /// Create a token that has not been cancelled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask every execution holding this token to stop
+    pub fn cancel(&self) {
+        self.flag.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether [`CancellationToken::cancel`] has been called
+    pub fn is_cancelled(&self) -> bool {
+        self.flag.load(Ordering::Relaxed)
+    }
+
+    /// The underlying flag, for wiring the token to signal handlers
+    pub fn flag(&self) -> &Arc<AtomicBool> {
+        &self.flag
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clones_share_the_flag() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert!(!clone.is_cancelled());
+
+        token.cancel();
+        assert!(clone.is_cancelled());
+        assert!(clone.flag().load(Ordering::Relaxed));
+    }
+}
@@ -1,17 +1,32 @@
 //! AST to Bytecode Compiler
 //!
-//! Single-pass compiler that transforms AST into register-based bytecode.
+//! Compilation runs as a [`Pipeline`] of named passes: the program is
+//! resolved with [`crate::resolver`], optimized at the AST level, and then
+//! transformed into register-based bytecode in a single code generation
+//! pass. [`CompilerBuilder`] lets embedders add passes of their own.
 //! Implements register allocation and critical SetResult emission rules.
 
-use crate::ast::{Expression, Program, Statement, UnaryOperator};
+pub mod pipeline;
+
+pub use pipeline::{AstPass, BytecodePass, CompilerBuilder, Pipeline};
+
+use crate::ast::simplify;
+use crate::ast::{ExprSpan, Expression, Program, SourcePos, Statement, StatementPos};
 use crate::bytecode::{Bytecode, BytecodeBuilder};
 use crate::error::CompileError;
+use crate::error_code::ErrorCode;
+use crate::resolver::SymbolTable;
+use crate::warnings::{self, CompileWarning};
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 
 #[cfg(test)]
-use crate::ast::BinaryOperator;
+use crate::ast::{BinaryOperator, UnaryOperator};
 
 /// Variable name interner for eliminating String allocations at runtime
+///
+/// IDs are handed out from 0 in the order names are first interned, so the
+/// same program always compiles to the same IDs.
 pub struct VariableInterner {
     /// Map from variable name to interned ID
     name_to_id: HashMap<String, u32>,
@@ -22,26 +37,13 @@ pub struct VariableInterner {
 }
 
 impl VariableInterner {
-    /// Create a new interner with pre-populated common variable names
+    /// Create an empty interner
     pub fn new() -> Self {
-        let mut interner = Self {
+        Self {
             name_to_id: HashMap::new(),
             id_to_name: HashMap::new(),
             next_id: 0,
-        };
-
-        // Pre-intern single-letter variables a-z
-        for c in b'a'..=b'z' {
-            let name = (c as char).to_string();
-            interner.intern(&name);
-        }
-
-        // Pre-intern common variable names
-        for name in &["result", "value", "temp", "count", "index", "data"] {
-            interner.intern(name);
         }
-
-        interner
     }
 
     /// Intern a variable name and return its ID
@@ -57,6 +59,11 @@ impl VariableInterner {
         id
     }
 
+    /// Look up the ID of an already-interned name without interning it
+    pub fn lookup(&self, name: &str) -> Option<u32> {
+        self.name_to_id.get(name).copied()
+    }
+
     /// Get the name for a given ID (for debugging/error messages)
     pub fn get_name(&self, id: u32) -> Option<&str> {
         self.id_to_name.get(&id).map(|s| s.as_str())
@@ -80,6 +87,74 @@ impl Default for VariableInterner {
     }
 }
 
+/// How much optimization the compiler performs
+///
+/// Higher levels spend more compile time to produce less bytecode. No level
+/// changes observable behavior, including which runtime errors are raised.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum OptLevel {
+    /// No optimization: every AST node compiles to its own instructions
+    #[default]
+    O0,
+    /// Fold constant arithmetic such as `2 * 3 + 1` into a single load,
+    /// looking through variables that are assigned a constant only once, and
+    /// load each variable at most once per statement
+    O1,
+    /// Everything in O1, and drop statements after a `return` in a function
+    O2,
+}
+
+impl OptLevel {
+    /// Level for a numeric `-O<n>` flag, if `level` is 0, 1 or 2
+    pub fn from_number(level: u8) -> Option<Self> {
+        match level {
+            0 => Some(OptLevel::O0),
+            1 => Some(OptLevel::O1),
+            2 => Some(OptLevel::O2),
+            _ => None,
+        }
+    }
+}
+
+/// How a diagnostic that can be either fatal or not is reported
+///
+/// Also the severity of every [`crate::diagnostic::Diagnostic`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Report it as a [`CompileWarning`] and compile anyway
+    #[default]
+    Warning,
+    /// Fail compilation with a [`CompileError`]
+    Error,
+}
+
+/// Settings that change the bytecode produced for a program
+///
+/// Two compilations of the same source share bytecode only if their options
+/// are equal, so caches of compiled code should key on these too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct CompileOptions {
+    /// Which optimization passes run
+    pub opt_level: OptLevel,
+    /// Whether dividing by a constant zero, as in `x / 0`, is an error
+    pub division_by_zero: Severity,
+    /// How every warning is reported; as [`Severity::Error`] the first one
+    /// fails compilation, as `-W error` does
+    pub warnings: Severity,
+}
+
+/// Register at which live operands start being spilled
+///
+/// Registers are allocated in stack order and freed as soon as their value is
+/// consumed, so only expressions nested more than this deep (or calls with
+/// this many arguments) reach it. Past this point, pending operands are moved
+/// into spill slots: synthetic variables stored with `StoreVar` and reloaded
+/// with `LoadVar` right before use. That is much slower than a register
+/// access, but keeps any expression the parser accepts compilable. Staying
+/// at half the register file leaves room to reload a full argument list.
+const SPILL_THRESHOLD: u8 = 128;
+
 /// Compiler state with register allocation
 pub struct Compiler {
     /// Bytecode builder for emitting instructions
@@ -88,12 +163,24 @@ pub struct Compiler {
     next_register: u8,
     /// Maximum register used so far
     max_register_used: u8,
-    /// Track current instruction count
-    instruction_counter: usize,
-    /// Parameter name mapping: actual_name -> param_N (when compiling function bodies)
-    param_mapping: HashMap<String, String>,
+    /// Parameters of the function being compiled; parameter `i` lives in
+    /// register `i` of the function's window
+    params: Vec<String>,
     /// Variable name interner
     interner: VariableInterner,
+    /// Optimization settings
+    options: CompileOptions,
+    /// Spill slots currently holding a value
+    spill_slots: usize,
+    /// Variables of the current scope that are assigned exactly once
+    single_assignments: HashSet<String>,
+    /// Constant values of those variables whose assignment has been compiled
+    constants: HashMap<String, i64>,
+    /// Registers already holding a variable's value in the current statement
+    loaded_vars: HashMap<String, u8>,
+    /// Arity of each function the program defines, by the definition calls
+    /// reach at runtime
+    arities: HashMap<String, usize>,
 }
 
 impl Compiler {
@@ -103,9 +190,14 @@ impl Compiler {
             builder: BytecodeBuilder::new(),
             next_register: 0,
             max_register_used: 0,
-            instruction_counter: 0,
-            param_mapping: HashMap::new(),
+            params: Vec::new(),
             interner: VariableInterner::new(),
+            options: CompileOptions::default(),
+            spill_slots: 0,
+            single_assignments: HashSet::new(),
+            constants: HashMap::new(),
+            loaded_vars: HashMap::new(),
+            arities: HashMap::new(),
         }
     }
 
@@ -117,10 +209,17 @@ impl Compiler {
         let reg = self.next_register;
         if reg == u8::MAX {
             return Err(CompileError {
+                code: ErrorCode::TooManyRegisters,
                 message: "Register limit exceeded (max 256 registers)".to_string(),
+                location: None,
+                end: None,
             });
         }
         self.next_register += 1;
+        // Whatever the register held before is about to be overwritten
+        if !self.loaded_vars.is_empty() {
+            self.loaded_vars.retain(|_, loaded| *loaded < reg);
+        }
 
         // Track maximum register used
         if reg > self.max_register_used {
@@ -130,11 +229,6 @@ impl Compiler {
         Ok(reg)
     }
 
-    /// Increment instruction counter (called after each emit)
-    fn inc_instruction_counter(&mut self) {
-        self.instruction_counter += 1;
-    }
-
     /// Compile a statement
     ///
     /// Implements critical SetResult emission rules:
@@ -142,455 +236,412 @@ impl Compiler {
     /// - Print: NO SetResult
     /// - Expression: YES SetResult
     ///
-    /// Returns true if this was a function definition (to be handled separately)
+    /// Instructions are attributed to `pos` when known: each expression node to
+    /// its own span, and the statement's final instruction to the statement.
+    ///
+    /// Function definitions emit nothing here; [`Compiler::generate`]
+    /// lays them out from the symbol table.
     fn compile_statement(
         &mut self,
         stmt: &Statement,
-        is_function_body: bool,
-    ) -> Result<bool, CompileError> {
-        match stmt {
+        pos: Option<&StatementPos>,
+    ) -> Result<(), CompileError> {
+        let statement_pos = pos.map(|pos| pos.pos);
+        let value_span = pos.and_then(|pos| pos.value.as_ref());
+        self.set_position(statement_pos);
+        // No value lives in a register past its statement, so every statement
+        // starts from the same register
+        let base_register = self.next_register;
+        self.loaded_vars.clear();
+
+        let result = match stmt {
             Statement::Assignment { name, value } => {
                 // Compile the expression and get the register containing its result
-                let value_reg = self.compile_expression(value)?;
-                // Check if this is a parameter reference that needs mapping
-                let actual_name = self.param_mapping.get(name).unwrap_or(name);
-                // Intern the variable name
-                let var_id = self.interner.intern(actual_name);
+                let value_reg = self.compile_expression(value, value_span)?;
+                self.set_position(statement_pos);
                 // Store the value in the variable
-                self.builder.emit_store_var(actual_name, var_id, value_reg);
-                self.inc_instruction_counter();
+                self.store_variable(name, value_reg);
+                self.record_constant(name, value);
                 // CRITICAL: Assignment does NOT emit SetResult
-                Ok(false)
+                Ok(())
+            }
+            Statement::MultiAssignment { targets, value } => {
+                // Evaluate the value once, then store it into every target left to right
+                let value_reg = self.compile_expression(value, value_span)?;
+                self.set_position(statement_pos);
+                for name in targets {
+                    self.store_variable(name, value_reg);
+                }
+                for name in targets {
+                    self.record_constant(name, value);
+                }
+                // CRITICAL: Assignment does NOT emit SetResult
+                Ok(())
             }
             Statement::Print { value } => {
                 // Compile the expression and get the register containing its result
-                let value_reg = self.compile_expression(value)?;
+                let value_reg = self.compile_expression(value, value_span)?;
+                self.set_position(statement_pos);
                 // Emit print instruction
                 self.builder.emit_print(value_reg);
-                self.inc_instruction_counter();
                 // CRITICAL: Print does NOT emit SetResult
-                Ok(false)
+                Ok(())
             }
             Statement::Expression { value } => {
                 // Compile the expression and get the register containing its result
-                let value_reg = self.compile_expression(value)?;
+                let value_reg = self.compile_expression(value, value_span)?;
+                self.set_position(statement_pos);
                 // CRITICAL: Expression statements DO emit SetResult
                 self.builder.emit_set_result(value_reg);
-                self.inc_instruction_counter();
-                Ok(false)
-            }
-            Statement::FunctionDef {
-                name: _,
-                params: _,
-                body: _,
-            } => {
-                if is_function_body {
-                    // Nested function definitions not supported
-                    return Err(CompileError {
-                        message: "Nested function definitions are not supported".to_string(),
-                    });
-                }
-                // Return true to indicate this is a function definition
-                // It will be handled in compile_program
-                Ok(true)
+                Ok(())
             }
+            Statement::FunctionDef { .. } => Ok(()),
             Statement::Return { value } => {
                 if let Some(expr) = value {
                     // Compile the return value expression
-                    let value_reg = self.compile_expression(expr)?;
+                    let value_reg = self.compile_expression(expr, value_span)?;
+                    self.set_position(statement_pos);
                     // Emit return instruction with value
                     self.builder.emit_return(true, Some(value_reg));
-                    self.inc_instruction_counter();
                 } else {
                     // Emit return instruction without value
                     self.builder.emit_return(false, None);
-                    self.inc_instruction_counter();
                 }
-                Ok(false)
+                Ok(())
             }
+        };
+        self.next_register = base_register;
+        result
+    }
+
+    /// Attribute the instructions emitted from now on to `pos`, if known
+    fn set_position(&mut self, pos: Option<SourcePos>) {
+        if let Some(pos) = pos {
+            self.builder.set_position(pos);
+        }
+    }
+
+    /// Attribute the instructions emitted from now on to `span`, if known
+    fn set_span(&mut self, span: Option<&ExprSpan>) {
+        if let Some(span) = span {
+            self.builder.set_span(span.start, span.end);
         }
     }
 
     /// Compile an expression and return the register containing its result
-    fn compile_expression(&mut self, expr: &Expression) -> Result<u8, CompileError> {
+    ///
+    /// `span` mirrors `expr` when positions are known. Operands are compiled
+    /// first, then the node's own instructions are attributed to its span.
+    fn compile_expression(
+        &mut self,
+        expr: &Expression,
+        span: Option<&ExprSpan>,
+    ) -> Result<u8, CompileError> {
+        let child = |index: usize| span.and_then(|span| span.children.get(index));
+        if self.options.opt_level >= OptLevel::O1
+            && matches!(
+                expr,
+                Expression::BinaryOp { .. } | Expression::UnaryOp { .. } | Expression::Variable(_)
+            )
+        {
+            if let Some(value) = self.fold_constant(expr) {
+                self.set_span(span);
+                let dest_reg = self.alloc_register()?;
+                self.builder.emit_load_const(dest_reg, value);
+                return Ok(dest_reg);
+            }
+        }
         match expr {
             Expression::Integer(value) => {
+                self.set_span(span);
                 // Allocate a register for the constant
                 let dest_reg = self.alloc_register()?;
                 // Load the constant into the register
                 self.builder.emit_load_const(dest_reg, *value);
-                self.inc_instruction_counter();
                 Ok(dest_reg)
             }
             Expression::Variable(name) => {
-                // Allocate a register for the variable value
-                let dest_reg = self.alloc_register()?;
-                // Check if this is a parameter reference that needs mapping
-                let actual_name = self.param_mapping.get(name).unwrap_or(name);
-                // Intern the variable name
-                let var_id = self.interner.intern(actual_name);
-                // Load the variable into the register
-                self.builder.emit_load_var(dest_reg, actual_name, var_id);
-                self.inc_instruction_counter();
-                Ok(dest_reg)
+                // Parameters are already in registers
+                if let Some(param_reg) = self.param_register(name) {
+                    return Ok(param_reg);
+                }
+                // Variables only change at the end of a statement, so a value
+                // loaded earlier in the statement is still current
+                if let Some(&loaded) = self.loaded_vars.get(name) {
+                    return Ok(loaded);
+                }
+                self.set_span(span);
+                self.load_variable(name)
             }
             Expression::BinaryOp { left, op, right } => {
-                // Compile left operand
-                let left_reg = self.compile_expression(left)?;
-                // Compile right operand
-                let right_reg = self.compile_expression(right)?;
-                // Allocate a register for the result
-                let dest_reg = self.alloc_register()?;
-                // Emit the binary operation
+                // Every register allocated from here on is a temporary of
+                // this expression, dead once the operation reads it
+                let start = self.next_register;
+                let left_reg = self.compile_expression(left, child(0))?;
+                // Keep deep right-nested expressions within the register file
+                let spilled_left = if self.next_register >= SPILL_THRESHOLD {
+                    self.set_span(span);
+                    let slot = self.spill(left_reg);
+                    self.next_register = start;
+                    Some(slot)
+                } else {
+                    None
+                };
+                let right_reg = self.compile_expression(right, child(1))?;
+                self.set_span(span);
+                let left_reg = match spilled_left {
+                    Some(slot) => self.reload(slot)?,
+                    None => left_reg,
+                };
+                // The result takes over the first temporary register
+                let dest_reg = self.reuse_register(start)?;
                 self.builder
                     .emit_binary_op(dest_reg, left_reg, *op, right_reg);
-                self.inc_instruction_counter();
                 Ok(dest_reg)
             }
             Expression::UnaryOp { op, operand } => {
-                // Compile the operand
-                let operand_reg = self.compile_expression(operand)?;
-                // Allocate a register for the result
-                let dest_reg = self.alloc_register()?;
-                // Emit the unary operation
+                let start = self.next_register;
+                let operand_reg = self.compile_expression(operand, child(0))?;
+                self.set_span(span);
+                let dest_reg = self.reuse_register(start)?;
                 self.builder.emit_unary_op(dest_reg, *op, operand_reg);
-                self.inc_instruction_counter();
                 Ok(dest_reg)
             }
             Expression::Call { name, args } => {
-                // Compile all arguments and collect their result registers
-                // Arguments are evaluated left-to-right for register-based VM
-                let mut arg_regs = Vec::new();
-                for arg in args.iter() {
-                    let arg_reg = self.compile_expression(arg)?;
-                    arg_regs.push(arg_reg);
+                if let Some(&arity) = self.arities.get(name) {
+                    if arity != args.len() {
+                        return Err(CompileError {
+                            code: ErrorCode::ArgumentCount,
+                            message: format!(
+                                "Function {} expects {} arguments, got {}",
+                                name,
+                                arity,
+                                args.len()
+                            ),
+                            location: span.map(|span| span.start),
+                            end: span.map(|span| span.end),
+                        });
+                    }
                 }
-
-                // Ensure arguments are in consecutive registers
-                // If they're not, move them to consecutive registers
-                let first_arg_reg = if arg_regs.is_empty() {
-                    0 // No arguments, use 0 as placeholder
-                } else {
-                    // Check if registers are already consecutive
-                    let are_consecutive = arg_regs.windows(2).all(|w| w[1] == w[0] + 1);
-
-                    if are_consecutive {
-                        // Already consecutive, use first register
-                        arg_regs[0]
-                    } else {
-                        // Not consecutive, need to copy to consecutive registers
-                        let first_consecutive_reg = self.next_register;
-
-                        for (i, &arg_reg) in arg_regs.iter().enumerate() {
-                            let target_reg = first_consecutive_reg + i as u8;
-
-                            // Skip if already in correct position
-                            if arg_reg != target_reg {
-                                // Allocate the target register
-                                let allocated_reg = self.alloc_register()?;
-                                debug_assert_eq!(allocated_reg, target_reg);
-
-                                // Copy using UnaryOp::Pos (identity operation)
-                                self.builder
-                                    .emit_unary_op(target_reg, UnaryOperator::Pos, arg_reg);
-                                self.inc_instruction_counter();
-                            } else {
-                                // Register already in correct position, just mark it as allocated
-                                self.alloc_register()?;
-                            }
+                // Arguments are evaluated left to right, each into the register
+                // after the previous one, so they end up consecutive
+                let first_arg_reg = self.next_register;
+                // First spill slot, once arguments are being spilled
+                let mut spilled_from = None;
+                for (index, arg) in args.iter().enumerate() {
+                    if spilled_from.is_none() && self.next_register >= SPILL_THRESHOLD {
+                        self.set_span(span);
+                        spilled_from = Some(self.spill_slots);
+                        for reg in first_arg_reg..self.next_register {
+                            self.spill(reg);
                         }
-
-                        first_consecutive_reg
+                        self.next_register = first_arg_reg;
                     }
-                };
-
-                // Allocate a register for the return value
-                let dest_reg = self.alloc_register()?;
+                    let slot_reg = self.next_register;
+                    let mut arg_reg = self.compile_expression(arg, child(index))?;
+                    if arg_reg != slot_reg {
+                        // A parameter or an already loaded variable; the call
+                        // needs its own copy
+                        self.set_span(child(index));
+                        let copy_reg = self.alloc_register()?;
+                        self.builder.emit_move(copy_reg, arg_reg);
+                        arg_reg = copy_reg;
+                    }
+                    if spilled_from.is_some() {
+                        self.spill(arg_reg);
+                        self.next_register = arg_reg;
+                    }
+                }
+                self.set_span(span);
+                if let Some(first_slot) = spilled_from {
+                    for slot in first_slot..first_slot + args.len() {
+                        self.reload(slot)?;
+                    }
+                    self.spill_slots = first_slot;
+                }
 
-                // Intern the function name
+                // The result replaces the arguments
+                let dest_reg = self.reuse_register(first_arg_reg)?;
+                let first_arg_reg = if args.is_empty() { 0 } else { first_arg_reg };
                 let var_id = self.interner.intern(name);
-
-                // Emit call instruction with argument register information
                 self.builder
                     .emit_call(name, var_id, args.len() as u8, first_arg_reg, dest_reg);
-                self.inc_instruction_counter();
 
                 Ok(dest_reg)
             }
         }
     }
 
-    /// Validate that a statement doesn't contain forward references to functions
-    /// Forward reference: calling a function that will be defined later in the program
-    fn validate_no_forward_references(
-        stmt: &Statement,
-        defined_so_far: &HashSet<String>,
-        all_defined_functions: &HashSet<String>,
-    ) -> Result<(), CompileError> {
-        match stmt {
-            Statement::Expression { value } | Statement::Assignment { value, .. } => {
-                Self::check_expression_for_forward_references(
-                    value,
-                    defined_so_far,
-                    all_defined_functions,
-                )
-            }
-            Statement::Print { value } => Self::check_expression_for_forward_references(
-                value,
-                defined_so_far,
-                all_defined_functions,
-            ),
-            Statement::Return { value } => {
-                if let Some(expr) = value {
-                    Self::check_expression_for_forward_references(
-                        expr,
-                        defined_so_far,
-                        all_defined_functions,
-                    )
-                } else {
-                    Ok(())
-                }
-            }
-            Statement::FunctionDef { .. } => Ok(()),
-        }
+    /// Register holding parameter `name` of the function being compiled
+    fn param_register(&self, name: &str) -> Option<u8> {
+        self.params
+            .iter()
+            .position(|param| param == name)
+            .map(|slot| slot as u8)
     }
 
-    /// Check if an expression contains forward references to functions
-    fn check_expression_for_forward_references(
-        expr: &Expression,
-        defined_so_far: &HashSet<String>,
-        all_defined_functions: &HashSet<String>,
-    ) -> Result<(), CompileError> {
-        match expr {
-            Expression::Call { name, args } => {
-                // Check if this is a forward reference:
-                // - The function will be defined later (in all_defined_functions)
-                // - But is NOT yet defined (not in defined_so_far)
-                if all_defined_functions.contains(name) && !defined_so_far.contains(name) {
-                    return Err(CompileError {
-                        message: format!(
-                            "Call to undefined function '{}' (function defined later in program)",
-                            name
-                        ),
-                    });
-                }
-                // Recursively check arguments
-                for arg in args {
-                    Self::check_expression_for_forward_references(
-                        arg,
-                        defined_so_far,
-                        all_defined_functions,
-                    )?;
-                }
-                Ok(())
-            }
-            Expression::BinaryOp { left, right, .. } => {
-                Self::check_expression_for_forward_references(
-                    left,
-                    defined_so_far,
-                    all_defined_functions,
-                )?;
-                Self::check_expression_for_forward_references(
-                    right,
-                    defined_so_far,
-                    all_defined_functions,
-                )
-            }
-            Expression::UnaryOp { operand, .. } => Self::check_expression_for_forward_references(
-                operand,
-                defined_so_far,
-                all_defined_functions,
-            ),
-            Expression::Integer(_) | Expression::Variable(_) => Ok(()),
+    /// Load variable `name` into a new register
+    fn load_variable(&mut self, name: &str) -> Result<u8, CompileError> {
+        let dest_reg = self.alloc_register()?;
+        let var_id = self.interner.intern(name);
+        self.builder.emit_load_var(dest_reg, name, var_id);
+        if self.options.opt_level >= OptLevel::O1 {
+            self.loaded_vars.insert(name.to_string(), dest_reg);
         }
+        Ok(dest_reg)
     }
 
-    /// Compile a program and return the bytecode
-    fn compile_program(mut self, program: &Program) -> Result<Bytecode, CompileError> {
-        // First pass: collect all function names that will be defined
-        let all_defined_functions: HashSet<String> = program
-            .statements
-            .iter()
-            .filter_map(|stmt| {
-                if let Statement::FunctionDef { name, .. } = stmt {
-                    Some(name.clone())
-                } else {
-                    None
-                }
-            })
-            .collect();
-
-        // Separate function definitions from main code
-        let mut function_defs = Vec::new();
-        let mut main_statements = Vec::new();
-        let mut defined_so_far = HashSet::new();
-
-        // Process statements in order to detect forward references
-        for stmt in &program.statements {
-            if let Statement::FunctionDef { name, body, .. } = stmt {
-                // Add function to defined set BEFORE validating body (allows recursion)
-                defined_so_far.insert(name.clone());
-                // Validate function body doesn't contain forward references
-                for body_stmt in body {
-                    Self::validate_no_forward_references(
-                        body_stmt,
-                        &defined_so_far,
-                        &all_defined_functions,
-                    )?;
-                }
-                function_defs.push(stmt);
-            } else {
-                // Validate that any function calls don't reference functions defined later
-                Self::validate_no_forward_references(
-                    stmt,
-                    &defined_so_far,
-                    &all_defined_functions,
-                )?;
-                main_statements.push(stmt);
+    /// Assign `value_reg` to variable `name`
+    fn store_variable(&mut self, name: &str, value_reg: u8) {
+        match self.param_register(name) {
+            Some(param_reg) => self.builder.emit_move(param_reg, value_reg),
+            None => {
+                let var_id = self.interner.intern(name);
+                self.builder.emit_store_var(name, var_id, value_reg);
             }
         }
+    }
 
-        // Pass 1: Compile function bodies and track their locations
-        // We need to know where function bodies will be AFTER main code + Halt
-        // Calculate offset: DefineFunction instructions + main code + Halt
-        let define_func_count = function_defs.len();
+    /// Free every register from `reg` up, then allocate `reg` again
+    fn reuse_register(&mut self, reg: u8) -> Result<u8, CompileError> {
+        self.next_register = reg;
+        self.alloc_register()
+    }
 
-        // We'll emit DefineFunction instructions first, then main code, then Halt, then function bodies
-        // So function bodies start at: define_func_count + main_code_length + 1 (for Halt)
+    /// Store `reg` into the next spill slot and return the slot
+    ///
+    /// Spill slots are synthetic variables (`$spill0`, `$spill1`, ...) that
+    /// user code cannot name. Slots are taken and released in stack order.
+    fn spill(&mut self, reg: u8) -> usize {
+        let slot = self.spill_slots;
+        self.spill_slots += 1;
+        let name = format!("$spill{}", slot);
+        let var_id = self.interner.intern(&name);
+        self.builder.emit_store_var(&name, var_id, reg);
+        slot
+    }
+
+    /// Load spill slot `slot` into a new register, releasing the slot
+    fn reload(&mut self, slot: usize) -> Result<u8, CompileError> {
+        let name = format!("$spill{}", slot);
+        let var_id = self.interner.intern(&name);
+        let reg = self.alloc_register()?;
+        self.builder.emit_load_var(reg, &name, var_id);
+        self.spill_slots = slot;
+        Ok(reg)
+    }
 
-        // First, we need to compile main code to know its length
-        let saved_counter = self.instruction_counter;
+    /// Value of `expr` if it is built only from integer literals and known
+    /// constant variables
+    ///
+    /// Uses the VM's own arithmetic, so anything that would raise at runtime
+    /// (division by zero, overflow) is left unfolded and still raises.
+    fn fold_constant(&self, expr: &Expression) -> Option<i64> {
+        simplify::constant_value(expr, &|name| self.constants.get(name).copied())
+    }
 
-        // Temporarily compile main code to measure length
-        for stmt in &main_statements {
-            self.compile_statement(stmt, false)?;
+    /// Remember `name`'s value for later uses if it can never change
+    ///
+    /// Statements run straight through, so once the only assignment to a
+    /// variable has executed, every later read in the same scope sees it.
+    fn record_constant(&mut self, name: &str, value: &Expression) {
+        if self.options.opt_level < OptLevel::O1 || !self.single_assignments.contains(name) {
+            return;
         }
-        let main_code_length = self.instruction_counter - saved_counter;
-
-        // Reset compiler state
-        self.instruction_counter = 0;
-        self.next_register = 0;
-        self.builder = BytecodeBuilder::new();
-
-        // Calculate where function bodies will start
-        let function_bodies_start = define_func_count + main_code_length + 1; // +1 for Halt
-
-        // Pass 2: Compile function bodies and emit DefineFunction instructions
-        let mut function_metadata = Vec::new();
-        let mut current_body_offset = function_bodies_start;
-
-        for func_def in &function_defs {
-            if let Statement::FunctionDef { name, params, body } = func_def {
-                // Save compiler state
-                let saved_reg = self.next_register;
-                let saved_param_mapping = self.param_mapping.clone();
-                let saved_max_reg = self.max_register_used;
-
-                // Set instruction counter to where this function body will be
-                let body_start = current_body_offset;
-                self.instruction_counter = body_start;
-
-                // Reset register allocation for function scope
-                self.next_register = params.len() as u8;
-
-                // Reset max_register_used for this function
-                self.max_register_used = if !params.is_empty() {
-                    params.len() as u8 - 1
-                } else {
-                    0
-                };
-
-                // Set up parameter mapping: param_name -> param_N
-                self.param_mapping.clear();
-                for (i, param_name) in params.iter().enumerate() {
-                    self.param_mapping
-                        .insert(param_name.clone(), format!("param_{}", i));
-                }
-
-                // Ensure all parameter names are interned in bytecode
-                // even if they're not used in the function body
-                for i in 0..params.len() {
-                    let param_name = format!("param_{}", i);
-                    let var_id = self.interner.intern(&param_name);
-                    // Add to var_names pool (will be deduplicated if already exists)
-                    self.builder.ensure_var_name(&param_name, var_id);
-                }
-
-                // Compile function body
-                for stmt in body {
-                    self.compile_statement(stmt, true)?;
-                }
+        if let Some(value) = self.fold_constant(value) {
+            self.constants.insert(name.to_string(), value);
+        }
+    }
 
-                // Calculate body length
-                let body_len = self.instruction_counter - body_start;
-
-                // Store metadata for later (including per-function max_register_used)
-                function_metadata.push((
-                    name.clone(),
-                    params.len() as u8,
-                    body_start,
-                    body_len,
-                    self.max_register_used,
-                ));
-
-                // Update offset for next function
-                current_body_offset = self.instruction_counter;
-
-                // Restore compiler state
-                self.next_register = saved_reg;
-                self.param_mapping = saved_param_mapping;
-                self.max_register_used = saved_max_reg;
+    /// Start a new variable scope made of `statements`
+    ///
+    /// `params` are bound on entry, so they are never treated as constants.
+    fn enter_scope(&mut self, statements: &[Statement], params: &[String]) {
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for stmt in statements {
+            let targets = match stmt {
+                Statement::Assignment { name, .. } => std::slice::from_ref(name),
+                Statement::MultiAssignment { targets, .. } => &targets[..],
+                _ => &[],
+            };
+            for name in targets {
+                *counts.entry(name).or_default() += 1;
             }
         }
+        self.single_assignments = counts
+            .into_iter()
+            .filter(|&(name, count)| count == 1 && !params.iter().any(|param| param == name))
+            .map(|(name, _)| name.to_string())
+            .collect();
+        self.constants.clear();
+    }
+
+    /// Emit bytecode for a resolved program
+    ///
+    /// `positions` parallels `program.statements` and may be empty, in which
+    /// case the bytecode has no line table. `symbols` is the resolver's table
+    /// for `program`.
+    ///
+    /// Main code is one `DefineFunction` per function followed by the
+    /// top-level statements and `Halt`; each function body is emitted into
+    /// the chunk its `DefineFunction` refers to.
+    pub(crate) fn generate(
+        &mut self,
+        program: &Program,
+        positions: &[StatementPos],
+        symbols: &SymbolTable,
+    ) -> Result<Bytecode, CompileError> {
+        self.arities = symbols
+            .functions
+            .iter()
+            .map(|function| (function.name.clone(), function.arity()))
+            .collect();
 
-        // Now we need to rebuild bytecode in correct order:
-        // 1. DefineFunction instructions
-        // 2. Main code
-        // 3. Halt (added by builder)
-        // 4. Function bodies
-
-        // Get the function body instructions we just compiled
-        let function_body_instructions = self.builder.instructions().to_vec();
-
-        // Save the constant and variable name pools from function compilation
-        let (constants, var_names, var_ids) = self.builder.get_pools();
-        let saved_constants = constants.clone();
-        let saved_var_names = var_names.clone();
-        let saved_var_ids = var_ids.clone();
-
-        // Reset builder with saved pools and instruction counter
-        self.builder = BytecodeBuilder::with_pools(saved_constants, saved_var_names, saved_var_ids);
-        self.instruction_counter = 0;
-        self.next_register = 0;
-
-        // Emit DefineFunction instructions first
-        for (name, param_count, body_start, body_len, max_reg_used) in &function_metadata {
-            let var_id = self.interner.intern(name);
-            self.builder.emit_define_function(
-                name,
+        let mut chunks = Vec::with_capacity(symbols.functions.len());
+        for function in &symbols.functions {
+            let var_id = self.interner.intern(&function.name);
+            chunks.push(self.builder.emit_define_function(
+                &function.name,
                 var_id,
-                *param_count,
-                *body_start,
-                *body_len,
-                *max_reg_used,
-            );
-            self.inc_instruction_counter();
+                function.arity() as u8,
+            ));
         }
 
-        // Compile main code
-        for stmt in &main_statements {
-            self.compile_statement(stmt, false)?;
+        self.enter_scope(&program.statements, &[]);
+        for (index, stmt) in program.statements.iter().enumerate() {
+            self.compile_statement(stmt, positions.get(index))?;
         }
+        self.builder.emit_halt();
+        let main_max_register = self.max_register_used;
 
-        // Build bytecode (this adds Halt)
-        let mut bytecode = self.builder.build();
-
-        // Append function body instructions
-        bytecode.instructions.extend(function_body_instructions);
+        for (function, chunk) in symbols.functions.iter().zip(chunks) {
+            let Statement::FunctionDef { body, .. } = &program.statements[function.statement_index]
+            else {
+                unreachable!("symbol table points at a function definition");
+            };
+            let body_positions = positions
+                .get(function.statement_index)
+                .map_or(&[][..], |pos| &pos.body[..]);
+            self.builder.begin_function(chunk);
+
+            // Calls pass the arguments in the first registers of the
+            // function's window
+            let arity = function.arity();
+            self.next_register = arity as u8;
+            self.max_register_used = arity.saturating_sub(1) as u8;
+            self.params = function.params.clone();
+
+            self.enter_scope(body, &function.params);
+            for (index, stmt) in body.iter().enumerate() {
+                self.compile_statement(stmt, body_positions.get(index))?;
+            }
 
-        // Set the max_register_used in metadata
-        bytecode.metadata.max_register_used = self.max_register_used;
+            self.builder.end_function(self.max_register_used);
+        }
+        self.params.clear();
 
+        let mut bytecode = std::mem::take(&mut self.builder).finish();
+        bytecode.metadata.max_register_used = main_max_register;
         Ok(bytecode)
     }
 }
@@ -629,8 +680,88 @@ impl Default for Compiler {
 /// let bytecode = compile(&program).unwrap();
 /// ```
 pub fn compile(program: &Program) -> Result<Bytecode, CompileError> {
-    let compiler = Compiler::new();
-    compiler.compile_program(program)
+    CompilerBuilder::new().build().compile(program, &[])
+}
+
+/// Compile a Program, recording a line table from its statement positions
+///
+/// `positions` is the list returned alongside the program by
+/// [`crate::parser::parse_with_positions`]. The resulting bytecode maps each
+/// instruction back to the statement it came from via
+/// [`Bytecode::source_position`].
+pub fn compile_with_positions(
+    program: &Program,
+    positions: &[StatementPos],
+) -> Result<Bytecode, CompileError> {
+    CompilerBuilder::new().build().compile(program, positions)
+}
+
+/// Compile a Program with the given optimization settings
+///
+/// `positions` may be empty, as for [`compile`]; otherwise it is used as in
+/// [`compile_with_positions`]. With the default options this produces the
+/// same bytecode as those functions.
+///
+/// # Examples
+/// ```
+/// use pyrust::bytecode::Instruction;
+/// use pyrust::compiler::{compile_with_options, CompileOptions, OptLevel};
+/// use pyrust::{lexer, parser};
+///
+/// let program = parser::parse(lexer::lex("print(6 * 7)").unwrap()).unwrap();
+/// let options = CompileOptions { opt_level: OptLevel::O1, ..Default::default() };
+/// let bytecode = compile_with_options(&program, &[], &options).unwrap();
+///
+/// // The multiplication happened at compile time
+/// assert!(matches!(bytecode.instructions[0], Instruction::LoadConst { .. }));
+/// assert_eq!(bytecode.constants, vec![42]);
+/// ```
+pub fn compile_with_options(
+    program: &Program,
+    positions: &[StatementPos],
+    options: &CompileOptions,
+) -> Result<Bytecode, CompileError> {
+    CompilerBuilder::new()
+        .options(*options)
+        .build()
+        .compile(program, positions)
+}
+
+/// Compile a Program and collect warnings about suspicious code
+///
+/// Warnings stop compilation only when [`CompileOptions::warnings`] is
+/// [`Severity::Error`]; see [`crate::warnings`] for what is reported. They
+/// carry source positions when `positions` is not empty.
+pub fn compile_with_warnings(
+    program: &Program,
+    positions: &[StatementPos],
+    options: &CompileOptions,
+) -> Result<(Bytecode, Vec<CompileWarning>), CompileError> {
+    // The resolve pass would run the same check again to fail on warnings
+    let lenient = CompileOptions {
+        warnings: Severity::Warning,
+        ..*options
+    };
+    let bytecode = compile_with_options(program, positions, &lenient)?;
+    let mut found = warnings::check(program, positions);
+    if options.warnings == Severity::Error && !found.is_empty() {
+        return Err(found.swap_remove(0).into_error());
+    }
+    Ok((bytecode, found))
+}
+
+/// Compile a Program using an existing variable interner
+///
+/// Names already in `interner` keep their IDs and new names are added to it,
+/// so bytecode from successive calls can share one VM's variable storage.
+/// This is what lets a [`crate::Session`] see globals from earlier evals.
+pub fn compile_with_interner(
+    program: &Program,
+    interner: &mut VariableInterner,
+) -> Result<Bytecode, CompileError> {
+    CompilerBuilder::new()
+        .build()
+        .compile_with_interner(program, &[], interner)
 }
 
 #[cfg(test)]
@@ -780,7 +911,7 @@ mod tests {
         assert_eq!(
             bytecode.instructions[2],
             Instruction::BinaryOp {
-                dest_reg: 2,
+                dest_reg: 0,
                 left_reg: 0,
                 op: BinaryOperator::Add,
                 right_reg: 1
@@ -788,7 +919,7 @@ mod tests {
         );
         assert_eq!(
             bytecode.instructions[3],
-            Instruction::SetResult { src_reg: 2 }
+            Instruction::SetResult { src_reg: 0 }
         );
         assert_eq!(bytecode.instructions[4], Instruction::Halt);
     }
@@ -852,14 +983,14 @@ mod tests {
         assert_eq!(
             bytecode.instructions[1],
             Instruction::UnaryOp {
-                dest_reg: 1,
+                dest_reg: 0,
                 op: UnaryOperator::Neg,
                 operand_reg: 0
             }
         );
         assert_eq!(
             bytecode.instructions[2],
-            Instruction::SetResult { src_reg: 1 }
+            Instruction::SetResult { src_reg: 0 }
         );
         assert_eq!(bytecode.instructions[3], Instruction::Halt);
     }
@@ -898,7 +1029,7 @@ mod tests {
         assert!(matches!(
             bytecode.instructions[2],
             Instruction::BinaryOp {
-                dest_reg: 2,
+                dest_reg: 0,
                 left_reg: 0,
                 op: BinaryOperator::Add,
                 right_reg: 1
@@ -906,20 +1037,20 @@ mod tests {
         ));
         assert!(matches!(
             bytecode.instructions[3],
-            Instruction::LoadConst { dest_reg: 3, .. }
+            Instruction::LoadConst { dest_reg: 1, .. }
         ));
         assert!(matches!(
             bytecode.instructions[4],
             Instruction::BinaryOp {
-                dest_reg: 4,
-                left_reg: 2,
+                dest_reg: 0,
+                left_reg: 0,
                 op: BinaryOperator::Mul,
-                right_reg: 3
+                right_reg: 1
             }
         ));
         assert_eq!(
             bytecode.instructions[5],
-            Instruction::SetResult { src_reg: 4 }
+            Instruction::SetResult { src_reg: 0 }
         );
     }
 
@@ -1211,14 +1342,12 @@ mod tests {
 
         // Verify we have SetResult for the expression statement
         assert!(bytecode
-            .instructions
-            .iter()
+            .code()
             .any(|i| matches!(i, Instruction::SetResult { .. })));
 
         // Verify we have multiple BinaryOp instructions
         let binop_count = bytecode
-            .instructions
-            .iter()
+            .code()
             .filter(|i| matches!(i, Instruction::BinaryOp { .. }))
             .count();
         assert_eq!(binop_count, 4); // 4 binary operations
@@ -1243,32 +1372,24 @@ mod tests {
 
         // Should have: LoadConst(42), Return, DefineFunction, Halt
         assert!(bytecode
-            .instructions
-            .iter()
+            .code()
             .any(|i| matches!(i, Instruction::DefineFunction { .. })));
         assert!(bytecode
-            .instructions
-            .iter()
+            .code()
             .any(|i| matches!(i, Instruction::Return { .. })));
 
         // Check DefineFunction metadata
         let define_func = bytecode
-            .instructions
-            .iter()
+            .code()
             .find(|i| matches!(i, Instruction::DefineFunction { .. }))
             .unwrap();
 
-        if let Instruction::DefineFunction {
-            param_count,
-            name_index,
-            ..
-        } = define_func
-        {
-            assert_eq!(*param_count, 0);
-            assert_eq!(bytecode.var_names[*name_index], "foo");
-        } else {
+        let Instruction::DefineFunction { chunk_index } = define_func else {
             panic!("Expected DefineFunction instruction");
-        }
+        };
+        let chunk = &bytecode.functions[*chunk_index];
+        assert_eq!(chunk.param_count, 0);
+        assert_eq!(bytecode.var_names[chunk.name_index], "foo");
     }
 
     #[test]
@@ -1292,23 +1413,19 @@ mod tests {
 
         // Verify DefineFunction instruction exists with correct param_count
         let define_func = bytecode
-            .instructions
-            .iter()
+            .code()
             .find(|i| matches!(i, Instruction::DefineFunction { .. }))
             .unwrap();
 
-        if let Instruction::DefineFunction {
-            param_count,
-            name_index,
-            ..
-        } = define_func
-        {
-            assert_eq!(*param_count, 2);
-            assert_eq!(bytecode.var_names[*name_index], "add");
-        }
+        let Instruction::DefineFunction { chunk_index } = define_func else {
+            panic!("Expected DefineFunction instruction");
+        };
+        let chunk = &bytecode.functions[*chunk_index];
+        assert_eq!(chunk.param_count, 2);
+        assert_eq!(bytecode.var_names[chunk.name_index], "add");
 
         // Verify function body compiled correctly
-        assert!(bytecode.instructions.iter().any(|i| matches!(
+        assert!(bytecode.code().any(|i| matches!(
             i,
             Instruction::Return {
                 has_value: true,
@@ -1333,14 +1450,12 @@ mod tests {
 
         // Should have: Call, SetResult, Halt
         assert!(bytecode
-            .instructions
-            .iter()
+            .code()
             .any(|i| matches!(i, Instruction::Call { .. })));
 
         // Check Call instruction
         let call_instr = bytecode
-            .instructions
-            .iter()
+            .code()
             .find(|i| matches!(i, Instruction::Call { .. }))
             .unwrap();
 
@@ -1371,22 +1486,19 @@ mod tests {
 
         // Should have: LoadConst(10), LoadConst(20), Call, SetResult, Halt
         assert!(bytecode
-            .instructions
-            .iter()
+            .code()
             .any(|i| matches!(i, Instruction::Call { .. })));
 
         // Check that arguments are compiled
         let loadconst_count = bytecode
-            .instructions
-            .iter()
+            .code()
             .filter(|i| matches!(i, Instruction::LoadConst { .. }))
             .count();
         assert_eq!(loadconst_count, 2);
 
         // Check Call instruction has correct arg_count
         let call_instr = bytecode
-            .instructions
-            .iter()
+            .code()
             .find(|i| matches!(i, Instruction::Call { .. }))
             .unwrap();
 
@@ -1412,8 +1524,7 @@ mod tests {
 
         // Find Return instruction
         let return_instr = bytecode
-            .instructions
-            .iter()
+            .code()
             .find(|i| matches!(i, Instruction::Return { .. }))
             .unwrap();
 
@@ -1438,8 +1549,7 @@ mod tests {
 
         // Find Return instruction
         let return_instr = bytecode
-            .instructions
-            .iter()
+            .code()
             .find(|i| matches!(i, Instruction::Return { .. }))
             .unwrap();
 
@@ -1473,12 +1583,10 @@ mod tests {
 
         // Verify compilation succeeds and function body is present
         assert!(bytecode
-            .instructions
-            .iter()
+            .code()
             .any(|i| matches!(i, Instruction::DefineFunction { .. })));
         assert!(bytecode
-            .instructions
-            .iter()
+            .code()
             .any(|i| matches!(i, Instruction::StoreVar { .. })));
     }
 
@@ -1508,8 +1616,7 @@ mod tests {
 
         // Should have two DefineFunction instructions
         let define_count = bytecode
-            .instructions
-            .iter()
+            .code()
             .filter(|i| matches!(i, Instruction::DefineFunction { .. }))
             .count();
         assert_eq!(define_count, 2);
@@ -1538,8 +1645,7 @@ mod tests {
 
         // Should have two Call instructions
         let call_count = bytecode
-            .instructions
-            .iter()
+            .code()
             .filter(|i| matches!(i, Instruction::Call { .. }))
             .count();
         assert_eq!(call_count, 2);
@@ -1579,20 +1685,16 @@ mod tests {
 
         // Verify function compiled with all statement types
         assert!(bytecode
-            .instructions
-            .iter()
+            .code()
             .any(|i| matches!(i, Instruction::DefineFunction { .. })));
         assert!(bytecode
-            .instructions
-            .iter()
+            .code()
             .any(|i| matches!(i, Instruction::StoreVar { .. })));
         assert!(bytecode
-            .instructions
-            .iter()
+            .code()
             .any(|i| matches!(i, Instruction::Print { .. })));
         assert!(bytecode
-            .instructions
-            .iter()
+            .code()
             .any(|i| matches!(i, Instruction::Return { .. })));
     }
 
@@ -1623,12 +1725,10 @@ mod tests {
 
         // Verify arguments are compiled as expressions
         assert!(bytecode
-            .instructions
-            .iter()
+            .code()
             .any(|i| matches!(i, Instruction::BinaryOp { .. })));
         assert!(bytecode
-            .instructions
-            .iter()
+            .code()
             .any(|i| matches!(i, Instruction::Call { .. })));
     }
 
@@ -1658,19 +1758,17 @@ mod tests {
 
         // Verify DefineFunction has correct param_count
         let define_func = bytecode
-            .instructions
-            .iter()
+            .code()
             .find(|i| matches!(i, Instruction::DefineFunction { .. }))
             .unwrap();
 
-        if let Instruction::DefineFunction { param_count, .. } = define_func {
-            assert_eq!(*param_count, 3);
+        if let Instruction::DefineFunction { chunk_index } = define_func {
+            assert_eq!(bytecode.functions[*chunk_index].param_count, 3);
         }
 
         // Function body should compile successfully
         assert!(bytecode
-            .instructions
-            .iter()
+            .code()
             .any(|i| matches!(i, Instruction::Return { .. })));
     }
 
@@ -1690,8 +1788,7 @@ mod tests {
 
         // Find Call instruction
         let call_instr = bytecode
-            .instructions
-            .iter()
+            .code()
             .find(|i| matches!(i, Instruction::Call { .. }))
             .unwrap();
 
@@ -1704,10 +1801,9 @@ mod tests {
         } = call_instr
         {
             assert_eq!(*arg_count, 2);
-            // With right-to-left evaluation and consecutive register allocation,
-            // arguments end up in consecutive registers (after potential copying)
-            // Just verify arg_count is correct and dest_reg comes after arguments
-            assert!(*dest_reg >= *first_arg_reg + 2);
+            // The result overwrites the first argument register
+            assert_eq!(*first_arg_reg, 0);
+            assert_eq!(*dest_reg, *first_arg_reg);
         } else {
             panic!("Expected Call instruction");
         }
@@ -1740,8 +1836,7 @@ mod tests {
 
         // Find Call instruction
         let call_instr = bytecode
-            .instructions
-            .iter()
+            .code()
             .find(|i| matches!(i, Instruction::Call { .. }))
             .unwrap();
 
@@ -1783,8 +1878,7 @@ mod tests {
 
         // Find both Call instructions
         let call_instrs: Vec<_> = bytecode
-            .instructions
-            .iter()
+            .code()
             .filter(|i| matches!(i, Instruction::Call { .. }))
             .collect();
 
@@ -1851,39 +1945,26 @@ mod tests {
 
         // Find both DefineFunction instructions
         let define_funcs: Vec<_> = bytecode
-            .instructions
-            .iter()
+            .code()
             .filter(|i| matches!(i, Instruction::DefineFunction { .. }))
             .collect();
 
         assert_eq!(define_funcs.len(), 2, "Should have 2 function definitions");
 
-        // Verify first function metadata
-        if let Instruction::DefineFunction {
-            name_index,
-            param_count,
-            body_start: _,
-            body_len,
-            ..
-        } = define_funcs[0]
-        {
-            assert_eq!(bytecode.var_names[*name_index], "foo");
-            assert_eq!(*param_count, 2);
-            assert!(*body_len > 0, "Function body should have instructions");
-        }
-
-        // Verify second function metadata
-        if let Instruction::DefineFunction {
-            name_index,
-            param_count,
-            body_start: _,
-            body_len,
-            ..
-        } = define_funcs[1]
+        // Each definition refers to its own chunk
+        for (index, (define, (name, arity))) in define_funcs
+            .iter()
+            .zip([("foo", 2), ("bar", 0)])
+            .enumerate()
         {
-            assert_eq!(bytecode.var_names[*name_index], "bar");
-            assert_eq!(*param_count, 0);
-            assert!(*body_len > 0, "Function body should have instructions");
+            assert_eq!(**define, Instruction::DefineFunction { chunk_index: index });
+            let chunk = &bytecode.functions[index];
+            assert_eq!(bytecode.var_names[chunk.name_index], name);
+            assert_eq!(chunk.param_count, arity);
+            assert!(
+                !chunk.instructions.is_empty(),
+                "Function body should have instructions"
+            );
         }
     }
 
@@ -1906,14 +1987,12 @@ mod tests {
 
         // Should compile successfully even without explicit return
         assert!(bytecode
-            .instructions
-            .iter()
+            .code()
             .any(|i| matches!(i, Instruction::DefineFunction { .. })));
 
         // Should NOT have a Return instruction (function has implicit None return)
         let has_return = bytecode
-            .instructions
-            .iter()
+            .code()
             .any(|i| matches!(i, Instruction::Return { .. }));
         assert!(
             !has_return,
@@ -1950,8 +2029,7 @@ mod tests {
 
         // Find Call instruction
         let call_instr = bytecode
-            .instructions
-            .iter()
+            .code()
             .find(|i| matches!(i, Instruction::Call { .. }))
             .unwrap();
 
@@ -1963,13 +2041,12 @@ mod tests {
         } = call_instr
         {
             assert_eq!(*arg_count, 3);
-            // With consecutive register allocation fix:
-            // Arg1 (1+2): compiles to regs 0, 1, result in 2
-            // Arg2 (3*4): compiles to regs 3, 4, result in 5
-            // Arg3 (5): compiles to reg 6
-            // Since results (2, 5, 6) are not consecutive, they're copied to consecutive registers starting at 7
-            // So first_arg_reg = 7, and args are in regs 7, 8, 9
-            assert_eq!(*first_arg_reg, 7);
+            // Each argument's result reuses its first operand register:
+            // Arg1 (1+2): regs 0, 1, result in 0
+            // Arg2 (3*4): regs 1, 2, result in 1
+            // Arg3 (5): reg 2
+            // so the arguments land in 0, 1, 2 without any copying
+            assert_eq!(*first_arg_reg, 0);
         }
     }
 
@@ -1992,13 +2069,12 @@ mod tests {
 
         // Verify DefineFunction has correct param_count
         let define_func = bytecode
-            .instructions
-            .iter()
+            .code()
             .find(|i| matches!(i, Instruction::DefineFunction { .. }))
             .unwrap();
 
-        if let Instruction::DefineFunction { param_count, .. } = define_func {
-            assert_eq!(*param_count, 20);
+        if let Instruction::DefineFunction { chunk_index } = define_func {
+            assert_eq!(bytecode.functions[*chunk_index].param_count, 20);
         }
     }
 
@@ -2023,15 +2099,27 @@ mod tests {
 
         // Should compile successfully (recursion detection is runtime, not compile-time)
         assert!(bytecode
-            .instructions
-            .iter()
+            .code()
             .any(|i| matches!(i, Instruction::DefineFunction { .. })));
         assert!(bytecode
-            .instructions
-            .iter()
+            .code()
             .any(|i| matches!(i, Instruction::Call { .. })));
     }
 
+    #[test]
+    fn test_function_body_may_call_later_function() {
+        let source =
+            "def outer(n):\n    return inner(n) + 1\ndef inner(n):\n    return n * 2\nouter(5)";
+        let program = crate::parser::parse(crate::lexer::lex(source).unwrap()).unwrap();
+        assert!(compile(&program).is_ok());
+
+        // Top-level code still runs in order
+        let source = "inner(1)\ndef inner(n):\n    return n";
+        let program = crate::parser::parse(crate::lexer::lex(source).unwrap()).unwrap();
+        let err = compile(&program).unwrap_err();
+        assert!(err.message.contains("defined later in program"));
+    }
+
     #[test]
     fn test_compile_function_call_in_assignment() {
         // Test: result = add(1, 2)
@@ -2049,18 +2137,15 @@ mod tests {
 
         // Should have Call and StoreVar, but NO SetResult
         assert!(bytecode
-            .instructions
-            .iter()
+            .code()
             .any(|i| matches!(i, Instruction::Call { .. })));
         assert!(bytecode
-            .instructions
-            .iter()
+            .code()
             .any(|i| matches!(i, Instruction::StoreVar { .. })));
 
         // CRITICAL: Assignment should NOT have SetResult
         let has_setresult = bytecode
-            .instructions
-            .iter()
+            .code()
             .any(|i| matches!(i, Instruction::SetResult { .. }));
         assert!(
             !has_setresult,
@@ -2069,8 +2154,7 @@ mod tests {
     }
 
     #[test]
-    fn test_compile_function_body_metadata_offsets() {
-        // Test: Verify body_start points to correct location
+    fn test_compile_function_bodies_in_own_chunks() {
         // def foo(): return 1
         // def bar(): return 2
         let program = Program {
@@ -2094,119 +2178,42 @@ mod tests {
 
         let bytecode = compile(&program).unwrap();
 
-        // Expected layout:
-        // 0: DefineFunction foo (body_start points after Halt)
-        // 1: DefineFunction bar (body_start points after foo's body)
-        // 2: Halt
-        // 3+: foo body
-        // N+: bar body
-
-        // Find both DefineFunction instructions
-        let define_funcs: Vec<_> = bytecode
-            .instructions
-            .iter()
-            .enumerate()
-            .filter(|(_, i)| matches!(i, Instruction::DefineFunction { .. }))
-            .collect();
-
-        assert_eq!(define_funcs.len(), 2);
-
-        // Find Halt instruction
-        let halt_index = bytecode
-            .instructions
-            .iter()
-            .position(|i| matches!(i, Instruction::Halt))
-            .expect("Should have Halt instruction");
-
-        // Verify body_start for first function
-        if let (
-            _idx1,
-            Instruction::DefineFunction {
-                body_start: start1,
-                body_len: len1,
-                ..
-            },
-        ) = define_funcs[0]
-        {
-            // body_start should point AFTER the Halt instruction
-            assert!(*start1 > halt_index, "body_start should point after Halt");
-            assert!(*len1 > 0, "body_len should be positive");
-        }
-
-        // Verify body_start for second function
-        if let (
-            _idx2,
-            Instruction::DefineFunction {
-                body_start: start2,
-                body_len: len2,
-                ..
-            },
-        ) = define_funcs[1]
-        {
-            // body_start should point AFTER the Halt instruction
-            assert!(*start2 > halt_index, "body_start should point after Halt");
-            assert!(*len2 > 0, "body_len should be positive");
-
-            // Second function should start after first function
-            if let (
-                _,
-                Instruction::DefineFunction {
-                    body_start: start1,
-                    body_len: len1,
+        // Main code is the two definitions and Halt; nothing follows Halt
+        assert_eq!(
+            bytecode.instructions,
+            vec![
+                Instruction::DefineFunction { chunk_index: 0 },
+                Instruction::DefineFunction { chunk_index: 1 },
+                Instruction::Halt,
+            ]
+        );
+        assert_eq!(bytecode.functions.len(), 2);
+        for chunk in &bytecode.functions {
+            assert!(matches!(
+                chunk.instructions.last(),
+                Some(Instruction::Return {
+                    has_value: true,
                     ..
-                },
-            ) = define_funcs[0]
-            {
-                assert!(
-                    *start2 >= start1 + len1,
-                    "Second function should start after first function"
-                );
-            }
+                })
+            ));
         }
+        // Bodies follow main code in the address space, in definition order
+        assert_eq!(bytecode.chunk_start(0), 3);
+        assert_eq!(
+            bytecode.chunk_start(1),
+            3 + bytecode.functions[0].instructions.len()
+        );
     }
 
     // ========== VariableInterner Tests ==========
 
     #[test]
-    fn test_variable_interner_new_preinterns_a_z() {
-        let interner = VariableInterner::new();
-
-        // Verify a-z are pre-interned (26 letters)
-        for c in b'a'..=b'z' {
-            let name = (c as char).to_string();
-            let id = interner.name_to_id.get(&name);
-            assert!(id.is_some(), "Variable '{}' should be pre-interned", name);
-        }
-    }
-
-    #[test]
-    fn test_variable_interner_new_preinterns_common_names() {
+    fn test_variable_interner_new_is_empty() {
         let interner = VariableInterner::new();
 
-        // Verify common names are pre-interned
-        let common_names = vec!["result", "value", "temp", "count", "index", "data"];
-        for name in &common_names {
-            let id = interner.name_to_id.get(*name);
-            assert!(id.is_some(), "Variable '{}' should be pre-interned", name);
-        }
-    }
-
-    #[test]
-    fn test_variable_interner_new_count() {
-        let interner = VariableInterner::new();
-
-        // 26 letters + 6 common names = 32 total
-        assert_eq!(
-            interner.name_to_id.len(),
-            32,
-            "Should have exactly 32 pre-interned names"
-        );
-        assert_eq!(
-            interner.id_to_name.len(),
-            32,
-            "Should have exactly 32 pre-interned IDs"
-        );
-        assert_eq!(interner.next_id, 32, "Next ID should be 32");
+        assert!(interner.name_to_id.is_empty());
+        assert!(interner.id_to_name.is_empty());
+        assert_eq!(interner.next_id, 0, "IDs should start at 0");
     }
 
     #[test]
@@ -2214,13 +2221,10 @@ mod tests {
         let mut interner = VariableInterner::new();
 
         let id = interner.intern("custom_var");
-        assert_eq!(id, 32, "First custom variable should get ID 32");
-        assert_eq!(interner.next_id, 33, "Next ID should be 33");
-        assert_eq!(interner.name_to_id.get("custom_var"), Some(&32));
-        assert_eq!(
-            interner.id_to_name.get(&32),
-            Some(&"custom_var".to_string())
-        );
+        assert_eq!(id, 0, "First variable should get ID 0");
+        assert_eq!(interner.next_id, 1, "Next ID should be 1");
+        assert_eq!(interner.name_to_id.get("custom_var"), Some(&0));
+        assert_eq!(interner.id_to_name.get(&0), Some(&"custom_var".to_string()));
     }
 
     #[test]
@@ -2235,25 +2239,11 @@ mod tests {
         assert_eq!(id2, id3, "Same variable should get same ID");
         assert_eq!(
             interner.name_to_id.len(),
-            33,
+            1,
             "Should only have one entry for my_var"
         );
     }
 
-    #[test]
-    fn test_variable_interner_intern_preintered_name() {
-        let mut interner = VariableInterner::new();
-
-        // Intern a pre-interned name
-        let id_a = interner.intern("a");
-        let id_result = interner.intern("result");
-
-        // Should return the pre-interned IDs, not create new ones
-        assert!(id_a < 32, "Pre-interned 'a' should have ID < 32");
-        assert!(id_result < 32, "Pre-interned 'result' should have ID < 32");
-        assert_eq!(interner.next_id, 32, "Next ID should still be 32");
-    }
-
     #[test]
     fn test_variable_interner_get_name() {
         let mut interner = VariableInterner::new();
@@ -2276,13 +2266,8 @@ mod tests {
 
         let all_names = interner.get_all_names();
 
-        // Should have 32 pre-interned + 2 custom = 34 total
-        assert_eq!(all_names.len(), 34);
-
         // Verify they're in ID order (not alphabetical)
-        // The first 26 should be a-z in order
-        assert_eq!(all_names[0], "a");
-        assert_eq!(all_names[25], "z");
+        assert_eq!(all_names, vec!["zebra", "apple"]);
     }
 
     #[test]
@@ -2290,8 +2275,8 @@ mod tests {
         let interner = VariableInterner::default();
 
         // Default should be same as new()
-        assert_eq!(interner.name_to_id.len(), 32);
-        assert_eq!(interner.next_id, 32);
+        assert!(interner.name_to_id.is_empty());
+        assert_eq!(interner.next_id, 0);
     }
 
     #[test]
@@ -2421,7 +2406,7 @@ mod tests {
     }
 
     #[test]
-    fn test_preintered_variables_use_low_ids() {
+    fn test_var_ids_follow_first_use_order() {
         let program = Program {
             statements: vec![
                 Statement::Assignment {
@@ -2455,20 +2440,403 @@ mod tests {
             }
         }
 
-        // Pre-interned variables should have IDs < 32
-        assert!(
-            var_id_map.get("a").unwrap() < &32,
-            "'a' should be pre-interned with ID < 32"
+        // No name is special: IDs depend only on where a name first appears
+        assert_eq!(var_id_map["a"], 0);
+        assert_eq!(var_id_map["result"], 1);
+        assert_eq!(var_id_map["custom_var"], 2);
+    }
+
+    #[test]
+    fn test_compilation_is_deterministic() {
+        let source = "def f(a, b):\n    c = a * b\n    return c - a\nresult = f(3, 4)\nz = 2\nprint(z + f(result, z))";
+        for opt_level in [OptLevel::O0, OptLevel::O2] {
+            let first = compile_at(source, opt_level);
+            let second = compile_at(source, opt_level);
+            assert_eq!(first.to_bytes(), second.to_bytes());
+            // A fresh compile numbers names by their position in the pool
+            let expected: Vec<u32> = (0..first.var_names.len() as u32).collect();
+            assert_eq!(first.var_ids, expected);
+        }
+    }
+
+    #[test]
+    fn test_compile_multi_assignment_evaluates_value_once() {
+        // a = b = 1 + 2
+        let program = Program {
+            statements: vec![Statement::MultiAssignment {
+                targets: vec!["a".to_string(), "b".to_string()],
+                value: Expression::BinaryOp {
+                    left: Box::new(Expression::Integer(1)),
+                    op: BinaryOperator::Add,
+                    right: Box::new(Expression::Integer(2)),
+                },
+            }],
+        };
+
+        let bytecode = compile(&program).unwrap();
+
+        // Should have: LoadConst, LoadConst, BinaryOp, StoreVar a, StoreVar b, Halt
+        assert_eq!(bytecode.instructions.len(), 6);
+        let binary_ops = bytecode
+            .code()
+            .filter(|i| matches!(i, Instruction::BinaryOp { .. }))
+            .count();
+        assert_eq!(binary_ops, 1);
+
+        let stored: Vec<&str> = bytecode
+            .code()
+            .filter_map(|i| match i {
+                Instruction::StoreVar {
+                    var_name_index,
+                    src_reg: 0,
+                    ..
+                } => Some(bytecode.var_names[*var_name_index].as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(stored, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_compile_with_positions_builds_line_table() {
+        let source = "def f(a):\n    return a + 1\nx = 2\nf(x)";
+        let (program, positions) =
+            crate::parser::parse_with_positions(crate::lexer::lex(source).unwrap()).unwrap();
+        let bytecode = compile_with_positions(&program, &positions).unwrap();
+
+        let line_of = |index| bytecode.source_position(index).map(|pos| pos.line);
+        // DefineFunction predates every statement position
+        assert_eq!(line_of(0), None);
+        for (index, instruction) in bytecode.code().enumerate() {
+            match instruction {
+                Instruction::StoreVar { .. } => assert_eq!(line_of(index), Some(3)),
+                Instruction::Call { .. } => assert_eq!(line_of(index), Some(4)),
+                Instruction::Return { .. } => assert_eq!(line_of(index), Some(2)),
+                _ => {}
+            }
+        }
+
+        // Without positions there is no line table
+        assert!(compile(&program).unwrap().line_table.is_empty());
+    }
+
+    #[test]
+    fn test_line_table_attributes_instructions_to_expressions() {
+        let source = "y = 7
+print(1 + y // (y - 7))";
+        let (program, positions) =
+            crate::parser::parse_with_positions(crate::lexer::lex(source).unwrap()).unwrap();
+        let bytecode = compile_with_positions(&program, &positions).unwrap();
+
+        let at = |index| {
+            let pos = bytecode.source_position(index).unwrap();
+            (pos.line, pos.column)
+        };
+        let ops: Vec<_> = bytecode
+            .code()
+            .enumerate()
+            .filter_map(|(index, instruction)| match instruction {
+                Instruction::BinaryOp { op, .. } => Some((*op, at(index))),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            ops,
+            vec![
+                (BinaryOperator::Sub, (2, 17)),
+                (BinaryOperator::FloorDiv, (2, 11)),
+                (BinaryOperator::Add, (2, 7)),
+            ]
         );
-        assert!(
-            var_id_map.get("result").unwrap() < &32,
-            "'result' should be pre-interned with ID < 32"
+
+        let print = bytecode
+            .code()
+            .position(|instruction| matches!(instruction, Instruction::Print { .. }))
+            .unwrap();
+        assert_eq!(at(print), (2, 1));
+    }
+
+    fn compile_at(source: &str, opt_level: OptLevel) -> Bytecode {
+        let (program, positions) =
+            crate::parser::parse_with_positions(crate::lexer::lex(source).unwrap()).unwrap();
+        compile_with_options(
+            &program,
+            &positions,
+            &CompileOptions {
+                opt_level,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_o1_folds_constant_arithmetic() {
+        let source = "x = 2 * (3 + 4) - -1\nx = x + 1\nprint(x + 1 * 5)";
+        let unoptimized = compile_at(source, OptLevel::O0);
+        assert_eq!(unoptimized.constants, vec![2, 3, 4, 1, 5]);
+
+        let bytecode = compile_at(source, OptLevel::O1);
+        assert_eq!(bytecode.constants, vec![15, 1, 5]);
+        let binary_ops = bytecode
+            .code()
+            .filter(|instruction| matches!(instruction, Instruction::BinaryOp { .. }))
+            .count();
+        // Only `x + 1` and `x + 5` are left, since `x` is reassigned
+        assert_eq!(binary_ops, 2);
+        // The folded load is attributed to the expression it replaces
+        let pos = bytecode.source_position(0).unwrap();
+        assert_eq!((pos.line, pos.column), (1, 5));
+    }
+
+    #[test]
+    fn test_o1_leaves_failing_arithmetic_to_runtime() {
+        for source in [
+            "1 // 0",
+            "9223372036854775807 + 1",
+            "-(-9223372036854775807 - 1)",
+        ] {
+            let bytecode = compile_at(source, OptLevel::O1);
+            let mut vm = crate::vm::VM::new();
+            assert!(
+                vm.execute(&bytecode).is_err(),
+                "{} should still raise",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn test_o1_propagates_single_assignment_constants() {
+        let bytecode = compile_at("n = 10\nn * n", OptLevel::O1);
+        assert_eq!(
+            &bytecode.instructions[2..],
+            &[
+                Instruction::LoadConst {
+                    dest_reg: 0,
+                    const_index: 1
+                },
+                Instruction::SetResult { src_reg: 0 },
+                Instruction::Halt,
+            ]
         );
+        assert_eq!(bytecode.constants, vec![10, 100]);
+    }
 
-        // Custom variable should have ID >= 32
-        assert!(
-            var_id_map.get("custom_var").unwrap() >= &32,
-            "'custom_var' should have ID >= 32"
+    #[test]
+    fn test_o1_keeps_loads_of_changing_variables() {
+        for source in [
+            // Reassigned
+            "n = 10\nn = 11\nn * n",
+            // Parameters differ per call, and globals may not be set yet
+            "def f(a):\n    a = 2\n    return a + g\ng = 1\nf(1)",
+        ] {
+            let bytecode = compile_at(source, OptLevel::O1);
+            assert!(
+                bytecode
+                    .code()
+                    .any(|i| matches!(i, Instruction::LoadVar { .. })),
+                "{} should load a variable",
+                source
+            );
+        }
+        let bytecode = compile_at("n = 10\nn = 11\nn * n", OptLevel::O1);
+        let mut vm = crate::vm::VM::new();
+        let result = vm.execute(&bytecode).unwrap();
+        assert_eq!(vm.format_output(result), "121");
+    }
+
+    #[test]
+    fn test_o1_loads_each_variable_once_per_statement() {
+        let count_loads = |bytecode: &Bytecode| {
+            bytecode
+                .code()
+                .filter(|i| matches!(i, Instruction::LoadVar { .. }))
+                .count()
+        };
+        let source =
+            "def f(a):\n    b = a\n    return b * b\nx = f(3)\ny = x + x * x\nprint(y - x)";
+        assert_eq!(count_loads(&compile_at(source, OptLevel::O0)), 7);
+        // One load per statement, plus `b` once in the body
+        assert_eq!(count_loads(&compile_at(source, OptLevel::O1)), 4);
+    }
+
+    #[test]
+    fn test_o1_reused_loads_keep_results_correct() {
+        let source = "def f(a, b):\n    return a - b\nx = f(7, 2)\nprint(x + (x * 3 + x))\nprint(f(x, x + 1) - -x)\nprint((x + 1) * x)";
+        for opt_level in [OptLevel::O0, OptLevel::O1] {
+            let bytecode = compile_at(source, opt_level);
+            let mut vm = crate::vm::VM::new();
+            let result = vm.execute(&bytecode).unwrap();
+            assert_eq!(vm.format_output(result), "25\n4\n30\n", "{:?}", opt_level);
+        }
+    }
+
+    #[test]
+    fn test_parameters_are_read_from_argument_registers() {
+        let source = "def f(a, b):\n    b = b * 10\n    return g(b, a)\ndef g(x, y):\n    return x - y\nf(1, 2)";
+        let bytecode = compile_at(source, OptLevel::O0);
+        assert!(!bytecode
+            .var_names
+            .iter()
+            .any(|name| name.starts_with("param_")));
+        let moves: Vec<_> = bytecode
+            .code()
+            .filter(|i| matches!(i, Instruction::Move { .. }))
+            .collect();
+        // The assignment to `b`, then both arguments of `g(b, a)`
+        assert_eq!(
+            moves,
+            vec![
+                &Instruction::Move {
+                    dest_reg: 1,
+                    src_reg: 2
+                },
+                &Instruction::Move {
+                    dest_reg: 2,
+                    src_reg: 1
+                },
+                &Instruction::Move {
+                    dest_reg: 3,
+                    src_reg: 0
+                },
+            ]
+        );
+
+        let mut vm = crate::vm::VM::new();
+        let result = vm.execute(&bytecode).unwrap();
+        assert_eq!(vm.format_output(result), "19");
+    }
+
+    #[test]
+    fn test_call_arity_checked_at_compile_time() {
+        let compile_source = |source: &str| {
+            let (program, positions) =
+                crate::parser::parse_with_positions(crate::lexer::lex(source).unwrap()).unwrap();
+            compile_with_positions(&program, &positions)
+        };
+
+        let err = compile_source("def f(a, b):\n    return a + b\nx = 1\nprint(f(x))").unwrap_err();
+        assert_eq!(err.message, "Function f expects 2 arguments, got 1");
+        assert_eq!(err.location, Some(SourcePos { line: 4, column: 7 }));
+
+        // Bodies are checked against functions defined below them, and the
+        // last definition of a name is the one that counts
+        let err = compile_source(
+            "def f():\n    return g(1, 2)\ndef g(a):\n    return a\ndef g(a, b, c):\n    return a",
+        )
+        .unwrap_err();
+        assert_eq!(err.message, "Function g expects 3 arguments, got 2");
+        assert_eq!(
+            err.location,
+            Some(SourcePos {
+                line: 2,
+                column: 12
+            })
+        );
+
+        // Without positions the error is still reported, just unlocated
+        let program =
+            crate::parser::parse(crate::lexer::lex("def f():\n    return 1\nf(1)").unwrap())
+                .unwrap();
+        assert_eq!(compile(&program).unwrap_err().location, None);
+    }
+
+    #[test]
+    fn test_constant_division_by_zero_severity() {
+        let source = "x = 4\nprint(x)\nprint(x % (1 - 1))";
+        let (program, positions) =
+            crate::parser::parse_with_positions(crate::lexer::lex(source).unwrap()).unwrap();
+
+        // By default it is only a warning and the program still compiles
+        let (_, warnings) =
+            compile_with_warnings(&program, &positions, &CompileOptions::default()).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(
+            warnings[0].kind,
+            crate::warnings::WarningKind::DivisionByZero
+        );
+
+        let options = CompileOptions {
+            division_by_zero: Severity::Error,
+            ..Default::default()
+        };
+        let err = compile_with_options(&program, &positions, &options).unwrap_err();
+        assert_eq!(err.message, "Division by zero");
+        assert_eq!(
+            err.location,
+            Some(SourcePos {
+                line: 3,
+                column: 12
+            })
         );
     }
+
+    #[test]
+    fn test_o2_drops_statements_after_return() {
+        let source = "def f(a):\n    return a\n    print(a)\n    a = 1\nf(3)";
+        let o1 = compile_at(source, OptLevel::O1);
+        let o2 = compile_at(source, OptLevel::O2);
+        assert!(o1.code().any(|i| matches!(i, Instruction::Print { .. })));
+        assert!(!o2.code().any(|i| matches!(i, Instruction::Print { .. })));
+        assert_eq!(
+            o2.functions[0].instructions.len(),
+            o1.functions[0].instructions.len() - 3
+        );
+
+        let mut vm = crate::vm::VM::new();
+        let result = vm.execute(&o2).unwrap();
+        assert_eq!(vm.format_output(result), "3");
+    }
+
+    fn run(source: &str) -> String {
+        let bytecode = compile_at(source, OptLevel::O0);
+        let mut vm = crate::vm::VM::new();
+        let result = vm.execute(&bytecode).unwrap();
+        vm.format_output(result)
+    }
+
+    #[test]
+    fn test_registers_are_released_between_statements() {
+        let source: String = (0..300).map(|i| format!("x{} = {}\n", i, i)).collect();
+        let bytecode = compile_at(&format!("{}x299 + x0", source), OptLevel::O0);
+        assert_eq!(bytecode.metadata.max_register_used, 1);
+    }
+
+    #[test]
+    fn test_long_flat_expression_reuses_registers() {
+        let terms: Vec<String> = (1..=300).map(|i| i.to_string()).collect();
+        let source = terms.join(" + ");
+        let bytecode = compile_at(&source, OptLevel::O0);
+        assert_eq!(bytecode.metadata.max_register_used, 1);
+        assert_eq!(run(&source), "45150");
+    }
+
+    #[test]
+    fn test_deeply_nested_expression_spills() {
+        // 1 + (2 + (3 + ...)) keeps every left operand live until the end
+        let depth = 200;
+        let mut source = depth.to_string();
+        for i in (1..depth).rev() {
+            source = format!("{} + ({})", i, source);
+        }
+        let bytecode = compile_at(&source, OptLevel::O0);
+        assert!(bytecode.metadata.max_register_used <= SPILL_THRESHOLD);
+        assert!(bytecode.var_names.iter().any(|name| name == "$spill0"));
+        assert_eq!(run(&source), "20100");
+    }
+
+    #[test]
+    fn test_call_arguments_spill_and_reload_in_order() {
+        // The call sits past the spill threshold, so its arguments go through
+        // spill slots and must come back in the same order
+        let depth = 200;
+        let mut source = "f(9, f(5, 1))".to_string();
+        for i in 0..depth {
+            source = format!("{} + ({})", i % 3, source);
+        }
+        let source = format!("def f(a, b):\n    return a - b\n{}", source);
+        let expected: i64 = (0..depth).map(|i| i % 3).sum::<i64>() + 5;
+        assert_eq!(run(&source), expected.to_string());
+    }
 }
@@ -0,0 +1,379 @@
+//! Compilation as a sequence of named passes
+//!
+//! Every compile runs these built-in passes in order:
+//!
+//! - `resolve` checks scoping rules with [`crate::resolver`] and, if
+//!   [`CompileOptions::division_by_zero`] is an error, rejects constant zero
+//!   divisors, and if [`CompileOptions::warnings`] is, any code that
+//!   [`crate::warnings::check`] warns about
+//! - `optimize` applies the AST rewrites of the [`OptLevel`]: at `O2`,
+//!   dropping statements after a `return`
+//! - `codegen` allocates registers and emits the bytecode
+//!
+//! [`CompilerBuilder`] adds custom passes around them. AST passes run, in the
+//! order added, between `optimize` and `codegen`; bytecode passes run after
+//! `codegen`. Both can rewrite what they are given or stop compilation with
+//! a [`CompileError`].
+//!
+//! # Example
+//!
+//! ```
+//! use pyrust::ast::{simplify, Program, Statement, StatementPos};
+//! use pyrust::bytecode::Bytecode;
+//! use pyrust::compiler::{AstPass, BytecodePass, CompilerBuilder};
+//! use pyrust::error::CompileError;
+//! use pyrust::error_code::ErrorCode;
+//! use pyrust::{lexer, parser};
+//!
+//! /// Folds printed literals, whatever the optimization level
+//! struct FoldPrints;
+//!
+//! impl AstPass for FoldPrints {
+//!     fn name(&self) -> &str {
+//!         "fold-prints"
+//!     }
+//!
+//!     fn run(&self, program: &mut Program, _: &mut Vec<StatementPos>) -> Result<(), CompileError> {
+//!         for stmt in &mut program.statements {
+//!             if let Statement::Print { value } = stmt {
+//!                 *value = simplify::simplify(value);
+//!             }
+//!         }
+//!         Ok(())
+//!     }
+//! }
+//!
+//! /// Rejects programs longer than a fixed budget
+//! struct MaxInstructions(usize);
+//!
+//! impl BytecodePass for MaxInstructions {
+//!     fn name(&self) -> &str {
+//!         "max-instructions"
+//!     }
+//!
+//!     fn run(&self, bytecode: &mut Bytecode) -> Result<(), CompileError> {
+//!         if bytecode.instructions.len() > self.0 {
+//!             return Err(CompileError {
+//!                 code: ErrorCode::CompileFailed,
+//!                 message: "Program is too long".to_string(),
+//!                 location: None,
+//!                 end: None,
+//!             });
+//!         }
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let pipeline = CompilerBuilder::new()
+//!     .ast_pass(FoldPrints)
+//!     .bytecode_pass(MaxInstructions(3))
+//!     .build();
+//! assert_eq!(
+//!     pipeline.pass_names(),
+//!     vec!["resolve", "optimize", "fold-prints", "codegen", "max-instructions"]
+//! );
+//!
+//! // LoadConst, Print, Halt
+//! let program = parser::parse(lexer::lex("print(2 * 3 + 1)").unwrap()).unwrap();
+//! assert_eq!(pipeline.compile(&program, &[]).unwrap().constants, vec![7]);
+//!
+//! let program = parser::parse(lexer::lex("x = 1\nprint(x)").unwrap()).unwrap();
+//! assert!(pipeline.compile(&program, &[]).is_err());
+//! ```
+
+use super::{CompileOptions, Compiler, OptLevel, Severity, VariableInterner};
+use crate::ast::{Program, Statement, StatementPos};
+use crate::bytecode::Bytecode;
+use crate::error::CompileError;
+use crate::error_code::ErrorCode;
+use crate::resolver::{self, SymbolTable};
+use crate::warnings;
+use std::borrow::Cow;
+
+/// A rewrite or check of the parsed program
+///
+/// `positions` parallels `program.statements` as described for
+/// [`crate::compiler::compile_with_positions`], and may be empty. A pass that
+/// adds or removes statements must keep it parallel, or clear it.
+pub trait AstPass {
+    /// Name listed by [`Pipeline::pass_names`]
+    fn name(&self) -> &str;
+
+    fn run(
+        &self,
+        program: &mut Program,
+        positions: &mut Vec<StatementPos>,
+    ) -> Result<(), CompileError>;
+}
+
+/// A rewrite or check of the generated bytecode
+///
+/// Jump targets and line table entries are local to their chunk, so a pass
+/// that moves instructions only has to keep that chunk consistent.
+pub trait BytecodePass {
+    /// Name listed by [`Pipeline::pass_names`]
+    fn name(&self) -> &str;
+
+    fn run(&self, bytecode: &mut Bytecode) -> Result<(), CompileError>;
+}
+
+/// Builder for a [`Pipeline`] with custom options and passes
+#[derive(Default)]
+pub struct CompilerBuilder {
+    options: CompileOptions,
+    ast_passes: Vec<Box<dyn AstPass>>,
+    bytecode_passes: Vec<Box<dyn BytecodePass>>,
+}
+
+impl CompilerBuilder {
+    /// A builder for the default pipeline: default options, no custom passes
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the options the built-in passes use
+    pub fn options(mut self, options: CompileOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Run `pass` after the built-in AST passes and any added before it
+    pub fn ast_pass(mut self, pass: impl AstPass + 'static) -> Self {
+        self.ast_passes.push(Box::new(pass));
+        self
+    }
+
+    /// Run `pass` after code generation and any bytecode passes added before it
+    pub fn bytecode_pass(mut self, pass: impl BytecodePass + 'static) -> Self {
+        self.bytecode_passes.push(Box::new(pass));
+        self
+    }
+
+    pub fn build(self) -> Pipeline {
+        Pipeline {
+            options: self.options,
+            ast_passes: self.ast_passes,
+            bytecode_passes: self.bytecode_passes,
+        }
+    }
+}
+
+/// A configured sequence of compiler passes
+///
+/// A pipeline holds no state between compiles, so one can be built once and
+/// reused for any number of programs.
+pub struct Pipeline {
+    options: CompileOptions,
+    ast_passes: Vec<Box<dyn AstPass>>,
+    bytecode_passes: Vec<Box<dyn BytecodePass>>,
+}
+
+impl Pipeline {
+    /// Names of every pass, in the order they run
+    pub fn pass_names(&self) -> Vec<&str> {
+        let mut names = vec!["resolve", "optimize"];
+        names.extend(self.ast_passes.iter().map(|pass| pass.name()));
+        names.push("codegen");
+        names.extend(self.bytecode_passes.iter().map(|pass| pass.name()));
+        names
+    }
+
+    /// Options the built-in passes use
+    pub fn options(&self) -> &CompileOptions {
+        &self.options
+    }
+
+    /// Run every pass over `program`
+    ///
+    /// `positions` may be empty; see [`crate::compiler::compile_with_positions`].
+    pub fn compile(
+        &self,
+        program: &Program,
+        positions: &[StatementPos],
+    ) -> Result<Bytecode, CompileError> {
+        self.compile_with_interner(program, positions, &mut VariableInterner::new())
+    }
+
+    /// Run every pass, assigning variable IDs from `interner`
+    ///
+    /// See [`crate::compiler::compile_with_interner`].
+    pub fn compile_with_interner(
+        &self,
+        program: &Program,
+        positions: &[StatementPos],
+        interner: &mut VariableInterner,
+    ) -> Result<Bytecode, CompileError> {
+        let mut symbols = self.resolve(program, positions)?;
+        let mut program = Cow::Borrowed(program);
+        let mut positions = Cow::Borrowed(positions);
+        self.optimize(&mut program, &mut positions);
+
+        if !self.ast_passes.is_empty() {
+            let program = program.to_mut();
+            let mut owned_positions = positions.into_owned();
+            for pass in &self.ast_passes {
+                pass.run(program, &mut owned_positions)?;
+            }
+            positions = Cow::Owned(owned_positions);
+            // The passes may have added or renamed functions
+            symbols = resolver::resolve(program)?;
+        }
+
+        let mut compiler = Compiler::new();
+        compiler.options = self.options;
+        std::mem::swap(&mut compiler.interner, interner);
+        let result = compiler.generate(&program, &positions, &symbols);
+        std::mem::swap(&mut compiler.interner, interner);
+        let mut bytecode = result?;
+
+        for pass in &self.bytecode_passes {
+            pass.run(&mut bytecode)?;
+        }
+        Ok(bytecode)
+    }
+
+    /// The `resolve` pass
+    fn resolve(
+        &self,
+        program: &Program,
+        positions: &[StatementPos],
+    ) -> Result<SymbolTable, CompileError> {
+        let symbols = resolver::resolve(program)?;
+        if self.options.division_by_zero == Severity::Error {
+            if let Some(found) = warnings::division_by_zero(program, positions)
+                .into_iter()
+                .next()
+            {
+                return Err(CompileError {
+                    code: ErrorCode::ConstantZeroDivisor,
+                    message: "Division by zero".to_string(),
+                    location: found.pos,
+                    end: found.end,
+                });
+            }
+        }
+        if self.options.warnings == Severity::Error {
+            if let Some(first) = warnings::check(program, positions).into_iter().next() {
+                return Err(first.into_error());
+            }
+        }
+        Ok(symbols)
+    }
+
+    /// The `optimize` pass
+    ///
+    /// Only copies the program if a rewrite applies, so programs with
+    /// nothing to optimize compile without the clone.
+    fn optimize(&self, program: &mut Cow<Program>, positions: &mut Cow<[StatementPos]>) {
+        if self.options.opt_level < OptLevel::O2 {
+            return;
+        }
+        for index in 0..program.statements.len() {
+            let Statement::FunctionDef { body, .. } = &program.statements[index] else {
+                continue;
+            };
+            // Nothing after a return can run
+            let Some(end) = body
+                .iter()
+                .position(|stmt| matches!(stmt, Statement::Return { .. }))
+                .map(|index| index + 1)
+                .filter(|&end| end < body.len())
+            else {
+                continue;
+            };
+            if let Statement::FunctionDef { body, .. } = &mut program.to_mut().statements[index] {
+                body.truncate(end);
+            }
+            if index < positions.len() {
+                positions.to_mut()[index].body.truncate(end);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Expression;
+    use crate::{lexer, parser, vm::VM};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Appends `print(answer())` and a definition of `answer`
+    struct AddAnswer;
+
+    impl AstPass for AddAnswer {
+        fn name(&self) -> &str {
+            "add-answer"
+        }
+
+        fn run(
+            &self,
+            program: &mut Program,
+            positions: &mut Vec<StatementPos>,
+        ) -> Result<(), CompileError> {
+            positions.clear();
+            program.statements.insert(
+                0,
+                Statement::FunctionDef {
+                    name: "answer".to_string(),
+                    params: Vec::new(),
+                    body: vec![Statement::Return {
+                        value: Some(Expression::Integer(42)),
+                    }],
+                },
+            );
+            program.statements.push(Statement::Print {
+                value: Expression::Call {
+                    name: "answer".to_string(),
+                    args: Vec::new(),
+                },
+            });
+            Ok(())
+        }
+    }
+
+    /// Records the order passes run in
+    struct Record(&'static str, Rc<RefCell<Vec<&'static str>>>);
+
+    impl BytecodePass for Record {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn run(&self, _: &mut Bytecode) -> Result<(), CompileError> {
+            self.1.borrow_mut().push(self.0);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_ast_passes_run_before_codegen() {
+        let (program, positions) =
+            parser::parse_with_positions(lexer::lex("print(1)").unwrap()).unwrap();
+        let pipeline = CompilerBuilder::new().ast_pass(AddAnswer).build();
+
+        // The added function is resolved even though the input never defined it
+        let bytecode = pipeline.compile(&program, &positions).unwrap();
+        let mut vm = VM::new();
+        vm.execute(&bytecode).unwrap();
+        assert_eq!(vm.format_output(None), "1\n42\n");
+    }
+
+    #[test]
+    fn test_bytecode_passes_run_in_order() {
+        let order = Rc::new(RefCell::new(Vec::new()));
+        let pipeline = CompilerBuilder::new()
+            .bytecode_pass(Record("first", order.clone()))
+            .bytecode_pass(Record("second", order.clone()))
+            .build();
+        assert_eq!(
+            pipeline.pass_names(),
+            vec!["resolve", "optimize", "codegen", "first", "second"]
+        );
+
+        let program = parser::parse(lexer::lex("print(1)").unwrap()).unwrap();
+        pipeline.compile(&program, &[]).unwrap();
+        assert_eq!(*order.borrow(), vec!["first", "second"]);
+    }
+}
@@ -6,8 +6,22 @@
 //! - Unix socket event loop at /tmp/pyrust.sock
 //! - Signal handling (SIGTERM/SIGINT) for graceful shutdown
 //! - PID file management at /tmp/pyrust.pid
-//! - Request timeout to prevent hung connections
+//! - Request timeout to prevent hung connections, and per-request time
+//!   limits enforced inside the VM
+//! - Cancellation of a running request when the client sends a cancel frame
+//!   or disconnects
 //! - Socket permissions set to 0600 (owner only)
+//! - A pool of reusable VMs, so requests skip VM construction
+//! - Request counts and latencies, served with the cache statistics as
+//!   Prometheus metrics
+//! - A listing of the programs in the cache
+//! - Print output of streamed requests sent back as it is produced
+//! - Named sessions whose globals persist across requests and connections
+//! - An optional shared-secret token that clients must present first
+//!   ([`AUTH_TOKEN_ENV`])
+//! - Per-request resource limits ([`crate::daemon_limits`])
+//! - Named instances with their own socket and PID file
+//!   ([`DAEMON_NAME_ENV`]), so several daemons can share a machine
 //!
 //! # Example
 //!
@@ -18,16 +32,39 @@
 //! daemon.run().unwrap();
 //! ```
 
-use crate::daemon_protocol::{DaemonRequest, DaemonResponse, ProtocolError};
-use crate::execute_python_cached_global;
+use crate::cancel::CancellationToken;
+use crate::daemon_limits::RequestLimits;
+use crate::daemon_protocol::{
+    Compression, DaemonRequest, DaemonResponse, ProtocolError, ARGS_MARKER, AUTH_MARKER,
+    BATCH_MARKER, CACHE_LIST_MARKER, CACHE_STATS_MARKER, CACHE_SUMMARY_MARKER, CANCEL_MARKER,
+    CANCEL_REQUEST_MARKER, CLEAR_CACHE_MARKER, COMPRESSED_MARKER, COMPRESSION_MARKER,
+    METRICS_MARKER, PING_MARKER, PRIORITY_MARKER, REQUEST_ID_MARKER, REQUEST_PREFIXES,
+    SESSION_MARKER, STATS_MARKER, TIMEOUT_MARKER,
+};
+use crate::daemon_session::{execute_in_session, SessionError, SessionTable};
+use crate::daemon_transport::{self, Listener, Stream};
+use crate::diagnostic::Diagnostic;
+use crate::error_code::ErrorCode;
+use crate::metrics::{self, DaemonStats, RequestMetrics};
+use crate::profiling::PipelineProfile;
+use crate::session::Session;
+use crate::vm::{StdoutSink, WatchdogAction, VM};
+use crate::vm_pool::VmPool;
+use crate::{
+    cache, clear_global_cache, compile_cached_global, compile_cached_global_profiled,
+    compile_profiled, compile_python, execute_compiled_on, execute_compiled_profiled_on,
+    get_global_cache_stats, global_cache_entries,
+};
+use std::collections::HashMap;
 use std::fs;
 use std::io::{Read, Write};
-use std::os::unix::fs::PermissionsExt;
-use std::os::unix::net::{UnixListener, UnixStream};
-use std::path::Path;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
-use std::time::Duration;
+use std::net::Shutdown;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
 /// Default socket path
 pub const SOCKET_PATH: &str = "/tmp/pyrust.sock";
@@ -35,11 +72,89 @@ pub const SOCKET_PATH: &str = "/tmp/pyrust.sock";
 /// Default PID file path
 pub const PID_FILE_PATH: &str = "/tmp/pyrust.pid";
 
+/// Environment variable naming the daemon instance to serve or talk to
+///
+/// Instance `name` uses `/tmp/pyrust-<name>.sock` and `/tmp/pyrust-<name>.pid`
+/// instead of the default paths.
+pub const DAEMON_NAME_ENV: &str = "PYRUST_DAEMON_NAME";
+
+/// Check that `name` can name a daemon instance
+///
+/// Names go into file paths, so they are limited to 1 to 64 ASCII letters,
+/// digits, `-` and `_`.
+pub fn is_valid_daemon_name(name: &str) -> bool {
+    (1..=64).contains(&name.len())
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+/// The instance named by [`DAEMON_NAME_ENV`], if set to a valid name
+///
+/// Anything else selects the default instance.
+pub fn daemon_name_from_env() -> Option<String> {
+    std::env::var(DAEMON_NAME_ENV)
+        .ok()
+        .filter(|name| is_valid_daemon_name(name))
+}
+
+/// Socket and PID file paths of instance `name`, or of the default one
+pub fn instance_paths(name: Option<&str>) -> (String, String) {
+    let stem = match name {
+        Some(name) => format!("pyrust-{}", name),
+        None => "pyrust".to_string(),
+    };
+    let path = |extension: &str| {
+        runtime_dir()
+            .join(format!("{}.{}", stem, extension))
+            .display()
+            .to_string()
+    };
+    (path("sock"), path("pid"))
+}
+
+/// Directory of the daemon's socket, PID file and log
+///
+/// `/tmp`, or the user's temporary directory on Windows.
+pub fn runtime_dir() -> PathBuf {
+    if cfg!(windows) {
+        std::env::temp_dir()
+    } else {
+        PathBuf::from("/tmp")
+    }
+}
+
 /// Request timeout in seconds
 pub const REQUEST_TIMEOUT_SECS: u64 = 30;
 
 /// Maximum request size (10 MB)
-const MAX_REQUEST_SIZE: usize = 10 * 1024 * 1024;
+pub(crate) const MAX_REQUEST_SIZE: usize = 10 * 1024 * 1024;
+
+/// Time a connection may sit idle, with nothing in flight, before it is closed
+pub(crate) const IDLE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Instructions between checks of a request's deadline
+const TIMEOUT_CHECK_INTERVAL: u64 = 1024;
+
+/// Number of VMs in the default request pool
+pub const DEFAULT_VM_POOL_SIZE: usize = 4;
+
+/// Environment variable holding the shared secret for daemon connections
+///
+/// When set (and not empty) as the daemon starts, every connection must
+/// open with an auth frame carrying the same token;
+/// [`crate::daemon_client::DaemonClient`] sends it from the same variable.
+pub const AUTH_TOKEN_ENV: &str = "PYRUST_DAEMON_TOKEN";
+
+/// Error sent to a connection that did not authenticate first
+pub(crate) const AUTH_REQUIRED_MESSAGE: &str = "Authentication required";
+
+/// The shared secret from [`AUTH_TOKEN_ENV`], if one is set
+pub fn auth_token_from_env() -> Option<String> {
+    std::env::var(AUTH_TOKEN_ENV)
+        .ok()
+        .filter(|token| !token.is_empty())
+}
 
 /// Daemon server error types
 #[derive(Debug)]
@@ -52,6 +167,8 @@ pub enum DaemonError {
     SocketInUse(String),
     /// PID file error
     PidFileError(String),
+    /// A background daemon did not come up
+    StartFailed(String),
 }
 
 impl std::fmt::Display for DaemonError {
@@ -61,6 +178,7 @@ impl std::fmt::Display for DaemonError {
             DaemonError::Protocol(e) => write!(f, "Protocol error: {}", e),
             DaemonError::SocketInUse(path) => write!(f, "Socket already in use: {}", path),
             DaemonError::PidFileError(msg) => write!(f, "PID file error: {}", msg),
+            DaemonError::StartFailed(msg) => write!(f, "Failed to start daemon: {}", msg),
         }
     }
 }
@@ -79,45 +197,788 @@ impl From<ProtocolError> for DaemonError {
     }
 }
 
+/// Message read from a client connection
+pub(crate) enum ClientMessage {
+    /// Code to execute
+    Execute(DaemonRequest),
+    /// Requests to execute one after another, each answered in turn
+    Batch(Vec<DaemonRequest>),
+    /// Abort the request currently executing
+    Cancel,
+    /// Abort the request with this id, executing or not
+    CancelRequest(u32),
+    /// Report the daemon's metrics
+    Metrics,
+    /// Report the daemon's stats
+    Stats,
+    /// Check the daemon is serving
+    Ping,
+    /// List the programs in the cache
+    CacheList,
+    /// Summarize the cache and its hottest programs
+    CacheSummary,
+    /// Report every counter of the cache
+    CacheStats,
+    /// Empty the in-memory cache
+    ClearCache,
+    /// Attach the connection to a session; empty to detach
+    Session(String),
+    /// Present the shared-secret token
+    Auth(String),
+    /// Compress large responses with this algorithm
+    Compression(u32),
+}
+
+impl ClientMessage {
+    /// Output of the answer to a report or cache administration frame; None
+    /// for other messages
+    pub(crate) fn report(
+        &self,
+        metrics: &RequestMetrics,
+        sessions: &SessionTable,
+    ) -> Option<String> {
+        match self {
+            ClientMessage::Metrics => {
+                Some(metrics::render(&get_global_cache_stats(), Some(metrics)))
+            }
+            ClientMessage::Ping => Some("pong".to_string()),
+            ClientMessage::Stats => Some(
+                DaemonStats::collect(metrics, &get_global_cache_stats(), sessions.len()).encode(),
+            ),
+            ClientMessage::CacheList => Some(cache::format_entries(&global_cache_entries())),
+            ClientMessage::CacheSummary => Some(cache::format_summary(&get_global_cache_stats())),
+            ClientMessage::CacheStats => Some(cache::format_stats(&get_global_cache_stats())),
+            ClientMessage::ClearCache => {
+                let removed = get_global_cache_stats().size;
+                clear_global_cache();
+                log::info!("cache cleared entries={}", removed);
+                Some(removed.to_string())
+            }
+            ClientMessage::Execute(_)
+            | ClientMessage::Batch(_)
+            | ClientMessage::Cancel
+            | ClientMessage::CancelRequest(_)
+            | ClientMessage::Session(_)
+            | ClientMessage::Auth(_)
+            | ClientMessage::Compression(_) => None,
+        }
+    }
+}
+
+/// What a [`MessageDecoder`] needs to make progress
+pub(crate) enum Next {
+    /// Another 4-byte word
+    Word,
+    /// A prefix block of this many bytes, then another word
+    Prefix(usize),
+    /// A body of this many bytes
+    Body(usize),
+    /// Nothing more: the message is complete
+    Message(ClientMessage),
+}
+
+/// Decodes one client message from the words and body read off a connection
+///
+/// A server feeds it 4-byte words until it asks for a body or yields a
+/// message, so the sync and async servers share the framing rules while
+/// doing their own I/O.
+#[derive(Default)]
+pub(crate) struct MessageDecoder {
+    /// Request prefixes and length read so far, in wire order
+    header: Vec<u8>,
+    /// Marker of the session, auth, batch, compressed or value frame being read
+    marker: Option<u32>,
+    /// The next word is the value of a timeout, priority or id prefix
+    value_next: bool,
+    /// The next word is the length of an args prefix block
+    block_next: bool,
+    /// Prefix markers read so far; each may lead a request only once, which
+    /// also bounds `header`
+    prefixes: Vec<u32>,
+}
+
+impl MessageDecoder {
+    /// Take the next word of the message
+    pub(crate) fn word(&mut self, word: [u8; 4]) -> Result<Next, DaemonError> {
+        let value = u32::from_be_bytes(word);
+        if self.value_next {
+            self.value_next = false;
+            self.header.extend_from_slice(&word);
+            return Ok(Next::Word);
+        }
+        if self.block_next {
+            self.block_next = false;
+            let length = value as usize;
+            check_request_size(length)?;
+            self.header.extend_from_slice(&word);
+            return Ok(Next::Prefix(length));
+        }
+        match self.marker {
+            Some(CANCEL_REQUEST_MARKER) => {
+                return Ok(Next::Message(ClientMessage::CancelRequest(value)))
+            }
+            Some(COMPRESSION_MARKER) => {
+                return Ok(Next::Message(ClientMessage::Compression(value)))
+            }
+            _ => {}
+        }
+        if self.marker.is_some() {
+            let length = value as usize;
+            check_request_size(length)?;
+            return Ok(Next::Body(length));
+        }
+
+        // Control and session frames stand alone; prefixes only lead requests
+        let first = self.header.is_empty();
+        let control = match value {
+            CANCEL_MARKER => Some(ClientMessage::Cancel),
+            METRICS_MARKER => Some(ClientMessage::Metrics),
+            STATS_MARKER => Some(ClientMessage::Stats),
+            PING_MARKER => Some(ClientMessage::Ping),
+            CACHE_LIST_MARKER => Some(ClientMessage::CacheList),
+            CACHE_SUMMARY_MARKER => Some(ClientMessage::CacheSummary),
+            CACHE_STATS_MARKER => Some(ClientMessage::CacheStats),
+            CLEAR_CACHE_MARKER => Some(ClientMessage::ClearCache),
+            _ => None,
+        };
+        match (value, control) {
+            (_, Some(control)) if first => Ok(Next::Message(control)),
+            (
+                SESSION_MARKER
+                | AUTH_MARKER
+                | BATCH_MARKER
+                | CANCEL_REQUEST_MARKER
+                | COMPRESSION_MARKER
+                | COMPRESSED_MARKER,
+                _,
+            ) if first => {
+                self.marker = Some(value);
+                Ok(Next::Word)
+            }
+            (prefix, _) if REQUEST_PREFIXES.contains(&prefix) => {
+                if self.prefixes.contains(&prefix) {
+                    return Err(ProtocolError::RepeatedPrefix(prefix).into());
+                }
+                self.prefixes.push(prefix);
+                self.header.extend_from_slice(&word);
+                match prefix {
+                    TIMEOUT_MARKER | PRIORITY_MARKER | REQUEST_ID_MARKER => self.value_next = true,
+                    ARGS_MARKER => self.block_next = true,
+                    _ => {}
+                }
+                Ok(Next::Word)
+            }
+            _ => {
+                let length = value as usize;
+                check_request_size(length)?;
+                self.header.extend_from_slice(&word);
+                Ok(Next::Body(length))
+            }
+        }
+    }
+
+    /// Take the block of a prefix it asked for; more words follow
+    pub(crate) fn prefix(&mut self, block: &[u8]) {
+        self.header.extend_from_slice(block);
+    }
+
+    /// Finish the message with the body it asked for
+    pub(crate) fn body(self, body: &[u8]) -> Result<ClientMessage, DaemonError> {
+        match self.marker {
+            Some(BATCH_MARKER) => {
+                return Ok(ClientMessage::Batch(DaemonRequest::decode_batch(body)?))
+            }
+            Some(COMPRESSED_MARKER) => {
+                let request = DaemonRequest::decode_compressed(body)?;
+                check_request_size(request.code().len())?;
+                return Ok(ClientMessage::Execute(request));
+            }
+            _ => {}
+        }
+        if let Some(marker) = self.marker {
+            let text = std::str::from_utf8(body)
+                .map_err(|e| ProtocolError::InvalidUtf8(e.to_string()))?
+                .to_string();
+            return Ok(match marker {
+                SESSION_MARKER => ClientMessage::Session(text),
+                _ => ClientMessage::Auth(text),
+            });
+        }
+        let mut message = self.header;
+        message.extend_from_slice(body);
+        let (request, _bytes_consumed) = DaemonRequest::decode(&message)?;
+        Ok(ClientMessage::Execute(request))
+    }
+}
+
+/// Reject a request longer than [`MAX_REQUEST_SIZE`]
+pub(crate) fn check_request_size(length: usize) -> Result<(), DaemonError> {
+    if length > MAX_REQUEST_SIZE {
+        return Err(DaemonError::Protocol(ProtocolError::IncompleteMessage(
+            format!(
+                "Request too large: {} bytes (max {})",
+                length, MAX_REQUEST_SIZE
+            ),
+        )));
+    }
+    Ok(())
+}
+
+/// Answer to a message on a connection that has not authenticated yet
+///
+/// Returns None if `message` may go ahead: no token is `expected`, it is an
+/// auth frame with the right one, or it is a ping. Otherwise the connection
+/// gets the returned error and is closed.
+///
+/// Pings are answered but leave the connection unauthenticated.
+pub(crate) fn check_auth(
+    expected: Option<&str>,
+    message: &ClientMessage,
+) -> Option<DaemonResponse> {
+    let expected = expected?;
+    match message {
+        ClientMessage::Auth(token) if tokens_match(token, expected) => None,
+        ClientMessage::Auth(_) => Some(DaemonResponse::error("Invalid authentication token")),
+        ClientMessage::Ping => None,
+        _ => Some(DaemonResponse::error(AUTH_REQUIRED_MESSAGE)),
+    }
+}
+
+/// Compare tokens in time that depends only on their lengths
+fn tokens_match(presented: &str, expected: &str) -> bool {
+    presented.len() == expected.len()
+        && presented
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Attach the connection to session `id`, or detach it if `id` is empty
+///
+/// `current` holds the connection's session. Returns the answer to the
+/// session frame.
+pub(crate) fn attach_session(
+    sessions: &SessionTable,
+    current: &mut Option<String>,
+    id: String,
+) -> DaemonResponse {
+    if id.is_empty() {
+        *current = None;
+        return DaemonResponse::success("");
+    }
+    match sessions.attach(&id) {
+        Ok(()) => {
+            let response = DaemonResponse::success(id.as_str());
+            *current = Some(id);
+            response
+        }
+        Err(e) => DaemonResponse::error(e.to_string()),
+    }
+}
+
+/// Turn on compression with wire value `algorithm` for the connection
+///
+/// `current` holds the connection's compression. Returns the answer to the
+/// compression frame; an unknown algorithm leaves `current` alone.
+pub(crate) fn negotiate_compression(
+    current: &mut Option<Compression>,
+    algorithm: u32,
+) -> DaemonResponse {
+    match Compression::from_u32(algorithm) {
+        Ok(compression) => {
+            *current = Some(compression);
+            DaemonResponse::success(compression.name())
+        }
+        Err(_) => DaemonResponse::error(format!("Unsupported compression algorithm {}", algorithm)),
+    }
+}
+
+/// The session a connection is attached to, if any
+pub(crate) fn lookup_session(
+    sessions: &SessionTable,
+    current: Option<&str>,
+) -> Result<Option<Arc<Mutex<Session>>>, SessionError> {
+    current.map(|id| sessions.get(id)).transpose()
+}
+
+/// Run `request` in `session`, or through the global cache on a VM from `pool`
+///
+/// With a `sink`, print output goes there instead of into the response. A
+/// request with a timeout is stopped by a watchdog once it runs out, and
+/// answered with a timeout response; one stopped by `limits` gets a
+/// limit-exceeded response. A pooled VM is reset and returned to the pool
+/// when done.
+///
+/// Also returns how the request ended; whether the bytecode came from the
+/// cache, None in a session or for a request that bypasses the cache, which
+/// both compile without it; and for a profiled request outside a session,
+/// the profile response to send before the final one.
+pub(crate) fn execute_request(
+    pool: &VmPool,
+    session: Option<&Mutex<Session>>,
+    request: &DaemonRequest,
+    limits: &RequestLimits,
+    token: CancellationToken,
+    sink: Option<StdoutSink>,
+) -> Executed {
+    let expired = Arc::new(AtomicBool::new(false));
+    let prepare = |vm: &mut VM| {
+        limits.apply(vm);
+        vm.set_cancellation_token(token);
+        if let Some(sink) = sink {
+            vm.set_stdout_sink(sink);
+        }
+        if let Some(timeout) = request.timeout() {
+            set_deadline(vm, timeout, Arc::clone(&expired));
+        }
+        vm.set_args(request.args().to_vec());
+    };
+    let mut profile = None;
+    let (result, cache_hit) = match session {
+        Some(session) => (execute_in_session(session, request.code(), prepare), None),
+        None if request.is_profiled() => {
+            let mut vm = pool.checkout();
+            prepare(&mut vm);
+            let mut stages = PipelineProfile::default();
+            let started = Instant::now();
+            let compiled = if request.uses_cache() {
+                compile_cached_global_profiled(request.code(), &mut stages)
+            } else {
+                compile_profiled(request.code(), &mut stages).map(|b| (Arc::new(b), false))
+            };
+            let (result, cache_hit) = match compiled {
+                Ok((bytecode, cache_hit)) => {
+                    let code = request.code();
+                    let result =
+                        execute_compiled_profiled_on(&mut vm, &bytecode, code, &mut stages);
+                    (result, cache_hit)
+                }
+                Err(e) => (Err(e), false),
+            };
+            stages.total_ns = started.elapsed().as_nanos() as u64;
+            let text = format!("{}cache_hit {}\n", stages.encode(), cache_hit);
+            profile = Some(DaemonResponse::profile(text));
+            (result, request.uses_cache().then_some(cache_hit))
+        }
+        None => {
+            let compiled = if request.uses_cache() {
+                compile_cached_global(request.code()).map(|(b, cache_hit)| (b, Some(cache_hit)))
+            } else {
+                compile_python(request.code()).map(|b| (Arc::new(b), None))
+            };
+            match compiled {
+                Ok((bytecode, cache_hit)) => {
+                    let mut vm = pool.checkout();
+                    prepare(&mut vm);
+                    let result = execute_compiled_on(&mut vm, &bytecode, request.code());
+                    (result, cache_hit)
+                }
+                Err(e) => (Err(e), request.uses_cache().then_some(false)),
+            }
+        }
+    };
+    let (response, outcome) = match (result, request.timeout()) {
+        (Ok(output), _) => (DaemonResponse::success(output), Outcome::Success),
+        (Err(_), Some(timeout)) if expired.load(Ordering::SeqCst) => {
+            (timed_out(timeout), Outcome::Timeout)
+        }
+        (Err(e), _) => match (e.exit_code(), limits.exceeded(&e)) {
+            (Some(code), _) => (DaemonResponse::exit(code), Outcome::Exit(code)),
+            (None, Some((limit, message))) => (
+                DaemonResponse::limit_exceeded(limit.name(), message),
+                Outcome::LimitExceeded,
+            ),
+            (None, None) => {
+                let response = if request.wants_diagnostics() {
+                    DaemonResponse::failure(&Diagnostic::from(&e))
+                } else {
+                    DaemonResponse::error(e.to_string())
+                };
+                let outcome = if e.code() == ErrorCode::Cancelled {
+                    Outcome::Cancelled
+                } else {
+                    Outcome::Error
+                };
+                (response, outcome)
+            }
+        },
+    };
+    Executed {
+        response,
+        outcome,
+        cache_hit,
+        profile,
+    }
+}
+
+/// What [`execute_request`] made of a request
+pub(crate) struct Executed {
+    /// The final response
+    pub response: DaemonResponse,
+    pub outcome: Outcome,
+    /// Whether the bytecode came from the cache, if it went through it
+    pub cache_hit: Option<bool>,
+    /// Profile response to send before the final one
+    pub profile: Option<DaemonResponse>,
+}
+
+impl Executed {
+    /// A request that failed before it could run, with `response`
+    pub(crate) fn failed(response: DaemonResponse) -> Self {
+        Self {
+            response,
+            outcome: Outcome::Error,
+            cache_hit: None,
+            profile: None,
+        }
+    }
+}
+
+/// How a request ended, as logged by [`log_request`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Outcome {
+    Success,
+    /// The program called `exit(n)`
+    Exit(i32),
+    Timeout,
+    LimitExceeded,
+    /// Stopped by a cancel frame or the client going away
+    Cancelled,
+    Error,
+}
+
+impl Outcome {
+    /// Name of the outcome in log lines
+    fn name(&self) -> &'static str {
+        match self {
+            Outcome::Success => "success",
+            Outcome::Exit(_) => "exit",
+            Outcome::Timeout => "timeout",
+            Outcome::LimitExceeded => "limit_exceeded",
+            Outcome::Cancelled => "cancelled",
+            Outcome::Error => "error",
+        }
+    }
+}
+
+/// Log a line for a finished request: how long it took, whether its
+/// bytecode came from the cache, and how it ended
+///
+/// `cache_hit` is as returned by [`execute_request`].
+pub(crate) fn log_request(
+    elapsed: Duration,
+    cache_hit: Option<bool>,
+    outcome: Outcome,
+    response: &DaemonResponse,
+) {
+    let cache = match cache_hit {
+        Some(true) => "hit",
+        Some(false) => "miss",
+        None => "bypass",
+    };
+    let duration_ms = elapsed.as_secs_f64() * 1000.0;
+    match outcome {
+        Outcome::Success => log::info!(
+            "request duration_ms={:.3} cache={} outcome=success",
+            duration_ms,
+            cache
+        ),
+        Outcome::Exit(code) => log::info!(
+            "request duration_ms={:.3} cache={} outcome=exit code={}",
+            duration_ms,
+            cache,
+            code
+        ),
+        _ => log::info!(
+            "request duration_ms={:.3} cache={} outcome={} error={:?}",
+            duration_ms,
+            cache,
+            outcome.name(),
+            response.output()
+        ),
+    }
+}
+
+/// Abort the VM's run once `timeout` has passed, raising `expired`
+pub(crate) fn set_deadline(vm: &mut VM, timeout: Duration, expired: Arc<AtomicBool>) {
+    let deadline = Instant::now() + timeout;
+    vm.set_watchdog(TIMEOUT_CHECK_INTERVAL, move |_| {
+        if Instant::now() < deadline {
+            WatchdogAction::Continue
+        } else {
+            expired.store(true, Ordering::SeqCst);
+            WatchdogAction::Abort("request timed out".to_string())
+        }
+    });
+}
+
+/// Answer to a request that ran out of time
+pub(crate) fn timed_out(timeout: Duration) -> DaemonResponse {
+    DaemonResponse::timeout(format!(
+        "Request timed out after {:.1}s",
+        timeout.as_secs_f64()
+    ))
+}
+
+/// Remove a stale socket at `socket_path`, failing if a daemon answers on it
+pub(crate) fn claim_socket_path(socket_path: &str) -> Result<(), DaemonError> {
+    if Path::new(socket_path).exists() {
+        // Try to connect to check if daemon is running
+        if daemon_transport::connect(socket_path).is_ok() {
+            return Err(DaemonError::SocketInUse(socket_path.to_string()));
+        }
+        // Socket exists but no daemon listening - remove stale socket
+        fs::remove_file(socket_path)?;
+    }
+    Ok(())
+}
+
+/// Set a bound socket's permissions to 0600 (owner only)
+///
+/// Windows has no such mode; see [`crate::daemon_transport`].
+#[cfg(unix)]
+pub(crate) fn restrict_socket(socket_path: &str) -> Result<(), DaemonError> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let metadata = fs::metadata(socket_path)?;
+    let mut permissions = metadata.permissions();
+    permissions.set_mode(0o600);
+    fs::set_permissions(socket_path, permissions)?;
+    Ok(())
+}
+
+#[cfg(windows)]
+pub(crate) fn restrict_socket(_socket_path: &str) -> Result<(), DaemonError> {
+    Ok(())
+}
+
+/// Write this process's PID to `pid_file_path`, replacing any old one
+pub(crate) fn write_pid_file(pid_file_path: &str) -> Result<(), DaemonError> {
+    let pid = std::process::id();
+
+    // If PID file exists, check if the process is still running
+    if Path::new(pid_file_path).exists() {
+        if let Ok(old_pid_str) = fs::read_to_string(pid_file_path) {
+            if let Ok(_old_pid) = old_pid_str.trim().parse::<u32>() {
+                // For now, just remove the old PID file
+                // In production, we would check if the process is running
+                let _ = fs::remove_file(pid_file_path);
+            }
+        }
+    }
+
+    fs::write(pid_file_path, pid.to_string())
+        .map_err(|e| DaemonError::PidFileError(format!("Failed to write PID file: {}", e)))?;
+    Ok(())
+}
+
+/// Remove the socket and PID file
+///
+/// Without `socket_path`, the socket is left for whoever bound it.
+pub(crate) fn remove_daemon_files(
+    socket_path: Option<&str>,
+    pid_file_path: &str,
+) -> Result<(), DaemonError> {
+    // Remove socket
+    if let Some(socket_path) = socket_path.filter(|path| Path::new(path).exists()) {
+        fs::remove_file(socket_path)?;
+    }
+
+    // Remove PID file
+    let _ = fs::remove_file(pid_file_path);
+
+    Ok(())
+}
+
+/// State shared between a connection's reader thread and the thread serving it
+#[derive(Default)]
+pub(crate) struct ConnectionState {
+    /// Requests read but not yet answered
+    pub(crate) in_flight: AtomicUsize,
+    /// Id and token of the request currently executing
+    running: Mutex<Option<(Option<u32>, CancellationToken)>>,
+    /// Ids of requests read but not yet executing, and whether each has
+    /// been cancelled
+    waiting: Mutex<HashMap<u32, bool>>,
+}
+
+impl ConnectionState {
+    /// Cancel the executing request, if any
+    pub(crate) fn cancel_running(&self) {
+        if let Some((_, token)) = lock(&self.running).as_ref() {
+            token.cancel();
+        }
+    }
+
+    /// Note the ids of the requests in `message`, just read, so that they
+    /// can be cancelled before they execute
+    pub(crate) fn expect(&self, message: &ClientMessage) {
+        let requests = match message {
+            ClientMessage::Execute(request) => std::slice::from_ref(request),
+            ClientMessage::Batch(batch) => batch.as_slice(),
+            _ => return,
+        };
+        let mut waiting = lock(&self.waiting);
+        // Requests answered without executing leave their ids behind
+        if self.in_flight.load(Ordering::SeqCst) == 0 {
+            waiting.clear();
+        }
+        for id in requests.iter().filter_map(DaemonRequest::id) {
+            waiting.insert(id, false);
+        }
+    }
+
+    /// Cancel the request with `id`, whether executing or waiting
+    pub(crate) fn cancel_request(&self, id: u32) {
+        let running = lock(&self.running);
+        match running.as_ref() {
+            Some((Some(running_id), token)) if *running_id == id => token.cancel(),
+            _ => {
+                if let Some(cancelled) = lock(&self.waiting).get_mut(&id) {
+                    *cancelled = true;
+                }
+            }
+        }
+    }
+
+    /// Park the token of the request with `id` that starts executing
+    ///
+    /// If the request was cancelled while it waited, so is the token.
+    pub(crate) fn start(&self, id: Option<u32>, token: CancellationToken) {
+        // Locked in the same order as by cancel_request
+        let mut running = lock(&self.running);
+        if let Some(true) = id.and_then(|id| lock(&self.waiting).remove(&id)) {
+            token.cancel();
+        }
+        *running = Some((id, token));
+    }
+
+    /// Clear the token of the request that finished executing
+    pub(crate) fn finish(&self) {
+        *lock(&self.running) = None;
+    }
+}
+
+/// Lock `mutex`, even if a thread panicked while holding it
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
 /// Unix socket daemon server
 pub struct DaemonServer {
     socket_path: String,
     pid_file_path: String,
+    /// Socket bound by someone else, accepted on instead of binding
+    inherited: Option<Listener>,
     shutdown_flag: Arc<AtomicBool>,
+    vm_pool: Arc<VmPool>,
+    sessions: SessionTable,
+    auth_token: Option<String>,
+    limits: RequestLimits,
+    metrics: RequestMetrics,
 }
 
 impl DaemonServer {
-    /// Create a new daemon server with default paths
+    /// Create a new daemon server at the paths of the instance named by
+    /// [`DAEMON_NAME_ENV`], or the default ones
+    ///
+    /// Under systemd socket activation, accepts on the socket systemd
+    /// passed in; see [`crate::daemon_activation`].
     pub fn new() -> Result<Self, DaemonError> {
-        Self::with_paths(SOCKET_PATH.to_string(), PID_FILE_PATH.to_string())
+        let (socket_path, pid_file_path) = instance_paths(daemon_name_from_env().as_deref());
+        #[cfg(unix)]
+        if let Some(listener) = crate::daemon_activation::take_listener()? {
+            return Ok(Self::with_listener(listener, socket_path, pid_file_path));
+        }
+        Self::with_paths(socket_path, pid_file_path)
     }
 
     /// Create a new daemon server with custom paths
     pub fn with_paths(socket_path: String, pid_file_path: String) -> Result<Self, DaemonError> {
-        // Check if socket already exists
-        if Path::new(&socket_path).exists() {
-            // Try to connect to check if daemon is running
-            if UnixStream::connect(&socket_path).is_ok() {
-                return Err(DaemonError::SocketInUse(socket_path));
-            }
-            // Socket exists but no daemon listening - remove stale socket
-            fs::remove_file(&socket_path)?;
-        }
+        claim_socket_path(&socket_path)?;
+        Ok(Self::build(socket_path, pid_file_path, None))
+    }
+
+    /// Create a new daemon server accepting on `listener`, which is bound
+    /// at `socket_path` by someone else
+    ///
+    /// The server leaves the socket's mode alone and does not remove it.
+    #[cfg(unix)]
+    pub fn with_listener(
+        listener: std::os::unix::net::UnixListener,
+        socket_path: String,
+        pid_file_path: String,
+    ) -> Self {
+        Self::build(socket_path, pid_file_path, Some(listener))
+    }
 
+    fn build(socket_path: String, pid_file_path: String, inherited: Option<Listener>) -> Self {
         let shutdown_flag = Arc::new(AtomicBool::new(false));
 
         // Setup signal handlers
         Self::setup_signal_handlers(Arc::clone(&shutdown_flag));
 
-        Ok(Self {
+        Self {
             socket_path,
             pid_file_path,
+            inherited,
             shutdown_flag,
-        })
+            vm_pool: Arc::new(VmPool::new(DEFAULT_VM_POOL_SIZE)),
+            sessions: SessionTable::default(),
+            auth_token: auth_token_from_env(),
+            limits: RequestLimits::from_env(),
+            metrics: RequestMetrics::new(),
+        }
+    }
+
+    /// Execute requests on VMs from `pool`
+    ///
+    /// Per-VM limits are configured through the pool's factory, once for
+    /// every request this server handles.
+    pub fn set_vm_pool(&mut self, pool: VmPool) {
+        self.vm_pool = Arc::new(pool);
+    }
+
+    /// The pool requests are executed on
+    pub fn vm_pool(&self) -> &VmPool {
+        &self.vm_pool
+    }
+
+    /// Keep sessions in `sessions`, replacing the default limits
+    pub fn set_sessions(&mut self, sessions: SessionTable) {
+        self.sessions = sessions;
+    }
+
+    /// The sessions clients have attached to
+    pub fn sessions(&self) -> &SessionTable {
+        &self.sessions
+    }
+
+    /// Require connections to present `token` first, or nothing if None
+    ///
+    /// Defaults to [`AUTH_TOKEN_ENV`] as it was when the server was created.
+    pub fn set_auth_token(&mut self, token: Option<String>) {
+        self.auth_token = token;
+    }
+
+    /// Stop requests that go over `limits`
+    ///
+    /// Defaults to the limits in the environment as it was when the server
+    /// was created (see [`RequestLimits::from_env`]).
+    pub fn set_limits(&mut self, limits: RequestLimits) {
+        self.limits = limits;
+    }
+
+    /// Counts and latencies of the requests served so far
+    pub fn metrics(&self) -> &RequestMetrics {
+        &self.metrics
     }
 
     /// Setup signal handlers for SIGTERM and SIGINT
-    fn setup_signal_handlers(shutdown_flag: Arc<AtomicBool>) {
+    pub(crate) fn setup_signal_handlers(shutdown_flag: Arc<AtomicBool>) {
         // Create signal handler for SIGTERM
         let shutdown_flag_term = Arc::clone(&shutdown_flag);
         unsafe {
@@ -137,44 +998,23 @@ impl DaemonServer {
         }
     }
 
-    /// Write PID file
-    fn write_pid_file(&self) -> Result<(), DaemonError> {
-        let pid = std::process::id();
-
-        // If PID file exists, check if the process is still running
-        if Path::new(&self.pid_file_path).exists() {
-            if let Ok(old_pid_str) = fs::read_to_string(&self.pid_file_path) {
-                if let Ok(_old_pid) = old_pid_str.trim().parse::<u32>() {
-                    // For now, just remove the old PID file
-                    // In production, we would check if the process is running
-                    let _ = fs::remove_file(&self.pid_file_path);
-                }
-            }
-        }
-
-        fs::write(&self.pid_file_path, pid.to_string())
-            .map_err(|e| DaemonError::PidFileError(format!("Failed to write PID file: {}", e)))?;
-        Ok(())
-    }
-
-    /// Remove PID file
-    fn remove_pid_file(&self) {
-        let _ = fs::remove_file(&self.pid_file_path);
-    }
-
     /// Run the daemon server
     pub fn run(&self) -> Result<(), DaemonError> {
-        // Bind to Unix socket
-        let listener = UnixListener::bind(&self.socket_path)?;
+        let bound;
+        let listener = match &self.inherited {
+            Some(listener) => listener,
+            None => {
+                // Bind to Unix socket (a loopback port on Windows)
+                bound = daemon_transport::bind(&self.socket_path)?;
 
-        // Set socket permissions to 0600 (owner only)
-        let metadata = fs::metadata(&self.socket_path)?;
-        let mut permissions = metadata.permissions();
-        permissions.set_mode(0o600);
-        fs::set_permissions(&self.socket_path, permissions)?;
+                // Set socket permissions to 0600 (owner only)
+                restrict_socket(&self.socket_path)?;
+                &bound
+            }
+        };
 
         // Write PID file
-        self.write_pid_file()?;
+        write_pid_file(&self.pid_file_path)?;
 
         // Set non-blocking mode for the listener to check shutdown flag
         listener.set_nonblocking(true)?;
@@ -191,7 +1031,7 @@ impl DaemonServer {
                 Ok((stream, _addr)) => {
                     // Handle connection
                     if let Err(e) = self.handle_connection(stream) {
-                        eprintln!("Error handling connection: {}", e);
+                        log::warn!("Error handling connection: {}", e);
                     }
                 }
                 Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
@@ -199,7 +1039,7 @@ impl DaemonServer {
                     std::thread::sleep(Duration::from_micros(100));
                 }
                 Err(e) => {
-                    eprintln!("Error accepting connection: {}", e);
+                    log::error!("Error accepting connection: {}", e);
                 }
             }
         }
@@ -211,85 +1051,229 @@ impl DaemonServer {
     }
 
     /// Handle a client connection (supports multiple requests on same connection)
-    fn handle_connection(&self, mut stream: UnixStream) -> Result<(), DaemonError> {
+    fn handle_connection(&self, stream: Stream) -> Result<(), DaemonError> {
         // Ensure socket is in blocking mode (listener is non-blocking but streams should block)
         stream.set_nonblocking(false)?;
 
         // Set idle timeout for persistent connections (5 seconds between requests)
         // This allows connection reuse for fast clients (benchmarks) while not blocking
         // new connections for too long
-        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+        stream.set_read_timeout(Some(IDLE_TIMEOUT))?;
         stream.set_write_timeout(Some(Duration::from_secs(REQUEST_TIMEOUT_SECS)))?;
 
-        // Handle multiple requests on same connection until client closes or idle timeout
-        loop {
-            // Read request (will return error when client closes or timeout)
-            let request = match self.read_request(&mut stream) {
-                Ok(req) => req,
-                Err(DaemonError::Io(ref e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
-                    // Client closed connection gracefully
-                    break;
+        // Requests are read on a separate thread so that cancel frames and
+        // disconnects are noticed while a request is executing
+        let state = Arc::new(ConnectionState::default());
+        let (request_tx, requests) = mpsc::channel();
+        let reader = {
+            let stream = stream.try_clone()?;
+            let state = Arc::clone(&state);
+            thread::spawn(move || Self::read_requests(stream, &state, &request_tx))
+        };
+
+        let result = self.serve_requests(&stream, &requests, &state);
+
+        // Unblock the reader if serving stopped first
+        let _ = stream.shutdown(Shutdown::Both);
+        let _ = reader.join();
+
+        result
+    }
+
+    /// Answer the messages forwarded by the reader thread, in order
+    fn serve_requests(
+        &self,
+        mut stream: &Stream,
+        requests: &Receiver<Result<ClientMessage, DaemonError>>,
+        state: &ConnectionState,
+    ) -> Result<(), DaemonError> {
+        let mut session = None;
+        let mut compression = None;
+        let mut authenticated = false;
+        // Ends when the reader stops (client closed or idle timeout)
+        for message in requests {
+            let message = message?;
+            if !authenticated || matches!(message, ClientMessage::Auth(_)) {
+                if let Some(rejection) = check_auth(self.auth_token.as_deref(), &message) {
+                    self.write_response(&mut stream, &rejection, None)?;
+                    return Ok(());
                 }
-                Err(DaemonError::Io(ref e))
-                    if e.kind() == std::io::ErrorKind::WouldBlock
-                        || e.kind() == std::io::ErrorKind::TimedOut =>
-                {
-                    // Idle timeout - no request received in 5 seconds, close connection
-                    break;
+                authenticated = !matches!(message, ClientMessage::Ping);
+            }
+            if let ClientMessage::Auth(_) = message {
+                self.write_response(&mut stream, &DaemonResponse::success(""), None)?;
+                state.in_flight.fetch_sub(1, Ordering::SeqCst);
+                continue;
+            }
+            if let Some(text) = message.report(&self.metrics, &self.sessions) {
+                self.write_response(&mut stream, &DaemonResponse::success(text), compression)?;
+                state.in_flight.fetch_sub(1, Ordering::SeqCst);
+                continue;
+            }
+            let batch = match message {
+                ClientMessage::Execute(request) => vec![request],
+                ClientMessage::Batch(batch) => batch,
+                ClientMessage::Session(id) => {
+                    let response = attach_session(&self.sessions, &mut session, id);
+                    self.write_response(&mut stream, &response, None)?;
+                    state.in_flight.fetch_sub(1, Ordering::SeqCst);
+                    continue;
                 }
-                Err(e) => return Err(e),
-            };
-
-            // Execute code using global cache (shared across all daemon requests)
-            let response = match execute_python_cached_global(request.code()) {
-                Ok(output) => DaemonResponse::success(output),
-                Err(e) => DaemonResponse::error(e.to_string()),
+                ClientMessage::Compression(algorithm) => {
+                    let response = negotiate_compression(&mut compression, algorithm);
+                    self.write_response(&mut stream, &response, None)?;
+                    state.in_flight.fetch_sub(1, Ordering::SeqCst);
+                    continue;
+                }
+                // Cancel frames are handled by the reader thread
+                _ => continue,
             };
-
-            // Send response
-            self.write_response(&mut stream, &response)?;
+            for request in batch {
+                let response = self.execute(stream, &request, session.as_deref(), state)?;
+                self.write_response(&mut stream, &response, compression)?;
+            }
+            state.in_flight.fetch_sub(1, Ordering::SeqCst);
         }
 
         Ok(())
     }
 
-    /// Read a request from the stream
-    fn read_request(&self, stream: &mut UnixStream) -> Result<DaemonRequest, DaemonError> {
-        // Read length prefix (4 bytes)
-        let mut length_buf = [0u8; 4];
-        stream.read_exact(&mut length_buf)?;
-        let length = u32::from_be_bytes(length_buf) as usize;
+    /// Execute `request`, in `session` if the connection is attached to one
+    ///
+    /// The print output of a streamed request is written to `stream` in
+    /// chunks while it runs, and the profile of a profiled one after it
+    /// finishes; the final response is left to the caller.
+    fn execute(
+        &self,
+        mut stream: &Stream,
+        request: &DaemonRequest,
+        session: Option<&str>,
+        state: &ConnectionState,
+    ) -> Result<DaemonResponse, DaemonError> {
+        let started = Instant::now();
+        let token = CancellationToken::new();
+        state.start(request.id(), token.clone());
 
-        // Check size limit
-        if length > MAX_REQUEST_SIZE {
-            return Err(DaemonError::Protocol(ProtocolError::IncompleteMessage(
-                format!(
-                    "Request too large: {} bytes (max {})",
-                    length, MAX_REQUEST_SIZE
-                ),
-            )));
+        // Execute code using global cache (shared across all daemon requests)
+        // on a pooled VM
+        let sink = if request.is_streaming() {
+            Some(Self::chunk_writer(stream.try_clone()?))
+        } else {
+            None
+        };
+        let executed = match lookup_session(&self.sessions, session) {
+            Ok(target) => execute_request(
+                &self.vm_pool,
+                target.as_deref(),
+                request,
+                &self.limits,
+                token,
+                sink,
+            ),
+            Err(e) => Executed::failed(DaemonResponse::error(e.to_string())),
+        };
+        state.finish();
+        if let Some(profile) = &executed.profile {
+            self.write_response(&mut stream, profile, None)?;
         }
+        let response = executed.response;
+        self.metrics
+            .record(started.elapsed(), response.is_success());
+        log_request(
+            started.elapsed(),
+            executed.cache_hit,
+            executed.outcome,
+            &response,
+        );
+        Ok(response)
+    }
 
-        // Read code
-        let mut code_buf = vec![0u8; length];
-        stream.read_exact(&mut code_buf)?;
+    /// Sink that sends each print to the client as an output chunk
+    ///
+    /// Write errors are ignored: a client that went away is noticed by the
+    /// reader thread, which cancels the request.
+    fn chunk_writer(mut stream: Stream) -> StdoutSink {
+        Box::new(move |text: &str| {
+            let _ = stream.write_all(&DaemonResponse::chunk(text).encode());
+        })
+    }
 
-        // Reconstruct full message and decode
-        let mut full_message = Vec::with_capacity(4 + length);
-        full_message.extend_from_slice(&length_buf);
-        full_message.extend_from_slice(&code_buf);
+    /// Read messages from the client until it closes the connection or goes idle
+    ///
+    /// Requests, metrics and cache list frames are forwarded to `requests`; cancel frames and disconnects
+    /// cancel whatever request is executing, and cancel-request frames the request they name.
+    fn read_requests(
+        mut stream: Stream,
+        state: &ConnectionState,
+        requests: &Sender<Result<ClientMessage, DaemonError>>,
+    ) {
+        loop {
+            match Self::read_message(&mut stream) {
+                Ok(ClientMessage::Cancel) => state.cancel_running(),
+                Ok(ClientMessage::CancelRequest(id)) => state.cancel_request(id),
+                Ok(message) => {
+                    state.expect(&message);
+                    state.in_flight.fetch_add(1, Ordering::SeqCst);
+                    if requests.send(Ok(message)).is_err() {
+                        break;
+                    }
+                }
+                Err(DaemonError::Io(ref e))
+                    if e.kind() == std::io::ErrorKind::WouldBlock
+                        || e.kind() == std::io::ErrorKind::TimedOut =>
+                {
+                    // Idle timeout - no request received in 5 seconds, close
+                    // connection. A slow request is not idleness.
+                    if state.in_flight.load(Ordering::SeqCst) == 0 {
+                        break;
+                    }
+                }
+                Err(DaemonError::Io(ref e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    // Client closed connection; nobody is left to wait for a running request
+                    state.cancel_running();
+                    break;
+                }
+                Err(e) => {
+                    state.cancel_running();
+                    let _ = requests.send(Err(e));
+                    break;
+                }
+            }
+        }
+    }
 
-        let (request, _bytes_consumed) = DaemonRequest::decode(&full_message)?;
-        Ok(request)
+    /// Read a request or a control frame from the stream
+    fn read_message(stream: &mut impl Read) -> Result<ClientMessage, DaemonError> {
+        let mut decoder = MessageDecoder::default();
+        loop {
+            let mut word = [0u8; 4];
+            stream.read_exact(&mut word)?;
+            match decoder.word(word)? {
+                Next::Word => {}
+                Next::Prefix(length) => {
+                    let mut block = vec![0u8; length];
+                    stream.read_exact(&mut block)?;
+                    decoder.prefix(&block);
+                }
+                Next::Message(message) => return Ok(message),
+                Next::Body(length) => {
+                    let mut body = vec![0u8; length];
+                    stream.read_exact(&mut body)?;
+                    return decoder.body(&body);
+                }
+            }
+        }
     }
 
-    /// Write a response to the stream
+    /// Write a response to the stream, compressed with `compression` if
+    /// it is large
     fn write_response(
         &self,
-        stream: &mut UnixStream,
+        stream: &mut impl Write,
         response: &DaemonResponse,
+        compression: Option<Compression>,
     ) -> Result<(), DaemonError> {
-        let encoded = response.encode();
+        let encoded = response.encode_with(compression);
         stream.write_all(&encoded)?;
         stream.flush()?;
         Ok(())
@@ -297,15 +1281,8 @@ impl DaemonServer {
 
     /// Cleanup resources (socket and PID file)
     fn cleanup(&self) -> Result<(), DaemonError> {
-        // Remove socket
-        if Path::new(&self.socket_path).exists() {
-            fs::remove_file(&self.socket_path)?;
-        }
-
-        // Remove PID file
-        self.remove_pid_file();
-
-        Ok(())
+        let socket_path = Some(self.socket_path.as_str()).filter(|_| self.inherited.is_none());
+        remove_daemon_files(socket_path, &self.pid_file_path)
     }
 
     /// Stop the daemon (for testing)
@@ -321,9 +1298,10 @@ impl Drop for DaemonServer {
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, unix))]
 mod tests {
     use super::*;
+    use std::os::unix::net::UnixStream;
 
     #[test]
     fn test_daemon_error_display() {
@@ -373,4 +1351,732 @@ mod tests {
         assert_eq!(SOCKET_PATH, "/tmp/pyrust.sock");
         assert_eq!(PID_FILE_PATH, "/tmp/pyrust.pid");
     }
+
+    #[test]
+    fn test_instance_paths() {
+        assert_eq!(
+            instance_paths(None),
+            (SOCKET_PATH.to_string(), PID_FILE_PATH.to_string())
+        );
+        assert_eq!(
+            instance_paths(Some("proj-a_1")),
+            (
+                "/tmp/pyrust-proj-a_1.sock".to_string(),
+                "/tmp/pyrust-proj-a_1.pid".to_string()
+            )
+        );
+        assert!(is_valid_daemon_name("proj-a_1"));
+        for name in ["", "../etc", "a b", "ü", &"x".repeat(65)] {
+            assert!(!is_valid_daemon_name(name), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn test_default_vm_pool_size() {
+        assert_eq!(DEFAULT_VM_POOL_SIZE, 4);
+    }
+
+    /// Program making 2^depth calls, far too slow to finish in a test
+    fn slow_program(depth: usize) -> String {
+        let mut source = format!("def f{}():\n    return 1\n", depth);
+        for i in (0..depth).rev() {
+            source.push_str(&format!(
+                "def f{}():\n    return f{}() + f{}()\n",
+                i,
+                i + 1,
+                i + 1
+            ));
+        }
+        source.push_str("f0()");
+        source
+    }
+
+    /// Run a daemon on private paths; returns it with its event-loop thread
+    fn spawn_daemon(
+        name: &str,
+    ) -> (
+        Arc<DaemonServer>,
+        thread::JoinHandle<Result<(), DaemonError>>,
+        String,
+    ) {
+        let base = std::env::temp_dir().join(format!("pyrust-{}-{}", name, std::process::id()));
+        let socket_path = format!("{}.sock", base.display());
+        let server = Arc::new(
+            DaemonServer::with_paths(socket_path.clone(), format!("{}.pid", base.display()))
+                .unwrap(),
+        );
+        let runner = {
+            let server = Arc::clone(&server);
+            thread::spawn(move || server.run())
+        };
+        while !Path::new(&socket_path).exists() {
+            thread::sleep(Duration::from_millis(5));
+        }
+        (server, runner, socket_path)
+    }
+
+    fn read_response(stream: &mut UnixStream) -> DaemonResponse {
+        let mut header = [0u8; 5];
+        stream.read_exact(&mut header).unwrap();
+        let length = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+        let mut message = header.to_vec();
+        message.resize(5 + length, 0);
+        stream.read_exact(&mut message[5..]).unwrap();
+        DaemonResponse::decode(&message).unwrap().0
+    }
+
+    #[test]
+    fn test_cancel_frame_aborts_running_request() {
+        let (server, runner, socket_path) = spawn_daemon("cancel-frame");
+
+        let mut stream = UnixStream::connect(&socket_path).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        stream
+            .write_all(&DaemonRequest::new(slow_program(40)).encode())
+            .unwrap();
+        thread::sleep(Duration::from_millis(50));
+        stream.write_all(&DaemonRequest::encode_cancel()).unwrap();
+
+        let response = read_response(&mut stream);
+        assert!(response.is_error());
+        assert!(response.output().contains(crate::cancel::CANCELLED_MESSAGE));
+
+        // The connection keeps serving requests after a cancellation
+        stream
+            .write_all(&DaemonRequest::new("6 * 7").encode())
+            .unwrap();
+        assert_eq!(read_response(&mut stream).output(), "42");
+
+        drop(stream);
+        server.stop();
+        runner.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_cancel_request_frame_cancels_by_id() {
+        let (server, runner, socket_path) = spawn_daemon("cancel-request");
+
+        let mut stream = UnixStream::connect(&socket_path).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        stream
+            .write_all(&DaemonRequest::new(slow_program(40)).with_id(1).encode())
+            .unwrap();
+        stream
+            .write_all(&DaemonRequest::new("6 * 7").with_id(2).encode())
+            .unwrap();
+        stream
+            .write_all(&DaemonRequest::new("6 * 7").with_id(3).encode())
+            .unwrap();
+        thread::sleep(Duration::from_millis(50));
+
+        // The waiting request is answered as cancelled without running;
+        // ids that match nothing unanswered are ignored
+        stream
+            .write_all(&DaemonRequest::encode_cancel_request(2))
+            .unwrap();
+        stream
+            .write_all(&DaemonRequest::encode_cancel_request(99))
+            .unwrap();
+        stream
+            .write_all(&DaemonRequest::encode_cancel_request(1))
+            .unwrap();
+        for _ in 0..2 {
+            let response = read_response(&mut stream);
+            assert!(response.is_error());
+            assert!(response.output().contains(crate::cancel::CANCELLED_MESSAGE));
+        }
+        assert_eq!(read_response(&mut stream).output(), "42");
+
+        drop(stream);
+        server.stop();
+        runner.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_streamed_request_sends_output_chunks() {
+        let (server, runner, socket_path) = spawn_daemon("stream");
+
+        let mut stream = UnixStream::connect(&socket_path).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        stream
+            .write_all(&DaemonRequest::streaming("print(1)\nprint(2)\n3").encode())
+            .unwrap();
+        let chunks: Vec<_> = (0..3).map(|_| read_response(&mut stream)).collect();
+        assert!(chunks[0].is_chunk() && chunks[1].is_chunk());
+        assert_eq!(chunks[0].output(), "1\n");
+        assert_eq!(chunks[1].output(), "2\n");
+        assert!(chunks[2].is_success());
+        assert_eq!(chunks[2].output(), "3");
+
+        // Output arrives while the request is still running
+        let code = format!("print(7)\n{}", slow_program(40));
+        stream
+            .write_all(&DaemonRequest::streaming(code).encode())
+            .unwrap();
+        let first = read_response(&mut stream);
+        assert!(first.is_chunk());
+        assert_eq!(first.output(), "7\n");
+        stream.write_all(&DaemonRequest::encode_cancel()).unwrap();
+        assert!(read_response(&mut stream).is_error());
+
+        drop(stream);
+        server.stop();
+        runner.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_script_arguments_reach_the_program() {
+        let (server, runner, socket_path) = spawn_daemon("args");
+
+        let mut stream = UnixStream::connect(&socket_path).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        let args = vec!["6".to_string(), "7".to_string()];
+        let request = DaemonRequest::streaming("print(argc())\nargv(0) * argv(1)")
+            .with_args(args)
+            .with_timeout(Duration::from_secs(5));
+        stream.write_all(&request.encode()).unwrap();
+        assert_eq!(read_response(&mut stream).output(), "2\n");
+        assert_eq!(read_response(&mut stream).output(), "42");
+
+        // The next request on the pooled VM has none
+        stream
+            .write_all(&DaemonRequest::new("argc()").encode())
+            .unwrap();
+        assert_eq!(read_response(&mut stream).output(), "0");
+
+        drop(stream);
+        server.stop();
+        runner.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_exit_is_reported_with_its_code() {
+        let (server, runner, socket_path) = spawn_daemon("exit");
+
+        let mut stream = UnixStream::connect(&socket_path).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        let request = DaemonRequest::streaming("print(1)\nexit(3)\nprint(2)");
+        stream.write_all(&request.encode()).unwrap();
+        assert_eq!(read_response(&mut stream).output(), "1\n");
+        assert_eq!(read_response(&mut stream).exit_code(), Some(3));
+
+        // exit() alone means success
+        stream
+            .write_all(&DaemonRequest::new("exit()").encode())
+            .unwrap();
+        assert_eq!(read_response(&mut stream).exit_code(), Some(0));
+
+        drop(stream);
+        server.stop();
+        runner.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_uncached_request_bypasses_the_cache() {
+        let pool = VmPool::new(1);
+        let limits = RequestLimits::from_env();
+        let code = "uncached_marker = 12\nuncached_marker * 2";
+        let run = |request: &DaemonRequest| {
+            let executed = execute_request(
+                &pool,
+                None,
+                request,
+                &limits,
+                CancellationToken::new(),
+                None,
+            );
+            assert_eq!(executed.response.output(), "24");
+            executed.cache_hit
+        };
+
+        for _ in 0..2 {
+            assert_eq!(run(&DaemonRequest::new(code).without_cache()), None);
+        }
+        // Nothing was cached, so the first cached request still misses
+        assert_eq!(run(&DaemonRequest::new(code)), Some(false));
+        assert_eq!(run(&DaemonRequest::new(code)), Some(true));
+    }
+
+    #[test]
+    fn test_failure_is_sent_as_a_diagnostic_when_asked() {
+        use crate::diagnostic::Stage;
+
+        let pool = VmPool::new(1);
+        let limits = RequestLimits::from_env();
+        let run = |request: DaemonRequest| {
+            execute_request(
+                &pool,
+                None,
+                &request,
+                &limits,
+                CancellationToken::new(),
+                None,
+            )
+            .response
+        };
+
+        let code = "x = 0\nprint(1 / x)";
+        let diagnostic = run(DaemonRequest::new(code).with_diagnostics())
+            .diagnostic()
+            .unwrap();
+        assert_eq!(diagnostic.stage, Stage::Runtime);
+        assert_eq!(diagnostic.message, "ZeroDivisionError: Division by zero");
+        assert_eq!(diagnostic.span.map(|span| span.line), Some(2));
+        // Otherwise, the message as before
+        let response = run(DaemonRequest::new(code));
+        assert!(response.is_error() && response.diagnostic().is_none());
+
+        // exit(n) is not a failure
+        let response = run(DaemonRequest::new("exit(2)").with_diagnostics());
+        assert_eq!(response.exit_code(), Some(2));
+    }
+
+    #[test]
+    fn test_outcome_of_a_cancelled_request() {
+        let pool = VmPool::new(1);
+        let limits = RequestLimits::from_env();
+        let token = CancellationToken::new();
+        token.cancel();
+        for request in [
+            DaemonRequest::new("print(1)"),
+            DaemonRequest::new("print(1)").with_diagnostics(),
+        ] {
+            let executed = execute_request(&pool, None, &request, &limits, token.clone(), None);
+            assert_eq!(executed.outcome, Outcome::Cancelled);
+        }
+
+        let request = DaemonRequest::new("print(1 / 0)").with_diagnostics();
+        let executed = execute_request(
+            &pool,
+            None,
+            &request,
+            &limits,
+            CancellationToken::new(),
+            None,
+        );
+        assert_eq!(executed.outcome, Outcome::Error);
+    }
+
+    #[test]
+    fn test_profiled_request_sends_profile_first() {
+        let (server, runner, socket_path) = spawn_daemon("profile");
+
+        let mut stream = UnixStream::connect(&socket_path).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        // Only this test runs this program, so the first run compiles it
+        let request = DaemonRequest::new("x = 40\nx + 2 + 0 * 17").with_profile();
+        let mut profiles = Vec::new();
+        for _ in 0..2 {
+            stream.write_all(&request.encode()).unwrap();
+            let profile = read_response(&mut stream);
+            assert!(profile.is_profile());
+            let response = read_response(&mut stream);
+            assert!(response.is_success());
+            assert_eq!(response.output(), "42");
+            profiles.push(profile.output().to_string());
+        }
+        assert!(profiles[0].contains("cache_hit false"));
+        assert!(PipelineProfile::parse(&profiles[0]).lex_ns > 0);
+        // The second run finds it in the cache
+        assert!(profiles[1].contains("cache_hit true"));
+        let warm = PipelineProfile::parse(&profiles[1]);
+        assert_eq!((warm.lex_ns, warm.parse_ns), (0, 0));
+        assert!(warm.vm_execute_ns > 0 && warm.total_ns > 0);
+
+        drop(stream);
+        server.stop();
+        runner.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_compression_negotiated_per_connection() {
+        let (server, runner, socket_path) = spawn_daemon("compression");
+
+        let mut stream = UnixStream::connect(&socket_path).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        stream.write_all(&COMPRESSION_MARKER.to_be_bytes()).unwrap();
+        stream.write_all(&9u32.to_be_bytes()).unwrap();
+        let refused = read_response(&mut stream);
+        assert_eq!(refused.output(), "Unsupported compression algorithm 9");
+
+        // Compressed requests are accepted before compression is asked for
+        let request = DaemonRequest::new("print(7)\n".repeat(3000));
+        stream
+            .write_all(&request.encode_compressed(Compression::Lz4))
+            .unwrap();
+        let mut header = [0u8; 5];
+        stream.read_exact(&mut header).unwrap();
+        assert_eq!(header[0], 0);
+        let mut body =
+            vec![0u8; u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize];
+        stream.read_exact(&mut body).unwrap();
+
+        stream
+            .write_all(&DaemonRequest::encode_compression(Compression::Lz4))
+            .unwrap();
+        assert_eq!(read_response(&mut stream).output(), "lz4");
+        stream
+            .write_all(&request.encode_compressed(Compression::Lz4))
+            .unwrap();
+        stream.read_exact(&mut header).unwrap();
+        assert_eq!(header[0], 7);
+        let length = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+        assert!(length < 1000);
+        let mut message = header.to_vec();
+        message.resize(5 + length, 0);
+        stream.read_exact(&mut message[5..]).unwrap();
+        let response = DaemonResponse::decode(&message).unwrap().0;
+        assert!(response.is_success());
+        assert_eq!(response.output(), "7\n".repeat(3000));
+
+        // Small responses stay plain
+        stream
+            .write_all(&DaemonRequest::new("2+3").encode())
+            .unwrap();
+        stream.read_exact(&mut header).unwrap();
+        assert_eq!(header[0], 0);
+
+        drop(stream);
+        server.stop();
+        runner.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_request_timeout_stops_request() {
+        let (server, runner, socket_path) = spawn_daemon("request-timeout");
+
+        let mut stream = UnixStream::connect(&socket_path).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        let request = DaemonRequest::new(slow_program(40)).with_timeout(Duration::from_millis(100));
+        stream.write_all(&request.encode()).unwrap();
+        let response = read_response(&mut stream);
+        assert!(response.is_timeout());
+        assert_eq!(response.output(), "Request timed out after 0.1s");
+
+        // Streamed requests in a session time out too, and the session's
+        // VM is left without a deadline
+        stream
+            .write_all(&DaemonRequest::encode_session("timeout"))
+            .unwrap();
+        assert!(read_response(&mut stream).is_success());
+        let code = format!("print(7)\n{}", slow_program(40));
+        let request = DaemonRequest::streaming(code).with_timeout(Duration::from_millis(100));
+        stream.write_all(&request.encode()).unwrap();
+        assert!(read_response(&mut stream).is_chunk());
+        assert!(read_response(&mut stream).is_timeout());
+        stream
+            .write_all(&DaemonRequest::new("6 * 7").encode())
+            .unwrap();
+        assert_eq!(read_response(&mut stream).output(), "42");
+
+        drop(stream);
+        server.stop();
+        runner.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_resource_limits_reject_requests() {
+        let base = std::env::temp_dir().join(format!("pyrust-limits-{}", std::process::id()));
+        let socket_path = format!("{}.sock", base.display());
+        let mut server =
+            DaemonServer::with_paths(socket_path.clone(), format!("{}.pid", base.display()))
+                .unwrap();
+        server.set_limits(RequestLimits {
+            max_instructions: Some(1_000),
+            max_output_bytes: Some(8),
+            max_call_depth: Some(10),
+            max_memory_bytes: None,
+        });
+        let server = Arc::new(server);
+        let runner = {
+            let server = Arc::clone(&server);
+            thread::spawn(move || server.run())
+        };
+        while !Path::new(&socket_path).exists() {
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        let mut stream = UnixStream::connect(&socket_path).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        let mut run = |code: String| {
+            stream
+                .write_all(&DaemonRequest::new(code).encode())
+                .unwrap();
+            read_response(&mut stream)
+        };
+        let response = run(slow_program(40));
+        assert_eq!(
+            response.exceeded_limit().map(|(limit, _)| limit),
+            Some("call_depth")
+        );
+        let response = run(slow_program(5));
+        assert!(response.is_success());
+        let response = run(slow_program(9));
+        assert_eq!(
+            response.exceeded_limit(),
+            Some((
+                "instructions",
+                "execution budget exceeded (1000 instructions)"
+            ))
+        );
+        let response = run("print(1234)\nprint(5678)".to_string());
+        assert_eq!(
+            response.exceeded_limit().map(|(limit, _)| limit),
+            Some("output_bytes")
+        );
+        // Other failures are plain errors
+        let response = run("1 / 0".to_string());
+        assert!(response.is_error() && response.exceeded_limit().is_none());
+
+        drop(stream);
+        server.stop();
+        runner.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_session_globals_persist_across_connections() {
+        let (server, runner, socket_path) = spawn_daemon("session");
+
+        let mut first = UnixStream::connect(&socket_path).unwrap();
+        first
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        first
+            .write_all(&DaemonRequest::encode_session("repl"))
+            .unwrap();
+        assert_eq!(read_response(&mut first).output(), "repl");
+        first
+            .write_all(&DaemonRequest::new("def double(n):\n    return n * 2\nx = 21").encode())
+            .unwrap();
+        assert!(read_response(&mut first).is_success());
+        drop(first);
+
+        let mut second = UnixStream::connect(&socket_path).unwrap();
+        second
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        second
+            .write_all(&DaemonRequest::encode_session("repl"))
+            .unwrap();
+        second
+            .write_all(&DaemonRequest::new("double(x)").encode())
+            .unwrap();
+        assert!(read_response(&mut second).is_success());
+        assert_eq!(read_response(&mut second).output(), "42");
+
+        // Detached, requests no longer see the session's globals
+        second
+            .write_all(&DaemonRequest::encode_session(""))
+            .unwrap();
+        second.write_all(&DaemonRequest::new("x").encode()).unwrap();
+        assert!(read_response(&mut second).is_success());
+        assert!(read_response(&mut second).is_error());
+        assert_eq!(server.sessions().len(), 1);
+
+        drop(second);
+        server.stop();
+        runner.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_auth_token_required_first() {
+        let base = std::env::temp_dir().join(format!("pyrust-auth-{}", std::process::id()));
+        let socket_path = format!("{}.sock", base.display());
+        let mut server =
+            DaemonServer::with_paths(socket_path.clone(), format!("{}.pid", base.display()))
+                .unwrap();
+        server.set_auth_token(Some("s3cret".to_string()));
+        let server = Arc::new(server);
+        let runner = {
+            let server = Arc::clone(&server);
+            thread::spawn(move || server.run())
+        };
+        while !Path::new(&socket_path).exists() {
+            thread::sleep(Duration::from_millis(5));
+        }
+        let connect = || {
+            let stream = UnixStream::connect(&socket_path).unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(10)))
+                .unwrap();
+            stream
+        };
+
+        // Requests without the token are rejected and the connection closed
+        let mut stream = connect();
+        stream
+            .write_all(&DaemonRequest::new("1 + 1").encode())
+            .unwrap();
+        let response = read_response(&mut stream);
+        assert!(response.is_error());
+        assert_eq!(response.output(), AUTH_REQUIRED_MESSAGE);
+        assert_eq!(stream.read(&mut [0u8; 1]).unwrap(), 0);
+
+        let mut stream = connect();
+        stream
+            .write_all(&DaemonRequest::encode_auth("guess"))
+            .unwrap();
+        assert!(read_response(&mut stream).is_error());
+
+        // Pings need no token, but do not authenticate the connection
+        let mut stream = connect();
+        stream.write_all(&DaemonRequest::encode_ping()).unwrap();
+        assert_eq!(read_response(&mut stream).output(), "pong");
+        stream
+            .write_all(&DaemonRequest::new("1 + 1").encode())
+            .unwrap();
+        assert_eq!(read_response(&mut stream).output(), AUTH_REQUIRED_MESSAGE);
+
+        let mut stream = connect();
+        stream
+            .write_all(&DaemonRequest::encode_auth("s3cret"))
+            .unwrap();
+        stream
+            .write_all(&DaemonRequest::new("1 + 1").encode())
+            .unwrap();
+        assert!(read_response(&mut stream).is_success());
+        assert_eq!(read_response(&mut stream).output(), "2");
+        assert_eq!(server.metrics().requests(), 1);
+
+        drop(stream);
+        server.stop();
+        runner.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_disconnect_aborts_running_request() {
+        let (server, runner, socket_path) = spawn_daemon("cancel-disconnect");
+
+        let mut abandoned = UnixStream::connect(&socket_path).unwrap();
+        abandoned
+            .write_all(&DaemonRequest::new(slow_program(40)).encode())
+            .unwrap();
+        thread::sleep(Duration::from_millis(50));
+        drop(abandoned);
+
+        // Connections are served one at a time, so this only gets an answer
+        // once the abandoned request has been cancelled
+        let mut stream = UnixStream::connect(&socket_path).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        stream
+            .write_all(&DaemonRequest::new("1 + 1").encode())
+            .unwrap();
+        assert_eq!(read_response(&mut stream).output(), "2");
+
+        drop(stream);
+        server.stop();
+        runner.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_batch_frame_answers_each_request() {
+        let (server, runner, socket_path) = spawn_daemon("batch");
+
+        let mut stream = UnixStream::connect(&socket_path).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        let batch = [
+            DaemonRequest::new("2 + 3"),
+            DaemonRequest::new("1 / 0"),
+            DaemonRequest::streaming("print(7)\n6 * 7"),
+        ];
+        stream
+            .write_all(&DaemonRequest::encode_batch(&batch))
+            .unwrap();
+        assert_eq!(read_response(&mut stream).output(), "5");
+        // A failure does not stop the rest
+        assert!(read_response(&mut stream).is_error());
+        let chunk = read_response(&mut stream);
+        assert!(chunk.is_chunk());
+        assert_eq!(chunk.output(), "7\n");
+        assert_eq!(read_response(&mut stream).output(), "42");
+        assert_eq!(server.metrics().requests(), 3);
+
+        // The connection goes on after the batch
+        stream.write_all(&DaemonRequest::encode_batch(&[])).unwrap();
+        stream.write_all(&DaemonRequest::new("1").encode()).unwrap();
+        assert_eq!(read_response(&mut stream).output(), "1");
+
+        drop(stream);
+        server.stop();
+        runner.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_metrics_frame_reports_requests() {
+        let (server, runner, socket_path) = spawn_daemon("metrics");
+
+        let mut stream = UnixStream::connect(&socket_path).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        // Answered after the requests sent before it
+        stream
+            .write_all(&DaemonRequest::new("1 + 1").encode())
+            .unwrap();
+        stream
+            .write_all(&DaemonRequest::new("1 / 0").encode())
+            .unwrap();
+        stream.write_all(&DaemonRequest::encode_metrics()).unwrap();
+        assert!(read_response(&mut stream).is_success());
+        assert!(read_response(&mut stream).is_error());
+
+        let metrics = read_response(&mut stream);
+        assert!(metrics.is_success());
+        assert!(metrics.output().contains("\npyrust_requests_total 2\n"));
+        assert!(metrics
+            .output()
+            .contains("\npyrust_request_errors_total 1\n"));
+        assert!(metrics.output().contains("pyrust_cache_hits_total"));
+        assert_eq!(server.metrics().requests(), 2);
+
+        stream
+            .write_all(&DaemonRequest::encode_cache_list())
+            .unwrap();
+        let listing = read_response(&mut stream);
+        assert!(listing.output().starts_with("HASH"));
+        assert!(listing.output().contains("1 + 1\n"));
+
+        stream
+            .write_all(&DaemonRequest::new("1 + 1").encode())
+            .unwrap();
+        stream
+            .write_all(&DaemonRequest::encode_cache_summary())
+            .unwrap();
+        assert!(read_response(&mut stream).is_success());
+        let summary = read_response(&mut stream);
+        assert!(summary.output().contains("Compile time saved: "));
+        assert!(summary.output().contains("Hottest entries:\n"));
+        assert!(summary.output().contains(" hits "));
+
+        stream.write_all(&DaemonRequest::encode_stats()).unwrap();
+        let stats = DaemonStats::parse(read_response(&mut stream).output());
+        assert_eq!((stats.requests, stats.errors, stats.sessions), (3, 1, 0));
+        assert!(stats.latency_p50.is_some() && stats.latency_p95 >= stats.latency_p50);
+
+        drop(stream);
+        server.stop();
+        runner.join().unwrap().unwrap();
+    }
 }
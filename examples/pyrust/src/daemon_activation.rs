@@ -0,0 +1,162 @@
+//! systemd socket activation
+//!
+//! systemd can own the daemon's socket and start the daemon on the first
+//! connection to it, passing the listening socket in as file descriptor 3
+//! with `LISTEN_PID` and `LISTEN_FDS` set (see `sd_listen_fds(3)`). When
+//! they name this process, [`crate::daemon::DaemonServer::new`] and its
+//! async counterpart accept on that socket instead of binding their own,
+//! and `pyrust --daemon` serves in the foreground instead of forking, as
+//! systemd expects of a service.
+//!
+//! The socket belongs to systemd: the daemon neither changes its mode nor
+//! removes it on exit, so the next connection starts the daemon again.
+//! Stop it with `systemctl stop`; `pyrust --stop-daemon` sees the socket
+//! still there and reports a failure.
+//!
+//! # Example
+//!
+//! ```text
+//! # ~/.config/systemd/user/pyrust.socket
+//! [Socket]
+//! ListenStream=/tmp/pyrust.sock
+//! SocketMode=0600
+//!
+//! [Install]
+//! WantedBy=sockets.target
+//!
+//! # ~/.config/systemd/user/pyrust.service
+//! [Service]
+//! ExecStart=/usr/local/bin/pyrust --daemon
+//! ```
+//!
+//! Named instances ([`crate::daemon::DAEMON_NAME_ENV`]) listen on
+//! `/tmp/pyrust-<name>.sock` instead.
+
+use std::env;
+use std::io;
+use std::mem;
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::os::unix::net::UnixListener;
+use std::process;
+
+/// First file descriptor systemd passes sockets in
+pub const SD_LISTEN_FDS_START: RawFd = 3;
+
+/// Check if systemd passed this process any sockets
+pub fn is_socket_activated() -> bool {
+    listen_fds() > 0
+}
+
+/// Take the listening socket systemd passed in, if any
+///
+/// Like `sd_listen_fds(1)`, clears the activation variables, so only the
+/// first call gets the socket and child processes never see it as theirs.
+/// Fails if systemd passed more than one socket, or one that is not a
+/// listening Unix socket.
+pub fn take_listener() -> io::Result<Option<UnixListener>> {
+    let count = listen_fds();
+    for name in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+        env::remove_var(name);
+    }
+    match count {
+        0 => Ok(None),
+        1 => {
+            let fd = SD_LISTEN_FDS_START;
+            check_listening_unix(fd)?;
+            // Not for the processes the daemon starts
+            if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Some(unsafe { UnixListener::from_raw_fd(fd) }))
+        }
+        n => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("expected one socket from systemd, got {}", n),
+        )),
+    }
+}
+
+/// Number of sockets systemd passed this process
+fn listen_fds() -> usize {
+    parse_listen_fds(
+        env::var("LISTEN_PID").ok().as_deref(),
+        env::var("LISTEN_FDS").ok().as_deref(),
+        process::id(),
+    )
+}
+
+/// Number of sockets the activation variables pass to the process `pid`
+///
+/// The variables are inherited, so they only count when `LISTEN_PID` names
+/// the process itself rather than an ancestor.
+fn parse_listen_fds(listen_pid: Option<&str>, listen_fds: Option<&str>, pid: u32) -> usize {
+    if listen_pid.and_then(|p| p.parse::<u32>().ok()) != Some(pid) {
+        return 0;
+    }
+    listen_fds.and_then(|n| n.parse().ok()).unwrap_or(0)
+}
+
+/// Check that `fd` is a Unix socket accepting connections
+fn check_listening_unix(fd: RawFd) -> io::Result<()> {
+    let mut addr: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let mut addr_len = mem::size_of_val(&addr) as libc::socklen_t;
+    let mut accepting: libc::c_int = 0;
+    let mut accepting_len = mem::size_of_val(&accepting) as libc::socklen_t;
+    unsafe {
+        if libc::getsockname(
+            fd,
+            &mut addr as *mut _ as *mut libc::sockaddr,
+            &mut addr_len,
+        ) < 0
+            || libc::getsockopt(
+                fd,
+                libc::SOL_SOCKET,
+                libc::SO_ACCEPTCONN,
+                &mut accepting as *mut _ as *mut libc::c_void,
+                &mut accepting_len,
+            ) < 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    if libc::c_int::from(addr.ss_family) != libc::AF_UNIX || accepting == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "systemd passed a socket that is not a listening Unix socket",
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::io::AsRawFd;
+
+    #[test]
+    fn test_parse_listen_fds() {
+        assert_eq!(parse_listen_fds(Some("42"), Some("1"), 42), 1);
+        assert_eq!(parse_listen_fds(Some("42"), Some("2"), 42), 2);
+        // Meant for another process
+        assert_eq!(parse_listen_fds(Some("41"), Some("1"), 42), 0);
+        assert_eq!(parse_listen_fds(None, Some("1"), 42), 0);
+        assert_eq!(parse_listen_fds(Some("42"), None, 42), 0);
+        assert_eq!(parse_listen_fds(Some("42"), Some("x"), 42), 0);
+    }
+
+    #[test]
+    fn test_check_listening_unix() {
+        let path = std::env::temp_dir().join(format!("pyrust-activation-{}.sock", process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        assert!(check_listening_unix(listener.as_raw_fd()).is_ok());
+
+        // A connected socket does not accept
+        let stream = std::os::unix::net::UnixStream::connect(&path).unwrap();
+        assert!(check_listening_unix(stream.as_raw_fd()).is_err());
+        // Nor does a TCP listener, which is not a Unix socket
+        let tcp = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        assert!(check_listening_unix(tcp.as_raw_fd()).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,975 @@
+//! Tokio-based daemon server
+//!
+//! Available with the `async-daemon` feature. [`AsyncDaemonServer`] speaks the
+//! same protocol over the same socket as [`DaemonServer`], control frames
+//! included, and shares its cache and PID file handling. Where they differ:
+//!
+//! - Every connection is a task rather than a turn of the accept loop, so
+//!   thousands of idle clients can stay connected while others are served.
+//! - Besides the Unix socket, it can listen on a TCP address
+//!   ([`AsyncDaemonServer::with_tcp`]), over TLS with the `tls` feature
+//!   (`AsyncDaemonServer::with_tls`).
+//! - A request still running after the request timeout
+//!   ([`AsyncDaemonServer::with_request_timeout`], [`REQUEST_TIMEOUT_SECS`]
+//!   by default) is cancelled and answered with an error.
+//! - Backpressure: at most [`AsyncDaemonServer::with_max_concurrent`]
+//!   requests execute at once, and the rest wait their turn, interactive
+//!   requests ahead of batch ones (see
+//!   [`crate::daemon_protocol::Priority`]). Once
+//!   [`AsyncDaemonServer::with_max_queued`] requests are waiting, more are
+//!   answered with a busy response without running. A connection
+//!   reads at most [`QUEUE_DEPTH`] messages ahead of its answers, so a client
+//!   that pipelines faster than it is served is slowed down instead of
+//!   buffered without bound.
+//!
+//! Requests execute on tokio's blocking threads, on VMs from the server's
+//! [`VmPool`]. The sync server remains the default; `pyrust --daemon --async`
+//! starts this one.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use pyrust::daemon_async::AsyncDaemonServer;
+//! use std::time::Duration;
+//!
+//! let daemon = AsyncDaemonServer::new()
+//!     .unwrap()
+//!     .with_tcp("127.0.0.1:7878".parse().unwrap())
+//!     .with_request_timeout(Duration::from_secs(5));
+//! daemon.run().unwrap();
+//! ```
+//!
+//! [`DaemonServer`]: crate::daemon::DaemonServer
+
+use crate::cancel::CancellationToken;
+use crate::daemon::{
+    attach_session, auth_token_from_env, check_auth, claim_socket_path, daemon_name_from_env,
+    execute_request, instance_paths, log_request, lookup_session, negotiate_compression,
+    remove_daemon_files, restrict_socket, timed_out, write_pid_file, ClientMessage,
+    ConnectionState, DaemonError, DaemonServer, Executed, MessageDecoder, Next, Outcome,
+    DEFAULT_VM_POOL_SIZE, IDLE_TIMEOUT, REQUEST_TIMEOUT_SECS,
+};
+use crate::daemon_limits::RequestLimits;
+use crate::daemon_protocol::{DaemonRequest, DaemonResponse};
+use crate::daemon_session::SessionTable;
+use crate::daemon_transport::Listener;
+use crate::metrics::RequestMetrics;
+use crate::session::Session;
+use crate::vm::StdoutSink;
+use crate::vm_pool::VmPool;
+use admission::{Admission, QueueFull};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+
+mod admission;
+#[cfg(feature = "tls")]
+mod tls;
+
+/// Messages a connection reads ahead of the answers it has written
+pub const QUEUE_DEPTH: usize = 32;
+
+/// Requests that may wait to execute before more are turned away
+pub const DEFAULT_MAX_QUEUED: usize = 64;
+
+/// Environment variable overriding [`DEFAULT_MAX_QUEUED`] for `pyrust
+/// --daemon --async`
+pub const MAX_QUEUED_ENV: &str = "PYRUST_DAEMON_MAX_QUEUED";
+
+/// How often the accept loop checks whether the server was stopped
+const SHUTDOWN_POLL: Duration = Duration::from_millis(10);
+
+/// Tokio-based Unix (and optionally TCP) socket daemon server
+pub struct AsyncDaemonServer {
+    socket_path: String,
+    pid_file_path: String,
+    /// Socket bound by someone else, accepted on instead of binding
+    inherited: Option<Listener>,
+    tcp_addr: Option<SocketAddr>,
+    shutdown_flag: Arc<AtomicBool>,
+    vm_pool: Arc<VmPool>,
+    sessions: Arc<SessionTable>,
+    auth_token: Option<String>,
+    limits: RequestLimits,
+    metrics: Arc<RequestMetrics>,
+    request_timeout: Duration,
+    max_concurrent: usize,
+    max_queued: usize,
+    #[cfg(feature = "tls")]
+    tls: Option<tokio_rustls::TlsAcceptor>,
+}
+
+/// What every connection of a running server shares
+struct Context {
+    vm_pool: Arc<VmPool>,
+    sessions: Arc<SessionTable>,
+    auth_token: Option<String>,
+    limits: RequestLimits,
+    metrics: Arc<RequestMetrics>,
+    request_timeout: Duration,
+    admission: Arc<Admission>,
+    #[cfg(feature = "tls")]
+    tls: Option<tokio_rustls::TlsAcceptor>,
+}
+
+impl AsyncDaemonServer {
+    /// Create a new daemon server at the paths of the instance named by
+    /// [`crate::daemon::DAEMON_NAME_ENV`], or the default ones
+    ///
+    /// Under systemd socket activation, accepts on the socket systemd
+    /// passed in; see [`crate::daemon_activation`].
+    pub fn new() -> Result<Self, DaemonError> {
+        let (socket_path, pid_file_path) = instance_paths(daemon_name_from_env().as_deref());
+        #[cfg(unix)]
+        if let Some(listener) = crate::daemon_activation::take_listener()? {
+            return Ok(Self::with_listener(listener, socket_path, pid_file_path));
+        }
+        Self::with_paths(socket_path, pid_file_path)
+    }
+
+    /// Create a new daemon server with custom paths
+    pub fn with_paths(socket_path: String, pid_file_path: String) -> Result<Self, DaemonError> {
+        claim_socket_path(&socket_path)?;
+        Ok(Self::build(socket_path, pid_file_path, None))
+    }
+
+    /// Create a new daemon server accepting on `listener`, which is bound
+    /// at `socket_path` by someone else
+    ///
+    /// The server leaves the socket's mode alone and does not remove it.
+    #[cfg(unix)]
+    pub fn with_listener(
+        listener: std::os::unix::net::UnixListener,
+        socket_path: String,
+        pid_file_path: String,
+    ) -> Self {
+        Self::build(socket_path, pid_file_path, Some(listener))
+    }
+
+    fn build(socket_path: String, pid_file_path: String, inherited: Option<Listener>) -> Self {
+        let shutdown_flag = Arc::new(AtomicBool::new(false));
+        DaemonServer::setup_signal_handlers(Arc::clone(&shutdown_flag));
+
+        Self {
+            socket_path,
+            pid_file_path,
+            inherited,
+            tcp_addr: None,
+            shutdown_flag,
+            vm_pool: Arc::new(VmPool::new(DEFAULT_VM_POOL_SIZE)),
+            sessions: Arc::new(SessionTable::default()),
+            auth_token: auth_token_from_env(),
+            limits: RequestLimits::from_env(),
+            metrics: Arc::new(RequestMetrics::new()),
+            request_timeout: Duration::from_secs(REQUEST_TIMEOUT_SECS),
+            max_concurrent: DEFAULT_VM_POOL_SIZE,
+            max_queued: DEFAULT_MAX_QUEUED,
+            #[cfg(feature = "tls")]
+            tls: None,
+        }
+    }
+
+    /// Also accept connections on the TCP address `addr`
+    ///
+    /// TCP connections are not restricted to the socket's owner: set an
+    /// auth token ([`AsyncDaemonServer::with_auth_token`]), and bind to a
+    /// loopback address unless every host that can reach it is trusted.
+    pub fn with_tcp(mut self, addr: SocketAddr) -> Self {
+        self.tcp_addr = Some(addr);
+        self
+    }
+
+    /// Serve TCP connections over TLS, with the PEM certificate chain at
+    /// `cert` and private key at `key`
+    ///
+    /// Fails if the files cannot be read or do not hold a matching
+    /// certificate and key. The Unix socket is unaffected.
+    #[cfg(feature = "tls")]
+    pub fn with_tls(
+        mut self,
+        cert: impl AsRef<std::path::Path>,
+        key: impl AsRef<std::path::Path>,
+    ) -> Result<Self, DaemonError> {
+        self.tls = Some(tls::acceptor(cert.as_ref(), key.as_ref())?);
+        Ok(self)
+    }
+
+    /// Cancel requests that run longer than `timeout`
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
+    /// Require connections to present `token` first, or nothing if None
+    ///
+    /// Defaults to [`AUTH_TOKEN_ENV`] as it was when the server was created.
+    ///
+    /// [`AUTH_TOKEN_ENV`]: crate::daemon::AUTH_TOKEN_ENV
+    pub fn with_auth_token(mut self, token: Option<String>) -> Self {
+        self.auth_token = token;
+        self
+    }
+
+    /// Stop requests that go over `limits`
+    ///
+    /// Defaults to the limits in the environment as it was when the server
+    /// was created (see [`RequestLimits::from_env`]).
+    pub fn with_limits(mut self, limits: RequestLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Execute at most `max` requests at once (at least one)
+    pub fn with_max_concurrent(mut self, max: usize) -> Self {
+        self.max_concurrent = max.max(1);
+        self
+    }
+
+    /// Let at most `max` requests wait to execute, answering any more with
+    /// a busy response
+    ///
+    /// Defaults to [`DEFAULT_MAX_QUEUED`]. With 0, requests are only
+    /// accepted while fewer than the concurrency limit are executing.
+    pub fn with_max_queued(mut self, max: usize) -> Self {
+        self.max_queued = max;
+        self
+    }
+
+    /// Execute requests on VMs from `pool`
+    pub fn set_vm_pool(&mut self, pool: VmPool) {
+        self.vm_pool = Arc::new(pool);
+    }
+
+    /// Keep sessions in `sessions`, replacing the default limits
+    pub fn set_sessions(&mut self, sessions: SessionTable) {
+        self.sessions = Arc::new(sessions);
+    }
+
+    /// Counts and latencies of the requests served so far
+    pub fn metrics(&self) -> &RequestMetrics {
+        &self.metrics
+    }
+
+    /// Run the daemon server on a new multi-threaded runtime
+    ///
+    /// Returns once the server is stopped, by a signal or
+    /// [`AsyncDaemonServer::stop`].
+    pub fn run(&self) -> Result<(), DaemonError> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()?;
+        let result = runtime.block_on(self.serve());
+        // Requests still executing have been cancelled with their connections
+        runtime.shutdown_timeout(Duration::from_secs(1));
+        result
+    }
+
+    /// Accept and serve connections until the server is stopped
+    ///
+    /// Must be called within a tokio runtime with I/O and time enabled.
+    pub async fn serve(&self) -> Result<(), DaemonError> {
+        let local = match &self.inherited {
+            Some(listener) => adopt_local(listener)?,
+            None => {
+                let local = bind_local(&self.socket_path)?;
+                restrict_socket(&self.socket_path)?;
+                local
+            }
+        };
+        let tcp = match self.tcp_addr {
+            Some(addr) => Some(TcpListener::bind(addr).await?),
+            None => None,
+        };
+        write_pid_file(&self.pid_file_path)?;
+
+        let context = Arc::new(Context {
+            vm_pool: Arc::clone(&self.vm_pool),
+            sessions: Arc::clone(&self.sessions),
+            auth_token: self.auth_token.clone(),
+            limits: self.limits,
+            metrics: Arc::clone(&self.metrics),
+            request_timeout: self.request_timeout,
+            admission: Admission::new(self.max_concurrent, self.max_queued),
+            #[cfg(feature = "tls")]
+            tls: self.tls.clone(),
+        });
+        let mut poll = tokio::time::interval(SHUTDOWN_POLL);
+        while !self.shutdown_flag.load(Ordering::SeqCst) {
+            tokio::select! {
+                accepted = local.accept() => match accepted {
+                    Ok((stream, _addr)) => {
+                        tokio::spawn(handle_connection(stream, Arc::clone(&context)));
+                    }
+                    Err(e) => log::error!("Error accepting connection: {}", e),
+                },
+                accepted = accept_tcp(tcp.as_ref()) => match accepted {
+                    Ok(stream) => {
+                        tokio::spawn(handle_tcp(stream, Arc::clone(&context)));
+                    }
+                    Err(e) => log::error!("Error accepting connection: {}", e),
+                },
+                _ = poll.tick() => {}
+            }
+        }
+
+        self.cleanup()
+    }
+
+    /// Cleanup resources (socket and PID file)
+    fn cleanup(&self) -> Result<(), DaemonError> {
+        let socket_path = Some(self.socket_path.as_str()).filter(|_| self.inherited.is_none());
+        remove_daemon_files(socket_path, &self.pid_file_path)
+    }
+
+    /// Stop the daemon
+    pub fn stop(&self) {
+        self.shutdown_flag.store(true, Ordering::SeqCst);
+    }
+}
+
+impl Drop for AsyncDaemonServer {
+    fn drop(&mut self) {
+        // Ensure cleanup on drop
+        let _ = self.cleanup();
+    }
+}
+
+/// Listen at the socket path; see [`crate::daemon_transport`]
+#[cfg(unix)]
+fn bind_local(socket_path: &str) -> std::io::Result<tokio::net::UnixListener> {
+    tokio::net::UnixListener::bind(socket_path)
+}
+
+/// Listen on a loopback port recorded at the socket path; see
+/// [`crate::daemon_transport`]
+#[cfg(windows)]
+fn bind_local(socket_path: &str) -> std::io::Result<TcpListener> {
+    let listener = crate::daemon_transport::bind(socket_path)?;
+    listener.set_nonblocking(true)?;
+    TcpListener::from_std(listener)
+}
+
+/// Accept on a socket bound by someone else
+#[cfg(unix)]
+fn adopt_local(listener: &Listener) -> std::io::Result<tokio::net::UnixListener> {
+    let listener = listener.try_clone()?;
+    listener.set_nonblocking(true)?;
+    tokio::net::UnixListener::from_std(listener)
+}
+
+/// Accept on a socket bound by someone else
+#[cfg(windows)]
+fn adopt_local(listener: &Listener) -> std::io::Result<TcpListener> {
+    let listener = listener.try_clone()?;
+    listener.set_nonblocking(true)?;
+    TcpListener::from_std(listener)
+}
+
+/// Accept from `listener`, or wait forever without one
+async fn accept_tcp(listener: Option<&TcpListener>) -> std::io::Result<TcpStream> {
+    match listener {
+        Some(listener) => listener.accept().await.map(|(stream, _addr)| stream),
+        None => std::future::pending().await,
+    }
+}
+
+/// Serve a client connection until it closes or goes idle
+/// Serve a TCP connection, after a TLS handshake if TLS is configured
+async fn handle_tcp(stream: TcpStream, context: Arc<Context>) {
+    let _ = stream.set_nodelay(true);
+    #[cfg(feature = "tls")]
+    if let Some(acceptor) = context.tls.clone() {
+        match tokio::time::timeout(IDLE_TIMEOUT, acceptor.accept(stream)).await {
+            Ok(Ok(stream)) => handle_connection(stream, context).await,
+            Ok(Err(e)) => log::warn!("TLS handshake failed: {}", e),
+            Err(_) => log::warn!("TLS handshake timed out"),
+        }
+        return;
+    }
+    handle_connection(stream, context).await;
+}
+
+async fn handle_connection<S>(stream: S, context: Arc<Context>)
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let (reader, mut writer) = tokio::io::split(stream);
+
+    // As in the sync server, messages are read by a task of their own so that
+    // cancel frames and disconnects are noticed while a request executes
+    let state = Arc::new(ConnectionState::default());
+    let (request_tx, mut requests) = mpsc::channel(QUEUE_DEPTH);
+    let reader = tokio::spawn(read_requests(reader, Arc::clone(&state), request_tx));
+
+    if let Err(e) = serve_requests(&mut writer, &mut requests, &state, &context).await {
+        log::warn!("Error handling connection: {}", e);
+    }
+    reader.abort();
+}
+
+/// Answer the messages forwarded by the reader task, in order
+async fn serve_requests<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    requests: &mut mpsc::Receiver<Result<ClientMessage, DaemonError>>,
+    state: &ConnectionState,
+    context: &Context,
+) -> Result<(), DaemonError> {
+    let mut session = None;
+    let mut compression = None;
+    let mut authenticated = false;
+    // Ends when the reader stops (client closed or idle timeout)
+    while let Some(message) = requests.recv().await {
+        let message = message?;
+        if !authenticated || matches!(message, ClientMessage::Auth(_)) {
+            if let Some(rejection) = check_auth(context.auth_token.as_deref(), &message) {
+                writer.write_all(&rejection.encode()).await?;
+                writer.flush().await?;
+                return Ok(());
+            }
+            authenticated = !matches!(message, ClientMessage::Ping);
+        }
+        let response = match message.report(&context.metrics, &context.sessions) {
+            Some(text) => DaemonResponse::success(text),
+            None => match message {
+                ClientMessage::Auth(_) => DaemonResponse::success(""),
+                ClientMessage::Session(id) => attach_session(&context.sessions, &mut session, id),
+                ClientMessage::Compression(algorithm) => {
+                    negotiate_compression(&mut compression, algorithm)
+                }
+                ClientMessage::Execute(request) => {
+                    run(request, session.as_deref(), writer, state, context).await
+                }
+                ClientMessage::Batch(batch) => {
+                    for request in batch {
+                        let response =
+                            run(request, session.as_deref(), writer, state, context).await;
+                        writer.write_all(&response.encode_with(compression)).await?;
+                    }
+                    writer.flush().await?;
+                    state.in_flight.fetch_sub(1, Ordering::SeqCst);
+                    continue;
+                }
+                // Cancel frames are handled by the reader task
+                _ => continue,
+            },
+        };
+        writer.write_all(&response.encode_with(compression)).await?;
+        writer.flush().await?;
+        state.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+    Ok(())
+}
+
+/// Execute `request` in the session named `session`, if any
+async fn run<W: AsyncWrite + Unpin>(
+    request: DaemonRequest,
+    session: Option<&str>,
+    writer: &mut W,
+    state: &ConnectionState,
+    context: &Context,
+) -> DaemonResponse {
+    match lookup_session(&context.sessions, session) {
+        Ok(target) => execute(request, target, writer, state, context).await,
+        Err(e) => DaemonResponse::error(e.to_string()),
+    }
+}
+
+/// Execute `request` once it is admitted, within the request timeout
+///
+/// Answers busy instead if too many requests are waiting already. The
+/// request runs in `session` if the connection is attached to one. The
+/// print output of a streamed request is written to `writer` in chunks
+/// while it runs, and the profile of a profiled one after it finishes; the
+/// final response is left to the caller.
+async fn execute<W: AsyncWrite + Unpin>(
+    request: DaemonRequest,
+    session: Option<Arc<Mutex<Session>>>,
+    writer: &mut W,
+    state: &ConnectionState,
+    context: &Context,
+) -> DaemonResponse {
+    let _permit = match context.admission.acquire(request.priority()).await {
+        Ok(permit) => permit,
+        Err(QueueFull) => {
+            log::warn!(
+                "request rejected priority={} reason=queue_full",
+                request.priority().name()
+            );
+            return DaemonResponse::busy("Server busy: too many requests waiting, try again later");
+        }
+    };
+    let started = Instant::now();
+    let token = CancellationToken::new();
+    state.start(request.id(), token.clone());
+
+    // Prints cross from the blocking thread to this task; the sender is
+    // dropped with the sink when the VM goes back to the pool
+    let (chunk_tx, mut chunks) = mpsc::unbounded_channel::<String>();
+    let sink = request.is_streaming().then(|| {
+        Box::new(move |text: &str| {
+            let _ = chunk_tx.send(text.to_string());
+        }) as StdoutSink
+    });
+
+    let pool = Arc::clone(&context.vm_pool);
+    let limits = context.limits;
+    let worker_token = token.clone();
+    let mut worker = tokio::task::spawn_blocking(move || {
+        execute_request(
+            &pool,
+            session.as_deref(),
+            &request,
+            &limits,
+            worker_token,
+            sink,
+        )
+    });
+    let deadline = tokio::time::sleep(context.request_timeout);
+    tokio::pin!(deadline);
+    let executed = loop {
+        tokio::select! {
+            Some(chunk) = chunks.recv() => write_chunk(writer, &chunk, &token).await,
+            finished = &mut worker => match finished {
+                Ok(finished) => break finished,
+                Err(e) => {
+                    break Executed::failed(DaemonResponse::error(format!("Request failed: {}", e)))
+                }
+            },
+            _ = &mut deadline => {
+                // Keep the permit until the VM is back in the pool
+                token.cancel();
+                let cache_hit = worker.await.map_or(None, |executed| executed.cache_hit);
+                break Executed {
+                    response: timed_out(context.request_timeout),
+                    outcome: Outcome::Timeout,
+                    cache_hit,
+                    profile: None,
+                };
+            }
+        }
+    };
+    // Prints made just before the request finished
+    while let Ok(chunk) = chunks.try_recv() {
+        write_chunk(writer, &chunk, &token).await;
+    }
+    if let Some(profile) = &executed.profile {
+        // A client that went away fails the final response instead
+        let _ = writer.write_all(&profile.encode()).await;
+    }
+    state.finish();
+    let response = executed.response;
+    context
+        .metrics
+        .record(started.elapsed(), response.is_success());
+    log_request(
+        started.elapsed(),
+        executed.cache_hit,
+        executed.outcome,
+        &response,
+    );
+    response
+}
+
+/// Send one chunk of print output, cancelling the request if the client is gone
+async fn write_chunk<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    chunk: &str,
+    token: &CancellationToken,
+) {
+    let frame = DaemonResponse::chunk(chunk).encode();
+    if writer.write_all(&frame).await.is_err() || writer.flush().await.is_err() {
+        token.cancel();
+    }
+}
+
+/// Read messages from the client until it closes the connection or goes idle
+///
+/// Requests and report frames are forwarded to `requests`, waiting while
+/// [`QUEUE_DEPTH`] are unanswered; cancel frames and disconnects cancel
+/// whatever request is executing, and cancel-request frames the request
+/// they name.
+async fn read_requests<R: AsyncRead + Unpin>(
+    mut reader: R,
+    state: Arc<ConnectionState>,
+    requests: mpsc::Sender<Result<ClientMessage, DaemonError>>,
+) {
+    loop {
+        let message = match read_message(&mut reader, &state).await {
+            Ok(Some(message)) => message,
+            // Idle with nothing in flight
+            Ok(None) => break,
+            Err(DaemonError::Io(ref e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                // Client closed connection; nobody is left to wait for a running request
+                state.cancel_running();
+                break;
+            }
+            Err(e) => {
+                state.cancel_running();
+                let _ = requests.send(Err(e)).await;
+                break;
+            }
+        };
+        match message {
+            ClientMessage::Cancel => {
+                state.cancel_running();
+                continue;
+            }
+            ClientMessage::CancelRequest(id) => {
+                state.cancel_request(id);
+                continue;
+            }
+            _ => state.expect(&message),
+        }
+        state.in_flight.fetch_add(1, Ordering::SeqCst);
+        if requests.send(Ok(message)).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Read a request or a control frame, or None once the connection is idle
+///
+/// The length prefix is read with single `read` calls, which lose nothing
+/// when the idle timeout interrupts them, so a slow request is not mistaken
+/// for idleness. A request body must then arrive within the idle timeout.
+async fn read_message<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    state: &ConnectionState,
+) -> Result<Option<ClientMessage>, DaemonError> {
+    let mut length_buf = [0u8; 4];
+    let mut filled = 0;
+    while filled < length_buf.len() {
+        match tokio::time::timeout(IDLE_TIMEOUT, reader.read(&mut length_buf[filled..])).await {
+            Ok(Ok(0)) => return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into()),
+            Ok(Ok(read)) => filled += read,
+            Ok(Err(e)) => return Err(e.into()),
+            Err(_) if filled == 0 && state.in_flight.load(Ordering::SeqCst) == 0 => {
+                return Ok(None)
+            }
+            Err(_) => {}
+        }
+    }
+    let mut decoder = MessageDecoder::default();
+    loop {
+        match decoder.word(length_buf)? {
+            Next::Word => read_within_idle_timeout(reader, &mut length_buf).await?,
+            Next::Prefix(length) => {
+                let mut block = vec![0u8; length];
+                read_within_idle_timeout(reader, &mut block).await?;
+                decoder.prefix(&block);
+                read_within_idle_timeout(reader, &mut length_buf).await?;
+            }
+            Next::Message(message) => return Ok(Some(message)),
+            Next::Body(length) => {
+                let mut body = vec![0u8; length];
+                read_within_idle_timeout(reader, &mut body).await?;
+                return decoder.body(&body).map(Some);
+            }
+        }
+    }
+}
+
+/// Fill `buf`, failing if the client stalls for longer than the idle timeout
+async fn read_within_idle_timeout<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    buf: &mut [u8],
+) -> Result<(), DaemonError> {
+    match tokio::time::timeout(IDLE_TIMEOUT, reader.read_exact(buf)).await {
+        Ok(read) => read?,
+        Err(_) => return Err(std::io::Error::from(std::io::ErrorKind::TimedOut).into()),
+    };
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::daemon_protocol::Priority;
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixStream;
+    use std::path::Path;
+    use std::thread;
+
+    fn spawn_daemon(
+        name: &str,
+        configure: impl FnOnce(AsyncDaemonServer) -> AsyncDaemonServer,
+    ) -> (
+        Arc<AsyncDaemonServer>,
+        thread::JoinHandle<Result<(), DaemonError>>,
+        String,
+    ) {
+        let base =
+            std::env::temp_dir().join(format!("pyrust-async-{}-{}", name, std::process::id()));
+        let socket_path = format!("{}.sock", base.display());
+        let server =
+            AsyncDaemonServer::with_paths(socket_path.clone(), format!("{}.pid", base.display()))
+                .unwrap();
+        let server = Arc::new(configure(server));
+        let runner = {
+            let server = Arc::clone(&server);
+            thread::spawn(move || server.run())
+        };
+        while !Path::new(&socket_path).exists() {
+            thread::sleep(Duration::from_millis(5));
+        }
+        (server, runner, socket_path)
+    }
+
+    fn connect(socket_path: &str) -> UnixStream {
+        let stream = UnixStream::connect(socket_path).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        stream
+    }
+
+    fn read_response(stream: &mut impl Read) -> DaemonResponse {
+        let mut header = [0u8; 5];
+        stream.read_exact(&mut header).unwrap();
+        let length = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+        let mut message = header.to_vec();
+        message.resize(5 + length, 0);
+        stream.read_exact(&mut message[5..]).unwrap();
+        DaemonResponse::decode(&message).unwrap().0
+    }
+
+    /// A program that runs for ages: f0 makes 2^depth calls
+    fn slow_program() -> String {
+        let depth = 40;
+        let mut source = format!("def f{}():\n    return 1\n", depth);
+        for i in (0..depth).rev() {
+            source.push_str(&format!(
+                "def f{}():\n    return f{}() + f{}()\n",
+                i,
+                i + 1,
+                i + 1
+            ));
+        }
+        source.push_str("f0()");
+        source
+    }
+
+    #[test]
+    fn test_idle_connections_do_not_block_others() {
+        let (server, runner, socket_path) = spawn_daemon("idle", |server| server);
+
+        // The sync server would serve only the first of these
+        let idle: Vec<_> = (0..50).map(|_| connect(&socket_path)).collect();
+        let mut stream = connect(&socket_path);
+        stream
+            .write_all(&DaemonRequest::new("print(6 * 7)").encode())
+            .unwrap();
+        let response = read_response(&mut stream);
+        assert!(response.is_success());
+        assert_eq!(response.output(), "42\n");
+
+        stream.write_all(&DaemonRequest::encode_metrics()).unwrap();
+        assert!(read_response(&mut stream)
+            .output()
+            .contains("\npyrust_requests_total 1\n"));
+        assert_eq!(server.metrics().requests(), 1);
+
+        drop(idle);
+        drop(stream);
+        server.stop();
+        runner.join().unwrap().unwrap();
+        assert!(!Path::new(&socket_path).exists());
+    }
+
+    #[test]
+    fn test_request_timeout_cancels_request() {
+        let (server, runner, socket_path) = spawn_daemon("timeout", |server| {
+            server.with_request_timeout(Duration::from_millis(100))
+        });
+
+        let mut stream = connect(&socket_path);
+        stream
+            .write_all(&DaemonRequest::new(slow_program()).encode())
+            .unwrap();
+        let response = read_response(&mut stream);
+        assert!(response.is_timeout());
+        assert!(response.output().contains("timed out"));
+
+        // A request's own, shorter timeout is enforced inside the VM
+        let request = DaemonRequest::new(slow_program()).with_timeout(Duration::from_millis(20));
+        stream.write_all(&request.encode()).unwrap();
+        let response = read_response(&mut stream);
+        assert!(response.is_timeout());
+        assert!(response.output().contains("timed out"));
+
+        // The connection keeps serving requests after a timeout
+        stream
+            .write_all(&DaemonRequest::new("6 * 7").encode())
+            .unwrap();
+        assert_eq!(read_response(&mut stream).output(), "42");
+
+        drop(stream);
+        server.stop();
+        runner.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_full_queue_answers_busy() {
+        let (server, runner, socket_path) = spawn_daemon("busy", |server| {
+            server.with_max_concurrent(1).with_max_queued(0)
+        });
+
+        let mut slow = connect(&socket_path);
+        slow.write_all(&DaemonRequest::new(slow_program()).encode())
+            .unwrap();
+        thread::sleep(Duration::from_millis(50));
+
+        // Nothing may wait while the slow request runs
+        let mut stream = connect(&socket_path);
+        let request = DaemonRequest::new("1").with_priority(Priority::Batch);
+        stream.write_all(&request.encode()).unwrap();
+        let response = read_response(&mut stream);
+        assert!(response.is_busy());
+        assert!(response.output().contains("Server busy"));
+
+        slow.write_all(&DaemonRequest::encode_cancel()).unwrap();
+        assert!(read_response(&mut slow).is_error());
+        stream.write_all(&request.encode()).unwrap();
+        assert_eq!(read_response(&mut stream).output(), "1");
+
+        drop(slow);
+        drop(stream);
+        server.stop();
+        runner.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_streamed_request_sends_output_chunks() {
+        let (server, runner, socket_path) = spawn_daemon("stream", |server| {
+            server.with_request_timeout(Duration::from_millis(200))
+        });
+
+        let mut stream = connect(&socket_path);
+        stream
+            .write_all(&DaemonRequest::streaming("print(1)\n2").encode())
+            .unwrap();
+        let chunk = read_response(&mut stream);
+        assert!(chunk.is_chunk());
+        assert_eq!(chunk.output(), "1\n");
+        assert_eq!(read_response(&mut stream).output(), "2");
+
+        // Output arrives before the request times out
+        let code = format!("print(7)\n{}", slow_program());
+        stream
+            .write_all(&DaemonRequest::streaming(code).encode())
+            .unwrap();
+        let chunk = read_response(&mut stream);
+        assert!(chunk.is_chunk());
+        assert_eq!(chunk.output(), "7\n");
+        let response = read_response(&mut stream);
+        assert!(response.is_error());
+        assert!(response.output().contains("timed out"));
+
+        drop(stream);
+        server.stop();
+        runner.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_sessions_and_session_limit() {
+        let (server, runner, socket_path) = spawn_daemon("session", |mut server| {
+            server.set_sessions(SessionTable::new(1, Duration::from_secs(60)));
+            server
+        });
+
+        let mut stream = connect(&socket_path);
+        stream
+            .write_all(&DaemonRequest::encode_session("a"))
+            .unwrap();
+        stream
+            .write_all(&DaemonRequest::new("x = 41").encode())
+            .unwrap();
+        stream
+            .write_all(&DaemonRequest::new("x + 1").encode())
+            .unwrap();
+        assert_eq!(read_response(&mut stream).output(), "a");
+        assert!(read_response(&mut stream).is_success());
+        assert_eq!(read_response(&mut stream).output(), "42");
+
+        // A batch runs in the connection's session, answering each request
+        let batch = ["x = x + 1", "x / 0", "x"].map(DaemonRequest::new);
+        stream
+            .write_all(&DaemonRequest::encode_batch(&batch))
+            .unwrap();
+        assert!(read_response(&mut stream).is_success());
+        assert!(read_response(&mut stream).is_error());
+        assert_eq!(read_response(&mut stream).output(), "42");
+
+        let mut other = connect(&socket_path);
+        other
+            .write_all(&DaemonRequest::encode_session("b"))
+            .unwrap();
+        let response = read_response(&mut other);
+        assert!(response.is_error());
+        assert!(response.output().contains("Too many sessions"));
+
+        drop(stream);
+        drop(other);
+        server.stop();
+        runner.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_cancel_frame_and_authenticated_tcp_listener() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        let (server, runner, _socket_path) = spawn_daemon("tcp", |server| {
+            server
+                .with_tcp(addr)
+                .with_max_concurrent(1)
+                .with_auth_token(Some("s3cret".to_string()))
+        });
+        let connect = || loop {
+            match std::net::TcpStream::connect(addr) {
+                Ok(stream) => {
+                    stream
+                        .set_read_timeout(Some(Duration::from_secs(10)))
+                        .unwrap();
+                    break stream;
+                }
+                Err(_) => thread::sleep(Duration::from_millis(5)),
+            }
+        };
+
+        // Unauthenticated connections are turned away
+        let mut stream = connect();
+        stream
+            .write_all(&DaemonRequest::new("1 + 1").encode())
+            .unwrap();
+        assert!(read_response(&mut stream).is_error());
+        assert_eq!(stream.read(&mut [0u8; 1]).unwrap(), 0);
+
+        let mut stream = connect();
+        stream
+            .write_all(&DaemonRequest::encode_auth("s3cret"))
+            .unwrap();
+        assert!(read_response(&mut stream).is_success());
+        stream
+            .write_all(&DaemonRequest::new(slow_program()).encode())
+            .unwrap();
+        thread::sleep(Duration::from_millis(50));
+        stream.write_all(&DaemonRequest::encode_cancel()).unwrap();
+        let response = read_response(&mut stream);
+        assert!(response.is_error());
+        assert!(response.output().contains(crate::cancel::CANCELLED_MESSAGE));
+
+        stream
+            .write_all(&DaemonRequest::new("1 + 1").encode())
+            .unwrap();
+        assert_eq!(read_response(&mut stream).output(), "2");
+
+        drop(stream);
+        server.stop();
+        runner.join().unwrap().unwrap();
+    }
+}
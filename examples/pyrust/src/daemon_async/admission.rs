@@ -0,0 +1,168 @@
+//! Admission control for requests waiting to execute
+//!
+//! At most `max_running` requests execute at once. The rest wait in a queue
+//! per [`Priority`] and are let in interactive first, so a CLI call does not
+//! sit behind a bulk job's backlog. At most `max_queued` requests wait in
+//! all: beyond that a request is turned away at once, and its client can
+//! retry or run the code itself rather than wait on an overloaded daemon.
+
+use crate::daemon_protocol::Priority;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, PoisonError};
+use tokio::sync::oneshot;
+
+/// Turns for requests to execute
+pub(super) struct Admission {
+    max_running: usize,
+    max_queued: usize,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Permits handed out
+    running: usize,
+    /// Requests waiting for a permit, by [`Priority`]
+    waiting: [VecDeque<oneshot::Sender<Permit>>; 2],
+}
+
+/// A request's turn to execute, passed on to the next one when dropped
+pub(super) struct Permit {
+    /// None once the turn has been passed on
+    admission: Option<Arc<Admission>>,
+}
+
+/// The request was turned away: too many are waiting already
+#[derive(Debug, PartialEq)]
+pub(super) struct QueueFull;
+
+impl Admission {
+    pub(super) fn new(max_running: usize, max_queued: usize) -> Arc<Self> {
+        Arc::new(Self {
+            max_running,
+            max_queued,
+            state: Mutex::new(State::default()),
+        })
+    }
+
+    /// Wait for a turn to execute a request of `priority`
+    pub(super) async fn acquire(self: &Arc<Self>, priority: Priority) -> Result<Permit, QueueFull> {
+        let turn = {
+            let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+            if state.running < self.max_running {
+                state.running += 1;
+                return Ok(Permit {
+                    admission: Some(Arc::clone(self)),
+                });
+            }
+            // Requests whose clients went away no longer wait
+            for queue in &mut state.waiting {
+                queue.retain(|waiter| !waiter.is_closed());
+            }
+            if state.waiting.iter().map(VecDeque::len).sum::<usize>() >= self.max_queued {
+                return Err(QueueFull);
+            }
+            let (waiter, turn) = oneshot::channel();
+            state.waiting[priority as usize].push_back(waiter);
+            turn
+        };
+        turn.await.map_err(|_| QueueFull)
+    }
+
+    /// Pass a finished request's turn to the first one waiting, if any
+    fn release(self: &Arc<Self>) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        while let Some(waiter) = state.waiting.iter_mut().find_map(VecDeque::pop_front) {
+            let permit = Permit {
+                admission: Some(Arc::clone(self)),
+            };
+            match waiter.send(permit) {
+                Ok(()) => return,
+                // Stopped waiting; the turn goes to the next one instead
+                Err(mut permit) => permit.admission = None,
+            }
+        }
+        state.running -= 1;
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let Some(admission) = self.admission.take() {
+            admission.release();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_interactive_requests_go_first() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let admission = Admission::new(1, 2);
+            let running = admission.acquire(Priority::Batch).await.unwrap();
+
+            let order = Arc::new(Mutex::new(Vec::new()));
+            let mut waiters = Vec::new();
+            for priority in [Priority::Batch, Priority::Interactive] {
+                let admission = Arc::clone(&admission);
+                let order = Arc::clone(&order);
+                waiters.push(tokio::spawn(async move {
+                    let _permit = admission.acquire(priority).await.unwrap();
+                    order.lock().unwrap().push(priority);
+                }));
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            // Two are waiting: a third is turned away
+            assert_eq!(
+                admission.acquire(Priority::Interactive).await.err(),
+                Some(QueueFull)
+            );
+
+            drop(running);
+            for waiter in waiters {
+                waiter.await.unwrap();
+            }
+            assert_eq!(
+                *order.lock().unwrap(),
+                [Priority::Interactive, Priority::Batch]
+            );
+            assert_eq!(admission.state.lock().unwrap().running, 0);
+        });
+    }
+
+    #[test]
+    fn test_abandoned_waiters_free_their_place() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let admission = Admission::new(1, 1);
+            let running = admission.acquire(Priority::Interactive).await.unwrap();
+
+            // Gives up waiting, as when its client disconnects
+            let wait = admission.acquire(Priority::Interactive);
+            assert!(tokio::time::timeout(Duration::from_millis(10), wait)
+                .await
+                .is_err());
+
+            // Its place in the queue and its turn go to the next request
+            let next = {
+                let admission = Arc::clone(&admission);
+                tokio::spawn(async move { admission.acquire(Priority::Batch).await.is_ok() })
+            };
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            drop(running);
+            assert!(next.await.unwrap());
+            assert_eq!(admission.state.lock().unwrap().running, 0);
+        });
+    }
+}
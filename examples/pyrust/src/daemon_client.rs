@@ -10,8 +10,46 @@
 //! 1. Check if daemon is running (socket file exists)
 //! 2. Connect to Unix socket
 //! 3. Send code execution request using binary protocol
-//! 4. Receive response with result or error
-//! 5. Fall back to direct execution if daemon unavailable
+//! 4. Receive response with result or error, after any print output the daemon
+//!    streams back
+//! 5. Fall back to direct execution if daemon unavailable, or start one
+//!    first if auto-start is on (see [`crate::daemon_spawn`])
+//!
+//! # Connection Reuse
+//!
+//! With [`POOL_SIZE_ENV`] set, a process keeps up to that many connections
+//! open after their requests are answered, and the next call reuses one
+//! instead of connecting and authenticating again. A [`DaemonConnection`]
+//! holds one connection for many requests, and can pipeline them: send
+//! several, then read the responses in order.
+//!
+//! Pooling is off by default because the sync daemon serves one connection
+//! at a time: while a process keeps an idle connection open, other clients
+//! wait until the daemon's idle timeout closes it. Turn it on with
+//! `pyrust --daemon --async`, or when one process is the daemon's only
+//! client.
+//!
+//! # Retries
+//!
+//! A connection that fails for a reason that may pass, such as a daemon
+//! still starting or too busy to accept, is retried according to a
+//! [`RetryPolicy`]: [`RETRIES_ENV`] times, with a backoff that doubles
+//! after each attempt. Each attempt gives up after the policy's connect
+//! timeout ([`CONNECT_TIMEOUT_ENV`]). Only connecting is retried: a request
+//! the daemon may have started is never sent twice. Embedders can set the
+//! policy with [`DaemonClient::set_retry_policy`] instead.
+//!
+//! With the `async-daemon` feature,
+//! [`crate::daemon_client_async::AsyncDaemonClient`] offers the same requests
+//! to tokio applications.
+//!
+//! # Compression
+//!
+//! With [`COMPRESSION_ENV`] set to `lz4`, each new connection asks the
+//! daemon to compress large responses, and requests over
+//! [`COMPRESSION_THRESHOLD`] bytes are sent compressed. This pays off for
+//! big generated scripts and long outputs over TCP; on a local socket it
+//! mostly costs CPU.
 //!
 //! # Example
 //!
@@ -32,13 +70,25 @@
 //! ```
 
 use std::fmt;
+use std::io;
 use std::io::{Read, Write};
-use std::os::unix::net::UnixStream;
 use std::path::Path;
-use std::time::Duration;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
 
-use crate::daemon_protocol::{DaemonRequest, DaemonResponse};
-use crate::execute_python;
+use crate::cancel::CancellationToken;
+use crate::compiler::Severity;
+use crate::daemon::{auth_token_from_env, daemon_name_from_env, instance_paths, AUTH_TOKEN_ENV};
+use crate::daemon_protocol::{
+    Compression, DaemonRequest, DaemonResponse, Priority, COMPRESSION_THRESHOLD,
+};
+use crate::daemon_spawn;
+use crate::daemon_transport::{self, Stream};
+use crate::diagnostic::Diagnostic;
+use crate::metrics::DaemonStats;
+use crate::profiling::PipelineProfile;
+use crate::{execute_python, execute_python_streaming, execute_script_streaming, ScriptOptions};
 
 /// Unix socket path for daemon IPC
 pub const SOCKET_PATH: &str = "/tmp/pyrust.sock";
@@ -49,10 +99,181 @@ pub const PID_FILE_PATH: &str = "/tmp/pyrust.pid";
 /// Maximum response size (10MB) to prevent unbounded allocation
 const MAX_RESPONSE_SIZE: usize = 10_485_760;
 
+/// How long to wait for the daemon's response
+pub(crate) const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often a cancellable request checks its token while waiting
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Id for the next cancellable request this process sends
+static NEXT_REQUEST_ID: AtomicU32 = AtomicU32::new(0);
+
+/// Environment variable setting how many idle connections a process keeps
+/// for reuse; unset or 0 turns pooling off
+pub const POOL_SIZE_ENV: &str = "PYRUST_DAEMON_POOL_SIZE";
+
+/// Environment variable naming the compression to use for large payloads;
+/// unset for none
+pub const COMPRESSION_ENV: &str = "PYRUST_DAEMON_COMPRESSION";
+
+/// How long an idle connection is kept: a little less than the daemon's
+/// idle timeout, so that it is not closed just as it is reused
+const MAX_IDLE_TIME: Duration = Duration::from_secs(4);
+
+/// Connections kept open for reuse, most recently used last
+static IDLE_CONNECTIONS: Mutex<Vec<IdleConnection>> = Mutex::new(Vec::new());
+
+/// Environment variable setting how many times a failed connection is
+/// retried (see [`RetryPolicy::retries`])
+pub const RETRIES_ENV: &str = "PYRUST_DAEMON_RETRIES";
+
+/// Environment variable setting the connect timeout in milliseconds (see
+/// [`RetryPolicy::connect_timeout`])
+pub const CONNECT_TIMEOUT_ENV: &str = "PYRUST_DAEMON_CONNECT_TIMEOUT_MS";
+
+/// Policy set with [`DaemonClient::set_retry_policy`], overriding the
+/// environment
+static RETRY_POLICY: Mutex<Option<RetryPolicy>> = Mutex::new(None);
+
+/// How clients connect to the daemon when connecting fails
+///
+/// The default tries once, so that a client without a daemon falls back to
+/// direct execution at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts after the first
+    pub retries: u32,
+    /// Wait before the first retry; doubled after each
+    pub initial_backoff: Duration,
+    /// Longest wait between attempts
+    pub max_backoff: Duration,
+    /// Time after which an attempt to connect fails
+    pub connect_timeout: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 0,
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(1),
+            connect_timeout: Duration::from_secs(1),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The default policy, with [`RETRIES_ENV`] and [`CONNECT_TIMEOUT_ENV`]
+    /// applied
+    pub fn from_env() -> Self {
+        let mut policy = Self::default();
+        if let Some(retries) = std::env::var(RETRIES_ENV)
+            .ok()
+            .and_then(|retries| retries.trim().parse().ok())
+        {
+            policy.retries = retries;
+        }
+        if let Some(millis) = std::env::var(CONNECT_TIMEOUT_ENV)
+            .ok()
+            .and_then(|millis| millis.trim().parse().ok())
+        {
+            policy.connect_timeout = Duration::from_millis(millis);
+        }
+        policy
+    }
+
+    /// Wait before retry number `retry`, counting from 0
+    pub fn backoff(&self, retry: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(1 << retry.min(31))
+            .min(self.max_backoff)
+    }
+
+    /// Call `connect` until it succeeds, fails for good, or runs out of
+    /// retries, sleeping between attempts
+    pub(crate) fn retry<T>(&self, mut connect: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+        let mut retry = 0;
+        loop {
+            match connect() {
+                Err(e) if retry < self.retries && is_transient(&e) => {
+                    std::thread::sleep(self.backoff(retry));
+                    retry += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Whether a failure to connect may go away on its own
+///
+/// A missing socket or refused connection is a daemon not yet (or no
+/// longer) listening; a timeout or `WouldBlock` one with a full backlog.
+pub(crate) fn is_transient(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::NotFound
+            | io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::TimedOut
+            | io::ErrorKind::WouldBlock
+            | io::ErrorKind::Interrupted
+    )
+}
+
+/// An open, authenticated connection with no request in flight
+struct IdleConnection {
+    socket_path: String,
+    stream: Stream,
+    since: Instant,
+}
+
 /// Client interface for daemon communication
 pub struct DaemonClient;
 
 impl DaemonClient {
+    /// Socket of the daemon instance this client talks to
+    ///
+    /// [`SOCKET_PATH`], unless [`crate::daemon::DAEMON_NAME_ENV`] names
+    /// another instance.
+    pub fn socket_path() -> String {
+        instance_paths(daemon_name_from_env().as_deref()).0
+    }
+
+    /// PID file of the daemon instance this client talks to
+    pub fn pid_file_path() -> String {
+        instance_paths(daemon_name_from_env().as_deref()).1
+    }
+
+    /// How this process connects to the daemon
+    ///
+    /// The policy set with [`DaemonClient::set_retry_policy`], otherwise
+    /// [`RetryPolicy::from_env`].
+    pub fn retry_policy() -> RetryPolicy {
+        RETRY_POLICY
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .unwrap_or_else(RetryPolicy::from_env)
+    }
+
+    /// Connect to the daemon with `policy` from now on, in the whole process
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use pyrust::daemon_client::{DaemonClient, RetryPolicy};
+    /// use std::time::Duration;
+    ///
+    /// DaemonClient::set_retry_policy(RetryPolicy {
+    ///     retries: 5,
+    ///     connect_timeout: Duration::from_millis(200),
+    ///     ..RetryPolicy::default()
+    /// });
+    /// ```
+    pub fn set_retry_policy(policy: RetryPolicy) {
+        *RETRY_POLICY.lock().unwrap_or_else(PoisonError::into_inner) = Some(policy);
+    }
+
     /// Check if daemon is running by testing socket existence
     ///
     /// # Returns
@@ -71,7 +292,7 @@ impl DaemonClient {
     /// }
     /// ```
     pub fn is_daemon_running() -> bool {
-        Path::new(SOCKET_PATH).exists()
+        Path::new(&Self::socket_path()).exists()
     }
 
     /// Execute code via daemon with automatic fallback to direct execution
@@ -80,6 +301,9 @@ impl DaemonClient {
     /// daemon is unavailable or any communication error occurs, it automatically
     /// falls back to direct execution via `execute_python()`.
     ///
+    /// With [`daemon_spawn::AUTOSTART_ENV`] set to `1`, a daemon is started
+    /// first when none is running.
+    ///
     /// # Arguments
     ///
     /// * `code` - Python source code to execute
@@ -98,7 +322,7 @@ impl DaemonClient {
     /// assert_eq!(result, "5");
     /// ```
     pub fn execute_or_fallback(code: &str) -> Result<String, Box<dyn std::error::Error>> {
-        match Self::execute_via_daemon(code) {
+        match Self::execute_via_daemon(code, &ScriptOptions::default(), None, None) {
             Ok(output) => Ok(output),
             Err(_) => {
                 // Daemon unavailable, fallback to direct execution
@@ -107,6 +331,109 @@ impl DaemonClient {
         }
     }
 
+    /// Execute code via daemon, falling back to streaming direct execution
+    ///
+    /// Like [`DaemonClient::execute_or_fallback`], but print output is
+    /// delivered to `sink` as it is produced: the daemon streams it back in
+    /// output chunks, and direct execution uses [`execute_python_streaming`].
+    /// Once the daemon has streamed any output, a failure is returned rather
+    /// than running the code a second time locally, as a
+    /// [`DaemonClientError::Failed`] if the program raised an error.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(String)` - The expression result, after streaming
+    /// * `Err(Box<dyn std::error::Error>)` - Error from the daemon or direct execution
+    pub fn execute_or_stream<F>(
+        code: &str,
+        mut sink: F,
+    ) -> Result<String, Box<dyn std::error::Error>>
+    where
+        F: FnMut(&str) + Send + 'static,
+    {
+        let mut streamed = false;
+        let result = Self::execute_via_daemon(
+            code,
+            &ScriptOptions::default(),
+            None,
+            Some(&mut |text: &str| {
+                streamed = true;
+                sink(text);
+            }),
+        );
+        match result {
+            Ok(output) => Ok(output),
+            Err(e) if streamed => Err(Box::new(e)),
+            Err(_) => execute_python_streaming(code, sink)
+                .map_err(|e| Box::new(e) as Box<dyn std::error::Error>),
+        }
+    }
+
+    /// Like [`DaemonClient::execute_or_stream`], but abortable through `token`
+    ///
+    /// While the daemon runs the code, cancelling `token` sends it a cancel
+    /// frame; when running directly, the token is attached to the VM. Either
+    /// way the call returns an "execution cancelled" error promptly, and a
+    /// cancelled daemon request is not retried locally.
+    pub fn execute_or_stream_cancellable<F>(
+        code: &str,
+        sink: F,
+        token: &CancellationToken,
+    ) -> Result<String, Box<dyn std::error::Error>>
+    where
+        F: FnMut(&str) + Send + 'static,
+    {
+        Self::execute_script(code, &ScriptOptions::default(), sink, token)
+    }
+
+    /// Like [`DaemonClient::execute_or_stream_cancellable`], running the code
+    /// as a script with `options`
+    ///
+    /// The options travel with the request, so the program reads the same
+    /// `argc()` and `argv(i)` from the daemon as from direct execution. A run
+    /// still going after the timeout is stopped, failing with
+    /// [`DaemonClientError::TimedOut`] from the daemon or a TimeoutError from
+    /// direct execution. With `no_cache`, neither the daemon's cache nor this
+    /// process's is used. A script whose warnings are errors runs directly,
+    /// since the daemon compiles without failing on them.
+    pub fn execute_script<F>(
+        code: &str,
+        options: &ScriptOptions,
+        mut sink: F,
+        token: &CancellationToken,
+    ) -> Result<String, Box<dyn std::error::Error>>
+    where
+        F: FnMut(&str) + Send + 'static,
+    {
+        if options.warnings == Severity::Error {
+            return execute_script_streaming(code, options, sink, token).map_err(Into::into);
+        }
+        if options.warnings == Severity::Error {
+            return execute_script_streaming(code, options, sink, token).map_err(Into::into);
+        }
+        let mut streamed = false;
+        let result = Self::execute_via_daemon(
+            code,
+            options,
+            Some(token),
+            Some(&mut |text: &str| {
+                streamed = true;
+                sink(text);
+            }),
+        );
+        match result {
+            Ok(output) => Ok(output),
+            Err(e) if streamed || token.is_cancelled() => Err(Box::new(e)),
+            // The program ran to its exit(n) or out of time; running it
+            // again would only repeat its side effects
+            Err(e @ (DaemonClientError::Exited(_) | DaemonClientError::TimedOut(_))) => {
+                Err(Box::new(e))
+            }
+            Err(_) => execute_script_streaming(code, options, sink, token)
+                .map_err(|e| Box::new(e) as Box<dyn std::error::Error>),
+        }
+    }
+
     /// Execute code via daemon connection
     ///
     /// This is a private method that handles the actual communication with the daemon.
@@ -115,70 +442,493 @@ impl DaemonClient {
     /// # Arguments
     ///
     /// * `code` - Python source code to execute
+    /// * `options` - Script arguments, time limit and cache use of the run
+    /// * `cancel` - Token whose cancellation is forwarded to the daemon
+    /// * `sink` - Receives print output as the daemon streams it; without
+    ///   one, the request is not streamed
     ///
     /// # Returns
     ///
     /// * `Ok(String)` - Execution output from daemon
     /// * `Err(DaemonClientError)` - Communication or execution error
-    fn execute_via_daemon(code: &str) -> Result<String, DaemonClientError> {
-        // Connect to Unix socket with timeout
-        let mut stream =
-            UnixStream::connect(SOCKET_PATH).map_err(DaemonClientError::ConnectionFailed)?;
+    fn execute_via_daemon(
+        code: &str,
+        options: &ScriptOptions,
+        cancel: Option<&CancellationToken>,
+        mut sink: Option<&mut dyn FnMut(&str)>,
+    ) -> Result<String, DaemonClientError> {
+        // Opted in: start a daemon rather than fall back; if that fails, the
+        // connect below does and the caller falls back anyway
+        if daemon_spawn::autostart_enabled() && !Self::is_daemon_running() {
+            let _ = daemon_spawn::autostart();
+        }
+        let mut stream = Self::connect()?;
 
-        // Set timeouts for read/write to prevent hung requests
-        stream
-            .set_read_timeout(Some(Duration::from_secs(5)))
-            .map_err(DaemonClientError::SocketConfig)?;
-        stream
-            .set_write_timeout(Some(Duration::from_secs(1)))
-            .map_err(DaemonClientError::SocketConfig)?;
+        // A cancellable request wakes up regularly to check its token
+        if cancel.is_some() {
+            stream
+                .set_read_timeout(Some(CANCEL_POLL_INTERVAL))
+                .map_err(DaemonClientError::SocketConfig)?;
+        }
 
         // Encode and send request using binary protocol
-        let request = DaemonRequest::new(code);
-        let request_bytes = request.encode();
+        let mut request = if sink.is_some() {
+            DaemonRequest::streaming(code)
+        } else {
+            DaemonRequest::new(code)
+        };
+        if !options.args.is_empty() {
+            request = request.with_args(options.args.clone());
+        }
+        if let Some(timeout) = options.timeout {
+            request = request.with_timeout(timeout);
+        }
+        if options.no_cache {
+            request = request.without_cache();
+        }
+        // A streamed run is not retried locally, so its failure is what the
+        // caller gets to show; ask for it with its span
+        if sink.is_some() {
+            request = request.with_diagnostics();
+        }
+        // A cancellable request is cancelled by its id
+        let cancel = cancel.map(|token| (token, NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed)));
+        if let Some((_, id)) = cancel {
+            request = request.with_id(id);
+        }
+        let request_bytes = encode_request(&request);
 
         stream
             .write_all(&request_bytes)
             .map_err(DaemonClientError::WriteFailed)?;
         stream.flush().map_err(DaemonClientError::WriteFailed)?;
 
-        // Read response header (status + length = 5 bytes)
-        let mut header_buf = [0u8; 5];
+        let mut cancel_sent = false;
+        loop {
+            // Every chunk shows the daemon is making progress, so the
+            // timeout counts from the latest one; the daemon answers a
+            // request with a time limit once it runs out
+            let deadline = Instant::now() + options.timeout.unwrap_or_default() + RESPONSE_TIMEOUT;
+            let response = Self::read_frame(|buf| match cancel {
+                Some((token, id)) => {
+                    Self::read_cancellable(&mut stream, buf, token, id, &mut cancel_sent, deadline)
+                }
+                None => stream
+                    .read_exact(buf)
+                    .map_err(DaemonClientError::ReadFailed),
+            })?;
+            if !response.is_chunk() {
+                // A cancelled request may still be answered after this
+                if !cancel_sent {
+                    release(&Self::socket_path(), stream);
+                }
+                return Self::into_result(response);
+            }
+            if let Some(sink) = sink.as_mut() {
+                sink(response.output());
+            }
+        }
+    }
+
+    /// Fetch the daemon's metrics as Prometheus text
+    ///
+    /// See [`crate::metrics`] for what is reported.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use pyrust::daemon_client::DaemonClient;
+    ///
+    /// print!("{}", DaemonClient::metrics().unwrap());
+    /// ```
+    pub fn metrics() -> Result<String, DaemonClientError> {
+        Self::query(&DaemonRequest::encode_metrics())
+    }
+
+    /// Fetch the daemon's uptime, request counts, latency and cache stats
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use pyrust::daemon_client::DaemonClient;
+    ///
+    /// let stats = DaemonClient::stats().unwrap();
+    /// println!("{} requests served", stats.requests);
+    /// ```
+    pub fn stats() -> Result<DaemonStats, DaemonClientError> {
+        Self::query(&DaemonRequest::encode_stats()).map(|text| DaemonStats::parse(&text))
+    }
+
+    /// Fetch the table of programs the daemon has cached
+    ///
+    /// See [`crate::cache::format_entries`] for the columns.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use pyrust::daemon_client::DaemonClient;
+    ///
+    /// print!("{}", DaemonClient::cache_list().unwrap());
+    /// ```
+    pub fn cache_list() -> Result<String, DaemonClientError> {
+        Self::query(&DaemonRequest::encode_cache_list())
+    }
+
+    /// Fetch the daemon's cache hit counts, compile time saved and hottest
+    /// programs
+    ///
+    /// See [`crate::cache::format_summary`] for the format.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use pyrust::daemon_client::DaemonClient;
+    ///
+    /// print!("{}", DaemonClient::cache_summary().unwrap());
+    /// ```
+    pub fn cache_summary() -> Result<String, DaemonClientError> {
+        Self::query(&DaemonRequest::encode_cache_summary())
+    }
+
+    /// Fetch every counter of the daemon's cache
+    ///
+    /// See [`crate::cache::format_stats`] for the format.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use pyrust::daemon_client::DaemonClient;
+    ///
+    /// print!("{}", DaemonClient::cache_stats().unwrap());
+    /// ```
+    pub fn cache_stats() -> Result<String, DaemonClientError> {
+        Self::query(&DaemonRequest::encode_cache_stats())
+    }
+
+    /// Empty the daemon's in-memory cache, returning how many programs it
+    /// held
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use pyrust::daemon_client::DaemonClient;
+    ///
+    /// let removed = DaemonClient::clear_cache().unwrap();
+    /// println!("{} programs removed", removed);
+    /// ```
+    pub fn clear_cache() -> Result<usize, DaemonClientError> {
+        let output = Self::query(&DaemonRequest::encode_clear_cache())?;
+        output.trim().parse().map_err(|_| {
+            DaemonClientError::ProtocolError(format!("Unexpected clear cache answer: {:?}", output))
+        })
+    }
+
+    /// Execute code in one of the daemon's sessions
+    ///
+    /// The session is created on first use. Variables and functions defined
+    /// by earlier calls with the same `session` id are visible, until the
+    /// daemon drops the session after it has gone unused for a while (see
+    /// [`crate::daemon_session`]). There is no fallback to direct execution,
+    /// since the session's state only exists in the daemon.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use pyrust::daemon_client::DaemonClient;
+    ///
+    /// DaemonClient::execute_in_session("repl", "x = 41").unwrap();
+    /// assert_eq!(DaemonClient::execute_in_session("repl", "x + 1").unwrap(), "42");
+    /// ```
+    pub fn execute_in_session(session: &str, code: &str) -> Result<String, DaemonClientError> {
+        let mut frames = DaemonRequest::encode_session(session);
+        frames.extend_from_slice(&DaemonRequest::new(code).encode());
+        let mut stream = Self::connect()?;
         stream
-            .read_exact(&mut header_buf)
-            .map_err(DaemonClientError::ReadFailed)?;
+            .write_all(&frames)
+            .map_err(DaemonClientError::WriteFailed)?;
 
-        // Parse response length
-        let output_len =
-            u32::from_be_bytes([header_buf[1], header_buf[2], header_buf[3], header_buf[4]])
-                as usize;
+        // The session frame is answered first
+        Self::read_result(&mut stream)?;
+        Self::read_result(&mut stream)
+    }
 
-        // Safety check: prevent unbounded allocation
-        if output_len > MAX_RESPONSE_SIZE {
-            return Err(DaemonClientError::ResponseTooLarge {
-                size: output_len,
-                max: MAX_RESPONSE_SIZE,
-            });
+    /// Execute several snippets via daemon in one round trip
+    ///
+    /// The snippets run one after another, each as if sent alone, and the
+    /// result of each is returned in order: a snippet that fails does not
+    /// stop the rest. Cheaper than a call per snippet when there are many
+    /// small ones. They are sent as [`Priority::Batch`], so a busy daemon
+    /// serves interactive requests first. There is no fallback to direct
+    /// execution.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use pyrust::daemon_client::DaemonClient;
+    ///
+    /// let results = DaemonClient::execute_batch(&["1+1", "1/0", "2*3"]).unwrap();
+    /// assert_eq!(results[0].as_deref().unwrap(), "2");
+    /// assert!(results[1].is_err());
+    /// ```
+    pub fn execute_batch(
+        codes: &[&str],
+    ) -> Result<Vec<Result<String, DaemonClientError>>, DaemonClientError> {
+        if codes.is_empty() {
+            return Ok(Vec::new());
         }
+        let requests: Vec<_> = codes
+            .iter()
+            .map(|&code| DaemonRequest::new(code).with_priority(Priority::Batch))
+            .collect();
+        let mut stream = Self::connect()?;
+        stream
+            .write_all(&DaemonRequest::encode_batch(&requests))
+            .map_err(DaemonClientError::WriteFailed)?;
+        let results = requests
+            .iter()
+            .map(|_| {
+                let response = Self::read_frame(|buf| {
+                    stream
+                        .read_exact(buf)
+                        .map_err(DaemonClientError::ReadFailed)
+                })?;
+                Ok(Self::into_result(response))
+            })
+            .collect::<Result<_, _>>()?;
+        release(&Self::socket_path(), stream);
+        Ok(results)
+    }
 
-        // Read response body
-        let mut output_buf = vec![0u8; output_len];
+    /// Execute code via daemon and profile its pipeline stages
+    ///
+    /// Returns the output, the time each stage took in the daemon, and
+    /// whether the daemon's cache already held the compiled program. Unlike
+    /// [`crate::profiling::execute_python_profiled`], a repeated run shows
+    /// the warm cache: no lexing or parsing, and a compile stage that is
+    /// only the lookup. There is no fallback to direct execution.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use pyrust::daemon_client::DaemonClient;
+    ///
+    /// let (output, profile, cache_hit) = DaemonClient::execute_profiled("2+3").unwrap();
+    /// assert_eq!(output, "5");
+    /// eprintln!("{} (cache hit: {})", profile.format_table(), cache_hit);
+    /// ```
+    pub fn execute_profiled(
+        code: &str,
+    ) -> Result<(String, PipelineProfile, bool), DaemonClientError> {
+        Self::profile_request(&DaemonRequest::new(code).with_profile())
+    }
+
+    /// Like [`DaemonClient::execute_profiled`], with the daemon compiling
+    /// the code afresh rather than through its cache
+    ///
+    /// The profile then always shows every stage, and the cache hit is
+    /// always false.
+    pub fn execute_profiled_without_cache(
+        code: &str,
+    ) -> Result<(String, PipelineProfile, bool), DaemonClientError> {
+        Self::profile_request(&DaemonRequest::new(code).with_profile().without_cache())
+    }
+
+    /// Send a profiled request, returning its output, profile and cache hit
+    fn profile_request(
+        request: &DaemonRequest,
+    ) -> Result<(String, PipelineProfile, bool), DaemonClientError> {
+        let mut stream = Self::connect()?;
+        stream
+            .write_all(&encode_request(request))
+            .map_err(DaemonClientError::WriteFailed)?;
+        let response = Self::read_frame(|buf| {
+            stream
+                .read_exact(buf)
+                .map_err(DaemonClientError::ReadFailed)
+        })?;
+        if !response.is_profile() {
+            // Answered without running, as when busy, or by a daemon that
+            // does not profile
+            Self::into_result(response)?;
+            return Err(DaemonClientError::ProtocolError(
+                "Daemon sent no profile".to_string(),
+            ));
+        }
+        let profile = PipelineProfile::parse(response.output());
+        let cache_hit = response
+            .output()
+            .lines()
+            .any(|line| line == "cache_hit true");
+        let output = Self::finish(stream)?;
+        Ok((output, profile, cache_hit))
+    }
+
+    /// Execute code via daemon, giving up on it after `timeout`
+    ///
+    /// The daemon stops the request once it has run for `timeout` and
+    /// answers with [`DaemonClientError::TimedOut`]. There is no fallback to
+    /// direct execution.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use pyrust::daemon_client::DaemonClient;
+    /// use std::time::Duration;
+    ///
+    /// let result = DaemonClient::execute_with_timeout("2+3", Duration::from_secs(1)).unwrap();
+    /// assert_eq!(result, "5");
+    /// ```
+    pub fn execute_with_timeout(
+        code: &str,
+        timeout: Duration,
+    ) -> Result<String, DaemonClientError> {
+        let mut stream = Self::connect()?;
         stream
-            .read_exact(&mut output_buf)
-            .map_err(DaemonClientError::ReadFailed)?;
+            .set_read_timeout(Some(timeout + RESPONSE_TIMEOUT))
+            .map_err(DaemonClientError::SocketConfig)?;
+        stream
+            .write_all(&encode_request(
+                &DaemonRequest::new(code).with_timeout(timeout),
+            ))
+            .map_err(DaemonClientError::WriteFailed)?;
+        Self::finish(stream)
+    }
+
+    /// Check that the daemon is up and answering
+    ///
+    /// Sends a ping without authenticating, so it works whether or not the
+    /// daemon requires a token.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use pyrust::daemon_client::DaemonClient;
+    ///
+    /// if DaemonClient::ping().is_err() {
+    ///     eprintln!("daemon is unhealthy");
+    /// }
+    /// ```
+    pub fn ping() -> Result<(), DaemonClientError> {
+        let mut stream = Self::open()?;
+        stream
+            .write_all(&DaemonRequest::encode_ping())
+            .map_err(DaemonClientError::WriteFailed)?;
+        match Self::read_result(&mut stream)?.as_str() {
+            "pong" => Ok(()),
+            other => Err(DaemonClientError::ProtocolError(format!(
+                "Unexpected ping answer: {:?}",
+                other
+            ))),
+        }
+    }
+
+    /// Send a control frame and return the output of the response to it
+    fn query(frame: &[u8]) -> Result<String, DaemonClientError> {
+        let mut stream = Self::connect()?;
+        stream
+            .write_all(frame)
+            .map_err(DaemonClientError::WriteFailed)?;
+        Self::finish(stream)
+    }
+
+    /// Connect to the daemon with the default timeouts, reusing an idle
+    /// connection if one is pooled
+    ///
+    /// If [`AUTH_TOKEN_ENV`] is set, the connection is authenticated with it
+    /// before it is returned. If [`COMPRESSION_ENV`] is set, compression is
+    /// asked for too; a daemon that refuses it answers uncompressed.
+    fn connect() -> Result<Stream, DaemonClientError> {
+        if let Some(stream) = take_idle(&Self::socket_path()) {
+            return Ok(stream);
+        }
+        let mut stream = Self::open()?;
+        if let Some(token) = auth_token_from_env() {
+            stream
+                .write_all(&DaemonRequest::encode_auth(&token))
+                .map_err(DaemonClientError::WriteFailed)?;
+            Self::read_result(&mut stream)
+                .map_err(|e| DaemonClientError::AuthFailed(e.to_string()))?;
+        }
+        if let Some(compression) = compression_from_env() {
+            stream
+                .write_all(&DaemonRequest::encode_compression(compression))
+                .map_err(DaemonClientError::WriteFailed)?;
+            let _ = Self::read_result(&mut stream);
+        }
+        Ok(stream)
+    }
+
+    /// Connect to the daemon with the default timeouts, unauthenticated,
+    /// retrying as the [`RetryPolicy`] says
+    fn open() -> Result<Stream, DaemonClientError> {
+        let socket_path = Self::socket_path();
+        let policy = Self::retry_policy();
+        let stream = policy
+            .retry(|| daemon_transport::connect_timeout(&socket_path, policy.connect_timeout))
+            .map_err(DaemonClientError::ConnectionFailed)?;
+        stream
+            .set_read_timeout(Some(RESPONSE_TIMEOUT))
+            .map_err(DaemonClientError::SocketConfig)?;
+        stream
+            .set_write_timeout(Some(Duration::from_secs(1)))
+            .map_err(DaemonClientError::SocketConfig)?;
+        Ok(stream)
+    }
+
+    /// Read the response to the last request on `stream`, then keep the
+    /// connection for reuse
+    fn finish(mut stream: Stream) -> Result<String, DaemonClientError> {
+        let response = Self::read_frame(|buf| {
+            stream
+                .read_exact(buf)
+                .map_err(DaemonClientError::ReadFailed)
+        })?;
+        release(&Self::socket_path(), stream);
+        Self::into_result(response)
+    }
+
+    /// Read one response and return its output
+    fn read_result(stream: &mut Stream) -> Result<String, DaemonClientError> {
+        let response = Self::read_frame(|buf| {
+            stream
+                .read_exact(buf)
+                .map_err(DaemonClientError::ReadFailed)
+        })?;
+        Self::into_result(response)
+    }
+
+    /// Read and decode one response frame, filling buffers with `read_exact`
+    fn read_frame(
+        mut read_exact: impl FnMut(&mut [u8]) -> Result<(), DaemonClientError>,
+    ) -> Result<DaemonResponse, DaemonClientError> {
+        // Read response header (status + length = 5 bytes)
+        let mut header_buf = [0u8; 5];
+        read_exact(&mut header_buf)?;
 
-        // Combine header and body for decoding
-        let mut full_response = Vec::with_capacity(5 + output_len);
-        full_response.extend_from_slice(&header_buf);
-        full_response.extend_from_slice(&output_buf);
+        // Read response body
+        let mut output_buf = vec![0u8; response_body_len(&header_buf)?];
+        read_exact(&mut output_buf)?;
 
-        // Decode response
-        let (response, _bytes_consumed) = DaemonResponse::decode(&full_response)
-            .map_err(|e| DaemonClientError::ProtocolError(format!("{}", e)))?;
+        decode_response(&header_buf, &output_buf)
+    }
 
-        // Check status and return result or error
+    /// The output of a final response, or its error message as an error
+    pub(crate) fn into_result(response: DaemonResponse) -> Result<String, DaemonClientError> {
         if response.is_success() {
             Ok(response.output().to_string())
+        } else if response.is_timeout() {
+            Err(DaemonClientError::TimedOut(response.output().to_string()))
+        } else if response.is_busy() {
+            Err(DaemonClientError::Busy(response.output().to_string()))
+        } else if let Some(code) = response.exit_code() {
+            Err(DaemonClientError::Exited(code))
+        } else if let Some((limit, message)) = response.exceeded_limit() {
+            Err(DaemonClientError::LimitExceeded {
+                limit: limit.to_string(),
+                message: message.to_string(),
+            })
+        } else if let Some(diagnostic) = response.diagnostic() {
+            Err(DaemonClientError::Failed(Box::new(diagnostic)))
         } else {
             // Return execution error with the error message from daemon
             Err(DaemonClientError::ExecutionError(
@@ -187,6 +937,51 @@ impl DaemonClient {
         }
     }
 
+    /// Fill `buf` from the daemon, cancelling the request with `id` once
+    /// `token` is cancelled
+    ///
+    /// The stream's read timeout sets how often the token is checked. Fails
+    /// with a timeout once `deadline` passes, cancelling the request first so
+    /// that it does not run on unanswered.
+    fn read_cancellable(
+        stream: &mut Stream,
+        buf: &mut [u8],
+        token: &CancellationToken,
+        id: u32,
+        cancel_sent: &mut bool,
+        deadline: Instant,
+    ) -> Result<(), DaemonClientError> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match stream.read(&mut buf[filled..]) {
+                Ok(0) => {
+                    return Err(DaemonClientError::ReadFailed(
+                        std::io::ErrorKind::UnexpectedEof.into(),
+                    ))
+                }
+                Ok(read) => filled += read,
+                Err(e)
+                    if e.kind() == std::io::ErrorKind::WouldBlock
+                        || e.kind() == std::io::ErrorKind::TimedOut =>
+                {
+                    let expired = Instant::now() >= deadline;
+                    if (token.is_cancelled() || expired) && !*cancel_sent {
+                        stream
+                            .write_all(&DaemonRequest::encode_cancel_request(id))
+                            .map_err(DaemonClientError::WriteFailed)?;
+                        *cancel_sent = true;
+                    }
+                    if expired {
+                        return Err(DaemonClientError::ReadFailed(e));
+                    }
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(DaemonClientError::ReadFailed(e)),
+            }
+        }
+        Ok(())
+    }
+
     /// Stop running daemon by reading PID and sending SIGTERM
     ///
     /// This method reads the daemon's PID from the PID file, sends a SIGTERM signal,
@@ -208,7 +1003,8 @@ impl DaemonClient {
         use std::fs;
 
         // Read PID from file
-        let pid_str = fs::read_to_string(PID_FILE_PATH).map_err(DaemonClientError::PidFileRead)?;
+        let pid_str =
+            fs::read_to_string(Self::pid_file_path()).map_err(DaemonClientError::PidFileRead)?;
 
         let pid: i32 = pid_str
             .trim()
@@ -221,11 +1017,25 @@ impl DaemonClient {
             libc::kill(pid, libc::SIGTERM);
         }
 
+        // A detached process has no console to signal, so it is killed and
+        // its files are removed here instead
+        #[cfg(windows)]
+        {
+            std::process::Command::new("taskkill")
+                .arg("/PID")
+                .arg(pid.to_string())
+                .arg("/F")
+                .output()
+                .map_err(|_| DaemonClientError::ShutdownFailed)?;
+            let _ = fs::remove_file(Self::socket_path());
+            let _ = fs::remove_file(Self::pid_file_path());
+        }
+
         // Wait briefly for cleanup
         std::thread::sleep(Duration::from_millis(100));
 
         // Verify shutdown by checking socket file removal
-        if Path::new(SOCKET_PATH).exists() {
+        if Self::is_daemon_running() {
             return Err(DaemonClientError::ShutdownFailed);
         }
 
@@ -254,6 +1064,219 @@ impl DaemonClient {
     }
 }
 
+/// A connection to the daemon kept open for many requests
+///
+/// Requests can be pipelined: [`DaemonConnection::send`] several, then
+/// [`DaemonConnection::receive`] their results in the order they were sent.
+/// Keep the number in flight small, as the daemon stops reading once its
+/// queue for the connection is full. Dropped with every response read, the
+/// connection goes back to the pool when pooling is on (see
+/// [`POOL_SIZE_ENV`]). There is no fallback to direct execution.
+///
+/// # Examples
+///
+/// ```no_run
+/// use pyrust::daemon_client::DaemonConnection;
+///
+/// let mut connection = DaemonConnection::open().unwrap();
+/// connection.send("1+1").unwrap();
+/// connection.send("2*3").unwrap();
+/// assert_eq!(connection.receive().unwrap(), "2");
+/// assert_eq!(connection.receive().unwrap(), "6");
+/// assert_eq!(connection.execute("7").unwrap(), "7");
+/// ```
+pub struct DaemonConnection {
+    /// None once dropped
+    stream: Option<Stream>,
+    /// Requests sent and not yet answered
+    pending: usize,
+    /// An I/O error left the connection unusable
+    broken: bool,
+}
+
+impl DaemonConnection {
+    /// Connect to the daemon, reusing a pooled connection if there is one
+    pub fn open() -> Result<Self, DaemonClientError> {
+        Ok(Self {
+            stream: Some(DaemonClient::connect()?),
+            pending: 0,
+            broken: false,
+        })
+    }
+
+    /// Send `code` to execute without waiting for its result
+    pub fn send(&mut self, code: &str) -> Result<(), DaemonClientError> {
+        let request = encode_request(&DaemonRequest::new(code));
+        let written = self.stream().write_all(&request);
+        self.broken |= written.is_err();
+        written.map_err(DaemonClientError::WriteFailed)?;
+        self.pending += 1;
+        Ok(())
+    }
+
+    /// Wait for the result of the oldest request still unanswered
+    pub fn receive(&mut self) -> Result<String, DaemonClientError> {
+        if self.pending == 0 {
+            return Err(DaemonClientError::ProtocolError(
+                "No request awaiting a response".to_string(),
+            ));
+        }
+        let stream = self.stream();
+        let response = DaemonClient::read_frame(|buf| {
+            stream
+                .read_exact(buf)
+                .map_err(DaemonClientError::ReadFailed)
+        });
+        self.broken |= response.is_err();
+        let response = response?;
+        self.pending -= 1;
+        DaemonClient::into_result(response)
+    }
+
+    /// Execute `code` and wait for its result
+    pub fn execute(&mut self, code: &str) -> Result<String, DaemonClientError> {
+        self.send(code)?;
+        self.receive()
+    }
+
+    fn stream(&mut self) -> &mut Stream {
+        self.stream.as_mut().expect("stream is only taken on drop")
+    }
+}
+
+impl Drop for DaemonConnection {
+    fn drop(&mut self) {
+        if let Some(stream) = self.stream.take() {
+            // Unread responses would be taken for the next caller's
+            if self.pending == 0 && !self.broken {
+                release(&DaemonClient::socket_path(), stream);
+            }
+        }
+    }
+}
+
+/// Length of the body of the response whose header is `header`
+pub(crate) fn response_body_len(header: &[u8; 5]) -> Result<usize, DaemonClientError> {
+    let output_len = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+
+    // Safety check: prevent unbounded allocation
+    if output_len > MAX_RESPONSE_SIZE {
+        return Err(DaemonClientError::ResponseTooLarge {
+            size: output_len,
+            max: MAX_RESPONSE_SIZE,
+        });
+    }
+    Ok(output_len)
+}
+
+/// Decode the response made of `header` and `body`
+pub(crate) fn decode_response(
+    header: &[u8; 5],
+    body: &[u8],
+) -> Result<DaemonResponse, DaemonClientError> {
+    // Combine header and body for decoding
+    let mut full_response = Vec::with_capacity(5 + body.len());
+    full_response.extend_from_slice(header);
+    full_response.extend_from_slice(body);
+
+    let (response, _bytes_consumed) = DaemonResponse::decode(&full_response)
+        .map_err(|e| DaemonClientError::ProtocolError(format!("{}", e)))?;
+
+    // A compressed response may expand past the limit
+    if response.output().len() > MAX_RESPONSE_SIZE {
+        return Err(DaemonClientError::ResponseTooLarge {
+            size: response.output().len(),
+            max: MAX_RESPONSE_SIZE,
+        });
+    }
+    Ok(response)
+}
+
+/// Compression named by [`COMPRESSION_ENV`], if it names one
+pub(crate) fn compression_from_env() -> Option<Compression> {
+    std::env::var(COMPRESSION_ENV)
+        .ok()
+        .and_then(|name| Compression::from_name(name.trim()))
+}
+
+/// Encode `request`, compressed if it is large and compression is on
+fn encode_request(request: &DaemonRequest) -> Vec<u8> {
+    encode_request_with(request, compression_from_env())
+}
+
+/// Encode `request`, compressed with `compression` if it is large
+pub(crate) fn encode_request_with(
+    request: &DaemonRequest,
+    compression: Option<Compression>,
+) -> Vec<u8> {
+    match compression {
+        Some(compression) if request.code().len() > COMPRESSION_THRESHOLD => {
+            request.encode_compressed(compression)
+        }
+        _ => request.encode(),
+    }
+}
+
+/// Number of idle connections to keep, from [`POOL_SIZE_ENV`]
+fn pool_size() -> usize {
+    std::env::var(POOL_SIZE_ENV)
+        .ok()
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Take a pooled connection to the daemon at `socket_path`, if one is
+/// still open
+fn take_idle(socket_path: &str) -> Option<Stream> {
+    let mut idle = IDLE_CONNECTIONS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    idle.retain(|connection| connection.since.elapsed() < MAX_IDLE_TIME);
+    while let Some(index) = idle
+        .iter()
+        .rposition(|connection| connection.socket_path == socket_path)
+    {
+        let connection = idle.remove(index);
+        if is_open(&connection.stream) {
+            return Some(connection.stream);
+        }
+    }
+    None
+}
+
+/// Keep `stream`, whose requests are all answered, for reuse if the pool
+/// has room
+fn release(socket_path: &str, stream: Stream) {
+    // Requests may have changed the timeouts
+    if stream.set_read_timeout(Some(RESPONSE_TIMEOUT)).is_err() {
+        return;
+    }
+    let mut idle = IDLE_CONNECTIONS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    if idle.len() < pool_size() {
+        idle.push(IdleConnection {
+            socket_path: socket_path.to_string(),
+            stream,
+            since: Instant::now(),
+        });
+    }
+}
+
+/// Check that the daemon has not closed `stream`, without blocking
+fn is_open(stream: &Stream) -> bool {
+    if stream.set_nonblocking(true).is_err() {
+        return false;
+    }
+    let mut reader = stream;
+    // Nothing to read is the only sign of a healthy idle connection
+    let open = matches!(
+        reader.read(&mut [0u8; 1]),
+        Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock
+    );
+    stream.set_nonblocking(false).is_ok() && open
+}
+
 /// Errors that can occur during daemon client operations
 #[derive(Debug)]
 pub enum DaemonClientError {
@@ -281,6 +1304,21 @@ pub enum DaemonClientError {
     ShutdownFailed,
     /// Protocol error during decode
     ProtocolError(String),
+    /// The daemon rejected the auth token
+    AuthFailed(String),
+    /// The daemon stopped the request when it ran out of time
+    TimedOut(String),
+    /// The daemon turned the request away because its queue was full
+    Busy(String),
+    /// The daemon stopped the request at one of its resource limits
+    ///
+    /// `limit` names the limit, as in [`crate::daemon_limits::Limit::name`].
+    LimitExceeded { limit: String, message: String },
+    /// The program stopped itself with `exit(code)`
+    Exited(i32),
+    /// The program failed with the error described, for a request that
+    /// asked for a diagnostic
+    Failed(Box<Diagnostic>),
 }
 
 impl fmt::Display for DaemonClientError {
@@ -302,6 +1340,17 @@ impl fmt::Display for DaemonClientError {
             DaemonClientError::InvalidPid(msg) => write!(f, "Invalid PID: {}", msg),
             DaemonClientError::ShutdownFailed => write!(f, "Daemon failed to shutdown cleanly"),
             DaemonClientError::ProtocolError(msg) => write!(f, "Protocol error: {}", msg),
+            DaemonClientError::AuthFailed(msg) => write!(
+                f,
+                "Daemon rejected the token in {}: {}",
+                AUTH_TOKEN_ENV, msg
+            ),
+            DaemonClientError::TimedOut(msg) | DaemonClientError::Busy(msg) => {
+                write!(f, "{}", msg)
+            }
+            DaemonClientError::LimitExceeded { message, .. } => write!(f, "{}", message),
+            DaemonClientError::Exited(code) => write!(f, "Program exited with code {}", code),
+            DaemonClientError::Failed(diagnostic) => write!(f, "{}", diagnostic),
         }
     }
 }
@@ -378,6 +1427,83 @@ mod tests {
         assert_eq!(status, "Daemon is running");
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn test_idle_connections_reused_while_open() {
+        use std::os::unix::net::{UnixListener, UnixStream};
+
+        let path = std::env::temp_dir()
+            .join(format!("pyrust-pool-{}.sock", std::process::id()))
+            .to_string_lossy()
+            .into_owned();
+        let _ = fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let pool = |stream, since| {
+            IDLE_CONNECTIONS.lock().unwrap().push(IdleConnection {
+                socket_path: path.clone(),
+                stream,
+                since,
+            })
+        };
+
+        let client = UnixStream::connect(&path).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        pool(client, Instant::now());
+        let client = take_idle(&path).expect("open connection not reused");
+        assert!(take_idle(&path).is_none());
+
+        // Kept too long, or closed by the daemon
+        pool(client.try_clone().unwrap(), Instant::now() - MAX_IDLE_TIME);
+        assert!(take_idle(&path).is_none());
+        drop(server);
+        pool(client, Instant::now());
+        assert!(take_idle(&path).is_none());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        let policy = RetryPolicy {
+            retries: 3,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(3),
+            ..RetryPolicy::default()
+        };
+        let backoffs: Vec<_> = (0..4).map(|retry| policy.backoff(retry)).collect();
+        assert_eq!(backoffs, [1, 2, 3, 3].map(Duration::from_millis));
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_millis(3));
+
+        // Transient failures are retried until the retries run out
+        let mut attempts = 0;
+        let result: io::Result<()> = policy.retry(|| {
+            attempts += 1;
+            Err(io::ErrorKind::ConnectionRefused.into())
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 4);
+
+        let mut attempts = 0;
+        let result = policy.retry(|| {
+            attempts += 1;
+            if attempts < 3 {
+                Err(io::ErrorKind::NotFound.into())
+            } else {
+                Ok(attempts)
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+
+        // Others are not
+        let mut attempts = 0;
+        let result: io::Result<()> = policy.retry(|| {
+            attempts += 1;
+            Err(io::ErrorKind::PermissionDenied.into())
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
     #[test]
     fn test_error_display() {
         let err = DaemonClientError::ConnectionFailed(std::io::Error::new(
@@ -0,0 +1,314 @@
+//! Tokio-based daemon client
+//!
+//! Available with the `async-daemon` feature. [`AsyncDaemonClient`] sends
+//! the same requests as [`DaemonClient`], but awaits the daemon instead of
+//! blocking a thread on it, for tokio applications that embed pyrust. It
+//! connects the same way: to the socket of the instance named in the
+//! environment, authenticating and asking for compression as the
+//! environment says, and retrying failed connections by a [`RetryPolicy`].
+//! Each of those can be set on the client instead.
+//!
+//! Unlike [`DaemonClient::execute_or_fallback`], nothing falls back to
+//! direct execution: a caller without a daemon gets the error, and can run
+//! the code itself on a blocking thread if it wants to.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use pyrust::daemon_client_async::AsyncDaemonClient;
+//!
+//! # async fn run() -> Result<(), pyrust::daemon_client::DaemonClientError> {
+//! let client = AsyncDaemonClient::new();
+//! assert_eq!(client.execute("2+3").await?, "5");
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`DaemonClient`]: crate::daemon_client::DaemonClient
+//! [`DaemonClient::execute_or_fallback`]: crate::daemon_client::DaemonClient::execute_or_fallback
+
+use crate::daemon::auth_token_from_env;
+use crate::daemon_client::{
+    compression_from_env, decode_response, encode_request_with, is_transient, response_body_len,
+    DaemonClient, DaemonClientError, RetryPolicy, RESPONSE_TIMEOUT,
+};
+use crate::daemon_protocol::{Compression, DaemonRequest, DaemonResponse};
+use std::io;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::time::timeout;
+
+#[cfg(unix)]
+use tokio::net::UnixStream as Stream;
+
+#[cfg(windows)]
+use tokio::net::TcpStream as Stream;
+
+/// A client of the daemon for async code
+#[derive(Debug, Clone)]
+pub struct AsyncDaemonClient {
+    socket_path: String,
+    policy: RetryPolicy,
+    auth_token: Option<String>,
+    compression: Option<Compression>,
+}
+
+impl Default for AsyncDaemonClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AsyncDaemonClient {
+    /// A client configured like [`DaemonClient`]
+    ///
+    /// [`DaemonClient`]: crate::daemon_client::DaemonClient
+    pub fn new() -> Self {
+        Self {
+            socket_path: DaemonClient::socket_path(),
+            policy: DaemonClient::retry_policy(),
+            auth_token: auth_token_from_env(),
+            compression: compression_from_env(),
+        }
+    }
+
+    /// Talk to the daemon listening at `socket_path`
+    pub fn with_socket_path(mut self, socket_path: impl Into<String>) -> Self {
+        self.socket_path = socket_path.into();
+        self
+    }
+
+    /// Retry failed connections according to `policy`
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Authenticate with `token`, or not at all
+    pub fn with_auth_token(mut self, token: Option<String>) -> Self {
+        self.auth_token = token;
+        self
+    }
+
+    /// Ask the daemon for `compression`, or for none
+    pub fn with_compression(mut self, compression: Option<Compression>) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Execute `code` on the daemon and return its output
+    pub async fn execute(&self, code: &str) -> Result<String, DaemonClientError> {
+        let mut stream = self.connect().await?;
+        self.send(&mut stream, &DaemonRequest::new(code)).await?;
+        DaemonClient::into_result(read_response(&mut stream).await?)
+    }
+
+    /// Execute `code` on the daemon, passing print output to `sink` as the
+    /// daemon streams it, and return the expression result
+    pub async fn execute_streaming(
+        &self,
+        code: &str,
+        mut sink: impl FnMut(&str),
+    ) -> Result<String, DaemonClientError> {
+        let mut stream = self.connect().await?;
+        self.send(&mut stream, &DaemonRequest::streaming(code))
+            .await?;
+        loop {
+            let response = read_response(&mut stream).await?;
+            if !response.is_chunk() {
+                return DaemonClient::into_result(response);
+            }
+            sink(response.output());
+        }
+    }
+
+    /// Check that the daemon answers
+    pub async fn ping(&self) -> Result<(), DaemonClientError> {
+        match self.query(&DaemonRequest::encode_ping()).await?.as_str() {
+            "pong" => Ok(()),
+            other => Err(DaemonClientError::ProtocolError(format!(
+                "Unexpected ping answer: {:?}",
+                other
+            ))),
+        }
+    }
+
+    /// Fetch the daemon's metrics as Prometheus text
+    pub async fn metrics(&self) -> Result<String, DaemonClientError> {
+        self.query(&DaemonRequest::encode_metrics()).await
+    }
+
+    /// Send a control frame and return the output of the response to it
+    async fn query(&self, frame: &[u8]) -> Result<String, DaemonClientError> {
+        let mut stream = self.connect().await?;
+        write_frame(&mut stream, frame).await?;
+        DaemonClient::into_result(read_response(&mut stream).await?)
+    }
+
+    async fn send(
+        &self,
+        stream: &mut Stream,
+        request: &DaemonRequest,
+    ) -> Result<(), DaemonClientError> {
+        write_frame(stream, &encode_request_with(request, self.compression)).await
+    }
+
+    /// Connect, authenticate and negotiate compression
+    async fn connect(&self) -> Result<Stream, DaemonClientError> {
+        let mut stream = self.open().await?;
+        if let Some(token) = &self.auth_token {
+            write_frame(&mut stream, &DaemonRequest::encode_auth(token)).await?;
+            read_response(&mut stream)
+                .await
+                .and_then(DaemonClient::into_result)
+                .map_err(|e| DaemonClientError::AuthFailed(e.to_string()))?;
+        }
+        if let Some(compression) = self.compression {
+            write_frame(&mut stream, &DaemonRequest::encode_compression(compression)).await?;
+            // A daemon that refuses answers uncompressed
+            let _ = read_response(&mut stream).await?;
+        }
+        Ok(stream)
+    }
+
+    /// Connect, retrying as the policy says
+    async fn open(&self) -> Result<Stream, DaemonClientError> {
+        let mut retry = 0;
+        loop {
+            let attempt =
+                match timeout(self.policy.connect_timeout, connect(&self.socket_path)).await {
+                    Ok(result) => result,
+                    Err(_) => Err(io::ErrorKind::TimedOut.into()),
+                };
+            match attempt {
+                Err(e) if retry < self.policy.retries && is_transient(&e) => {
+                    tokio::time::sleep(self.policy.backoff(retry)).await;
+                    retry += 1;
+                }
+                result => return result.map_err(DaemonClientError::ConnectionFailed),
+            }
+        }
+    }
+}
+
+/// Connect to the daemon whose socket is at `path`
+#[cfg(unix)]
+async fn connect(path: &str) -> io::Result<Stream> {
+    Stream::connect(path).await
+}
+
+/// Connect to the daemon whose socket is at `path`
+#[cfg(windows)]
+async fn connect(path: &str) -> io::Result<Stream> {
+    let port = crate::daemon_transport::loopback::port(path)?;
+    let stream = Stream::connect((std::net::Ipv4Addr::LOCALHOST, port)).await?;
+    stream.set_nodelay(true)?;
+    Ok(stream)
+}
+
+async fn write_frame(stream: &mut Stream, frame: &[u8]) -> Result<(), DaemonClientError> {
+    stream
+        .write_all(frame)
+        .await
+        .map_err(DaemonClientError::WriteFailed)
+}
+
+/// Read one response, giving up after [`RESPONSE_TIMEOUT`]
+async fn read_response(stream: &mut Stream) -> Result<DaemonResponse, DaemonClientError> {
+    let read = async {
+        let mut header = [0u8; 5];
+        stream
+            .read_exact(&mut header)
+            .await
+            .map_err(DaemonClientError::ReadFailed)?;
+        let mut body = vec![0u8; response_body_len(&header)?];
+        stream
+            .read_exact(&mut body)
+            .await
+            .map_err(DaemonClientError::ReadFailed)?;
+        decode_response(&header, &body)
+    };
+    timeout(RESPONSE_TIMEOUT, read).await.unwrap_or_else(|_| {
+        Err(DaemonClientError::ReadFailed(
+            io::ErrorKind::TimedOut.into(),
+        ))
+    })
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::daemon_async::AsyncDaemonServer;
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_async_client_retries_until_daemon_listens() {
+        let base = std::env::temp_dir().join(format!("pyrust-async-client-{}", std::process::id()));
+        let socket_path = format!("{}.sock", base.display());
+        let pid_path = format!("{}.pid", base.display());
+        let _ = std::fs::remove_file(&socket_path);
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let client = AsyncDaemonClient::new()
+            .with_socket_path(socket_path.clone())
+            .with_auth_token(None)
+            .with_compression(Some(Compression::Lz4));
+
+        // Without retries, a missing daemon fails at once
+        let result = runtime.block_on(client.ping());
+        assert!(matches!(
+            result,
+            Err(DaemonClientError::ConnectionFailed(_))
+        ));
+
+        // The daemon comes up while the client backs off
+        let server = {
+            let socket_path = socket_path.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(100));
+                let server =
+                    Arc::new(AsyncDaemonServer::with_paths(socket_path, pid_path).unwrap());
+                let runner = {
+                    let server = Arc::clone(&server);
+                    thread::spawn(move || server.run())
+                };
+                (server, runner)
+            })
+        };
+        let client = client.with_retry_policy(RetryPolicy {
+            retries: 20,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(50),
+            ..RetryPolicy::default()
+        });
+        runtime.block_on(async {
+            client.ping().await.unwrap();
+            assert_eq!(client.execute("6 * 7").await.unwrap(), "42");
+
+            let mut printed = String::new();
+            let result = client
+                .execute_streaming("print(7)\n8", |text| printed.push_str(text))
+                .await
+                .unwrap();
+            assert_eq!((printed.as_str(), result.as_str()), ("7\n", "8"));
+
+            let error = client.execute("1 / 0").await.unwrap_err();
+            assert!(matches!(error, DaemonClientError::ExecutionError(_)));
+            assert!(client
+                .metrics()
+                .await
+                .unwrap()
+                .contains("pyrust_requests_total"));
+        });
+
+        let (server, runner) = server.join().unwrap();
+        server.stop();
+        runner.join().unwrap().unwrap();
+        assert!(!Path::new(&socket_path).exists());
+    }
+}
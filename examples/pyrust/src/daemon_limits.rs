@@ -0,0 +1,171 @@
+//! Per-request resource limits for the daemon
+//!
+//! A daemon serving semi-trusted callers caps what each request may use:
+//! instructions executed, print output, call depth and memory (as estimated
+//! by [`VM::set_memory_limit`]). A request that goes over a limit is stopped
+//! and answered with a limit-exceeded response naming the limit (see
+//! [`crate::daemon_protocol`]), rather than a plain error.
+//!
+//! The limits are read from the environment when the daemon starts:
+//!
+//! | Variable | Limit |
+//! |----------|-------|
+//! | `PYRUST_DAEMON_MAX_INSTRUCTIONS` | instructions per request |
+//! | `PYRUST_DAEMON_MAX_OUTPUT_BYTES` | print output bytes per request |
+//! | `PYRUST_DAEMON_MAX_CALL_DEPTH` | nested function calls |
+//! | `PYRUST_DAEMON_MAX_MEMORY_BYTES` | bytes held by call frames and registers |
+//!
+//! Unset or invalid variables leave the limit off.
+
+use crate::error::PyRustError;
+use crate::error_code::ErrorCode;
+use crate::vm::{OutputLimit, OutputOverflow, VM};
+use std::fmt;
+
+/// A resource a request can run out of
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    /// Instructions executed
+    Instructions,
+    /// Bytes of print output
+    OutputBytes,
+    /// Depth of nested function calls
+    CallDepth,
+    /// Bytes held by call frames and registers
+    Memory,
+}
+
+impl Limit {
+    /// Name of the limit in limit-exceeded responses
+    pub fn name(&self) -> &'static str {
+        match self {
+            Limit::Instructions => "instructions",
+            Limit::OutputBytes => "output_bytes",
+            Limit::CallDepth => "call_depth",
+            Limit::Memory => "memory",
+        }
+    }
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Caps applied to every request the daemon runs; `None` leaves one off
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RequestLimits {
+    /// Instructions a request may execute
+    pub max_instructions: Option<u64>,
+    /// Bytes of print output a request may produce
+    pub max_output_bytes: Option<usize>,
+    /// Depth of nested function calls
+    pub max_call_depth: Option<usize>,
+    /// Estimated bytes a request may hold in call frames and registers
+    pub max_memory_bytes: Option<usize>,
+}
+
+impl RequestLimits {
+    /// Read the limits from the `PYRUST_DAEMON_MAX_*` variables
+    pub fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(name: &str) -> Option<T> {
+            std::env::var(name).ok().and_then(|s| s.parse().ok())
+        }
+        Self {
+            max_instructions: var("PYRUST_DAEMON_MAX_INSTRUCTIONS"),
+            max_output_bytes: var("PYRUST_DAEMON_MAX_OUTPUT_BYTES"),
+            max_call_depth: var("PYRUST_DAEMON_MAX_CALL_DEPTH"),
+            max_memory_bytes: var("PYRUST_DAEMON_MAX_MEMORY_BYTES"),
+        }
+    }
+
+    /// Configure `vm` with the limits that are set
+    ///
+    /// Limits left off keep whatever the VM was built with, such as the
+    /// process-wide `PYRUST_MAX_INSTRUCTIONS` budget.
+    pub(crate) fn apply(&self, vm: &mut VM) {
+        if let Some(max) = self.max_instructions {
+            vm.set_instruction_limit(Some(max));
+        }
+        if let Some(max_bytes) = self.max_output_bytes {
+            vm.set_output_limit(Some(OutputLimit {
+                max_bytes,
+                on_overflow: OutputOverflow::Error,
+            }));
+        }
+        if let Some(max) = self.max_call_depth {
+            vm.set_call_depth_limit(Some(max));
+        }
+        if let Some(max) = self.max_memory_bytes {
+            vm.set_memory_limit(Some(max));
+        }
+    }
+
+    /// The limit `error` reports going over, with the error's message
+    ///
+    /// Only limits set here count: a run stopped by a budget the VM was
+    /// built with is a plain error.
+    pub(crate) fn exceeded<'e>(&self, error: &'e PyRustError) -> Option<(Limit, &'e str)> {
+        let PyRustError::RuntimeError(error) = error else {
+            return None;
+        };
+        let limit = match error.code {
+            ErrorCode::InstructionLimit if self.max_instructions.is_some() => Limit::Instructions,
+            ErrorCode::OutputLimit if self.max_output_bytes.is_some() => Limit::OutputBytes,
+            ErrorCode::CallDepthLimit if self.max_call_depth.is_some() => Limit::CallDepth,
+            ErrorCode::MemoryLimit if self.max_memory_bytes.is_some() => Limit::Memory,
+            _ => return None,
+        };
+        Some((limit, error.message.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exceeded_limits_are_recognized() {
+        let limits = RequestLimits {
+            max_instructions: Some(100),
+            max_output_bytes: Some(4),
+            max_call_depth: None,
+            max_memory_bytes: None,
+        };
+        let run = |code: &str| {
+            let mut vm = VM::new();
+            limits.apply(&mut vm);
+            let program = crate::parser::parse(crate::lexer::lex(code).unwrap()).unwrap();
+            let bytecode = crate::compiler::compile(&program).unwrap();
+            PyRustError::RuntimeError(vm.execute(&bytecode).unwrap_err())
+        };
+
+        let error = run("print(123)\nprint(456)");
+        assert_eq!(
+            limits.exceeded(&error),
+            Some((Limit::OutputBytes, "output limit exceeded (4 bytes)"))
+        );
+        let mut source = String::from("def f(n):\n    return n\n");
+        source.push_str(&"f(1)\n".repeat(100));
+        let error = run(&source);
+        assert_eq!(
+            limits.exceeded(&error).map(|(limit, _)| limit),
+            Some(Limit::Instructions)
+        );
+        assert_eq!(error.code(), ErrorCode::InstructionLimit);
+        // Other runtime errors are not limits
+        assert_eq!(limits.exceeded(&run("1 / 0")), None);
+
+        // Nor is a limit these limits leave off
+        let mut vm = VM::new();
+        vm.set_call_depth_limit(Some(2));
+        let program =
+            crate::parser::parse(crate::lexer::lex("def f(n):\n    return f(n)\nf(1)").unwrap())
+                .unwrap();
+        let bytecode = crate::compiler::compile(&program).unwrap();
+        let error = PyRustError::RuntimeError(vm.execute(&bytecode).unwrap_err());
+        assert_eq!(error.code(), ErrorCode::CallDepthLimit);
+        assert_eq!(limits.exceeded(&error), None);
+    }
+}
@@ -0,0 +1,261 @@
+//! Log file for the daemon
+//!
+//! Once the daemon detaches, its stderr points at /dev/null, so the servers
+//! report through the [`log`] facade instead. [`init`] installs a
+//! [`FileLogger`] that appends one line per record:
+//!
+//! ```text
+//! 1760000000.123 INFO pyrust::daemon: request duration_ms=0.412 cache=hit outcome=success
+//! ```
+//!
+//! The timestamp is seconds since the Unix epoch. Messages carry their
+//! details as `key=value` fields so the file can be searched with ordinary
+//! text tools. Once a line would grow the file past
+//! [`LogConfig::max_bytes`], it is rotated: `pyrust.log` becomes
+//! `pyrust.log.1`, older files shift up by one, and files beyond
+//! [`LogConfig::max_files`] are dropped.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use pyrust::daemon_log::{self, LogConfig};
+//!
+//! let config = LogConfig {
+//!     level: log::LevelFilter::Debug,
+//!     ..LogConfig::default()
+//! };
+//! daemon_log::init(&config).unwrap();
+//! log::info!("daemon started");
+//! ```
+
+use log::{LevelFilter, Log, Metadata, Record};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Default log file path (in the user's temporary directory on Windows)
+pub const LOG_PATH: &str = "/tmp/pyrust.log";
+
+/// Default size at which the log file is rotated (10 MB)
+pub const DEFAULT_MAX_LOG_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Default number of rotated log files kept
+pub const DEFAULT_MAX_LOG_FILES: usize = 5;
+
+/// Where the daemon logs, and how much
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogConfig {
+    /// Most verbose level written
+    pub level: LevelFilter,
+    /// Log file, created if missing and appended to otherwise
+    pub path: PathBuf,
+    /// Size past which the file is rotated
+    pub max_bytes: u64,
+    /// Rotated files kept besides the current one
+    pub max_files: usize,
+}
+
+impl LogConfig {
+    /// Default configuration for daemon instance `name`
+    ///
+    /// Named instances log to `/tmp/pyrust-<name>.log` (see
+    /// [`crate::daemon::DAEMON_NAME_ENV`]); the default one to [`LOG_PATH`].
+    pub fn for_instance(name: Option<&str>) -> Self {
+        let file = match name {
+            Some(name) => format!("pyrust-{}.log", name),
+            None => "pyrust.log".to_string(),
+        };
+        Self {
+            path: crate::daemon::runtime_dir().join(file),
+            ..Self::default()
+        }
+    }
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: LevelFilter::Info,
+            path: if cfg!(windows) {
+                crate::daemon::runtime_dir().join("pyrust.log")
+            } else {
+                PathBuf::from(LOG_PATH)
+            },
+            max_bytes: DEFAULT_MAX_LOG_BYTES,
+            max_files: DEFAULT_MAX_LOG_FILES,
+        }
+    }
+}
+
+/// Install a [`FileLogger`] for `config` as the process's logger
+///
+/// Fails if the log file cannot be opened or a logger is already installed.
+pub fn init(config: &LogConfig) -> io::Result<()> {
+    let logger = FileLogger::open(config)?;
+    log::set_boxed_logger(Box::new(logger)).map_err(|e| io::Error::other(e.to_string()))?;
+    log::set_max_level(config.level);
+    Ok(())
+}
+
+/// Logger appending to a file that is rotated by size
+pub struct FileLogger {
+    level: LevelFilter,
+    file: Mutex<RotatingFile>,
+}
+
+impl FileLogger {
+    /// Open (or create) the log file of `config`
+    pub fn open(config: &LogConfig) -> io::Result<Self> {
+        Ok(Self {
+            level: config.level,
+            file: Mutex::new(RotatingFile::open(config)?),
+        })
+    }
+}
+
+impl Log for FileLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let line = format!(
+            "{}.{:03} {} {}: {}\n",
+            now.as_secs(),
+            now.subsec_millis(),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+        // Nowhere left to report a failed write
+        let _ = self
+            .file
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .write_line(&line);
+    }
+
+    fn flush(&self) {
+        let _ = self
+            .file
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .file
+            .flush();
+    }
+}
+
+/// The current log file and its size
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_bytes: u64,
+    max_files: usize,
+}
+
+impl RotatingFile {
+    fn open(config: &LogConfig) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&config.path)?;
+        Ok(Self {
+            path: config.path.clone(),
+            size: file.metadata()?.len(),
+            file,
+            max_bytes: config.max_bytes,
+            max_files: config.max_files,
+        })
+    }
+
+    /// Append `line`, rotating first if it would not fit
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        if self.size > 0 && self.size + line.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    /// Shift the rotated files up by one and start an empty log file
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.max_files > 0 {
+            for n in (1..self.max_files).rev() {
+                // Missing files are fine: there were fewer rotations so far
+                let _ = fs::rename(self.rotated(n), self.rotated(n + 1));
+            }
+            fs::rename(&self.path, self.rotated(1))?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+
+    /// Path of the `n`th most recent rotated file
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", n));
+        path.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::Level;
+
+    #[test]
+    fn test_log_file_rotation() {
+        let dir = std::env::temp_dir().join(format!("pyrust-log-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let config = LogConfig {
+            level: LevelFilter::Info,
+            path: dir.join("pyrust.log"),
+            max_bytes: 200,
+            max_files: 2,
+        };
+        let logger = FileLogger::open(&config).unwrap();
+        let log = |level: Level, n: usize| {
+            logger.log(
+                &Record::builder()
+                    .level(level)
+                    .target("pyrust::daemon")
+                    .args(format_args!("request n={}", n))
+                    .build(),
+            )
+        };
+
+        // Below the configured level: not written
+        log(Level::Debug, 0);
+        assert_eq!(fs::read_to_string(&config.path).unwrap(), "");
+
+        // Lines are about 50 bytes, so four fit in each file
+        for n in 1..=14 {
+            log(Level::Info, n);
+        }
+        let current = fs::read_to_string(&config.path).unwrap();
+        let first = fs::read_to_string(dir.join("pyrust.log.1")).unwrap();
+        assert!(current.ends_with(" INFO pyrust::daemon: request n=14\n"));
+        assert!(first.contains("request n=12\n") && !first.contains("request n=13\n"));
+        assert!(fs::metadata(dir.join("pyrust.log.2")).is_ok());
+        // Only two rotated files are kept
+        assert!(fs::metadata(dir.join("pyrust.log.3")).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
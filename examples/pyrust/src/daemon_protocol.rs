@@ -16,10 +16,235 @@
 //! ```text
 //! [u8 status][u32 length (big-endian)][UTF-8 output]
 //! ```
-//! - `status`: 1-byte status code (0 = success, 1 = error)
+//! - `status`: 1-byte status code (0 = success, 1 = error, 2 = output chunk,
+//!   3 = timed out, 4 = resource limit exceeded, 5 = server busy,
+//!   6 = pipeline profile, 8 = exited, 9 = diagnostic)
 //! - `length`: 4-byte big-endian integer indicating the length of the UTF-8 output
 //! - `output`: Variable-length UTF-8 encoded output or error message
 //!
+//! A request stopped by one of the daemon's resource limits is answered with
+//! status 4 and the output `<limit>: <message>`, where `<limit>` is one of
+//! `instructions`, `output_bytes`, `call_depth` or `memory` (see
+//! [`crate::daemon_limits`]).
+//!
+//! A daemon whose request queue is full answers with status 5 instead of
+//! running the request; the client may retry later or run the code itself.
+//!
+//! A program that stops itself with `exit(n)` is answered with status 8 and
+//! the output `n`. As with an error, any buffered print output is dropped;
+//! a streamed request has already received it.
+//!
+//! ## Compression
+//! ```text
+//! [u32 0xFFFFFFF0][u32 algorithm (big-endian)]
+//! [u32 0xFFFFFFEF][u32 length (big-endian)][u32 algorithm][block]
+//! [u8 7][u32 length (big-endian)][u32 algorithm][block]
+//! ```
+//! - A [`COMPRESSION_MARKER`] frame asks the daemon to compress responses
+//!   on the connection with a [`Compression`] algorithm (1 for LZ4). It is
+//!   answered with a success response naming the algorithm, or an error if
+//!   the daemon does not know it, and the connection goes on uncompressed.
+//! - A [`COMPRESSED_MARKER`] frame carries one request, prefixes included,
+//!   compressed. Daemons accept it whether or not compression was asked
+//!   for; clients send it for requests over [`COMPRESSION_THRESHOLD`]
+//!   bytes.
+//! - Once compression is on, responses whose output is over
+//!   [`COMPRESSION_THRESHOLD`] bytes come compressed, with status 7. The
+//!   block expands to the response as it would otherwise have been sent.
+//!   Output chunks and profiles are never compressed.
+//! - A block is an LZ4 block prefixed with its uncompressed length (u32
+//!   little-endian), which may be at most [`MAX_DECOMPRESSED_SIZE`].
+//!
+//! ## Streamed Requests
+//! ```text
+//! [u32 0xFFFFFFFB][u32 length (big-endian)][UTF-8 code]
+//! ```
+//! - A request prefixed with [`STREAM_MARKER`] asks for print output as it is
+//!   produced. Each print is answered with an output chunk (status 2) as soon
+//!   as it executes, and the request ends with the usual success or error
+//!   response. A successful final response carries only the expression
+//!   result, since the prints have already been sent. Daemons answer
+//!   unprefixed requests in a single response as before.
+//!
+//! ## Profiled Requests
+//! ```text
+//! [u32 0xFFFFFFF1][request]
+//! ```
+//! - A request prefixed with [`PROFILE_MARKER`] asks how long each stage of
+//!   the pipeline took. The daemon compiles it through its cache as usual,
+//!   so a cache hit shows as no lexing or parsing and a compile stage that
+//!   is only the lookup. Before the final response it sends a profile
+//!   response (status 6) whose output is
+//!   [`crate::profiling::PipelineProfile::encode`] followed by a line
+//!   `cache_hit true` or `cache_hit false`. Requests in a session compile
+//!   without the cache and are not profiled. The prefix comes after any
+//!   timeout prefix, before the stream prefix.
+//!
+//! ## Script Arguments
+//! ```text
+//! [u32 0xFFFFFFEC][u32 length (big-endian)][u32 length][UTF-8 arg]...[request]
+//! ```
+//! - A request prefixed with [`ARGS_MARKER`] runs with the script arguments
+//!   that follow it, each length-prefixed, read by the program with the
+//!   `argc()` and `argv(i)` builtins. The first length covers them all and
+//!   counts against the daemon's request size limit. Requests without it
+//!   run with no arguments. The prefix comes last, right before the code.
+//!
+//! ## Uncached Requests
+//! ```text
+//! [u32 0xFFFFFFEB][request]
+//! ```
+//! - A request prefixed with [`NO_CACHE_MARKER`] is compiled afresh instead
+//!   of being looked up in the daemon's cache, and is not added to it, so
+//!   the cache neither serves nor records it. A profiled one reports
+//!   `cache_hit false`. The prefix comes after any profile prefix, before
+//!   the stream prefix.
+//!
+//! ## Diagnostics
+//! ```text
+//! [u32 0xFFFFFFEA][request]
+//! ```
+//! - A request prefixed with [`DIAGNOSTIC_MARKER`] wants its failure as
+//!   data. If the program fails with an error, rather than timing out,
+//!   hitting a resource limit or calling `exit(n)`, the final response has
+//!   status 9 and its output is the error as a JSON
+//!   [`crate::diagnostic::Diagnostic`] instead of its message. The prefix
+//!   comes after any no-cache prefix, before the stream prefix.
+//!
+//! ## Cancel Frame
+//! ```text
+//! [u32 0xFFFFFFFF]
+//! ```
+//! - A bare length prefix of [`CANCEL_MARKER`], sent by the client while it
+//!   waits for a response, asks the daemon to abort the running request. The
+//!   request still gets a (error) response. A cancel frame that arrives when
+//!   nothing is running is ignored.
+//!
+//! ## Request Ids
+//! ```text
+//! [u32 0xFFFFFFF3][u32 id (big-endian)][request]
+//! [u32 0xFFFFFFF2][u32 id (big-endian)]
+//! ```
+//! - A request prefixed with [`REQUEST_ID_MARKER`] carries an id of the
+//!   client's choosing, unique among its unanswered requests on the
+//!   connection. The prefix comes first, before any other.
+//! - A [`CANCEL_REQUEST_MARKER`] frame cancels the request with that id on
+//!   the same connection, whether it is executing or still waiting behind
+//!   others; one that has not started is answered as cancelled without
+//!   running. Responses stay in request order and carry no id. Ids that
+//!   match no unanswered request are ignored.
+//!
+//! ## Metrics Frame
+//! ```text
+//! [u32 0xFFFFFFFE]
+//! ```
+//! - A bare length prefix of [`METRICS_MARKER`] asks for the daemon's
+//!   metrics instead of running code. The answer is a success response whose
+//!   output is Prometheus text (see [`crate::metrics`]), sent in turn with
+//!   the responses to any requests before it.
+//!
+//! ## Cache List Frame
+//! ```text
+//! [u32 0xFFFFFFFD]
+//! ```
+//! - A bare length prefix of [`CACHE_LIST_MARKER`] asks which programs the
+//!   daemon has cached. It is answered like a metrics frame, with the table
+//!   of [`crate::cache::format_entries`] as the output.
+//!
+//! ## Cache Summary Frame
+//! ```text
+//! [u32 0xFFFFFFFC]
+//! ```
+//! - A bare length prefix of [`CACHE_SUMMARY_MARKER`] asks for the cache's
+//!   hit counts, the compile time it saved and its hottest programs. It is
+//!   answered like a metrics frame, with [`crate::cache::format_summary`] as
+//!   the output.
+//!
+//! ## Cache Stats Frame
+//! ```text
+//! [u32 0xFFFFFFED]
+//! ```
+//! - A bare length prefix of [`CACHE_STATS_MARKER`] asks for every counter
+//!   of the cache. It is answered like a metrics frame, with
+//!   [`crate::cache::format_stats`] as the output.
+//!
+//! ## Clear Cache Frame
+//! ```text
+//! [u32 0xFFFFFFEE]
+//! ```
+//! - A bare length prefix of [`CLEAR_CACHE_MARKER`] empties the daemon's
+//!   in-memory cache. It is answered like a metrics frame, with the number
+//!   of programs removed as the output. The disk tier is left alone: it is
+//!   shared with other processes, which can clear it themselves.
+//!
+//! ## Stats Frame
+//! ```text
+//! [u32 0xFFFFFFF7]
+//! ```
+//! - A bare length prefix of [`STATS_MARKER`] asks for a snapshot of the
+//!   daemon: uptime, request and error counts, latency percentiles, cache
+//!   counts and live sessions. It is answered like a metrics frame, with
+//!   [`crate::metrics::DaemonStats::encode`] as the output.
+//!
+//! ## Ping Frame
+//! ```text
+//! [u32 0xFFFFFFF6]
+//! ```
+//! - A bare length prefix of [`PING_MARKER`] checks that the daemon is
+//!   serving. It is answered with a success response whose output is
+//!   `pong`. A daemon that requires a token answers pings before the auth
+//!   frame too, without authenticating the connection, so health checks
+//!   need no secret.
+//!
+//! ## Batch Frame
+//! ```text
+//! [u32 0xFFFFFFF5][u32 length (big-endian)][request][request]...
+//! ```
+//! - A [`BATCH_MARKER`] followed by the byte length of the requests after
+//!   it, each encoded as on its own, prefixes included. The daemon runs them
+//!   one after another, each as if it had been sent alone, and answers each
+//!   in order: an error in one does not stop the rest. The length counts
+//!   against the daemon's request size limit as a whole.
+//!
+//! ## Request Timeouts
+//! ```text
+//! [u32 0xFFFFFFF8][u32 timeout (milliseconds, big-endian)][request]
+//! ```
+//! - A request prefixed with [`TIMEOUT_MARKER`] is aborted once it has run
+//!   for the given time, and answered with a timed-out response (status 3)
+//!   instead of its result. The request may itself be streamed.
+//!
+//! ## Request Priority
+//! ```text
+//! [u32 0xFFFFFFF4][u32 class (big-endian)][request]
+//! ```
+//! - A request prefixed with [`PRIORITY_MARKER`] declares its [`Priority`]:
+//!   0 for interactive, 1 for batch. Requests without it are interactive. A
+//!   daemon that queues requests runs waiting interactive ones first. The
+//!   prefix comes before any timeout prefix.
+//!
+//! ## Session Frame
+//! ```text
+//! [u32 0xFFFFFFFA][u32 length (big-endian)][UTF-8 session id]
+//! ```
+//! - A [`SESSION_MARKER`] followed by an id attaches the connection to that
+//!   session (see [`crate::daemon_session`]), creating it if needed. The
+//!   requests after it on the connection share the session's globals and
+//!   functions. It is answered with a success response echoing the id, or an
+//!   error if the session cannot be created. An empty id detaches the
+//!   connection again.
+//!
+//! ## Auth Frame
+//! ```text
+//! [u32 0xFFFFFFF9][u32 length (big-endian)][UTF-8 token]
+//! ```
+//! - A [`AUTH_MARKER`] followed by a shared-secret token. A daemon configured
+//!   with a token (see [`crate::daemon::AUTH_TOKEN_ENV`]) requires this as
+//!   the first frame of every connection: it is answered with an empty
+//!   success response if the token matches, and otherwise, like any other
+//!   first frame, with an error response before the daemon closes the
+//!   connection. A daemon without a token accepts any auth frame.
+//!
 //! # Examples
 //!
 //! ```
@@ -45,7 +270,98 @@
 //! assert_eq!(bytes_consumed, encoded.len());
 //! ```
 
+use crate::diagnostic::Diagnostic;
 use std::fmt;
+use std::time::Duration;
+
+/// Length prefix reserved for the cancel frame
+///
+/// No request can be this long, so it never collides with a real request.
+pub const CANCEL_MARKER: u32 = u32::MAX;
+
+/// Length prefix reserved for the metrics frame
+pub const METRICS_MARKER: u32 = u32::MAX - 1;
+
+/// Length prefix reserved for the cache list frame
+pub const CACHE_LIST_MARKER: u32 = u32::MAX - 2;
+
+/// Length prefix reserved for the cache summary frame
+pub const CACHE_SUMMARY_MARKER: u32 = u32::MAX - 3;
+
+/// Length prefix that marks the request after it as streamed
+pub const STREAM_MARKER: u32 = u32::MAX - 4;
+
+/// Length prefix that starts a session frame
+pub const SESSION_MARKER: u32 = u32::MAX - 5;
+
+/// Length prefix that starts an auth frame
+pub const AUTH_MARKER: u32 = u32::MAX - 6;
+
+/// Length prefix that gives the request after it a timeout
+pub const TIMEOUT_MARKER: u32 = u32::MAX - 7;
+
+/// Length prefix reserved for the stats frame
+pub const STATS_MARKER: u32 = u32::MAX - 8;
+
+/// Length prefix reserved for the ping frame
+pub const PING_MARKER: u32 = u32::MAX - 9;
+
+/// Marker starting a batch frame
+pub const BATCH_MARKER: u32 = u32::MAX - 10;
+
+/// Prefix marking a request with its priority class
+pub const PRIORITY_MARKER: u32 = u32::MAX - 11;
+
+/// Prefix marking a request with its id
+pub const REQUEST_ID_MARKER: u32 = u32::MAX - 12;
+
+/// Marker starting the frame that cancels a request by id
+pub const CANCEL_REQUEST_MARKER: u32 = u32::MAX - 13;
+
+/// Prefix asking for the request's pipeline profile
+pub const PROFILE_MARKER: u32 = u32::MAX - 14;
+
+/// Marker starting the frame that turns on response compression
+pub const COMPRESSION_MARKER: u32 = u32::MAX - 15;
+
+/// Marker starting a compressed request frame
+pub const COMPRESSED_MARKER: u32 = u32::MAX - 16;
+
+/// Marker of the frame that clears the daemon's cache
+pub const CLEAR_CACHE_MARKER: u32 = u32::MAX - 17;
+
+/// Marker of the frame that asks for the daemon's cache counters
+pub const CACHE_STATS_MARKER: u32 = u32::MAX - 18;
+
+/// Prefix carrying the request's script arguments
+pub const ARGS_MARKER: u32 = u32::MAX - 19;
+
+/// Prefix asking for the request to bypass the compilation cache
+pub const NO_CACHE_MARKER: u32 = u32::MAX - 20;
+
+/// Prefix asking for the request's failure as a diagnostic
+pub const DIAGNOSTIC_MARKER: u32 = u32::MAX - 21;
+
+/// Markers that may lead a request, each at most once
+pub(crate) const REQUEST_PREFIXES: [u32; 8] = [
+    STREAM_MARKER,
+    TIMEOUT_MARKER,
+    PRIORITY_MARKER,
+    REQUEST_ID_MARKER,
+    PROFILE_MARKER,
+    ARGS_MARKER,
+    NO_CACHE_MARKER,
+    DIAGNOSTIC_MARKER,
+];
+
+/// Payloads up to this many bytes are sent uncompressed
+pub const COMPRESSION_THRESHOLD: usize = 4096;
+
+/// Most bytes a compressed block may expand to
+pub const MAX_DECOMPRESSED_SIZE: usize = 16 * 1024 * 1024;
+
+/// Status byte of a compressed response frame
+const COMPRESSED_STATUS: u8 = 7;
 
 /// Protocol error types
 #[derive(Debug, Clone, PartialEq)]
@@ -56,6 +372,14 @@ pub enum ProtocolError {
     IncompleteMessage(String),
     /// Invalid status code
     InvalidStatus(u8),
+    /// Invalid priority class
+    InvalidPriority(u32),
+    /// Unknown compression algorithm
+    InvalidCompression(u32),
+    /// A compressed block that does not expand to a message
+    InvalidCompressed(String),
+    /// A request prefix, by its marker, that appears more than once
+    RepeatedPrefix(u32),
 }
 
 impl fmt::Display for ProtocolError {
@@ -64,22 +388,237 @@ impl fmt::Display for ProtocolError {
             ProtocolError::InvalidUtf8(msg) => write!(f, "Invalid UTF-8: {}", msg),
             ProtocolError::IncompleteMessage(msg) => write!(f, "Incomplete message: {}", msg),
             ProtocolError::InvalidStatus(status) => write!(f, "Invalid status code: {}", status),
+            ProtocolError::InvalidPriority(class) => {
+                write!(f, "Invalid priority class: {}", class)
+            }
+            ProtocolError::InvalidCompression(algorithm) => {
+                write!(f, "Invalid compression algorithm: {}", algorithm)
+            }
+            ProtocolError::InvalidCompressed(msg) => write!(f, "Invalid compressed block: {}", msg),
+            ProtocolError::RepeatedPrefix(marker) => {
+                write!(f, "Repeated request prefix: {:#010x}", marker)
+            }
         }
     }
 }
 
 impl std::error::Error for ProtocolError {}
 
+/// How urgently a request wants an answer
+///
+/// When requests have to wait for the daemon, interactive ones go ahead of
+/// batch ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Priority {
+    /// Someone is waiting on the answer, as for the CLI
+    #[default]
+    Interactive = 0,
+    /// Bulk work that can wait its turn
+    Batch = 1,
+}
+
+impl Priority {
+    /// The priority with this wire value
+    pub fn from_u32(class: u32) -> Result<Self, ProtocolError> {
+        match class {
+            0 => Ok(Priority::Interactive),
+            1 => Ok(Priority::Batch),
+            other => Err(ProtocolError::InvalidPriority(other)),
+        }
+    }
+
+    /// Name used in logs
+    pub fn name(self) -> &'static str {
+        match self {
+            Priority::Interactive => "interactive",
+            Priority::Batch => "batch",
+        }
+    }
+}
+
+/// Algorithm for compressing large payloads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// LZ4 block format: fast, with a modest ratio
+    Lz4 = 1,
+}
+
+impl Compression {
+    /// The algorithm with this wire value
+    pub fn from_u32(algorithm: u32) -> Result<Self, ProtocolError> {
+        match algorithm {
+            1 => Ok(Compression::Lz4),
+            other => Err(ProtocolError::InvalidCompression(other)),
+        }
+    }
+
+    /// The algorithm called `name`, as in [`Compression::name`]
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "lz4" => Some(Compression::Lz4),
+            _ => None,
+        }
+    }
+
+    /// Name used in configuration and logs
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::Lz4 => "lz4",
+        }
+    }
+
+    /// Compress `bytes` into [u32 algorithm][block]
+    fn compress(self, bytes: &[u8]) -> Vec<u8> {
+        let mut buffer = (self as u32).to_be_bytes().to_vec();
+        match self {
+            Compression::Lz4 => buffer.extend(lz4_flex::block::compress_prepend_size(bytes)),
+        }
+        buffer
+    }
+
+    /// Expand [u32 algorithm][block] back into the bytes it was made from
+    fn decompress(bytes: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+        if bytes.len() < 8 {
+            return Err(ProtocolError::IncompleteMessage(format!(
+                "Expected at least 8 bytes of compressed block, got {}",
+                bytes.len()
+            )));
+        }
+        let algorithm =
+            Self::from_u32(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))?;
+        let size = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as usize;
+        if size > MAX_DECOMPRESSED_SIZE {
+            return Err(ProtocolError::InvalidCompressed(format!(
+                "expands to {} bytes, more than the maximum of {}",
+                size, MAX_DECOMPRESSED_SIZE
+            )));
+        }
+        match algorithm {
+            Compression::Lz4 => lz4_flex::block::decompress(&bytes[8..], size)
+                .map_err(|e| ProtocolError::InvalidCompressed(e.to_string())),
+        }
+    }
+}
+
 /// A daemon request containing Python code to execute
 #[derive(Debug, Clone, PartialEq)]
 pub struct DaemonRequest {
     code: String,
+    streaming: bool,
+    timeout: Option<Duration>,
+    priority: Priority,
+    id: Option<u32>,
+    profiled: bool,
+    args: Vec<String>,
+    uncached: bool,
+    diagnostics: bool,
 }
 
 impl DaemonRequest {
     /// Create a new daemon request with the given Python code
     pub fn new(code: impl Into<String>) -> Self {
-        Self { code: code.into() }
+        Self {
+            code: code.into(),
+            streaming: false,
+            timeout: None,
+            priority: Priority::Interactive,
+            id: None,
+            profiled: false,
+            args: Vec::new(),
+            uncached: false,
+            diagnostics: false,
+        }
+    }
+
+    /// Create a request whose print output is streamed back in chunks
+    pub fn streaming(code: impl Into<String>) -> Self {
+        Self {
+            streaming: true,
+            ..Self::new(code)
+        }
+    }
+
+    /// Abort the request once it has run for `timeout`
+    ///
+    /// The timeout travels in whole milliseconds, up to `u32::MAX`.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        let millis = timeout.as_millis().min(u32::MAX as u128) as u64;
+        self.timeout = Some(Duration::from_millis(millis));
+        self
+    }
+
+    /// The time the request may run for, if limited
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Declare how urgently the request wants an answer
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// How urgently the request wants an answer
+    pub fn priority(&self) -> Priority {
+        self.priority
+    }
+
+    /// Give the request an id, by which it can be cancelled
+    pub fn with_id(mut self, id: u32) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    /// The id the request can be cancelled by, if it has one
+    pub fn id(&self) -> Option<u32> {
+        self.id
+    }
+
+    /// Ask for the time each pipeline stage takes, answered before the
+    /// final response
+    pub fn with_profile(mut self) -> Self {
+        self.profiled = true;
+        self
+    }
+
+    /// Check if the pipeline profile is wanted
+    pub fn is_profiled(&self) -> bool {
+        self.profiled
+    }
+
+    /// Run the code with script arguments
+    pub fn with_args(mut self, args: Vec<String>) -> Self {
+        self.args = args;
+        self
+    }
+
+    /// The script arguments the code runs with
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    /// Compile the code afresh, neither looking it up in the daemon's cache
+    /// nor adding it
+    pub fn without_cache(mut self) -> Self {
+        self.uncached = true;
+        self
+    }
+
+    /// Check if the code may be compiled through the daemon's cache
+    pub fn uses_cache(&self) -> bool {
+        !self.uncached
+    }
+
+    /// Ask for a failure as a [`crate::diagnostic::Diagnostic`] rather than
+    /// an error message
+    pub fn with_diagnostics(mut self) -> Self {
+        self.diagnostics = true;
+        self
+    }
+
+    /// Check if a failure should be answered with a diagnostic
+    pub fn wants_diagnostics(&self) -> bool {
+        self.diagnostics
     }
 
     /// Get the Python code from this request
@@ -87,25 +626,290 @@ impl DaemonRequest {
         &self.code
     }
 
+    /// Check if print output should be streamed back as it is produced
+    pub fn is_streaming(&self) -> bool {
+        self.streaming
+    }
+
     /// Encode the request as a binary message
     ///
-    /// Format: [u32 length][UTF-8 code]
+    /// Format: [u32 length][UTF-8 code], prefixed with [u32 ARGS_MARKER][u32
+    /// length][u32 length][UTF-8 arg]... if it has script arguments, before
+    /// that with [u32 STREAM_MARKER] for a streamed request, before that
+    /// with [u32 DIAGNOSTIC_MARKER] for one wanting a diagnostic, before that
+    /// with [u32 NO_CACHE_MARKER] for one bypassing the cache, before that
+    /// with [u32 PROFILE_MARKER] for a
+    /// profiled one, before that with [u32 TIMEOUT_MARKER][u32
+    /// milliseconds] if the request has a timeout, before that with [u32
+    /// PRIORITY_MARKER][u32 class] if it is not interactive, and first with
+    /// [u32 REQUEST_ID_MARKER][u32 id] if it has an id
     pub fn encode(&self) -> Vec<u8> {
         let code_bytes = self.code.as_bytes();
         let length = code_bytes.len() as u32;
 
-        let mut buffer = Vec::with_capacity(4 + code_bytes.len());
+        let mut buffer = Vec::with_capacity(32 + code_bytes.len());
+        if let Some(id) = self.id {
+            buffer.extend_from_slice(&REQUEST_ID_MARKER.to_be_bytes());
+            buffer.extend_from_slice(&id.to_be_bytes());
+        }
+        if self.priority != Priority::Interactive {
+            buffer.extend_from_slice(&PRIORITY_MARKER.to_be_bytes());
+            buffer.extend_from_slice(&(self.priority as u32).to_be_bytes());
+        }
+        if let Some(timeout) = self.timeout {
+            buffer.extend_from_slice(&TIMEOUT_MARKER.to_be_bytes());
+            buffer.extend_from_slice(&(timeout.as_millis() as u32).to_be_bytes());
+        }
+        if self.profiled {
+            buffer.extend_from_slice(&PROFILE_MARKER.to_be_bytes());
+        }
+        if self.uncached {
+            buffer.extend_from_slice(&NO_CACHE_MARKER.to_be_bytes());
+        }
+        if self.diagnostics {
+            buffer.extend_from_slice(&DIAGNOSTIC_MARKER.to_be_bytes());
+        }
+        if self.streaming {
+            buffer.extend_from_slice(&STREAM_MARKER.to_be_bytes());
+        }
+        if !self.args.is_empty() {
+            let block: usize = self.args.iter().map(|arg| 4 + arg.len()).sum();
+            buffer.extend_from_slice(&ARGS_MARKER.to_be_bytes());
+            buffer.extend_from_slice(&(block as u32).to_be_bytes());
+            for arg in &self.args {
+                buffer.extend_from_slice(&(arg.len() as u32).to_be_bytes());
+                buffer.extend_from_slice(arg.as_bytes());
+            }
+        }
         buffer.extend_from_slice(&length.to_be_bytes());
         buffer.extend_from_slice(code_bytes);
 
         buffer
     }
 
+    /// Encode the frame that cancels the request currently running
+    ///
+    /// Format: [u32 CANCEL_MARKER]
+    pub fn encode_cancel() -> [u8; 4] {
+        CANCEL_MARKER.to_be_bytes()
+    }
+
+    /// Encode the frame that cancels the request with `id`
+    ///
+    /// Format: [u32 CANCEL_REQUEST_MARKER][u32 id]
+    pub fn encode_cancel_request(id: u32) -> [u8; 8] {
+        let mut frame = [0u8; 8];
+        frame[..4].copy_from_slice(&CANCEL_REQUEST_MARKER.to_be_bytes());
+        frame[4..].copy_from_slice(&id.to_be_bytes());
+        frame
+    }
+
+    /// Encode the frame that asks for responses compressed with
+    /// `compression`
+    ///
+    /// Format: [u32 COMPRESSION_MARKER][u32 algorithm]
+    pub fn encode_compression(compression: Compression) -> [u8; 8] {
+        let mut frame = [0u8; 8];
+        frame[..4].copy_from_slice(&COMPRESSION_MARKER.to_be_bytes());
+        frame[4..].copy_from_slice(&(compression as u32).to_be_bytes());
+        frame
+    }
+
+    /// Encode the request compressed with `compression`
+    ///
+    /// Format: [u32 COMPRESSED_MARKER][u32 length][u32 algorithm][block]
+    pub fn encode_compressed(&self, compression: Compression) -> Vec<u8> {
+        let body = compression.compress(&self.encode());
+        let mut buffer = Vec::with_capacity(8 + body.len());
+        buffer.extend_from_slice(&COMPRESSED_MARKER.to_be_bytes());
+        buffer.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        buffer.extend_from_slice(&body);
+        buffer
+    }
+
+    /// Decode the request in the body of a compressed request frame
+    pub fn decode_compressed(body: &[u8]) -> Result<Self, ProtocolError> {
+        let bytes = Compression::decompress(body)?;
+        let (request, consumed) = Self::decode(&bytes)?;
+        if consumed != bytes.len() {
+            return Err(ProtocolError::InvalidCompressed(format!(
+                "{} bytes after the request",
+                bytes.len() - consumed
+            )));
+        }
+        Ok(request)
+    }
+
+    /// Encode the frame that asks for the daemon's metrics
+    ///
+    /// Format: [u32 METRICS_MARKER]
+    pub fn encode_metrics() -> [u8; 4] {
+        METRICS_MARKER.to_be_bytes()
+    }
+
+    /// Encode the frame that asks for the daemon's cached programs
+    ///
+    /// Format: [u32 CACHE_LIST_MARKER]
+    pub fn encode_cache_list() -> [u8; 4] {
+        CACHE_LIST_MARKER.to_be_bytes()
+    }
+
+    /// Encode the frame that asks for a summary of the daemon's cache
+    ///
+    /// Format: [u32 CACHE_SUMMARY_MARKER]
+    pub fn encode_cache_summary() -> [u8; 4] {
+        CACHE_SUMMARY_MARKER.to_be_bytes()
+    }
+
+    /// Encode the frame that asks for the daemon's cache counters
+    ///
+    /// Format: [u32 CACHE_STATS_MARKER]
+    pub fn encode_cache_stats() -> [u8; 4] {
+        CACHE_STATS_MARKER.to_be_bytes()
+    }
+
+    /// Encode the frame that clears the daemon's cache
+    ///
+    /// Format: [u32 CLEAR_CACHE_MARKER]
+    pub fn encode_clear_cache() -> [u8; 4] {
+        CLEAR_CACHE_MARKER.to_be_bytes()
+    }
+
+    /// Encode the frame that asks for the daemon's stats
+    ///
+    /// Format: [u32 STATS_MARKER]
+    pub fn encode_stats() -> [u8; 4] {
+        STATS_MARKER.to_be_bytes()
+    }
+
+    /// Encode the frame that checks the daemon is serving
+    ///
+    /// Format: [u32 PING_MARKER]
+    pub fn encode_ping() -> [u8; 4] {
+        PING_MARKER.to_be_bytes()
+    }
+
+    /// Encode the frame that attaches the connection to session `id`
+    ///
+    /// Format: [u32 SESSION_MARKER][u32 length][UTF-8 id]
+    pub fn encode_session(id: &str) -> Vec<u8> {
+        Self::encode_marked(SESSION_MARKER, id)
+    }
+
+    /// Encode the frame that presents the shared-secret `token`
+    ///
+    /// Format: [u32 AUTH_MARKER][u32 length][UTF-8 token]
+    pub fn encode_auth(token: &str) -> Vec<u8> {
+        Self::encode_marked(AUTH_MARKER, token)
+    }
+
+    /// Encode a batch frame running `requests` in one round trip
+    ///
+    /// Format: [u32 BATCH_MARKER][u32 length][request]...
+    pub fn encode_batch(requests: &[DaemonRequest]) -> Vec<u8> {
+        let mut body = Vec::new();
+        for request in requests {
+            body.extend_from_slice(&request.encode());
+        }
+        let mut buffer = Vec::with_capacity(8 + body.len());
+        buffer.extend_from_slice(&BATCH_MARKER.to_be_bytes());
+        buffer.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        buffer.extend_from_slice(&body);
+        buffer
+    }
+
+    /// Decode the requests in the body of a batch frame
+    ///
+    /// The body must hold whole requests and nothing else.
+    pub fn decode_batch(mut body: &[u8]) -> Result<Vec<Self>, ProtocolError> {
+        let mut requests = Vec::new();
+        while !body.is_empty() {
+            let (request, consumed) = Self::decode(body)?;
+            requests.push(request);
+            body = &body[consumed..];
+        }
+        Ok(requests)
+    }
+
+    /// Encode `text` as the body of a frame starting with `marker`
+    fn encode_marked(marker: u32, text: &str) -> Vec<u8> {
+        let bytes = text.as_bytes();
+        let mut buffer = Vec::with_capacity(8 + bytes.len());
+        buffer.extend_from_slice(&marker.to_be_bytes());
+        buffer.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+        buffer.extend_from_slice(bytes);
+        buffer
+    }
+
     /// Decode a binary message into a daemon request
     ///
     /// Returns `(Self, bytes_consumed)` tuple on success, `ProtocolError` if the message is invalid or incomplete.
     /// The `bytes_consumed` value indicates how many bytes were read from the input slice.
+    /// A prefix that appears twice is a [`ProtocolError::RepeatedPrefix`].
     pub fn decode(bytes: &[u8]) -> Result<(Self, usize), ProtocolError> {
+        let mut request = Self::new(String::new());
+        let mut seen = Vec::new();
+        let mut offset = 0;
+        // Prefixes come in any order, each at most once, before the length
+        while let Some(word) = bytes.get(offset..offset + 4) {
+            let marker = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+            if !REQUEST_PREFIXES.contains(&marker) {
+                break;
+            }
+            if seen.contains(&marker) {
+                return Err(ProtocolError::RepeatedPrefix(marker));
+            }
+            seen.push(marker);
+            let rest = &bytes[offset..];
+            offset += match marker {
+                STREAM_MARKER => {
+                    request.streaming = true;
+                    4
+                }
+                PROFILE_MARKER => {
+                    request.profiled = true;
+                    4
+                }
+                NO_CACHE_MARKER => {
+                    request.uncached = true;
+                    4
+                }
+                DIAGNOSTIC_MARKER => {
+                    request.diagnostics = true;
+                    4
+                }
+                ARGS_MARKER => {
+                    let block = Self::decode_prefix_value(rest, "args")? as usize;
+                    request.args = rest
+                        .get(8..8 + block)
+                        .ok_or_else(|| {
+                            ProtocolError::IncompleteMessage(format!(
+                                "Expected {} bytes of arguments, got {}",
+                                block,
+                                rest.len() - 8
+                            ))
+                        })
+                        .and_then(Self::decode_args)?;
+                    8 + block
+                }
+                TIMEOUT_MARKER => {
+                    let millis = Self::decode_prefix_value(rest, "timeout")?;
+                    request.timeout = Some(Duration::from_millis(millis.into()));
+                    8
+                }
+                PRIORITY_MARKER => {
+                    request.priority =
+                        Priority::from_u32(Self::decode_prefix_value(rest, "priority")?)?;
+                    8
+                }
+                _ => {
+                    request.id = Some(Self::decode_prefix_value(rest, "id")?);
+                    8
+                }
+            };
+        }
+        let bytes = &bytes[offset..];
+
         // Check we have at least the length prefix
         if bytes.len() < 4 {
             return Err(ProtocolError::IncompleteMessage(format!(
@@ -140,7 +944,39 @@ impl DaemonRequest {
             .map_err(|e| ProtocolError::InvalidUtf8(e.to_string()))?
             .to_string();
 
-        Ok((Self { code }, total_size))
+        request.code = code;
+        Ok((request, offset + total_size))
+    }
+
+    /// The length-prefixed arguments making up an args prefix block
+    fn decode_args(mut block: &[u8]) -> Result<Vec<String>, ProtocolError> {
+        let mut args = Vec::new();
+        while !block.is_empty() {
+            let arg = block
+                .get(..4)
+                .map(|length| u32::from_be_bytes([length[0], length[1], length[2], length[3]]))
+                .and_then(|length| block.get(4..4 + length as usize))
+                .ok_or_else(|| {
+                    ProtocolError::IncompleteMessage("Truncated argument".to_string())
+                })?;
+            let text =
+                std::str::from_utf8(arg).map_err(|e| ProtocolError::InvalidUtf8(e.to_string()))?;
+            args.push(text.to_string());
+            block = &block[4 + arg.len()..];
+        }
+        Ok(args)
+    }
+
+    /// The value word of a prefix starting `bytes`
+    fn decode_prefix_value(bytes: &[u8], prefix: &str) -> Result<u32, ProtocolError> {
+        if bytes.len() < 8 {
+            return Err(ProtocolError::IncompleteMessage(format!(
+                "Expected 8 bytes for {} prefix, got {}",
+                prefix,
+                bytes.len()
+            )));
+        }
+        Ok(u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]))
     }
 }
 
@@ -158,6 +994,20 @@ pub enum ResponseStatus {
     Success = 0,
     /// Execution failed with an error
     Error = 1,
+    /// Print output of a streamed request; more frames follow
+    Output = 2,
+    /// Execution was aborted when its timeout ran out
+    Timeout = 3,
+    /// Execution was stopped by a resource limit
+    LimitExceeded = 4,
+    /// The daemon's queue was full; the request did not run
+    Busy = 5,
+    /// Pipeline profile of a profiled request; the final response follows
+    Profile = 6,
+    /// The program called `exit(n)`; the output is the exit code
+    Exit = 8,
+    /// Execution failed; the output is the error as a JSON diagnostic
+    Diagnostic = 9,
 }
 
 impl DaemonResponse {
@@ -177,14 +1027,126 @@ impl DaemonResponse {
         }
     }
 
+    /// Create a chunk of print output for a streamed request
+    pub fn chunk(output: impl Into<String>) -> Self {
+        Self {
+            status: ResponseStatus::Output,
+            output: output.into(),
+        }
+    }
+
+    /// Create a response for a request that ran out of time
+    pub fn timeout(message: impl Into<String>) -> Self {
+        Self {
+            status: ResponseStatus::Timeout,
+            output: message.into(),
+        }
+    }
+
+    /// Create a response for a request stopped by the resource limit named
+    /// `limit`
+    pub fn limit_exceeded(limit: &str, message: &str) -> Self {
+        Self {
+            status: ResponseStatus::LimitExceeded,
+            output: format!("{}: {}", limit, message),
+        }
+    }
+
+    /// Create a response for a request turned away because the daemon's
+    /// queue is full
+    pub fn busy(message: impl Into<String>) -> Self {
+        Self {
+            status: ResponseStatus::Busy,
+            output: message.into(),
+        }
+    }
+
+    /// Create the pipeline profile of a profiled request, its output as
+    /// described under "Profiled Requests" above
+    pub fn profile(profile: impl Into<String>) -> Self {
+        Self {
+            status: ResponseStatus::Profile,
+            output: profile.into(),
+        }
+    }
+
+    /// Create a response for a program that stopped itself with `exit(code)`
+    pub fn exit(code: i32) -> Self {
+        Self {
+            status: ResponseStatus::Exit,
+            output: code.to_string(),
+        }
+    }
+
+    /// Create an error response carrying `diagnostic`, for a request that
+    /// asked for one
+    pub fn failure(diagnostic: &Diagnostic) -> Self {
+        Self {
+            status: ResponseStatus::Diagnostic,
+            output: diagnostic.to_json(),
+        }
+    }
+
     /// Check if this response indicates success
     pub fn is_success(&self) -> bool {
         self.status == ResponseStatus::Success
     }
 
-    /// Check if this response indicates an error
+    /// Check if this response indicates an error, timeouts, exceeded
+    /// limits and a busy daemon included
     pub fn is_error(&self) -> bool {
-        self.status == ResponseStatus::Error
+        matches!(
+            self.status,
+            ResponseStatus::Error
+                | ResponseStatus::Timeout
+                | ResponseStatus::LimitExceeded
+                | ResponseStatus::Busy
+                | ResponseStatus::Diagnostic
+        )
+    }
+
+    /// Check if the daemon turned the request away without running it
+    pub fn is_busy(&self) -> bool {
+        self.status == ResponseStatus::Busy
+    }
+
+    /// The resource limit that stopped the request, and the error message
+    pub fn exceeded_limit(&self) -> Option<(&str, &str)> {
+        if self.status != ResponseStatus::LimitExceeded {
+            return None;
+        }
+        Some(self.output.split_once(": ").unwrap_or((&self.output, "")))
+    }
+
+    /// Check if the request was aborted because its timeout ran out
+    pub fn is_timeout(&self) -> bool {
+        self.status == ResponseStatus::Timeout
+    }
+
+    /// Check if this is a chunk of print output rather than a final response
+    pub fn is_chunk(&self) -> bool {
+        self.status == ResponseStatus::Output
+    }
+
+    /// The exit code, if the program stopped itself with `exit(n)`
+    pub fn exit_code(&self) -> Option<i32> {
+        if self.status != ResponseStatus::Exit {
+            return None;
+        }
+        self.output.parse().ok()
+    }
+
+    /// Check if this is a pipeline profile rather than a final response
+    pub fn is_profile(&self) -> bool {
+        self.status == ResponseStatus::Profile
+    }
+
+    /// The error, if this response carries it as a diagnostic
+    pub fn diagnostic(&self) -> Option<Diagnostic> {
+        if self.status != ResponseStatus::Diagnostic {
+            return None;
+        }
+        Diagnostic::from_json(&self.output)
     }
 
     /// Get the output or error message from this response
@@ -207,10 +1169,29 @@ impl DaemonResponse {
         buffer
     }
 
+    /// Encode the response, compressed with `compression` if its output is
+    /// over [`COMPRESSION_THRESHOLD`] bytes
+    ///
+    /// Format: [u8 7][u32 length][u32 algorithm][block] when compressed
+    pub fn encode_with(&self, compression: Option<Compression>) -> Vec<u8> {
+        let encoded = self.encode();
+        let compression = match compression {
+            Some(compression) if self.output.len() > COMPRESSION_THRESHOLD => compression,
+            _ => return encoded,
+        };
+        let body = compression.compress(&encoded);
+        let mut buffer = Vec::with_capacity(5 + body.len());
+        buffer.push(COMPRESSED_STATUS);
+        buffer.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        buffer.extend_from_slice(&body);
+        buffer
+    }
+
     /// Decode a binary message into a daemon response
     ///
     /// Returns `(Self, bytes_consumed)` tuple on success, `ProtocolError` if the message is invalid or incomplete.
     /// The `bytes_consumed` value indicates how many bytes were read from the input slice.
+    /// A compressed response is expanded.
     pub fn decode(bytes: &[u8]) -> Result<(Self, usize), ProtocolError> {
         // Check we have at least the status and length prefix
         if bytes.len() < 5 {
@@ -224,6 +1205,14 @@ impl DaemonResponse {
         let status = match bytes[0] {
             0 => ResponseStatus::Success,
             1 => ResponseStatus::Error,
+            2 => ResponseStatus::Output,
+            3 => ResponseStatus::Timeout,
+            4 => ResponseStatus::LimitExceeded,
+            5 => ResponseStatus::Busy,
+            6 => ResponseStatus::Profile,
+            8 => ResponseStatus::Exit,
+            9 => ResponseStatus::Diagnostic,
+            COMPRESSED_STATUS => return Self::decode_compressed(bytes),
             other => return Err(ProtocolError::InvalidStatus(other)),
         };
 
@@ -255,6 +1244,39 @@ impl DaemonResponse {
 
         Ok((Self { status, output }, total_size))
     }
+
+    /// Decode a compressed response frame, whose status byte is 7
+    fn decode_compressed(bytes: &[u8]) -> Result<(Self, usize), ProtocolError> {
+        let length = u32::from_be_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]) as usize;
+        let total_size = 5_usize.checked_add(length).ok_or_else(|| {
+            ProtocolError::IncompleteMessage(format!(
+                "Length overflow: u32 length {} would overflow usize when adding header",
+                length
+            ))
+        })?;
+        if bytes.len() < total_size {
+            return Err(ProtocolError::IncompleteMessage(format!(
+                "Expected {} bytes of compressed response, got {}",
+                length,
+                bytes.len() - 5
+            )));
+        }
+
+        let inner = Compression::decompress(&bytes[5..total_size])?;
+        if inner.first() == Some(&COMPRESSED_STATUS) {
+            return Err(ProtocolError::InvalidCompressed(
+                "compressed response inside a compressed response".to_string(),
+            ));
+        }
+        let (response, consumed) = Self::decode(&inner)?;
+        if consumed != inner.len() {
+            return Err(ProtocolError::InvalidCompressed(format!(
+                "{} bytes after the response",
+                inner.len() - consumed
+            )));
+        }
+        Ok((response, total_size))
+    }
 }
 
 #[cfg(test)]
@@ -308,6 +1330,346 @@ mod tests {
         assert_eq!(code, "2+3");
     }
 
+    #[test]
+    fn test_cancel_frame_format() {
+        let frame = DaemonRequest::encode_cancel();
+        assert_eq!(u32::from_be_bytes(frame), CANCEL_MARKER);
+        // A cancel frame is never mistaken for a complete request
+        assert!(DaemonRequest::decode(&frame).is_err());
+    }
+
+    #[test]
+    fn test_metrics_frame_format() {
+        let frame = DaemonRequest::encode_metrics();
+        assert_eq!(u32::from_be_bytes(frame), METRICS_MARKER);
+        assert_ne!(METRICS_MARKER, CANCEL_MARKER);
+        assert!(DaemonRequest::decode(&frame).is_err());
+
+        let frame = DaemonRequest::encode_cache_list();
+        assert_eq!(u32::from_be_bytes(frame), CACHE_LIST_MARKER);
+        assert!(DaemonRequest::decode(&frame).is_err());
+
+        let frame = DaemonRequest::encode_cache_summary();
+        assert_eq!(u32::from_be_bytes(frame), CACHE_SUMMARY_MARKER);
+        assert!(DaemonRequest::decode(&frame).is_err());
+
+        let frame = DaemonRequest::encode_cache_stats();
+        assert_eq!(u32::from_be_bytes(frame), CACHE_STATS_MARKER);
+        assert!(DaemonRequest::decode(&frame).is_err());
+
+        let frame = DaemonRequest::encode_clear_cache();
+        assert_eq!(u32::from_be_bytes(frame), CLEAR_CACHE_MARKER);
+        assert!(DaemonRequest::decode(&frame).is_err());
+
+        let frame = DaemonRequest::encode_stats();
+        assert_eq!(u32::from_be_bytes(frame), STATS_MARKER);
+        assert!(DaemonRequest::decode(&frame).is_err());
+
+        let frame = DaemonRequest::encode_ping();
+        assert_eq!(u32::from_be_bytes(frame), PING_MARKER);
+        assert!(DaemonRequest::decode(&frame).is_err());
+    }
+
+    #[test]
+    fn test_streaming_request_format() {
+        let request = DaemonRequest::streaming("print(1)");
+        let encoded = request.encode();
+        assert_eq!(encoded.len(), 4 + 4 + 8);
+        assert_eq!(
+            u32::from_be_bytes([encoded[0], encoded[1], encoded[2], encoded[3]]),
+            STREAM_MARKER
+        );
+
+        let (decoded, bytes_consumed) = DaemonRequest::decode(&encoded).unwrap();
+        assert!(decoded.is_streaming());
+        assert_eq!(decoded.code(), "print(1)");
+        assert_eq!(bytes_consumed, encoded.len());
+        assert!(!DaemonRequest::new("print(1)").is_streaming());
+
+        // The marker alone is not a request
+        assert!(DaemonRequest::decode(&STREAM_MARKER.to_be_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_request_args_format() {
+        let args = vec!["1".to_string(), String::new(), "é x".to_string()];
+        let request = DaemonRequest::streaming("argc()")
+            .with_args(args.clone())
+            .with_id(3);
+        let encoded = request.encode();
+        // id, stream, args marker and length, then each argument
+        assert_eq!(encoded.len(), 8 + 4 + 8 + (4 + 1) + 4 + (4 + 4) + 4 + 6);
+        assert_eq!(
+            u32::from_be_bytes([encoded[12], encoded[13], encoded[14], encoded[15]]),
+            ARGS_MARKER
+        );
+
+        let (decoded, bytes_consumed) = DaemonRequest::decode(&encoded).unwrap();
+        assert_eq!(decoded.args(), args.as_slice());
+        assert_eq!(decoded, request);
+        assert_eq!(bytes_consumed, encoded.len());
+        assert!(DaemonRequest::new("1").args().is_empty());
+
+        // An argument running past its block is rejected
+        let mut truncated = encoded.clone();
+        truncated[19] -= 1;
+        assert!(DaemonRequest::decode(&truncated).is_err());
+    }
+
+    #[test]
+    fn test_profiled_request_format() {
+        let request = DaemonRequest::streaming("1")
+            .with_profile()
+            .with_timeout(Duration::from_millis(10));
+        let encoded = request.encode();
+        assert_eq!(encoded.len(), 8 + 4 + 4 + 4 + 1);
+        assert_eq!(
+            u32::from_be_bytes([encoded[8], encoded[9], encoded[10], encoded[11]]),
+            PROFILE_MARKER
+        );
+
+        let (decoded, bytes_consumed) = DaemonRequest::decode(&encoded).unwrap();
+        assert_eq!(decoded, request);
+        assert!(decoded.is_profiled() && decoded.is_streaming());
+        assert_eq!(bytes_consumed, encoded.len());
+        assert!(!DaemonRequest::new("1").is_profiled());
+
+        let response = DaemonResponse::profile("lex_ns 1\n");
+        let (decoded, _) = DaemonResponse::decode(&response.encode()).unwrap();
+        assert!(decoded.is_profile());
+        assert!(!decoded.is_success() && !decoded.is_error() && !decoded.is_chunk());
+    }
+
+    #[test]
+    fn test_uncached_request_format() {
+        let request = DaemonRequest::streaming("1").without_cache().with_profile();
+        let encoded = request.encode();
+        assert_eq!(encoded.len(), 4 + 4 + 4 + 4 + 1);
+        assert_eq!(
+            u32::from_be_bytes([encoded[4], encoded[5], encoded[6], encoded[7]]),
+            NO_CACHE_MARKER
+        );
+
+        let (decoded, bytes_consumed) = DaemonRequest::decode(&encoded).unwrap();
+        assert_eq!(decoded, request);
+        assert!(!decoded.uses_cache() && decoded.is_profiled());
+        assert_eq!(bytes_consumed, encoded.len());
+        assert!(DaemonRequest::new("1").uses_cache());
+    }
+
+    #[test]
+    fn test_diagnostic_request_format() {
+        let request = DaemonRequest::streaming("1")
+            .with_diagnostics()
+            .without_cache();
+        let encoded = request.encode();
+        assert_eq!(encoded.len(), 4 + 4 + 4 + 4 + 1);
+        assert_eq!(
+            u32::from_be_bytes([encoded[4], encoded[5], encoded[6], encoded[7]]),
+            DIAGNOSTIC_MARKER
+        );
+
+        let (decoded, bytes_consumed) = DaemonRequest::decode(&encoded).unwrap();
+        assert_eq!(decoded, request);
+        assert!(decoded.wants_diagnostics() && decoded.is_streaming());
+        assert_eq!(bytes_consumed, encoded.len());
+        assert!(!DaemonRequest::new("1").wants_diagnostics());
+    }
+
+    #[test]
+    fn test_compressed_roundtrip() {
+        let code = "print(7)\n".repeat(1000);
+        let request = DaemonRequest::streaming(&code).with_priority(Priority::Batch);
+        let encoded = request.encode_compressed(Compression::Lz4);
+        assert!(encoded.len() < code.len() / 4);
+        assert_eq!(
+            u32::from_be_bytes([encoded[0], encoded[1], encoded[2], encoded[3]]),
+            COMPRESSED_MARKER
+        );
+        assert_eq!(
+            DaemonRequest::decode_compressed(&encoded[8..]).unwrap(),
+            request
+        );
+
+        let frame = DaemonRequest::encode_compression(Compression::Lz4);
+        assert_eq!(
+            u32::from_be_bytes([frame[0], frame[1], frame[2], frame[3]]),
+            COMPRESSION_MARKER
+        );
+        assert_eq!(Compression::from_u32(1).unwrap(), Compression::Lz4);
+        assert!(Compression::from_u32(9).is_err());
+        assert_eq!(Compression::from_name("lz4"), Some(Compression::Lz4));
+
+        let small = DaemonResponse::success("7\n");
+        assert_eq!(small.encode_with(Some(Compression::Lz4)), small.encode());
+        let large = DaemonResponse::success("7\n".repeat(5000));
+        let encoded = large.encode_with(Some(Compression::Lz4));
+        assert_eq!(encoded[0], COMPRESSED_STATUS);
+        assert!(encoded.len() < 1000);
+        let (decoded, bytes_consumed) = DaemonResponse::decode(&encoded).unwrap();
+        assert_eq!(decoded, large);
+        assert_eq!(bytes_consumed, encoded.len());
+        assert_eq!(large.encode_with(None), large.encode());
+    }
+
+    #[test]
+    fn test_compressed_block_size_limit() {
+        let mut body = (Compression::Lz4 as u32).to_be_bytes().to_vec();
+        body.extend_from_slice(&((MAX_DECOMPRESSED_SIZE as u32) + 1).to_le_bytes());
+        body.extend_from_slice(&[0; 16]);
+        assert!(matches!(
+            DaemonRequest::decode_compressed(&body),
+            Err(ProtocolError::InvalidCompressed(_))
+        ));
+    }
+
+    #[test]
+    fn test_timeout_request_format() {
+        let request = DaemonRequest::streaming("1").with_timeout(Duration::from_millis(1500));
+        let encoded = request.encode();
+        assert_eq!(encoded.len(), 8 + 4 + 4 + 1);
+        assert_eq!(
+            u32::from_be_bytes([encoded[0], encoded[1], encoded[2], encoded[3]]),
+            TIMEOUT_MARKER
+        );
+        assert_eq!(
+            u32::from_be_bytes([encoded[4], encoded[5], encoded[6], encoded[7]]),
+            1500
+        );
+
+        let (decoded, bytes_consumed) = DaemonRequest::decode(&encoded).unwrap();
+        assert_eq!(decoded, request);
+        assert_eq!(decoded.timeout(), Some(Duration::from_millis(1500)));
+        assert!(decoded.is_streaming());
+        assert_eq!(bytes_consumed, encoded.len());
+        assert_eq!(DaemonRequest::new("1").timeout(), None);
+        assert!(DaemonRequest::decode(&encoded[..6]).is_err());
+
+        let response = DaemonResponse::timeout("Request timed out after 1.5s");
+        let (decoded, _) = DaemonResponse::decode(&response.encode()).unwrap();
+        assert!(decoded.is_timeout());
+        assert!(decoded.is_error());
+        assert!(!decoded.is_success());
+    }
+
+    #[test]
+    fn test_request_id_format() {
+        let request = DaemonRequest::streaming("1")
+            .with_priority(Priority::Batch)
+            .with_id(7);
+        let encoded = request.encode();
+        assert_eq!(
+            u32::from_be_bytes([encoded[0], encoded[1], encoded[2], encoded[3]]),
+            REQUEST_ID_MARKER
+        );
+        assert_eq!(
+            u32::from_be_bytes([encoded[4], encoded[5], encoded[6], encoded[7]]),
+            7
+        );
+        let (decoded, bytes_consumed) = DaemonRequest::decode(&encoded).unwrap();
+        assert_eq!(decoded.id(), Some(7));
+        assert_eq!(decoded, request);
+        assert_eq!(bytes_consumed, encoded.len());
+        assert_eq!(DaemonRequest::new("1").id(), None);
+
+        let frame = DaemonRequest::encode_cancel_request(7);
+        assert_eq!(
+            u32::from_be_bytes([frame[0], frame[1], frame[2], frame[3]]),
+            CANCEL_REQUEST_MARKER
+        );
+        assert_eq!(
+            u32::from_be_bytes([frame[4], frame[5], frame[6], frame[7]]),
+            7
+        );
+        assert!(DaemonRequest::decode(&frame).is_err());
+    }
+
+    #[test]
+    fn test_priority_request_format() {
+        let request = DaemonRequest::new("1")
+            .with_timeout(Duration::from_millis(5))
+            .with_priority(Priority::Batch);
+        let encoded = request.encode();
+        assert_eq!(encoded.len(), 8 + 8 + 4 + 1);
+        assert_eq!(
+            u32::from_be_bytes([encoded[0], encoded[1], encoded[2], encoded[3]]),
+            PRIORITY_MARKER
+        );
+        assert_eq!(
+            u32::from_be_bytes([encoded[4], encoded[5], encoded[6], encoded[7]]),
+            1
+        );
+        let (decoded, bytes_consumed) = DaemonRequest::decode(&encoded).unwrap();
+        assert_eq!(decoded, request);
+        assert_eq!(bytes_consumed, encoded.len());
+
+        // Interactive is the default and goes without a prefix
+        assert_eq!(DaemonRequest::new("1").priority(), Priority::Interactive);
+        assert_eq!(
+            DaemonRequest::new("1")
+                .with_priority(Priority::Interactive)
+                .encode(),
+            DaemonRequest::new("1").encode()
+        );
+        let mut unknown = encoded.clone();
+        unknown[7] = 9;
+        assert_eq!(
+            DaemonRequest::decode(&unknown).unwrap_err(),
+            ProtocolError::InvalidPriority(9)
+        );
+
+        let response = DaemonResponse::busy("Server busy");
+        let (decoded, _) = DaemonResponse::decode(&response.encode()).unwrap();
+        assert!(decoded.is_busy());
+        assert!(decoded.is_error());
+    }
+
+    #[test]
+    fn test_session_frame_format() {
+        let frame = DaemonRequest::encode_session("repl");
+        assert_eq!(frame.len(), 4 + 4 + 4);
+        assert_eq!(
+            u32::from_be_bytes([frame[0], frame[1], frame[2], frame[3]]),
+            SESSION_MARKER
+        );
+        assert_eq!(
+            u32::from_be_bytes([frame[4], frame[5], frame[6], frame[7]]),
+            4
+        );
+        assert_eq!(&frame[8..], b"repl");
+        // A session frame is not a request
+        assert!(DaemonRequest::decode(&frame).is_err());
+
+        let frame = DaemonRequest::encode_auth("secret");
+        assert_eq!(
+            u32::from_be_bytes([frame[0], frame[1], frame[2], frame[3]]),
+            AUTH_MARKER
+        );
+        assert_eq!(&frame[8..], b"secret");
+        assert!(DaemonRequest::decode(&frame).is_err());
+    }
+
+    #[test]
+    fn test_batch_frame_format() {
+        let requests = [
+            DaemonRequest::new("1+1"),
+            DaemonRequest::new("x").with_timeout(Duration::from_millis(5)),
+        ];
+        let frame = DaemonRequest::encode_batch(&requests);
+        assert_eq!(
+            u32::from_be_bytes([frame[0], frame[1], frame[2], frame[3]]),
+            BATCH_MARKER
+        );
+        let length = u32::from_be_bytes([frame[4], frame[5], frame[6], frame[7]]);
+        assert_eq!(length as usize, frame.len() - 8);
+        assert_eq!(DaemonRequest::decode_batch(&frame[8..]).unwrap(), requests);
+        assert!(DaemonRequest::decode(&frame).is_err());
+
+        // An empty batch is valid; a truncated one is not
+        assert!(DaemonRequest::decode_batch(&[]).unwrap().is_empty());
+        assert!(DaemonRequest::decode_batch(&frame[8..frame.len() - 1]).is_err());
+    }
+
     #[test]
     fn test_request_decode_invalid_utf8() {
         // Create invalid UTF-8 sequence
@@ -335,6 +1697,46 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_repeated_prefixes_are_rejected() {
+        let mut bytes = Vec::new();
+        for _ in 0..100_000 {
+            bytes.extend_from_slice(&STREAM_MARKER.to_be_bytes());
+        }
+        bytes.extend_from_slice(&DaemonRequest::new("1").encode());
+        assert_eq!(
+            DaemonRequest::decode(&bytes).unwrap_err(),
+            ProtocolError::RepeatedPrefix(STREAM_MARKER)
+        );
+
+        // The daemon stops reading at the second one
+        let mut decoder = crate::daemon::MessageDecoder::default();
+        assert!(matches!(
+            decoder.word(STREAM_MARKER.to_be_bytes()),
+            Ok(crate::daemon::Next::Word)
+        ));
+        assert!(matches!(
+            decoder.word(STREAM_MARKER.to_be_bytes()),
+            Err(crate::daemon::DaemonError::Protocol(
+                ProtocolError::RepeatedPrefix(STREAM_MARKER)
+            ))
+        ));
+
+        // Every prefix once, in any order, still decodes
+        let request = DaemonRequest::streaming("1")
+            .with_id(7)
+            .with_timeout(Duration::from_secs(1))
+            .with_args(vec!["a".to_string()])
+            .without_cache()
+            .with_profile()
+            .with_diagnostics();
+        let encoded = request.encode();
+        assert_eq!(
+            DaemonRequest::decode(&encoded).unwrap(),
+            (request, encoded.len())
+        );
+    }
+
     #[test]
     fn test_request_decode_no_length_prefix() {
         // Less than 4 bytes
@@ -372,6 +1774,61 @@ mod tests {
         assert_eq!(bytes_consumed, encoded.len());
     }
 
+    #[test]
+    fn test_response_encode_decode_chunk() {
+        let response = DaemonResponse::chunk("hello\n");
+        let encoded = response.encode();
+        assert_eq!(encoded[0], 2);
+
+        let (decoded, bytes_consumed) = DaemonResponse::decode(&encoded).unwrap();
+        assert!(decoded.is_chunk());
+        assert!(!decoded.is_success());
+        assert!(!decoded.is_error());
+        assert_eq!(decoded.output(), "hello\n");
+        assert_eq!(bytes_consumed, encoded.len());
+    }
+
+    #[test]
+    fn test_response_encode_decode_limit_exceeded() {
+        let response = DaemonResponse::limit_exceeded("memory", "memory limit exceeded (64 bytes)");
+        let encoded = response.encode();
+        assert_eq!(encoded[0], 4);
+
+        let (decoded, _) = DaemonResponse::decode(&encoded).unwrap();
+        assert!(decoded.is_error());
+        assert_eq!(
+            decoded.exceeded_limit(),
+            Some(("memory", "memory limit exceeded (64 bytes)"))
+        );
+        assert_eq!(DaemonResponse::error("memory: x").exceeded_limit(), None);
+    }
+
+    #[test]
+    fn test_response_encode_decode_exit() {
+        let response = DaemonResponse::exit(3);
+        let encoded = response.encode();
+        assert_eq!(encoded[0], 8);
+
+        let (decoded, _) = DaemonResponse::decode(&encoded).unwrap();
+        assert_eq!(decoded, response);
+        assert_eq!(decoded.exit_code(), Some(3));
+        assert_eq!(DaemonResponse::error("3").exit_code(), None);
+    }
+
+    #[test]
+    fn test_response_encode_decode_diagnostic() {
+        let error = crate::execute_python("x = 1 $ 2").unwrap_err();
+        let diagnostic = Diagnostic::from(&error);
+        let response = DaemonResponse::failure(&diagnostic);
+        let encoded = response.encode();
+        assert_eq!(encoded[0], 9);
+
+        let (decoded, _) = DaemonResponse::decode(&encoded).unwrap();
+        assert!(decoded.is_error());
+        assert_eq!(decoded.diagnostic(), Some(diagnostic));
+        assert_eq!(DaemonResponse::error("x").diagnostic(), None);
+    }
+
     #[test]
     fn test_response_encode_format() {
         let response = DaemonResponse::success("42");
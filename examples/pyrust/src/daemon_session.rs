@@ -0,0 +1,217 @@
+//! Named sessions kept by the daemon
+//!
+//! A client attaches its connection to a session with a session frame (see
+//! [`crate::daemon_protocol`]); the requests that follow run in that
+//! session's [`Session`], so variables and functions defined by one request
+//! are visible to the next, across connections, much like a remote REPL.
+//!
+//! Sessions are created on first attach and dropped once nobody has used
+//! them for the idle timeout. The number of live sessions is capped, since
+//! each keeps a VM and its globals in memory.
+
+use crate::error::PyRustError;
+use crate::session::Session;
+use crate::vm::VM;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Default cap on the number of live sessions
+pub const DEFAULT_MAX_SESSIONS: usize = 64;
+
+/// Default time a session is kept without being used
+pub const DEFAULT_SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Longest accepted session id, in bytes
+pub const MAX_SESSION_ID_LEN: usize = 256;
+
+/// Why a session could not be attached or used
+#[derive(Debug, Clone, PartialEq)]
+pub enum SessionError {
+    /// The id is empty or longer than [`MAX_SESSION_ID_LEN`]
+    InvalidId,
+    /// Every session slot is taken by a session that is still in use
+    TooManySessions { max: usize },
+    /// The session was dropped after going idle
+    Expired(String),
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::InvalidId => write!(
+                f,
+                "Invalid session id: must be 1 to {} bytes",
+                MAX_SESSION_ID_LEN
+            ),
+            SessionError::TooManySessions { max } => {
+                write!(f, "Too many sessions (max {})", max)
+            }
+            SessionError::Expired(id) => write!(f, "Session '{}' expired", id),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+struct Entry {
+    session: Arc<Mutex<Session>>,
+    last_used: Instant,
+}
+
+/// The daemon's sessions, by id
+pub struct SessionTable {
+    sessions: Mutex<HashMap<String, Entry>>,
+    max_sessions: usize,
+    idle_timeout: Duration,
+}
+
+impl SessionTable {
+    /// Keep at most `max_sessions` sessions, each until it has been idle
+    /// for `idle_timeout`
+    pub fn new(max_sessions: usize, idle_timeout: Duration) -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+            max_sessions,
+            idle_timeout,
+        }
+    }
+
+    /// Number of sessions currently kept, expired ones included until the
+    /// next attach drops them
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Check if no sessions are kept
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Create the session `id` if it does not exist, and mark it used
+    ///
+    /// Expired sessions are dropped first, so they never count against the
+    /// limit.
+    pub fn attach(&self, id: &str) -> Result<(), SessionError> {
+        if id.is_empty() || id.len() > MAX_SESSION_ID_LEN {
+            return Err(SessionError::InvalidId);
+        }
+        let now = Instant::now();
+        let mut sessions = self.lock();
+        sessions.retain(|_, entry| now.duration_since(entry.last_used) < self.idle_timeout);
+
+        if let Some(entry) = sessions.get_mut(id) {
+            entry.last_used = now;
+            return Ok(());
+        }
+        if sessions.len() >= self.max_sessions {
+            return Err(SessionError::TooManySessions {
+                max: self.max_sessions,
+            });
+        }
+        sessions.insert(
+            id.to_string(),
+            Entry {
+                session: Arc::new(Mutex::new(Session::new())),
+                last_used: now,
+            },
+        );
+        Ok(())
+    }
+
+    /// The session `id`, marked used, unless it expired or never existed
+    pub fn get(&self, id: &str) -> Result<Arc<Mutex<Session>>, SessionError> {
+        let now = Instant::now();
+        let mut sessions = self.lock();
+        match sessions.get_mut(id) {
+            Some(entry) if now.duration_since(entry.last_used) < self.idle_timeout => {
+                entry.last_used = now;
+                Ok(Arc::clone(&entry.session))
+            }
+            _ => {
+                sessions.remove(id);
+                Err(SessionError::Expired(id.to_string()))
+            }
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Entry>> {
+        self.sessions
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Default for SessionTable {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_SESSIONS, DEFAULT_SESSION_IDLE_TIMEOUT)
+    }
+}
+
+/// Evaluate `code` in `session`, like [`crate::daemon::DaemonServer`] runs
+/// a request on a pooled VM
+///
+/// `prepare` attaches the request's token, sink and watchdog to the
+/// session's VM; they are detached again afterwards, so they do not outlive
+/// the request.
+pub(crate) fn execute_in_session(
+    session: &Mutex<Session>,
+    code: &str,
+    prepare: impl FnOnce(&mut VM),
+) -> Result<String, PyRustError> {
+    let mut session = session
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    prepare(session.vm_mut());
+    let result = session.eval(code);
+    let vm = session.vm_mut();
+    vm.clear_stdout_sink();
+    vm.clear_cancellation_token();
+    vm.clear_watchdog();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sessions_keep_globals() {
+        let table = SessionTable::default();
+        table.attach("a").unwrap();
+        table.attach("b").unwrap();
+
+        let run = |id: &str, code: &str| {
+            let session = table.get(id).unwrap();
+            execute_in_session(&session, code, |_| {})
+        };
+        assert!(run("a", "x = 40").is_ok());
+        assert_eq!(run("a", "x + 2").unwrap(), "42");
+        // Sessions do not share globals
+        assert!(run("b", "x").is_err());
+        assert_eq!(table.len(), 2);
+    }
+
+    #[test]
+    fn test_session_limit_and_expiry() {
+        let table = SessionTable::new(1, Duration::from_millis(50));
+        assert_eq!(table.attach(""), Err(SessionError::InvalidId));
+        table.attach("a").unwrap();
+        // Attaching again is not a new session
+        table.attach("a").unwrap();
+        assert_eq!(
+            table.attach("b"),
+            Err(SessionError::TooManySessions { max: 1 })
+        );
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(
+            table.get("a").err(),
+            Some(SessionError::Expired("a".to_string()))
+        );
+        // The expired session no longer takes a slot
+        table.attach("b").unwrap();
+        assert_eq!(table.len(), 1);
+    }
+}
@@ -0,0 +1,296 @@
+//! Starting the daemon in the background
+//!
+//! [`spawn`] forks a child that detaches from the terminal, binds the daemon
+//! and serves until stopped. The parent waits on a pipe until the child
+//! reports it is listening, so a client can connect as soon as `spawn`
+//! returns. Both `pyrust --daemon` and the client's auto-start use it.
+//!
+//! Windows has no fork. There, `spawn_process` starts the `pyrust`
+//! executable again, detached, with [`DAEMON_CHILD_ARG`]; the child calls
+//! `run_child` and reports on its stdout instead of a pipe.
+//!
+//! # Auto-start
+//!
+//! With [`AUTOSTART_ENV`] set to `1`, the fallback methods of
+//! [`crate::daemon_client::DaemonClient`] start a daemon when none is
+//! running, then send the code to it. The first run still pays for the
+//! fork and a cold cache; later runs get the daemon's warm cache without a
+//! manual `pyrust --daemon`.
+
+use crate::daemon::DaemonError;
+#[cfg(unix)]
+use crate::daemon::{daemon_name_from_env, DaemonServer};
+use crate::daemon_client::DaemonClient;
+#[cfg(unix)]
+use crate::daemon_log::LogConfig;
+use crate::daemon_transport;
+#[cfg(unix)]
+use std::io;
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Environment variable that lets clients start the daemon on first use
+pub const AUTOSTART_ENV: &str = "PYRUST_DAEMON_AUTOSTART";
+
+/// Argument that makes `pyrust` run as the daemon child of
+/// `spawn_process`
+pub const DAEMON_CHILD_ARG: &str = "--daemon-child";
+
+/// Line a Windows daemon child prints once it is ready
+#[cfg(windows)]
+const READY_LINE: &str = "ready";
+
+/// How long [`autostart`] waits for the new daemon to accept connections
+const AUTOSTART_WAIT: Duration = Duration::from_secs(1);
+
+/// A daemon server ready to run until stopped
+pub type DaemonRun = Box<dyn FnOnce() -> Result<(), DaemonError>>;
+
+/// Check if [`AUTOSTART_ENV`] asks for the daemon to be started on demand
+pub fn autostart_enabled() -> bool {
+    std::env::var(AUTOSTART_ENV).is_ok_and(|v| v == "1")
+}
+
+/// Start the default daemon in the background for a client
+///
+/// Logs to the instance's [`crate::daemon_log`] file when it can be opened.
+/// Like any fork, this is only safe while the calling process has a single
+/// thread, as the CLI does when it runs a script.
+///
+/// Unlike [`spawn`], returns only once the daemon accepts connections, so
+/// the caller's first request does not race the daemon binding its socket.
+pub fn autostart() -> Result<u32, DaemonError> {
+    #[cfg(unix)]
+    let pid = spawn(|| {
+        let daemon = DaemonServer::new().map_err(|e| e.to_string())?;
+        // Logging is a nicety here; the daemon serves without it
+        let log_config = LogConfig::for_instance(daemon_name_from_env().as_deref());
+        if crate::daemon_log::init(&log_config).is_ok() {
+            log::info!("daemon started pid={} autostart=true", process::id());
+        }
+        Ok(Box::new(move || daemon.run()))
+    })?;
+    // Without fork, only the pyrust executable itself can become the daemon
+    #[cfg(windows)]
+    let pid = {
+        let exe = std::env::current_exe()?;
+        if !exe.file_stem().is_some_and(|stem| stem == "pyrust") {
+            return Err(DaemonError::StartFailed(
+                "auto-start needs the pyrust executable on Windows".to_string(),
+            ));
+        }
+        spawn_process(&exe, &[DAEMON_CHILD_ARG.to_string()])?
+    };
+
+    let started = Instant::now();
+    let socket_path = DaemonClient::socket_path();
+    while daemon_transport::connect(&socket_path).is_err() {
+        if started.elapsed() > AUTOSTART_WAIT {
+            return Err(DaemonError::StartFailed(format!(
+                "daemon {} is not accepting connections",
+                pid
+            )));
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+    Ok(pid)
+}
+
+/// Fork a detached daemon, returning its PID once it is ready to serve
+///
+/// `init` runs in the child before it detaches, while its stderr still
+/// reaches the terminal: it binds the server and returns how to run it, or
+/// the reason it cannot. The child never returns from this function; it
+/// exits when the server stops.
+#[cfg(unix)]
+pub fn spawn<F>(init: F) -> Result<u32, DaemonError>
+where
+    F: FnOnce() -> Result<DaemonRun, String>,
+{
+    // Create a pipe for parent-child synchronization
+    let mut pipe_fds: [libc::c_int; 2] = [0, 0];
+    unsafe {
+        if libc::pipe(pipe_fds.as_mut_ptr()) < 0 {
+            return Err(DaemonError::Io(io::Error::last_os_error()));
+        }
+    }
+    let pipe_read_fd = pipe_fds[0];
+    let pipe_write_fd = pipe_fds[1];
+
+    // Fork the process
+    let pid = unsafe { libc::fork() };
+
+    if pid < 0 {
+        let error = io::Error::last_os_error();
+        // Close pipe FDs to prevent resource leak
+        unsafe {
+            libc::close(pipe_read_fd);
+            libc::close(pipe_write_fd);
+        }
+        return Err(DaemonError::Io(error));
+    } else if pid > 0 {
+        // Parent process - wait for child to signal readiness
+        unsafe {
+            libc::close(pipe_write_fd); // Close write end in parent
+        }
+
+        // Read from pipe to confirm child is ready
+        let mut ready_byte = [0u8; 1];
+        let result = unsafe {
+            libc::read(
+                pipe_read_fd,
+                ready_byte.as_mut_ptr() as *mut libc::c_void,
+                1,
+            )
+        };
+
+        unsafe {
+            libc::close(pipe_read_fd);
+        }
+
+        return if result == 1 && ready_byte[0] == b'R' {
+            Ok(pid as u32)
+        } else {
+            // Child failed to start (pipe closed without sending 'R')
+            Err(DaemonError::StartFailed("initialization error".to_string()))
+        };
+    }
+
+    // Child process continues below
+    unsafe {
+        libc::close(pipe_read_fd); // Close read end in child
+    }
+
+    // Become session leader
+    unsafe {
+        if libc::setsid() < 0 {
+            // Can still report error via pipe before closing stderr
+            let error_msg = b"Failed to create new session\n";
+            let _ = libc::write(
+                libc::STDERR_FILENO,
+                error_msg.as_ptr() as *const libc::c_void,
+                error_msg.len(),
+            );
+            libc::close(pipe_write_fd);
+            process::exit(1);
+        }
+    }
+
+    // Initialize daemon BEFORE closing stderr so errors can be reported
+    let daemon = match init() {
+        Ok(d) => d,
+        Err(e) => {
+            // Report error before closing stderr
+            eprintln!("Failed to initialize daemon: {}", e);
+            unsafe {
+                libc::close(pipe_write_fd);
+            }
+            process::exit(1);
+        }
+    };
+
+    // Close standard file descriptors
+    unsafe {
+        libc::close(0); // stdin
+        libc::close(1); // stdout
+        libc::close(2); // stderr
+    }
+
+    // Redirect standard file descriptors to /dev/null
+    unsafe {
+        use std::ffi::CString;
+        let dev_null = CString::new("/dev/null").unwrap();
+        let fd = libc::open(dev_null.as_ptr(), libc::O_RDWR);
+        if fd < 0 {
+            // Failed to open /dev/null - daemon cannot run safely
+            libc::close(pipe_write_fd);
+            process::exit(1);
+        }
+        libc::dup2(fd, 0); // stdin
+        libc::dup2(fd, 1); // stdout
+        libc::dup2(fd, 2); // stderr
+        if fd > 2 {
+            libc::close(fd);
+        }
+    }
+
+    // Signal parent that daemon is ready
+    unsafe {
+        let ready_byte = b"R";
+        libc::write(pipe_write_fd, ready_byte.as_ptr() as *const libc::c_void, 1);
+        libc::close(pipe_write_fd);
+    }
+
+    serve(daemon)
+}
+
+/// Start `program` with `args` as a detached daemon, returning its PID once
+/// it is ready to serve
+///
+/// `program` must call `run_child` when given [`DAEMON_CHILD_ARG`], as
+/// `pyrust` does. The child gets no console; its stdout is a pipe on which
+/// it reports that it is ready, or why it could not start.
+#[cfg(windows)]
+pub fn spawn_process(program: &std::path::Path, args: &[String]) -> Result<u32, DaemonError> {
+    use std::io::{BufRead, BufReader};
+    use std::os::windows::process::CommandExt;
+    use std::process::{Command, Stdio};
+
+    const DETACHED_PROCESS: u32 = 0x0000_0008;
+    const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .creation_flags(DETACHED_PROCESS | CREATE_NEW_PROCESS_GROUP)
+        .spawn()?;
+    let mut line = String::new();
+    if let Some(stdout) = child.stdout.take() {
+        BufReader::new(stdout).read_line(&mut line)?;
+    }
+    match line.trim_end() {
+        READY_LINE => Ok(child.id()),
+        // Exited without a word
+        "" => Err(DaemonError::StartFailed("initialization error".to_string())),
+        reason => Err(DaemonError::StartFailed(reason.to_string())),
+    }
+}
+
+/// Serve as the daemon child of `spawn_process`, exiting when stopped
+///
+/// `init` binds the server as for [`spawn`]; its error, if any, is passed
+/// back to the parent.
+#[cfg(windows)]
+pub fn run_child<F>(init: F) -> !
+where
+    F: FnOnce() -> Result<DaemonRun, String>,
+{
+    let daemon = match init() {
+        Ok(d) => d,
+        Err(e) => {
+            println!("{}", e);
+            process::exit(1);
+        }
+    };
+    // The parent stops reading after this; nothing else goes to stdout
+    println!("{}", READY_LINE);
+    serve(daemon)
+}
+
+/// Run a daemon until it stops, logging how it ends, then exit
+///
+/// Used by the detached children, and by daemons that some service manager
+/// keeps in the foreground.
+pub fn serve(daemon: DaemonRun) -> ! {
+    // Start the daemon server event loop
+    if let Err(e) = daemon() {
+        // stderr may lead nowhere by now; the log is what is sure to be read
+        log::error!("daemon failed: {}", e);
+        process::exit(1);
+    }
+    log::info!("daemon stopped");
+    process::exit(0);
+}
@@ -0,0 +1,296 @@
+//! Restarting the daemon when it crashes
+//!
+//! `pyrust --daemon --supervise` leaves a supervisor in the background
+//! instead of the daemon itself. The supervisor starts the daemon as its
+//! child with [`crate::daemon_spawn::spawn`] and waits on it. When the
+//! daemon exits cleanly, as after `pyrust --stop-daemon`, so does the
+//! supervisor. When it crashes (a panic, a signal, any other nonzero exit),
+//! the supervisor records a crash report and starts a new daemon after a
+//! backoff that doubles with each crash in a row, from [`INITIAL_BACKOFF`]
+//! up to [`MAX_BACKOFF`]. A daemon that stayed up for [`STABLE_UPTIME`]
+//! resets the backoff.
+//!
+//! The new daemon binds the same socket and writes its own PID file, so
+//! clients only see the requests that were in flight fail. Its in-memory
+//! cache starts empty, but the disk tier (see [`crate::cache`]) is left as
+//! it was, so with one configured the restarted daemon serves its first
+//! requests warm.
+//!
+//! SIGTERM or SIGINT to the supervisor stops the daemon, then the
+//! supervisor.
+//!
+//! # Crash Reports
+//!
+//! Each crash is written to [`crash_dir`] as `crash-<unix seconds>-<pid>.txt`
+//! with the daemon's exit status, uptime and restart count, followed by the
+//! end of its log. A panicking daemon logs the panic before it exits, so
+//! the report usually names what went wrong.
+
+use crate::daemon::{runtime_dir, DaemonError};
+use crate::daemon_client::DaemonClient;
+use crate::daemon_spawn::{self, DaemonRun};
+use crate::daemon_transport;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Environment variable overriding where crash reports are written
+pub const CRASH_DIR_ENV: &str = "PYRUST_DAEMON_CRASH_DIR";
+
+/// Wait before the first restart after a crash
+pub const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Longest wait between restarts
+pub const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Uptime after which a crash no longer counts as one in a row
+pub const STABLE_UPTIME: Duration = Duration::from_secs(60);
+
+/// How often the supervisor checks on its child and on stop signals
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Lines of the daemon's log copied into a crash report
+const LOG_TAIL_LINES: usize = 20;
+
+/// Directory of the crash reports of daemon instance `name`
+///
+/// [`CRASH_DIR_ENV`] if set, otherwise `/tmp/pyrust-crashes`, or
+/// `/tmp/pyrust-<name>-crashes` for a named instance.
+pub fn crash_dir(name: Option<&str>) -> PathBuf {
+    if let Some(dir) = std::env::var_os(CRASH_DIR_ENV) {
+        return PathBuf::from(dir);
+    }
+    let dir = match name {
+        Some(name) => format!("pyrust-{}-crashes", name),
+        None => "pyrust-crashes".to_string(),
+    };
+    runtime_dir().join(dir)
+}
+
+/// Start a daemon with `init`, returning how to keep it running
+///
+/// Call this as the `init` of [`daemon_spawn::spawn`]: the first daemon is
+/// started before the supervisor detaches, so that a daemon that cannot
+/// start is reported on the terminal. Restarts call `init` again, in a new
+/// child. `log_path` is the daemon's log, whose end goes into crash
+/// reports, and `crash_dir` where they are written.
+pub fn supervise<F>(init: F, log_path: PathBuf, crash_dir: PathBuf) -> Result<DaemonRun, String>
+where
+    F: Fn() -> Result<DaemonRun, String> + 'static,
+{
+    let child = start(&init).map_err(|e| e.to_string())?;
+    let stop = Arc::new(AtomicBool::new(false));
+    for signal in [signal_hook::consts::SIGTERM, signal_hook::consts::SIGINT] {
+        signal_hook::flag::register(signal, Arc::clone(&stop)).map_err(|e| e.to_string())?;
+    }
+    let supervisor = Supervisor {
+        init: Box::new(init),
+        log_path,
+        crash_dir,
+        stop,
+    };
+    Ok(Box::new(move || supervisor.run(child)))
+}
+
+/// The parent of a supervised daemon
+struct Supervisor {
+    /// Starts a daemon in the child process
+    init: Box<dyn Fn() -> Result<DaemonRun, String>>,
+    /// The daemon's log
+    log_path: PathBuf,
+    /// Where crash reports go
+    crash_dir: PathBuf,
+    /// Set by SIGTERM or SIGINT
+    stop: Arc<AtomicBool>,
+}
+
+impl Supervisor {
+    /// Wait on the daemon `child`, restarting it until it exits cleanly or
+    /// the supervisor is stopped
+    fn run(self, child: u32) -> Result<(), DaemonError> {
+        let mut child = Some(child);
+        let mut started = Instant::now();
+        let mut backoff = INITIAL_BACKOFF;
+        let mut restarts = 0;
+        loop {
+            let crash = match child {
+                Some(pid) => {
+                    let status = self.wait(pid)?;
+                    if self.stopped() || exited_cleanly(status) {
+                        return Ok(());
+                    }
+                    if started.elapsed() >= STABLE_UPTIME {
+                        backoff = INITIAL_BACKOFF;
+                    }
+                    format!(
+                        "pid {} {} after {:.3}s",
+                        pid,
+                        describe_status(status),
+                        started.elapsed().as_secs_f64()
+                    )
+                }
+                None => {
+                    reap_children();
+                    "daemon failed to restart".to_string()
+                }
+            };
+            // A failed report must not keep the daemon down
+            let _ = self.report(&crash, restarts);
+
+            if !self.sleep(backoff) {
+                return Ok(());
+            }
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+            // Someone else started a daemon on the socket meanwhile
+            if daemon_transport::connect(&DaemonClient::socket_path()).is_ok() {
+                return Ok(());
+            }
+            restarts += 1;
+            child = start(&self.init).ok();
+            started = Instant::now();
+        }
+    }
+
+    /// Wait for `pid` to exit, passing a stop signal on to it, and return
+    /// its wait status
+    fn wait(&self, pid: u32) -> Result<libc::c_int, DaemonError> {
+        let mut signalled = false;
+        loop {
+            let mut status = 0;
+            match unsafe { libc::waitpid(pid as libc::pid_t, &mut status, libc::WNOHANG) } {
+                0 => {}
+                -1 => return Err(DaemonError::Io(io::Error::last_os_error())),
+                _ => return Ok(status),
+            }
+            if self.stopped() && !signalled {
+                unsafe {
+                    libc::kill(pid as libc::pid_t, libc::SIGTERM);
+                }
+                signalled = true;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Sleep for `duration`; false if stopped meanwhile
+    fn sleep(&self, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+        while Instant::now() < deadline {
+            if self.stopped() {
+                return false;
+            }
+            thread::sleep(POLL_INTERVAL.min(deadline.saturating_duration_since(Instant::now())));
+        }
+        !self.stopped()
+    }
+
+    fn stopped(&self) -> bool {
+        self.stop.load(Ordering::SeqCst)
+    }
+
+    /// Write a crash report for `crash`, the `restarts`th restart so far
+    fn report(&self, crash: &str, restarts: u32) -> io::Result<PathBuf> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let path = self.crash_dir.join(format!(
+            "crash-{}-{}.txt",
+            now.as_secs(),
+            std::process::id()
+        ));
+        fs::create_dir_all(&self.crash_dir)?;
+        fs::write(&path, format_report(crash, now, restarts, &self.log_path))?;
+        Ok(path)
+    }
+}
+
+/// Start a daemon with `init` in a child process, returning its PID
+///
+/// The child logs a panic before exiting, since its stderr goes nowhere.
+fn start(init: &dyn Fn() -> Result<DaemonRun, String>) -> Result<u32, DaemonError> {
+    daemon_spawn::spawn(|| {
+        let run = init()?;
+        Ok(Box::new(move || {
+            std::panic::set_hook(Box::new(|info| log::error!("daemon panicked: {}", info)));
+            run()
+        }) as DaemonRun)
+    })
+}
+
+/// Collect the exit status of children that failed to start
+fn reap_children() {
+    let mut status = 0;
+    while unsafe { libc::waitpid(-1, &mut status, libc::WNOHANG) } > 0 {}
+}
+
+/// Whether a wait status is an exit with status 0
+fn exited_cleanly(status: libc::c_int) -> bool {
+    libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0
+}
+
+/// How a process with wait status `status` ended
+fn describe_status(status: libc::c_int) -> String {
+    if libc::WIFSIGNALED(status) {
+        format!("killed by signal {}", libc::WTERMSIG(status))
+    } else {
+        format!("exited with status {}", libc::WEXITSTATUS(status))
+    }
+}
+
+/// Text of a crash report: `crash` at `time`, then the end of the log
+fn format_report(crash: &str, time: Duration, restarts: u32, log_path: &Path) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "crash {}", crash);
+    let _ = writeln!(out, "time {}", time.as_secs());
+    let _ = writeln!(out, "restarts {}", restarts);
+    let _ = writeln!(out, "log {}", log_path.display());
+    if let Ok(log) = fs::read_to_string(log_path) {
+        let lines: Vec<_> = log.lines().collect();
+        out.push('\n');
+        for line in &lines[lines.len().saturating_sub(LOG_TAIL_LINES)..] {
+            let _ = writeln!(out, "{}", line);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_status() {
+        // Wait statuses as Linux and the BSDs encode them
+        assert!(exited_cleanly(0));
+        assert!(!exited_cleanly(101 << 8));
+        assert_eq!(describe_status(101 << 8), "exited with status 101");
+        assert_eq!(describe_status(libc::SIGKILL), "killed by signal 9");
+    }
+
+    #[test]
+    fn test_report_ends_with_log_tail() {
+        let log_path =
+            std::env::temp_dir().join(format!("pyrust-supervisor-test-{}.log", std::process::id()));
+        let log: String = (0..30).map(|i| format!("line {}\n", i)).collect();
+        fs::write(&log_path, log).unwrap();
+
+        let report = format_report(
+            "pid 7 killed by signal 11 after 1.000s",
+            Duration::from_secs(1760000000),
+            2,
+            &log_path,
+        );
+        assert!(report.starts_with("crash pid 7 killed by signal 11 after 1.000s\n"));
+        assert!(report.contains("\nrestarts 2\n"));
+        assert!(!report.contains("line 9\n"));
+        assert!(report.contains("\nline 10\n") && report.ends_with("line 29\n"));
+
+        fs::remove_file(&log_path).unwrap();
+        assert!(!format_report("x", Duration::ZERO, 0, &log_path).contains("line"));
+    }
+}
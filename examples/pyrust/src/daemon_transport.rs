@@ -0,0 +1,167 @@
+//! Local connections between the daemon and its clients
+//!
+//! On Unix, a daemon listens on a Unix socket at its socket path. Windows
+//! has no Unix sockets in std, so there the daemon listens on a TCP port on
+//! the loopback interface instead, and writes the port to a file at the
+//! socket path for clients to find. Either way the path exists exactly
+//! while a daemon is serving, so [`crate::daemon_client::DaemonClient`]'s
+//! checks for a running daemon work unchanged.
+//!
+//! Unlike a Unix socket, which is owner-only, a loopback port is reachable
+//! by every local user. Shared Windows machines should set
+//! [`crate::daemon::AUTH_TOKEN_ENV`].
+//!
+//! [`Stream`] and [`Listener`] are the platform's connection types.
+
+use std::io;
+use std::time::Duration;
+
+#[cfg(unix)]
+pub(crate) use std::os::unix::net::{UnixListener as Listener, UnixStream as Stream};
+
+#[cfg(windows)]
+pub(crate) use std::net::{TcpListener as Listener, TcpStream as Stream};
+
+/// Connect to the daemon whose socket is at `path`
+#[cfg(unix)]
+pub(crate) fn connect(path: &str) -> io::Result<Stream> {
+    Stream::connect(path)
+}
+
+/// Connect to the daemon whose socket is at `path`
+#[cfg(windows)]
+pub(crate) fn connect(path: &str) -> io::Result<Stream> {
+    loopback::connect(path)
+}
+
+/// Connect to the daemon whose socket is at `path`, giving up after
+/// `timeout`
+///
+/// A blocking connect to a Unix socket waits while the daemon's accept
+/// backlog is full; this one fails with [`io::ErrorKind::TimedOut`] instead.
+#[cfg(unix)]
+pub(crate) fn connect_timeout(path: &str, timeout: Duration) -> io::Result<Stream> {
+    use socket2::{Domain, SockAddr, Socket, Type};
+
+    let socket = Socket::new(Domain::UNIX, Type::STREAM, None)?;
+    socket.connect_timeout(&SockAddr::unix(path)?, timeout)?;
+    Ok(Stream::from(std::os::fd::OwnedFd::from(socket)))
+}
+
+/// Connect to the daemon whose socket is at `path`, giving up after
+/// `timeout`
+#[cfg(windows)]
+pub(crate) fn connect_timeout(path: &str, timeout: Duration) -> io::Result<Stream> {
+    loopback::connect_timeout(path, timeout)
+}
+
+/// Listen for clients at `path`
+#[cfg(unix)]
+pub(crate) fn bind(path: &str) -> io::Result<Listener> {
+    Listener::bind(path)
+}
+
+/// Listen for clients at `path`
+#[cfg(windows)]
+pub(crate) fn bind(path: &str) -> io::Result<Listener> {
+    loopback::bind(path)
+}
+
+/// Loopback TCP endpoints named by a port file
+///
+/// Only used on Windows, but built everywhere so that it is tested.
+#[cfg_attr(not(windows), allow(dead_code))]
+pub(crate) mod loopback {
+    use std::fs;
+    use std::io;
+    use std::net::{Ipv4Addr, TcpListener, TcpStream};
+    use std::time::Duration;
+
+    /// Listen on a free loopback port, recording it in the file at `path`
+    pub(crate) fn bind(path: &str) -> io::Result<TcpListener> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+        fs::write(path, listener.local_addr()?.port().to_string())?;
+        Ok(listener)
+    }
+
+    /// Connect to the loopback port recorded in the file at `path`
+    pub(crate) fn connect(path: &str) -> io::Result<TcpStream> {
+        let stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port(path)?))?;
+        // Requests and responses are small; do not hold them back
+        stream.set_nodelay(true)?;
+        Ok(stream)
+    }
+
+    /// Like [`connect`], giving up after `timeout`
+    pub(crate) fn connect_timeout(path: &str, timeout: Duration) -> io::Result<TcpStream> {
+        let addr = (Ipv4Addr::LOCALHOST, port(path)?).into();
+        let stream = TcpStream::connect_timeout(&addr, timeout)?;
+        stream.set_nodelay(true)?;
+        Ok(stream)
+    }
+
+    /// The port recorded in the file at `path`
+    pub(crate) fn port(path: &str) -> io::Result<u16> {
+        fs::read_to_string(path)?.trim().parse().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} does not hold a port", path),
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::loopback;
+    use std::io::{Read, Write};
+    use std::time::Duration;
+
+    #[test]
+    fn test_loopback_port_file() {
+        let path = std::env::temp_dir()
+            .join(format!("pyrust-loopback-{}.sock", std::process::id()))
+            .display()
+            .to_string();
+        let listener = loopback::bind(&path).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), port.to_string());
+
+        let mut client = loopback::connect(&path).unwrap();
+        let (mut server, _) = listener.accept().unwrap();
+        client.write_all(b"ping").unwrap();
+        let mut buf = [0u8; 4];
+        server.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ping");
+        assert!(loopback::connect_timeout(&path, Duration::from_secs(1)).is_ok());
+
+        // A file without a port is not a daemon
+        std::fs::write(&path, "").unwrap();
+        assert!(loopback::connect(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_connect_timeout() {
+        let path = std::env::temp_dir()
+            .join(format!(
+                "pyrust-connect-timeout-{}.sock",
+                std::process::id()
+            ))
+            .display()
+            .to_string();
+        let _ = std::fs::remove_file(&path);
+        let error = super::connect_timeout(&path, Duration::from_secs(1)).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+
+        let listener = super::bind(&path).unwrap();
+        let mut client = super::connect_timeout(&path, Duration::from_secs(1)).unwrap();
+        let (mut server, _) = listener.accept().unwrap();
+        client.write_all(b"ping").unwrap();
+        let mut buf = [0u8; 4];
+        server.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ping");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
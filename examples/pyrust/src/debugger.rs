@@ -0,0 +1,225 @@
+//! Programmatic debugger interface for the VM
+//!
+//! A [`Debugger`] attached with [`crate::vm::VM::set_debugger`] is consulted
+//! before every instruction. When it reports a breakpoint (or the previous
+//! pause asked to single-step), execution pauses and [`Debugger::on_pause`]
+//! receives a [`PausedState`] for inspecting registers, variables, and the call
+//! stack. The returned [`DebugAction`] resumes or aborts the run.
+//!
+//! Breakpoints are instruction indices; source-line breakpoints can be mapped
+//! onto them once bytecode carries a line table.
+//!
+//! # Example
+//!
+//! ```
+//! use pyrust::debugger::{DebugAction, Debugger, PausedState};
+//! use pyrust::value::Value;
+//! use pyrust::vm::VM;
+//! use pyrust::{compiler, lexer, parser};
+//!
+//! /// Pause once before instruction 2 and record `x`
+//! struct Probe {
+//!     seen: std::sync::Arc<std::sync::Mutex<Option<Value>>>,
+//! }
+//!
+//! impl Debugger for Probe {
+//!     fn is_breakpoint(&self, ip: usize) -> bool {
+//!         ip == 2
+//!     }
+//!
+//!     fn on_pause(&mut self, state: &PausedState<'_>) -> DebugAction {
+//!         *self.seen.lock().unwrap() = state.variable("x");
+//!         DebugAction::Continue
+//!     }
+//! }
+//!
+//! let program = parser::parse(lexer::lex("x = 7\nx + 1").unwrap()).unwrap();
+//! let bytecode = compiler::compile(&program).unwrap();
+//!
+//! let seen = std::sync::Arc::new(std::sync::Mutex::new(None));
+//! let mut vm = VM::new();
+//! vm.set_debugger(Probe { seen: seen.clone() });
+//! vm.execute(&bytecode).unwrap();
+//! assert_eq!(*seen.lock().unwrap(), Some(Value::Integer(7)));
+//! ```
+
+use crate::bytecode::{Bytecode, Instruction};
+use crate::value::Value;
+use crate::vm::VM;
+
+/// How execution continues after a pause
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugAction {
+    /// Run until the next breakpoint
+    Continue,
+    /// Execute one instruction, then pause again
+    Step,
+    /// Stop execution with a RuntimeError
+    Abort,
+}
+
+/// Hooks the VM consults while executing under a debugger
+pub trait Debugger: Send {
+    /// Whether execution should pause before the instruction at `ip`
+    fn is_breakpoint(&self, ip: usize) -> bool;
+
+    /// Called while execution is paused before the instruction at `state.ip()`
+    fn on_pause(&mut self, state: &PausedState<'_>) -> DebugAction;
+}
+
+/// One entry of the call stack, innermost last
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameInfo {
+    /// Instruction index execution resumes at when this call returns
+    pub return_address: usize,
+    /// Caller register that receives the return value
+    pub dest_reg: u8,
+}
+
+/// Read-only view of a paused VM
+pub struct PausedState<'a> {
+    pub(crate) vm: &'a VM,
+    pub(crate) bytecode: &'a Bytecode,
+    pub(crate) ip: usize,
+}
+
+impl<'a> PausedState<'a> {
+    /// Index of the instruction about to execute
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    /// The instruction about to execute
+    pub fn instruction(&self) -> Option<&'a Instruction> {
+        self.bytecode.instruction(self.ip)
+    }
+
+    /// The program being executed
+    pub fn bytecode(&self) -> &'a Bytecode {
+        self.bytecode
+    }
+
+    /// Value of a register, or None if it has not been written
+    pub fn register(&self, reg: u8) -> Option<Value> {
+        self.vm.register_value(reg)
+    }
+
+    /// Value of a variable as the current scope sees it
+    ///
+    /// Inside a function, locals shadow globals. Parameters are not variables:
+    /// parameter `i` is in register `i`, see [`PausedState::register`].
+    pub fn variable(&self, name: &str) -> Option<Value> {
+        let index = self.bytecode.var_names.iter().position(|n| n == name)?;
+        let var_id = *self.bytecode.var_ids.get(index)?;
+        self.vm.visible_variable(var_id)
+    }
+
+    /// Active calls, outermost first
+    pub fn call_stack(&self) -> Vec<FrameInfo> {
+        self.vm.frame_infos()
+    }
+
+    /// Number of active function calls
+    pub fn call_depth(&self) -> usize {
+        self.vm.call_depth()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ExceptionKind;
+    use crate::{compiler, lexer, parser};
+    use std::collections::HashSet;
+    use std::sync::{Arc, Mutex};
+
+    /// (ip, call depth, value of register 0) observed at a pause
+    type Pause = (usize, usize, Option<Value>);
+
+    /// Records every pause and replies with a scripted action
+    struct Recorder {
+        breakpoints: HashSet<usize>,
+        action: DebugAction,
+        pauses: Arc<Mutex<Vec<Pause>>>,
+    }
+
+    impl Debugger for Recorder {
+        fn is_breakpoint(&self, ip: usize) -> bool {
+            self.breakpoints.contains(&ip)
+        }
+
+        fn on_pause(&mut self, state: &PausedState<'_>) -> DebugAction {
+            self.pauses
+                .lock()
+                .unwrap()
+                .push((state.ip(), state.call_depth(), state.register(0)));
+            assert_eq!(state.call_stack().len(), state.call_depth());
+            self.action
+        }
+    }
+
+    fn compile_source(source: &str) -> Bytecode {
+        let program = parser::parse(lexer::lex(source).unwrap()).unwrap();
+        compiler::compile(&program).unwrap()
+    }
+
+    fn run_with(
+        source: &str,
+        breakpoints: &[usize],
+        action: DebugAction,
+    ) -> (
+        Result<Option<Value>, crate::error::RuntimeError>,
+        Vec<Pause>,
+    ) {
+        let bytecode = compile_source(source);
+        let pauses = Arc::new(Mutex::new(Vec::new()));
+        let mut vm = VM::new();
+        vm.set_debugger(Recorder {
+            breakpoints: breakpoints.iter().copied().collect(),
+            action,
+            pauses: Arc::clone(&pauses),
+        });
+        let result = vm.execute(&bytecode);
+        let pauses = pauses.lock().unwrap().clone();
+        (result, pauses)
+    }
+
+    #[test]
+    fn test_breakpoint_pauses_once_and_continues() {
+        let (result, pauses) = run_with("x = 1\ny = 2\nx + y", &[2], DebugAction::Continue);
+        assert_eq!(result.unwrap(), Some(Value::Integer(3)));
+        assert_eq!(pauses.len(), 1);
+        assert_eq!(pauses[0].0, 2);
+    }
+
+    #[test]
+    fn test_step_pauses_before_every_following_instruction() {
+        let (result, pauses) = run_with("x = 1\nx", &[0], DebugAction::Step);
+        assert!(result.is_ok());
+        let ips: Vec<usize> = pauses.iter().map(|p| p.0).collect();
+        assert_eq!(ips, vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_abort_stops_execution() {
+        let (result, pauses) = run_with("print(1)", &[1], DebugAction::Abort);
+        let err = result.unwrap_err();
+        assert_eq!(err.kind, ExceptionKind::RuntimeError);
+        assert_eq!(err.instruction_index, 1);
+        assert_eq!(pauses.len(), 1);
+    }
+
+    #[test]
+    fn test_pause_inside_function_sees_locals_and_depth() {
+        let source = "def double(n):\n    return n + n\ndouble(21)";
+        let bytecode = compile_source(source);
+        let body_start = match bytecode.instructions[0] {
+            Instruction::DefineFunction { chunk_index } => bytecode.chunk_start(chunk_index),
+            _ => panic!("Expected DefineFunction"),
+        };
+
+        let (result, pauses) = run_with(source, &[body_start], DebugAction::Continue);
+        assert_eq!(result.unwrap(), Some(Value::Integer(42)));
+        assert_eq!(pauses, vec![(body_start, 1, Some(Value::Integer(21)))]);
+    }
+}
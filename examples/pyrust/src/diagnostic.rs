@@ -0,0 +1,391 @@
+//! One serializable shape for every error and warning
+//!
+//! A [`Diagnostic`] describes a lex, parse, compile or runtime error, a
+//! bytecode file that failed to load, or a compile warning: the stage that
+//! reported it, its severity, its message and where in the source it
+//! points. Every [`PyRustError`] and [`CompileWarning`] converts to one.
+//! Diagnostics serialize with serde, so the CLI's JSON output
+//! (`pyrust --error-format=json`), the daemon protocol (see
+//! [`crate::daemon_protocol`]) and editor integrations all share the same
+//! representation:
+//!
+//! ```text
+//! {"stage":"runtime","severity":"error","code":"E2001",
+//!  "message":"ZeroDivisionError: Division by zero",
+//!  "span":{"line":1,"column":7,"end_line":1,"end_column":12},"notes":[]}
+//! ```
+//!
+//! # Example
+//!
+//! ```
+//! use pyrust::diagnostic::{Diagnostic, Stage};
+//!
+//! let error = pyrust::execute_python("print(1 / 0)").unwrap_err();
+//! let diagnostic = Diagnostic::from(&error);
+//! assert_eq!(diagnostic.stage, Stage::Runtime);
+//! assert_eq!(diagnostic.span.unwrap().column, 7);
+//! assert_eq!(Diagnostic::from_json(&diagnostic.to_json()), Some(diagnostic));
+//! ```
+
+use crate::ast::SourcePos;
+use crate::compiler::Severity;
+use crate::error::{first_line, snippet, with_details, PyRustError, Verbosity};
+use crate::error_code::ErrorCode;
+use crate::warnings::{CompileWarning, WarningKind};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Pipeline stage a diagnostic comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stage {
+    /// Tokenizing the source
+    Lex,
+    /// Building the syntax tree
+    Parse,
+    /// Resolving names and generating bytecode
+    Compile,
+    /// Running the bytecode
+    Runtime,
+    /// Reading a script or loading precompiled bytecode
+    Load,
+}
+
+/// Source extent a diagnostic points at
+///
+/// Lines and columns are 1-indexed, columns counted in characters as the
+/// lexer does; the end is exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+impl Span {
+    /// The span from `start` up to `end`
+    pub fn new(start: SourcePos, end: SourcePos) -> Self {
+        Self {
+            line: start.line,
+            column: start.column,
+            end_line: end.line,
+            end_column: end.column,
+        }
+    }
+
+    /// The single column at `pos`, for when only a start is known
+    pub fn point(pos: SourcePos) -> Self {
+        Self::on_line(pos.line, pos.column, pos.column + 1)
+    }
+
+    /// The columns `column` up to `end_column` of `line`
+    pub fn on_line(line: usize, column: usize, end_column: usize) -> Self {
+        Self {
+            line,
+            column,
+            end_line: line,
+            end_column,
+        }
+    }
+}
+
+/// An error or warning, as reported to a user or a tool
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub stage: Stage,
+    pub severity: Severity,
+    /// Stable identifier of the kind of problem, such as `E1003` (see
+    /// [`crate::error_code`]), when it has one
+    pub code: Option<String>,
+    /// What went wrong, on one line; a runtime error's starts with its
+    /// exception class
+    pub message: String,
+    /// Where in the source, when known
+    pub span: Option<Span>,
+    /// Further details, one per line, such as the tokens a parser expected
+    /// or a runtime error's traceback, outermost frame first
+    pub notes: Vec<String>,
+    /// Other places in the source that led to the problem, such as the
+    /// calls active when a runtime error was raised
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub related: Vec<RelatedSpan>,
+    /// For a runtime error described at [`Verbosity::Verbose`], the
+    /// instructions of the failing statement, the failing one marked with
+    /// `>` (see [`crate::error::RuntimeError::bytecode_listing`])
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bytecode: Vec<String>,
+}
+
+/// A labelled source extent that helps explain a diagnostic
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RelatedSpan {
+    /// What happens at the span, such as `call to f`
+    pub message: String,
+    pub span: Span,
+}
+
+impl Diagnostic {
+    /// An error reported by `stage`, with no span or notes
+    pub fn error(stage: Stage, message: impl Into<String>) -> Self {
+        Self {
+            stage,
+            severity: Severity::Error,
+            code: None,
+            message: message.into(),
+            span: None,
+            notes: Vec::new(),
+            related: Vec::new(),
+            bytecode: Vec::new(),
+        }
+    }
+
+    /// The diagnostic describing `error` in as much detail as `verbosity`
+    /// asks for
+    ///
+    /// Only [`Verbosity::Verbose`] adds to what [`Diagnostic::from`] gives:
+    /// a runtime error's [`Diagnostic::bytecode`].
+    pub fn from_error(error: &PyRustError, verbosity: Verbosity) -> Self {
+        let bytecode = match error {
+            PyRustError::RuntimeError(e) if verbosity == Verbosity::Verbose => e.bytecode_listing(),
+            _ => Vec::new(),
+        };
+        Self {
+            bytecode,
+            ..Self::from(error)
+        }
+    }
+
+    /// Serialize as a single line of JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("diagnostics always serialize")
+    }
+
+    /// Read a diagnostic written by [`Diagnostic::to_json`]
+    pub fn from_json(json: &str) -> Option<Self> {
+        serde_json::from_str(json).ok()
+    }
+
+    /// Format at the given level of detail, as
+    /// [`PyRustError::render_with`] does
+    pub fn render_with(&self, source: Option<&str>, verbosity: Verbosity) -> String {
+        match verbosity {
+            Verbosity::Short => first_line(&self.to_string()),
+            Verbosity::Normal => self.render(source),
+            Verbosity::Verbose => with_details(
+                self.render(source),
+                self.code.as_deref().and_then(ErrorCode::from_code),
+                &self.bytecode,
+            ),
+        }
+    }
+
+    /// Format with the offending line of `source` and carets under the
+    /// span, laid out as [`PyRustError::render`] does
+    ///
+    /// Without a span or a matching line, this is the `Display` output.
+    pub fn render(&self, source: Option<&str>) -> String {
+        let text = self.to_string();
+        let Some(span) = self.span else {
+            return text;
+        };
+        let Some(source_line) =
+            source.and_then(|source| source.lines().nth(span.line.checked_sub(1)?))
+        else {
+            return text;
+        };
+        let end_column = if span.end_line > span.line {
+            usize::MAX
+        } else {
+            span.end_column
+        };
+        let mut lines = text.lines();
+        let mut out = format!(
+            "{}\n{}",
+            lines.next().unwrap_or_default(),
+            snippet(source_line, span.column, end_column)
+        );
+        for note in lines {
+            out.push('\n');
+            out.push_str(note);
+        }
+        out
+    }
+
+    /// Name the diagnostic is shown under
+    fn heading(&self) -> &'static str {
+        match (self.severity, self.stage) {
+            (Severity::Warning, _) => "Warning",
+            (Severity::Error, Stage::Lex) => "LexError",
+            (Severity::Error, Stage::Parse) => "ParseError",
+            (Severity::Error, Stage::Compile) => "CompileError",
+            (Severity::Error, Stage::Runtime) => "RuntimeError",
+            (Severity::Error, Stage::Load) => "BytecodeError",
+        }
+    }
+}
+
+/// The heading, position and message as the matching [`PyRustError`] or
+/// [`CompileWarning`] shows them, then each note on its own indented line
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.heading())?;
+        if let Some(code) = &self.code {
+            write!(f, "[{}]", code)?;
+        }
+        match self.span {
+            Some(span) if self.stage == Stage::Runtime => {
+                write!(f, " at line {}, column {}", span.line, span.column)?
+            }
+            Some(span) => write!(f, " at {}:{}", span.line, span.column)?,
+            None => {}
+        }
+        write!(f, ": {}", self.message)?;
+        for note in &self.notes {
+            write!(f, "\n  {}", note)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<&PyRustError> for Diagnostic {
+    fn from(error: &PyRustError) -> Self {
+        let diagnostic = match error {
+            PyRustError::LexError(e) => Self {
+                span: Some(Span::on_line(e.line, e.column, e.end_column)),
+                ..Self::error(Stage::Lex, &e.message)
+            },
+            PyRustError::ParseError(e) => Self {
+                span: Some(Span::on_line(e.line, e.column, e.end_column)),
+                notes: vec![
+                    format!("Found: {}", e.found_token),
+                    format!("Expected: {}", e.expected_tokens.join(" | ")),
+                ],
+                ..Self::error(Stage::Parse, &e.message)
+            },
+            PyRustError::CompileError(e) => Self {
+                span: e.location.map(|pos| match e.end {
+                    Some(end) => Span::new(pos, end),
+                    None => Span::point(pos),
+                }),
+                ..Self::error(Stage::Compile, &e.message)
+            },
+            PyRustError::RuntimeError(e) => {
+                let message = format!("{}: {}", e.kind, e.message);
+                // Each caller's frame points at its call to the next one
+                let mut related: Vec<RelatedSpan> = e
+                    .traceback
+                    .windows(2)
+                    .filter_map(|pair| {
+                        let l = pair[0].location.as_ref()?;
+                        Some(RelatedSpan {
+                            message: format!("call to {}", pair[1].function_name()),
+                            span: Span::on_line(l.line, l.column, l.end_column),
+                        })
+                    })
+                    .collect();
+                related.dedup();
+                let traceback = e.traceback.iter().map(|frame| match &frame.location {
+                    Some(l) if !l.source_line.is_empty() => {
+                        format!("{}: {}", frame.describe(), l.source_line.trim())
+                    }
+                    _ => frame.describe(),
+                });
+                match &e.location {
+                    Some(l) => Self {
+                        span: Some(Span::on_line(l.line, l.column, l.end_column)),
+                        notes: traceback.collect(),
+                        related,
+                        ..Self::error(Stage::Runtime, message)
+                    },
+                    None => Self {
+                        notes: std::iter::once(format!("at instruction {}", e.instruction_index))
+                            .chain(traceback)
+                            .collect(),
+                        related,
+                        ..Self::error(Stage::Runtime, message)
+                    },
+                }
+            }
+            PyRustError::BytecodeError(e) => Self::error(Stage::Load, e.to_string()),
+        };
+        Self {
+            code: Some(error.code().to_string()),
+            ..diagnostic
+        }
+    }
+}
+
+impl From<&CompileWarning> for Diagnostic {
+    fn from(warning: &CompileWarning) -> Self {
+        let stage = match warning.kind {
+            WarningKind::MixedIndentation => Stage::Lex,
+            WarningKind::UnreachableCode => Stage::Parse,
+            _ => Stage::Compile,
+        };
+        Self {
+            severity: Severity::Warning,
+            code: Some(warning.kind.code().to_string()),
+            span: warning.pos.map(|pos| match warning.end {
+                Some(end) => Span::new(pos, end),
+                None => Span::point(pos),
+            }),
+            ..Self::error(stage, &warning.message)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_matches_the_error() {
+        for code in [
+            "x = 1 $ 2",
+            "print(1 +)",
+            "def f(a):\n    return a\nf(1, 2)",
+            "x = 1\ny = x / 0",
+            "y = missing",
+        ] {
+            let error = crate::execute_python(code).unwrap_err();
+            let diagnostic = Diagnostic::from(&error);
+            assert_eq!(diagnostic.severity, Severity::Error);
+            assert_eq!(diagnostic.render(Some(code)), error.render(Some(code)));
+            for verbosity in [Verbosity::Short, Verbosity::Normal, Verbosity::Verbose] {
+                assert_eq!(
+                    Diagnostic::from_error(&error, verbosity).render_with(Some(code), verbosity),
+                    error.render_with(Some(code), verbosity)
+                );
+            }
+        }
+
+        let warning = CompileWarning {
+            kind: crate::warnings::WarningKind::DivisionByZero,
+            message: "division by zero will always raise".to_string(),
+            pos: Some(SourcePos { line: 2, column: 9 }),
+            end: Some(SourcePos {
+                line: 2,
+                column: 10,
+            }),
+        };
+        let diagnostic = Diagnostic::from(&warning);
+        assert_eq!(diagnostic.to_string(), warning.to_string());
+        assert_eq!(
+            diagnostic.render(Some("x = 1\ny = x / 0")),
+            "Warning[W0005] at 2:9: division by zero will always raise\n    y = x / 0\n            ^"
+        );
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let error = crate::execute_python("print(1 +)").unwrap_err();
+        let diagnostic = Diagnostic::from(&error);
+        let json = diagnostic.to_json();
+        assert!(json.starts_with(
+            "{\"stage\":\"parse\",\"severity\":\"error\",\"code\":\"E0007\",\"message\":\"Expected expression\",\"span\":{\"line\":1,\"column\":10,\"end_line\":1,\"end_column\":11}"
+        ));
+        assert_eq!(Diagnostic::from_json(&json), Some(diagnostic));
+        assert_eq!(Diagnostic::from_json("{}"), None);
+    }
+}
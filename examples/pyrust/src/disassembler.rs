@@ -0,0 +1,121 @@
+//! Readable listings of compiled bytecode, for `pyrust --dis`
+//!
+//! [`disassemble`] lists a program's constant and name pools followed by its
+//! main code and each function chunk. Every instruction is shown with its
+//! offset within its chunk (the numbering jump targets use), the source
+//! position it was compiled from, and its operands resolved against the
+//! pools by [`Bytecode::describe_instruction`].
+//!
+//! # Example
+//!
+//! ```
+//! let bytecode = pyrust::compile_python("x = 5\nprint(x)").unwrap();
+//! let listing = pyrust::disassembler::disassemble(&bytecode);
+//! assert!(listing.contains("StoreVar x, r0"));
+//! ```
+
+use crate::bytecode::Bytecode;
+use std::fmt::Write;
+
+/// Render the whole program as text
+///
+/// Sections are `Constants`, `Names`, `Main` and one `Function` per chunk,
+/// in code address order. Empty pools are left out. Instructions without a
+/// recorded source position show `-` in place of `line:column`.
+pub fn disassemble(bytecode: &Bytecode) -> String {
+    let mut out = String::new();
+    if !bytecode.constants.is_empty() {
+        out.push_str("Constants:\n");
+        for (index, value) in bytecode.constants.iter().enumerate() {
+            let _ = writeln!(out, "  #{:<4} {}", index, value);
+        }
+    }
+    if !bytecode.var_names.is_empty() {
+        out.push_str("Names:\n");
+        for (index, name) in bytecode.var_names.iter().enumerate() {
+            match bytecode.var_ids.get(index) {
+                Some(id) => {
+                    let _ = writeln!(out, "  #{:<4} {} (id {})", index, name, id);
+                }
+                None => {
+                    let _ = writeln!(out, "  #{:<4} {}", index, name);
+                }
+            }
+        }
+    }
+
+    let _ = writeln!(
+        out,
+        "Main (registers r0-r{}):",
+        bytecode.metadata.max_register_used
+    );
+    write_chunk(&mut out, bytecode, None);
+    for (index, chunk) in bytecode.functions.iter().enumerate() {
+        let name = bytecode
+            .var_names
+            .get(chunk.name_index)
+            .map_or("?", |n| n.as_str());
+        let _ = writeln!(
+            out,
+            "Function {}/{} (chunk {}, registers r0-r{}):",
+            name, chunk.param_count, index, chunk.max_register_used
+        );
+        write_chunk(&mut out, bytecode, Some(index));
+    }
+    out
+}
+
+/// One line per instruction of the main code (`None`) or a chunk
+fn write_chunk(out: &mut String, bytecode: &Bytecode, chunk: Option<usize>) {
+    let start = chunk.map_or(0, |index| bytecode.chunk_start(index));
+    for offset in 0..bytecode.chunk_code(chunk).len() {
+        let address = start + offset;
+        let position = bytecode.source_position(address).map_or_else(
+            || "-".to_string(),
+            |pos| format!("{}:{}", pos.line, pos.column),
+        );
+        let _ = writeln!(
+            out,
+            "  {:>4}  {:<7} {}",
+            offset,
+            position,
+            bytecode.describe_instruction(address)
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disassemble_lists_pools_and_chunks() {
+        let bytecode =
+            crate::compile_python("def f(a):\n    return a + 1\ny = f(2)\nprint(y)").unwrap();
+        // Definitions carry no source position; jump targets would count
+        // from the start of their chunk, like the offsets on the left
+        assert_eq!(
+            disassemble(&bytecode),
+            concat!(
+                "Constants:\n",
+                "  #0    2\n",
+                "  #1    1\n",
+                "Names:\n",
+                "  #0    f (id 0)\n",
+                "  #1    y (id 1)\n",
+                "Main (registers r0-r0):\n",
+                "     0  -       DefineFunction f/1, chunk 0\n",
+                "     1  3:7     LoadConst r0, 2\n",
+                "     2  3:5     Call r0, f(1 args from r0)\n",
+                "     3  3:1     StoreVar y, r0\n",
+                "     4  4:7     LoadVar r0, y\n",
+                "     5  4:1     Print r0\n",
+                "     6  4:1     Halt\n",
+                "Function f/1 (chunk 0, registers r0-r1):\n",
+                "     0  2:16    LoadConst r1, 1\n",
+                "     1  2:12    BinaryOp r1, r0 + r1\n",
+                "     2  2:5     Return r1\n",
+            )
+        );
+    }
+}
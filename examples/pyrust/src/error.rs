@@ -1,3 +1,7 @@
+use crate::ast::SourcePos;
+use crate::bytecode::Bytecode;
+use crate::bytecode_format::FormatError;
+use crate::error_code::ErrorCode;
 use std::fmt;
 
 /// All errors that can occur during Python execution
@@ -11,22 +15,30 @@ pub enum PyRustError {
     CompileError(CompileError),
     /// Runtime error during execution
     RuntimeError(RuntimeError),
+    /// Precompiled bytecode could not be loaded
+    BytecodeError(FormatError),
 }
 
 /// Lexer error with location information
 #[derive(Debug, Clone, PartialEq)]
 pub struct LexError {
+    pub code: ErrorCode,
     pub message: String,
     pub line: usize,
     pub column: usize,
+    /// Column just past the offending text, on `line`
+    pub end_column: usize,
 }
 
 /// Parser error with location information
 #[derive(Debug, Clone, PartialEq)]
 pub struct ParseError {
+    pub code: ErrorCode,
     pub message: String,
     pub line: usize,
     pub column: usize,
+    /// Column just past the found token, on `line`
+    pub end_column: usize,
     pub found_token: String,
     pub expected_tokens: Vec<String>,
 }
@@ -34,44 +46,713 @@ pub struct ParseError {
 /// Compiler error (should be rare in Phase 1)
 #[derive(Debug, Clone, PartialEq)]
 pub struct CompileError {
+    pub code: ErrorCode,
     pub message: String,
+    /// Source position of the offending code, when known
+    pub location: Option<SourcePos>,
+    /// Where the offending code ends (exclusive), when known
+    pub end: Option<SourcePos>,
 }
 
 /// Runtime error during execution
 #[derive(Debug, Clone, PartialEq)]
 pub struct RuntimeError {
     pub message: String,
-    /// Index into bytecode.instructions Vec (NOT byte offset)
+    /// Code address of the failing instruction (NOT byte offset); see
+    /// [`crate::bytecode::Bytecode::locate`]
     pub instruction_index: usize,
+    /// Python exception class this failure maps to
+    pub kind: ExceptionKind,
+    /// Stable code of the failure: the kind's own code, unless the error
+    /// reports a resource limit (see [`RuntimeError::with_code`])
+    pub code: ErrorCode,
+    /// Source position of the failing statement, when the bytecode has a line table
+    pub location: Option<SourceLocation>,
+    /// Function calls active when the error was raised, outermost first and
+    /// ending with the frame that raised it; empty if no call was active
+    pub traceback: Vec<TracebackFrame>,
+    /// Instructions of the failing statement, once attached by
+    /// [`RuntimeError::with_location`]; boxed to keep the error small
+    pub bytecode: Option<Box<BytecodeSpan>>,
+}
+
+/// The instructions compiled from the source line a runtime error was
+/// raised on, in the chunk of the failing instruction
+#[derive(Debug, Clone, PartialEq)]
+pub struct BytecodeSpan {
+    /// Code address and description of each instruction, in order
+    pub instructions: Vec<(usize, String)>,
+}
+
+/// One frame of a runtime error's traceback
+#[derive(Debug, Clone, PartialEq)]
+pub struct TracebackFrame {
+    /// Function running in the frame, `None` for the main program
+    pub function: Option<String>,
+    /// Code address the frame was at: the call it made, or for the last
+    /// frame the failing instruction
+    pub instruction_index: usize,
+    /// Source position of that instruction, once attached by
+    /// [`RuntimeError::with_location`]
+    pub location: Option<SourceLocation>,
+}
+
+impl TracebackFrame {
+    /// Name the frame is shown under, as Python shows it
+    pub fn function_name(&self) -> &str {
+        self.function.as_deref().unwrap_or("<module>")
+    }
+
+    /// Where the frame was, such as `line 4, in f`
+    pub fn describe(&self) -> String {
+        match &self.location {
+            Some(location) => format!("line {}, in {}", location.line, self.function_name()),
+            None => format!(
+                "instruction {}, in {}",
+                self.instruction_index,
+                self.function_name()
+            ),
+        }
+    }
+}
+
+/// Identical frames shown in full before the rest are summed up, as
+/// Python does for deep recursion
+const REPEATED_FRAMES_SHOWN: usize = 3;
+
+/// Where in the source a runtime error happened
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceLocation {
+    /// 1-indexed line number
+    pub line: usize,
+    /// 1-indexed column number
+    pub column: usize,
+    /// Column just past the offending expression, or past the end of the
+    /// line if the expression continues on the next one; `column + 1` when
+    /// only its start is known
+    pub end_column: usize,
+    /// Text of the offending source line, without its newline
+    pub source_line: String,
+}
+
+impl RuntimeError {
+    /// An error of `kind` raised at `instruction_index`, with no source
+    /// location or traceback yet
+    pub fn new(kind: ExceptionKind, message: impl Into<String>, instruction_index: usize) -> Self {
+        Self {
+            message: message.into(),
+            instruction_index,
+            kind,
+            code: kind.code(),
+            location: None,
+            traceback: Vec::new(),
+            bytecode: None,
+        }
+    }
+
+    /// Give the error a more specific code than its kind's, such as
+    /// [`ErrorCode::InstructionLimit`] for a run stopped by its budget
+    pub fn with_code(mut self, code: ErrorCode) -> Self {
+        self.code = code;
+        self
+    }
+
+    /// The exit code, if this is the SystemExit raised by `exit(n)`
+    pub fn exit_code(&self) -> Option<i32> {
+        if self.kind != ExceptionKind::SystemExit {
+            return None;
+        }
+        self.message.parse::<i64>().ok().map(|code| code as i32)
+    }
+
+    /// Attach the source location of the failing instruction
+    ///
+    /// Looks `instruction_index` up in the line table of the `bytecode` that
+    /// raised the error, and copies the matching line out of `source`; every
+    /// traceback frame gets its location the same way. Leaves a location
+    /// unset if its instruction has no recorded position.
+    pub fn with_location(mut self, bytecode: &Bytecode, source: &str) -> Self {
+        let locate = |address| {
+            let (pos, end) = bytecode.source_span(address)?;
+            let source_line = source.lines().nth(pos.line - 1).unwrap_or_default();
+            Some(SourceLocation {
+                line: pos.line,
+                column: pos.column,
+                end_column: end_column(pos, end, source_line),
+                source_line: source_line.to_string(),
+            })
+        };
+        if let Some(location) = locate(self.instruction_index) {
+            self.bytecode = Some(Box::new(BytecodeSpan {
+                instructions: statement_code(bytecode, self.instruction_index, location.line),
+            }));
+            self.location = Some(location);
+        }
+        for frame in &mut self.traceback {
+            if let Some(location) = locate(frame.instruction_index) {
+                frame.location = Some(location);
+            }
+        }
+        self
+    }
+
+    /// The instructions of [`RuntimeError::bytecode`], one line each, with
+    /// the failing one marked by `>`
+    pub fn bytecode_listing(&self) -> Vec<String> {
+        let Some(span) = &self.bytecode else {
+            return Vec::new();
+        };
+        span.instructions
+            .iter()
+            .map(|(address, instruction)| {
+                let marker = if *address == self.instruction_index {
+                    '>'
+                } else {
+                    ' '
+                };
+                format!("{} {:>4}  {}", marker, address, instruction)
+            })
+            .collect()
+    }
+
+    /// The traceback as shown under the error's heading, with `innermost`
+    /// in place of the failing frame's source line
+    ///
+    /// Source lines are shown without their indentation, as Python does.
+    /// Runs of more than [`REPEATED_FRAMES_SHOWN`] identical frames, as
+    /// deep recursion leaves, are cut short with a count of the rest.
+    fn format_traceback(&self, innermost: Option<&str>) -> String {
+        let mut out = String::from("Traceback (most recent call last):");
+        let last = self.traceback.len().saturating_sub(1);
+        let mut index = 0;
+        while index < self.traceback.len() {
+            let frame = &self.traceback[index];
+            let run = self.traceback[index..last]
+                .iter()
+                .take_while(|other| other.instruction_index == frame.instruction_index)
+                .count()
+                .max(1);
+            for _ in 0..run.min(REPEATED_FRAMES_SHOWN) {
+                out.push_str("\n  ");
+                out.push_str(&frame.describe());
+                let source_line = match &frame.location {
+                    _ if index == last => innermost.map(str::to_string),
+                    Some(location) if !location.source_line.is_empty() => {
+                        Some(format!("    {}", location.source_line.trim_start()))
+                    }
+                    _ => None,
+                };
+                if let Some(source_line) = source_line {
+                    out.push('\n');
+                    out.push_str(&source_line);
+                }
+            }
+            if run > REPEATED_FRAMES_SHOWN {
+                out.push_str(&format!(
+                    "\n  [Previous frame repeated {} more times]",
+                    run - REPEATED_FRAMES_SHOWN
+                ));
+            }
+            index += run;
+        }
+        out
+    }
+}
+
+/// Builtin exception classes that VM failures map to
+///
+/// Names follow Python's builtin exception hierarchy so that `except` clauses
+/// can match them by name via [`ExceptionKind::from_name`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExceptionKind {
+    /// Division or modulo by zero
+    ZeroDivisionError,
+    /// Reference to an undefined variable or function
+    NameError,
+    /// Operation applied to a value of the wrong type, or wrong argument count
+    TypeError,
+    /// Maximum call depth exceeded
+    RecursionError,
+    /// Integer arithmetic overflowed i64
+    OverflowError,
+    /// Standard input exhausted while reading a line
+    EOFError,
+    /// Index outside the bounds of a sequence, such as `argv(i)` past the
+    /// last script argument
+    IndexError,
+    /// Argument of the right type but an unusable value, such as a script
+    /// argument that is not an integer
+    ValueError,
+    /// Raised by the `exit(n)` builtin to stop the program; the message is
+    /// the exit code
+    SystemExit,
+    /// The run went past the deadline it was given
+    TimeoutError,
+    /// Internal VM failure with no more specific class
+    RuntimeError,
+}
+
+impl ExceptionKind {
+    /// All builtin exception kinds
+    pub const ALL: [ExceptionKind; 11] = [
+        ExceptionKind::ZeroDivisionError,
+        ExceptionKind::NameError,
+        ExceptionKind::TypeError,
+        ExceptionKind::RecursionError,
+        ExceptionKind::OverflowError,
+        ExceptionKind::EOFError,
+        ExceptionKind::IndexError,
+        ExceptionKind::ValueError,
+        ExceptionKind::SystemExit,
+        ExceptionKind::TimeoutError,
+        ExceptionKind::RuntimeError,
+    ];
+
+    /// Python class name of the exception
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExceptionKind::ZeroDivisionError => "ZeroDivisionError",
+            ExceptionKind::NameError => "NameError",
+            ExceptionKind::TypeError => "TypeError",
+            ExceptionKind::RecursionError => "RecursionError",
+            ExceptionKind::OverflowError => "OverflowError",
+            ExceptionKind::EOFError => "EOFError",
+            ExceptionKind::IndexError => "IndexError",
+            ExceptionKind::ValueError => "ValueError",
+            ExceptionKind::SystemExit => "SystemExit",
+            ExceptionKind::TimeoutError => "TimeoutError",
+            ExceptionKind::RuntimeError => "RuntimeError",
+        }
+    }
+
+    /// Stable code of the exception
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            ExceptionKind::ZeroDivisionError => ErrorCode::ZeroDivision,
+            ExceptionKind::NameError => ErrorCode::UndefinedName,
+            ExceptionKind::TypeError => ErrorCode::WrongType,
+            ExceptionKind::RecursionError => ErrorCode::RecursionLimit,
+            ExceptionKind::OverflowError => ErrorCode::Overflow,
+            ExceptionKind::EOFError => ErrorCode::EndOfInput,
+            ExceptionKind::IndexError => ErrorCode::IndexOutOfRange,
+            ExceptionKind::ValueError => ErrorCode::InvalidValue,
+            ExceptionKind::SystemExit => ErrorCode::Exit,
+            ExceptionKind::TimeoutError => ErrorCode::Timeout,
+            ExceptionKind::RuntimeError => ErrorCode::InternalError,
+        }
+    }
+
+    /// Look up an exception kind by its Python class name
+    pub fn from_name(name: &str) -> Option<ExceptionKind> {
+        Self::ALL.iter().copied().find(|kind| kind.name() == name)
+    }
+
+    /// Whether an `except <name>` clause catches this exception
+    ///
+    /// `BaseException` catches every builtin kind and `Exception` every one
+    /// but SystemExit, `ArithmeticError` catches the arithmetic kinds and
+    /// `LookupError` catches IndexError and `OSError` TimeoutError, matching
+    /// Python's class hierarchy.
+    pub fn is_caught_by(&self, name: &str) -> bool {
+        match name {
+            "BaseException" => true,
+            "Exception" => *self != ExceptionKind::SystemExit,
+            "ArithmeticError" => matches!(
+                self,
+                ExceptionKind::ZeroDivisionError | ExceptionKind::OverflowError
+            ),
+            "LookupError" => *self == ExceptionKind::IndexError,
+            "OSError" => *self == ExceptionKind::TimeoutError,
+            _ => self.name() == name,
+        }
+    }
+}
+
+impl fmt::Display for ExceptionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
 }
 
 impl fmt::Display for PyRustError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            PyRustError::LexError(e) => {
-                write!(f, "LexError at {}:{}: {}", e.line, e.column, e.message)
-            }
+            PyRustError::LexError(e) => write!(
+                f,
+                "LexError[{}] at {}:{}: {}",
+                e.code, e.line, e.column, e.message
+            ),
             PyRustError::ParseError(e) => write!(
                 f,
-                "ParseError at {}:{}: {}\n  Found: {}\n  Expected: {}",
+                "ParseError[{}] at {}:{}: {}\n  Found: {}\n  Expected: {}",
+                e.code,
                 e.line,
                 e.column,
                 e.message,
                 e.found_token,
                 e.expected_tokens.join(" | ")
             ),
-            PyRustError::CompileError(e) => write!(f, "CompileError: {}", e.message),
-            PyRustError::RuntimeError(e) => write!(
-                f,
-                "RuntimeError at instruction {}: {}",
-                e.instruction_index, e.message
-            ),
+            PyRustError::CompileError(e) => match e.location {
+                Some(pos) => write!(
+                    f,
+                    "CompileError[{}] at {}:{}: {}",
+                    e.code, pos.line, pos.column, e.message
+                ),
+                None => write!(f, "CompileError[{}]: {}", e.code, e.message),
+            },
+            PyRustError::BytecodeError(e) => write!(f, "BytecodeError[{}]: {}", e.code(), e),
+            PyRustError::RuntimeError(e) => {
+                match &e.location {
+                    Some(location) => write!(
+                        f,
+                        "RuntimeError[{}] at line {}, column {}: {}: {}",
+                        e.code, location.line, location.column, e.kind, e.message
+                    )?,
+                    None => write!(
+                        f,
+                        "RuntimeError[{}] at instruction {}: {}: {}",
+                        e.code, e.instruction_index, e.kind, e.message
+                    )?,
+                }
+                // Bytecode loaded from a file has positions but no source text
+                let source_line = e
+                    .location
+                    .as_ref()
+                    .map(|location| location.source_line.as_str())
+                    .filter(|source_line| !source_line.is_empty());
+                match source_line {
+                    _ if !e.traceback.is_empty() => {
+                        let innermost =
+                            source_line.map(|line| format!("    {}", line.trim_start()));
+                        write!(f, "\n{}", e.format_traceback(innermost.as_deref()))
+                    }
+                    Some(source_line) => write!(f, "\n    {}", source_line),
+                    None => Ok(()),
+                }
+            }
         }
     }
 }
 
 impl std::error::Error for PyRustError {}
 
+impl PyRustError {
+    /// The exit code, if the program stopped by calling `exit(n)`
+    ///
+    /// Such a program did not fail: a CLI should exit with the code rather
+    /// than report an error.
+    pub fn exit_code(&self) -> Option<i32> {
+        match self {
+            PyRustError::RuntimeError(e) => e.exit_code(),
+            _ => None,
+        }
+    }
+
+    /// Stable code of the kind of error, shown in its message
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            PyRustError::LexError(e) => e.code,
+            PyRustError::ParseError(e) => e.code,
+            PyRustError::CompileError(e) => e.code,
+            PyRustError::RuntimeError(e) => e.code,
+            PyRustError::BytecodeError(e) => e.code(),
+        }
+    }
+
+    /// Whether the run was stopped for going past its deadline
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, PyRustError::RuntimeError(e) if e.kind == ExceptionKind::TimeoutError)
+    }
+
+    /// Line, column and end column the error points at, when known
+    ///
+    /// A compile error's end column may be past the end of its line.
+    fn span(&self) -> Option<(usize, usize, usize)> {
+        match self {
+            PyRustError::LexError(e) => Some((e.line, e.column, e.end_column)),
+            PyRustError::ParseError(e) => Some((e.line, e.column, e.end_column)),
+            PyRustError::CompileError(e) => e.location.map(|pos| {
+                let end = e.end.unwrap_or(pos);
+                let end_column = if end.line == pos.line {
+                    end.column
+                } else {
+                    usize::MAX
+                };
+                (pos.line, pos.column, end_column)
+            }),
+            PyRustError::RuntimeError(e) => {
+                let l = e.location.as_ref()?;
+                Some((l.line, l.column, l.end_column))
+            }
+            PyRustError::BytecodeError(_) => None,
+        }
+    }
+
+    /// Format at the given level of detail
+    ///
+    /// [`Verbosity::Normal`] is [`PyRustError::render`]; see [`Verbosity`]
+    /// for the others.
+    ///
+    /// ```
+    /// use pyrust::error::Verbosity;
+    ///
+    /// let code = "x = 0\nprint(1 / x)";
+    /// let error = pyrust::execute_python(code).unwrap_err();
+    /// assert_eq!(
+    ///     error.render_with(Some(code), Verbosity::Short),
+    ///     "RuntimeError[E2001] at line 2, column 7: ZeroDivisionError: Division by zero"
+    /// );
+    /// let verbose = error.render_with(Some(code), Verbosity::Verbose);
+    /// assert!(verbose.contains("\n  hint: "));
+    /// assert!(verbose.contains("\n  bytecode:\n"));
+    /// ```
+    pub fn render_with(&self, source: Option<&str>, verbosity: Verbosity) -> String {
+        match verbosity {
+            Verbosity::Short => first_line(&self.to_string()),
+            Verbosity::Normal => self.render(source),
+            Verbosity::Verbose => {
+                let bytecode = match self {
+                    PyRustError::RuntimeError(e) => e.bytecode_listing(),
+                    _ => Vec::new(),
+                };
+                with_details(self.render(source), Some(self.code()), &bytecode)
+            }
+        }
+    }
+
+    /// Format with the offending source line and carets under the span
+    ///
+    /// The line follows the first line of the `Display` output, as laid out
+    /// by [`snippet`]; any further details come after. In a runtime error
+    /// with a traceback, the marked line ends the traceback instead. The
+    /// line is taken from the error itself for a runtime error, otherwise
+    /// from `source`, the code the error came from. Without a position or a
+    /// line, this is the `Display` output.
+    pub fn render(&self, source: Option<&str>) -> String {
+        let text = self.to_string();
+        let Some((line, column, end_column)) = self.span() else {
+            return text;
+        };
+        let own_line = match self {
+            PyRustError::RuntimeError(e) => e.location.as_ref().map(|l| l.source_line.as_str()),
+            _ => None,
+        };
+        let source_line = own_line
+            .filter(|own| !own.is_empty())
+            .or_else(|| source.and_then(|source| source.lines().nth(line.checked_sub(1)?)));
+        let Some(source_line) = source_line else {
+            return text;
+        };
+
+        let marked = snippet(source_line, column, end_column);
+        let mut lines = text.lines();
+        let heading = lines.next().unwrap_or_default();
+        match self {
+            // The failing frame's line is marked, unindented, where the
+            // traceback ends
+            PyRustError::RuntimeError(e) if !e.traceback.is_empty() => {
+                let trimmed = source_line.trim_start();
+                let indent = source_line.chars().count() - trimmed.chars().count();
+                let marked = snippet(
+                    trimmed,
+                    column.saturating_sub(indent).max(1),
+                    end_column.saturating_sub(indent),
+                );
+                format!("{}\n{}", heading, e.format_traceback(Some(&marked)))
+            }
+            // A runtime error's only other detail is the line marked here
+            PyRustError::RuntimeError(_) => format!("{}\n{}", heading, marked),
+            _ => {
+                let mut out = format!("{}\n{}", heading, marked);
+                for detail in lines {
+                    out.push('\n');
+                    out.push_str(detail);
+                }
+                out
+            }
+        }
+    }
+}
+
+/// Instructions of the chunk holding `address` that were compiled from
+/// source line `line`, as code address and description
+fn statement_code(bytecode: &Bytecode, address: usize, line: usize) -> Vec<(usize, String)> {
+    let Some((chunk, _)) = bytecode.locate(address) else {
+        return Vec::new();
+    };
+    let start = chunk.map_or(0, |index| bytecode.chunk_start(index));
+    (start..start + bytecode.chunk_code(chunk).len())
+        .filter(|&address| bytecode.source_position(address).map(|pos| pos.line) == Some(line))
+        .map(|address| (address, bytecode.describe_instruction(address)))
+        .collect()
+}
+
+/// Column just past a span from `pos` to `end` on `pos`'s line
+fn end_column(pos: SourcePos, end: SourcePos, source_line: &str) -> usize {
+    if end.line > pos.line {
+        source_line.chars().count() + 1
+    } else {
+        end.column.max(pos.column + 1)
+    }
+}
+
+/// Format a source line and a line of `^` under columns `column` up to
+/// `end_column`, both indented by four spaces
+///
+/// Columns are 1-indexed and count characters, as the lexer does; the end
+/// is exclusive and is clamped to the end of the line. At least one `^` is
+/// drawn, even under an empty span or past the end of the line. Tabs before
+/// the span are kept so that it lines up however they are shown.
+///
+/// ```
+/// let snippet = pyrust::error::snippet("y = x / 0", 5, 10);
+/// assert_eq!(snippet, "    y = x / 0\n        ^^^^^");
+/// ```
+pub fn snippet(source_line: &str, column: usize, end_column: usize) -> String {
+    // Byte offset of a column, or the end of the line past its last one
+    let byte_offset = |column: usize| {
+        source_line
+            .char_indices()
+            .nth(column.saturating_sub(1))
+            .map_or(source_line.len(), |(offset, _)| offset)
+    };
+    let (start, end) = (byte_offset(column), byte_offset(end_column));
+    let indent: String = source_line[..start]
+        .chars()
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let width = source_line[start..end.max(start)].chars().count().max(1);
+    format!("    {}\n    {}{}", source_line, indent, "^".repeat(width))
+}
+
+/// The first line of a formatted diagnostic
+pub fn first_line(text: &str) -> String {
+    text.lines().next().unwrap_or_default().to_string()
+}
+
+/// `rendered` followed by the explanation of `code`, then the failing
+/// statement's `bytecode`, as [`Verbosity::Verbose`] shows them
+pub(crate) fn with_details(
+    mut rendered: String,
+    code: Option<ErrorCode>,
+    bytecode: &[String],
+) -> String {
+    if let Some(code) = code {
+        for (index, line) in code.explanation().lines().enumerate() {
+            let prefix = if index == 0 { "hint: " } else { "      " };
+            rendered.push('\n');
+            rendered.push_str(format!("  {}{}", prefix, line).trim_end());
+        }
+    }
+    if !bytecode.is_empty() {
+        rendered.push_str("\n  bytecode:");
+        for line in bytecode {
+            rendered.push_str("\n    ");
+            rendered.push_str(line);
+        }
+    }
+    rendered
+}
+
+/// How much detail to show with a diagnostic, as chosen by a CLI's
+/// `--verbosity` option
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Verbosity {
+    /// Only the heading, position and message, on one line
+    Short,
+    /// Also the source line with carets under the span, and details such as
+    /// a traceback
+    #[default]
+    Normal,
+    /// Also the explanation of the error code and, for a runtime error, the
+    /// bytecode of the failing statement
+    Verbose,
+}
+
+impl Verbosity {
+    /// Parse `short`, `normal` or `verbose`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "short" => Some(Verbosity::Short),
+            "normal" => Some(Verbosity::Normal),
+            "verbose" => Some(Verbosity::Verbose),
+            _ => None,
+        }
+    }
+}
+
+/// When to color diagnostics, as chosen by a CLI's `--color` option
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Color a terminal, unless `NO_COLOR` is set
+    #[default]
+    Auto,
+    /// Always color
+    Always,
+    /// Never color
+    Never,
+}
+
+impl ColorChoice {
+    /// Parse `auto`, `always` or `never`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "auto" => Some(ColorChoice::Auto),
+            "always" => Some(ColorChoice::Always),
+            "never" => Some(ColorChoice::Never),
+            _ => None,
+        }
+    }
+
+    /// Whether to color output going to a terminal or not
+    ///
+    /// `NO_COLOR` counts when set to anything but the empty string, as
+    /// <https://no-color.org> asks.
+    pub fn enabled(self, is_terminal: bool) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                is_terminal && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+            }
+        }
+    }
+}
+
+/// Add ANSI colors to a formatted error
+///
+/// The heading of the first line, up to its first `: `, is shown in bold
+/// red and the rest of it in bold; caret lines, as added by
+/// [`PyRustError::render`], in red. Other lines are left as they are.
+pub fn colorize(text: &str) -> String {
+    const RED: &str = "\x1b[31m";
+    const BOLD: &str = "\x1b[1m";
+    const BOLD_RED: &str = "\x1b[1;31m";
+    const RESET: &str = "\x1b[0m";
+
+    let mut out = String::new();
+    for (index, line) in text.lines().enumerate() {
+        if index > 0 {
+            out.push('\n');
+        }
+        let is_caret = line.trim_start().starts_with('^') && line.trim().chars().all(|c| c == '^');
+        if index == 0 {
+            match line.split_once(": ") {
+                Some((heading, rest)) => {
+                    out.push_str(&format!("{BOLD_RED}{heading}:{RESET} {BOLD}{rest}{RESET}"))
+                }
+                None => out.push_str(&format!("{BOLD_RED}{line}{RESET}")),
+            }
+        } else if is_caret {
+            let (indent, carets) = line.split_at(line.len() - line.trim_start().len());
+            out.push_str(&format!("{indent}{RED}{carets}{RESET}"));
+        } else {
+            out.push_str(line);
+        }
+    }
+    out
+}
+
 // Conversion traits for ergonomic error propagation with ? operator
 impl From<LexError> for PyRustError {
     fn from(e: LexError) -> Self {
@@ -97,6 +778,12 @@ impl From<RuntimeError> for PyRustError {
     }
 }
 
+impl From<FormatError> for PyRustError {
+    fn from(e: FormatError) -> Self {
+        PyRustError::BytecodeError(e)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -104,26 +791,30 @@ mod tests {
     #[test]
     fn test_lex_error_display() {
         let err = LexError {
+            code: ErrorCode::UnexpectedCharacter,
             message: "Unexpected character".to_string(),
             line: 1,
             column: 5,
+            end_column: 6,
         };
         let display = format!("{}", PyRustError::from(err));
-        assert!(display.contains("LexError at 1:5"));
+        assert!(display.contains("LexError[E0001] at 1:5"));
         assert!(display.contains("Unexpected character"));
     }
 
     #[test]
     fn test_parse_error_display() {
         let err = ParseError {
+            code: ErrorCode::ExpectedExpression,
             message: "Expected expression".to_string(),
             line: 2,
             column: 10,
+            end_column: 11,
             found_token: "+".to_string(),
             expected_tokens: vec!["integer".to_string(), "identifier".to_string()],
         };
         let display = format!("{}", PyRustError::from(err));
-        assert!(display.contains("ParseError at 2:10"));
+        assert!(display.contains("ParseError[E0007] at 2:10"));
         assert!(display.contains("Expected expression"));
         assert!(display.contains("Found: +"));
         assert!(display.contains("integer | identifier"));
@@ -132,30 +823,247 @@ mod tests {
     #[test]
     fn test_compile_error_display() {
         let err = CompileError {
+            code: ErrorCode::TooManyRegisters,
             message: "Register overflow".to_string(),
+            location: None,
+            end: None,
         };
         let display = format!("{}", PyRustError::from(err));
-        assert!(display.contains("CompileError"));
+        assert!(display.contains("CompileError[E1005]: "));
         assert!(display.contains("Register overflow"));
+
+        let err = CompileError {
+            code: ErrorCode::ArgumentCount,
+            message: "Function f expects 2 arguments, got 1".to_string(),
+            location: Some(SourcePos { line: 3, column: 7 }),
+            end: None,
+        };
+        assert_eq!(
+            PyRustError::from(err).to_string(),
+            "CompileError[E1001] at 3:7: Function f expects 2 arguments, got 1"
+        );
     }
 
     #[test]
     fn test_runtime_error_display() {
+        let err = RuntimeError::new(ExceptionKind::ZeroDivisionError, "Division by zero", 42);
+        let display = format!("{}", PyRustError::from(err));
+        assert!(display.contains("RuntimeError[E2001] at instruction 42"));
+        assert!(display.contains("ZeroDivisionError: Division by zero"));
+    }
+
+    #[test]
+    fn test_runtime_error_display_with_location() {
         let err = RuntimeError {
-            message: "Division by zero".to_string(),
-            instruction_index: 42,
+            location: Some(SourceLocation {
+                line: 3,
+                column: 1,
+                end_column: 2,
+                source_line: "y = x / 0".to_string(),
+            }),
+            ..RuntimeError::new(ExceptionKind::ZeroDivisionError, "Division by zero", 7)
         };
         let display = format!("{}", PyRustError::from(err));
-        assert!(display.contains("RuntimeError at instruction 42"));
-        assert!(display.contains("Division by zero"));
+        assert_eq!(
+            display,
+            "RuntimeError[E2001] at line 3, column 1: ZeroDivisionError: Division by zero\n    y = x / 0"
+        );
+    }
+
+    #[test]
+    fn test_render_marks_the_span() {
+        let err = ParseError {
+            code: ErrorCode::ExpectedExpression,
+            message: "Expected expression".to_string(),
+            line: 2,
+            column: 9,
+            end_column: 10,
+            found_token: ")".to_string(),
+            expected_tokens: vec!["integer".to_string()],
+        };
+        assert_eq!(
+            PyRustError::from(err).render(Some("x = 1\n\tprint(1 +)")),
+            "ParseError[E0007] at 2:9: Expected expression\n    \tprint(1 +)\n    \t       ^\n  Found: )\n  Expected: integer"
+        );
+
+        let err = RuntimeError {
+            location: Some(SourceLocation {
+                line: 1,
+                column: 5,
+                end_column: 10,
+                source_line: "y = x / 0".to_string(),
+            }),
+            ..RuntimeError::new(ExceptionKind::ZeroDivisionError, "Division by zero", 7)
+        };
+        assert_eq!(
+            PyRustError::from(err).render(None),
+            "RuntimeError[E2001] at line 1, column 5: ZeroDivisionError: Division by zero\n    y = x / 0\n        ^^^^^"
+        );
+
+        // A span running onto the next line is marked to the end of its first
+        let err = CompileError {
+            code: ErrorCode::ArgumentCount,
+            message: "Function f expects 1 arguments, got 2".to_string(),
+            location: Some(SourcePos { line: 1, column: 5 }),
+            end: Some(SourcePos { line: 2, column: 3 }),
+        };
+        assert_eq!(
+            PyRustError::from(err).render(Some("y = f(1,\n  2)")),
+            "CompileError[E1001] at 1:5: Function f expects 1 arguments, got 2\n    y = f(1,\n        ^^^^"
+        );
+
+        // Without the source there is nothing to point at
+        let err = LexError {
+            code: ErrorCode::UnexpectedCharacter,
+            message: "Unexpected character '$'".to_string(),
+            line: 1,
+            column: 3,
+            end_column: 4,
+        };
+        let err = PyRustError::from(err);
+        assert_eq!(err.render(None), err.to_string());
+    }
+
+    #[test]
+    fn test_traceback_lists_each_call() {
+        let code = "def f(d):\n    return 10 / d\ndef g(n):\n    return f(n) + 1\ny = g(0)";
+        let err = crate::execute_python(code).unwrap_err();
+        let PyRustError::RuntimeError(runtime) = &err else {
+            panic!("Expected RuntimeError, got {:?}", err);
+        };
+        let names: Vec<_> = runtime
+            .traceback
+            .iter()
+            .map(TracebackFrame::function_name)
+            .collect();
+        assert_eq!(names, vec!["<module>", "g", "f"]);
+        assert_eq!(
+            err.to_string(),
+            "RuntimeError[E2001] at line 2, column 12: ZeroDivisionError: Division by zero\n\
+             Traceback (most recent call last):\n  line 5, in <module>\n    y = g(0)\n  \
+             line 4, in g\n    return f(n) + 1\n  line 2, in f\n    return 10 / d"
+        );
+        // The failing line is marked without its indentation
+        assert!(err
+            .render(Some(code))
+            .ends_with("  line 2, in f\n    return 10 / d\n           ^^^^^^"));
+
+        // Errors outside any call have no traceback
+        match crate::execute_python("x = 1 / 0").unwrap_err() {
+            PyRustError::RuntimeError(e) => assert!(e.traceback.is_empty()),
+            other => panic!("Expected RuntimeError, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_traceback_cuts_recursion_short() {
+        let err = crate::execute_python("def r(n):\n    return r(n + 1)\nr(0)").unwrap_err();
+        let text = err.to_string();
+        assert_eq!(
+            text.matches("line 2, in r").count(),
+            REPEATED_FRAMES_SHOWN + 1
+        );
+        assert!(text.contains("\n  [Previous frame repeated 996 more times]\n  line 2, in r"));
+    }
+
+    #[test]
+    fn test_snippet_counts_characters() {
+        assert_eq!(snippet("\tx = 10", 6, 8), "    \tx = 10\n    \t    ^^");
+        assert_eq!(snippet("é = 1 $", 7, 8), "    é = 1 $\n          ^");
+        assert_eq!(snippet("x", 2, 2), "    x\n     ^");
+        assert_eq!(snippet("x = y", 5, usize::MAX), "    x = y\n        ^");
+    }
+
+    #[test]
+    fn test_render_marks_non_ascii_characters() {
+        let code = "x = 1 é 2";
+        let err = crate::execute_python(code).unwrap_err();
+        let PyRustError::LexError(e) = &err else {
+            panic!("expected a lex error, got {:?}", err);
+        };
+        assert_eq!((e.column, e.end_column), (7, 8));
+        assert_eq!(
+            err.render(Some(code)),
+            "LexError[E0001] at 1:7: Unexpected character 'é'\n    x = 1 é 2\n          ^"
+        );
+    }
+
+    #[test]
+    fn test_render_with_verbosity() {
+        let code = "def f(d):\n    return 10 / d\nprint(f(0))";
+        let err = crate::execute_python(code).unwrap_err();
+        assert_eq!(
+            err.render_with(Some(code), Verbosity::Short),
+            "RuntimeError[E2001] at line 2, column 12: ZeroDivisionError: Division by zero"
+        );
+        assert_eq!(
+            err.render_with(Some(code), Verbosity::Normal),
+            err.render(Some(code))
+        );
+
+        let verbose = err.render_with(Some(code), Verbosity::Verbose);
+        let details = verbose.strip_prefix(&err.render(Some(code))).unwrap();
+        // Only the function's own instructions, the failing one marked
+        assert_eq!(
+            details,
+            "\n  hint: The divisor of `/`, `//` or `%` was zero when the program ran, raising a\n        \
+             ZeroDivisionError.\n  bytecode:\n         5  LoadConst r1, 10\n    \
+             >    6  BinaryOp r1, r1 / r0\n         7  Return r1"
+        );
+
+        // Errors before running have no bytecode to show
+        let err = crate::execute_python("x = 1 $ 2").unwrap_err();
+        let verbose = err.render_with(Some("x = 1 $ 2"), Verbosity::Verbose);
+        assert!(verbose.contains("\n  hint: "));
+        assert!(!verbose.contains("bytecode:"));
+        assert_eq!(Verbosity::from_name("verbose"), Some(Verbosity::Verbose));
+        assert_eq!(Verbosity::from_name("loud"), None);
+    }
+
+    #[test]
+    fn test_colorize_heading_and_carets() {
+        assert_eq!(
+            colorize("RuntimeError[E1003] at line 1, column 1: NameError: x\n    x\n    ^"),
+            "\x1b[1;31mRuntimeError[E1003] at line 1, column 1:\x1b[0m \x1b[1mNameError: x\x1b[0m\n    x\n    \x1b[31m^\x1b[0m"
+        );
+        assert!(ColorChoice::Always.enabled(false));
+        assert!(!ColorChoice::Never.enabled(true));
+        assert!(!ColorChoice::Auto.enabled(false));
+        assert_eq!(ColorChoice::from_name("never"), Some(ColorChoice::Never));
+        assert_eq!(ColorChoice::from_name("yes"), None);
+    }
+
+    #[test]
+    fn test_exception_kind_names_round_trip() {
+        for kind in ExceptionKind::ALL {
+            assert_eq!(ExceptionKind::from_name(kind.name()), Some(kind));
+        }
+        assert_eq!(ExceptionKind::from_name("KeyError"), None);
+    }
+
+    #[test]
+    fn test_exception_kind_is_caught_by() {
+        let kind = ExceptionKind::ZeroDivisionError;
+        assert!(kind.is_caught_by("ZeroDivisionError"));
+        assert!(kind.is_caught_by("ArithmeticError"));
+        assert!(kind.is_caught_by("Exception"));
+        assert!(!kind.is_caught_by("NameError"));
+        assert!(!ExceptionKind::NameError.is_caught_by("ArithmeticError"));
+        assert!(ExceptionKind::IndexError.is_caught_by("LookupError"));
+        assert!(!ExceptionKind::ValueError.is_caught_by("LookupError"));
+        assert!(!ExceptionKind::SystemExit.is_caught_by("Exception"));
+        assert!(ExceptionKind::TimeoutError.is_caught_by("OSError"));
+        assert!(ExceptionKind::SystemExit.is_caught_by("BaseException"));
     }
 
     #[test]
     fn test_error_conversion_traits() {
         let lex_err = LexError {
+            code: ErrorCode::UnexpectedCharacter,
             message: "test".to_string(),
             line: 1,
             column: 1,
+            end_column: 2,
         };
         let _: PyRustError = lex_err.into();
         // Should compile successfully
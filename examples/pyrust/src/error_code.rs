@@ -0,0 +1,527 @@
+//! Stable codes for every kind of error and warning
+//!
+//! Each [`ErrorCode`] names one kind of problem and keeps its code across
+//! releases, whatever the wording of its message, so that scripts, editors
+//! and search engines can rely on it. Codes are grouped by the stage that
+//! reports them:
+//!
+//! - `E0xxx`: the source could not be tokenized or parsed
+//! - `E1xxx`: names and definitions, checked while compiling or running
+//! - `E2xxx`: other failures while running, one per exception class
+//! - `E3xxx`: a bytecode file could not be loaded
+//! - `W0xxx`: compile warnings
+//!
+//! Codes appear in every message (`LexError[E0001] at 1:5: ...`) and in the
+//! `code` field of a [`crate::diagnostic::Diagnostic`]; `pyrust explain
+//! <code>` prints the [`ErrorCode::explanation`].
+//!
+//! # Example
+//!
+//! ```
+//! use pyrust::error_code::ErrorCode;
+//!
+//! let error = pyrust::execute_python("x = 1 $ 2").unwrap_err();
+//! assert_eq!(error.code(), ErrorCode::UnexpectedCharacter);
+//! assert_eq!(error.code().as_str(), "E0001");
+//! assert_eq!(ErrorCode::from_code("e0001"), Some(ErrorCode::UnexpectedCharacter));
+//! ```
+
+use std::fmt;
+
+/// A stable identifier for a kind of error or warning
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    /// E0001: a character that starts no token
+    UnexpectedCharacter,
+    /// E0002: an integer literal outside the 64-bit range
+    IntegerTooLarge,
+    /// E0003: a line indented with both tabs and spaces
+    InconsistentIndentation,
+    /// E0004: a dedent to a column no enclosing block starts at
+    UnindentMismatch,
+    /// E0005: an indented line where no block starts
+    UnexpectedIndent,
+    /// E0006: a token missing from a statement
+    ExpectedToken,
+    /// E0007: a token where an expression should start
+    ExpectedExpression,
+    /// E1000: a compile failure with no more specific code, such as one
+    /// raised by a custom compiler pass
+    CompileFailed,
+    /// E1001: a call with the wrong number of arguments
+    ArgumentCount,
+    /// E1002: a `def` inside a function body
+    NestedFunction,
+    /// E1003: a variable or function that was never defined
+    UndefinedName,
+    /// E1004: a call to a function defined further down the program
+    FunctionUsedBeforeDefinition,
+    /// E1005: an expression needing more than 256 registers
+    TooManyRegisters,
+    /// E2001: division or modulo by zero
+    ZeroDivision,
+    /// E2002: an operation on a value of the wrong type
+    WrongType,
+    /// E2003: calls nested deeper than the call depth limit
+    RecursionLimit,
+    /// E2004: integer arithmetic outside the 64-bit range
+    Overflow,
+    /// E2005: reading a line after standard input ended
+    EndOfInput,
+    /// E2006: an index past the end of a sequence
+    IndexOutOfRange,
+    /// E2007: an argument of the right type with an unusable value
+    InvalidValue,
+    /// E2008: the program called `exit(n)`
+    Exit,
+    /// E2009: the program ran past its deadline
+    Timeout,
+    /// E2010: an internal VM failure
+    InternalError,
+    /// E2011: more instructions executed than the run's budget allows
+    InstructionLimit,
+    /// E2012: more print output than the run's output limit allows
+    OutputLimit,
+    /// E2013: calls nested deeper than the run's call depth limit
+    CallDepthLimit,
+    /// E2014: more memory held than the run's memory limit allows
+    MemoryLimit,
+    /// E2015: the run was cancelled before it finished
+    Cancelled,
+    /// E3001: the file is not pyrust bytecode
+    NotBytecode,
+    /// E3002: bytecode written by a different format version
+    UnsupportedVersion,
+    /// E3003: bytecode cut short or containing bytes that decode to nothing
+    CorruptBytecode,
+    /// E3004: bytecode that decodes but does not verify
+    MalformedBytecode,
+    /// E3005: the bytecode file could not be read
+    UnreadableFile,
+    /// W0001: a local variable that is never read
+    UnusedVariable,
+    /// W0002: a variable read before it is assigned
+    UseBeforeAssignment,
+    /// W0003: a parameter named like a global
+    ShadowedParameter,
+    /// W0004: a function that is never called
+    UnusedFunction,
+    /// W0005: a divisor that is always zero
+    ConstantZeroDivisor,
+    /// W0006: an indent made of both tabs and spaces
+    MixedIndentation,
+    /// W0007: a statement after a `return`
+    UnreachableCode,
+}
+
+impl ErrorCode {
+    /// All codes, in order
+    pub const ALL: [ErrorCode; 40] = [
+        ErrorCode::UnexpectedCharacter,
+        ErrorCode::IntegerTooLarge,
+        ErrorCode::InconsistentIndentation,
+        ErrorCode::UnindentMismatch,
+        ErrorCode::UnexpectedIndent,
+        ErrorCode::ExpectedToken,
+        ErrorCode::ExpectedExpression,
+        ErrorCode::CompileFailed,
+        ErrorCode::ArgumentCount,
+        ErrorCode::NestedFunction,
+        ErrorCode::UndefinedName,
+        ErrorCode::FunctionUsedBeforeDefinition,
+        ErrorCode::TooManyRegisters,
+        ErrorCode::ZeroDivision,
+        ErrorCode::WrongType,
+        ErrorCode::RecursionLimit,
+        ErrorCode::Overflow,
+        ErrorCode::EndOfInput,
+        ErrorCode::IndexOutOfRange,
+        ErrorCode::InvalidValue,
+        ErrorCode::Exit,
+        ErrorCode::Timeout,
+        ErrorCode::InternalError,
+        ErrorCode::InstructionLimit,
+        ErrorCode::OutputLimit,
+        ErrorCode::CallDepthLimit,
+        ErrorCode::MemoryLimit,
+        ErrorCode::Cancelled,
+        ErrorCode::NotBytecode,
+        ErrorCode::UnsupportedVersion,
+        ErrorCode::CorruptBytecode,
+        ErrorCode::MalformedBytecode,
+        ErrorCode::UnreadableFile,
+        ErrorCode::UnusedVariable,
+        ErrorCode::UseBeforeAssignment,
+        ErrorCode::ShadowedParameter,
+        ErrorCode::UnusedFunction,
+        ErrorCode::ConstantZeroDivisor,
+        ErrorCode::MixedIndentation,
+        ErrorCode::UnreachableCode,
+    ];
+
+    /// The code itself, such as `E1003`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::UnexpectedCharacter => "E0001",
+            ErrorCode::IntegerTooLarge => "E0002",
+            ErrorCode::InconsistentIndentation => "E0003",
+            ErrorCode::UnindentMismatch => "E0004",
+            ErrorCode::UnexpectedIndent => "E0005",
+            ErrorCode::ExpectedToken => "E0006",
+            ErrorCode::ExpectedExpression => "E0007",
+            ErrorCode::CompileFailed => "E1000",
+            ErrorCode::ArgumentCount => "E1001",
+            ErrorCode::NestedFunction => "E1002",
+            ErrorCode::UndefinedName => "E1003",
+            ErrorCode::FunctionUsedBeforeDefinition => "E1004",
+            ErrorCode::TooManyRegisters => "E1005",
+            ErrorCode::ZeroDivision => "E2001",
+            ErrorCode::WrongType => "E2002",
+            ErrorCode::RecursionLimit => "E2003",
+            ErrorCode::Overflow => "E2004",
+            ErrorCode::EndOfInput => "E2005",
+            ErrorCode::IndexOutOfRange => "E2006",
+            ErrorCode::InvalidValue => "E2007",
+            ErrorCode::Exit => "E2008",
+            ErrorCode::Timeout => "E2009",
+            ErrorCode::InternalError => "E2010",
+            ErrorCode::InstructionLimit => "E2011",
+            ErrorCode::OutputLimit => "E2012",
+            ErrorCode::CallDepthLimit => "E2013",
+            ErrorCode::MemoryLimit => "E2014",
+            ErrorCode::Cancelled => "E2015",
+            ErrorCode::NotBytecode => "E3001",
+            ErrorCode::UnsupportedVersion => "E3002",
+            ErrorCode::CorruptBytecode => "E3003",
+            ErrorCode::MalformedBytecode => "E3004",
+            ErrorCode::UnreadableFile => "E3005",
+            ErrorCode::UnusedVariable => "W0001",
+            ErrorCode::UseBeforeAssignment => "W0002",
+            ErrorCode::ShadowedParameter => "W0003",
+            ErrorCode::UnusedFunction => "W0004",
+            ErrorCode::ConstantZeroDivisor => "W0005",
+            ErrorCode::MixedIndentation => "W0006",
+            ErrorCode::UnreachableCode => "W0007",
+        }
+    }
+
+    /// Look up a code such as `E1003`, ignoring case
+    pub fn from_code(code: &str) -> Option<ErrorCode> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|c| c.as_str().eq_ignore_ascii_case(code))
+    }
+
+    /// One line saying what the code means
+    pub fn summary(&self) -> &'static str {
+        match self {
+            ErrorCode::UnexpectedCharacter => "unexpected character",
+            ErrorCode::IntegerTooLarge => "integer literal too large",
+            ErrorCode::InconsistentIndentation => "inconsistent use of tabs and spaces",
+            ErrorCode::UnindentMismatch => "unindent does not match any outer level",
+            ErrorCode::UnexpectedIndent => "unexpected indent",
+            ErrorCode::ExpectedToken => "expected token",
+            ErrorCode::ExpectedExpression => "expected expression",
+            ErrorCode::CompileFailed => "compilation failed",
+            ErrorCode::ArgumentCount => "wrong number of arguments",
+            ErrorCode::NestedFunction => "nested function definition",
+            ErrorCode::UndefinedName => "undefined variable",
+            ErrorCode::FunctionUsedBeforeDefinition => "function called before its definition",
+            ErrorCode::TooManyRegisters => "register limit exceeded",
+            ErrorCode::ZeroDivision => "division by zero",
+            ErrorCode::WrongType => "wrong type",
+            ErrorCode::RecursionLimit => "maximum call depth exceeded",
+            ErrorCode::Overflow => "integer overflow",
+            ErrorCode::EndOfInput => "end of input",
+            ErrorCode::IndexOutOfRange => "index out of range",
+            ErrorCode::InvalidValue => "invalid value",
+            ErrorCode::Exit => "program exited",
+            ErrorCode::Timeout => "timed out",
+            ErrorCode::InternalError => "internal error",
+            ErrorCode::InstructionLimit => "execution budget exceeded",
+            ErrorCode::OutputLimit => "output limit exceeded",
+            ErrorCode::CallDepthLimit => "call depth limit exceeded",
+            ErrorCode::MemoryLimit => "memory limit exceeded",
+            ErrorCode::Cancelled => "execution cancelled",
+            ErrorCode::NotBytecode => "not a bytecode file",
+            ErrorCode::UnsupportedVersion => "unsupported bytecode version",
+            ErrorCode::CorruptBytecode => "corrupt bytecode",
+            ErrorCode::MalformedBytecode => "malformed bytecode",
+            ErrorCode::UnreadableFile => "bytecode file could not be read",
+            ErrorCode::UnusedVariable => "unused variable",
+            ErrorCode::UseBeforeAssignment => "use before assignment",
+            ErrorCode::ShadowedParameter => "parameter shadows a global",
+            ErrorCode::UnusedFunction => "unused function",
+            ErrorCode::ConstantZeroDivisor => "division by a constant zero",
+            ErrorCode::MixedIndentation => "indent mixes tabs and spaces",
+            ErrorCode::UnreachableCode => "unreachable code",
+        }
+    }
+
+    /// A longer description of the problem and how to fix it, as printed
+    /// by `pyrust explain`
+    pub fn explanation(&self) -> &'static str {
+        match self {
+            ErrorCode::UnexpectedCharacter => {
+                "The source contains a character that does not start any token, such as `$`, `<`\n\
+                 or a quote. pyrust reads integers, names, the operators `+ - * / // % =`,\n\
+                 parentheses, commas and colons; strings and comparisons are not supported.\n\
+                 \n\
+                 Remove the character or rewrite the expression without it."
+            }
+            ErrorCode::IntegerTooLarge => {
+                "An integer literal does not fit in a signed 64-bit integer, the only integer\n\
+                 type pyrust has. Literals range from -9223372036854775808 to\n\
+                 9223372036854775807."
+            }
+            ErrorCode::InconsistentIndentation => {
+                "A line is indented with a mix of tabs and spaces, so its depth depends on how\n\
+                 wide a tab is taken to be. Indent each block with spaces only or tabs only."
+            }
+            ErrorCode::UnindentMismatch => {
+                "A line is indented less than the line before it, but not back to the column\n\
+                 of any enclosing block:\n\
+                 \n\
+                 \x20   def f(x):\n\
+                 \x20           y = x\n\
+                 \x20     return y\n\
+                 \n\
+                 Line a dedented statement up with the block it belongs to."
+            }
+            ErrorCode::UnexpectedIndent => {
+                "A line is indented although no block starts before it. Only the body of a\n\
+                 `def` is indented; remove the leading whitespace."
+            }
+            ErrorCode::ExpectedToken => {
+                "A statement is missing a token it needs, such as the `:` ending a `def` line\n\
+                 or the `)` closing a call. The message names the token and where it was\n\
+                 expected, and the error shows the token found instead."
+            }
+            ErrorCode::ExpectedExpression => {
+                "A value was needed, but the next token cannot start an expression, as in\n\
+                 `print(1 +)`. Expressions start with an integer, a name, `(`, `+` or `-`."
+            }
+            ErrorCode::CompileFailed => {
+                "Compilation failed for a reason with no more specific code, usually an error\n\
+                 raised by a custom compiler pass. The message says what went wrong."
+            }
+            ErrorCode::ArgumentCount => {
+                "A function is called with more or fewer arguments than it has parameters:\n\
+                 \n\
+                 \x20   def add(a, b):\n\
+                 \x20       return a + b\n\
+                 \x20   add(1)\n\
+                 \n\
+                 Calls to functions defined earlier in the program are checked while\n\
+                 compiling; other calls fail with a TypeError when they run."
+            }
+            ErrorCode::NestedFunction => {
+                "A `def` appears inside the body of another function. pyrust only supports\n\
+                 functions defined at the top level of a program; move the inner function out."
+            }
+            ErrorCode::UndefinedName => {
+                "A variable is read, or a function called, before anything of that name was\n\
+                 defined, raising a NameError:\n\
+                 \n\
+                 \x20   print(total)\n\
+                 \x20   total = 1\n\
+                 \n\
+                 Check the name for typos and assign the variable, or define the function,\n\
+                 before it is used."
+            }
+            ErrorCode::FunctionUsedBeforeDefinition => {
+                "A function is called at a point in the program before its `def` runs:\n\
+                 \n\
+                 \x20   print(f(1))\n\
+                 \x20   def f(x):\n\
+                 \x20       return x\n\
+                 \n\
+                 Move the `def` above the first call."
+            }
+            ErrorCode::TooManyRegisters => {
+                "An expression or function needs more than the 256 registers the VM has.\n\
+                 Split the expression into several assignments to smaller temporaries."
+            }
+            ErrorCode::ZeroDivision => {
+                "The divisor of `/`, `//` or `%` was zero when the program ran, raising a\n\
+                 ZeroDivisionError."
+            }
+            ErrorCode::WrongType => {
+                "An operation was applied to a value it does not accept, or a function\n\
+                 defined later was called with the wrong number of arguments, raising a\n\
+                 TypeError."
+            }
+            ErrorCode::RecursionLimit => {
+                "Function calls nested deeper than the VM's call depth limit, raising a\n\
+                 RecursionError. This usually means a recursive function is missing its base\n\
+                 case; otherwise rewrite the recursion as a loop."
+            }
+            ErrorCode::Overflow => {
+                "An addition, subtraction, multiplication or negation produced a result\n\
+                 outside the signed 64-bit range, raising an OverflowError. pyrust integers\n\
+                 do not grow past 64 bits."
+            }
+            ErrorCode::EndOfInput => {
+                "`input()` was called after standard input had ended, raising an EOFError."
+            }
+            ErrorCode::IndexOutOfRange => {
+                "An index is past the end of a sequence, such as `argv(i)` with `i` not less\n\
+                 than `argc()`, raising an IndexError."
+            }
+            ErrorCode::InvalidValue => {
+                "An argument has the right type but a value that cannot be used, such as a\n\
+                 script argument read with `argv(i)` that is not an integer, raising a\n\
+                 ValueError."
+            }
+            ErrorCode::Exit => {
+                "The program called `exit(n)`, raising SystemExit. The CLI exits with status\n\
+                 `n` and reports no error."
+            }
+            ErrorCode::Timeout => {
+                "The program ran past the deadline it was given, with `--timeout` or a\n\
+                 daemon's limit, raising a TimeoutError. The CLI exits with status 124."
+            }
+            ErrorCode::InternalError => {
+                "The VM failed in a way no more specific exception describes, such as a\n\
+                 register read before it was written. This points at a bug in pyrust; please\n\
+                 report it with the program that triggers it."
+            }
+            ErrorCode::InstructionLimit => {
+                "The program executed more instructions than its budget allows, as set by\n\
+                 `PYRUST_MAX_INSTRUCTIONS` or a daemon's `PYRUST_DAEMON_MAX_INSTRUCTIONS`.\n\
+                 Look for a runaway loop or recursion, or raise the budget."
+            }
+            ErrorCode::OutputLimit => {
+                "The program printed more bytes than its output limit allows, such as a\n\
+                 daemon's `PYRUST_DAEMON_MAX_OUTPUT_BYTES`. Print less, or raise the limit."
+            }
+            ErrorCode::CallDepthLimit => {
+                "Function calls nested deeper than the call depth limit set for the run, such\n\
+                 as a daemon's `PYRUST_DAEMON_MAX_CALL_DEPTH`, raising a RecursionError.\n\
+                 Without such a limit the VM's own maximum applies (E2003)."
+            }
+            ErrorCode::MemoryLimit => {
+                "The call frames and registers of the program held more bytes than its\n\
+                 memory limit allows, such as a daemon's `PYRUST_DAEMON_MAX_MEMORY_BYTES`.\n\
+                 Deep recursion is the usual cause."
+            }
+            ErrorCode::Cancelled => {
+                "The run was stopped through its cancellation token before it finished, for\n\
+                 instance by Ctrl-C or by a daemon client that cancelled the request or went\n\
+                 away."
+            }
+            ErrorCode::NotBytecode => {
+                "`pyrust run` was given a file that does not start with the bytecode magic\n\
+                 number. Compile a script with `pyrust --compile <file.py>` first, or run the\n\
+                 source directly."
+            }
+            ErrorCode::UnsupportedVersion => {
+                "The bytecode file was written by a pyrust with a different bytecode format.\n\
+                 Recompile the source with `pyrust --compile`."
+            }
+            ErrorCode::CorruptBytecode => {
+                "The bytecode file ends early, has bytes left over, or contains an opcode,\n\
+                 operator or name that does not decode. Recompile the source."
+            }
+            ErrorCode::MalformedBytecode => {
+                "The bytecode file decodes but fails verification, for instance because an\n\
+                 instruction jumps outside the program. Recompile the source."
+            }
+            ErrorCode::UnreadableFile => {
+                "The bytecode file could not be read; the message has the operating system's\n\
+                 reason."
+            }
+            ErrorCode::UnusedVariable => {
+                "A local variable of a function is assigned but its value is never read.\n\
+                 Remove the assignment, or use the value."
+            }
+            ErrorCode::UseBeforeAssignment => {
+                "A variable is read at a point where no assignment to it has run yet, which\n\
+                 raises a NameError (E1003) when that line runs."
+            }
+            ErrorCode::ShadowedParameter => {
+                "A function parameter has the name of a global variable or function, which\n\
+                 the function then cannot reach. Rename the parameter."
+            }
+            ErrorCode::UnusedFunction => {
+                "A function is defined but never called. Remove it, or call it."
+            }
+            ErrorCode::ConstantZeroDivisor => {
+                "A division or modulo has a divisor that is zero whatever the program's\n\
+                 input, so it always raises ZeroDivisionError (E2001). With\n\
+                 `--deny-division-by-zero` this is a compile error with the same code."
+            }
+            ErrorCode::MixedIndentation => {
+                "A line is indented with both tabs and spaces. pyrust accepts it as long as\n\
+                 its depth does not depend on the tab width (otherwise it is error E0003),\n\
+                 but editors set to another tab width may show the block misaligned.\n\
+                 Indent with spaces only or tabs only."
+            }
+            ErrorCode::UnreachableCode => {
+                "A statement follows a `return` in the same function body, so it never runs:\n\
+                 \n\
+                 \x20   def f(x):\n\
+                 \x20       return x\n\
+                 \x20       print(x)\n\
+                 \n\
+                 Remove it, or move it above the `return`."
+            }
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codes_are_unique_and_round_trip() {
+        let mut seen = std::collections::HashSet::new();
+        for code in ErrorCode::ALL {
+            assert!(seen.insert(code.as_str()), "{} is used twice", code);
+            assert_eq!(ErrorCode::from_code(code.as_str()), Some(code));
+            assert!(!code.summary().is_empty());
+            assert!(!code.explanation().ends_with('\n'));
+        }
+        assert_eq!(
+            ErrorCode::from_code("e1003"),
+            Some(ErrorCode::UndefinedName)
+        );
+        assert_eq!(ErrorCode::from_code("E9999"), None);
+    }
+
+    #[test]
+    fn test_errors_carry_their_code() {
+        for (code, expected) in [
+            ("x = 1 $ 2", ErrorCode::UnexpectedCharacter),
+            ("x = 99999999999999999999", ErrorCode::IntegerTooLarge),
+            ("x = 1\n    y = 2", ErrorCode::UnexpectedIndent),
+            ("def f(a)\n    return a", ErrorCode::ExpectedToken),
+            ("print(1 +)", ErrorCode::ExpectedExpression),
+            ("def f(a):\n    return a\nf(1, 2)", ErrorCode::ArgumentCount),
+            (
+                "print(f(1))\ndef f(a):\n    return a",
+                ErrorCode::FunctionUsedBeforeDefinition,
+            ),
+            ("y = missing", ErrorCode::UndefinedName),
+            ("x = 0\ny = 1 / x", ErrorCode::ZeroDivision),
+        ] {
+            let error = crate::execute_python(code).unwrap_err();
+            assert_eq!(error.code(), expected, "{}", code);
+            assert!(error.to_string().contains(&format!("[{}]", expected)));
+        }
+        assert_eq!(
+            crate::bytecode::Bytecode::from_bytes(b"nope")
+                .err()
+                .map(|e| e.code()),
+            Some(ErrorCode::NotBytecode)
+        );
+    }
+}
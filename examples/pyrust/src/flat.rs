@@ -0,0 +1,336 @@
+//! Flattened instruction encoding for the fast dispatch loop
+//!
+//! Available with the `fast-dispatch` feature. [`FlatCode::new`] lowers a
+//! [`Bytecode`] program one-to-one into fixed-size 8-byte [`FlatOp`]s, laid
+//! out by code address with every chunk's jump targets made absolute: a dense
+//! `u8` opcode, three register operands, and a 32-bit immediate. Arithmetic is
+//! specialized per operator, so the hot loop in
+//! [`crate::vm::VM::execute_flat`] is a single `match` on a byte that compiles
+//! to a jump table, with no second match on `BinaryOperator` and no pool
+//! bounds checks (these are validated once at lowering time).
+//!
+//! Rust has no guaranteed tail calls, so threaded "tail-call" dispatch is not
+//! expressible on stable; the jump table gets most of the benefit.
+//!
+//! Instructions without a fast form (calls, returns, function definitions,
+//! print, and operators that can raise for reasons other than overflow) are
+//! encoded as [`Opcode::Generic`] and run through the regular dispatcher.
+//! Fast forms that hit an error condition fall back the same way, so errors
+//! and their messages are identical to [`crate::vm::VM::execute`].
+//!
+//! # Example
+//!
+//! ```
+//! use pyrust::flat::FlatCode;
+//! use pyrust::value::Value;
+//! use pyrust::vm::VM;
+//! use pyrust::{compiler, lexer, parser};
+//!
+//! let program = parser::parse(lexer::lex("x = 6\nx * 7").unwrap()).unwrap();
+//! let code = FlatCode::new(compiler::compile(&program).unwrap());
+//!
+//! let mut vm = VM::new();
+//! assert_eq!(vm.execute_flat(&code).unwrap(), Some(Value::Integer(42)));
+//! ```
+
+use crate::ast::{BinaryOperator, UnaryOperator};
+use crate::bytecode::{Bytecode, Instruction};
+
+/// Operation selector of a [`FlatOp`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Opcode {
+    /// `r[a] = constants[imm]`
+    LoadConst,
+    /// `r[a] = variable imm` (locals, then globals)
+    LoadVar,
+    /// `variable imm = r[a]`
+    StoreVar,
+    /// `r[a] = r[b] + r[c]`
+    Add,
+    /// `r[a] = r[b] - r[c]`
+    Sub,
+    /// `r[a] = r[b] * r[c]`
+    Mul,
+    /// `r[a] = -r[b]`
+    Neg,
+    /// `r[a] = +r[b]`
+    Pos,
+    /// `r[a] = r[b]`
+    Move,
+    /// `result = r[a]`
+    SetResult,
+    /// `ip = imm`
+    Jump,
+    /// `if !r[a] { ip = imm }`
+    JumpIfFalse,
+    /// `if r[a] { ip = imm }`
+    JumpIfTrue,
+    /// Stop execution
+    Halt,
+    /// Run the original instruction through the regular dispatcher
+    Generic,
+}
+
+/// One fixed-size flattened instruction
+///
+/// Operand meaning depends on the [`Opcode`]; unused operands are zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C, align(8))]
+pub struct FlatOp {
+    pub opcode: Opcode,
+    pub a: u8,
+    pub b: u8,
+    pub c: u8,
+    pub imm: u32,
+}
+
+impl FlatOp {
+    fn new(opcode: Opcode, a: u8, b: u8, c: u8, imm: u32) -> Self {
+        Self {
+            opcode,
+            a,
+            b,
+            c,
+            imm,
+        }
+    }
+
+    /// An op that defers to the regular dispatcher
+    pub(crate) fn generic() -> Self {
+        Self::new(Opcode::Generic, 0, 0, 0, 0)
+    }
+}
+
+/// A program lowered for [`crate::vm::VM::execute_flat`]
+///
+/// Keeps the original bytecode for instructions that use the regular
+/// dispatcher. Lower once and reuse the result; lowering is a linear pass.
+#[derive(Debug, Clone)]
+pub struct FlatCode {
+    ops: Vec<FlatOp>,
+    bytecode: Bytecode,
+}
+
+impl FlatCode {
+    /// Lower `bytecode` into the flat encoding
+    pub fn new(bytecode: Bytecode) -> Self {
+        let mut ops = Vec::with_capacity(bytecode.code_len());
+        let chunks = std::iter::once(&bytecode.instructions[..]).chain(
+            bytecode
+                .functions
+                .iter()
+                .map(|chunk| &chunk.instructions[..]),
+        );
+        for code in chunks {
+            let base = ops.len();
+            ops.extend(
+                code.iter()
+                    .map(|instruction| lower(instruction, code, base, &bytecode)),
+            );
+        }
+        Self { ops, bytecode }
+    }
+
+    /// Flattened instructions, indexed by code address
+    pub fn ops(&self) -> &[FlatOp] {
+        &self.ops
+    }
+
+    /// The program this code was lowered from
+    pub fn bytecode(&self) -> &Bytecode {
+        &self.bytecode
+    }
+}
+
+/// Encode one instruction of `code`, which starts at code address `base`,
+/// falling back to `Generic` when operands do not fit
+fn lower(
+    instruction: &Instruction,
+    code: &[Instruction],
+    base: usize,
+    bytecode: &Bytecode,
+) -> FlatOp {
+    let imm = |value: usize| u32::try_from(value).ok();
+    // Targets leaving the chunk stay generic so the dispatcher reports them
+    let target = |target: usize| imm(base + target).filter(|_| target < code.len());
+
+    let op = match *instruction {
+        Instruction::LoadConst {
+            dest_reg,
+            const_index,
+        } if const_index < bytecode.constants.len() => {
+            imm(const_index).map(|i| FlatOp::new(Opcode::LoadConst, dest_reg, 0, 0, i))
+        }
+        Instruction::LoadVar {
+            dest_reg,
+            var_name_index,
+            var_id,
+        } if var_name_index < bytecode.var_names.len() => {
+            Some(FlatOp::new(Opcode::LoadVar, dest_reg, 0, 0, var_id))
+        }
+        Instruction::StoreVar {
+            var_name_index,
+            var_id,
+            src_reg,
+        } if var_name_index < bytecode.var_names.len() => {
+            Some(FlatOp::new(Opcode::StoreVar, src_reg, 0, 0, var_id))
+        }
+        Instruction::BinaryOp {
+            dest_reg,
+            op,
+            left_reg,
+            right_reg,
+        } => {
+            let opcode = match op {
+                BinaryOperator::Add => Some(Opcode::Add),
+                BinaryOperator::Sub => Some(Opcode::Sub),
+                BinaryOperator::Mul => Some(Opcode::Mul),
+                BinaryOperator::Div | BinaryOperator::FloorDiv | BinaryOperator::Mod => None,
+            };
+            opcode.map(|opcode| FlatOp::new(opcode, dest_reg, left_reg, right_reg, 0))
+        }
+        Instruction::UnaryOp {
+            dest_reg,
+            op,
+            operand_reg,
+        } => {
+            let opcode = match op {
+                UnaryOperator::Neg => Opcode::Neg,
+                UnaryOperator::Pos => Opcode::Pos,
+            };
+            Some(FlatOp::new(opcode, dest_reg, operand_reg, 0, 0))
+        }
+        Instruction::SetResult { src_reg } => {
+            Some(FlatOp::new(Opcode::SetResult, src_reg, 0, 0, 0))
+        }
+        Instruction::Jump { target: to } => {
+            target(to).map(|t| FlatOp::new(Opcode::Jump, 0, 0, 0, t))
+        }
+        Instruction::JumpIfFalse {
+            cond_reg,
+            target: to,
+        } => target(to).map(|t| FlatOp::new(Opcode::JumpIfFalse, cond_reg, 0, 0, t)),
+        Instruction::JumpIfTrue {
+            cond_reg,
+            target: to,
+        } => target(to).map(|t| FlatOp::new(Opcode::JumpIfTrue, cond_reg, 0, 0, t)),
+        Instruction::Move { dest_reg, src_reg } => {
+            Some(FlatOp::new(Opcode::Move, dest_reg, src_reg, 0, 0))
+        }
+        Instruction::Halt => Some(FlatOp::new(Opcode::Halt, 0, 0, 0, 0)),
+        _ => None,
+    };
+    op.unwrap_or_else(FlatOp::generic)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode::BytecodeBuilder;
+    use crate::value::Value;
+    use crate::vm::VM;
+    use crate::{compiler, lexer, parser};
+
+    fn compile_source(source: &str) -> Bytecode {
+        let program = parser::parse(lexer::lex(source).unwrap()).unwrap();
+        compiler::compile(&program).unwrap()
+    }
+
+    /// Run `source` through both dispatchers and return (regular, flat) outputs
+    fn run_both(source: &str) -> (Result<String, String>, Result<String, String>) {
+        let bytecode = compile_source(source);
+        let run = |flat: bool| {
+            let mut vm = VM::new();
+            let result = if flat {
+                vm.execute_flat(&FlatCode::new(bytecode.clone()))
+            } else {
+                vm.execute(&bytecode)
+            };
+            result
+                .map(|value| vm.format_output(value))
+                .map_err(|e| format!("{:?} {} @{}", e.kind, e.message, e.instruction_index))
+        };
+        (run(false), run(true))
+    }
+
+    #[test]
+    fn test_flat_op_is_eight_bytes() {
+        assert_eq!(std::mem::size_of::<FlatOp>(), 8);
+    }
+
+    #[test]
+    fn test_lowering_is_index_aligned() {
+        let bytecode = compile_source("def f(n):\n    return n * 2\nx = 3\nprint(f(x) - 1)");
+        let code = FlatCode::new(bytecode.clone());
+        assert_eq!(code.ops().len(), bytecode.code_len());
+
+        for (op, instruction) in code.ops().iter().zip(bytecode.code()) {
+            let generic = matches!(
+                instruction,
+                Instruction::DefineFunction { .. }
+                    | Instruction::Call { .. }
+                    | Instruction::Return { .. }
+                    | Instruction::Print { .. }
+            );
+            assert_eq!(op.opcode == Opcode::Generic, generic, "{:?}", instruction);
+        }
+    }
+
+    #[test]
+    fn test_out_of_range_pool_index_stays_generic() {
+        let mut bytecode = compile_source("1");
+        bytecode.instructions[0] = Instruction::LoadConst {
+            dest_reg: 0,
+            const_index: 99,
+        };
+        assert_eq!(FlatCode::new(bytecode).ops()[0].opcode, Opcode::Generic);
+    }
+
+    #[test]
+    fn test_flat_matches_regular_dispatch() {
+        let sources = [
+            "1 + 2 * 3 - 4",
+            "x = 10\ny = -x\nprint(+y)\nx // 3 + x % 3 + x / 3",
+            "def add(a, b):\n    return a + b\ndef twice(n):\n    return add(n, n)\ntwice(add(1, 2))",
+            "def f():\n    print(1)\nf()",
+            "undefined + 1",
+            "x = 1\ny = x / 0",
+            "9223372036854775807 + 1",
+            "-(-9223372036854775807 - 1)",
+            "x = 3\nx * 9223372036854775807",
+        ];
+        for source in sources {
+            let (regular, flat) = run_both(source);
+            assert_eq!(regular, flat, "source: {}", source);
+        }
+    }
+
+    #[test]
+    fn test_flat_jumps() {
+        // r0 = 0; if !r0 jump over the print; result = 5
+        let mut builder = BytecodeBuilder::new();
+        let skip = builder.new_label();
+        builder.emit_load_const(0, 0);
+        builder.emit_jump_if_false(0, skip);
+        builder.emit_print(0);
+        builder.bind_label(skip);
+        builder.emit_load_const(1, 5);
+        builder.emit_set_result(1);
+        let code = FlatCode::new(builder.build());
+
+        let mut vm = VM::new();
+        assert_eq!(vm.execute_flat(&code).unwrap(), Some(Value::Integer(5)));
+        assert_eq!(vm.format_output(None), "");
+    }
+
+    #[test]
+    fn test_flat_respects_instruction_limit() {
+        let code = FlatCode::new(compile_source("1 + 2 + 3 + 4"));
+        let mut vm = VM::new();
+        vm.set_instruction_limit(Some(3));
+        let err = vm.execute_flat(&code).unwrap_err();
+        assert!(err.message.contains("execution budget exceeded"));
+        assert_eq!(vm.instructions_executed(), 3);
+    }
+}
@@ -0,0 +1,343 @@
+//! Garbage-collected heap for VM objects
+//!
+//! [`Heap`] is a mark-sweep arena. Objects live in slots addressed by
+//! [`HeapRef`] handles, which are small `Copy` values (slot index plus
+//! generation) so a future `Value` variant holding one keeps `Value: Copy`.
+//! Objects report their outgoing references through [`Trace`]; anything not
+//! reachable from the roots passed to [`Heap::collect`] is freed, reference
+//! cycles included.
+//!
+//! Collection never happens implicitly, because only the owner knows the
+//! roots. The VM is expected to check [`Heap::should_collect`] at safe points
+//! (between instructions) and pass its registers, globals, and frame locals as
+//! roots. The threshold adapts after every collection: the next collection is
+//! due once the live count reaches `live * growth_factor`, but never below
+//! [`GcConfig::initial_threshold`].
+//!
+//! Freed slots are reused; their generation is bumped so stale handles are
+//! detected (`get` returns `None`) rather than aliasing a new object.
+//!
+//! # Example
+//!
+//! ```
+//! use pyrust::heap::{Heap, HeapRef, Trace};
+//!
+//! /// A cons cell that may point at another cell
+//! struct Cell(Option<HeapRef>);
+//!
+//! impl Trace for Cell {
+//!     fn trace(&self, visit: &mut dyn FnMut(HeapRef)) {
+//!         if let Some(next) = self.0 {
+//!             visit(next);
+//!         }
+//!     }
+//! }
+//!
+//! let mut heap = Heap::new();
+//! let tail = heap.alloc(Cell(None));
+//! let head = heap.alloc(Cell(Some(tail)));
+//! let garbage = heap.alloc(Cell(None));
+//!
+//! assert_eq!(heap.collect([head]), 1);
+//! assert!(heap.get(tail).is_some());
+//! assert!(heap.get(garbage).is_none());
+//! ```
+
+/// Handle to an object in a [`Heap`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HeapRef {
+    index: u32,
+    generation: u32,
+}
+
+/// Implemented by heap objects to report the handles they hold
+pub trait Trace {
+    /// Call `visit` once for every [`HeapRef`] this object references
+    fn trace(&self, visit: &mut dyn FnMut(HeapRef));
+}
+
+/// Collection tuning
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GcConfig {
+    /// Live objects at which the first collection becomes due, and the floor
+    /// for every later threshold
+    pub initial_threshold: usize,
+    /// Multiplier applied to the surviving object count to set the next threshold
+    pub growth_factor: usize,
+}
+
+impl Default for GcConfig {
+    fn default() -> Self {
+        Self {
+            initial_threshold: 1024,
+            growth_factor: 2,
+        }
+    }
+}
+
+/// Counters describing heap activity
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GcStats {
+    /// Completed collections
+    pub collections: u64,
+    /// Objects allocated over the heap's lifetime
+    pub allocated: u64,
+    /// Objects freed over the heap's lifetime
+    pub freed: u64,
+    /// Objects currently live
+    pub live: usize,
+    /// Live count at which the next collection becomes due
+    pub threshold: usize,
+}
+
+/// One arena slot; `object` is None while the slot is on the free list
+struct Slot<T> {
+    generation: u32,
+    marked: bool,
+    object: Option<T>,
+}
+
+/// Mark-sweep arena of traceable objects
+pub struct Heap<T> {
+    slots: Vec<Slot<T>>,
+    free: Vec<u32>,
+    config: GcConfig,
+    stats: GcStats,
+}
+
+impl<T: Trace> Heap<T> {
+    /// Create an empty heap with the default configuration
+    pub fn new() -> Self {
+        Self::with_config(GcConfig::default())
+    }
+
+    /// Create an empty heap with the given configuration
+    pub fn with_config(config: GcConfig) -> Self {
+        Self {
+            slots: Vec::new(),
+            free: Vec::new(),
+            config,
+            stats: GcStats {
+                threshold: config.initial_threshold,
+                ..GcStats::default()
+            },
+        }
+    }
+
+    /// Store `object` and return its handle
+    pub fn alloc(&mut self, object: T) -> HeapRef {
+        self.stats.allocated += 1;
+        self.stats.live += 1;
+
+        if let Some(index) = self.free.pop() {
+            let slot = &mut self.slots[index as usize];
+            slot.object = Some(object);
+            return HeapRef {
+                index,
+                generation: slot.generation,
+            };
+        }
+
+        let index = u32::try_from(self.slots.len()).expect("heap slot index overflow");
+        self.slots.push(Slot {
+            generation: 0,
+            marked: false,
+            object: Some(object),
+        });
+        HeapRef {
+            index,
+            generation: 0,
+        }
+    }
+
+    /// The object behind `handle`, or None if it has been collected
+    pub fn get(&self, handle: HeapRef) -> Option<&T> {
+        self.slots
+            .get(handle.index as usize)
+            .filter(|slot| slot.generation == handle.generation)
+            .and_then(|slot| slot.object.as_ref())
+    }
+
+    /// Mutable access to the object behind `handle`
+    pub fn get_mut(&mut self, handle: HeapRef) -> Option<&mut T> {
+        self.slots
+            .get_mut(handle.index as usize)
+            .filter(|slot| slot.generation == handle.generation)
+            .and_then(|slot| slot.object.as_mut())
+    }
+
+    /// Whether enough objects are live that a collection is due
+    pub fn should_collect(&self) -> bool {
+        self.stats.live >= self.stats.threshold
+    }
+
+    /// Free every object not reachable from `roots`, returning how many were freed
+    ///
+    /// Stale or dangling root handles are ignored.
+    pub fn collect(&mut self, roots: impl IntoIterator<Item = HeapRef>) -> usize {
+        // Mark
+        let mut worklist: Vec<HeapRef> = roots.into_iter().collect();
+        while let Some(handle) = worklist.pop() {
+            let Some(slot) = self.slots.get_mut(handle.index as usize) else {
+                continue;
+            };
+            if slot.generation != handle.generation || slot.marked {
+                continue;
+            }
+            let Some(object) = slot.object.as_ref() else {
+                continue;
+            };
+            slot.marked = true;
+            object.trace(&mut |child| worklist.push(child));
+        }
+
+        // Sweep
+        let mut freed = 0;
+        for (index, slot) in self.slots.iter_mut().enumerate() {
+            if slot.marked {
+                slot.marked = false;
+            } else if slot.object.take().is_some() {
+                slot.generation = slot.generation.wrapping_add(1);
+                self.free.push(index as u32);
+                freed += 1;
+            }
+        }
+
+        self.stats.collections += 1;
+        self.stats.freed += freed as u64;
+        self.stats.live -= freed;
+        self.stats.threshold = self
+            .config
+            .initial_threshold
+            .max(self.stats.live.saturating_mul(self.config.growth_factor));
+        freed
+    }
+
+    /// Number of live objects
+    pub fn len(&self) -> usize {
+        self.stats.live
+    }
+
+    /// Whether the heap holds no live objects
+    pub fn is_empty(&self) -> bool {
+        self.stats.live == 0
+    }
+
+    /// Activity counters
+    pub fn stats(&self) -> GcStats {
+        self.stats
+    }
+
+    /// The configuration this heap was created with
+    pub fn config(&self) -> GcConfig {
+        self.config
+    }
+}
+
+impl<T: Trace> Default for Heap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Object with an arbitrary list of outgoing edges
+    struct Node(Vec<HeapRef>);
+
+    impl Trace for Node {
+        fn trace(&self, visit: &mut dyn FnMut(HeapRef)) {
+            self.0.iter().copied().for_each(visit);
+        }
+    }
+
+    #[test]
+    fn test_alloc_and_get() {
+        let mut heap = Heap::new();
+        let a = heap.alloc(Node(vec![]));
+        let b = heap.alloc(Node(vec![a]));
+        assert_eq!(heap.len(), 2);
+        assert_eq!(heap.get(b).unwrap().0, vec![a]);
+
+        heap.get_mut(a).unwrap().0.push(b);
+        assert_eq!(heap.get(a).unwrap().0, vec![b]);
+    }
+
+    #[test]
+    fn test_unreachable_cycle_is_collected() {
+        let mut heap = Heap::new();
+        let a = heap.alloc(Node(vec![]));
+        let b = heap.alloc(Node(vec![a]));
+        heap.get_mut(a).unwrap().0.push(b);
+        let root = heap.alloc(Node(vec![]));
+
+        assert_eq!(heap.collect([root]), 2);
+        assert!(heap.get(a).is_none());
+        assert!(heap.get(b).is_none());
+        assert!(heap.get(root).is_some());
+    }
+
+    #[test]
+    fn test_reachable_cycle_survives() {
+        let mut heap = Heap::new();
+        let a = heap.alloc(Node(vec![]));
+        let b = heap.alloc(Node(vec![a]));
+        heap.get_mut(a).unwrap().0.push(b);
+
+        assert_eq!(heap.collect([a]), 0);
+        assert_eq!(heap.len(), 2);
+        // Marks are cleared, so a second collection sees the same graph
+        assert_eq!(heap.collect([b]), 0);
+    }
+
+    #[test]
+    fn test_stale_handle_does_not_alias_reused_slot() {
+        let mut heap = Heap::new();
+        let old = heap.alloc(Node(vec![]));
+        heap.collect([]);
+        let new = heap.alloc(Node(vec![]));
+
+        assert_eq!(old.index, new.index);
+        assert!(heap.get(old).is_none());
+        assert!(heap.get(new).is_some());
+        // Stale roots are ignored rather than keeping the new object alive
+        assert_eq!(heap.collect([old]), 1);
+    }
+
+    #[test]
+    fn test_threshold_adapts_to_survivors() {
+        let mut heap = Heap::with_config(GcConfig {
+            initial_threshold: 4,
+            growth_factor: 3,
+        });
+        let roots: Vec<HeapRef> = (0..3).map(|_| heap.alloc(Node(vec![]))).collect();
+        assert!(!heap.should_collect());
+        heap.alloc(Node(vec![]));
+        assert!(heap.should_collect());
+
+        heap.collect(roots.iter().copied());
+        assert_eq!(heap.stats().threshold, 9);
+        assert!(!heap.should_collect());
+
+        heap.collect([]);
+        assert_eq!(heap.stats().threshold, 4);
+    }
+
+    #[test]
+    fn test_stats_track_activity() {
+        let mut heap = Heap::new();
+        let keep = heap.alloc(Node(vec![]));
+        heap.alloc(Node(vec![]));
+        heap.alloc(Node(vec![]));
+        heap.collect([keep]);
+
+        let stats = heap.stats();
+        assert_eq!(stats.collections, 1);
+        assert_eq!(stats.allocated, 3);
+        assert_eq!(stats.freed, 2);
+        assert_eq!(stats.live, 1);
+        assert!(!heap.is_empty());
+    }
+}
@@ -0,0 +1,151 @@
+//! Standard input provisioning for executions
+//!
+//! An [`InputSource`] supplies the lines a program reads with `input()`.
+//! Callers attach one to a VM with [`crate::vm::VM::set_stdin`] or pass it to
+//! [`crate::execute_python_with_input`]. Sources can be built from a string,
+//! a vector of lines, or any buffered reader.
+//!
+//! # Example
+//!
+//! ```
+//! use pyrust::input::InputSource;
+//!
+//! let mut input = InputSource::from_string("alice\nbob\n");
+//! assert_eq!(input.read_line().unwrap(), Some("alice".to_string()));
+//! assert_eq!(input.read_line().unwrap(), Some("bob".to_string()));
+//! assert_eq!(input.read_line().unwrap(), None);
+//! ```
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead};
+
+/// Source of standard input lines for an execution
+pub struct InputSource {
+    inner: Inner,
+}
+
+/// Backing storage for an input source
+enum Inner {
+    /// Pre-split lines consumed front to back
+    Lines(VecDeque<String>),
+    /// Lines read lazily from a reader
+    Reader(Box<dyn BufRead + Send>),
+}
+
+impl InputSource {
+    /// Create a source with no input (every read reports end of file)
+    pub fn empty() -> Self {
+        Self::from_lines(Vec::<String>::new())
+    }
+
+    /// Create a source from text, split on `\n` or `\r\n`
+    pub fn from_string(text: impl Into<String>) -> Self {
+        Self::from_lines(text.into().lines().map(str::to_string).collect::<Vec<_>>())
+    }
+
+    /// Create a source from individual lines (without line terminators)
+    pub fn from_lines<I, S>(lines: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            inner: Inner::Lines(lines.into_iter().map(Into::into).collect()),
+        }
+    }
+
+    /// Create a source that reads lines lazily from `reader`
+    pub fn from_reader(reader: impl BufRead + Send + 'static) -> Self {
+        Self {
+            inner: Inner::Reader(Box::new(reader)),
+        }
+    }
+
+    /// Read the next line without its terminator
+    ///
+    /// Returns `Ok(None)` once the input is exhausted.
+    pub fn read_line(&mut self) -> io::Result<Option<String>> {
+        match &mut self.inner {
+            Inner::Lines(lines) => Ok(lines.pop_front()),
+            Inner::Reader(reader) => {
+                let mut line = String::new();
+                if reader.read_line(&mut line)? == 0 {
+                    return Ok(None);
+                }
+                if line.ends_with('\n') {
+                    line.pop();
+                    if line.ends_with('\r') {
+                        line.pop();
+                    }
+                }
+                Ok(Some(line))
+            }
+        }
+    }
+}
+
+impl fmt::Debug for InputSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.inner {
+            Inner::Lines(lines) => f.debug_tuple("InputSource::Lines").field(lines).finish(),
+            Inner::Reader(_) => f.write_str("InputSource::Reader(..)"),
+        }
+    }
+}
+
+impl From<&str> for InputSource {
+    fn from(text: &str) -> Self {
+        Self::from_string(text)
+    }
+}
+
+impl From<String> for InputSource {
+    fn from(text: String) -> Self {
+        Self::from_string(text)
+    }
+}
+
+impl From<Vec<String>> for InputSource {
+    fn from(lines: Vec<String>) -> Self {
+        Self::from_lines(lines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_from_string_splits_lines() {
+        let mut input = InputSource::from_string("1\r\n2\n3");
+        assert_eq!(input.read_line().unwrap(), Some("1".to_string()));
+        assert_eq!(input.read_line().unwrap(), Some("2".to_string()));
+        assert_eq!(input.read_line().unwrap(), Some("3".to_string()));
+        assert_eq!(input.read_line().unwrap(), None);
+    }
+
+    #[test]
+    fn test_from_lines() {
+        let mut input = InputSource::from_lines(["a", "b"]);
+        assert_eq!(input.read_line().unwrap(), Some("a".to_string()));
+        assert_eq!(input.read_line().unwrap(), Some("b".to_string()));
+        assert_eq!(input.read_line().unwrap(), None);
+    }
+
+    #[test]
+    fn test_from_reader_strips_terminators() {
+        let mut input = InputSource::from_reader(Cursor::new("x\r\n\ny"));
+        assert_eq!(input.read_line().unwrap(), Some("x".to_string()));
+        assert_eq!(input.read_line().unwrap(), Some(String::new()));
+        assert_eq!(input.read_line().unwrap(), Some("y".to_string()));
+        assert_eq!(input.read_line().unwrap(), None);
+    }
+
+    #[test]
+    fn test_empty_source() {
+        let mut input = InputSource::empty();
+        assert_eq!(input.read_line().unwrap(), None);
+    }
+}
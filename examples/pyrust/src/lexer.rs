@@ -4,7 +4,16 @@
 //! Uses lifetime parameters to store zero-copy &str slices, avoiding allocations.
 //! Target performance: ~5μs for 50-byte input.
 
+use crate::ast::SourcePos;
 use crate::error::LexError;
+use crate::error_code::ErrorCode;
+use crate::warnings::{CompileWarning, WarningKind};
+use std::cmp::Ordering;
+
+/// Tab stop width used when measuring indentation
+///
+/// Tabs advance to the next multiple of this width, as in CPython.
+pub const TAB_WIDTH: usize = 8;
 
 /// All token types supported in Phase 1
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -37,7 +46,10 @@ pub enum TokenKind {
 
     // Special
     Newline, // \n
+    Indent,  // Increase in indentation opening a block
+    Dedent,  // Decrease in indentation closing a block
     Eof,     // End of file
+    Error,   // Text that failed to lex, from lex_recovering
 }
 
 /// Token with location tracking and zero-copy text slice
@@ -65,6 +77,36 @@ impl<'src> Token<'src> {
             column,
         }
     }
+
+    /// Column just past the token's text
+    pub fn end_column(&self) -> usize {
+        self.column + self.text.chars().count()
+    }
+}
+
+/// Indentation width of a line, measured under two tab policies
+///
+/// Comparing both widths detects indentation whose meaning depends on the tab
+/// size (CPython's TabError), which is rejected as inconsistent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Indentation {
+    /// Width with tabs advancing to the next multiple of TAB_WIDTH
+    tabbed: usize,
+    /// Width with every tab counted as a single column
+    flat: usize,
+}
+
+impl Indentation {
+    /// Compares two indentations, returning None if the result depends on tab size
+    fn compare(&self, other: &Indentation) -> Option<Ordering> {
+        let tabbed = self.tabbed.cmp(&other.tabbed);
+        let flat = self.flat.cmp(&other.flat);
+        if tabbed == flat {
+            Some(tabbed)
+        } else {
+            None
+        }
+    }
 }
 
 /// Lexer state for tracking position in source
@@ -77,6 +119,14 @@ struct Lexer<'src> {
     line: usize,
     /// Current column number (1-indexed, byte offset from line start + 1)
     column: usize,
+    /// Open indentation levels; the first entry is the base level of the file
+    indent_stack: Vec<Indentation>,
+    /// Whether the next token starts a new line
+    at_line_start: bool,
+    /// Warnings found so far
+    warnings: Vec<CompileWarning>,
+    /// Errors recovered from so far, or `None` if lexing stops at the first
+    errors: Option<Vec<LexError>>,
 }
 
 impl<'src> Lexer<'src> {
@@ -87,7 +137,128 @@ impl<'src> Lexer<'src> {
             pos: 0,
             line: 1,
             column: 1,
+            indent_stack: Vec::new(),
+            at_line_start: true,
+            warnings: Vec::new(),
+            errors: None,
+        }
+    }
+
+    /// Record `error` and carry on when recovering, or return it otherwise
+    fn recover(&mut self, error: LexError) -> Result<(), LexError> {
+        match &mut self.errors {
+            Some(errors) => {
+                errors.push(error);
+                Ok(())
+            }
+            None => Err(error),
+        }
+    }
+
+    /// Measures the indentation of the current line and emits Indent/Dedent tokens
+    ///
+    /// Blank lines never change indentation, and dedents must return to an
+    /// enclosing level. Whether an indent is allowed is left to the parser. The
+    /// base level is taken from the first logical line and may be lowered, so
+    /// uniformly indented snippets lex normally.
+    fn lex_indentation(&mut self, tokens: &mut Vec<Token<'src>>) -> Result<(), LexError> {
+        let start_pos = self.pos;
+        let mut indent = Indentation { tabbed: 0, flat: 0 };
+        let (mut spaces, mut tabs) = (false, false);
+
+        while let Some(ch) = self.peek() {
+            match ch {
+                ' ' => {
+                    indent.tabbed += 1;
+                    indent.flat += 1;
+                    spaces = true;
+                }
+                '\t' => {
+                    indent.tabbed = (indent.tabbed / TAB_WIDTH + 1) * TAB_WIDTH;
+                    indent.flat += 1;
+                    tabs = true;
+                }
+                _ => break,
+            }
+            self.advance();
+        }
+
+        // Blank lines (only whitespace) don't affect indentation
+        self.skip_whitespace();
+        if matches!(self.peek(), None | Some('\n')) {
+            return Ok(());
+        }
+
+        let line = self.line;
+        let column = self.column;
+        if spaces && tabs {
+            self.warnings.push(CompileWarning {
+                kind: WarningKind::MixedIndentation,
+                message: "indentation mixes tabs and spaces".to_string(),
+                pos: Some(SourcePos { line, column: 1 }),
+                end: Some(SourcePos {
+                    line,
+                    column: indent.flat + 1,
+                }),
+            });
         }
+        let inconsistent = || LexError {
+            code: ErrorCode::InconsistentIndentation,
+            message: "Inconsistent use of tabs and spaces in indentation".to_string(),
+            line,
+            column,
+            end_column: column + 1,
+        };
+
+        let top = match self.indent_stack.last() {
+            Some(top) => *top,
+            None => {
+                self.indent_stack.push(indent);
+                return Ok(());
+            }
+        };
+
+        match indent.compare(&top).ok_or_else(inconsistent)? {
+            Ordering::Equal => {}
+            Ordering::Greater => {
+                self.indent_stack.push(indent);
+                tokens.push(Token::new(
+                    TokenKind::Indent,
+                    &self.source[start_pos..start_pos + indent.flat],
+                    line,
+                    1,
+                ));
+            }
+            Ordering::Less => {
+                while self.indent_stack.len() > 1 {
+                    let top = self.indent_stack[self.indent_stack.len() - 1];
+                    if indent.compare(&top).ok_or_else(inconsistent)? != Ordering::Less {
+                        break;
+                    }
+                    self.indent_stack.pop();
+                    tokens.push(Token::new(TokenKind::Dedent, "", line, column));
+                }
+
+                let top = self.indent_stack[self.indent_stack.len() - 1];
+                match indent.compare(&top).ok_or_else(inconsistent)? {
+                    Ordering::Equal => {}
+                    // Dedenting below the base level lowers the base
+                    Ordering::Less => self.indent_stack[0] = indent,
+                    Ordering::Greater => {
+                        return Err(LexError {
+                            code: ErrorCode::UnindentMismatch,
+                            message: "Unindent does not match any outer indentation level"
+                                .to_string(),
+                            line,
+                            column,
+                            end_column: column + 1,
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok(())
     }
 
     /// Returns the current character without consuming it
@@ -139,12 +310,14 @@ impl<'src> Lexer<'src> {
         // Validate integer doesn't overflow i64
         if text.parse::<i64>().is_err() {
             return Err(LexError {
+                code: ErrorCode::IntegerTooLarge,
                 message: format!(
                     "Integer literal '{}' is too large (exceeds i64 range)",
                     text
                 ),
                 line: start_line,
                 column: start_column,
+                end_column: start_column + text.len(),
             });
         }
 
@@ -338,18 +511,82 @@ impl<'src> Lexer<'src> {
                 )));
             }
 
-            // Unexpected character
+            // Unexpected character, consumed so lexing can resume after it
             _ => {
+                self.advance();
                 return Err(LexError {
+                    code: ErrorCode::UnexpectedCharacter,
                     message: format!("Unexpected character '{}'", ch),
                     line: start_line,
                     column: start_column,
+                    end_column: start_column + 1,
                 });
             }
         };
 
         Ok(Some(token))
     }
+
+    /// Tokenize the whole source, ending with an Eof token
+    fn lex_all(&mut self) -> Result<Vec<Token<'src>>, LexError> {
+        let mut tokens = Vec::new();
+
+        loop {
+            if self.at_line_start {
+                self.at_line_start = false;
+                if let Err(e) = self.lex_indentation(&mut tokens) {
+                    self.recover(e)?;
+                }
+            }
+
+            self.skip_whitespace();
+            let start_pos = self.pos;
+            let token = match self.next_token() {
+                Ok(token) => token,
+                Err(e) => {
+                    let token = Token::new(
+                        TokenKind::Error,
+                        &self.source[start_pos..self.pos],
+                        e.line,
+                        e.column,
+                    );
+                    self.recover(e)?;
+                    Some(token)
+                }
+            };
+
+            match token {
+                Some(token) => {
+                    let is_eof = token.kind == TokenKind::Eof;
+                    if is_eof {
+                        // Close every block still open at end of file
+                        for _ in 1..self.indent_stack.len() {
+                            tokens.push(Token::new(
+                                TokenKind::Dedent,
+                                "",
+                                token.line,
+                                token.column,
+                            ));
+                        }
+                    }
+                    if token.kind == TokenKind::Newline {
+                        self.at_line_start = true;
+                    }
+                    tokens.push(token);
+                    if is_eof {
+                        break;
+                    }
+                }
+                None => {
+                    // Should not happen, but handle gracefully
+                    tokens.push(Token::new(TokenKind::Eof, "", self.line, self.column));
+                    break;
+                }
+            }
+        }
+
+        Ok(tokens)
+    }
 }
 
 /// Tokenizes Python source code into a vector of tokens
@@ -374,27 +611,55 @@ impl<'src> Lexer<'src> {
 /// assert_eq!(tokens[0].text, "x");
 /// ```
 pub fn lex(source: &str) -> Result<Vec<Token<'_>>, LexError> {
+    lex_with_warnings(source).map(|(tokens, _)| tokens)
+}
+
+/// Tokenize source code, also returning the warnings found on the way
+///
+/// The lexer warns about a line indented with both tabs and spaces whose
+/// depth is still unambiguous; see [`crate::warnings`].
+///
+/// # Examples
+/// ```
+/// use pyrust::lexer::lex_with_warnings;
+/// use pyrust::warnings::WarningKind;
+///
+/// let (_, warnings) = lex_with_warnings("def f():\n \treturn 1").unwrap();
+/// assert_eq!(warnings[0].kind, WarningKind::MixedIndentation);
+/// ```
+pub fn lex_with_warnings(source: &str) -> Result<(Vec<Token<'_>>, Vec<CompileWarning>), LexError> {
     let mut lexer = Lexer::new(source);
-    let mut tokens = Vec::new();
-
-    loop {
-        match lexer.next_token()? {
-            Some(token) => {
-                let is_eof = token.kind == TokenKind::Eof;
-                tokens.push(token);
-                if is_eof {
-                    break;
-                }
-            }
-            None => {
-                // Should not happen, but handle gracefully
-                tokens.push(Token::new(TokenKind::Eof, "", lexer.line, lexer.column));
-                break;
-            }
-        }
-    }
+    let tokens = lexer.lex_all()?;
+    Ok((tokens, lexer.warnings))
+}
 
-    Ok(tokens)
+/// Tokenize source code, carrying on past lexical errors
+///
+/// Each unexpected character or oversized integer becomes a
+/// [`TokenKind::Error`] token and its error is recorded, so tools such as
+/// `pyrust --check` can report every lexical problem in a file at once. A
+/// line whose indentation is inconsistent keeps the indentation of the block
+/// it is in. Tokens, errors and warnings are returned in source order; the
+/// tokens only make a program if there are no errors.
+///
+/// # Examples
+/// ```
+/// use pyrust::lexer::{lex_recovering, TokenKind};
+///
+/// let (tokens, errors, _) = lex_recovering("x = 1 $ 2\ny = ?");
+/// assert_eq!(errors.len(), 2);
+/// assert_eq!((errors[1].line, errors[1].column), (2, 5));
+/// assert_eq!(tokens[3].kind, TokenKind::Error);
+/// assert_eq!(tokens[3].text, "$");
+/// ```
+pub fn lex_recovering(source: &str) -> (Vec<Token<'_>>, Vec<LexError>, Vec<CompileWarning>) {
+    let mut lexer = Lexer::new(source);
+    lexer.errors = Some(Vec::new());
+    let tokens = lexer
+        .lex_all()
+        .expect("a recovering lexer records its errors");
+    let errors = lexer.errors.take().unwrap_or_default();
+    (tokens, errors, lexer.warnings)
 }
 
 #[cfg(test)]
@@ -876,4 +1141,136 @@ mod tests {
         assert_eq!(tokens[7].kind, TokenKind::RightParen);
         assert_eq!(tokens[8].kind, TokenKind::Eof);
     }
+
+    #[test]
+    fn test_indent_dedent_tokens() {
+        let tokens = lex("def f():\n    return 1\nx").unwrap();
+        let kinds: Vec<TokenKind> = tokens.iter().map(|t| t.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::Def,
+                TokenKind::Identifier,
+                TokenKind::LeftParen,
+                TokenKind::RightParen,
+                TokenKind::Colon,
+                TokenKind::Newline,
+                TokenKind::Indent,
+                TokenKind::Return,
+                TokenKind::Integer,
+                TokenKind::Newline,
+                TokenKind::Dedent,
+                TokenKind::Identifier,
+                TokenKind::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dedents_closed_at_eof() {
+        let tokens = lex("def f():\n    def g():\n        return 1").unwrap();
+        let dedents = tokens
+            .iter()
+            .filter(|t| t.kind == TokenKind::Dedent)
+            .count();
+        assert_eq!(dedents, 2);
+        assert_eq!(tokens.last().unwrap().kind, TokenKind::Eof);
+    }
+
+    #[test]
+    fn test_blank_lines_ignore_indentation() {
+        let tokens = lex("def f():\n    x = 1\n\n  \n    return x").unwrap();
+        let indents = tokens
+            .iter()
+            .filter(|t| t.kind == TokenKind::Indent)
+            .count();
+        assert_eq!(indents, 1);
+    }
+
+    #[test]
+    fn test_tab_indentation_matches_eight_spaces() {
+        // A tab followed by a tab-only line is consistent under any tab size
+        let tokens = lex("def f():\n\tx = 1\n\treturn x").unwrap();
+        let indents = tokens
+            .iter()
+            .filter(|t| t.kind == TokenKind::Indent)
+            .count();
+        assert_eq!(indents, 1);
+    }
+
+    #[test]
+    fn test_mixed_indentation_warns() {
+        // A tab then a space is deeper than either alone under any tab size
+        let (tokens, warnings) = lex_with_warnings("def f():\n\t return 1\nx = 2").unwrap();
+        assert!(tokens.iter().any(|t| t.kind == TokenKind::Indent));
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(
+            warnings[0].to_string(),
+            "Warning[W0006] at 2:1: indentation mixes tabs and spaces"
+        );
+        assert_eq!(warnings[0].end.unwrap().column, 3);
+
+        let (_, warnings) = lex_with_warnings("def f():\n\tx = 1\n\treturn x").unwrap();
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_inconsistent_tabs_and_spaces() {
+        let err = lex("def f():\n\tx = 1\n        return x").unwrap_err();
+        assert_eq!(
+            err.message,
+            "Inconsistent use of tabs and spaces in indentation"
+        );
+        assert_eq!(err.line, 3);
+    }
+
+    #[test]
+    fn test_unindent_mismatch() {
+        let err = lex("def f():\n    x = 1\n  return x").unwrap_err();
+        assert_eq!(
+            err.message,
+            "Unindent does not match any outer indentation level"
+        );
+        assert_eq!(err.line, 3);
+    }
+
+    #[test]
+    fn test_recovering_lexer_reports_every_error() {
+        let source = "x = 1 $ 2\ny = 99999999999999999999\nprint(y ? x)";
+        let (tokens, errors, _) = lex_recovering(source);
+        let codes: Vec<_> = errors.iter().map(|e| (e.code, e.line, e.column)).collect();
+        assert_eq!(
+            codes,
+            [
+                (ErrorCode::UnexpectedCharacter, 1, 7),
+                (ErrorCode::IntegerTooLarge, 2, 5),
+                (ErrorCode::UnexpectedCharacter, 3, 9),
+            ]
+        );
+        let bad: Vec<_> = tokens
+            .iter()
+            .filter(|t| t.kind == TokenKind::Error)
+            .map(|t| t.text)
+            .collect();
+        assert_eq!(bad, ["$", "99999999999999999999", "?"]);
+        // Tokens after each error are still there
+        assert_eq!(tokens.last().unwrap().kind, TokenKind::Eof);
+        assert!(tokens.iter().any(|t| t.text == "print"));
+
+        // Without errors, the tokens are those of the plain lexer
+        let (tokens, errors, _) = lex_recovering("def f():\n    return 1");
+        assert!(errors.is_empty());
+        assert_eq!(tokens, lex("def f():\n    return 1").unwrap());
+    }
+
+    #[test]
+    fn test_recovering_lexer_continues_past_bad_indentation() {
+        let (tokens, errors, _) = lex_recovering("def f():\n    x = 1\n  return x\ny = é");
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].code, ErrorCode::UnindentMismatch);
+        assert_eq!(errors[1].code, ErrorCode::UnexpectedCharacter);
+        assert_eq!(errors[1].line, 4);
+        let last_error = tokens.iter().rev().find(|t| t.kind == TokenKind::Error);
+        assert_eq!(last_error.unwrap().text, "é");
+    }
 }
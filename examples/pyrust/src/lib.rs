@@ -50,39 +50,114 @@
 //! - [`ParseError`]: Syntax errors with location and context
 //! - [`CompileError`]: Compilation failures (register overflow, etc.)
 //! - [`RuntimeError`]: Execution errors (division by zero, undefined variables)
+//! - [`FormatError`]: Precompiled bytecode that cannot be loaded
+//!
+//! Each of them, and each compile warning, converts to a serializable
+//! [`Diagnostic`] for tools that want errors as data, and carries a stable
+//! [`ErrorCode`] such as `E1003` that `pyrust explain` describes. With the
+//! `fancy-errors` feature, a diagnostic also renders as a rich miette report
+//! (see `report::Report`).
 //!
 //! [`LexError`]: error::LexError
 //! [`ParseError`]: error::ParseError
 //! [`CompileError`]: error::CompileError
 //! [`RuntimeError`]: error::RuntimeError
+//! [`FormatError`]: bytecode_format::FormatError
 //! [`PyRustError`]: error::PyRustError
+//! [`Diagnostic`]: diagnostic::Diagnostic
+//! [`ErrorCode`]: error_code::ErrorCode
 
 pub mod ast;
+pub mod bench;
 pub mod bytecode;
+pub mod bytecode_format;
 pub mod cache;
+pub mod cancel;
 pub mod compiler;
 pub mod daemon;
+#[cfg(unix)]
+pub mod daemon_activation;
+#[cfg(feature = "async-daemon")]
+pub mod daemon_async;
 pub mod daemon_client;
+#[cfg(feature = "async-daemon")]
+pub mod daemon_client_async;
+pub mod daemon_limits;
+pub mod daemon_log;
 pub mod daemon_protocol;
+pub mod daemon_session;
+pub mod daemon_spawn;
+#[cfg(unix)]
+pub mod daemon_supervisor;
+pub(crate) mod daemon_transport;
+pub mod debugger;
+pub mod diagnostic;
+pub mod disassembler;
 pub mod error;
+pub mod error_code;
+#[cfg(feature = "fast-dispatch")]
+pub mod flat;
+pub mod heap;
+pub mod input;
 pub mod lexer;
+pub mod metrics;
+pub mod parallel;
 pub mod parser;
 pub mod profiling;
+#[cfg(feature = "fancy-errors")]
+pub mod report;
+pub mod resolver;
+pub mod session;
 pub mod value;
 pub mod vm;
+pub mod vm_pool;
+pub mod warnings;
+pub mod watch;
 
 use error::PyRustError;
+pub use parallel::ParallelExecutor;
+pub use session::Session;
 use std::cell::RefCell;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
 // Global compilation cache for daemon mode
-// Uses Mutex for thread-safe access across daemon requests
+// Sharded so that daemon threads working on different programs don't contend
+lazy_static::lazy_static! {
+    static ref GLOBAL_CACHE: cache::ShardedCache = cache::ShardedCache::from_env();
+}
+
+// Default per-execution instruction budget for library and daemon execution
+// PYRUST_MAX_INSTRUCTIONS sets the limit; unset or invalid means unlimited
+lazy_static::lazy_static! {
+    static ref INSTRUCTION_LIMIT: Option<u64> = {
+        std::env::var("PYRUST_MAX_INSTRUCTIONS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+    };
+}
+
+// Default per-execution print output cap for library and daemon execution
+// PYRUST_MAX_OUTPUT_BYTES sets the limit; output past it is truncated
 lazy_static::lazy_static! {
-    static ref GLOBAL_CACHE: Mutex<cache::CompilationCache> = {
-        Mutex::new(cache::CompilationCache::from_env())
+    static ref OUTPUT_LIMIT: Option<vm::OutputLimit> = {
+        std::env::var("PYRUST_MAX_OUTPUT_BYTES")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .map(|max_bytes| vm::OutputLimit {
+                max_bytes,
+                on_overflow: vm::OutputOverflow::Truncate,
+            })
     };
 }
 
+/// Create a VM configured with the process-wide execution limits
+pub(crate) fn new_vm() -> vm::VM {
+    let mut vm = vm::VM::new();
+    vm.set_instruction_limit(*INSTRUCTION_LIMIT);
+    vm.set_output_limit(*OUTPUT_LIMIT);
+    vm
+}
+
 // Thread-local compilation cache for library mode
 // No locking overhead for single-threaded library usage
 thread_local! {
@@ -91,6 +166,26 @@ thread_local! {
     };
 }
 
+// Thread-local VM reused across executions, so repeated calls skip
+// allocating a fresh register file
+thread_local! {
+    static THREAD_VM: RefCell<vm::VM> = RefCell::new(new_vm());
+}
+
+/// Run `f` on this thread's reusable VM after resetting it
+///
+/// Falls back to a fresh VM if the thread's VM is already in use further up
+/// the stack.
+fn with_thread_vm<R>(f: impl FnOnce(&mut vm::VM) -> R) -> R {
+    THREAD_VM.with(|cell| match cell.try_borrow_mut() {
+        Ok(mut vm) => {
+            vm.reset();
+            f(&mut vm)
+        }
+        Err(_) => f(&mut new_vm()),
+    })
+}
+
 /// Execute Python source code with thread-local cache (library mode)
 ///
 /// This variant uses a thread-local cache with no locking overhead, optimized
@@ -108,46 +203,357 @@ thread_local! {
 /// * `Ok(String)` - Formatted output according to the output specification
 /// * `Err(PyRustError)` - Error from any stage of the pipeline
 pub fn execute_python_cached(code: &str) -> Result<String, PyRustError> {
+    let bytecode = compile_cached_thread_local(code)?;
+
+    with_thread_vm(|vm| {
+        // Stage 4: Execute bytecode in the VM
+        let result = vm
+            .execute(&bytecode)
+            .map_err(|e| e.with_location(&bytecode, code))?;
+
+        // Stage 5: Format output according to specification
+        Ok(vm.format_output(result))
+    })
+}
+
+/// Execute many independent snippets on this thread's VM
+///
+/// Each snippet runs in a fresh global environment, as if passed to
+/// [`execute_python`] on its own, but the VM is set up once for the whole
+/// batch. Results are returned in input order; a snippet that fails to compile
+/// or raises does not affect the others.
+///
+/// # Example
+///
+/// ```
+/// let results = pyrust::execute_python_batch(&["1 + 2", "x", "print(4)"]);
+/// assert_eq!(results[0].as_deref().unwrap(), "3");
+/// assert!(results[1].is_err());
+/// assert_eq!(results[2].as_deref().unwrap(), "4\n");
+/// ```
+pub fn execute_python_batch(codes: &[&str]) -> Vec<Result<String, PyRustError>> {
+    let compiled: Vec<_> = codes
+        .iter()
+        .map(|code| compile_cached_thread_local(code))
+        .collect();
+    let programs: Vec<_> = compiled
+        .iter()
+        .filter_map(|bytecode| bytecode.as_ref().ok().cloned())
+        .collect();
+
+    let mut outputs = with_thread_vm(|vm| vm.execute_many(&programs)).into_iter();
+    compiled
+        .into_iter()
+        .zip(codes)
+        .map(|(bytecode, code)| {
+            let bytecode = bytecode?;
+            let output = outputs.next().expect("one result per compiled program");
+            output.map_err(|e| e.with_location(&bytecode, code).into())
+        })
+        .collect()
+}
+
+/// Execute Python source code, streaming print output as it is produced
+///
+/// Each `print` line (including its trailing newline) is passed to `sink`
+/// immediately instead of being buffered, so long-running programs show
+/// progress. Compilation uses the thread-local cache like [`execute_python`].
+///
+/// # Returns
+///
+/// * `Ok(String)` - The formatted expression result, if any (print output is
+///   not repeated)
+/// * `Err(PyRustError)` - Error from any stage of the pipeline; lines printed
+///   before a runtime error have already been delivered to `sink`
+///
+/// # Example
+///
+/// ```
+/// use std::sync::{Arc, Mutex};
+///
+/// let lines = Arc::new(Mutex::new(Vec::new()));
+/// let captured = Arc::clone(&lines);
+/// let result = pyrust::execute_python_streaming("print(1)\nprint(2)\n3", move |line| {
+///     captured.lock().unwrap().push(line.to_string());
+/// })
+/// .unwrap();
+///
+/// assert_eq!(*lines.lock().unwrap(), vec!["1\n", "2\n"]);
+/// assert_eq!(result, "3");
+/// ```
+pub fn execute_python_streaming<F>(code: &str, sink: F) -> Result<String, PyRustError>
+where
+    F: FnMut(&str) + Send + 'static,
+{
+    let bytecode = compile_cached_thread_local(code)?;
+
+    let mut vm = new_vm();
+    vm.set_stdout_sink(sink);
+    let result = vm
+        .execute(&bytecode)
+        .map_err(|e| e.with_location(&bytecode, code))?;
+
+    Ok(vm.format_output(result))
+}
+
+/// Execute Python source code, reporting every executed instruction
+///
+/// `hook` receives a [`vm::TraceEvent`] after each instruction runs, carrying
+/// the instruction pointer, the decoded instruction, and any register it
+/// wrote. Formatting an event with `{}` gives a ready-made trace line.
+/// Otherwise behaves like [`execute_python`].
+///
+/// # Example
+///
+/// ```
+/// use std::sync::{Arc, Mutex};
+///
+/// let trace = Arc::new(Mutex::new(Vec::new()));
+/// let lines = Arc::clone(&trace);
+/// let output = pyrust::execute_python_traced("1 + 2", move |event| {
+///     lines.lock().unwrap().push(event.to_string());
+/// })
+/// .unwrap();
+///
+/// assert_eq!(output, "3");
+/// assert!(trace.lock().unwrap()[2].contains("BinaryOp r0, r0 + r1"));
+/// ```
+pub fn execute_python_traced<F>(code: &str, hook: F) -> Result<String, PyRustError>
+where
+    F: for<'a> FnMut(&vm::TraceEvent<'a>) + Send + 'static,
+{
+    let bytecode = compile_cached_thread_local(code)?;
+
+    let mut vm = new_vm();
+    vm.set_trace_hook(hook);
+    let result = vm
+        .execute(&bytecode)
+        .map_err(|e| e.with_location(&bytecode, code))?;
+
+    Ok(vm.format_output(result))
+}
+
+/// Execute Python source code with standard input attached
+///
+/// `input` supplies the lines returned by `input()`; it accepts anything
+/// convertible into an [`input::InputSource`] (a string, a vector of lines, or
+/// an explicit source wrapping a reader). Otherwise behaves like
+/// [`execute_python`].
+///
+/// # Example
+///
+/// ```
+/// use pyrust::input::InputSource;
+///
+/// let output = pyrust::execute_python_with_input("print(1)", "unused").unwrap();
+/// assert_eq!(output, "1\n");
+///
+/// let reader = std::io::Cursor::new("line\n");
+/// let output = pyrust::execute_python_with_input("2", InputSource::from_reader(reader)).unwrap();
+/// assert_eq!(output, "2");
+/// ```
+pub fn execute_python_with_input(
+    code: &str,
+    input: impl Into<input::InputSource>,
+) -> Result<String, PyRustError> {
+    let bytecode = compile_cached_thread_local(code)?;
+
+    let mut vm = new_vm();
+    vm.set_stdin(input);
+    let result = vm
+        .execute(&bytecode)
+        .map_err(|e| e.with_location(&bytecode, code))?;
+
+    Ok(vm.format_output(result))
+}
+
+/// Execute Python source code with script arguments
+///
+/// The program reads `args` with the `argc()` and `argv(i)` builtins, where
+/// `argv(i)` parses argument `i` as an integer. A function the program
+/// defines with either name takes precedence. Otherwise behaves like
+/// [`execute_python`].
+///
+/// # Example
+///
+/// ```
+/// let args = vec!["6".to_string(), "7".to_string()];
+/// let output = pyrust::execute_python_with_args("print(argc())\nargv(0) * argv(-1)", args).unwrap();
+/// assert_eq!(output, "2\n42");
+///
+/// let error = pyrust::execute_python_with_args("argv(0)", Vec::new()).unwrap_err();
+/// assert!(error.to_string().contains("IndexError"));
+/// ```
+pub fn execute_python_with_args(code: &str, args: Vec<String>) -> Result<String, PyRustError> {
+    let bytecode = compile_cached_thread_local(code)?;
+
+    let mut vm = new_vm();
+    vm.set_args(args);
+    let result = vm
+        .execute(&bytecode)
+        .map_err(|e| e.with_location(&bytecode, code))?;
+
+    Ok(vm.format_output(result))
+}
+
+/// Execute Python source code, stopping early if `token` is cancelled
+///
+/// Cancel the token from another thread (or a signal handler) to abort a
+/// long-running script; execution then fails with an "execution cancelled"
+/// runtime error. Otherwise behaves like [`execute_python`].
+///
+/// # Example
+///
+/// ```
+/// use pyrust::cancel::CancellationToken;
+///
+/// let token = CancellationToken::new();
+/// assert_eq!(pyrust::execute_python_cancellable("6 * 7", &token).unwrap(), "42");
+/// ```
+pub fn execute_python_cancellable(
+    code: &str,
+    token: &cancel::CancellationToken,
+) -> Result<String, PyRustError> {
+    let bytecode = compile_cached_thread_local(code)?;
+
+    with_thread_vm(|vm| {
+        vm.set_cancellation_token(token.clone());
+        let result = vm
+            .execute(&bytecode)
+            .map_err(|e| e.with_location(&bytecode, code));
+        vm.clear_cancellation_token();
+
+        Ok(vm.format_output(result?))
+    })
+}
+
+/// Execute Python source code with streamed output, stopping early if `token` is cancelled
+///
+/// Combines [`execute_python_streaming`] and [`execute_python_cancellable`]:
+/// lines printed before cancellation have already been delivered to `sink`.
+pub fn execute_python_streaming_cancellable<F>(
+    code: &str,
+    sink: F,
+    token: &cancel::CancellationToken,
+) -> Result<String, PyRustError>
+where
+    F: FnMut(&str) + Send + 'static,
+{
+    execute_script_streaming(code, &ScriptOptions::default(), sink, token)
+}
+
+/// How to run a program as a script, as the CLI does
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScriptOptions {
+    /// Script arguments, read by the program with `argc()` and `argv(i)`
+    pub args: Vec<String>,
+    /// Time after which the run is stopped with a TimeoutError
+    pub timeout: Option<std::time::Duration>,
+    /// Compile afresh, neither looking the program up in a cache nor
+    /// adding it to one
+    pub no_cache: bool,
+    /// How compile warnings are reported; as [`compiler::Severity::Error`]
+    /// the first one fails the run before it starts, as `-W error` does, and
+    /// the program is compiled afresh
+    pub warnings: compiler::Severity,
+}
+
+/// [`execute_python_streaming_cancellable`] with script `options`
+///
+/// # Example
+///
+/// ```
+/// use pyrust::cancel::CancellationToken;
+/// use pyrust::ScriptOptions;
+///
+/// let options = ScriptOptions {
+///     args: vec!["41".to_string()],
+///     no_cache: true,
+///     ..ScriptOptions::default()
+/// };
+/// let token = CancellationToken::new();
+/// let output = pyrust::execute_script_streaming("argv(0) + 1", &options, |_| {}, &token);
+/// assert_eq!(output.unwrap(), "42");
+/// ```
+pub fn execute_script_streaming<F>(
+    code: &str,
+    options: &ScriptOptions,
+    sink: F,
+    token: &cancel::CancellationToken,
+) -> Result<String, PyRustError>
+where
+    F: FnMut(&str) + Send + 'static,
+{
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    // Cached programs were compiled without failing on warnings
+    let bytecode = if options.warnings == compiler::Severity::Error {
+        let compile_options = compiler::CompileOptions {
+            warnings: options.warnings,
+            ..Default::default()
+        };
+        Arc::new(compile_python_with_warnings(code, &compile_options)?.0)
+    } else if options.no_cache {
+        Arc::new(compile_python(code)?)
+    } else {
+        compile_cached_thread_local(code)?
+    };
+
+    let mut vm = new_vm();
+    vm.set_stdout_sink(sink);
+    vm.set_cancellation_token(token.clone());
+    vm.set_args(options.args.clone());
+    let expired = Arc::new(AtomicBool::new(false));
+    let timeout = options.timeout;
+    if let Some(timeout) = timeout {
+        daemon::set_deadline(&mut vm, timeout, Arc::clone(&expired));
+    }
+    let result = vm.execute(&bytecode).map_err(|mut e| {
+        if let (Some(timeout), true) = (timeout, expired.load(Ordering::SeqCst)) {
+            e.kind = error::ExceptionKind::TimeoutError;
+            e.message = format!("Timed out after {:.1}s", timeout.as_secs_f64());
+        }
+        e.with_location(&bytecode, code)
+    })?;
+
+    Ok(vm.format_output(result))
+}
+
+/// Look up bytecode in the thread-local cache, compiling and caching on a miss
+fn compile_cached_thread_local(code: &str) -> Result<Arc<bytecode::Bytecode>, PyRustError> {
     // Try to get bytecode from thread-local cache
     let bytecode = THREAD_LOCAL_CACHE.with(|cache| {
         let mut cache = cache.borrow_mut();
         cache.get(code)
     });
 
-    let bytecode = if let Some(cached_bytecode) = bytecode {
+    if let Some(cached_bytecode) = bytecode {
         // Cache hit - use cached bytecode
-        cached_bytecode
-    } else {
-        // Cache miss - compile and cache
-        // Stage 1: Lex the source code into tokens
-        let tokens = lexer::lex(code)?;
-
-        // Stage 2: Parse tokens into an Abstract Syntax Tree
-        let ast = parser::parse(tokens)?;
+        return Ok(cached_bytecode);
+    }
 
-        // Stage 3: Compile AST into bytecode
-        let bytecode = compiler::compile(&ast)?;
+    // Cache miss - compile and cache
+    let start = std::time::Instant::now();
 
-        // Wrap in Arc once
-        let bytecode_arc = Arc::new(bytecode);
+    // Stage 1: Lex the source code into tokens
+    let tokens = lexer::lex(code)?;
 
-        // Insert into thread-local cache
-        THREAD_LOCAL_CACHE.with(|cache| {
-            let mut cache = cache.borrow_mut();
-            cache.insert(code.to_string(), Arc::clone(&bytecode_arc));
-        });
+    // Stage 2: Parse tokens into an Abstract Syntax Tree
+    let (ast, positions) = parser::parse_with_positions(tokens)?;
 
-        bytecode_arc
-    };
+    // Stage 3: Compile AST into bytecode
+    let bytecode = compiler::compile_with_positions(&ast, &positions)?;
 
-    // Stage 4: Execute bytecode in the VM
-    let mut vm = vm::VM::new();
-    let result = vm.execute(&bytecode)?;
+    // Wrap in Arc once
+    let bytecode_arc = Arc::new(bytecode);
 
-    // Stage 5: Format output according to specification
-    let output = vm.format_output(result);
+    // Insert into thread-local cache
+    THREAD_LOCAL_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        cache.insert_timed(code.to_string(), Arc::clone(&bytecode_arc), start.elapsed());
+    });
 
-    Ok(output)
+    Ok(bytecode_arc)
 }
 
 /// Execute Python source code with global cache (daemon mode)
@@ -166,46 +572,122 @@ pub fn execute_python_cached(code: &str) -> Result<String, PyRustError> {
 /// * `Ok(String)` - Formatted output according to the output specification
 /// * `Err(PyRustError)` - Error from any stage of the pipeline
 pub fn execute_python_cached_global(code: &str) -> Result<String, PyRustError> {
+    with_thread_vm(|vm| execute_cached_global_on(vm, code))
+}
+
+/// Run `code` on `vm`, compiling through the global cache
+///
+/// Shared by [`execute_python_cached_global`] and the daemon's VM pool.
+pub(crate) fn execute_cached_global_on(vm: &mut vm::VM, code: &str) -> Result<String, PyRustError> {
+    let (bytecode, _cache_hit) = compile_cached_global(code)?;
+    execute_compiled_on(vm, &bytecode, code)
+}
+
+/// Run `bytecode`, compiled from `code`, on `vm` and format its output
+pub(crate) fn execute_compiled_on(
+    vm: &mut vm::VM,
+    bytecode: &bytecode::Bytecode,
+    code: &str,
+) -> Result<String, PyRustError> {
+    // Stage 4: Execute bytecode in the VM
+    let result = vm
+        .execute(bytecode)
+        .map_err(|e| e.with_location(bytecode, code))?;
+
+    // Stage 5: Format output according to specification
+    Ok(vm.format_output(result))
+}
+
+/// Like [`execute_compiled_on`], timing execution and formatting into
+/// `profile`
+pub(crate) fn execute_compiled_profiled_on(
+    vm: &mut vm::VM,
+    bytecode: &bytecode::Bytecode,
+    code: &str,
+    profile: &mut profiling::PipelineProfile,
+) -> Result<String, PyRustError> {
+    let start = std::time::Instant::now();
+    let result = vm.execute(bytecode);
+    let executed = std::time::Instant::now();
+    profile.vm_execute_ns = executed.duration_since(start).as_nanos() as u64;
+    let result = result.map_err(|e| e.with_location(bytecode, code))?;
+
+    let output = vm.format_output(result);
+    profile.format_ns = executed.elapsed().as_nanos() as u64;
+    Ok(output)
+}
+
+/// Look up bytecode in the global cache, compiling and caching on a miss
+///
+/// Also returns whether the bytecode came from the cache.
+pub(crate) fn compile_cached_global(
+    code: &str,
+) -> Result<(Arc<bytecode::Bytecode>, bool), PyRustError> {
     // Try to get bytecode from global cache
-    let bytecode = {
-        let mut cache = GLOBAL_CACHE.lock().unwrap();
-        cache.get(code)
-    };
+    let bytecode = GLOBAL_CACHE.get(code);
 
-    let bytecode = if let Some(cached_bytecode) = bytecode {
+    if let Some(cached_bytecode) = bytecode {
         // Cache hit - use cached bytecode
-        cached_bytecode
-    } else {
-        // Cache miss - compile and cache
-        // Stage 1: Lex the source code into tokens
-        let tokens = lexer::lex(code)?;
+        return Ok((cached_bytecode, true));
+    }
 
-        // Stage 2: Parse tokens into an Abstract Syntax Tree
-        let ast = parser::parse(tokens)?;
+    // Cache miss - compile and cache
+    let start = std::time::Instant::now();
 
-        // Stage 3: Compile AST into bytecode
-        let bytecode = compiler::compile(&ast)?;
+    // Stage 1: Lex the source code into tokens
+    let tokens = lexer::lex(code)?;
 
-        // Wrap in Arc once
-        let bytecode_arc = Arc::new(bytecode);
+    // Stage 2: Parse tokens into an Abstract Syntax Tree
+    let (ast, positions) = parser::parse_with_positions(tokens)?;
 
-        // Insert into global cache
-        {
-            let mut cache = GLOBAL_CACHE.lock().unwrap();
-            cache.insert(code.to_string(), Arc::clone(&bytecode_arc));
-        }
+    // Stage 3: Compile AST into bytecode
+    let bytecode = compiler::compile_with_positions(&ast, &positions)?;
 
-        bytecode_arc
-    };
+    // Wrap in Arc once
+    let bytecode_arc = Arc::new(bytecode);
 
-    // Stage 4: Execute bytecode in the VM
-    let mut vm = vm::VM::new();
-    let result = vm.execute(&bytecode)?;
+    // Insert into global cache
+    GLOBAL_CACHE.insert_timed(code.to_string(), Arc::clone(&bytecode_arc), start.elapsed());
 
-    // Stage 5: Format output according to specification
-    let output = vm.format_output(result);
+    Ok((bytecode_arc, false))
+}
 
-    Ok(output)
+/// Like [`compile_cached_global`], timing each stage into `profile`
+///
+/// On a cache hit no stage runs: the lookup is counted as compiling, and
+/// lexing and parsing take no time.
+pub(crate) fn compile_cached_global_profiled(
+    code: &str,
+    profile: &mut profiling::PipelineProfile,
+) -> Result<(Arc<bytecode::Bytecode>, bool), PyRustError> {
+    let start = std::time::Instant::now();
+    if let Some(cached_bytecode) = GLOBAL_CACHE.get(code) {
+        profile.compile_ns = start.elapsed().as_nanos() as u64;
+        return Ok((cached_bytecode, true));
+    }
+
+    let bytecode = Arc::new(compile_profiled(code, profile)?);
+    GLOBAL_CACHE.insert_timed(code.to_string(), Arc::clone(&bytecode), start.elapsed());
+    Ok((bytecode, false))
+}
+
+/// Compile `code` without any cache, timing each stage into `profile`
+pub(crate) fn compile_profiled(
+    code: &str,
+    profile: &mut profiling::PipelineProfile,
+) -> Result<bytecode::Bytecode, PyRustError> {
+    let start = std::time::Instant::now();
+    let tokens = lexer::lex(code)?;
+    let lexed = std::time::Instant::now();
+    profile.lex_ns = lexed.duration_since(start).as_nanos() as u64;
+
+    let (ast, positions) = parser::parse_with_positions(tokens)?;
+    let parsed = std::time::Instant::now();
+    profile.parse_ns = parsed.duration_since(lexed).as_nanos() as u64;
+
+    let bytecode = compiler::compile_with_positions(&ast, &positions)?;
+    profile.compile_ns = parsed.elapsed().as_nanos() as u64;
+    Ok(bytecode)
 }
 
 /// Execute Python source code and return formatted output
@@ -244,6 +726,15 @@ pub fn execute_python_cached_global(code: &str) -> Result<String, PyRustError> {
 /// - **Multiple prints**: `"1\n2\n3\n"` (each with newline)
 /// - **Empty program**: `""` (empty string)
 ///
+/// # Execution Limits
+///
+/// Setting `PYRUST_MAX_INSTRUCTIONS` caps the number of VM instructions each
+/// execution may run; programs exceeding it fail with an "execution budget
+/// exceeded" runtime error. Setting `PYRUST_MAX_OUTPUT_BYTES` caps print
+/// output; lines past the cap are dropped and replaced by a single
+/// `[output truncated after N bytes]` marker. Both variables are read once per
+/// process.
+///
 /// # Examples
 ///
 /// ## Basic Expression
@@ -316,7 +807,8 @@ pub fn execute_python_cached_global(code: &str) -> Result<String, PyRustError> {
 ///
 /// - **LexError**: Invalid character, integer literal overflow
 /// - **ParseError**: Syntax error, unexpected token, missing token
-/// - **CompileError**: Register limit exceeded (rare in practice)
+/// - **CompileError**: Wrong argument count for a function defined in the program,
+///   register limit exceeded (only by calls with more than 127 arguments)
 /// - **RuntimeError**: Division by zero, undefined variable, integer overflow
 ///
 /// All errors include detailed location information and context.
@@ -325,6 +817,134 @@ pub fn execute_python(code: &str) -> Result<String, PyRustError> {
     execute_python_cached(code)
 }
 
+/// Compile Python source code to bytecode without running it
+///
+/// The bytecode carries a line table, so runtime errors can still be mapped
+/// back to `code`. Nothing is cached; use this for ahead-of-time compilation,
+/// for example together with [`bytecode::Bytecode::to_bytes`].
+///
+/// # Example
+///
+/// ```
+/// let bytecode = pyrust::compile_python("x = 2\nx * 21").unwrap();
+///
+/// let mut vm = pyrust::vm::VM::new();
+/// let result = vm.execute(&bytecode).unwrap();
+/// assert_eq!(vm.format_output(result), "42");
+/// ```
+pub fn compile_python(code: &str) -> Result<bytecode::Bytecode, PyRustError> {
+    compile_python_with_options(code, &compiler::CompileOptions::default())
+}
+
+/// Parse Python source code and return its AST as indented text
+///
+/// The tree is the parser's output, before any simplification; see
+/// [`ast::Program::pretty`] for the format. `pyrust --ast` prints it.
+///
+/// # Example
+///
+/// ```
+/// let tree = pyrust::parse_to_string("x = (1 + 2) * 3").unwrap();
+/// assert_eq!(
+///     tree,
+///     "Program\n  Assignment x\n    BinaryOp *\n      BinaryOp +\n        Integer 1\n        Integer 2\n      Integer 3\n"
+/// );
+/// ```
+pub fn parse_to_string(code: &str) -> Result<String, PyRustError> {
+    let tokens = lexer::lex(code)?;
+    Ok(parser::parse(tokens)?.pretty())
+}
+
+/// Compile Python source code with explicit optimization settings
+///
+/// Like [`compile_python`], but lets the caller pick the
+/// [`compiler::OptLevel`].
+///
+/// # Example
+///
+/// ```
+/// use pyrust::compiler::{CompileOptions, OptLevel};
+///
+/// let options = CompileOptions { opt_level: OptLevel::O2, ..Default::default() };
+/// let bytecode = pyrust::compile_python_with_options("print(6 * 7)", &options).unwrap();
+/// assert_eq!(bytecode.constants, vec![42]);
+/// ```
+pub fn compile_python_with_options(
+    code: &str,
+    options: &compiler::CompileOptions,
+) -> Result<bytecode::Bytecode, PyRustError> {
+    let tokens = lexer::lex(code)?;
+    let (ast, positions) = parser::parse_with_positions(tokens)?;
+    Ok(compiler::compile_with_options(&ast, &positions, options)?)
+}
+
+/// Compile Python source code, returning warnings alongside the bytecode
+///
+/// The warnings of every stage are returned, the lexer's first, then the
+/// parser's and the compiler's. With [`compiler::CompileOptions::warnings`]
+/// set to [`compiler::Severity::Error`], the first of them is returned as a
+/// [`PyRustError::CompileError`] instead.
+///
+/// # Example
+///
+/// ```
+/// use pyrust::compiler::CompileOptions;
+///
+/// let code = "def f(n):\n    unused = n\n    return n\nprint(f(1))";
+/// let (_, warnings) = pyrust::compile_python_with_warnings(code, &CompileOptions::default()).unwrap();
+/// assert_eq!(warnings.len(), 1);
+/// assert_eq!(
+///     warnings[0].to_string(),
+///     "Warning[W0001] at 2:5: local variable 'unused' in 'f' is never read"
+/// );
+/// ```
+pub fn compile_python_with_warnings(
+    code: &str,
+    options: &compiler::CompileOptions,
+) -> Result<(bytecode::Bytecode, Vec<warnings::CompileWarning>), PyRustError> {
+    let (tokens, mut warnings) = lexer::lex_with_warnings(code)?;
+    let (ast, positions, parse_warnings) = parser::parse_with_warnings(tokens)?;
+    warnings.extend(parse_warnings);
+    if options.warnings == compiler::Severity::Error && !warnings.is_empty() {
+        return Err(warnings.swap_remove(0).into_error().into());
+    }
+    let (bytecode, compile_warnings) = compiler::compile_with_warnings(&ast, &positions, options)?;
+    warnings.extend(compile_warnings);
+    Ok((bytecode, warnings))
+}
+
+/// Execute a bytecode file written by `pyrust --compile`
+///
+/// Lexing, parsing and compilation are skipped entirely. The file is decoded
+/// with [`bytecode::Bytecode::from_bytes`] and checked with
+/// [`bytecode::Bytecode::verify`] before it runs, so a file from another
+/// format version or a corrupted file fails with
+/// [`PyRustError::BytecodeError`] instead of misbehaving. Runtime errors
+/// report the line and column recorded at compile time.
+///
+/// # Example
+///
+/// ```
+/// let path = std::env::temp_dir().join("pyrust_doc_example.pybc");
+/// let bytecode = pyrust::compile_python("print(6 * 7)").unwrap();
+/// std::fs::write(&path, bytecode.to_bytes()).unwrap();
+///
+/// assert_eq!(pyrust::execute_bytecode_file(&path).unwrap(), "42\n");
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+pub fn execute_bytecode_file(path: impl AsRef<std::path::Path>) -> Result<String, PyRustError> {
+    let bytes = std::fs::read(path).map_err(|e| bytecode_format::FormatError::Io(e.to_string()))?;
+    let bytecode = bytecode::Bytecode::from_bytes(&bytes)?;
+    bytecode.verify()?;
+
+    with_thread_vm(|vm| {
+        let result = vm
+            .execute(&bytecode)
+            .map_err(|e| e.with_location(&bytecode, ""))?;
+        Ok(vm.format_output(result))
+    })
+}
+
 /// Clear the thread-local cache
 ///
 /// This clears the compilation cache for the current thread.
@@ -341,8 +961,47 @@ pub fn clear_thread_local_cache() {
 /// This clears the compilation cache shared across all threads.
 /// Useful for daemon mode or when you want to reset the global cache state.
 pub fn clear_global_cache() {
-    let mut cache = GLOBAL_CACHE.lock().unwrap();
-    cache.clear();
+    GLOBAL_CACHE.clear();
+}
+
+/// Drop the cached bytecode of `code` from this thread's cache and the
+/// global cache, disk tier included
+///
+/// For source that has changed, so that the old version stops taking up
+/// room. Returns whether either cache held it.
+pub fn evict_cached(code: &str) -> bool {
+    let local = THREAD_LOCAL_CACHE.with(|cache| cache.borrow_mut().remove(code));
+    GLOBAL_CACHE.remove(code) || local
+}
+
+/// Remove the on-disk compilation cache entries
+///
+/// The disk tier and shared cache file are configured by the environment
+/// (see [`cache`]) and shared by every process using the same paths. Returns
+/// the number of entries removed; without either there is nothing to remove.
+pub fn clear_disk_cache() -> std::io::Result<usize> {
+    GLOBAL_CACHE.clear_disk()
+}
+
+/// Directory of the global cache's disk tier, if one is configured
+pub fn disk_cache_dir() -> Option<std::path::PathBuf> {
+    GLOBAL_CACHE.disk_dir()
+}
+
+/// Pre-compile scripts into the global cache
+///
+/// With a disk tier the bytecode outlives this process, so a daemon started
+/// later serves its first requests for these scripts from disk. See
+/// [`cache::ShardedCache::warm`].
+pub fn warm_global_cache(paths: &[std::path::PathBuf]) -> cache::WarmReport {
+    GLOBAL_CACHE.warm(paths)
+}
+
+/// Summaries of the programs in the global cache, largest first
+///
+/// See [`cache::format_entries`] for a printable table.
+pub fn global_cache_entries() -> Vec<cache::EntrySummary> {
+    GLOBAL_CACHE.entries()
 }
 
 /// Get global cache statistics
@@ -350,8 +1009,7 @@ pub fn clear_global_cache() {
 /// Returns statistics about the global cache (hits, misses, size, capacity, hit rate).
 /// Useful for monitoring daemon cache performance.
 pub fn get_global_cache_stats() -> cache::CacheStats {
-    let cache = GLOBAL_CACHE.lock().unwrap();
-    cache.stats()
+    GLOBAL_CACHE.stats()
 }
 
 /// Get thread-local cache statistics
@@ -548,6 +1206,140 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_runtime_error_reports_source_location() {
+        let code = "x = 10\ndef f(d):\n    return x / d\ny = f(0)";
+        match execute_python(code).unwrap_err() {
+            PyRustError::RuntimeError(e) => {
+                let location = e.location.clone().expect("runtime error has a location");
+                // Points at the division, not the start of the statement
+                assert_eq!((location.line, location.column), (3, 12));
+                assert_eq!(location.source_line, "    return x / d");
+                assert!(PyRustError::RuntimeError(e)
+                    .to_string()
+                    .starts_with("RuntimeError[E2001] at line 3, column 12: ZeroDivisionError"));
+            }
+            other => panic!("Expected RuntimeError, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_execute_bytecode_file() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("pyrust_test_{}.pybc", std::process::id()));
+        let bytecode = compile_python("x = 5\nprint(x * 2)\nx / 0").unwrap();
+        let mut bytes = bytecode.to_bytes();
+        std::fs::write(&path, &bytes).unwrap();
+
+        // Runtime errors keep their compile-time position without the source
+        let err = execute_bytecode_file(&path).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("RuntimeError[E2001] at line 3, column 1: ZeroDivisionError"));
+
+        // A newer format version is reported, not executed
+        bytes[5] += 1;
+        std::fs::write(&path, &bytes).unwrap();
+        match execute_bytecode_file(&path).unwrap_err() {
+            PyRustError::BytecodeError(bytecode_format::FormatError::UnsupportedVersion {
+                found,
+                ..
+            }) => assert_eq!(found, bytecode_format::FORMAT_VERSION + 1),
+            other => panic!("Expected BytecodeError, got {:?}", other),
+        }
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            execute_bytecode_file(&path),
+            Err(PyRustError::BytecodeError(
+                bytecode_format::FormatError::Io(_)
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_batch_reports_each_snippet_separately() {
+        let results = execute_python_batch(&["x = 3\nx * 2", "print(", "y = 1\ny / 0", "x"]);
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0].as_deref().unwrap(), "6");
+        assert!(matches!(results[1], Err(PyRustError::ParseError(_))));
+        match &results[2] {
+            Err(PyRustError::RuntimeError(e)) => {
+                assert_eq!(e.location.as_ref().map(|l| l.line), Some(2));
+            }
+            other => panic!("Expected RuntimeError, got {:?}", other),
+        }
+        // Globals from earlier snippets are not visible
+        assert!(matches!(results[3], Err(PyRustError::RuntimeError(_))));
+        assert!(execute_python_batch(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_cancelled_execution_leaves_thread_vm_usable() {
+        let token = cancel::CancellationToken::new();
+        token.cancel();
+        match execute_python_cancellable("x = 1\nx", &token).unwrap_err() {
+            PyRustError::RuntimeError(e) => assert_eq!(e.message, cancel::CANCELLED_MESSAGE),
+            other => panic!("Expected RuntimeError, got {:?}", other),
+        }
+
+        // The token is detached again, so later runs are unaffected
+        assert_eq!(execute_python("x = 2\nx").unwrap(), "2");
+    }
+
+    #[test]
+    fn test_script_timeout_raises_timeout_error() {
+        // f0 makes 2^40 calls
+        let mut code = "print(1)\ndef f40():\n    return 1\n".to_string();
+        for i in (0..40).rev() {
+            code.push_str(&format!(
+                "def f{}():\n    return f{}() + f{}()\n",
+                i,
+                i + 1,
+                i + 1
+            ));
+        }
+        code.push_str("f0()");
+
+        let token = cancel::CancellationToken::new();
+        let options = ScriptOptions {
+            timeout: Some(std::time::Duration::from_millis(50)),
+            ..ScriptOptions::default()
+        };
+        let error = execute_script_streaming(&code, &options, |_| {}, &token).unwrap_err();
+        assert!(error.is_timeout());
+        assert!(error
+            .to_string()
+            .contains("TimeoutError: Timed out after 0.1s"));
+
+        let output = execute_script_streaming("6 * 7", &options, |_| {}, &token);
+        assert_eq!(output.unwrap(), "42");
+    }
+
+    #[test]
+    fn test_script_fails_on_warnings_before_running() {
+        let code = "print(1)\ndef f():\n    return 1\n    print(2)\nf()";
+        let token = cancel::CancellationToken::new();
+        let printed = Arc::new(std::sync::Mutex::new(String::new()));
+        let sink = Arc::clone(&printed);
+        let options = ScriptOptions {
+            warnings: compiler::Severity::Error,
+            ..ScriptOptions::default()
+        };
+        let error = execute_script_streaming(
+            code,
+            &options,
+            move |text| sink.lock().unwrap().push_str(text),
+            &token,
+        )
+        .unwrap_err();
+        assert_eq!(error.code(), error_code::ErrorCode::UnreachableCode);
+        assert!(printed.lock().unwrap().is_empty());
+
+        let output = execute_script_streaming(code, &ScriptOptions::default(), |_| {}, &token);
+        assert_eq!(output.unwrap(), "1");
+    }
+
     #[test]
     fn test_runtime_error_undefined_variable() {
         let result = execute_python("undefined_var");
@@ -561,6 +1353,39 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_runtime_error_exception_kinds() {
+        use error::ExceptionKind;
+
+        let cases = [
+            ("10 / 0", ExceptionKind::ZeroDivisionError),
+            ("10 % 0", ExceptionKind::ZeroDivisionError),
+            ("undefined_var", ExceptionKind::NameError),
+            ("missing()", ExceptionKind::NameError),
+            ("def f():\n    return\n-f()", ExceptionKind::TypeError),
+            ("9223372036854775807 + 1", ExceptionKind::OverflowError),
+        ];
+
+        for (code, expected) in cases {
+            match execute_python(code).unwrap_err() {
+                PyRustError::RuntimeError(e) => assert_eq!(e.kind, expected, "{}", code),
+                other => panic!("Expected RuntimeError for {:?}, got {:?}", code, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_runtime_error_unbounded_recursion() {
+        let code = "def f(n):\n    return f(n)\nf(1)";
+        match execute_python(code).unwrap_err() {
+            PyRustError::RuntimeError(e) => {
+                assert_eq!(e.kind, error::ExceptionKind::RecursionError);
+                assert!(e.message.contains("maximum recursion depth"));
+            }
+            other => panic!("Expected RuntimeError, got {:?}", other),
+        }
+    }
+
     // Negative number tests
     #[test]
     fn test_negative_numbers() {
@@ -679,6 +1504,18 @@ d
         assert_eq!(result, "20");
     }
 
+    #[test]
+    fn test_multi_target_assignment() {
+        let code = "a = b = c = 7\na + b + c";
+        assert_eq!(execute_python(code).unwrap(), "21");
+    }
+
+    #[test]
+    fn test_multi_target_assignment_in_function() {
+        let code = "def f(x):\n    a = b = x * 2\n    return a + b\nf(5)";
+        assert_eq!(execute_python(code).unwrap(), "20");
+    }
+
     // Complex expressions
     #[test]
     fn test_deeply_nested_expression() {
@@ -805,4 +1642,24 @@ d
         let result2_again = execute_python(code2).unwrap();
         assert_eq!(result2_again, "30");
     }
+
+    #[test]
+    fn test_reused_vm_does_not_leak_state_between_calls() {
+        assert_eq!(execute_python("leaky = 5\nprint(leaky)").unwrap(), "5\n");
+        assert!(execute_python("leaky").is_err());
+
+        assert_eq!(
+            execute_python("def leaky_fn():\n    return 1\nleaky_fn()").unwrap(),
+            "1"
+        );
+        assert!(execute_python("leaky_fn()").is_err());
+
+        assert_eq!(execute_python("7").unwrap(), "7");
+    }
+
+    #[test]
+    fn test_thread_vm_falls_back_when_in_use() {
+        let output = with_thread_vm(|_| execute_python("1 + 1").unwrap());
+        assert_eq!(output, "2");
+    }
 }
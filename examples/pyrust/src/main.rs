@@ -1,36 +1,110 @@
 use std::env;
 use std::fs;
+use std::io::Write;
 use std::process;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+    select_instance(&mut args);
+    let timeout = take_timeout(&mut args);
+    let _ = COLOR.set(take_color(&mut args));
+    let _ = JSON_ERRORS.set(take_error_format(&mut args));
+    let _ = VERBOSITY.set(take_verbosity(&mut args));
+    let _ = WARNINGS_AS_ERRORS.set(take_warnings_as_errors(&mut args));
 
     // Check for daemon management commands
     if args.len() > 1 {
         match args[1].as_str() {
             "--daemon" => {
-                start_daemon();
+                start_daemon(&args[2..]);
                 return;
             }
+            #[cfg(windows)]
+            pyrust::daemon_spawn::DAEMON_CHILD_ARG => run_daemon_child(&args[2..]),
             "--stop-daemon" => {
                 stop_daemon();
                 return;
             }
             "--daemon-status" => {
-                show_daemon_status();
+                show_daemon_status(&args[2..]);
+                return;
+            }
+            "--daemon-health" => {
+                check_daemon_health();
                 return;
             }
             "--clear-cache" => {
                 clear_cache();
                 return;
             }
+            "--stats" => {
+                show_stats(&args[2..]);
+                return;
+            }
+            "--cache-list" => {
+                show_cache_list();
+                return;
+            }
+            "--cache-stats" => {
+                show_cache_stats();
+                return;
+            }
+            "--warm-cache" => {
+                warm_cache(&args[2..]);
+                return;
+            }
+            "--compile" => {
+                compile_to_file(&args[2..]);
+                return;
+            }
+            "run" => {
+                run_bytecode_file(&args[2..]);
+                return;
+            }
+            "explain" => {
+                explain_code(&args[2..]);
+                return;
+            }
+            "--session" => {
+                run_in_session(&args[2..]);
+                return;
+            }
+            "--watch" => watch_script(&args[2..]),
+            "--ast" => {
+                show_ast(&args[2..]);
+                return;
+            }
+            "--dis" => {
+                show_disassembly(&args[2..]);
+                return;
+            }
+            "--check" => check_scripts(&args[2..]),
+            "--bench" => {
+                bench_script(&args[2..]);
+                return;
+            }
             _ => {}
         }
     }
 
+    // Everything after `--` is passed to the script
+    let script_args = match args.iter().position(|arg| arg == "--") {
+        Some(index) => {
+            let script_args = args.split_off(index + 1);
+            args.pop();
+            script_args
+        }
+        None => Vec::new(),
+    };
+
     // Check for profiling flags
     let enable_profile = args.contains(&"--profile".to_string());
     let profile_json = args.contains(&"--profile-json".to_string());
+    let trace = args.contains(&"--trace".to_string());
+    let unbuffered = args.contains(&"--unbuffered".to_string());
+    let show_warnings = args.contains(&"--warnings".to_string());
+    let no_daemon = args.contains(&"--no-daemon".to_string());
+    let no_cache = args.contains(&"--no-cache".to_string());
 
     let code = if args.len() > 1 {
         if args[1] == "-c" {
@@ -42,7 +116,7 @@ fn main() {
             args[2].clone()
         } else if args[1].starts_with("--") {
             // Handle flag-only invocations
-            eprintln!("Usage: pyrust <file.py> | pyrust -c <code> [--profile | --profile-json | --daemon | --stop-daemon | --daemon-status | --clear-cache]");
+            eprintln!("{}", USAGE);
             process::exit(1);
         } else {
             // File mode: pyrust script.py
@@ -55,14 +129,71 @@ fn main() {
             }
         }
     } else {
-        eprintln!("Usage: pyrust <file.py> | pyrust -c <code> [--profile | --profile-json | --daemon | --stop-daemon | --daemon-status | --clear-cache]");
+        eprintln!("{}", USAGE);
         process::exit(1);
     };
 
-    if enable_profile || profile_json {
-        // Execute with profiling (always direct execution, no daemon)
-        match pyrust::profiling::execute_python_profiled(&code) {
-            Ok((output, profile)) => {
+    // With -W error there is nothing to show: the run below fails on the
+    // first warning. Other compile errors are reported by the run too.
+    if show_warnings && !warnings_are_errors() {
+        if let Ok((_, warnings)) = pyrust::compile_python_with_warnings(&code, &Default::default())
+        {
+            for warning in &warnings {
+                print_warning(warning);
+            }
+        }
+    }
+
+    if !script_args.is_empty() && (trace || enable_profile || profile_json) {
+        eprintln!("Script arguments cannot be used with --trace or --profile");
+        process::exit(1);
+    }
+    if timeout.is_some() && (trace || enable_profile || profile_json) {
+        eprintln!("--timeout cannot be used with --trace or --profile");
+        process::exit(1);
+    }
+    if warnings_are_errors() && (trace || enable_profile || profile_json) {
+        eprintln!("-W error cannot be used with --trace or --profile");
+        process::exit(1);
+    }
+    if no_cache && trace {
+        eprintln!("--no-cache cannot be used with --trace");
+        process::exit(1);
+    }
+    let options = pyrust::ScriptOptions {
+        args: script_args,
+        timeout,
+        no_cache,
+        warnings: warning_severity(),
+    };
+
+    if trace {
+        // Trace every instruction to stderr (always direct execution, no daemon)
+        let trace_line = |event: &pyrust::vm::TraceEvent| eprintln!("{}", event);
+        match pyrust::execute_python_traced(&code, trace_line) {
+            Ok(output) => {
+                if !output.is_empty() {
+                    print!("{}", output);
+                }
+            }
+            Err(e) => exit_with_error(&e, Some(&code)),
+        }
+    } else if enable_profile || profile_json {
+        // Profile in the daemon, whose cache may already hold the program,
+        // falling back to direct execution, which compiles without a cache
+        use pyrust::daemon_client::DaemonClient;
+        let in_daemon = match (no_daemon, no_cache) {
+            (true, _) => None,
+            (false, false) => DaemonClient::execute_profiled(&code).ok(),
+            (false, true) => DaemonClient::execute_profiled_without_cache(&code).ok(),
+        };
+        let profiled = match in_daemon {
+            Some((output, profile, cache_hit)) => Ok((output, profile, Some(cache_hit))),
+            None => pyrust::profiling::execute_python_profiled(&code)
+                .map(|(output, profile)| (output, profile, None)),
+        };
+        match profiled {
+            Ok((output, profile, cache_hit)) => {
                 // Print output first (stdout)
                 if !output.is_empty() {
                     print!("{}", output);
@@ -73,163 +204,609 @@ fn main() {
                     eprintln!("{}", profile.format_json());
                 } else {
                     eprintln!("\n{}", profile.format_table());
+                    if let Some(cache_hit) = cache_hit {
+                        let cache = if cache_hit { "hit" } else { "miss" };
+                        eprintln!("Profiled in daemon (cache {})", cache);
+                    }
                 }
             }
-            Err(e) => {
-                eprintln!("{}", e);
-                process::exit(1);
+            Err(e) => exit_with_error(&e, Some(&code)),
+        }
+    } else if unbuffered {
+        // Flush each print as soon as it arrives, from the daemon or from
+        // direct execution
+        let write_through = |line: &str| {
+            let mut stdout = std::io::stdout().lock();
+            let _ = stdout.write_all(line.as_bytes());
+            let _ = stdout.flush();
+        };
+        let interrupt = interrupt_token();
+        match run_script(&code, &options, no_daemon, write_through, &interrupt) {
+            Ok(output) => {
+                if !output.is_empty() {
+                    print!("{}", output);
+                }
             }
+            Err(_) if interrupt.is_cancelled() => {
+                eprintln!("KeyboardInterrupt");
+                process::exit(130);
+            }
+            Err(e) => exit_with_error(&*e, Some(&code)),
         }
     } else {
-        // Try daemon execution with fallback to direct execution
-        match pyrust::daemon_client::DaemonClient::execute_or_fallback(&code) {
+        // Try daemon execution with fallback to direct execution, streaming
+        // prints as they happen either way (stdout is line-buffered)
+        let stream_stdout = |line: &str| {
+            let _ = std::io::stdout().write_all(line.as_bytes());
+        };
+        let interrupt = interrupt_token();
+        match run_script(&code, &options, no_daemon, stream_stdout, &interrupt) {
             Ok(output) => {
                 if !output.is_empty() {
                     print!("{}", output);
                 }
             }
-            Err(e) => {
-                eprintln!("{}", e);
-                process::exit(1);
+            Err(_) if interrupt.is_cancelled() => {
+                eprintln!("KeyboardInterrupt");
+                process::exit(130);
             }
+            Err(e) => exit_with_error(&*e, Some(&code)),
         }
     }
 }
 
-/// Start the daemon in background using fork
-fn start_daemon() {
-    use pyrust::daemon::DaemonServer;
+/// Run a script, printing through `sink`
+///
+/// Runs in the daemon, falling back to direct execution, or directly with
+/// `--no-daemon`.
+fn run_script<F>(
+    code: &str,
+    options: &pyrust::ScriptOptions,
+    no_daemon: bool,
+    sink: F,
+    token: &pyrust::cancel::CancellationToken,
+) -> Result<String, Box<dyn std::error::Error>>
+where
+    F: FnMut(&str) + Send + 'static,
+{
+    if no_daemon {
+        pyrust::execute_script_streaming(code, options, sink, token).map_err(Into::into)
+    } else {
+        pyrust::daemon_client::DaemonClient::execute_script(code, options, sink, token)
+    }
+}
 
-    // Check if daemon is already running
-    if pyrust::daemon_client::DaemonClient::is_daemon_running() {
-        eprintln!("Daemon is already running");
-        process::exit(1);
+/// Usage line printed when no script is given
+const USAGE: &str = "Usage: pyrust [--name <id>] [--timeout <duration>] [--color=<when>] [--error-format=<format>] [--verbosity=<level>] [-W error] <file.py> [-- <args>...] | pyrust [--name <id>] [--timeout <duration>] [--color=<when>] [--error-format=<format>] [--verbosity=<level>] [-W error] -c <code> [-- <args>...] | pyrust explain [<code>] | pyrust run <file.pybc> [--profile | --profile-json | --trace | --unbuffered | --warnings | --no-daemon | --no-cache | --compile <file.py> [-o <file.pybc>] [-O0 | -O1 | -O2] [--warnings] [--deny-division-by-zero] | --session <id> (<file.py> | -c <code>) | --watch <file.py> | --ast (<file.py> | -c <code>) | --dis (<file.py> | -c <code>) | --check [--deny-warnings] [--deny-division-by-zero] (<file.py>... | -c <code>) | --bench <runs> [--warm] (<file.py> | -c <code>) | --daemon [--async] [--supervise] [--log-level <level>] [--log-file <path>] | --stop-daemon | --daemon-status [--json] | --daemon-health | --stats [--format=text|prometheus] | --cache-list | --cache-stats | --clear-cache | --warm-cache <dir>]";
+
+/// Exit status of a run stopped by `--timeout`, as used by `timeout(1)`
+const TIMEOUT_EXIT_CODE: i32 = 124;
+
+/// `--color` choice for diagnostics, set once at startup
+static COLOR: std::sync::OnceLock<pyrust::error::ColorChoice> = std::sync::OnceLock::new();
+
+/// `--error-format=json`, set once at startup
+static JSON_ERRORS: std::sync::OnceLock<bool> = std::sync::OnceLock::new();
+
+/// `--verbosity` level of diagnostics, set once at startup
+static VERBOSITY: std::sync::OnceLock<pyrust::error::Verbosity> = std::sync::OnceLock::new();
+
+/// `-W error`, set once at startup
+static WARNINGS_AS_ERRORS: std::sync::OnceLock<bool> = std::sync::OnceLock::new();
+
+/// Whether `-W error` was given
+fn warnings_are_errors() -> bool {
+    WARNINGS_AS_ERRORS.get() == Some(&true)
+}
+
+/// How compilation should report warnings, given `-W error`
+fn warning_severity() -> pyrust::compiler::Severity {
+    if warnings_are_errors() {
+        pyrust::compiler::Severity::Error
+    } else {
+        pyrust::compiler::Severity::Warning
     }
+}
 
-    // Create a pipe for parent-child synchronization
-    let mut pipe_fds: [libc::c_int; 2] = [0, 0];
-    unsafe {
-        if libc::pipe(pipe_fds.as_mut_ptr()) < 0 {
-            eprintln!("Failed to create pipe");
-            process::exit(1);
+/// Print an error to stderr
+///
+/// With `--error-format=json`, the error is printed as a one-line JSON
+/// diagnostic (see [`diagnostic_of`]). Otherwise, on a terminal or with
+/// `--color=always`, a pyrust error is shown with its source line from
+/// `source` and carets under its span (see
+/// [`pyrust::error::PyRustError::render`]); colors follow `--color`.
+/// `--verbosity=short` cuts any error down to its first line, and
+/// `--verbosity=verbose` always shows the source line, with the details of
+/// [`pyrust::error::Verbosity::Verbose`].
+fn print_error(error: &(dyn std::error::Error + 'static), source: Option<&str>) {
+    use pyrust::daemon_client::DaemonClientError;
+    use pyrust::error::Verbosity;
+    use std::io::IsTerminal;
+
+    if JSON_ERRORS.get() == Some(&true) {
+        eprintln!("{}", diagnostic_of(error).to_json());
+        return;
+    }
+    let terminal = std::io::stderr().is_terminal();
+    let choice = COLOR.get().copied().unwrap_or_default();
+    let verbosity = VERBOSITY.get().copied().unwrap_or_default();
+    let render =
+        terminal || choice == pyrust::error::ColorChoice::Always || verbosity == Verbosity::Verbose;
+    let text = match (
+        error.downcast_ref::<pyrust::error::PyRustError>(),
+        error.downcast_ref::<DaemonClientError>(),
+    ) {
+        (Some(e), _) if render => e.render_with(source, verbosity),
+        (_, Some(DaemonClientError::Failed(diagnostic))) if render => {
+            diagnostic.render_with(source, verbosity)
         }
+        _ if verbosity == Verbosity::Short => pyrust::error::first_line(&error.to_string()),
+        _ => error.to_string(),
+    };
+    if choice.enabled(terminal) {
+        eprintln!("{}", pyrust::error::colorize(&text));
+    } else {
+        eprintln!("{}", text);
+    }
+}
+
+/// Print a compile warning to stderr, as JSON with `--error-format=json`
+fn print_warning(warning: &pyrust::warnings::CompileWarning) {
+    if JSON_ERRORS.get() == Some(&true) {
+        eprintln!(
+            "{}",
+            pyrust::diagnostic::Diagnostic::from(warning).to_json()
+        );
+    } else {
+        eprintln!("{}", warning);
+    }
+}
+
+/// The diagnostic describing `error`
+///
+/// Errors that are neither a pyrust error nor a daemon's diagnostic, such
+/// as a run stopped at a daemon's limit, are reported as runtime errors.
+fn diagnostic_of(error: &(dyn std::error::Error + 'static)) -> pyrust::diagnostic::Diagnostic {
+    use pyrust::daemon_client::DaemonClientError;
+    use pyrust::diagnostic::{Diagnostic, Stage};
+
+    if let Some(e) = error.downcast_ref::<pyrust::error::PyRustError>() {
+        let verbosity = VERBOSITY.get().copied().unwrap_or_default();
+        return Diagnostic::from_error(e, verbosity);
+    }
+    match error.downcast_ref::<DaemonClientError>() {
+        Some(DaemonClientError::Failed(diagnostic)) => (**diagnostic).clone(),
+        _ => Diagnostic::error(Stage::Runtime, error.to_string()),
+    }
+}
+
+/// Exit after a failed run of `source`
+///
+/// A program that called `exit(n)` ends the process with status `n` and
+/// prints nothing; any other error is printed with [`print_error`], with
+/// status [`TIMEOUT_EXIT_CODE`] for a run that timed out and 1 otherwise.
+fn exit_with_error(error: &(dyn std::error::Error + 'static), source: Option<&str>) -> ! {
+    if let Some(code) = exit_code(error) {
+        let _ = std::io::stdout().flush();
+        process::exit(code);
+    }
+    print_error(error, source);
+    process::exit(if timed_out(error) {
+        TIMEOUT_EXIT_CODE
+    } else {
+        1
+    });
+}
+
+/// Whether the error stopped a run that went past its `--timeout`
+fn timed_out(error: &(dyn std::error::Error + 'static)) -> bool {
+    if let Some(e) = error.downcast_ref::<pyrust::error::PyRustError>() {
+        return e.is_timeout();
     }
-    let pipe_read_fd = pipe_fds[0];
-    let pipe_write_fd = pipe_fds[1];
+    matches!(
+        error.downcast_ref::<pyrust::daemon_client::DaemonClientError>(),
+        Some(pyrust::daemon_client::DaemonClientError::TimedOut(_))
+    )
+}
 
-    // Fork the process
-    let pid = unsafe { libc::fork() };
+/// The status a program asked to exit with, if the error is its `exit(n)`
+fn exit_code(error: &(dyn std::error::Error + 'static)) -> Option<i32> {
+    if let Some(e) = error.downcast_ref::<pyrust::error::PyRustError>() {
+        return e.exit_code();
+    }
+    match error.downcast_ref::<pyrust::daemon_client::DaemonClientError>() {
+        Some(pyrust::daemon_client::DaemonClientError::Exited(code)) => Some(*code),
+        _ => None,
+    }
+}
 
-    if pid < 0 {
-        // Close pipe FDs to prevent resource leak
-        unsafe {
-            libc::close(pipe_read_fd);
-            libc::close(pipe_write_fd);
+/// Select the daemon instance named by a `--name <id>` option
+///
+/// The option may appear anywhere before `-c` and its code, or before the
+/// `--` that starts the script's arguments, as in `pyrust --daemon --name
+/// proj` or `pyrust --name proj script.py`. It is taken out of `args` and
+/// handed on through `PYRUST_DAEMON_NAME`, which the client, and any daemon
+/// started from here, read their paths from.
+fn select_instance(args: &mut Vec<String>) {
+    use pyrust::daemon::{is_valid_daemon_name, DAEMON_NAME_ENV};
+
+    let end = args
+        .iter()
+        .position(|arg| arg == "-c" || arg == "--")
+        .unwrap_or(args.len());
+    let name = match args[..end].iter().position(|arg| arg == "--name") {
+        Some(index) if index + 1 < end => {
+            let name = args.remove(index + 1);
+            args.remove(index);
+            name
+        }
+        Some(_) => {
+            eprintln!("Usage: pyrust --name <id> ...");
+            process::exit(1);
         }
-        eprintln!("Failed to fork process");
+        None => match env::var(DAEMON_NAME_ENV) {
+            Ok(name) => name,
+            Err(_) => return,
+        },
+    };
+    if !is_valid_daemon_name(&name) {
+        eprintln!(
+            "Invalid daemon name {:?}: use 1 to 64 letters, digits, '-' or '_'",
+            name
+        );
         process::exit(1);
-    } else if pid > 0 {
-        // Parent process - wait for child to signal readiness
-        unsafe {
-            libc::close(pipe_write_fd); // Close write end in parent
-        }
-
-        // Read from pipe to confirm child is ready
-        let mut ready_byte = [0u8; 1];
-        let result = unsafe {
-            libc::read(
-                pipe_read_fd,
-                ready_byte.as_mut_ptr() as *mut libc::c_void,
-                1,
-            )
-        };
+    }
+    env::set_var(DAEMON_NAME_ENV, name);
+}
 
-        unsafe {
-            libc::close(pipe_read_fd);
+/// Take a `--timeout <duration>` option out of `args`
+///
+/// Like `--name`, the option goes before `-c` or the `--` that starts the
+/// script's arguments, as in `pyrust --timeout 500ms script.py`. The
+/// duration is a number with an `ms`, `s` or `m` suffix, seconds without
+/// one.
+fn take_timeout(args: &mut Vec<String>) -> Option<std::time::Duration> {
+    let end = args
+        .iter()
+        .position(|arg| arg == "-c" || arg == "--")
+        .unwrap_or(args.len());
+    let index = args[..end].iter().position(|arg| arg == "--timeout")?;
+    if index + 1 >= end {
+        eprintln!("Usage: pyrust --timeout <duration> ...");
+        process::exit(1);
+    }
+    let text = args.remove(index + 1);
+    args.remove(index);
+    match parse_duration(&text) {
+        Some(timeout) => Some(timeout),
+        None => {
+            eprintln!(
+                "Invalid timeout {:?}: use a number of ms, s or m, as in 500ms",
+                text
+            );
+            process::exit(1);
         }
+    }
+}
 
-        if result == 1 && ready_byte[0] == b'R' {
-            // Child signaled ready successfully
-            println!("Daemon started with PID {}", pid);
-            process::exit(0);
-        } else {
-            // Child failed to start (pipe closed without sending 'R')
-            eprintln!("Failed to start daemon: initialization error");
+/// Take a `<name>=<value>` option out of `args`, if given
+///
+/// The value may also come as the next argument, and is empty if missing.
+/// Like `--name`, the option goes before `-c` or the `--` that starts the
+/// script's arguments.
+fn take_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let end = args
+        .iter()
+        .position(|arg| arg == "-c" || arg == "--")
+        .unwrap_or(args.len());
+    let index = args[..end].iter().position(|arg| {
+        arg.strip_prefix(name)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('='))
+    })?;
+    let value = match args.remove(index)[name.len()..].strip_prefix('=') {
+        Some(value) => value.to_string(),
+        None if index + 1 < end => args.remove(index),
+        None => String::new(),
+    };
+    Some(value)
+}
+
+/// Take a `--color=<when>` option out of `args`
+///
+/// `<when>` is `auto` (the default), `always` or `never`.
+fn take_color(args: &mut Vec<String>) -> pyrust::error::ColorChoice {
+    let Some(when) = take_option(args, "--color") else {
+        return pyrust::error::ColorChoice::default();
+    };
+    match pyrust::error::ColorChoice::from_name(&when) {
+        Some(choice) => choice,
+        None => {
+            eprintln!("Usage: pyrust --color=(auto | always | never) ...");
             process::exit(1);
         }
     }
+}
 
-    // Child process continues below
-    unsafe {
-        libc::close(pipe_read_fd); // Close read end in child
+/// Take an `--error-format=<format>` option out of `args`, returning
+/// whether errors are wanted as JSON
+///
+/// `<format>` is `human` (the default) or `json`.
+fn take_error_format(args: &mut Vec<String>) -> bool {
+    match take_option(args, "--error-format").as_deref() {
+        None | Some("human") => false,
+        Some("json") => true,
+        Some(_) => {
+            eprintln!("Usage: pyrust --error-format=(human | json) ...");
+            process::exit(1);
+        }
     }
+}
 
-    // Become session leader
-    unsafe {
-        if libc::setsid() < 0 {
-            // Can still report error via pipe before closing stderr
-            let error_msg = b"Failed to create new session\n";
-            let _ = libc::write(
-                libc::STDERR_FILENO,
-                error_msg.as_ptr() as *const libc::c_void,
-                error_msg.len(),
-            );
-            libc::close(pipe_write_fd);
+/// Take a `--verbosity=<level>` option out of `args`
+///
+/// `<level>` is `short`, `normal` (the default) or `verbose`.
+fn take_verbosity(args: &mut Vec<String>) -> pyrust::error::Verbosity {
+    let Some(level) = take_option(args, "--verbosity") else {
+        return pyrust::error::Verbosity::default();
+    };
+    match pyrust::error::Verbosity::from_name(&level) {
+        Some(verbosity) => verbosity,
+        None => {
+            eprintln!("Usage: pyrust --verbosity=(short | normal | verbose) ...");
             process::exit(1);
         }
     }
+}
 
-    // Initialize daemon BEFORE closing stderr so errors can be reported
-    let daemon = match DaemonServer::new() {
-        Ok(d) => d,
-        Err(e) => {
-            // Report error before closing stderr
-            eprintln!("Failed to initialize daemon: {}", e);
-            unsafe {
-                libc::close(pipe_write_fd);
-            }
+/// Take a `-W error` option out of `args`, returning whether warnings are
+/// to fail like errors
+fn take_warnings_as_errors(args: &mut Vec<String>) -> bool {
+    match take_option(args, "-W").as_deref() {
+        None => false,
+        Some("error") => true,
+        Some(_) => {
+            eprintln!("Usage: pyrust -W error ...");
             process::exit(1);
         }
+    }
+}
+
+/// Parse a duration such as `500ms`, `2s`, `1.5m` or `3` (seconds)
+fn parse_duration(text: &str) -> Option<std::time::Duration> {
+    let (number, scale) = if let Some(number) = text.strip_suffix("ms") {
+        (number, 0.001)
+    } else if let Some(number) = text.strip_suffix('s') {
+        (number, 1.0)
+    } else if let Some(number) = text.strip_suffix('m') {
+        (number, 60.0)
+    } else {
+        (text, 1.0)
     };
+    let seconds = number.parse::<f64>().ok()?;
+    if !seconds.is_finite() {
+        return None;
+    }
+    std::time::Duration::try_from_secs_f64(seconds * scale).ok()
+}
+
+/// Token cancelled by Ctrl-C
+///
+/// The first SIGINT stops the running script cooperatively; a second one
+/// terminates the process immediately in case the script does not stop.
+fn interrupt_token() -> pyrust::cancel::CancellationToken {
+    use signal_hook::consts::SIGINT;
+
+    let token = pyrust::cancel::CancellationToken::new();
+    let flag = std::sync::Arc::clone(token.flag());
+    // Registered first so it sees the flag before this interrupt sets it
+    let _ =
+        signal_hook::flag::register_conditional_shutdown(SIGINT, 130, std::sync::Arc::clone(&flag));
+    let _ = signal_hook::flag::register(SIGINT, flag);
+    token
+}
 
-    // Close standard file descriptors
-    unsafe {
-        libc::close(0); // stdin
-        libc::close(1); // stdout
-        libc::close(2); // stderr
-    }
-
-    // Redirect standard file descriptors to /dev/null
-    unsafe {
-        use std::ffi::CString;
-        let dev_null = CString::new("/dev/null").unwrap();
-        let fd = libc::open(dev_null.as_ptr(), libc::O_RDWR);
-        if fd < 0 {
-            // Failed to open /dev/null - daemon cannot run safely
-            libc::close(pipe_write_fd);
+/// Start the daemon in background using fork (a detached process on Windows)
+///
+/// With `--async`, the tokio-based server of the `async-daemon` feature is
+/// started instead of the default one. `--log-level <level>` (off, error,
+/// warn, info, debug or trace) and `--log-file <path>` configure the daemon's
+/// log (see [`pyrust::daemon_log`]). A daemon selected with `--name` gets
+/// its own socket, PID file and log.
+fn start_daemon(args: &[String]) {
+    let DaemonArgs {
+        async_server,
+        supervise,
+        log_config,
+    } = parse_daemon_args(args);
+
+    // systemd owns the socket and runs the daemon as a foreground service
+    #[cfg(unix)]
+    if pyrust::daemon_activation::is_socket_activated() {
+        run_activated_daemon(async_server, &log_config);
+    }
+
+    // Check if daemon is already running
+    if pyrust::daemon_client::DaemonClient::is_daemon_running() {
+        eprintln!("Daemon is already running");
+        process::exit(1);
+    }
+
+    // The supervisor starts the daemon itself, and again after each crash
+    #[cfg(unix)]
+    let spawned = if supervise {
+        let log_path = log_config.path.clone();
+        let crash_dir =
+            pyrust::daemon_supervisor::crash_dir(pyrust::daemon::daemon_name_from_env().as_deref());
+        pyrust::daemon_spawn::spawn(|| {
+            pyrust::daemon_supervisor::supervise(
+                move || init_logged_daemon(async_server, &log_config),
+                log_path,
+                crash_dir,
+            )
+        })
+    } else {
+        pyrust::daemon_spawn::spawn(|| init_logged_daemon(async_server, &log_config))
+    };
+    // Without fork, the daemon is this executable started again, and there
+    // is nothing to supervise it with
+    #[cfg(windows)]
+    let _ = supervise;
+    #[cfg(windows)]
+    let spawned = env::current_exe()
+        .map_err(pyrust::daemon::DaemonError::from)
+        .and_then(|exe| {
+            let mut child_args = vec![pyrust::daemon_spawn::DAEMON_CHILD_ARG.to_string()];
+            child_args.extend_from_slice(args);
+            pyrust::daemon_spawn::spawn_process(&exe, &child_args)
+        });
+    match spawned {
+        Ok(pid) => {
+            println!("Daemon started with PID {}", pid);
+            process::exit(0);
+        }
+        Err(e) => {
+            eprintln!("{}", e);
             process::exit(1);
         }
-        libc::dup2(fd, 0); // stdin
-        libc::dup2(fd, 1); // stdout
-        libc::dup2(fd, 2); // stderr
-        if fd > 2 {
-            libc::close(fd);
+    }
+}
+
+/// Serve in the foreground on the socket systemd passed in
+#[cfg(unix)]
+fn run_activated_daemon(async_server: bool, log_config: &pyrust::daemon_log::LogConfig) -> ! {
+    match init_logged_daemon(async_server, log_config) {
+        Ok(daemon) => pyrust::daemon_spawn::serve(daemon),
+        Err(e) => {
+            eprintln!("Failed to initialize daemon: {}", e);
+            process::exit(1);
         }
     }
+}
+
+/// Serve as the detached daemon process started by `--daemon` on Windows
+#[cfg(windows)]
+fn run_daemon_child(args: &[String]) -> ! {
+    let DaemonArgs {
+        async_server,
+        log_config,
+        ..
+    } = parse_daemon_args(args);
+    pyrust::daemon_spawn::run_child(|| init_logged_daemon(async_server, &log_config))
+}
 
-    // Signal parent that daemon is ready
-    unsafe {
-        let ready_byte = b"R";
-        libc::write(pipe_write_fd, ready_byte.as_ptr() as *const libc::c_void, 1);
-        libc::close(pipe_write_fd);
+/// Options of `--daemon`
+struct DaemonArgs {
+    /// Run the async server
+    async_server: bool,
+    /// Leave a supervisor that restarts the daemon when it crashes
+    supervise: bool,
+    /// How to log
+    log_config: pyrust::daemon_log::LogConfig,
+}
+
+/// Parse the options of `--daemon`
+fn parse_daemon_args(args: &[String]) -> DaemonArgs {
+    let usage = "Usage: pyrust --daemon [--name <id>] [--async] [--supervise] \
+                 [--log-level <level>] [--log-file <path>]";
+    let mut async_server = false;
+    let mut supervise = false;
+    let mut log_config = pyrust::daemon_log::LogConfig::for_instance(
+        pyrust::daemon::daemon_name_from_env().as_deref(),
+    );
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--async" {
+            async_server = true;
+        } else if arg == "--supervise" && cfg!(unix) {
+            supervise = true;
+        } else if arg == "--log-level" {
+            match args.next().map(|level| level.parse()) {
+                Some(Ok(level)) => log_config.level = level,
+                _ => {
+                    eprintln!("{}", usage);
+                    process::exit(1);
+                }
+            }
+        } else if arg == "--log-file" {
+            match args.next() {
+                Some(path) => log_config.path = path.into(),
+                None => {
+                    eprintln!("{}", usage);
+                    process::exit(1);
+                }
+            }
+        } else {
+            eprintln!("{}", usage);
+            process::exit(1);
+        }
+    }
+    DaemonArgs {
+        async_server,
+        supervise,
+        log_config,
     }
+}
 
-    // Start the daemon server event loop
-    if let Err(_e) = daemon.run() {
-        // stderr is now redirected to /dev/null, so errors are lost
-        // This is expected for a daemon process
-        process::exit(1);
+/// Bind the daemon and open its log, in the process that will serve
+fn init_logged_daemon(
+    async_server: bool,
+    log_config: &pyrust::daemon_log::LogConfig,
+) -> Result<pyrust::daemon_spawn::DaemonRun, String> {
+    let daemon = init_daemon(async_server)?;
+    pyrust::daemon_log::init(log_config).map_err(|e| {
+        format!(
+            "cannot open daemon log {}: {}",
+            log_config.path.display(),
+            e
+        )
+    })?;
+    log::info!(
+        "daemon started pid={} async={}",
+        process::id(),
+        async_server
+    );
+    Ok(daemon)
+}
+
+/// Bind the chosen daemon server's socket path, returning how to run it
+///
+/// The async server queues at most `PYRUST_DAEMON_MAX_QUEUED` requests if
+/// set, and also listens on `PYRUST_DAEMON_TCP` if set, over TLS
+/// when built with the `tls` feature and given `PYRUST_DAEMON_TLS_CERT` and
+/// `PYRUST_DAEMON_TLS_KEY`.
+fn init_daemon(async_server: bool) -> Result<pyrust::daemon_spawn::DaemonRun, String> {
+    if async_server {
+        #[cfg(feature = "async-daemon")]
+        {
+            let mut daemon =
+                pyrust::daemon_async::AsyncDaemonServer::new().map_err(|e| e.to_string())?;
+            if let Ok(addr) = env::var("PYRUST_DAEMON_TCP") {
+                let addr = addr
+                    .parse()
+                    .map_err(|e| format!("invalid PYRUST_DAEMON_TCP {}: {}", addr, e))?;
+                daemon = daemon.with_tcp(addr);
+            }
+            let max_queued_env = pyrust::daemon_async::MAX_QUEUED_ENV;
+            if let Ok(max) = env::var(max_queued_env) {
+                let max = max
+                    .parse()
+                    .map_err(|e| format!("invalid {} {}: {}", max_queued_env, max, e))?;
+                daemon = daemon.with_max_queued(max);
+            }
+            #[cfg(feature = "tls")]
+            if let (Ok(cert), Ok(key)) = (
+                env::var("PYRUST_DAEMON_TLS_CERT"),
+                env::var("PYRUST_DAEMON_TLS_KEY"),
+            ) {
+                daemon = daemon.with_tls(cert, key).map_err(|e| e.to_string())?;
+            }
+            return Ok(Box::new(move || daemon.run()));
+        }
+        #[cfg(not(feature = "async-daemon"))]
+        return Err("pyrust was built without the async-daemon feature".to_string());
     }
+    let daemon = pyrust::daemon::DaemonServer::new().map_err(|e| e.to_string())?;
+    Ok(Box::new(move || daemon.run()))
 }
 
 /// Stop the running daemon
@@ -247,19 +824,61 @@ fn stop_daemon() {
 }
 
 /// Show daemon status
-fn show_daemon_status() {
-    let status = pyrust::daemon_client::DaemonClient::daemon_status();
-    println!("{}", status);
+///
+/// A running daemon's stats, cache summary and hottest programs follow.
+/// With `--json`, prints `{"running": ..., "stats": ...}` instead, `stats`
+/// being null for a daemon too old to report them.
+fn show_daemon_status(args: &[String]) {
+    let json = match args {
+        [] => false,
+        [flag] if flag == "--json" => true,
+        _ => {
+            eprintln!("Usage: pyrust --daemon-status [--json]");
+            process::exit(1);
+        }
+    };
+    let running = pyrust::daemon_client::DaemonClient::is_daemon_running();
+    // A daemon too old to answer still counts as running
+    let stats = running
+        .then(|| pyrust::daemon_client::DaemonClient::stats().ok())
+        .flatten();
 
-    // Exit with 0 if running, 1 if not running
-    if pyrust::daemon_client::DaemonClient::is_daemon_running() {
-        process::exit(0);
+    if json {
+        match (running, stats) {
+            (false, _) => println!("{{\"running\":false}}"),
+            (true, Some(stats)) => println!("{{\"running\":true,\"stats\":{}}}", stats.to_json()),
+            (true, None) => println!("{{\"running\":true,\"stats\":null}}"),
+        }
     } else {
-        process::exit(1);
+        println!("{}", pyrust::daemon_client::DaemonClient::daemon_status());
+        if let Some(stats) = &stats {
+            print!("{}", stats.to_text());
+        }
+        if running {
+            if let Ok(summary) = pyrust::daemon_client::DaemonClient::cache_summary() {
+                // The stats already gave the cache's hit counts
+                let skip = usize::from(stats.is_some());
+                for line in summary.lines().skip(skip) {
+                    println!("{}", line);
+                }
+            }
+        }
     }
+
+    // Exit with 0 if running, 1 if not running
+    process::exit(if running { 0 } else { 1 });
 }
 
-/// Clear all caches (both global and thread-local)
+/// Exit with 0 if the daemon answers a ping, 1 otherwise, printing nothing
+///
+/// Meant for service managers and container health checks.
+fn check_daemon_health() {
+    let healthy = pyrust::daemon_client::DaemonClient::ping().is_ok();
+    process::exit(if healthy { 0 } else { 1 });
+}
+
+/// Clear all caches (global, thread-local, and on disk), and the running
+/// daemon's if there is one
 fn clear_cache() {
     // Clear global cache
     pyrust::clear_global_cache();
@@ -267,6 +886,597 @@ fn clear_cache() {
     // Clear thread-local cache for current thread
     pyrust::clear_thread_local_cache();
 
+    // Clear on-disk entries, if a disk cache is configured
+    if let Err(e) = pyrust::clear_disk_cache() {
+        eprintln!("Failed to clear disk cache: {}", e);
+        process::exit(1);
+    }
+
+    // The daemon's cache is what later runs would hit
+    if pyrust::daemon_client::DaemonClient::is_daemon_running() {
+        match pyrust::daemon_client::DaemonClient::clear_cache() {
+            Ok(removed) => println!("Cleared {} programs from the daemon's cache", removed),
+            Err(e) => {
+                eprintln!("Failed to clear the daemon's cache: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+
     println!("Cache cleared successfully");
     process::exit(0);
 }
+
+/// Print the running daemon's cache and request metrics
+///
+/// Usage: `pyrust --stats [--format=text|prometheus]`. Prometheus format is
+/// the daemon's metrics text as a scraper would get it; text, the default,
+/// is the same samples without the `# HELP` and `# TYPE` lines.
+fn show_stats(args: &[String]) {
+    let usage = "Usage: pyrust --stats [--format=text|prometheus]";
+    let prometheus = match args {
+        [] => false,
+        [format] => match format.as_str() {
+            "--format=text" => false,
+            "--format=prometheus" => true,
+            _ => {
+                eprintln!("{}", usage);
+                process::exit(1);
+            }
+        },
+        _ => {
+            eprintln!("{}", usage);
+            process::exit(1);
+        }
+    };
+
+    let metrics = match pyrust::daemon_client::DaemonClient::metrics() {
+        Ok(metrics) => metrics,
+        Err(e) => {
+            eprintln!("Cannot read daemon metrics: {}", e);
+            process::exit(1);
+        }
+    };
+    if prometheus {
+        print!("{}", metrics);
+    } else {
+        for line in metrics.lines().filter(|line| !line.starts_with('#')) {
+            println!("{}", line);
+        }
+    }
+}
+
+/// Print the programs in the running daemon's cache
+///
+/// Usage: `pyrust --cache-list`. One line per program, largest first, with
+/// its key hash, bytes charged, hits, age and the start of its source.
+fn show_cache_list() {
+    match pyrust::daemon_client::DaemonClient::cache_list() {
+        Ok(table) => print!("{}", table),
+        Err(e) => {
+            eprintln!("Cannot list the daemon's cache: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+/// Print every counter of the running daemon's cache
+///
+/// Usage: `pyrust --cache-stats`. One `name value` line per counter.
+fn show_cache_stats() {
+    match pyrust::daemon_client::DaemonClient::cache_stats() {
+        Ok(stats) => print!("{}", stats),
+        Err(e) => {
+            eprintln!("Cannot read the daemon's cache stats: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+/// Pre-compile every `.py` file under a directory into the disk cache
+///
+/// Usage: `pyrust --warm-cache <dir>`. Subdirectories are included. Only the
+/// disk tier outlives this command, so it fails unless one is configured
+/// with `PYRUST_CACHE_DIR` or `PYRUST_DISK_CACHE=1`. Exits with 1 if any
+/// script failed to compile; the others are still cached.
+fn warm_cache(args: &[String]) {
+    let [dir] = args else {
+        eprintln!("Usage: pyrust --warm-cache <dir>");
+        process::exit(1);
+    };
+    let Some(cache_dir) = pyrust::disk_cache_dir() else {
+        eprintln!("No disk cache configured: set PYRUST_CACHE_DIR or PYRUST_DISK_CACHE=1");
+        process::exit(1);
+    };
+
+    let mut scripts = Vec::new();
+    if let Err(e) = collect_scripts(std::path::Path::new(dir), &mut scripts) {
+        eprintln!("Error reading {}: {}", dir, e);
+        process::exit(1);
+    }
+    scripts.sort();
+
+    let report = pyrust::warm_global_cache(&scripts);
+    for (path, message) in &report.failed {
+        eprintln!("{}: {}", path.display(), message);
+    }
+    println!(
+        "Cached {} of {} scripts in {}",
+        report.compiled,
+        scripts.len(),
+        cache_dir.display()
+    );
+    if !report.failed.is_empty() {
+        process::exit(1);
+    }
+}
+
+/// Add the `.py` files under `dir`, recursively, to `scripts`
+fn collect_scripts(
+    dir: &std::path::Path,
+    scripts: &mut Vec<std::path::PathBuf>,
+) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_scripts(&path, scripts)?;
+        } else if path.extension().is_some_and(|ext| ext == "py") {
+            scripts.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Compile a script to a bytecode file without running it
+///
+/// Usage: `pyrust --compile script.py [-o script.pybc] [-O0|-O1|-O2]
+/// [--warnings] [--deny-division-by-zero]`. The output defaults to the script
+/// path with a `.pybc` extension, and the optimization level to `-O0`.
+/// `--deny-division-by-zero` turns a constant zero divisor from a warning
+/// into a compile error, and `-W error` every warning.
+fn compile_to_file(args: &[String]) {
+    let usage = "Usage: pyrust --compile <file.py> [-o <file.pybc>] [-O0 | -O1 | -O2] [--warnings] [--deny-division-by-zero]";
+    let mut source_path = None;
+    let mut output_path = None;
+    let mut options = pyrust::compiler::CompileOptions {
+        warnings: warning_severity(),
+        ..Default::default()
+    };
+    let mut show_warnings = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "-o" {
+            match args.next() {
+                Some(path) if output_path.is_none() => {
+                    output_path = Some(std::path::PathBuf::from(path))
+                }
+                _ => {
+                    eprintln!("{}", usage);
+                    process::exit(1);
+                }
+            }
+        } else if arg == "--warnings" {
+            show_warnings = true;
+        } else if arg == "--deny-division-by-zero" {
+            options.division_by_zero = pyrust::compiler::Severity::Error;
+        } else if let Some(level) = arg.strip_prefix("-O") {
+            match level
+                .parse()
+                .ok()
+                .and_then(pyrust::compiler::OptLevel::from_number)
+            {
+                Some(level) => options.opt_level = level,
+                None => {
+                    eprintln!("Unknown optimization level: {}", arg);
+                    process::exit(1);
+                }
+            }
+        } else if source_path.is_none() && !arg.starts_with('-') {
+            source_path = Some(arg);
+        } else {
+            source_path = None;
+            break;
+        }
+    }
+    let Some(source_path) = source_path else {
+        eprintln!("{}", usage);
+        process::exit(1);
+    };
+    let output_path =
+        output_path.unwrap_or_else(|| std::path::Path::new(source_path).with_extension("pybc"));
+
+    let code = match fs::read_to_string(source_path) {
+        Ok(contents) => contents,
+        Err(e) => {
+            eprintln!("Error reading {}: {}", source_path, e);
+            process::exit(1);
+        }
+    };
+
+    let bytecode = match pyrust::compile_python_with_warnings(&code, &options) {
+        Ok((bytecode, warnings)) => {
+            if show_warnings {
+                for warning in &warnings {
+                    print_warning(warning);
+                }
+            }
+            bytecode
+        }
+        Err(e) => {
+            print_error(&e, Some(&code));
+            process::exit(1);
+        }
+    };
+
+    if let Err(e) = fs::write(&output_path, bytecode.to_bytes()) {
+        eprintln!("Error writing {}: {}", output_path.display(), e);
+        process::exit(1);
+    }
+}
+
+/// Describe an error code
+///
+/// Usage: `pyrust explain [<code>]`. Prints the longer explanation of a code
+/// such as `E1003`, as shown in error messages, or without one a list of
+/// every code and what it means.
+fn explain_code(args: &[String]) {
+    use pyrust::error_code::ErrorCode;
+
+    match args {
+        [] => {
+            for code in ErrorCode::ALL {
+                println!("{}  {}", code, code.summary());
+            }
+        }
+        [code] => match ErrorCode::from_code(code) {
+            Some(code) => println!("{}: {}\n\n{}", code, code.summary(), code.explanation()),
+            None => {
+                eprintln!(
+                    "Unknown error code: {} (run `pyrust explain` to list them)",
+                    code
+                );
+                process::exit(1);
+            }
+        },
+        _ => {
+            eprintln!("Usage: pyrust explain [<code>]");
+            process::exit(1);
+        }
+    }
+}
+
+/// Run a script in one of the daemon's sessions
+///
+/// Usage: `pyrust --session <id> <file.py>` or `pyrust --session <id> -c
+/// <code>`. Globals and functions persist between runs with the same id,
+/// so successive invocations behave like lines typed into a REPL. Needs a
+/// running daemon.
+fn run_in_session(args: &[String]) {
+    let usage = "Usage: pyrust --session <id> (<file.py> | -c <code>)";
+    let (id, code) = match args {
+        [id, flag, code] if flag == "-c" => (id, code.clone()),
+        [id, path] => match fs::read_to_string(path) {
+            Ok(contents) => (id, contents),
+            Err(e) => {
+                eprintln!("Error reading {}: {}", path, e);
+                process::exit(1);
+            }
+        },
+        _ => {
+            eprintln!("{}", usage);
+            process::exit(1);
+        }
+    };
+
+    match pyrust::daemon_client::DaemonClient::execute_in_session(id, &code) {
+        Ok(output) => {
+            if !output.is_empty() {
+                print!("{}", output);
+            }
+        }
+        Err(e @ pyrust::daemon_client::DaemonClientError::ConnectionFailed(_)) => {
+            eprintln!("Sessions need a running daemon: {}", e);
+            process::exit(1);
+        }
+        Err(e) => exit_with_error(&e, Some(&code)),
+    }
+}
+
+/// Print the parsed AST of a script
+///
+/// Usage: `pyrust --ast <file.py>` or `pyrust --ast -c <code>`. Nothing is
+/// compiled or run.
+fn show_ast(args: &[String]) {
+    let code = source_arg(args, "Usage: pyrust --ast (<file.py> | -c <code>)");
+    match pyrust::parse_to_string(&code) {
+        Ok(tree) => print!("{}", tree),
+        Err(e) => {
+            print_error(&e, Some(&code));
+            process::exit(1);
+        }
+    }
+}
+
+/// Print the disassembled bytecode of a script
+///
+/// Usage: `pyrust --dis <file.py>` or `pyrust --dis -c <code>`. The script is
+/// compiled as a normal run would compile it, but not run.
+fn show_disassembly(args: &[String]) {
+    let code = source_arg(args, "Usage: pyrust --dis (<file.py> | -c <code>)");
+    match pyrust::compile_python(&code) {
+        Ok(bytecode) => print!("{}", pyrust::disassembler::disassemble(&bytecode)),
+        Err(e) => {
+            print_error(&e, Some(&code));
+            process::exit(1);
+        }
+    }
+}
+
+/// Check scripts for errors and warnings without running them
+///
+/// Usage: `pyrust --check [--deny-warnings] [--deny-division-by-zero]
+/// (<file.py>... | -c <code>)`. Each script is lexed, parsed and compiled,
+/// and every diagnostic is printed to stdout prefixed with the script's path
+/// (`<string>` for `-c`), or with `--error-format=json` as one JSON object
+/// per line with the path in its `file` field. Every lexical error in a
+/// script is reported, not just the first (see
+/// [`pyrust::lexer::lex_recovering`]). Warnings are reported even when
+/// compilation fails, as long as the script parses. Exits with 1 if any
+/// script has an error, or with `--deny-warnings` or `-W error` a warning,
+/// and with 0 otherwise, so it can back a pre-commit hook or an editor's
+/// check on save.
+fn check_scripts(args: &[String]) -> ! {
+    let usage = "Usage: pyrust --check [--deny-warnings] [--deny-division-by-zero] (<file.py>... | -c <code>)";
+    let mut options = pyrust::compiler::CompileOptions::default();
+    let mut deny_warnings = warnings_are_errors();
+    let mut sources = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--deny-warnings" {
+            deny_warnings = true;
+        } else if arg == "--deny-division-by-zero" {
+            options.division_by_zero = pyrust::compiler::Severity::Error;
+        } else if arg == "-c" {
+            match args.next() {
+                Some(code) => sources.push(("<string>".to_string(), Ok(code.clone()))),
+                None => {
+                    eprintln!("{}", usage);
+                    process::exit(1);
+                }
+            }
+        } else if !arg.starts_with('-') {
+            sources.push((arg.clone(), fs::read_to_string(arg)));
+        } else {
+            eprintln!("{}", usage);
+            process::exit(1);
+        }
+    }
+    if sources.is_empty() {
+        eprintln!("{}", usage);
+        process::exit(1);
+    }
+
+    use pyrust::diagnostic::{Diagnostic, Stage};
+
+    #[derive(serde::Serialize)]
+    struct FileDiagnostic<'a> {
+        file: &'a str,
+        #[serde(flatten)]
+        diagnostic: &'a Diagnostic,
+    }
+    let report = |file: &str, diagnostic: Diagnostic| {
+        if JSON_ERRORS.get() == Some(&true) {
+            let line = FileDiagnostic {
+                file,
+                diagnostic: &diagnostic,
+            };
+            println!(
+                "{}",
+                serde_json::to_string(&line).expect("diagnostics always serialize")
+            );
+        } else {
+            println!("{}: {}", file, diagnostic);
+        }
+    };
+
+    let mut failed = false;
+    for (name, code) in sources {
+        let code = match code {
+            Ok(code) => code,
+            Err(e) => {
+                let message = format!("Error reading file: {}", e);
+                if JSON_ERRORS.get() == Some(&true) {
+                    report(&name, Diagnostic::error(Stage::Load, message));
+                } else {
+                    println!("{}: {}", name, message);
+                }
+                failed = true;
+                continue;
+            }
+        };
+        // Every lexical error is reported, not just the first
+        let (tokens, lex_errors, mut lex_warnings) = pyrust::lexer::lex_recovering(&code);
+        if !lex_errors.is_empty() {
+            for e in lex_errors {
+                report(
+                    &name,
+                    Diagnostic::from(&pyrust::error::PyRustError::from(e)),
+                );
+            }
+            for warning in lex_warnings {
+                report(&name, Diagnostic::from(&warning));
+            }
+            failed = true;
+            continue;
+        }
+        let parsed = pyrust::parser::parse_with_warnings(tokens)
+            .map_err(pyrust::error::PyRustError::from)
+            .map(|(program, positions, parse_warnings)| {
+                lex_warnings.extend(parse_warnings);
+                (program, positions, lex_warnings)
+            });
+        let (program, positions, early_warnings) = match parsed {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                report(&name, Diagnostic::from(&e));
+                failed = true;
+                continue;
+            }
+        };
+        let mut error_at = None;
+        if let Err(e) = pyrust::compiler::compile_with_options(&program, &positions, &options) {
+            error_at = Some(e.location);
+            report(
+                &name,
+                Diagnostic::from(&pyrust::error::PyRustError::from(e)),
+            );
+            failed = true;
+        }
+        for warning in early_warnings
+            .into_iter()
+            .chain(pyrust::warnings::check(&program, &positions))
+        {
+            // A denied division by zero is already reported as the error
+            if warning.kind == pyrust::warnings::WarningKind::DivisionByZero
+                && error_at == Some(warning.pos)
+            {
+                continue;
+            }
+            report(&name, Diagnostic::from(&warning));
+            failed |= deny_warnings;
+        }
+    }
+    process::exit(if failed { 1 } else { 0 });
+}
+
+/// Time repeated runs of a script
+///
+/// Usage: `pyrust --bench <runs> [--warm] (<file.py> | -c <code>)`. Runs
+/// in-process, compiling the script from source every run, or with `--warm`
+/// once into the cache beforehand. Prints the minimum, median and 95th
+/// percentile times of compiling, executing and both; the script's own
+/// output is discarded.
+fn bench_script(args: &[String]) {
+    let usage = "Usage: pyrust --bench <runs> [--warm] (<file.py> | -c <code>)";
+    let (runs, rest) = match args.split_first() {
+        Some((runs, rest)) => match runs.parse::<usize>() {
+            Ok(runs) if runs > 0 => (runs, rest),
+            _ => {
+                eprintln!("Invalid number of runs {:?}: use a positive integer", runs);
+                process::exit(1);
+            }
+        },
+        None => {
+            eprintln!("{}", usage);
+            process::exit(1);
+        }
+    };
+    let (warm, rest) = match rest.split_first() {
+        Some((flag, rest)) if flag == "--warm" => (true, rest),
+        _ => (false, rest),
+    };
+    let code = source_arg(rest, usage);
+    match pyrust::bench::run(&code, runs, warm) {
+        Ok(report) => print!("{}", report.format_table()),
+        Err(e) => {
+            print_error(&e, Some(&code));
+            process::exit(1);
+        }
+    }
+}
+
+/// Source named by `<file.py>` or `-c <code>`, exiting with `usage` if
+/// `args` is neither
+fn source_arg(args: &[String], usage: &str) -> String {
+    match args {
+        [flag, code] if flag == "-c" => code.clone(),
+        [path] if !path.starts_with('-') => match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) => {
+                eprintln!("Error reading {}: {}", path, e);
+                process::exit(1);
+            }
+        },
+        _ => {
+            eprintln!("{}", usage);
+            process::exit(1);
+        }
+    }
+}
+
+/// Run a script again every time it changes
+///
+/// Usage: `pyrust --watch script.py`. Runs in-process, so that the program
+/// stays in this process's caches between runs; when the source changes,
+/// the old version is evicted from them. Each run is framed on stderr by a
+/// separator and its timing, and an error does not stop the watch. Runs
+/// until interrupted.
+fn watch_script(args: &[String]) -> ! {
+    let [path] = args else {
+        eprintln!("Usage: pyrust --watch <file.py>");
+        process::exit(1);
+    };
+
+    let mut watcher = pyrust::watch::FileWatcher::new(path);
+    eprintln!("Watching {} for changes (Ctrl-C to stop)", path);
+    let mut previous: Option<String> = None;
+    let mut runs = 0;
+    loop {
+        match fs::read_to_string(path) {
+            Ok(code) => {
+                if let Some(old) = previous.take().filter(|old| *old != code) {
+                    pyrust::evict_cached(&old);
+                }
+                runs += 1;
+                eprintln!("--- run {}: {} ---", runs, path);
+                let started = std::time::Instant::now();
+                let stream_stdout = |line: &str| {
+                    let _ = std::io::stdout().write_all(line.as_bytes());
+                };
+                let result = pyrust::execute_python_streaming(&code, stream_stdout);
+                let elapsed = started.elapsed().as_secs_f64() * 1000.0;
+                let status = match result {
+                    Ok(output) => {
+                        if !output.is_empty() {
+                            println!("{}", output);
+                        }
+                        "ok".to_string()
+                    }
+                    Err(e) => match e.exit_code() {
+                        Some(code) => format!("exited with code {}", code),
+                        None => {
+                            print_error(&e, Some(&code));
+                            "failed".to_string()
+                        }
+                    },
+                };
+                // Keep the output above the timing
+                let _ = std::io::stdout().flush();
+                eprintln!("--- {} in {:.3} ms ---", status, elapsed);
+                previous = Some(code);
+            }
+            Err(e) => eprintln!("Error reading {}: {}", path, e),
+        }
+        watcher.wait_for_change();
+    }
+}
+
+/// Run a bytecode file written by `--compile`
+///
+/// Usage: `pyrust run script.pybc`. Always executes in-process; the daemon
+/// only accepts source code.
+fn run_bytecode_file(args: &[String]) {
+    let [path] = args else {
+        eprintln!("Usage: pyrust run <file.pybc>");
+        process::exit(1);
+    };
+
+    match pyrust::execute_bytecode_file(path) {
+        Ok(output) => {
+            if !output.is_empty() {
+                print!("{}", output);
+            }
+        }
+        Err(e) => exit_with_error(&e, None),
+    }
+}
@@ -0,0 +1,523 @@
+//! Cache and daemon metrics in the Prometheus text format
+//!
+//! [`render`] writes the compilation cache's [`CacheStats`] and, for a
+//! daemon, its [`RequestMetrics`] as Prometheus exposition text (version
+//! 0.0.4), so a scraper can collect them as-is. The daemon serves this text
+//! over its socket (see [`crate::daemon_protocol::METRICS_MARKER`]) and
+//! `pyrust --stats --format=prometheus` prints it.
+//!
+//! Cache counters restart from zero when the cache is cleared; Prometheus
+//! treats that as a counter reset.
+//!
+//! For people rather than scrapers, [`DaemonStats`] condenses the same
+//! numbers, plus uptime, latency percentiles and live sessions, into what
+//! `pyrust --daemon-status` shows.
+//!
+//! # Example
+//!
+//! ```
+//! use pyrust::cache::CompilationCache;
+//! use pyrust::metrics::{render, RequestMetrics};
+//! use std::time::Duration;
+//!
+//! let requests = RequestMetrics::new();
+//! requests.record(Duration::from_micros(300), true);
+//!
+//! let text = render(&CompilationCache::new(10).stats(), Some(&requests));
+//! assert!(text.contains("pyrust_cache_misses_total 0\n"));
+//! assert!(text.contains("pyrust_requests_total 1\n"));
+//! assert!(text.contains("pyrust_request_duration_seconds_bucket{le=\"0.0005\"} 1\n"));
+//! ```
+
+use crate::cache::CacheStats;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Upper bounds, in seconds, of the request latency histogram buckets
+pub const LATENCY_BUCKETS: [f64; 14] = [
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0,
+];
+
+/// Counts and latencies of the requests a daemon has served
+///
+/// Updated with atomics, so it can be shared between connections without a
+/// lock.
+#[derive(Debug)]
+pub struct RequestMetrics {
+    requests: AtomicU64,
+    errors: AtomicU64,
+    /// Requests per bucket, not cumulative; the last slot is `+Inf`
+    buckets: [AtomicU64; LATENCY_BUCKETS.len() + 1],
+    total_nanos: AtomicU64,
+    created: Instant,
+}
+
+impl Default for RequestMetrics {
+    fn default() -> Self {
+        Self {
+            requests: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            buckets: Default::default(),
+            total_nanos: AtomicU64::new(0),
+            created: Instant::now(),
+        }
+    }
+}
+
+impl RequestMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Time since the metrics were created, which is when the daemon was
+    pub fn uptime(&self) -> Duration {
+        self.created.elapsed()
+    }
+
+    /// Estimated latency that a fraction `q` of the requests stayed within
+    ///
+    /// Interpolated within the histogram bucket the quantile falls in, like
+    /// Prometheus's `histogram_quantile`; requests slower than the last
+    /// bucket count as taking its bound. None before the first request.
+    pub fn quantile(&self, q: f64) -> Option<Duration> {
+        let counts: Vec<u64> = self
+            .buckets
+            .iter()
+            .map(|bucket| bucket.load(Ordering::Relaxed))
+            .collect();
+        let total: u64 = counts.iter().sum();
+        let rank = q.clamp(0.0, 1.0) * total as f64;
+        let mut below = 0;
+        for (index, &count) in counts.iter().enumerate() {
+            if count > 0 && (below + count) as f64 >= rank {
+                let Some(&upper) = LATENCY_BUCKETS.get(index) else {
+                    return Some(Duration::from_secs_f64(LATENCY_BUCKETS[index - 1]));
+                };
+                let lower = index.checked_sub(1).map_or(0.0, |i| LATENCY_BUCKETS[i]);
+                let fraction = (rank - below as f64) / count as f64;
+                return Some(Duration::from_secs_f64(lower + (upper - lower) * fraction));
+            }
+            below += count;
+        }
+        None
+    }
+
+    /// Count a request that took `elapsed` and did or did not succeed
+    pub fn record(&self, elapsed: Duration, success: bool) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        if !success {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        let seconds = elapsed.as_secs_f64();
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|&bound| seconds <= bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        let nanos = u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+        self.total_nanos.fetch_add(nanos, Ordering::Relaxed);
+    }
+
+    /// Requests recorded so far
+    pub fn requests(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    /// Requests recorded as failed
+    pub fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    fn write(&self, out: &mut String) {
+        write_family(
+            out,
+            "pyrust_requests_total",
+            "counter",
+            "Requests served by the daemon",
+        );
+        write_sample(out, "pyrust_requests_total", "", self.requests());
+        write_family(
+            out,
+            "pyrust_request_errors_total",
+            "counter",
+            "Requests that ended in an error",
+        );
+        write_sample(out, "pyrust_request_errors_total", "", self.errors());
+
+        let name = "pyrust_request_duration_seconds";
+        write_family(out, name, "histogram", "Time to compile and run a request");
+        let mut cumulative = 0;
+        for (index, bucket) in self.buckets.iter().enumerate() {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let bound = match LATENCY_BUCKETS.get(index) {
+                Some(bound) => bound.to_string(),
+                None => "+Inf".to_string(),
+            };
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, cumulative);
+        }
+        let seconds = self.total_nanos.load(Ordering::Relaxed) as f64 / 1e9;
+        let _ = writeln!(out, "{}_sum {}", name, seconds);
+        let _ = writeln!(out, "{}_count {}", name, cumulative);
+    }
+}
+
+/// Prometheus text for the cache statistics and, if given, request metrics
+pub fn render(cache: &CacheStats, requests: Option<&RequestMetrics>) -> String {
+    let mut out = String::new();
+    let counters = [
+        (
+            "pyrust_cache_hits_total",
+            "Cache lookups that found bytecode",
+            cache.hits,
+        ),
+        (
+            "pyrust_cache_misses_total",
+            "Cache lookups that did not",
+            cache.misses,
+        ),
+        (
+            "pyrust_cache_disk_hits_total",
+            "Hits served by the disk tier",
+            cache.disk_hits,
+        ),
+        (
+            "pyrust_cache_shared_hits_total",
+            "Hits served by the shared cache file",
+            cache.shared_hits,
+        ),
+        (
+            "pyrust_cache_expired_total",
+            "Lookups that found an expired entry",
+            cache.expired,
+        ),
+    ];
+    for (name, help, value) in counters {
+        write_family(&mut out, name, "counter", help);
+        write_sample(&mut out, name, "", value as u64);
+    }
+    write_family(
+        &mut out,
+        "pyrust_cache_compile_seconds_saved_total",
+        "counter",
+        "Compile time avoided by hits on the memory tier",
+    );
+    let _ = writeln!(
+        out,
+        "pyrust_cache_compile_seconds_saved_total {}",
+        cache.compile_time_saved.as_secs_f64()
+    );
+    write_family(
+        &mut out,
+        "pyrust_cache_evictions_total",
+        "counter",
+        "Entries evicted to respect the capacity or byte budget",
+    );
+    write_sample(
+        &mut out,
+        "pyrust_cache_evictions_total",
+        &format!("{{policy=\"{}\"}}", cache.policy),
+        cache.evictions as u64,
+    );
+
+    let mut gauges = vec![
+        ("pyrust_cache_entries", "Entries in memory", cache.size),
+        (
+            "pyrust_cache_capacity",
+            "Maximum entries in memory",
+            cache.capacity,
+        ),
+        (
+            "pyrust_cache_bytes",
+            "Bytes charged for the entries in memory",
+            cache.bytes,
+        ),
+    ];
+    if let Some(max_bytes) = cache.max_bytes {
+        gauges.push((
+            "pyrust_cache_max_bytes",
+            "Byte budget of the memory tier",
+            max_bytes,
+        ));
+    }
+    for (name, help, value) in gauges {
+        write_family(&mut out, name, "gauge", help);
+        write_sample(&mut out, name, "", value as u64);
+    }
+
+    if let Some(requests) = requests {
+        requests.write(&mut out);
+    }
+    out
+}
+
+/// Snapshot of a running daemon, as served for the stats frame
+///
+/// See [`crate::daemon_protocol::STATS_MARKER`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DaemonStats {
+    /// Time since the daemon started
+    pub uptime: Duration,
+    /// Requests served
+    pub requests: u64,
+    /// Requests that ended in an error
+    pub errors: u64,
+    /// Median request latency; None before the first request
+    pub latency_p50: Option<Duration>,
+    /// 95th percentile request latency; None before the first request
+    pub latency_p95: Option<Duration>,
+    /// Entries in the cache's memory tier
+    pub cache_entries: usize,
+    /// Cache lookups that found bytecode
+    pub cache_hits: usize,
+    /// Cache lookups that did not
+    pub cache_misses: usize,
+    /// Sessions the daemon keeps
+    pub sessions: usize,
+}
+
+impl DaemonStats {
+    /// Gather the stats of a daemon with `requests`, `cache` and `sessions`
+    /// live sessions
+    pub fn collect(requests: &RequestMetrics, cache: &CacheStats, sessions: usize) -> Self {
+        Self {
+            uptime: requests.uptime(),
+            requests: requests.requests(),
+            errors: requests.errors(),
+            latency_p50: requests.quantile(0.5),
+            latency_p95: requests.quantile(0.95),
+            cache_entries: cache.size,
+            cache_hits: cache.hits,
+            cache_misses: cache.misses,
+            sessions,
+        }
+    }
+
+    /// Share of cache lookups that hit, from 0 to 1
+    pub fn cache_hit_rate(&self) -> f64 {
+        let lookups = self.cache_hits + self.cache_misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            self.cache_hits as f64 / lookups as f64
+        }
+    }
+
+    /// Encode as `name value` lines, durations in seconds
+    ///
+    /// Percentiles are left out until there has been a request.
+    pub fn encode(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "uptime_seconds {}", self.uptime.as_secs_f64());
+        let _ = writeln!(out, "requests {}", self.requests);
+        let _ = writeln!(out, "errors {}", self.errors);
+        if let Some(p50) = self.latency_p50 {
+            let _ = writeln!(out, "latency_p50_seconds {}", p50.as_secs_f64());
+        }
+        if let Some(p95) = self.latency_p95 {
+            let _ = writeln!(out, "latency_p95_seconds {}", p95.as_secs_f64());
+        }
+        let _ = writeln!(out, "cache_entries {}", self.cache_entries);
+        let _ = writeln!(out, "cache_hits {}", self.cache_hits);
+        let _ = writeln!(out, "cache_misses {}", self.cache_misses);
+        let _ = writeln!(out, "sessions {}", self.sessions);
+        out
+    }
+
+    /// Decode the output of [`DaemonStats::encode`]
+    ///
+    /// Unknown or malformed lines are skipped, so a newer daemon may add
+    /// fields; missing ones keep their defaults.
+    pub fn parse(text: &str) -> Self {
+        let mut stats = Self::default();
+        for (name, value) in text.lines().filter_map(|line| line.split_once(' ')) {
+            let seconds = || {
+                value
+                    .parse()
+                    .ok()
+                    .and_then(|s| Duration::try_from_secs_f64(s).ok())
+            };
+            match name {
+                "uptime_seconds" => stats.uptime = seconds().unwrap_or_default(),
+                "requests" => stats.requests = value.parse().unwrap_or_default(),
+                "errors" => stats.errors = value.parse().unwrap_or_default(),
+                "latency_p50_seconds" => stats.latency_p50 = seconds(),
+                "latency_p95_seconds" => stats.latency_p95 = seconds(),
+                "cache_entries" => stats.cache_entries = value.parse().unwrap_or_default(),
+                "cache_hits" => stats.cache_hits = value.parse().unwrap_or_default(),
+                "cache_misses" => stats.cache_misses = value.parse().unwrap_or_default(),
+                "sessions" => stats.sessions = value.parse().unwrap_or_default(),
+                _ => {}
+            }
+        }
+        stats
+    }
+
+    /// Readable summary, one line per topic
+    pub fn to_text(&self) -> String {
+        let secs = self.uptime.as_secs();
+        let latency = match (self.latency_p50, self.latency_p95) {
+            (Some(p50), Some(p95)) => format!(
+                "p50 {:.3}ms, p95 {:.3}ms",
+                p50.as_secs_f64() * 1000.0,
+                p95.as_secs_f64() * 1000.0
+            ),
+            _ => "no requests yet".to_string(),
+        };
+        format!(
+            "Uptime: {}h {:02}m {:02}s\n\
+             Requests: {} ({} errors)\n\
+             Latency: {}\n\
+             Cache: {} entries, {} hits, {} misses ({:.1}% hit rate)\n\
+             Sessions: {}\n",
+            secs / 3600,
+            secs / 60 % 60,
+            secs % 60,
+            self.requests,
+            self.errors,
+            latency,
+            self.cache_entries,
+            self.cache_hits,
+            self.cache_misses,
+            self.cache_hit_rate() * 100.0,
+            self.sessions
+        )
+    }
+
+    /// JSON object with the fields of [`DaemonStats::encode`], plus
+    /// `cache_hit_rate`; percentiles are null before the first request
+    pub fn to_json(&self) -> String {
+        let seconds = |d: Option<Duration>| match d {
+            Some(d) => d.as_secs_f64().to_string(),
+            None => "null".to_string(),
+        };
+        format!(
+            "{{\"uptime_seconds\":{},\"requests\":{},\"errors\":{},\
+             \"latency_p50_seconds\":{},\"latency_p95_seconds\":{},\
+             \"cache_entries\":{},\"cache_hits\":{},\"cache_misses\":{},\
+             \"cache_hit_rate\":{},\"sessions\":{}}}",
+            self.uptime.as_secs_f64(),
+            self.requests,
+            self.errors,
+            seconds(self.latency_p50),
+            seconds(self.latency_p95),
+            self.cache_entries,
+            self.cache_hits,
+            self.cache_misses,
+            self.cache_hit_rate(),
+            self.sessions
+        )
+    }
+}
+
+fn write_family(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn write_sample(out: &mut String, name: &str, labels: &str, value: u64) {
+    let _ = writeln!(out, "{}{} {}", name, labels, value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::CompilationCache;
+
+    #[test]
+    fn test_histogram_buckets_are_cumulative() {
+        let requests = RequestMetrics::new();
+        requests.record(Duration::from_micros(50), true);
+        requests.record(Duration::from_millis(3), false);
+        requests.record(Duration::from_secs(10), true);
+
+        let mut out = String::new();
+        requests.write(&mut out);
+        let sample = |line: &str| {
+            out.lines()
+                .find(|l| l.starts_with(line))
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(
+            sample("pyrust_request_errors_total"),
+            "pyrust_request_errors_total 1"
+        );
+        assert_eq!(
+            sample("pyrust_request_duration_seconds_bucket{le=\"0.0001\"}"),
+            "pyrust_request_duration_seconds_bucket{le=\"0.0001\"} 1"
+        );
+        assert_eq!(
+            sample("pyrust_request_duration_seconds_bucket{le=\"0.005\"}"),
+            "pyrust_request_duration_seconds_bucket{le=\"0.005\"} 2"
+        );
+        assert_eq!(
+            sample("pyrust_request_duration_seconds_bucket{le=\"+Inf\"}"),
+            "pyrust_request_duration_seconds_bucket{le=\"+Inf\"} 3"
+        );
+        assert_eq!(
+            sample("pyrust_request_duration_seconds_count"),
+            "pyrust_request_duration_seconds_count 3"
+        );
+    }
+
+    #[test]
+    fn test_quantiles_interpolate_within_buckets() {
+        let requests = RequestMetrics::new();
+        assert_eq!(requests.quantile(0.5), None);
+        // Two requests in (0.001, 0.0025], two in (0.0025, 0.005]
+        for millis in [2, 2, 3, 4] {
+            requests.record(Duration::from_millis(millis), true);
+        }
+        let p50 = requests.quantile(0.5).unwrap().as_secs_f64();
+        assert!((p50 - 0.0025).abs() < 1e-9);
+        let p95 = requests.quantile(0.95).unwrap().as_secs_f64();
+        assert!((p95 - 0.00475).abs() < 1e-9);
+
+        // Past the last bucket, the last bound is the best estimate
+        requests.record(Duration::from_secs(60), true);
+        assert_eq!(requests.quantile(1.0), Some(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn test_daemon_stats_round_trip() {
+        let requests = RequestMetrics::new();
+        requests.record(Duration::from_micros(200), true);
+        requests.record(Duration::from_millis(2), false);
+        let mut cache = CompilationCache::new(10);
+        cache.get("print(1)");
+        let stats = DaemonStats::collect(&requests, &cache.stats(), 3);
+        assert_eq!((stats.requests, stats.errors, stats.sessions), (2, 1, 3));
+        assert_eq!((stats.cache_hits, stats.cache_misses), (0, 1));
+
+        let decoded = DaemonStats::parse(&stats.encode());
+        assert_eq!(decoded, stats);
+        assert!(stats.to_text().contains("Requests: 2 (1 errors)\n"));
+        let json = stats.to_json();
+        assert!(json.starts_with("{\"uptime_seconds\":"));
+        assert!(json.contains("\"errors\":1,") && json.ends_with("\"sessions\":3}"));
+
+        // Before any request there are no percentiles
+        let idle = DaemonStats::parse(&DaemonStats::default().encode());
+        assert_eq!(idle.latency_p50, None);
+        assert!(idle.to_json().contains("\"latency_p50_seconds\":null"));
+    }
+
+    #[test]
+    fn test_render_cache_stats() {
+        let mut cache = CompilationCache::new(10).with_max_bytes(1 << 20);
+        cache.get("print(1)");
+        let text = render(&cache.stats(), None);
+
+        assert!(text
+            .contains("# TYPE pyrust_cache_misses_total counter\npyrust_cache_misses_total 1\n"));
+        assert!(text.contains("pyrust_cache_evictions_total{policy=\"lru\"} 0\n"));
+        assert!(text.contains("pyrust_cache_max_bytes 1048576\n"));
+        assert!(!text.contains("pyrust_requests_total"));
+        // Every sample belongs to a declared family
+        for line in text.lines().filter(|line| !line.starts_with('#')) {
+            let name = line.split(['{', ' ']).next().unwrap();
+            assert!(text.contains(&format!("# TYPE {} ", name)), "{}", line);
+        }
+    }
+}
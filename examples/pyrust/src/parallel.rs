@@ -0,0 +1,192 @@
+//! Parallel batch execution in library mode
+//!
+//! A [`ParallelExecutor`] owns a fixed set of worker threads. A batch passed to
+//! [`ParallelExecutor::execute`] becomes one job per snippet, picked up by
+//! whichever worker is free, and the results are reassembled in input order.
+//!
+//! Workers live as long as the executor and run each snippet through
+//! [`crate::execute_python`], so every worker keeps its own thread-local
+//! compilation cache and VM warm across batches without any shared locking
+//! beyond the job queue.
+//!
+//! # Example
+//!
+//! ```
+//! use pyrust::ParallelExecutor;
+//!
+//! let executor = ParallelExecutor::new(4);
+//! let results = executor.execute(&["1 + 1", "print(2)", "x"]);
+//! assert_eq!(results[0].as_deref().unwrap(), "2");
+//! assert_eq!(results[1].as_deref().unwrap(), "2\n");
+//! assert!(results[2].is_err());
+//! ```
+
+use crate::error::PyRustError;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// Result of one snippet, tagged with its position in the batch
+type Outcome = (usize, Result<String, PyRustError>);
+
+/// One snippet to run and where to report its result
+struct Job {
+    index: usize,
+    code: String,
+    results: Sender<Outcome>,
+}
+
+/// Pool of worker threads that execute snippets in parallel
+///
+/// Dropping the executor lets queued jobs finish, then joins the workers.
+pub struct ParallelExecutor {
+    /// Job queue shared by all workers; taken on drop to stop them
+    jobs: Option<Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl ParallelExecutor {
+    /// Start an executor with `workers` threads (at least one)
+    pub fn new(workers: usize) -> Self {
+        let (jobs, queue) = mpsc::channel();
+        let queue = Arc::new(Mutex::new(queue));
+        let workers = (0..workers.max(1))
+            .map(|i| {
+                let queue = Arc::clone(&queue);
+                thread::Builder::new()
+                    .name(format!("pyrust-worker-{}", i))
+                    .spawn(move || work(&queue))
+                    .expect("failed to spawn worker thread")
+            })
+            .collect();
+
+        Self {
+            jobs: Some(jobs),
+            workers,
+        }
+    }
+
+    /// Number of worker threads
+    pub fn worker_count(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Execute every snippet, returning the results in input order
+    ///
+    /// Each snippet runs in a fresh global environment, as if passed to
+    /// [`crate::execute_python`] on its own; a failing snippet does not affect
+    /// the others. Blocks until the whole batch has finished.
+    pub fn execute(&self, codes: &[&str]) -> Vec<Result<String, PyRustError>> {
+        let jobs = self.jobs.as_ref().expect("executor is running");
+        let (results, outcomes) = mpsc::channel();
+        for (index, code) in codes.iter().enumerate() {
+            jobs.send(Job {
+                index,
+                code: code.to_string(),
+                results: results.clone(),
+            })
+            .expect("worker threads have exited");
+        }
+        // Only the jobs hold senders now, so the loop below ends with the batch
+        drop(results);
+
+        let mut ordered: Vec<Option<Result<String, PyRustError>>> = Vec::new();
+        ordered.resize_with(codes.len(), || None);
+        for (index, outcome) in outcomes {
+            ordered[index] = Some(outcome);
+        }
+        ordered
+            .into_iter()
+            .map(|outcome| outcome.expect("worker thread panicked"))
+            .collect()
+    }
+}
+
+impl Default for ParallelExecutor {
+    /// One worker per available CPU
+    fn default() -> Self {
+        Self::new(thread::available_parallelism().map_or(1, |n| n.get()))
+    }
+}
+
+impl Drop for ParallelExecutor {
+    fn drop(&mut self) {
+        // Closing the queue makes each worker exit once it is drained
+        self.jobs.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Worker loop: run jobs until the queue is closed
+fn work(queue: &Mutex<Receiver<Job>>) {
+    loop {
+        let job = queue
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .recv();
+        let Ok(job) = job else {
+            return;
+        };
+        let result = crate::execute_python(&job.code);
+        // The caller may have stopped listening if it panicked mid-batch
+        let _ = job.results.send((job.index, result));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_results_keep_input_order() {
+        let executor = ParallelExecutor::new(4);
+        let codes: Vec<String> = (0..200)
+            .map(|i| format!("x = {}\nprint(x)\nx * 2", i))
+            .collect();
+        let codes: Vec<&str> = codes.iter().map(String::as_str).collect();
+
+        let results = executor.execute(&codes);
+        assert_eq!(results.len(), 200);
+        for (i, result) in results.iter().enumerate() {
+            assert_eq!(result.as_deref().unwrap(), format!("{}\n{}", i, i * 2));
+        }
+    }
+
+    #[test]
+    fn test_errors_stay_with_their_snippet() {
+        let executor = ParallelExecutor::new(2);
+        let results = executor.execute(&["1 / 0", "print(", "3", "y"]);
+        assert!(matches!(results[0], Err(PyRustError::RuntimeError(_))));
+        assert!(matches!(results[1], Err(PyRustError::ParseError(_))));
+        assert_eq!(results[2].as_deref().unwrap(), "3");
+        assert!(matches!(results[3], Err(PyRustError::RuntimeError(_))));
+    }
+
+    #[test]
+    fn test_executor_is_reusable_across_batches() {
+        let executor = ParallelExecutor::new(0);
+        assert_eq!(executor.worker_count(), 1);
+
+        assert!(executor.execute(&[]).is_empty());
+        assert_eq!(executor.execute(&["x = 5\nx"])[0].as_deref().unwrap(), "5");
+        // Globals from the previous batch are gone
+        assert!(executor.execute(&["x"])[0].is_err());
+    }
+
+    #[test]
+    fn test_shared_between_caller_threads() {
+        let executor = Arc::new(ParallelExecutor::new(2));
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let executor = Arc::clone(&executor);
+                thread::spawn(move || executor.execute(&[&format!("{} + 1", i)]))
+            })
+            .collect();
+        for (i, handle) in handles.into_iter().enumerate() {
+            let results = handle.join().unwrap();
+            assert_eq!(results[0].as_deref().unwrap(), (i + 1).to_string());
+        }
+    }
+}
@@ -4,9 +4,14 @@
 //! Uses Pratt parsing to handle operator precedence correctly.
 //! Target performance: ~10μs for 10-token input.
 
-use crate::ast::{BinaryOperator, Expression, Program, Statement, UnaryOperator};
+use crate::ast::{
+    BinaryOperator, ExprSpan, Expression, Program, SourcePos, Statement, StatementPos,
+    UnaryOperator,
+};
 use crate::error::ParseError;
+use crate::error_code::ErrorCode;
 use crate::lexer::{Token, TokenKind};
+use crate::warnings::{CompileWarning, WarningKind};
 
 /// Parser state for tracking position in token stream
 pub struct Parser<'src> {
@@ -14,12 +19,24 @@ pub struct Parser<'src> {
     tokens: Vec<Token<'src>>,
     /// Current position in token stream
     pos: usize,
+    /// Start positions of the statements parsed so far in the current block
+    positions: Vec<StatementPos>,
+    /// Spans of parsed expressions not yet claimed by their parent node
+    expr_spans: Vec<ExprSpan>,
+    /// Warnings found so far
+    warnings: Vec<CompileWarning>,
 }
 
 impl<'src> Parser<'src> {
     /// Creates a new parser for the given token stream
     fn new(tokens: Vec<Token<'src>>) -> Self {
-        Self { tokens, pos: 0 }
+        Self {
+            tokens,
+            pos: 0,
+            positions: Vec::new(),
+            expr_spans: Vec::new(),
+            warnings: Vec::new(),
+        }
     }
 
     /// Returns the current token without consuming it
@@ -41,6 +58,31 @@ impl<'src> Parser<'src> {
         token
     }
 
+    /// Position just past the most recently consumed token
+    fn previous_end(&self) -> SourcePos {
+        let token = &self.tokens[self.pos.saturating_sub(1)];
+        SourcePos {
+            line: token.line,
+            column: token.end_column(),
+        }
+    }
+
+    /// Record the span of an expression that ends at the last consumed token
+    ///
+    /// The node's `child_count` operand spans, pushed while parsing them, are
+    /// moved under it.
+    fn push_span(&mut self, start: SourcePos, child_count: usize) {
+        let children = self
+            .expr_spans
+            .split_off(self.expr_spans.len() - child_count);
+        let end = self.previous_end();
+        self.expr_spans.push(ExprSpan {
+            start,
+            end,
+            children,
+        });
+    }
+
     /// Checks if current token matches the expected kind
     fn check(&self, kind: TokenKind) -> bool {
         self.peek().kind == kind
@@ -53,9 +95,11 @@ impl<'src> Parser<'src> {
             Ok(self.advance())
         } else {
             Err(ParseError {
+                code: ErrorCode::ExpectedToken,
                 message: format!("Expected {} in {}", token_kind_name(kind), context),
                 line: token.line,
                 column: token.column,
+                end_column: token.end_column(),
                 found_token: token.text.to_string(),
                 expected_tokens: vec![token_kind_name(kind)],
             })
@@ -77,15 +121,55 @@ impl<'src> Parser<'src> {
         self.skip_newlines();
 
         while !self.check(TokenKind::Eof) {
-            statements.push(self.parse_statement()?);
+            statements.push(self.parse_positioned_statement()?);
             self.skip_newlines();
         }
 
         Ok(Program { statements })
     }
 
+    /// Parses a statement and records where it starts
+    ///
+    /// Statements parsed while this one is in progress (a function body) are
+    /// collected as its nested positions.
+    fn parse_positioned_statement(&mut self) -> Result<Statement, ParseError> {
+        let token = self.peek();
+        let pos = SourcePos {
+            line: token.line,
+            column: token.column,
+        };
+
+        let outer = std::mem::take(&mut self.positions);
+        let pending_spans = self.expr_spans.len();
+        let statement = self.parse_statement()?;
+        let body = std::mem::replace(&mut self.positions, outer);
+        // A statement leaves at most its one top-level expression behind
+        let value = if self.expr_spans.len() > pending_spans {
+            self.expr_spans.pop()
+        } else {
+            None
+        };
+        self.positions.push(StatementPos { pos, body, value });
+
+        Ok(statement)
+    }
+
     /// Parses a single statement
     fn parse_statement(&mut self) -> Result<Statement, ParseError> {
+        // Blocks are only opened by compound statements such as def
+        if self.check(TokenKind::Indent) {
+            let token = self.peek();
+            return Err(ParseError {
+                code: ErrorCode::UnexpectedIndent,
+                message: "Unexpected indent".to_string(),
+                line: token.line,
+                column: token.column,
+                end_column: token.end_column(),
+                found_token: token.text.to_string(),
+                expected_tokens: vec!["statement".to_string()],
+            });
+        }
+
         // Check for function definition
         if self.check(TokenKind::Def) {
             return self.parse_function_def();
@@ -116,15 +200,34 @@ impl<'src> Parser<'src> {
     }
 
     /// Parses an assignment statement: name = expression
+    ///
+    /// Chained targets (`a = b = expression`) produce a MultiAssignment.
     fn parse_assignment_statement(&mut self) -> Result<Statement, ParseError> {
-        let name_token = self.expect(TokenKind::Identifier, "assignment statement")?;
-        let name = name_token.text.to_string();
+        let mut targets = Vec::new();
+
+        loop {
+            let name_token = self.expect(TokenKind::Identifier, "assignment statement")?;
+            targets.push(name_token.text.to_string());
+
+            self.expect(TokenKind::Equals, "assignment statement")?;
 
-        self.expect(TokenKind::Equals, "assignment statement")?;
+            // Another `identifier =` pair means another assignment target
+            let is_another_target = self.check(TokenKind::Identifier)
+                && self.pos + 1 < self.tokens.len()
+                && self.tokens[self.pos + 1].kind == TokenKind::Equals;
+            if !is_another_target {
+                break;
+            }
+        }
 
         let value = self.parse_expression()?;
 
-        Ok(Statement::Assignment { name, value })
+        if targets.len() == 1 {
+            let name = targets.pop().unwrap();
+            Ok(Statement::Assignment { name, value })
+        } else {
+            Ok(Statement::MultiAssignment { targets, value })
+        }
     }
 
     /// Parses a print statement: print(expression)
@@ -147,8 +250,7 @@ impl<'src> Parser<'src> {
 
     /// Parses a function definition: def name(params): body
     fn parse_function_def(&mut self) -> Result<Statement, ParseError> {
-        let def_token = self.expect(TokenKind::Def, "function definition")?;
-        let def_indent = def_token.column;
+        self.expect(TokenKind::Def, "function definition")?;
 
         let name_token = self.expect(TokenKind::Identifier, "function definition")?;
         let name = name_token.text.to_string();
@@ -179,42 +281,65 @@ impl<'src> Parser<'src> {
         // Expect at least one newline after colon
         self.expect(TokenKind::Newline, "function definition")?;
 
-        // Parse function body (indented statements)
+        // Parse function body (the block between Indent and its matching Dedent)
         let mut body = Vec::new();
 
         // Skip any additional newlines
         self.skip_newlines();
 
-        // Parse statements in the body until we hit EOF or a dedent
-        // A dedent is when we encounter a non-empty line at the same or less indentation as the def
-        while !self.check(TokenKind::Eof) {
-            let token = self.peek();
+        // No indented block means an empty body
+        if self.check(TokenKind::Indent) {
+            self.advance();
 
-            // Skip empty lines
-            if self.check(TokenKind::Newline) {
-                self.advance();
-                continue;
-            }
+            let mut returned = false;
+            while !self.check(TokenKind::Eof) {
+                if self.check(TokenKind::Dedent) {
+                    self.advance();
+                    break;
+                }
 
-            // Check if this line is dedented (at or before the def indent level)
-            // If so, we're done with the function body
-            if token.column <= def_indent {
-                break;
+                // Only the first statement after a return is reported
+                if returned {
+                    returned = false;
+                    self.warn_unreachable();
+                }
+                let statement = self.parse_positioned_statement()?;
+                if matches!(statement, Statement::Return { .. }) {
+                    returned = true;
+                }
+                body.push(statement);
+                self.skip_newlines();
             }
-
-            body.push(self.parse_statement()?);
-            self.skip_newlines();
         }
 
         Ok(Statement::FunctionDef { name, params, body })
     }
 
+    /// Warns that the statement starting at the current token never runs
+    fn warn_unreachable(&mut self) {
+        let token = self.peek();
+        let (line, column) = (token.line, token.column);
+        let end_column = token.end_column().max(column + 1);
+        self.warnings.push(CompileWarning {
+            kind: WarningKind::UnreachableCode,
+            message: "code after `return` is never run".to_string(),
+            pos: Some(SourcePos { line, column }),
+            end: Some(SourcePos {
+                line,
+                column: end_column,
+            }),
+        });
+    }
+
     /// Parses a return statement: return [expression]
     fn parse_return_statement(&mut self) -> Result<Statement, ParseError> {
         self.expect(TokenKind::Return, "return statement")?;
 
         // Check if there's a value to return
-        let value = if self.check(TokenKind::Newline) || self.check(TokenKind::Eof) {
+        let value = if self.check(TokenKind::Newline)
+            || self.check(TokenKind::Dedent)
+            || self.check(TokenKind::Eof)
+        {
             None
         } else {
             Some(self.parse_expression()?)
@@ -223,8 +348,9 @@ impl<'src> Parser<'src> {
         Ok(Statement::Return { value })
     }
 
-    /// Parses a function call: name(args)
-    fn parse_call(&mut self, name: String) -> Result<Expression, ParseError> {
+    /// Parses a function call: name(args), where the name starting at `start`
+    /// has already been consumed
+    fn parse_call(&mut self, name: String, start: SourcePos) -> Result<Expression, ParseError> {
         self.expect(TokenKind::LeftParen, "function call")?;
 
         let mut args = Vec::new();
@@ -244,6 +370,7 @@ impl<'src> Parser<'src> {
         }
 
         self.expect(TokenKind::RightParen, "function call")?;
+        self.push_span(start, args.len());
 
         Ok(Expression::Call { name, args })
     }
@@ -289,6 +416,8 @@ impl<'src> Parser<'src> {
             // Parse right-hand side with higher precedence
             // Use precedence + 1 for left-associativity
             let right = self.parse_expression_with_precedence(precedence + 1)?;
+            let start = self.expr_spans[self.expr_spans.len() - 2].start;
+            self.push_span(start, 2);
 
             // Build binary operation
             left = Expression::BinaryOp {
@@ -304,6 +433,10 @@ impl<'src> Parser<'src> {
     /// Parses a primary expression (integer, variable, or parenthesized expression)
     fn parse_primary(&mut self) -> Result<Expression, ParseError> {
         let token = *self.peek();
+        let start = SourcePos {
+            line: token.line,
+            column: token.column,
+        };
 
         match token.kind {
             TokenKind::Plus | TokenKind::Minus => {
@@ -317,6 +450,7 @@ impl<'src> Parser<'src> {
 
                 // Parse the operand
                 let operand = self.parse_primary()?;
+                self.push_span(start, 1);
 
                 Ok(Expression::UnaryOp {
                     op,
@@ -332,12 +466,15 @@ impl<'src> Parser<'src> {
 
                 // Parse the integer value
                 let value = text.parse::<i64>().map_err(|_| ParseError {
+                    code: ErrorCode::IntegerTooLarge,
                     message: format!("Integer literal '{}' is too large", text),
                     line,
                     column,
+                    end_column: column + text.len(),
                     found_token: text.to_string(),
                     expected_tokens: vec!["valid integer".to_string()],
                 })?;
+                self.push_span(start, 0);
 
                 Ok(Expression::Integer(value))
             }
@@ -348,8 +485,9 @@ impl<'src> Parser<'src> {
 
                 // Check if this is a function call (identifier followed by left paren)
                 if self.check(TokenKind::LeftParen) {
-                    return self.parse_call(name);
+                    return self.parse_call(name, start);
                 }
+                self.push_span(start, 0);
 
                 Ok(Expression::Variable(name))
             }
@@ -362,9 +500,11 @@ impl<'src> Parser<'src> {
             }
 
             _ => Err(ParseError {
+                code: ErrorCode::ExpectedExpression,
                 message: "Expected expression".to_string(),
                 line: token.line,
                 column: token.column,
+                end_column: token.end_column(),
                 found_token: token.text.to_string(),
                 expected_tokens: vec![
                     "integer".to_string(),
@@ -396,7 +536,10 @@ fn token_kind_name(kind: TokenKind) -> String {
         TokenKind::Def => "'def'".to_string(),
         TokenKind::Return => "'return'".to_string(),
         TokenKind::Newline => "newline".to_string(),
+        TokenKind::Indent => "indent".to_string(),
+        TokenKind::Dedent => "dedent".to_string(),
         TokenKind::Eof => "end of file".to_string(),
+        TokenKind::Error => "invalid token".to_string(),
     }
 }
 
@@ -426,6 +569,51 @@ pub fn parse(tokens: Vec<Token>) -> Result<Program, ParseError> {
     parser.parse_program()
 }
 
+/// Parse a token stream, also returning where each statement and expression is
+///
+/// The positions feed [`crate::compiler::compile_with_positions`] so runtime
+/// errors can be reported against the exact expression that failed.
+///
+/// # Examples
+/// ```
+/// use pyrust::lexer::lex;
+/// use pyrust::parser::parse_with_positions;
+///
+/// let (program, positions) = parse_with_positions(lex("x = 1\n\ny = x").unwrap()).unwrap();
+/// assert_eq!(program.statements.len(), 2);
+/// assert_eq!(positions[1].pos.line, 3);
+/// ```
+pub fn parse_with_positions(
+    tokens: Vec<Token>,
+) -> Result<(Program, Vec<StatementPos>), ParseError> {
+    let mut parser = Parser::new(tokens);
+    let program = parser.parse_program()?;
+    Ok((program, parser.positions))
+}
+
+/// Parse a token stream like [`parse_with_positions`], also returning the
+/// warnings found on the way
+///
+/// The parser warns about statements that follow a `return` in a function
+/// body and so never run; see [`crate::warnings`].
+///
+/// # Examples
+/// ```
+/// use pyrust::lexer::lex;
+/// use pyrust::parser::parse_with_warnings;
+///
+/// let tokens = lex("def f():\n    return 1\n    print(2)").unwrap();
+/// let (_, _, warnings) = parse_with_warnings(tokens).unwrap();
+/// assert_eq!(warnings[0].pos.unwrap().line, 3);
+/// ```
+pub fn parse_with_warnings(
+    tokens: Vec<Token>,
+) -> Result<(Program, Vec<StatementPos>, Vec<CompileWarning>), ParseError> {
+    let mut parser = Parser::new(tokens);
+    let program = parser.parse_program()?;
+    Ok((program, parser.positions, parser.warnings))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1461,17 +1649,41 @@ mod tests {
 
     #[test]
     fn test_parse_function_with_mixed_indent_in_body() {
-        // Function body where statements have varying indentation (all > def indent)
+        // A deeper indent inside a body without a block opener is rejected
         let source = "def foo():\n    x = 1\n        y = 2\n    return x";
         let tokens = lex(source).unwrap();
-        let program = parse(tokens).unwrap();
+        let err = parse(tokens).unwrap_err();
+        assert_eq!(err.message, "Unexpected indent");
+        assert_eq!(err.line, 3);
+    }
 
+    #[test]
+    fn test_parse_warns_about_code_after_return() {
+        let source = "def f(x):\n    return x\n    y = x\n    print(y)\nprint(f(1))";
+        let (program, _, warnings) = parse_with_warnings(lex(source).unwrap()).unwrap();
         match &program.statements[0] {
-            Statement::FunctionDef { body, .. } => {
-                assert_eq!(body.len(), 3);
-            }
+            Statement::FunctionDef { body, .. } => assert_eq!(body.len(), 3),
             _ => panic!("Expected function definition"),
         }
+        // Only the first unreachable statement is reported
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(
+            warnings[0].to_string(),
+            "Warning[W0007] at 3:5: code after `return` is never run"
+        );
+        assert_eq!(warnings[0].end.unwrap().column, 6);
+
+        let (_, _, warnings) =
+            parse_with_warnings(lex("def f(x):\n    return x\nprint(f(1))").unwrap()).unwrap();
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_parse_unexpected_indent_at_top_level() {
+        let tokens = lex("x = 1\n    y = 2").unwrap();
+        let err = parse(tokens).unwrap_err();
+        assert_eq!(err.message, "Unexpected indent");
+        assert_eq!(err.line, 2);
     }
 
     #[test]
@@ -1634,4 +1846,85 @@ mod tests {
             _ => panic!("Expected function definition"),
         }
     }
+
+    // ========== Multi-target Assignment Tests ==========
+
+    #[test]
+    fn test_parse_multi_target_assignment() {
+        let tokens = lex("a = b = c = 1 + 2").unwrap();
+        let program = parse(tokens).unwrap();
+
+        assert_eq!(program.statements.len(), 1);
+        match &program.statements[0] {
+            Statement::MultiAssignment { targets, value } => {
+                assert_eq!(targets, &vec!["a", "b", "c"]);
+                assert!(matches!(value, Expression::BinaryOp { .. }));
+            }
+            _ => panic!("Expected multi-target assignment"),
+        }
+    }
+
+    #[test]
+    fn test_parse_single_target_stays_assignment() {
+        // A variable on the right-hand side is not mistaken for another target
+        let tokens = lex("a = b + 1").unwrap();
+        let program = parse(tokens).unwrap();
+
+        match &program.statements[0] {
+            Statement::Assignment { name, value } => {
+                assert_eq!(name, "a");
+                assert!(matches!(value, Expression::BinaryOp { .. }));
+            }
+            _ => panic!("Expected assignment statement"),
+        }
+    }
+
+    #[test]
+    fn test_parse_multi_target_assignment_missing_value() {
+        let tokens = lex("a = b =").unwrap();
+        assert!(parse(tokens).is_err());
+    }
+
+    #[test]
+    fn test_parse_with_positions_tracks_function_bodies() {
+        let source = "x = 1\ndef f(a):\n    y = a\n    return y\nf(x)";
+        let (program, positions) = parse_with_positions(lex(source).unwrap()).unwrap();
+
+        assert_eq!(positions.len(), program.statements.len());
+        let at = |p: &StatementPos| (p.pos.line, p.pos.column);
+        assert_eq!(at(&positions[0]), (1, 1));
+        assert_eq!(at(&positions[1]), (2, 1));
+        assert_eq!(at(&positions[2]), (5, 1));
+
+        let body: Vec<_> = positions[1].body.iter().map(at).collect();
+        assert_eq!(body, vec![(3, 5), (4, 5)]);
+        assert!(positions[0].body.is_empty());
+        // A function definition has no value of its own
+        assert!(positions[1].value.is_none());
+    }
+
+    #[test]
+    fn test_parse_with_positions_records_expression_spans() {
+        let source = "y = -(a + 10) * f(b, 2)
+return";
+        let (_, positions) = parse_with_positions(lex(source).unwrap()).unwrap();
+        let cols = |span: &ExprSpan| (span.start.column, span.end.column);
+
+        let value = positions[0].value.as_ref().unwrap();
+        assert_eq!(cols(value), (5, 24));
+        let [negation, call] = &value.children[..] else {
+            panic!("expected two operands, got {:?}", value.children);
+        };
+        // The parentheses belong to the operand, not to the sum inside them
+        assert_eq!(cols(negation), (5, 14));
+        assert_eq!(cols(&negation.children[0]), (7, 13));
+        let sum: Vec<_> = negation.children[0].children.iter().map(cols).collect();
+        assert_eq!(sum, vec![(7, 8), (11, 13)]);
+
+        assert_eq!(cols(call), (17, 24));
+        let args: Vec<_> = call.children.iter().map(cols).collect();
+        assert_eq!(args, vec![(19, 20), (22, 23)]);
+
+        assert!(positions[1].value.is_none());
+    }
 }
@@ -1,4 +1,4 @@
-use crate::{compiler, error::PyRustError, lexer, parser, vm};
+use crate::{compiler, error::PyRustError, lexer, parser};
 use std::time::Instant;
 
 /// Pipeline profiling data with per-stage nanosecond timings
@@ -71,6 +71,39 @@ impl PipelineProfile {
         )
     }
 
+    /// Encode for the daemon protocol, one `name value` line per field
+    pub fn encode(&self) -> String {
+        format!(
+            "lex_ns {}\nparse_ns {}\ncompile_ns {}\nvm_execute_ns {}\nformat_ns {}\ntotal_ns {}\n",
+            self.lex_ns,
+            self.parse_ns,
+            self.compile_ns,
+            self.vm_execute_ns,
+            self.format_ns,
+            self.total_ns
+        )
+    }
+
+    /// Decode the output of [`PipelineProfile::encode`]
+    ///
+    /// Unknown or malformed lines are skipped; missing fields are zero.
+    pub fn parse(text: &str) -> Self {
+        let mut profile = Self::default();
+        for (name, value) in text.lines().filter_map(|line| line.split_once(' ')) {
+            let field = match name {
+                "lex_ns" => &mut profile.lex_ns,
+                "parse_ns" => &mut profile.parse_ns,
+                "compile_ns" => &mut profile.compile_ns,
+                "vm_execute_ns" => &mut profile.vm_execute_ns,
+                "format_ns" => &mut profile.format_ns,
+                "total_ns" => &mut profile.total_ns,
+                _ => continue,
+            };
+            *field = value.parse().unwrap_or_default();
+        }
+        profile
+    }
+
     /// Validate that sum of stages ≈ total (within 5%)
     /// Used to detect measurement errors or hidden overhead
     pub fn validate_timing_sum(&self) -> bool {
@@ -96,20 +129,22 @@ pub fn execute_python_profiled(code: &str) -> Result<(String, PipelineProfile),
     last_time = now;
 
     // Stage 2: Parse
-    let ast = parser::parse(tokens)?;
+    let (ast, positions) = parser::parse_with_positions(tokens)?;
     let now = Instant::now();
     profile.parse_ns = now.duration_since(last_time).as_nanos() as u64;
     last_time = now;
 
     // Stage 3: Compile
-    let bytecode = compiler::compile(&ast)?;
+    let bytecode = compiler::compile_with_positions(&ast, &positions)?;
     let now = Instant::now();
     profile.compile_ns = now.duration_since(last_time).as_nanos() as u64;
     last_time = now;
 
     // Stage 4: VM Execute
-    let mut vm = vm::VM::new();
-    let result = vm.execute(&bytecode)?;
+    let mut vm = crate::new_vm();
+    let result = vm
+        .execute(&bytecode)
+        .map_err(|e| e.with_location(&bytecode, code))?;
     let now = Instant::now();
     profile.vm_execute_ns = now.duration_since(last_time).as_nanos() as u64;
     last_time = now;
@@ -178,6 +213,18 @@ mod tests {
         assert!(json.contains("\"total_ns\":"));
     }
 
+    #[test]
+    fn test_encode_parse_roundtrip() {
+        let (_, profile) = execute_python_profiled("2+3").unwrap();
+        let parsed = PipelineProfile::parse(&profile.encode());
+        assert_eq!(parsed.total_ns, profile.total_ns);
+        assert_eq!(parsed.format_json(), profile.format_json());
+
+        // Fields a newer daemon might add are skipped
+        let parsed = PipelineProfile::parse("lex_ns 5\ncache_hit true\ntotal_ns 9\n");
+        assert_eq!((parsed.lex_ns, parsed.parse_ns, parsed.total_ns), (5, 0, 9));
+    }
+
     #[test]
     fn test_profiling_with_print_statement() {
         let (output, profile) = execute_python_profiled("print(42)").unwrap();
@@ -0,0 +1,273 @@
+//! Rich terminal reports of diagnostics, through miette
+//!
+//! Available with the `fancy-errors` feature. [`Diagnostic::to_report`] pairs
+//! a [`Diagnostic`] with the source it points into, giving a [`Report`] that
+//! implements [`miette::Diagnostic`]: the span is its primary label, named
+//! after the error code, each [`Diagnostic::related`] span (such as the calls
+//! active when a runtime error was raised) is a further label, and the notes
+//! and the code's explanation (see [`crate::error_code`]) are its help text.
+//! Applications embedding pyrust can hand a report to any miette handler, or
+//! call [`Report::render`] for plain text.
+//!
+//! # Example
+//!
+//! ```
+//! use pyrust::diagnostic::Diagnostic;
+//!
+//! let code = "def f(d):\n    return 10 / d\nprint(f(0))";
+//! let error = pyrust::execute_python(code).unwrap_err();
+//! let report = Diagnostic::from(&error).to_report("script.py", code);
+//!
+//! // With colors and the terminal's width, through miette's own handler
+//! eprintln!("{:?}", miette::Report::new(report.clone()));
+//!
+//! let text = report.render();
+//! assert!(text.contains("[script.py:2:12]"));
+//! assert!(text.contains("call to f"));
+//! ```
+
+use crate::diagnostic::{Diagnostic, Span};
+use crate::error_code::ErrorCode;
+use miette::{GraphicalReportHandler, GraphicalTheme, LabeledSpan, NamedSource, SourceSpan};
+use std::fmt;
+
+/// A [`Diagnostic`] together with its source, for miette to render
+#[derive(Debug, Clone)]
+pub struct Report {
+    diagnostic: Diagnostic,
+    source: NamedSource<String>,
+    /// The diagnostic's span as a byte range of the source, if it has one
+    /// that falls inside it
+    primary: Option<SourceSpan>,
+    /// Each related span that falls inside the source, with its label
+    related: Vec<(String, SourceSpan)>,
+}
+
+impl Diagnostic {
+    /// Pair the diagnostic with `source`, the text its spans point into,
+    /// shown under `name`, such as a file path
+    pub fn to_report(&self, name: &str, source: &str) -> Report {
+        let related = self
+            .related
+            .iter()
+            .filter_map(|related| {
+                Some((related.message.clone(), byte_range(source, related.span)?))
+            })
+            .collect();
+        Report {
+            diagnostic: self.clone(),
+            source: NamedSource::new(name, source.to_string()),
+            primary: self.span.and_then(|span| byte_range(source, span)),
+            related,
+        }
+    }
+}
+
+impl Report {
+    /// The diagnostic being reported
+    pub fn diagnostic(&self) -> &Diagnostic {
+        &self.diagnostic
+    }
+
+    /// Format with miette's graphical handler, without colors
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        GraphicalReportHandler::new_themed(GraphicalTheme::unicode_nocolor())
+            .render_report(&mut out, self)
+            .expect("formatting into a String never fails");
+        out
+    }
+
+    /// The diagnostic's error code, if it is a known one
+    fn error_code(&self) -> Option<ErrorCode> {
+        self.diagnostic
+            .code
+            .as_deref()
+            .and_then(ErrorCode::from_code)
+    }
+}
+
+/// The message alone; the code, severity and spans are shown by miette
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.diagnostic.message)
+    }
+}
+
+impl std::error::Error for Report {}
+
+impl miette::Diagnostic for Report {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        let code = self.diagnostic.code.as_ref()?;
+        Some(Box::new(code))
+    }
+
+    fn severity(&self) -> Option<miette::Severity> {
+        Some(match self.diagnostic.severity {
+            crate::compiler::Severity::Error => miette::Severity::Error,
+            crate::compiler::Severity::Warning => miette::Severity::Warning,
+        })
+    }
+
+    /// The notes, unless they only repeat the related spans as text, then
+    /// the code's explanation
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        let mut help = Vec::new();
+        if self.related.is_empty() {
+            help.extend(self.diagnostic.notes.iter().map(String::as_str));
+        }
+        if let Some(code) = self.error_code() {
+            if !help.is_empty() {
+                help.push("");
+            }
+            help.push(code.explanation());
+        }
+        if help.is_empty() {
+            return None;
+        }
+        Some(Box::new(help.join("\n")))
+    }
+
+    fn source_code(&self) -> Option<&dyn miette::SourceCode> {
+        Some(&self.source)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        if self.primary.is_none() && self.related.is_empty() {
+            return None;
+        }
+        let summary = self.error_code().map(|code| code.summary().to_string());
+        let primary = self
+            .primary
+            .map(|span| LabeledSpan::new_primary_with_span(summary, span));
+        let related = self
+            .related
+            .iter()
+            .map(|(message, span)| LabeledSpan::new_with_span(Some(message.clone()), *span));
+        Some(Box::new(primary.into_iter().chain(related)))
+    }
+}
+
+/// Byte range of `span` in `source`, or `None` if its lines are not there
+///
+/// Columns count characters, as the lexer does; columns past the end of
+/// their line are taken as the line's end.
+fn byte_range(source: &str, span: Span) -> Option<SourceSpan> {
+    let line_start = |line: usize| {
+        let mut start = 0;
+        for _ in 1..line {
+            start += source[start..].find('\n')? + 1;
+        }
+        Some(start)
+    };
+    let offset = |line_start: usize, column: usize| {
+        let line = source[line_start..].split('\n').next().unwrap_or("");
+        let within = line
+            .char_indices()
+            .nth(column.saturating_sub(1))
+            .map_or(line.len(), |(index, _)| index);
+        line_start + within
+    };
+    let start = offset(line_start(span.line)?, span.column);
+    let end = offset(line_start(span.end_line)?, span.end_column).max(start);
+    Some(SourceSpan::new(start.into(), end - start))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_byte_range() {
+        let source = "x = 1\nprint(x / 0)\n";
+        let range = byte_range(source, Span::on_line(2, 7, 12)).unwrap();
+        assert_eq!(
+            &source[range.offset()..range.offset() + range.len()],
+            "x / 0"
+        );
+
+        // Past the end of the line is the end of the line
+        let range = byte_range(source, Span::on_line(1, 5, 40)).unwrap();
+        assert_eq!((range.offset(), range.len()), (4, 1));
+        assert_eq!(byte_range(source, Span::on_line(9, 1, 2)), None);
+
+        // Columns count characters, not bytes
+        let source = "x = é + été";
+        let range = byte_range(source, Span::on_line(1, 9, 12)).unwrap();
+        assert_eq!(&source[range.offset()..range.offset() + range.len()], "été");
+    }
+
+    #[test]
+    fn test_report_on_a_non_ascii_character() {
+        let code = "x = é";
+        let error = crate::execute_python(code).unwrap_err();
+        let report = Diagnostic::from(&error).to_report("<string>", code);
+        let labels: Vec<_> = miette::Diagnostic::labels(&report).unwrap().collect();
+        assert_eq!(labels[0].offset(), 4);
+        assert_eq!(labels[0].len(), "é".len());
+        assert!(report.render().contains("E0001"));
+    }
+
+    #[test]
+    fn test_report_labels_the_calls() {
+        let code = "def f(d):\n    return 10 / d\ndef g(n):\n    return f(n)\nprint(g(0))";
+        let error = crate::execute_python(code).unwrap_err();
+        let report = Diagnostic::from(&error).to_report("nested.py", code);
+        let labels: Vec<_> = miette::Diagnostic::labels(&report).unwrap().collect();
+        let texts: Vec<_> = labels.iter().map(|label| label.label().unwrap()).collect();
+        assert_eq!(texts, ["division by zero", "call to g", "call to f"]);
+        assert!(labels[0].primary());
+        assert_eq!(labels[1].offset(), code.find("g(0)").unwrap());
+
+        let text = report.render();
+        assert!(text.contains("E2001"), "{}", text);
+        assert!(
+            text.contains("ZeroDivisionError: Division by zero"),
+            "{}",
+            text
+        );
+        // The traceback is in the labels, so the help is the explanation
+        assert!(!text.contains("in <module>"), "{}", text);
+        assert!(text.contains("raising a"), "{}", text);
+    }
+
+    #[test]
+    fn test_related_labels_without_a_span() {
+        use crate::diagnostic::{RelatedSpan, Stage};
+
+        let code = "def f():\n    return 1\nprint(f())";
+        let diagnostic = Diagnostic {
+            related: vec![RelatedSpan {
+                message: "call to f".to_string(),
+                span: Span::on_line(3, 7, 10),
+            }],
+            ..Diagnostic::error(Stage::Runtime, "stopped")
+        };
+        let report = diagnostic.to_report("<string>", code);
+        let labels: Vec<_> = miette::Diagnostic::labels(&report).unwrap().collect();
+        assert_eq!(labels.len(), 1);
+        assert!(!labels[0].primary());
+        assert_eq!(labels[0].label(), Some("call to f"));
+    }
+
+    #[test]
+    fn test_report_warning_and_notes() {
+        let error = crate::execute_python("print(1 +)").unwrap_err();
+        let report = Diagnostic::from(&error).to_report("<string>", "print(1 +)");
+        assert_eq!(
+            miette::Diagnostic::severity(&report),
+            Some(miette::Severity::Error)
+        );
+        let help = miette::Diagnostic::help(&report).unwrap().to_string();
+        assert!(help.starts_with("Found: )\nExpected: "), "{}", help);
+
+        let code = "def f():\n    return 1";
+        let (_, warnings) = crate::compile_python_with_warnings(code, &Default::default()).unwrap();
+        let report = Diagnostic::from(&warnings[0]).to_report("unused.py", code);
+        assert_eq!(
+            miette::Diagnostic::severity(&report),
+            Some(miette::Severity::Warning)
+        );
+        assert!(report.render().contains("W0004"));
+    }
+}
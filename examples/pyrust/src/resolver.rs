@@ -0,0 +1,250 @@
+//! Semantic analysis pass run before code generation
+//!
+//! [`resolve`] checks the scoping rules of a program and records every scope
+//! in a [`SymbolTable`]: the global scope, and for each function its
+//! signature, parameter slots, and locals. The compiler lays out and emits
+//! code from this table, so code generation itself never has to reject a
+//! program for scoping reasons.
+//!
+//! The rules checked are:
+//!
+//! - top-level code may only call functions defined above it (function bodies
+//!   may call any function, since all are registered before main code runs)
+//! - function definitions may not be nested
+//!
+//! # Example
+//!
+//! ```
+//! use pyrust::resolver::resolve;
+//! use pyrust::{lexer, parser};
+//!
+//! let source = "total = 0\ndef add(a, b):\n    sum = a + b\n    return sum\nprint(add(1, 2))";
+//! let program = parser::parse(lexer::lex(source).unwrap()).unwrap();
+//! let symbols = resolve(&program).unwrap();
+//!
+//! let add = symbols.function("add").unwrap();
+//! assert_eq!(add.arity(), 2);
+//! assert_eq!(add.param_slot("b"), Some(1));
+//! assert_eq!(add.locals, vec!["sum"]);
+//! assert_eq!(symbols.globals, vec!["total"]);
+//! ```
+
+use crate::ast::{Expression, Program, Statement};
+use crate::error::CompileError;
+use crate::error_code::ErrorCode;
+use std::collections::HashSet;
+
+/// Scopes and functions of a resolved program
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SymbolTable {
+    /// Function definitions in source order
+    pub functions: Vec<FunctionSymbol>,
+    /// Names assigned at the top level, in order of first assignment
+    pub globals: Vec<String>,
+}
+
+impl SymbolTable {
+    /// The function a call to `name` reaches at runtime
+    ///
+    /// Every definition is registered before main code runs, so when a name
+    /// is defined more than once the last definition wins.
+    pub fn function(&self, name: &str) -> Option<&FunctionSymbol> {
+        self.functions.iter().rev().find(|f| f.name == name)
+    }
+}
+
+/// A function's signature and local scope
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionSymbol {
+    pub name: String,
+    /// Parameter names; parameter `i` is passed in slot `i`
+    pub params: Vec<String>,
+    /// Names assigned in the body that are not parameters, in order of first
+    /// assignment
+    pub locals: Vec<String>,
+    /// Index of the definition in [`Program::statements`]
+    pub statement_index: usize,
+}
+
+impl FunctionSymbol {
+    /// Number of parameters
+    pub fn arity(&self) -> usize {
+        self.params.len()
+    }
+
+    /// Slot that receives the argument for parameter `name`
+    pub fn param_slot(&self, name: &str) -> Option<usize> {
+        self.params.iter().position(|param| param == name)
+    }
+}
+
+/// Check scoping rules and build the symbol table for `program`
+pub fn resolve(program: &Program) -> Result<SymbolTable, CompileError> {
+    let all_functions: HashSet<&str> = program
+        .statements
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Statement::FunctionDef { name, .. } => Some(name.as_str()),
+            _ => None,
+        })
+        .collect();
+
+    let mut symbols = SymbolTable::default();
+    let mut defined_so_far = HashSet::new();
+    for (statement_index, stmt) in program.statements.iter().enumerate() {
+        match stmt {
+            Statement::FunctionDef { name, params, body } => {
+                defined_so_far.insert(name.as_str());
+                symbols.functions.push(FunctionSymbol {
+                    name: name.clone(),
+                    params: params.clone(),
+                    locals: resolve_body(body, params)?,
+                    statement_index,
+                });
+            }
+            _ => {
+                // Main code runs in order after every function is registered,
+                // but a call above the definition reads as a use before it exists
+                if let Some(value) = statement_value(stmt) {
+                    check_forward_references(value, &defined_so_far, &all_functions)?;
+                }
+                for name in assigned_names(stmt) {
+                    if !symbols.globals.iter().any(|global| global == name) {
+                        symbols.globals.push(name.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(symbols)
+}
+
+/// Collect the locals of a function body, rejecting nested definitions
+fn resolve_body(body: &[Statement], params: &[String]) -> Result<Vec<String>, CompileError> {
+    let mut locals: Vec<String> = Vec::new();
+    for stmt in body {
+        if let Statement::FunctionDef { .. } = stmt {
+            return Err(CompileError {
+                code: ErrorCode::NestedFunction,
+                message: "Nested function definitions are not supported".to_string(),
+                location: None,
+                end: None,
+            });
+        }
+        for name in assigned_names(stmt) {
+            if !params.contains(name) && !locals.contains(name) {
+                locals.push(name.clone());
+            }
+        }
+    }
+    Ok(locals)
+}
+
+/// The expression a statement evaluates, if any
+fn statement_value(stmt: &Statement) -> Option<&Expression> {
+    match stmt {
+        Statement::Assignment { value, .. }
+        | Statement::MultiAssignment { value, .. }
+        | Statement::Print { value }
+        | Statement::Expression { value } => Some(value),
+        Statement::Return { value } => value.as_ref(),
+        Statement::FunctionDef { .. } => None,
+    }
+}
+
+/// Variables a statement assigns
+fn assigned_names(stmt: &Statement) -> &[String] {
+    match stmt {
+        Statement::Assignment { name, .. } => std::slice::from_ref(name),
+        Statement::MultiAssignment { targets, .. } => targets,
+        _ => &[],
+    }
+}
+
+/// Reject calls to functions that are only defined further down
+fn check_forward_references(
+    expr: &Expression,
+    defined_so_far: &HashSet<&str>,
+    all_functions: &HashSet<&str>,
+) -> Result<(), CompileError> {
+    match expr {
+        Expression::Call { name, args } => {
+            if all_functions.contains(name.as_str()) && !defined_so_far.contains(name.as_str()) {
+                return Err(CompileError {
+                    code: ErrorCode::FunctionUsedBeforeDefinition,
+                    message: format!(
+                        "Call to undefined function '{}' (function defined later in program)",
+                        name
+                    ),
+                    location: None,
+                    end: None,
+                });
+            }
+            args.iter()
+                .try_for_each(|arg| check_forward_references(arg, defined_so_far, all_functions))
+        }
+        Expression::BinaryOp { left, right, .. } => {
+            check_forward_references(left, defined_so_far, all_functions)?;
+            check_forward_references(right, defined_so_far, all_functions)
+        }
+        Expression::UnaryOp { operand, .. } => {
+            check_forward_references(operand, defined_so_far, all_functions)
+        }
+        Expression::Integer(_) | Expression::Variable(_) => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer, parser};
+
+    fn resolve_source(source: &str) -> Result<SymbolTable, CompileError> {
+        resolve(&parser::parse(lexer::lex(source).unwrap()).unwrap())
+    }
+
+    #[test]
+    fn test_records_scopes_in_source_order() {
+        let symbols = resolve_source(
+            "a = b = 1\ndef f(x):\n    y = x\n    x = y\n    z = y\n    return z\ndef g():\n    return f(a)\nc = g()\na = 2",
+        )
+        .unwrap();
+
+        assert_eq!(symbols.globals, vec!["a", "b", "c"]);
+        let names: Vec<_> = symbols.functions.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["f", "g"]);
+
+        let f = &symbols.functions[0];
+        assert_eq!(f.statement_index, 1);
+        assert_eq!(f.locals, vec!["y", "z"]);
+        assert_eq!(f.param_slot("x"), Some(0));
+        assert_eq!(f.param_slot("y"), None);
+        assert_eq!(symbols.functions[1].arity(), 0);
+    }
+
+    #[test]
+    fn test_last_definition_wins() {
+        let symbols =
+            resolve_source("def f():\n    return 1\ndef f(a):\n    return a\nf(2)").unwrap();
+        assert_eq!(symbols.functions.len(), 2);
+        assert_eq!(symbols.function("f").unwrap().statement_index, 1);
+        assert!(symbols.function("g").is_none());
+    }
+
+    #[test]
+    fn test_rejects_top_level_forward_reference() {
+        let err = resolve_source("print(f(1))\ndef f(a):\n    return a").unwrap_err();
+        assert!(err.message.contains("defined later in program"));
+
+        // Bodies may call functions defined below them
+        assert!(resolve_source("def f():\n    return g()\ndef g():\n    return 1\nf()").is_ok());
+    }
+
+    #[test]
+    fn test_rejects_nested_definitions() {
+        let err = resolve_source("def outer():\n    def inner():\n        return 1\n    return 2")
+            .unwrap_err();
+        assert_eq!(err.message, "Nested function definitions are not supported");
+    }
+}
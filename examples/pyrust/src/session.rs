@@ -0,0 +1,179 @@
+//! Persistent execution sessions with REPL semantics
+//!
+//! A [`Session`] keeps one VM alive across many `eval` calls, so variables and
+//! functions defined by one snippet are visible to the next:
+//!
+//! ```
+//! use pyrust::Session;
+//!
+//! let mut session = Session::new();
+//! session.eval("x = 1").unwrap();
+//! session.eval("def inc(n):\n    return n + 1").unwrap();
+//! assert_eq!(session.eval("inc(x)").unwrap(), "2");
+//! ```
+//!
+//! Globals persist in the VM because every snippet is compiled against the
+//! session's variable interner, which keeps variable IDs stable. Function
+//! bodies live inside the bytecode they were compiled into, so the session
+//! remembers each definition and recompiles it ahead of later snippets.
+
+use crate::ast::{Program, Statement};
+use crate::compiler::{self, VariableInterner};
+use crate::error::PyRustError;
+use crate::value::Value;
+use crate::vm::VM;
+use crate::{lexer, parser};
+
+/// Interactive session sharing globals and functions across evaluations
+pub struct Session {
+    /// VM holding the session's global environment
+    vm: VM,
+    /// Interner shared by every compilation in this session
+    interner: VariableInterner,
+    /// Most recent definition of every function, in definition order
+    functions: Vec<Statement>,
+}
+
+impl Session {
+    /// Create an empty session
+    pub fn new() -> Self {
+        Self {
+            vm: crate::new_vm(),
+            interner: VariableInterner::new(),
+            functions: Vec::new(),
+        }
+    }
+
+    /// Evaluate a snippet in the session's environment
+    ///
+    /// Returns output formatted like [`crate::execute_python`]. State from
+    /// earlier snippets is visible; assignments and function definitions made
+    /// here persist even if execution later fails with a runtime error.
+    pub fn eval(&mut self, code: &str) -> Result<String, PyRustError> {
+        let tokens = lexer::lex(code)?;
+        let program = parser::parse(tokens)?;
+
+        let redefined = |def: &Statement| {
+            program.statements.iter().any(|stmt| match (stmt, def) {
+                (
+                    Statement::FunctionDef { name: new, .. },
+                    Statement::FunctionDef { name: old, .. },
+                ) => new == old,
+                _ => false,
+            })
+        };
+
+        // Earlier definitions go first so the snippet can call them
+        let mut statements: Vec<Statement> = self
+            .functions
+            .iter()
+            .filter(|def| !redefined(def))
+            .cloned()
+            .collect();
+        statements.extend(program.statements.iter().cloned());
+
+        let bytecode =
+            compiler::compile_with_interner(&Program { statements }, &mut self.interner)?;
+
+        // Only remember definitions once they have compiled successfully
+        self.functions.retain(|def| !redefined(def));
+        self.functions.extend(
+            program
+                .statements
+                .into_iter()
+                .filter(|stmt| matches!(stmt, Statement::FunctionDef { .. })),
+        );
+
+        self.vm.reset_execution_state();
+        let result = self.vm.execute(&bytecode)?;
+
+        Ok(self.vm.format_output(result))
+    }
+
+    /// Value of a global variable, if it has been assigned
+    pub fn get(&self, name: &str) -> Option<Value> {
+        self.interner
+            .lookup(name)
+            .and_then(|var_id| self.vm.global(var_id))
+    }
+
+    /// Names of the functions defined in this session, in definition order
+    pub fn function_names(&self) -> Vec<&str> {
+        self.functions
+            .iter()
+            .filter_map(|def| match def {
+                Statement::FunctionDef { name, .. } => Some(name.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The session's VM, for configuring limits, stdout sinks, or stdin
+    pub fn vm_mut(&mut self) -> &mut VM {
+        &mut self.vm
+    }
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_globals_persist_across_evals() {
+        let mut session = Session::new();
+        assert_eq!(session.eval("x = 40").unwrap(), "");
+        assert_eq!(session.eval("y = x + 2").unwrap(), "");
+        assert_eq!(session.eval("y").unwrap(), "42");
+        assert_eq!(session.get("y"), Some(Value::Integer(42)));
+        assert_eq!(session.get("missing"), None);
+    }
+
+    #[test]
+    fn test_uncommon_names_keep_their_ids() {
+        let mut session = Session::new();
+        session.eval("first_total = 5").unwrap();
+        session.eval("second_total = 7").unwrap();
+        assert_eq!(session.eval("first_total * second_total").unwrap(), "35");
+    }
+
+    #[test]
+    fn test_functions_persist_across_evals() {
+        let mut session = Session::new();
+        session.eval("def square(n):\n    return n * n").unwrap();
+        session.eval("def add(a, b):\n    return a + b").unwrap();
+        assert_eq!(session.eval("add(square(3), 1)").unwrap(), "10");
+        assert_eq!(session.function_names(), vec!["square", "add"]);
+    }
+
+    #[test]
+    fn test_function_redefinition_replaces_previous() {
+        let mut session = Session::new();
+        session.eval("def f():\n    return 1").unwrap();
+        session.eval("def f():\n    return 2").unwrap();
+        assert_eq!(session.eval("f()").unwrap(), "2");
+        assert_eq!(session.function_names(), vec!["f"]);
+    }
+
+    #[test]
+    fn test_output_does_not_leak_between_evals() {
+        let mut session = Session::new();
+        assert_eq!(session.eval("print(1)\n2").unwrap(), "1\n2");
+        assert_eq!(session.eval("x = 3").unwrap(), "");
+    }
+
+    #[test]
+    fn test_session_recovers_after_errors() {
+        let mut session = Session::new();
+        session.eval("x = 1").unwrap();
+        assert!(session.eval("x = (").is_err());
+        assert!(session.eval("def f():\n    return 1 / 0\nf()").is_err());
+        assert_eq!(session.eval("x").unwrap(), "1");
+        assert_eq!(session.function_names(), vec!["f"]);
+    }
+}
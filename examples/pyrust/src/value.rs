@@ -4,7 +4,7 @@
 //! Phase 1 supports only Integer values with arithmetic operations.
 
 use crate::ast::{BinaryOperator, UnaryOperator};
-use crate::error::RuntimeError;
+use crate::error::{ExceptionKind, RuntimeError};
 use std::fmt;
 
 /// Runtime value representation
@@ -19,7 +19,40 @@ pub enum Value {
     None,
 }
 
+/// Type tag of a [`Value`] in its packed form
+///
+/// Packed values are a tag byte plus one 8-byte payload word, stored in
+/// separate arrays by the VM's register file. Variants added to `Value` get a
+/// new tag and must encode into the payload word (float bits, heap handles),
+/// so register slots stay 9 bytes no matter how large the enum grows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub(crate) enum ValueTag {
+    /// Payload is the integer's two's-complement bits
+    Integer,
+    /// Payload is unused (zero)
+    None,
+}
+
 impl Value {
+    /// Split into a type tag and payload word
+    #[inline(always)]
+    pub(crate) fn pack(self) -> (ValueTag, u64) {
+        match self {
+            Value::Integer(value) => (ValueTag::Integer, value as u64),
+            Value::None => (ValueTag::None, 0),
+        }
+    }
+
+    /// Rebuild a value from the output of [`Value::pack`]
+    #[inline(always)]
+    pub(crate) fn unpack(tag: ValueTag, payload: u64) -> Value {
+        match tag {
+            ValueTag::Integer => Value::Integer(payload as i64),
+            ValueTag::None => Value::None,
+        }
+    }
+
     /// Perform a binary operation on two values
     ///
     /// # Arguments
@@ -35,74 +68,73 @@ impl Value {
     /// * Integer overflow/underflow for any arithmetic operation
     pub fn binary_op(&self, op: BinaryOperator, right: &Value) -> Result<Value, RuntimeError> {
         match (self, right) {
-            (Value::None, _) | (_, Value::None) => Err(RuntimeError {
-                message: "Cannot perform binary operation on None".to_string(),
-                instruction_index: 0,
-            }),
+            (Value::None, _) | (_, Value::None) => Err(RuntimeError::new(
+                ExceptionKind::TypeError,
+                "Cannot perform binary operation on None",
+                0,
+            )),
             (Value::Integer(left_val), Value::Integer(right_val)) => {
                 let result = match op {
-                    BinaryOperator::Add => {
-                        left_val
-                            .checked_add(*right_val)
-                            .ok_or_else(|| RuntimeError {
-                                message: format!("Integer overflow: {} + {}", left_val, right_val),
-                                instruction_index: 0,
-                            })?
-                    }
-                    BinaryOperator::Sub => {
-                        left_val
-                            .checked_sub(*right_val)
-                            .ok_or_else(|| RuntimeError {
-                                message: format!("Integer overflow: {} - {}", left_val, right_val),
-                                instruction_index: 0,
-                            })?
-                    }
-                    BinaryOperator::Mul => {
-                        left_val
-                            .checked_mul(*right_val)
-                            .ok_or_else(|| RuntimeError {
-                                message: format!("Integer overflow: {} * {}", left_val, right_val),
-                                instruction_index: 0,
-                            })?
-                    }
+                    BinaryOperator::Add => left_val.checked_add(*right_val).ok_or_else(|| {
+                        RuntimeError::new(
+                            ExceptionKind::OverflowError,
+                            format!("Integer overflow: {} + {}", left_val, right_val),
+                            0,
+                        )
+                    })?,
+                    BinaryOperator::Sub => left_val.checked_sub(*right_val).ok_or_else(|| {
+                        RuntimeError::new(
+                            ExceptionKind::OverflowError,
+                            format!("Integer overflow: {} - {}", left_val, right_val),
+                            0,
+                        )
+                    })?,
+                    BinaryOperator::Mul => left_val.checked_mul(*right_val).ok_or_else(|| {
+                        RuntimeError::new(
+                            ExceptionKind::OverflowError,
+                            format!("Integer overflow: {} * {}", left_val, right_val),
+                            0,
+                        )
+                    })?,
                     BinaryOperator::Div => {
                         if *right_val == 0 {
-                            return Err(RuntimeError {
-                                message: "Division by zero".to_string(),
-                                instruction_index: 0,
-                            });
+                            return Err(RuntimeError::new(
+                                ExceptionKind::ZeroDivisionError,
+                                "Division by zero",
+                                0,
+                            ));
                         }
-                        left_val
-                            .checked_div(*right_val)
-                            .ok_or_else(|| RuntimeError {
-                                message: format!("Integer overflow: {} / {}", left_val, right_val),
-                                instruction_index: 0,
-                            })?
+                        left_val.checked_div(*right_val).ok_or_else(|| {
+                            RuntimeError::new(
+                                ExceptionKind::OverflowError,
+                                format!("Integer overflow: {} / {}", left_val, right_val),
+                                0,
+                            )
+                        })?
                     }
                     BinaryOperator::FloorDiv => {
                         if *right_val == 0 {
-                            return Err(RuntimeError {
-                                message: "Division by zero".to_string(),
-                                instruction_index: 0,
-                            });
+                            return Err(RuntimeError::new(
+                                ExceptionKind::ZeroDivisionError,
+                                "Division by zero",
+                                0,
+                            ));
                         }
                         // Floor division in Python/Rust: rounds toward negative infinity
-                        let quot =
-                            left_val
-                                .checked_div(*right_val)
-                                .ok_or_else(|| RuntimeError {
-                                    message: format!(
-                                        "Integer overflow: {} // {}",
-                                        left_val, right_val
-                                    ),
-                                    instruction_index: 0,
-                                })?;
-                        let rem = left_val
-                            .checked_rem(*right_val)
-                            .ok_or_else(|| RuntimeError {
-                                message: format!("Integer overflow: {} % {}", left_val, right_val),
-                                instruction_index: 0,
-                            })?;
+                        let quot = left_val.checked_div(*right_val).ok_or_else(|| {
+                            RuntimeError::new(
+                                ExceptionKind::OverflowError,
+                                format!("Integer overflow: {} // {}", left_val, right_val),
+                                0,
+                            )
+                        })?;
+                        let rem = left_val.checked_rem(*right_val).ok_or_else(|| {
+                            RuntimeError::new(
+                                ExceptionKind::OverflowError,
+                                format!("Integer overflow: {} % {}", left_val, right_val),
+                                0,
+                            )
+                        })?;
                         // Adjust for Python floor division semantics
                         if (rem != 0) && ((left_val < &0) != (right_val < &0)) {
                             quot - 1
@@ -112,18 +144,20 @@ impl Value {
                     }
                     BinaryOperator::Mod => {
                         if *right_val == 0 {
-                            return Err(RuntimeError {
-                                message: "Division by zero".to_string(),
-                                instruction_index: 0,
-                            });
+                            return Err(RuntimeError::new(
+                                ExceptionKind::ZeroDivisionError,
+                                "Division by zero",
+                                0,
+                            ));
                         }
                         // Python modulo: result has same sign as divisor
-                        let rem = left_val
-                            .checked_rem(*right_val)
-                            .ok_or_else(|| RuntimeError {
-                                message: format!("Integer overflow: {} % {}", left_val, right_val),
-                                instruction_index: 0,
-                            })?;
+                        let rem = left_val.checked_rem(*right_val).ok_or_else(|| {
+                            RuntimeError::new(
+                                ExceptionKind::OverflowError,
+                                format!("Integer overflow: {} % {}", left_val, right_val),
+                                0,
+                            )
+                        })?;
                         if (rem != 0) && ((left_val < &0) != (right_val < &0)) {
                             rem + right_val
                         } else {
@@ -150,17 +184,21 @@ impl Value {
     /// * Unsupported operation for operators not in Phase 1
     pub fn unary_op(&self, op: UnaryOperator) -> Result<Value, RuntimeError> {
         match self {
-            Value::None => Err(RuntimeError {
-                message: "Cannot perform unary operation on None".to_string(),
-                instruction_index: 0,
-            }),
+            Value::None => Err(RuntimeError::new(
+                ExceptionKind::TypeError,
+                "Cannot perform unary operation on None",
+                0,
+            )),
             Value::Integer(val) => match op {
                 UnaryOperator::Pos => Ok(Value::Integer(*val)),
                 UnaryOperator::Neg => val
                     .checked_neg()
-                    .ok_or_else(|| RuntimeError {
-                        message: format!("Integer overflow: -{}", val),
-                        instruction_index: 0,
+                    .ok_or_else(|| {
+                        RuntimeError::new(
+                            ExceptionKind::OverflowError,
+                            format!("Integer overflow: -{}", val),
+                            0,
+                        )
                     })
                     .map(Value::Integer),
             },
@@ -186,6 +224,14 @@ impl Value {
             Value::None => panic!("Called as_integer on None value: expected Value::Integer but found Value::None. This indicates a type error in the VM - ensure all operations produce valid Integer values."),
         }
     }
+
+    /// Python truthiness: zero and None are false, every other integer is true
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Integer(val) => *val != 0,
+            Value::None => false,
+        }
+    }
 }
 
 impl fmt::Display for Value {
@@ -569,4 +615,27 @@ mod tests {
             "Cannot perform unary operation on None"
         );
     }
+
+    #[test]
+    fn test_is_truthy() {
+        assert!(Value::Integer(1).is_truthy());
+        assert!(Value::Integer(-5).is_truthy());
+        assert!(!Value::Integer(0).is_truthy());
+        assert!(!Value::None.is_truthy());
+    }
+
+    #[test]
+    fn test_pack_round_trip() {
+        for value in [
+            Value::Integer(0),
+            Value::Integer(-1),
+            Value::Integer(i64::MIN),
+            Value::Integer(i64::MAX),
+            Value::None,
+        ] {
+            let (tag, payload) = value.pack();
+            assert_eq!(Value::unpack(tag, payload), value);
+        }
+        assert_eq!(std::mem::size_of::<ValueTag>(), 1);
+    }
 }
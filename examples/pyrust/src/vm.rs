@@ -1,13 +1,34 @@
 //! Virtual Machine for bytecode execution
 //!
-//! Register-based VM with 256 preallocated registers for efficient execution.
+//! Register-based VM whose register file is sized from bytecode metadata.
 //! Executes bytecode instructions and tracks program state including variables,
 //! stdout output, and expression results.
 
 use crate::bytecode::{Bytecode, Instruction};
-use crate::error::RuntimeError;
-use crate::value::Value;
+use crate::cancel::{CancellationToken, CANCELLED_MESSAGE};
+use crate::debugger::{DebugAction, Debugger, FrameInfo, PausedState};
+use crate::error::{ExceptionKind, RuntimeError, TracebackFrame};
+use crate::error_code::ErrorCode;
+#[cfg(feature = "fast-dispatch")]
+use crate::flat::{FlatCode, FlatOp, Opcode};
+use crate::input::InputSource;
+use crate::value::{Value, ValueTag};
 use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// Maximum function call depth before raising RecursionError (matches CPython's default)
+pub const MAX_CALL_DEPTH: usize = 1000;
+
+/// Registers addressable by one frame (the full u8 register range)
+const REGISTER_WINDOW: usize = 256;
+
+/// Register windows and pooled locals maps kept across [`VM::reset`]; memory
+/// beyond this from an unusually deep run is released
+const RETAINED_FRAMES: usize = 16;
+
+/// Instructions between checks of the cancellation token
+const CANCEL_CHECK_INTERVAL: u64 = 256;
 
 /// Small string optimization for stdout buffer
 ///
@@ -72,6 +93,12 @@ impl SmallString {
         }
     }
 
+    /// Remove all contents, returning to inline storage
+    #[inline]
+    fn clear(&mut self) {
+        *self = SmallString::new();
+    }
+
     /// Check if the SmallString is empty
     #[inline]
     fn is_empty(&self) -> bool {
@@ -82,15 +109,289 @@ impl SmallString {
     }
 }
 
+/// Callback receiving print output as it is produced
+///
+/// Each call receives one complete line including its trailing newline.
+pub type StdoutSink = Box<dyn FnMut(&str) + Send>;
+
+/// Cap on the print output one execution may produce
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputLimit {
+    /// Maximum bytes of print output, counting both buffered and streamed lines
+    pub max_bytes: usize,
+    /// What happens to the line that would cross `max_bytes`
+    pub on_overflow: OutputOverflow,
+}
+
+/// Behaviour when print output reaches an [`OutputLimit`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputOverflow {
+    /// Drop the line and everything after it, emit a one-line marker, and keep running
+    Truncate,
+    /// Fail the execution with an "output limit exceeded" runtime error
+    Error,
+}
+
+/// Resource usage of one execution
+///
+/// Returned by [`VM::execute_with_stats`], or read with [`VM::stats`] after
+/// any run (including a failed one), so callers can log usage or enforce
+/// their own quotas.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExecutionStats {
+    /// Instructions executed
+    pub instructions_executed: u64,
+    /// Deepest nesting of function calls reached
+    pub max_call_depth: usize,
+    /// Register slots in use at the peak, counting every active frame's window
+    /// up to the highest register written in the deepest one
+    pub peak_registers: usize,
+    /// Bytes of print output produced, whether buffered or streamed
+    pub stdout_bytes: usize,
+    /// Call frames that needed a newly allocated locals map rather than a pooled one
+    pub allocations: u64,
+}
+
+/// Outcome of executing a single instruction with [`VM::step`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepResult {
+    /// Execution can continue with the instruction at `ip`
+    Running { ip: usize },
+    /// The program halted, carrying its expression result
+    Halted(Option<Value>),
+}
+
+/// Snapshot of a running execution, as reported to a watchdog
+///
+/// See [`VM::set_watchdog`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchdogEvent {
+    /// Index of the instruction about to run
+    pub ip: usize,
+    /// Number of active function calls
+    pub call_depth: usize,
+    /// Resource usage so far
+    pub stats: ExecutionStats,
+}
+
+/// What a watchdog wants the VM to do next
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WatchdogAction {
+    /// Keep running
+    Continue,
+    /// Stop with a runtime error carrying this reason
+    Abort(String),
+}
+
+/// Callback consulted periodically by the VM while it runs
+pub type WatchdogHook = Box<dyn FnMut(&WatchdogEvent) -> WatchdogAction + Send>;
+
+/// A watchdog hook and how often to call it
+struct Watchdog {
+    interval: u64,
+    hook: WatchdogHook,
+}
+
+/// Callback receiving every executed instruction when tracing is enabled
+pub type TraceHook = Box<dyn for<'a> FnMut(&TraceEvent<'a>) + Send>;
+
+/// One executed instruction, as reported to a [`TraceHook`]
+///
+/// Events are emitted after the instruction completes. The `Display`
+/// implementation renders a single trace line, indented by call depth:
+///
+/// ```
+/// use std::sync::{Arc, Mutex};
+///
+/// let trace = Arc::new(Mutex::new(Vec::new()));
+/// let lines = Arc::clone(&trace);
+/// pyrust::execute_python_traced("1 + 2", move |event| {
+///     lines.lock().unwrap().push(event.to_string());
+/// })
+/// .unwrap();
+///
+/// assert_eq!(trace.lock().unwrap()[2], "0002 BinaryOp r0, r0 + r1  ; r0 = 3");
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct TraceEvent<'a> {
+    /// Index of the executed instruction
+    pub ip: usize,
+    /// The executed instruction
+    pub instruction: &'a Instruction,
+    /// Number of active function calls after the instruction ran
+    pub call_depth: usize,
+    /// Register written by the instruction and its new value, if any
+    pub written: Option<(u8, Value)>,
+    /// Program the instruction belongs to, for decoding operands
+    pub bytecode: &'a Bytecode,
+}
+
+impl fmt::Display for TraceEvent<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04} {:indent$}{}",
+            self.ip,
+            "",
+            self.bytecode.describe_instruction(self.ip),
+            indent = self.call_depth * 2
+        )?;
+        if let Some((reg, value)) = self.written {
+            write!(f, "  ; r{} = {}", reg, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Register storage in packed form: parallel payload words and type tags
+///
+/// Splitting [`Value`] into a tag byte and a payload word keeps each slot at
+/// 9 bytes, so a 256-register window stays within a few cache lines however
+/// many variants `Value` gains.
+#[derive(Debug, Clone, Default)]
+struct RegisterFile {
+    payloads: Vec<u64>,
+    tags: Vec<ValueTag>,
+}
+
+impl RegisterFile {
+    fn len(&self) -> usize {
+        self.payloads.len()
+    }
+
+    #[inline(always)]
+    fn get(&self, index: usize) -> Value {
+        Value::unpack(self.tags[index], self.payloads[index])
+    }
+
+    /// Store `value` in slot `index`, growing the file if it is too short
+    ///
+    /// Metadata only presizes the file; handcrafted bytecode may address
+    /// registers beyond what it declares.
+    #[inline(always)]
+    fn set(&mut self, index: usize, value: Value) {
+        if index >= self.len() {
+            self.grow_to_fit(index);
+        }
+        let (tag, payload) = value.pack();
+        self.tags[index] = tag;
+        self.payloads[index] = payload;
+    }
+
+    /// The integer in slot `index`, without building a `Value`
+    #[cfg(feature = "fast-dispatch")]
+    #[inline(always)]
+    fn integer(&self, index: usize) -> Option<i64> {
+        (self.tags[index] == ValueTag::Integer).then(|| self.payloads[index] as i64)
+    }
+
+    #[cold]
+    fn grow_to_fit(&mut self, index: usize) {
+        self.ensure_len(index + 1);
+    }
+
+    /// Grow to at least `len` slots
+    fn ensure_len(&mut self, len: usize) {
+        if self.payloads.len() < len {
+            self.payloads.resize(len, 0);
+            self.tags.resize(len, ValueTag::Integer);
+        }
+    }
+
+    /// Shrink to at most `len` slots, releasing the memory beyond
+    fn shrink_to(&mut self, len: usize) {
+        if self.payloads.len() > len {
+            self.payloads.truncate(len);
+            self.payloads.shrink_to(len);
+            self.tags.truncate(len);
+            self.tags.shrink_to(len);
+        }
+    }
+}
+
+/// Interned IDs below this are stored in the dense global slot array
+const DENSE_GLOBAL_LIMIT: u32 = 1 << 16;
+
+/// Global variables indexed directly by interned ID
+///
+/// The compiler's interner hands out IDs sequentially, so globals fit a dense
+/// array and LoadVar/StoreVar skip hashing entirely. IDs past
+/// `DENSE_GLOBAL_LIMIT` (only seen in handcrafted bytecode) fall back to a map
+/// so a stray large ID cannot force a huge allocation.
+#[derive(Debug, Default)]
+struct GlobalSlots {
+    dense: Vec<Option<Value>>,
+    sparse: HashMap<u32, Value>,
+    len: usize,
+}
+
+impl GlobalSlots {
+    #[inline]
+    fn get(&self, var_id: &u32) -> Option<&Value> {
+        if *var_id < DENSE_GLOBAL_LIMIT {
+            self.dense.get(*var_id as usize).and_then(Option::as_ref)
+        } else {
+            self.sparse.get(var_id)
+        }
+    }
+
+    #[inline]
+    fn insert(&mut self, var_id: u32, value: Value) -> Option<Value> {
+        let previous = if var_id < DENSE_GLOBAL_LIMIT {
+            let index = var_id as usize;
+            if index >= self.dense.len() {
+                // One allocation covers the first few dozen names
+                let len = (index + 1).next_power_of_two().max(32);
+                self.dense.resize(len, None);
+            }
+            self.dense[index].replace(value)
+        } else {
+            self.sparse.insert(var_id, value)
+        };
+        if previous.is_none() {
+            self.len += 1;
+        }
+        previous
+    }
+
+    /// Remove every global, keeping the slot array's allocation
+    fn clear(&mut self) {
+        self.dense.fill(None);
+        self.sparse.clear();
+        self.len = 0;
+    }
+
+    #[cfg(test)]
+    fn contains_key(&self, var_id: &u32) -> bool {
+        self.get(var_id).is_some()
+    }
+
+    #[cfg(test)]
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
 /// Function metadata stored in the VM
 #[derive(Debug, Clone)]
 struct FunctionMetadata {
     /// Parameter count
     param_count: u8,
-    /// Start index of function body in bytecode
-    body_start: usize,
-    /// Maximum register used in this function (optional for backward compat)
-    max_register_used: Option<u8>,
+    /// Index of the body in [`Bytecode::functions`]
+    chunk: usize,
+    /// Code address of the body's first instruction
+    start: usize,
+    /// Registers the body uses, from its `max_register_used`
+    register_count: usize,
+}
+
+/// Inline cache for one Call instruction, filled on its first execution
+#[derive(Debug, Clone)]
+struct CallSite {
+    /// `VM::function_epoch` the entry was resolved in
+    epoch: u64,
+    /// Resolved callee (its arity already checked against the call)
+    function: FunctionMetadata,
 }
 
 /// Call frame for function execution
@@ -98,72 +399,519 @@ struct FunctionMetadata {
 struct CallFrame {
     /// Return address (instruction pointer to resume after return)
     return_address: usize,
+    /// Caller's chunk and its start address, restored on return
+    return_chunk: Option<usize>,
+    return_chunk_start: usize,
     /// Local variables for this function scope using interned IDs
     local_vars: HashMap<u32, Value>,
-    /// Saved registers state (only used registers)
-    saved_registers: Vec<Value>,
-    /// Saved register validity bitmap
-    saved_register_valid: [u64; 4],
-    /// Maximum register that was saved
-    max_saved_reg: u8,
-    /// Register where return value should be stored
+    /// Caller's register window base, restored on return
+    caller_base: usize,
+    /// Caller's register validity bitmap, restored on return
+    caller_register_valid: [u64; 4],
+    /// Register (in the caller's window) where return value should be stored
     dest_reg: u8,
 }
 
 /// Virtual Machine for bytecode execution
 ///
 /// Provides a register-based execution environment with:
-/// - 256 preallocated registers for fast value manipulation
+/// - Up to 256 registers per frame, stored as packed tag bytes and payload
+///   words and allocated only as far as the bytecode's metadata requires
+/// - A register stack giving each call its own window above the caller's
+///   live registers, so calls never copy the caller's registers
 /// - Bitmap-based register validity tracking for optimal performance
-/// - Variable storage using HashMap
+/// - Globals in a dense slot array indexed by interned variable ID
+/// - Per-instruction inline caches for function calls
 /// - stdout capture for print statements
 /// - Result tracking for expression statements
 /// - Function call stack for nested function calls
 pub struct VM {
-    /// Preallocated register file (256 registers)
-    registers: Vec<Value>,
+    /// Register stack: one window per active frame, grown on demand
+    registers: RegisterFile,
+
+    /// Index in `registers` of the current frame's register 0
+    base: usize,
 
-    /// Register validity bitmap (4 x u64 = 256 bits for 256 registers)
+    /// Validity bitmap for the current window (4 x u64 = 256 bits)
     register_valid: [u64; 4],
 
-    /// Current instruction pointer for accurate error reporting
+    /// Current instruction pointer for accurate error reporting, as a code
+    /// address (see [`Bytecode::functions`])
     ip: usize,
 
+    /// Function chunk being executed (None = main code)
+    chunk: Option<usize>,
+
+    /// Code address of the current chunk's first instruction
+    chunk_start: usize,
+
     /// Variable storage (interned ID -> value) - global scope
-    variables: HashMap<u32, Value>,
+    variables: GlobalSlots,
 
     /// Accumulated stdout output from print statements
     stdout: SmallString,
 
+    /// Streaming destination for print output; bypasses `stdout` when set
+    stdout_sink: Option<StdoutSink>,
+
+    /// Standard input attached to this VM (None = no input available)
+    stdin: Option<InputSource>,
+
+    /// Script arguments, read with the `argc()` and `argv(i)` builtins
+    args: Vec<String>,
+
+    /// Per-instruction trace callback (None = tracing disabled)
+    trace_hook: Option<TraceHook>,
+
+    /// Attached debugger consulted before each instruction
+    debugger: Option<Box<dyn Debugger>>,
+
+    /// Whether the debugger asked to pause before the next instruction
+    debug_stepping: bool,
+
+    /// Whether [`VM::step`] reached `Halt`, so later steps do nothing
+    halted: bool,
+
     /// Result from last SetResult instruction
     result: Option<Value>,
 
     /// Function storage (name -> metadata)
     functions: HashMap<String, FunctionMetadata>,
 
+    /// Inline caches indexed by Call instruction position
+    call_sites: Vec<Option<CallSite>>,
+
+    /// Bumped whenever cached call sites may be stale: a function was
+    /// (re)defined or a new run started, possibly with different bytecode
+    function_epoch: u64,
+
     /// Call stack for function calls
     call_stack: Vec<CallFrame>,
+
+    /// Cleared locals maps from returned frames, reused by later calls so a
+    /// call only allocates when it runs deeper than any call before it
+    locals_pool: Vec<HashMap<u32, Value>>,
+
+    /// Maximum instructions a single execute() may run (None = unlimited)
+    instruction_limit: Option<u64>,
+
+    /// Token checked periodically to abort execution from another thread
+    cancellation: Option<CancellationToken>,
+
+    /// Hook called every few instructions that may abort execution
+    watchdog: Option<Watchdog>,
+
+    /// Cap on print output (None = unlimited)
+    output_limit: Option<OutputLimit>,
+
+    /// Cap on nested function calls, below [`MAX_CALL_DEPTH`] (None = unlimited)
+    call_depth_limit: Option<usize>,
+
+    /// Cap on the estimated bytes held by call frames and registers (None = unlimited)
+    memory_limit: Option<usize>,
+
+    /// Print output bytes produced since the last reset of execution state
+    output_bytes: usize,
+
+    /// Whether output was cut off by a truncating limit
+    output_truncated: bool,
+
+    /// Instructions executed by the current (or last) execute()
+    instructions_executed: u64,
+
+    /// Deepest call stack seen by the current (or last) execute()
+    max_call_depth: usize,
+
+    /// Highest register slot written in any window that has been left
+    peak_registers: usize,
+
+    /// Locals maps allocated because the pool was empty
+    frame_allocations: u64,
+
+    /// `output_bytes` when the current (or last) execute() started
+    output_bytes_at_start: usize,
 }
 
 impl VM {
-    /// Create a new VM with preallocated 256-register file
+    /// Create a new VM with an empty register file
     ///
-    /// All registers are initialized to Value::Integer(0) with validity bits cleared.
-    /// Variables HashMap starts empty.
+    /// Registers are allocated on the first execution, sized from the
+    /// bytecode's metadata, with validity bits cleared.
+    /// No globals are defined.
     /// stdout buffer and result are empty/None.
     pub fn new() -> Self {
         Self {
-            registers: vec![Value::Integer(0); 256],
+            registers: RegisterFile::default(),
+            base: 0,
             register_valid: [0; 4],
             ip: 0,
-            variables: HashMap::new(),
+            chunk: None,
+            chunk_start: 0,
+            variables: GlobalSlots::default(),
             stdout: SmallString::new(),
+            stdout_sink: None,
+            stdin: None,
+            args: Vec::new(),
+            trace_hook: None,
+            debugger: None,
+            debug_stepping: false,
+            halted: false,
             result: None,
             functions: HashMap::new(),
+            call_sites: Vec::new(),
+            function_epoch: 0,
             call_stack: Vec::new(),
+            locals_pool: Vec::new(),
+            instruction_limit: None,
+            cancellation: None,
+            watchdog: None,
+            output_limit: None,
+            call_depth_limit: None,
+            memory_limit: None,
+            output_bytes: 0,
+            output_truncated: false,
+            instructions_executed: 0,
+            max_call_depth: 0,
+            peak_registers: 0,
+            frame_allocations: 0,
+            output_bytes_at_start: 0,
+        }
+    }
+
+    /// Cap the number of instructions a single execute() may run
+    ///
+    /// Exceeding the budget aborts execution with an "execution budget
+    /// exceeded" error, so runaway programs cannot hang the caller.
+    /// `None` (the default) disables the limit.
+    pub fn set_instruction_limit(&mut self, limit: Option<u64>) {
+        self.instruction_limit = limit;
+    }
+
+    /// Abort execution once `token` is cancelled
+    ///
+    /// The token is polled every few hundred instructions, so a cancelled run
+    /// stops promptly with an "execution cancelled" error while the VM stays
+    /// usable. A token cancelled before execution starts stops it at the
+    /// first instruction.
+    pub fn set_cancellation_token(&mut self, token: CancellationToken) {
+        self.cancellation = Some(token);
+    }
+
+    /// Detach the cancellation token
+    pub fn clear_cancellation_token(&mut self) {
+        self.cancellation = None;
+    }
+
+    /// Call `hook` every `interval` instructions while executing
+    ///
+    /// The hook sees the instruction pointer, call depth, and resource usage
+    /// so far, and returns [`WatchdogAction::Abort`] to stop the run with an
+    /// "execution aborted by watchdog" runtime error. One hook covers wall-clock
+    /// timeouts, progress reporting, and custom quotas. An interval of zero is
+    /// treated as one.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pyrust::vm::{WatchdogAction, VM};
+    /// use pyrust::{compiler, lexer, parser};
+    ///
+    /// let program = parser::parse(lexer::lex("x = 1\nx = x + 1\nx = x + 1").unwrap()).unwrap();
+    /// let bytecode = compiler::compile(&program).unwrap();
+    ///
+    /// let mut vm = VM::new();
+    /// vm.set_watchdog(4, |event| {
+    ///     if event.stats.instructions_executed >= 8 {
+    ///         WatchdogAction::Abort("too slow".to_string())
+    ///     } else {
+    ///         WatchdogAction::Continue
+    ///     }
+    /// });
+    /// let err = vm.execute(&bytecode).unwrap_err();
+    /// assert_eq!(err.message, "execution aborted by watchdog: too slow");
+    /// ```
+    pub fn set_watchdog(
+        &mut self,
+        interval: u64,
+        hook: impl FnMut(&WatchdogEvent) -> WatchdogAction + Send + 'static,
+    ) {
+        self.watchdog = Some(Watchdog {
+            interval: interval.max(1),
+            hook: Box::new(hook),
+        });
+    }
+
+    /// Detach the watchdog
+    pub fn clear_watchdog(&mut self) {
+        self.watchdog = None;
+    }
+
+    /// Cap the print output produced between resets of execution state
+    ///
+    /// Guards against programs printing without bound, which would otherwise
+    /// grow the stdout buffer (and any daemon response) indefinitely.
+    /// Streamed output counts too. `None` (the default) disables the limit.
+    pub fn set_output_limit(&mut self, limit: Option<OutputLimit>) {
+        self.output_limit = limit;
+    }
+
+    /// Cap the depth of nested function calls
+    ///
+    /// Calling deeper fails with a "call depth limit exceeded" error. The
+    /// limit only tightens [`MAX_CALL_DEPTH`], which always applies. `None`
+    /// (the default) disables the limit.
+    pub fn set_call_depth_limit(&mut self, limit: Option<usize>) {
+        self.call_depth_limit = limit;
+    }
+
+    /// Cap the memory a run may hold in call frames and registers
+    ///
+    /// The usage is an estimate, checked on every function call: the
+    /// registers of all active windows plus one call frame per active call.
+    /// Exceeding it fails with a "memory limit exceeded" error. `None` (the
+    /// default) disables the limit.
+    pub fn set_memory_limit(&mut self, limit: Option<usize>) {
+        self.memory_limit = limit;
+    }
+
+    /// Bytes of print output produced since the last reset of execution state
+    pub fn output_bytes(&self) -> usize {
+        self.output_bytes
+    }
+
+    /// Stream print output to `sink` instead of buffering it
+    ///
+    /// While a sink is attached, print output is not included in
+    /// [`VM::format_output`]; only the expression result is.
+    pub fn set_stdout_sink(&mut self, sink: impl FnMut(&str) + Send + 'static) {
+        self.stdout_sink = Some(Box::new(sink));
+    }
+
+    /// Detach the stdout sink, returning to buffered output
+    pub fn clear_stdout_sink(&mut self) {
+        self.stdout_sink = None;
+    }
+
+    /// Report every executed instruction to `hook`
+    ///
+    /// Tracing slows execution considerably; it is meant for debugging
+    /// compiler output rather than production runs.
+    pub fn set_trace_hook(&mut self, hook: impl for<'a> FnMut(&TraceEvent<'a>) + Send + 'static) {
+        self.trace_hook = Some(Box::new(hook));
+    }
+
+    /// Disable tracing
+    pub fn clear_trace_hook(&mut self) {
+        self.trace_hook = None;
+    }
+
+    /// Attach a debugger that is consulted before every instruction
+    pub fn set_debugger(&mut self, debugger: impl Debugger + 'static) {
+        self.debugger = Some(Box::new(debugger));
+    }
+
+    /// Detach and return the debugger
+    pub fn take_debugger(&mut self) -> Option<Box<dyn Debugger>> {
+        self.debugger.take()
+    }
+
+    /// Return to the state of a newly constructed VM, reusing allocations
+    ///
+    /// Clears globals, functions, buffered stdout, the last result, call
+    /// frames, and register validity in place, so reuse skips reallocating the
+    /// register file. Configuration survives: the instruction and output
+    /// limits, stdout sink, stdin, script arguments, trace hook, watchdog, and
+    /// debugger stay attached.
+    /// The call depth and memory limits survive too.
+    pub fn reset(&mut self) {
+        self.reset_execution_state();
+        self.variables.clear();
+        self.functions.clear();
+        self.function_epoch += 1;
+        self.base = 0;
+        self.register_valid = [0; 4];
+        self.ip = 0;
+        self.chunk = None;
+        self.chunk_start = 0;
+        self.reset_stats();
+        self.debug_stepping = false;
+        self.halted = false;
+
+        self.registers.shrink_to(RETAINED_FRAMES * REGISTER_WINDOW);
+        self.locals_pool.truncate(RETAINED_FRAMES);
+    }
+
+    /// Clear per-execution state while keeping globals and functions
+    ///
+    /// Discards buffered stdout, the last expression result, and any call
+    /// frames left behind by a failed execution, so the VM can run further
+    /// bytecode against the same global environment.
+    pub fn reset_execution_state(&mut self) {
+        self.stdout.clear();
+        self.output_bytes = 0;
+        self.output_bytes_at_start = 0;
+        self.output_truncated = false;
+        self.result = None;
+        self.abandon_calls();
+    }
+
+    /// Drop call frames left by an interrupted run, returning to the top-level window
+    fn abandon_calls(&mut self) {
+        if let Some(outermost) = self.call_stack.first() {
+            self.leave_window(outermost.caller_base, outermost.caller_register_valid);
+        }
+        while let Some(frame) = self.call_stack.pop() {
+            self.recycle_locals(frame.local_vars);
+        }
+    }
+
+    /// Return a finished frame's locals map to the pool for the next call
+    #[inline]
+    fn recycle_locals(&mut self, mut locals: HashMap<u32, Value>) {
+        locals.clear();
+        self.locals_pool.push(locals);
+    }
+
+    /// Look up a global variable by interned ID
+    pub fn global(&self, var_id: u32) -> Option<Value> {
+        self.variables.get(&var_id).copied()
+    }
+
+    /// Register value, or None if the register has not been written
+    pub(crate) fn register_value(&self, reg: u8) -> Option<Value> {
+        self.get_register(reg).ok()
+    }
+
+    /// Variable as LoadVar would resolve it: locals of the innermost call, then globals
+    pub(crate) fn visible_variable(&self, var_id: u32) -> Option<Value> {
+        self.call_stack
+            .last()
+            .and_then(|frame| frame.local_vars.get(&var_id))
+            .or_else(|| self.variables.get(&var_id))
+            .copied()
+    }
+
+    /// Fill in the traceback of `error`, raised in `bytecode`, from the
+    /// active call frames
+    ///
+    /// Each frame is shown at the call it made, which sits just before its
+    /// return address; the last is at the failing instruction. Errors raised
+    /// outside any call keep an empty traceback.
+    fn with_traceback(&self, mut error: RuntimeError, bytecode: &Bytecode) -> RuntimeError {
+        if self.call_stack.is_empty() || !error.traceback.is_empty() {
+            return error;
+        }
+        let addresses = self
+            .call_stack
+            .iter()
+            .map(|frame| frame.return_address - 1)
+            .chain(std::iter::once(error.instruction_index));
+        error.traceback = addresses
+            .map(|address| TracebackFrame {
+                function: bytecode.function_name(address).map(str::to_string),
+                instruction_index: address,
+                location: None,
+            })
+            .collect();
+        error
+    }
+
+    /// Summary of the active call frames, outermost first
+    pub(crate) fn frame_infos(&self) -> Vec<FrameInfo> {
+        self.call_stack
+            .iter()
+            .map(|frame| FrameInfo {
+                return_address: frame.return_address,
+                dest_reg: frame.dest_reg,
+            })
+            .collect()
+    }
+
+    /// Number of active function calls
+    pub(crate) fn call_depth(&self) -> usize {
+        self.call_stack.len()
+    }
+
+    /// Attach standard input for `input()` to read from
+    pub fn set_stdin(&mut self, input: impl Into<InputSource>) {
+        self.stdin = Some(input.into());
+    }
+
+    /// Detach and return the standard input, including any unread lines
+    pub fn take_stdin(&mut self) -> Option<InputSource> {
+        self.stdin.take()
+    }
+
+    /// Pass script arguments, as `pyrust script.py -- <args>` does
+    ///
+    /// Programs read them with the `argc()` and `argv(i)` builtins. Since
+    /// values are integers, `argv(i)` parses argument `i` when it is read.
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.args = args;
+    }
+
+    /// The script arguments
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    /// Read one line of standard input, as `input()` does
+    ///
+    /// Fails with EOFError when no input is attached or it is exhausted.
+    pub fn read_input_line(&mut self) -> Result<String, RuntimeError> {
+        let line = match self.stdin.as_mut() {
+            Some(stdin) => stdin.read_line().map_err(|e| {
+                RuntimeError::new(
+                    ExceptionKind::RuntimeError,
+                    format!("Failed to read standard input: {}", e),
+                    self.ip,
+                )
+            })?,
+            None => None,
+        };
+
+        line.ok_or_else(|| {
+            RuntimeError::new(ExceptionKind::EOFError, "EOF when reading a line", self.ip)
+        })
+    }
+
+    /// Number of instructions executed by the most recent execute()
+    pub fn instructions_executed(&self) -> u64 {
+        self.instructions_executed
+    }
+
+    /// Resource usage of the current (or most recent) execution
+    pub fn stats(&self) -> ExecutionStats {
+        ExecutionStats {
+            instructions_executed: self.instructions_executed,
+            max_call_depth: self.max_call_depth,
+            peak_registers: self.peak_registers.max(self.window_extent()),
+            stdout_bytes: self.output_bytes - self.output_bytes_at_start,
+            allocations: self.frame_allocations,
         }
     }
 
+    /// Start counting resource usage from zero
+    fn reset_stats(&mut self) {
+        self.instructions_executed = 0;
+        self.max_call_depth = 0;
+        self.peak_registers = 0;
+        self.frame_allocations = 0;
+        self.output_bytes_at_start = self.output_bytes;
+    }
+
+    /// Register slots in use up to the highest valid register of the current window
+    fn window_extent(&self) -> usize {
+        let highest = self
+            .register_valid
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(_, word)| **word != 0)
+            .map(|(index, word)| index * 64 + 63 - word.leading_zeros() as usize);
+        highest.map_or(self.base, |reg| self.base + reg + 1)
+    }
+
     /// Check if a register is valid (has been set)
     #[inline]
     fn is_register_valid(&self, reg: u8) -> bool {
@@ -184,41 +932,60 @@ impl VM {
     #[inline]
     fn get_register(&self, reg: u8) -> Result<Value, RuntimeError> {
         if self.is_register_valid(reg) {
-            Ok(self.registers[reg as usize])
+            Ok(self.registers.get(self.base + reg as usize))
         } else {
-            Err(RuntimeError {
-                message: format!("Register {} is empty", reg),
-                instruction_index: self.ip,
-            })
+            Err(RuntimeError::new(
+                ExceptionKind::RuntimeError,
+                format!("Register {} is empty", reg),
+                self.ip,
+            ))
         }
     }
 
     /// Set a register value and mark it as valid
     #[inline]
     fn set_register(&mut self, reg: u8, value: Value) {
-        self.registers[reg as usize] = value;
+        self.registers.set(self.base + reg as usize, value);
         self.set_register_valid(reg);
     }
 
-    /// Save register state for function call (only saves registers [0..=max_reg])
-    fn save_register_state(&self, max_reg: u8) -> Vec<Value> {
-        let count = (max_reg as usize) + 1;
-        self.registers[0..count].to_vec()
+    /// Switch to a fresh, empty window of `register_count` registers
+    ///
+    /// The window starts just past the caller's highest valid register, so a
+    /// frame occupies only the registers it has written. Returns the caller's
+    /// base and validity bitmap for [`VM::leave_window`]. Only the bitmap is
+    /// reset; stale values in the window are unreachable until written, so
+    /// entering a window costs O(1) unless the file must grow.
+    #[inline]
+    fn enter_window(&mut self, register_count: usize) -> (usize, [u64; 4]) {
+        let caller = (self.base, self.register_valid);
+        self.base = self.window_extent();
+        self.registers.ensure_len(self.base + register_count);
+        self.register_valid = [0; 4];
+        caller
     }
 
-    /// Restore register state after function return
-    fn restore_register_state(
-        &mut self,
-        saved: Vec<Value>,
-        saved_valid: [u64; 4],
-        max_saved_reg: u8,
-    ) {
-        // Restore saved registers
-        let count = (max_saved_reg as usize) + 1;
-        self.registers[0..count].copy_from_slice(&saved[0..count]);
+    /// Estimated bytes held once a call with a window of `register_count`
+    /// registers is entered
+    fn frame_memory(&self, register_count: usize) -> usize {
+        let registers = self.window_extent() + register_count;
+        let frames = self.call_stack.len() + 1;
+        registers * std::mem::size_of::<Value>() + frames * std::mem::size_of::<CallFrame>()
+    }
+
+    /// Presize the top-level window from the program's register metadata
+    #[inline]
+    fn reserve_registers(&mut self, bytecode: &Bytecode) {
+        self.registers
+            .ensure_len(bytecode.metadata.max_register_used as usize + 1);
+    }
 
-        // Restore validity bitmap
-        self.register_valid = saved_valid;
+    /// Return to the caller's register window
+    #[inline]
+    fn leave_window(&mut self, caller_base: usize, caller_register_valid: [u64; 4]) {
+        self.peak_registers = self.peak_registers.max(self.window_extent());
+        self.base = caller_base;
+        self.register_valid = caller_register_valid;
     }
 
     /// Execute bytecode program
@@ -233,292 +1000,937 @@ impl VM {
     /// - Undefined variable access during LoadVar
     /// - Integer overflow during arithmetic operations
     pub fn execute(&mut self, bytecode: &Bytecode) -> Result<Option<Value>, RuntimeError> {
-        self.ip = 0; // Instruction pointer
+        self.restart();
+        self.reserve_registers(bytecode);
+        while !self
+            .run_instruction(bytecode)
+            .map_err(|e| self.with_traceback(e, bytecode))?
+        {}
+        Ok(self.result)
+    }
 
-        loop {
-            if self.ip >= bytecode.instructions.len() {
-                return Err(RuntimeError {
-                    message: "Instruction pointer out of bounds".to_string(),
-                    instruction_index: self.ip,
-                });
-            }
+    /// Execute bytecode like [`VM::execute`], also returning its resource usage
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pyrust::vm::VM;
+    /// use pyrust::{compiler, lexer, parser};
+    ///
+    /// let program = parser::parse(lexer::lex("print(1 + 2)").unwrap()).unwrap();
+    /// let bytecode = compiler::compile(&program).unwrap();
+    ///
+    /// let (_, stats) = VM::new().execute_with_stats(&bytecode).unwrap();
+    /// assert_eq!(stats.stdout_bytes, 2);
+    /// assert_eq!(stats.max_call_depth, 0);
+    /// ```
+    pub fn execute_with_stats(
+        &mut self,
+        bytecode: &Bytecode,
+    ) -> Result<(Option<Value>, ExecutionStats), RuntimeError> {
+        let result = self.execute(bytecode)?;
+        Ok((result, self.stats()))
+    }
 
-            let instruction = &bytecode.instructions[self.ip];
+    /// Run independent programs one after another on this VM
+    ///
+    /// The VM is [reset](VM::reset) before each program, so globals and
+    /// functions never leak between them, but the register file and frame
+    /// pools are reused. Each entry holds that program's formatted output or
+    /// its runtime error; a failing program does not stop the rest.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::sync::Arc;
+    /// use pyrust::vm::VM;
+    /// use pyrust::{compiler, lexer, parser};
+    ///
+    /// let compile = |src: &str| {
+    ///     let program = parser::parse(lexer::lex(src).unwrap()).unwrap();
+    ///     Arc::new(compiler::compile(&program).unwrap())
+    /// };
+    /// let programs = [compile("x = 2\nx * 3"), compile("1 // 0"), compile("print(x)")];
+    ///
+    /// let results = VM::new().execute_many(&programs);
+    /// assert_eq!(results[0].as_deref().unwrap(), "6");
+    /// assert!(results[1].is_err());
+    /// assert!(results[2].is_err());
+    /// ```
+    pub fn execute_many(
+        &mut self,
+        programs: &[Arc<Bytecode>],
+    ) -> Vec<Result<String, RuntimeError>> {
+        programs
+            .iter()
+            .map(|bytecode| {
+                self.reset();
+                let result = self.execute(bytecode)?;
+                Ok(self.format_output(result))
+            })
+            .collect()
+    }
 
-            match instruction {
-                Instruction::LoadConst {
-                    dest_reg,
-                    const_index,
-                } => {
-                    if *const_index >= bytecode.constants.len() {
-                        return Err(RuntimeError {
-                            message: format!("Constant index {} out of bounds", const_index),
-                            instruction_index: self.ip,
-                        });
-                    }
-                    let value = bytecode.constants[*const_index];
-                    self.set_register(*dest_reg, Value::Integer(value));
-                }
+    /// Rewind to the first instruction so [`VM::step`] can drive a fresh run
+    ///
+    /// Globals, functions, and buffered output are kept, while call frames left
+    /// by an interrupted run are dropped. Combine with
+    /// [`VM::reset_execution_state`] to also discard output.
+    pub fn restart(&mut self) {
+        self.abandon_calls();
+        self.ip = 0;
+        self.chunk = None;
+        self.chunk_start = 0;
+        self.reset_stats();
+        self.function_epoch += 1;
+        self.debug_stepping = false;
+        self.halted = false;
+    }
 
-                Instruction::LoadVar {
-                    dest_reg,
-                    var_name_index,
-                    var_id,
-                } => {
-                    if *var_name_index >= bytecode.var_names.len() {
-                        return Err(RuntimeError {
-                            message: format!(
-                                "Variable name index {} out of bounds",
-                                var_name_index
-                            ),
-                            instruction_index: self.ip,
-                        });
-                    }
-                    let var_name = &bytecode.var_names[*var_name_index];
+    /// Execute exactly one instruction at the current instruction pointer
+    ///
+    /// Lets external drivers advance execution incrementally. The instruction
+    /// budget, debugger, and trace hook apply exactly as in [`VM::execute`].
+    /// Stepping a halted program reports `Halted` again without running or
+    /// counting anything, until [`VM::restart`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pyrust::vm::{StepResult, VM};
+    /// use pyrust::value::Value;
+    /// use pyrust::{compiler, lexer, parser};
+    ///
+    /// let program = parser::parse(lexer::lex("1 + 2").unwrap()).unwrap();
+    /// let bytecode = compiler::compile(&program).unwrap();
+    ///
+    /// let mut vm = VM::new();
+    /// assert_eq!(vm.step(&bytecode).unwrap(), StepResult::Running { ip: 1 });
+    /// let result = loop {
+    ///     if let StepResult::Halted(result) = vm.step(&bytecode).unwrap() {
+    ///         break result;
+    ///     }
+    /// };
+    /// assert_eq!(result, Some(Value::Integer(3)));
+    /// ```
+    pub fn step(&mut self, bytecode: &Bytecode) -> Result<StepResult, RuntimeError> {
+        if !self.halted {
+            self.halted = self
+                .run_instruction(bytecode)
+                .map_err(|e| self.with_traceback(e, bytecode))?;
+        }
+        if self.halted {
+            Ok(StepResult::Halted(self.result))
+        } else {
+            Ok(StepResult::Running { ip: self.ip })
+        }
+    }
 
-                    // Check local scope first if we're in a function, then global scope
-                    let value = if let Some(frame) = self.call_stack.last() {
-                        frame
-                            .local_vars
-                            .get(var_id)
-                            .or_else(|| self.variables.get(var_id))
-                    } else {
-                        self.variables.get(var_id)
-                    };
+    /// Index of the next instruction to execute
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
 
-                    match value {
-                        Some(val) => {
-                            self.set_register(*dest_reg, *val);
-                        }
-                        None => {
-                            return Err(RuntimeError {
-                                message: format!("Undefined variable: {}", var_name),
-                                instruction_index: self.ip,
-                            });
+    /// Execute a program lowered with [`FlatCode::new`] using the fast dispatch loop
+    ///
+    /// Produces the same result, output, and errors as [`VM::execute`] on the
+    /// original bytecode. The instruction budget applies as usual; when a
+    /// trace hook or debugger is attached this delegates to [`VM::execute`] so
+    /// both keep seeing every instruction.
+    #[cfg(feature = "fast-dispatch")]
+    pub fn execute_flat(&mut self, code: &FlatCode) -> Result<Option<Value>, RuntimeError> {
+        let bytecode = code.bytecode();
+        if self.trace_hook.is_some() || self.debugger.is_some() {
+            return self.execute(bytecode);
+        }
+
+        self.restart();
+        self.reserve_registers(bytecode);
+        let ops = code.ops();
+        let constants = &bytecode.constants;
+
+        loop {
+            self.check_budget()
+                .map_err(|e| self.with_traceback(e, bytecode))?;
+
+            // An out-of-range ip takes the generic path, which reports it
+            let op = ops.get(self.ip).copied().unwrap_or_else(FlatOp::generic);
+
+            // `None` means the fast form cannot complete; the instruction is
+            // then run by `dispatch`, which also raises the canonical error
+            let halted = match op.opcode {
+                Opcode::LoadConst => {
+                    let value = constants[op.imm as usize];
+                    self.set_register(op.a, Value::Integer(value));
+                    Some(false)
+                }
+                Opcode::LoadVar => {
+                    let value = match self.call_stack.last() {
+                        Some(frame) => frame
+                            .local_vars
+                            .get(&op.imm)
+                            .or_else(|| self.variables.get(&op.imm)),
+                        None => self.variables.get(&op.imm),
+                    };
+                    value.copied().map(|value| {
+                        self.set_register(op.a, value);
+                        false
+                    })
+                }
+                Opcode::StoreVar if self.is_register_valid(op.a) => {
+                    let value = self.registers.get(self.base + op.a as usize);
+                    match self.call_stack.last_mut() {
+                        Some(frame) => frame.local_vars.insert(op.imm, value),
+                        None => self.variables.insert(op.imm, value),
+                    };
+                    Some(false)
+                }
+                Opcode::Add | Opcode::Sub | Opcode::Mul => {
+                    let result = match (self.flat_operand(op.b), self.flat_operand(op.c)) {
+                        (Some(left), Some(right)) => match op.opcode {
+                            Opcode::Add => left.checked_add(right),
+                            Opcode::Sub => left.checked_sub(right),
+                            _ => left.checked_mul(right),
+                        },
+                        _ => None,
+                    };
+                    result.map(|result| {
+                        self.set_register(op.a, Value::Integer(result));
+                        false
+                    })
+                }
+                Opcode::Neg | Opcode::Pos => {
+                    let result = self.flat_operand(op.b).and_then(|operand| {
+                        if op.opcode == Opcode::Neg {
+                            operand.checked_neg()
+                        } else {
+                            Some(operand)
                         }
-                    }
+                    });
+                    result.map(|result| {
+                        self.set_register(op.a, Value::Integer(result));
+                        false
+                    })
                 }
-
-                Instruction::StoreVar {
-                    var_name_index,
-                    var_id,
-                    src_reg,
-                } => {
-                    if *var_name_index >= bytecode.var_names.len() {
-                        return Err(RuntimeError {
-                            message: format!(
-                                "Variable name index {} out of bounds",
-                                var_name_index
-                            ),
-                            instruction_index: self.ip,
-                        });
+                Opcode::Move if self.is_register_valid(op.b) => {
+                    let value = self.registers.get(self.base + op.b as usize);
+                    self.set_register(op.a, value);
+                    Some(false)
+                }
+                Opcode::SetResult if self.is_register_valid(op.a) => {
+                    self.result = Some(self.registers.get(self.base + op.a as usize));
+                    Some(false)
+                }
+                Opcode::Jump => {
+                    self.ip = op.imm as usize;
+                    continue;
+                }
+                Opcode::JumpIfFalse | Opcode::JumpIfTrue if self.is_register_valid(op.a) => {
+                    let truthy = self.registers.get(self.base + op.a as usize).is_truthy();
+                    if truthy == (op.opcode == Opcode::JumpIfTrue) {
+                        self.ip = op.imm as usize;
+                        continue;
                     }
-                    let value = self.get_register(*src_reg)?;
-
-                    // Store in local scope if we're in a function, otherwise in global scope
-                    if let Some(frame) = self.call_stack.last_mut() {
-                        frame.local_vars.insert(*var_id, value);
-                    } else {
-                        self.variables.insert(*var_id, value);
+                    Some(false)
+                }
+                Opcode::Halt => Some(true),
+                _ => None,
+            };
+
+            match halted {
+                Some(true) => break,
+                Some(false) => self.ip += 1,
+                None => {
+                    if self
+                        .dispatch(bytecode)
+                        .map_err(|e| self.with_traceback(e, bytecode))?
+                    {
+                        break;
                     }
                 }
+            }
+        }
 
-                Instruction::BinaryOp {
-                    dest_reg,
-                    left_reg,
-                    op,
-                    right_reg,
-                } => {
-                    let left = self.get_register(*left_reg)?;
-                    let right = self.get_register(*right_reg)?;
-
-                    let result = left.binary_op(*op, &right).map_err(|mut e| {
-                        e.instruction_index = self.ip;
-                        e
-                    })?;
+        Ok(self.result)
+    }
 
-                    self.set_register(*dest_reg, result);
+    /// Integer in `reg`, or None if it is empty or holds None
+    #[cfg(feature = "fast-dispatch")]
+    #[inline(always)]
+    fn flat_operand(&self, reg: u8) -> Option<i64> {
+        if !self.is_register_valid(reg) {
+            return None;
+        }
+        self.registers.integer(self.base + reg as usize)
+    }
+
+    /// Count the next instruction against the budget and poll for cancellation
+    #[inline(always)]
+    fn check_budget(&mut self) -> Result<(), RuntimeError> {
+        if let Some(limit) = self.instruction_limit {
+            if self.instructions_executed >= limit {
+                return Err(RuntimeError::new(
+                    ExceptionKind::RuntimeError,
+                    format!("execution budget exceeded ({} instructions)", limit),
+                    self.ip,
+                )
+                .with_code(ErrorCode::InstructionLimit));
+            }
+        }
+        if self
+            .instructions_executed
+            .is_multiple_of(CANCEL_CHECK_INTERVAL)
+        {
+            if let Some(token) = &self.cancellation {
+                if token.is_cancelled() {
+                    return Err(RuntimeError::new(
+                        ExceptionKind::RuntimeError,
+                        CANCELLED_MESSAGE,
+                        self.ip,
+                    )
+                    .with_code(ErrorCode::Cancelled));
                 }
+            }
+        }
+        if let Some(watchdog) = &self.watchdog {
+            if self.instructions_executed > 0
+                && self.instructions_executed.is_multiple_of(watchdog.interval)
+            {
+                self.consult_watchdog()?;
+            }
+        }
+        self.instructions_executed += 1;
+        Ok(())
+    }
+
+    /// Report progress to the watchdog, failing if it asks to abort
+    #[cold]
+    fn consult_watchdog(&mut self) -> Result<(), RuntimeError> {
+        let event = WatchdogEvent {
+            ip: self.ip,
+            call_depth: self.call_stack.len(),
+            stats: self.stats(),
+        };
+        let Some(watchdog) = self.watchdog.as_mut() else {
+            return Ok(());
+        };
+        match (watchdog.hook)(&event) {
+            WatchdogAction::Continue => Ok(()),
+            WatchdogAction::Abort(reason) => Err(RuntimeError::new(
+                ExceptionKind::RuntimeError,
+                format!("execution aborted by watchdog: {}", reason),
+                self.ip,
+            )),
+        }
+    }
+
+    /// Run one instruction with budget, debugger, and trace handling
+    ///
+    /// Returns `Ok(true)` when the program halted.
+    #[inline(always)]
+    fn run_instruction(&mut self, bytecode: &Bytecode) -> Result<bool, RuntimeError> {
+        self.check_budget()?;
 
-                Instruction::UnaryOp {
-                    dest_reg,
-                    op,
-                    operand_reg,
-                } => {
-                    let operand = self.get_register(*operand_reg)?;
+        if self.trace_hook.is_none() && self.debugger.is_none() {
+            return self.dispatch(bytecode);
+        }
 
-                    let result = operand.unary_op(*op).map_err(|mut e| {
-                        e.instruction_index = self.ip;
-                        e
-                    })?;
+        if self.debugger.is_some() {
+            self.consult_debugger(bytecode)?;
+        }
+
+        // Return writes the caller's destination register, which is only
+        // known before its frame is popped
+        let ip = self.ip;
+        let return_dest = match bytecode.instruction(ip) {
+            Some(Instruction::Return { .. }) if self.trace_hook.is_some() => {
+                self.call_stack.last().map(|f| f.dest_reg)
+            }
+            _ => None,
+        };
+        let halted = self.dispatch(bytecode)?;
+        if self.trace_hook.is_some() {
+            self.emit_trace(ip, bytecode, return_dest);
+        }
+        Ok(halted)
+    }
+
+    /// Pause for the debugger if the next instruction is a breakpoint or being stepped to
+    fn consult_debugger(&mut self, bytecode: &Bytecode) -> Result<(), RuntimeError> {
+        let Some(mut debugger) = self.debugger.take() else {
+            return Ok(());
+        };
+
+        let ip = self.ip;
+        let action = if self.debug_stepping || debugger.is_breakpoint(ip) {
+            let state = PausedState {
+                vm: self,
+                bytecode,
+                ip,
+            };
+            Some(debugger.on_pause(&state))
+        } else {
+            None
+        };
+        self.debugger = Some(debugger);
+
+        match action {
+            Some(DebugAction::Continue) => self.debug_stepping = false,
+            Some(DebugAction::Step) => self.debug_stepping = true,
+            Some(DebugAction::Abort) => {
+                return Err(RuntimeError::new(
+                    ExceptionKind::RuntimeError,
+                    "Execution aborted by debugger",
+                    ip,
+                ))
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// Report the instruction just executed at `ip` to the trace hook
+    fn emit_trace(&mut self, ip: usize, bytecode: &Bytecode, return_dest: Option<u8>) {
+        let Some(instruction) = bytecode.instruction(ip) else {
+            return;
+        };
+        let written_reg = match instruction {
+            Instruction::LoadConst { dest_reg, .. }
+            | Instruction::LoadVar { dest_reg, .. }
+            | Instruction::BinaryOp { dest_reg, .. }
+            | Instruction::UnaryOp { dest_reg, .. } => Some(*dest_reg),
+            Instruction::Return { .. } => return_dest,
+            _ => None,
+        };
+        let written = written_reg.and_then(|reg| self.get_register(reg).ok().map(|v| (reg, v)));
+
+        let event = TraceEvent {
+            ip,
+            instruction,
+            call_depth: self.call_stack.len(),
+            written,
+            bytecode,
+        };
+        if let Some(hook) = self.trace_hook.as_mut() {
+            hook(&event);
+        }
+    }
+
+    /// Execute the instruction at `ip`, advancing `ip` to the next instruction
+    ///
+    /// Returns `Ok(true)` when the program halted.
+    #[inline(always)]
+    fn dispatch(&mut self, bytecode: &Bytecode) -> Result<bool, RuntimeError> {
+        let code = bytecode.chunk_code(self.chunk);
+        let Some(instruction) = code.get(self.ip.wrapping_sub(self.chunk_start)) else {
+            return Err(RuntimeError::new(
+                ExceptionKind::RuntimeError,
+                "Instruction pointer out of bounds",
+                self.ip,
+            ));
+        };
 
-                    self.set_register(*dest_reg, result);
+        match instruction {
+            Instruction::LoadConst {
+                dest_reg,
+                const_index,
+            } => {
+                if *const_index >= bytecode.constants.len() {
+                    return Err(RuntimeError::new(
+                        ExceptionKind::RuntimeError,
+                        format!("Constant index {} out of bounds", const_index),
+                        self.ip,
+                    ));
                 }
+                let value = bytecode.constants[*const_index];
+                self.set_register(*dest_reg, Value::Integer(value));
+            }
 
-                Instruction::Print { src_reg } => {
-                    let value = self.get_register(*src_reg)?;
-                    self.stdout.push_str(&format!("{}\n", value));
+            Instruction::LoadVar {
+                dest_reg,
+                var_name_index,
+                var_id,
+            } => {
+                if *var_name_index >= bytecode.var_names.len() {
+                    return Err(RuntimeError::new(
+                        ExceptionKind::RuntimeError,
+                        format!("Variable name index {} out of bounds", var_name_index),
+                        self.ip,
+                    ));
                 }
+                let var_name = &bytecode.var_names[*var_name_index];
+
+                // Check local scope first if we're in a function, then global scope
+                let value = if let Some(frame) = self.call_stack.last() {
+                    frame
+                        .local_vars
+                        .get(var_id)
+                        .or_else(|| self.variables.get(var_id))
+                } else {
+                    self.variables.get(var_id)
+                };
 
-                Instruction::SetResult { src_reg } => {
-                    let value = self.get_register(*src_reg)?;
-                    self.result = Some(value);
+                match value {
+                    Some(val) => {
+                        self.set_register(*dest_reg, *val);
+                    }
+                    None => {
+                        return Err(RuntimeError::new(
+                            ExceptionKind::NameError,
+                            format!("Undefined variable: {}", var_name),
+                            self.ip,
+                        ));
+                    }
                 }
+            }
 
-                Instruction::Halt => {
-                    break;
+            Instruction::StoreVar {
+                var_name_index,
+                var_id,
+                src_reg,
+            } => {
+                if *var_name_index >= bytecode.var_names.len() {
+                    return Err(RuntimeError::new(
+                        ExceptionKind::RuntimeError,
+                        format!("Variable name index {} out of bounds", var_name_index),
+                        self.ip,
+                    ));
                 }
+                let value = self.get_register(*src_reg)?;
 
-                Instruction::DefineFunction {
-                    name_index,
-                    param_count,
-                    body_start,
-                    body_len: _,
-                    max_register_used,
-                } => {
-                    // Store function metadata
-                    if *name_index >= bytecode.var_names.len() {
-                        return Err(RuntimeError {
-                            message: format!("Function name index {} out of bounds", name_index),
-                            instruction_index: self.ip,
-                        });
-                    }
-                    let func_name = bytecode.var_names[*name_index].clone();
-                    self.functions.insert(
-                        func_name,
-                        FunctionMetadata {
-                            param_count: *param_count,
-                            body_start: *body_start,
-                            max_register_used: Some(*max_register_used),
-                        },
-                    );
-                    // Don't skip - just register the function and continue
+                // Store in local scope if we're in a function, otherwise in global scope
+                if let Some(frame) = self.call_stack.last_mut() {
+                    frame.local_vars.insert(*var_id, value);
+                } else {
+                    self.variables.insert(*var_id, value);
+                }
+            }
+
+            Instruction::BinaryOp {
+                dest_reg,
+                left_reg,
+                op,
+                right_reg,
+            } => {
+                let left = self.get_register(*left_reg)?;
+                let right = self.get_register(*right_reg)?;
+
+                let result = left.binary_op(*op, &right).map_err(|mut e| {
+                    e.instruction_index = self.ip;
+                    e
+                })?;
+
+                self.set_register(*dest_reg, result);
+            }
+
+            Instruction::UnaryOp {
+                dest_reg,
+                op,
+                operand_reg,
+            } => {
+                let operand = self.get_register(*operand_reg)?;
+
+                let result = operand.unary_op(*op).map_err(|mut e| {
+                    e.instruction_index = self.ip;
+                    e
+                })?;
+
+                self.set_register(*dest_reg, result);
+            }
+
+            Instruction::Print { src_reg } => {
+                let value = self.get_register(*src_reg)?;
+                let line = format!("{}\n", value);
+                self.write_output(&line)?;
+            }
+
+            Instruction::SetResult { src_reg } => {
+                let value = self.get_register(*src_reg)?;
+                self.result = Some(value);
+            }
+
+            Instruction::Halt => {
+                return Ok(true);
+            }
+
+            Instruction::DefineFunction { chunk_index } => {
+                // Store function metadata
+                let Some(chunk) = bytecode.functions.get(*chunk_index) else {
+                    return Err(RuntimeError::new(
+                        ExceptionKind::RuntimeError,
+                        format!("Function chunk {} out of bounds", chunk_index),
+                        self.ip,
+                    ));
+                };
+                if chunk.name_index >= bytecode.var_names.len() {
+                    return Err(RuntimeError::new(
+                        ExceptionKind::RuntimeError,
+                        format!("Function name index {} out of bounds", chunk.name_index),
+                        self.ip,
+                    ));
                 }
+                let func_name = bytecode.var_names[chunk.name_index].clone();
+                self.functions.insert(
+                    func_name,
+                    FunctionMetadata {
+                        param_count: chunk.param_count,
+                        chunk: *chunk_index,
+                        start: bytecode.chunk_start(*chunk_index),
+                        register_count: chunk.max_register_used as usize + 1,
+                    },
+                );
+                self.function_epoch += 1;
+                // Don't skip - just register the function and continue
+            }
 
-                Instruction::Call {
-                    name_index,
-                    arg_count,
-                    first_arg_reg,
-                    dest_reg,
-                } => {
-                    // Look up function
-                    if *name_index >= bytecode.var_names.len() {
-                        return Err(RuntimeError {
-                            message: format!("Function name index {} out of bounds", name_index),
-                            instruction_index: self.ip,
-                        });
+            Instruction::Call {
+                name_index,
+                arg_count,
+                first_arg_reg,
+                dest_reg,
+            } => {
+                let ip = self.ip;
+                let cached = matches!(
+                    self.call_sites.get(ip),
+                    Some(Some(site)) if site.epoch == self.function_epoch
+                );
+                if !cached {
+                    if let Some(value) =
+                        self.call_builtin(bytecode, *name_index, *arg_count, *first_arg_reg)?
+                    {
+                        self.set_register(*dest_reg, value);
+                        self.ip += 1;
+                        return Ok(false);
                     }
-                    let func_name = &bytecode.var_names[*name_index];
-
-                    let func_meta = self
-                        .functions
-                        .get(func_name)
-                        .ok_or_else(|| RuntimeError {
-                            message: format!("Undefined function: {}", func_name),
-                            instruction_index: self.ip,
-                        })?
-                        .clone();
-
-                    // Check argument count
-                    if *arg_count != func_meta.param_count {
-                        return Err(RuntimeError {
-                            message: format!(
-                                "Function {} expects {} arguments, got {}",
-                                func_name, func_meta.param_count, arg_count
-                            ),
-                            instruction_index: self.ip,
-                        });
+                    let site = self.resolve_call_site(bytecode, *name_index, *arg_count)?;
+                    let code_len = bytecode.code_len();
+                    if self.call_sites.len() < code_len {
+                        self.call_sites.resize(code_len, None);
                     }
+                    self.call_sites[ip] = Some(site);
+                }
 
-                    // Create new call frame
-                    let mut local_vars = HashMap::new();
-
-                    // Pass arguments as local variables (param_0, param_1, ...)
-                    // IMPORTANT: Parameters are stored in local_vars HashMap, NOT in registers.
-                    // This prevents register allocation collisions when parameters are used
-                    // in multiple operations (e.g., x+1, x*2, x-3 all use the same parameter x).
-                    // The compiler allocates fresh registers for each LoadVar instruction,
-                    // ensuring that intermediate values don't overwrite parameter values.
-                    for i in 0..*arg_count {
-                        let arg_reg = (*first_arg_reg as usize + i as usize) as u8;
-                        let arg_value = self.get_register(arg_reg)?;
-
-                        // Find the var_id for param_i by looking up the name in bytecode
-                        let param_name = format!("param_{}", i);
-                        let param_var_id = bytecode
-                            .var_names
-                            .iter()
-                            .position(|n| n == &param_name)
-                            .and_then(|idx| bytecode.var_ids.get(idx).copied())
-                            .ok_or_else(|| RuntimeError {
-                                message: format!("Parameter {} not found in bytecode", param_name),
-                                instruction_index: self.ip,
-                            })?;
-
-                        local_vars.insert(param_var_id, arg_value);
+                if self.call_stack.len() >= MAX_CALL_DEPTH {
+                    return Err(RuntimeError::new(
+                        ExceptionKind::RecursionError,
+                        "maximum recursion depth exceeded",
+                        self.ip,
+                    ));
+                }
+                if let Some(limit) = self.call_depth_limit {
+                    if self.call_stack.len() >= limit {
+                        return Err(RuntimeError::new(
+                            ExceptionKind::RecursionError,
+                            format!("call depth limit exceeded ({} calls)", limit),
+                            self.ip,
+                        )
+                        .with_code(ErrorCode::CallDepthLimit));
                     }
+                }
 
-                    // Determine how many registers to save
-                    // Use metadata if available, otherwise save all (backward compat)
-                    let max_reg_to_save = func_meta.max_register_used.unwrap_or(255);
-                    let saved_registers = self.save_register_state(max_reg_to_save);
-                    let saved_register_valid = self.register_valid;
-
-                    let call_frame = CallFrame {
-                        return_address: self.ip + 1,
-                        local_vars,
-                        saved_registers,
-                        saved_register_valid,
-                        max_saved_reg: max_reg_to_save,
-                        dest_reg: *dest_reg,
-                    };
+                let site = self.call_sites[ip]
+                    .as_ref()
+                    .expect("call site resolved above");
+                let (chunk, start) = (site.function.chunk, site.function.start);
+                let register_count = site.function.register_count;
+                if let Some(limit) = self.memory_limit {
+                    if self.frame_memory(register_count.max(*arg_count as usize)) > limit {
+                        return Err(RuntimeError::new(
+                            ExceptionKind::RuntimeError,
+                            format!("memory limit exceeded ({} bytes)", limit),
+                            self.ip,
+                        )
+                        .with_code(ErrorCode::MemoryLimit));
+                    }
+                }
 
-                    self.call_stack.push(call_frame);
+                let arg_count = *arg_count as usize;
+                let first_arg_reg = *first_arg_reg as usize;
+                for arg in 0..arg_count {
+                    self.get_register((first_arg_reg + arg) as u8)?;
+                }
+                let local_vars = self.locals_pool.pop().unwrap_or_else(|| {
+                    self.frame_allocations += 1;
+                    HashMap::new()
+                });
 
-                    // Jump to function body
-                    self.ip = func_meta.body_start;
-                    continue; // Skip ip increment at end of loop
+                // The callee runs in its own register window; the caller's
+                // registers stay in place underneath it
+                let (caller_base, caller_register_valid) =
+                    self.enter_window(register_count.max(arg_count));
+                // Parameter i lives in the callee's register i
+                for arg in 0..arg_count {
+                    let value = self.registers.get(caller_base + first_arg_reg + arg);
+                    self.set_register(arg as u8, value);
                 }
 
-                Instruction::Return { has_value, src_reg } => {
-                    // CAPTURE return value BEFORE popping frame
-                    // This ensures parameters are still accessible if needed
-                    let return_value = if *has_value {
-                        let return_reg = src_reg.ok_or_else(|| RuntimeError {
-                            message: "Return with value but no register specified".to_string(),
-                            instruction_index: self.ip,
-                        })?;
-                        self.get_register(return_reg)?
-                    } else {
-                        Value::None
-                    };
+                let call_frame = CallFrame {
+                    return_address: self.ip + 1,
+                    return_chunk: self.chunk,
+                    return_chunk_start: self.chunk_start,
+                    local_vars,
+                    caller_base,
+                    caller_register_valid,
+                    dest_reg: *dest_reg,
+                };
+
+                self.call_stack.push(call_frame);
+                self.max_call_depth = self.max_call_depth.max(self.call_stack.len());
+
+                // Jump to function body
+                self.chunk = Some(chunk);
+                self.chunk_start = start;
+                self.ip = start;
+                return Ok(false); // Skip ip increment
+            }
 
-                    // NOW safe to pop call frame
-                    let call_frame = self.call_stack.pop().ok_or_else(|| RuntimeError {
-                        message: "Return outside of function".to_string(),
-                        instruction_index: self.ip,
+            Instruction::Return { has_value, src_reg } => {
+                // CAPTURE return value BEFORE popping frame
+                // This ensures parameters are still accessible if needed
+                let return_value = if *has_value {
+                    let return_reg = src_reg.ok_or_else(|| {
+                        RuntimeError::new(
+                            ExceptionKind::RuntimeError,
+                            "Return with value but no register specified",
+                            self.ip,
+                        )
                     })?;
+                    self.get_register(return_reg)?
+                } else {
+                    Value::None
+                };
+
+                // NOW safe to pop call frame
+                let call_frame = self.call_stack.pop().ok_or_else(|| {
+                    RuntimeError::new(
+                        ExceptionKind::RuntimeError,
+                        "Return outside of function",
+                        self.ip,
+                    )
+                })?;
+
+                // Back to the caller's register window
+                self.leave_window(call_frame.caller_base, call_frame.caller_register_valid);
+
+                // Set return value in destination register
+                self.set_register(call_frame.dest_reg, return_value);
+
+                // Jump back to return address
+                self.chunk = call_frame.return_chunk;
+                self.chunk_start = call_frame.return_chunk_start;
+                self.ip = call_frame.return_address;
+                self.recycle_locals(call_frame.local_vars);
+                return Ok(false); // Skip ip increment
+            }
 
-                    // Restore registers using optimized method
-                    self.restore_register_state(
-                        call_frame.saved_registers,
-                        call_frame.saved_register_valid,
-                        call_frame.max_saved_reg,
-                    );
+            Instruction::Jump { target } => {
+                self.ip = self.chunk_start + *target;
+                return Ok(false);
+            }
 
-                    // Set return value in destination register
-                    self.set_register(call_frame.dest_reg, return_value);
+            Instruction::JumpIfFalse { cond_reg, target } => {
+                if !self.get_register(*cond_reg)?.is_truthy() {
+                    self.ip = self.chunk_start + *target;
+                    return Ok(false);
+                }
+            }
 
-                    // Jump back to return address
-                    self.ip = call_frame.return_address;
-                    continue; // Skip ip increment at end of loop
+            Instruction::JumpIfTrue { cond_reg, target } => {
+                if self.get_register(*cond_reg)?.is_truthy() {
+                    self.ip = self.chunk_start + *target;
+                    return Ok(false);
                 }
             }
 
-            self.ip += 1;
+            Instruction::Move { dest_reg, src_reg } => {
+                let value = self.get_register(*src_reg)?;
+                self.set_register(*dest_reg, value);
+            }
         }
 
-        Ok(self.result)
+        self.ip += 1;
+        Ok(false)
+    }
+
+    /// Deliver one line of print output, enforcing the output limit
+    fn write_output(&mut self, line: &str) -> Result<(), RuntimeError> {
+        if self.output_truncated {
+            return Ok(());
+        }
+        if let Some(limit) = self.output_limit {
+            if self.output_bytes + line.len() > limit.max_bytes {
+                match limit.on_overflow {
+                    OutputOverflow::Error => {
+                        return Err(RuntimeError::new(
+                            ExceptionKind::RuntimeError,
+                            format!("output limit exceeded ({} bytes)", limit.max_bytes),
+                            self.ip,
+                        )
+                        .with_code(ErrorCode::OutputLimit));
+                    }
+                    OutputOverflow::Truncate => {
+                        self.output_truncated = true;
+                        let marker =
+                            format!("[output truncated after {} bytes]\n", self.output_bytes);
+                        self.emit_output(&marker);
+                        return Ok(());
+                    }
+                }
+            }
+        }
+        self.output_bytes += line.len();
+        self.emit_output(line);
+        Ok(())
+    }
+
+    /// Send text to the stdout sink, or buffer it
+    fn emit_output(&mut self, text: &str) {
+        match self.stdout_sink.as_mut() {
+            Some(sink) => sink(text),
+            None => self.stdout.push_str(text),
+        }
+    }
+
+    /// Run a call to a builtin function
+    ///
+    /// Returns None when the callee is not a builtin, or is one that the
+    /// program has shadowed by defining a function of the same name. The
+    /// builtins are:
+    ///
+    /// - `argc()`: the number of script arguments
+    /// - `argv(i)`: script argument `i` as an integer, counting from the end
+    ///   when negative; IndexError past either end, ValueError if the
+    ///   argument is not an integer
+    /// - `exit(n)`: stop the program with exit code `n` (0 when left out or
+    ///   None) by raising SystemExit
+    fn call_builtin(
+        &self,
+        bytecode: &Bytecode,
+        name_index: usize,
+        arg_count: u8,
+        first_arg_reg: u8,
+    ) -> Result<Option<Value>, RuntimeError> {
+        let Some(name) = bytecode.var_names.get(name_index) else {
+            return Ok(None);
+        };
+        let (min_args, max_args) = match name.as_str() {
+            "argc" => (0, 0),
+            "argv" => (1, 1),
+            "exit" => (0, 1),
+            _ => return Ok(None),
+        };
+        if self.functions.contains_key(name) {
+            return Ok(None);
+        }
+        let error = |kind, message: String| RuntimeError::new(kind, message, self.ip);
+        if !(min_args..=max_args).contains(&arg_count) {
+            let expected = if min_args == max_args {
+                max_args.to_string()
+            } else {
+                format!("{} to {}", min_args, max_args)
+            };
+            return Err(error(
+                ExceptionKind::TypeError,
+                format!(
+                    "Function {} expects {} arguments, got {}",
+                    name, expected, arg_count
+                ),
+            ));
+        }
+
+        match name.as_str() {
+            "argc" => Ok(Some(Value::Integer(self.args.len() as i64))),
+            "exit" => {
+                let code = match arg_count {
+                    0 => 0,
+                    _ => match self.get_register(first_arg_reg)? {
+                        Value::Integer(code) => code,
+                        Value::None => 0,
+                    },
+                };
+                Err(error(ExceptionKind::SystemExit, code.to_string()))
+            }
+            _ => {
+                let index = match self.get_register(first_arg_reg)? {
+                    Value::Integer(index) => index,
+                    Value::None => {
+                        return Err(error(
+                            ExceptionKind::TypeError,
+                            "argv index must be an integer, not None".to_string(),
+                        ))
+                    }
+                };
+                let position = if index < 0 {
+                    index.checked_add(self.args.len() as i64)
+                } else {
+                    Some(index)
+                };
+                let arg = position
+                    .and_then(|position| usize::try_from(position).ok())
+                    .and_then(|position| self.args.get(position))
+                    .ok_or_else(|| {
+                        error(
+                            ExceptionKind::IndexError,
+                            format!("argv index {} out of range", index),
+                        )
+                    })?;
+                match arg.trim().parse() {
+                    Ok(value) => Ok(Some(Value::Integer(value))),
+                    Err(_) => Err(error(
+                        ExceptionKind::ValueError,
+                        format!("argv({}) is not an integer: {:?}", index, arg),
+                    )),
+                }
+            }
+        }
+    }
+
+    /// Look up the callee of a Call and check its arity
+    ///
+    /// This is the slow path behind the call-site inline cache.
+    fn resolve_call_site(
+        &self,
+        bytecode: &Bytecode,
+        name_index: usize,
+        arg_count: u8,
+    ) -> Result<CallSite, RuntimeError> {
+        if name_index >= bytecode.var_names.len() {
+            return Err(RuntimeError::new(
+                ExceptionKind::RuntimeError,
+                format!("Function name index {} out of bounds", name_index),
+                self.ip,
+            ));
+        }
+        let func_name = &bytecode.var_names[name_index];
+
+        let function = self
+            .functions
+            .get(func_name)
+            .ok_or_else(|| {
+                RuntimeError::new(
+                    ExceptionKind::NameError,
+                    format!("Undefined function: {}", func_name),
+                    self.ip,
+                )
+            })?
+            .clone();
+
+        // Check argument count
+        if arg_count != function.param_count {
+            return Err(RuntimeError::new(
+                ExceptionKind::TypeError,
+                format!(
+                    "Function {} expects {} arguments, got {}",
+                    func_name, function.param_count, arg_count
+                ),
+                self.ip,
+            ));
+        }
+
+        Ok(CallSite {
+            epoch: self.function_epoch,
+            function,
+        })
     }
 
     /// Format output according to output specification
@@ -566,12 +1978,13 @@ impl Default for VM {
 mod tests {
     use super::*;
     use crate::ast::{BinaryOperator, UnaryOperator};
-    use crate::bytecode::BytecodeBuilder;
+    use crate::bytecode::{BytecodeBuilder, FunctionChunk};
 
     #[test]
     fn test_vm_new() {
         let vm = VM::new();
-        assert_eq!(vm.registers.len(), 256);
+        // Registers are allocated when a program declares how many it needs
+        assert_eq!(vm.registers.len(), 0);
         assert!(vm.variables.is_empty());
         assert!(vm.stdout.is_empty());
         assert!(vm.result.is_none());
@@ -587,7 +2000,7 @@ mod tests {
         let result = vm.execute(&bytecode).unwrap();
 
         assert_eq!(result, None);
-        assert_eq!(vm.registers[0], Value::Integer(42));
+        assert_eq!(vm.registers.get(0), Value::Integer(42));
     }
 
     #[test]
@@ -602,7 +2015,7 @@ mod tests {
         let result = vm.execute(&bytecode).unwrap();
 
         assert_eq!(result, None);
-        assert_eq!(vm.registers[1], Value::Integer(100));
+        assert_eq!(vm.registers.get(1), Value::Integer(100));
         assert_eq!(vm.variables.get(&1), Some(&Value::Integer(100)));
     }
 
@@ -617,7 +2030,7 @@ mod tests {
         let mut vm = VM::new();
         vm.execute(&bytecode).unwrap();
 
-        assert_eq!(vm.registers[2], Value::Integer(30));
+        assert_eq!(vm.registers.get(2), Value::Integer(30));
     }
 
     #[test]
@@ -630,7 +2043,7 @@ mod tests {
         let bytecode = builder.build();
         let mut vm = VM::new();
         vm.execute(&bytecode).unwrap();
-        assert_eq!(vm.registers[2], Value::Integer(13));
+        assert_eq!(vm.registers.get(2), Value::Integer(13));
 
         // Test Sub
         let mut builder = BytecodeBuilder::new();
@@ -640,7 +2053,7 @@ mod tests {
         let bytecode = builder.build();
         let mut vm = VM::new();
         vm.execute(&bytecode).unwrap();
-        assert_eq!(vm.registers[2], Value::Integer(7));
+        assert_eq!(vm.registers.get(2), Value::Integer(7));
 
         // Test Mul
         let mut builder = BytecodeBuilder::new();
@@ -650,7 +2063,7 @@ mod tests {
         let bytecode = builder.build();
         let mut vm = VM::new();
         vm.execute(&bytecode).unwrap();
-        assert_eq!(vm.registers[2], Value::Integer(30));
+        assert_eq!(vm.registers.get(2), Value::Integer(30));
 
         // Test Div
         let mut builder = BytecodeBuilder::new();
@@ -660,7 +2073,7 @@ mod tests {
         let bytecode = builder.build();
         let mut vm = VM::new();
         vm.execute(&bytecode).unwrap();
-        assert_eq!(vm.registers[2], Value::Integer(3));
+        assert_eq!(vm.registers.get(2), Value::Integer(3));
 
         // Test FloorDiv
         let mut builder = BytecodeBuilder::new();
@@ -670,7 +2083,7 @@ mod tests {
         let bytecode = builder.build();
         let mut vm = VM::new();
         vm.execute(&bytecode).unwrap();
-        assert_eq!(vm.registers[2], Value::Integer(3));
+        assert_eq!(vm.registers.get(2), Value::Integer(3));
 
         // Test Mod
         let mut builder = BytecodeBuilder::new();
@@ -680,7 +2093,7 @@ mod tests {
         let bytecode = builder.build();
         let mut vm = VM::new();
         vm.execute(&bytecode).unwrap();
-        assert_eq!(vm.registers[2], Value::Integer(1));
+        assert_eq!(vm.registers.get(2), Value::Integer(1));
     }
 
     #[test]
@@ -694,7 +2107,7 @@ mod tests {
         let mut vm = VM::new();
         vm.execute(&bytecode).unwrap();
 
-        assert_eq!(vm.registers[1], Value::Integer(-42));
+        assert_eq!(vm.registers.get(1), Value::Integer(-42));
 
         // Test Pos
         let mut builder = BytecodeBuilder::new();
@@ -705,7 +2118,7 @@ mod tests {
         let mut vm = VM::new();
         vm.execute(&bytecode).unwrap();
 
-        assert_eq!(vm.registers[1], Value::Integer(42));
+        assert_eq!(vm.registers.get(1), Value::Integer(42));
     }
 
     #[test]
@@ -884,7 +2297,29 @@ mod tests {
     #[test]
     fn test_vm_default() {
         let vm = VM::default();
-        assert_eq!(vm.registers.len(), 256);
+        assert_eq!(vm.registers.len(), 0);
+    }
+
+    #[test]
+    fn test_register_file_sized_from_metadata() {
+        let bytecode = compile_source("x = 1 + 2\nx");
+        let mut vm = VM::new();
+        vm.execute(&bytecode).unwrap();
+        assert_eq!(
+            vm.registers.len(),
+            bytecode.metadata.max_register_used as usize + 1
+        );
+
+        // Bytecode that understates its registers still runs
+        let mut builder = BytecodeBuilder::new();
+        builder.emit_load_const(0, 7);
+        builder.emit_load_const(40, 1);
+        builder.emit_binary_op(41, 0, BinaryOperator::Add, 40);
+        builder.emit_set_result(41);
+        let bytecode = builder.build();
+        assert_eq!(bytecode.metadata.max_register_used, 0);
+        assert_eq!(vm.execute(&bytecode).unwrap(), Some(Value::Integer(8)));
+        assert_eq!(vm.registers.len(), 42);
     }
 
     #[test]
@@ -898,7 +2333,7 @@ mod tests {
         let result = vm.execute(&bytecode);
 
         assert!(result.is_ok());
-        assert_eq!(vm.registers[255], Value::Integer(42));
+        assert_eq!(vm.registers.get(255), Value::Integer(42));
     }
 
     #[test]
@@ -932,7 +2367,7 @@ mod tests {
         let mut vm = VM::new();
         vm.execute(&bytecode).unwrap();
 
-        assert_eq!(vm.registers[4], Value::Integer(-8));
+        assert_eq!(vm.registers.get(4), Value::Integer(-8));
     }
 
     #[test]
@@ -946,7 +2381,7 @@ mod tests {
         let mut vm = VM::new();
         vm.execute(&bytecode).unwrap();
 
-        assert_eq!(vm.registers[2], Value::Integer(1));
+        assert_eq!(vm.registers.get(2), Value::Integer(1));
     }
 
     #[test]
@@ -985,31 +2420,48 @@ mod tests {
 
     // ========== Function Execution Tests ==========
 
+    /// Function chunk without a line table
+    fn chunk(
+        name_index: usize,
+        param_count: u8,
+        max_register_used: u8,
+        instructions: Vec<Instruction>,
+    ) -> FunctionChunk {
+        FunctionChunk {
+            name_index,
+            param_count,
+            max_register_used,
+            instructions,
+            line_table: Vec::new(),
+        }
+    }
+
     #[test]
     fn test_define_function_stores_metadata() {
         let instructions = vec![
-            Instruction::DefineFunction {
-                name_index: 0,
-                param_count: 2,
-                body_start: 2,
-                body_len: 1,
-                max_register_used: 1,
-            },
+            Instruction::DefineFunction { chunk_index: 0 },
             Instruction::Halt,
-            Instruction::Return {
+        ];
+        let functions = vec![chunk(
+            0,
+            2,
+            1,
+            vec![Instruction::Return {
                 has_value: false,
                 src_reg: None,
-            },
-        ];
+            }],
+        )];
 
         let bytecode = Bytecode {
             instructions,
+            functions,
             constants: vec![],
             var_names: vec!["foo".to_string()],
             var_ids: vec![1],
             metadata: crate::bytecode::CompilerMetadata {
                 max_register_used: 255,
             },
+            line_table: Vec::new(),
         };
 
         let mut vm = VM::new();
@@ -1019,7 +2471,64 @@ mod tests {
         assert!(vm.functions.contains_key("foo"));
         let func = &vm.functions["foo"];
         assert_eq!(func.param_count, 2);
-        assert_eq!(func.body_start, 2);
+        assert_eq!(func.start, 2);
+    }
+
+    #[test]
+    fn test_arguments_land_in_callee_registers() {
+        let bytecode = compile_source("def add(a, b):\n    return a + b\nadd(1, 2)");
+        // Parameters are never looked up by name
+        assert!(!bytecode
+            .instructions
+            .iter()
+            .any(|i| matches!(i, Instruction::LoadVar { .. })));
+
+        let mut vm = VM::new();
+        assert_eq!(vm.execute(&bytecode).unwrap(), Some(Value::Integer(3)));
+        let site = vm.call_sites.iter().flatten().next().unwrap();
+        assert_eq!(site.function.param_count, 2);
+    }
+
+    #[test]
+    fn test_empty_argument_register_reported_at_call() {
+        let instructions = vec![
+            Instruction::DefineFunction { chunk_index: 0 },
+            Instruction::LoadConst {
+                dest_reg: 1,
+                const_index: 0,
+            },
+            Instruction::Call {
+                name_index: 0,
+                arg_count: 1,
+                first_arg_reg: 0,
+                dest_reg: 1,
+            },
+            Instruction::Halt,
+        ];
+        let functions = vec![chunk(
+            0,
+            1,
+            0,
+            vec![Instruction::Return {
+                has_value: false,
+                src_reg: None,
+            }],
+        )];
+        let bytecode = Bytecode {
+            instructions,
+            functions,
+            constants: vec![10],
+            var_names: vec!["f".to_string()],
+            var_ids: vec![0],
+            metadata: crate::bytecode::CompilerMetadata {
+                max_register_used: 1,
+            },
+            line_table: Vec::new(),
+        };
+
+        let err = VM::new().execute(&bytecode).unwrap_err();
+        assert_eq!(err.message, "Register 0 is empty");
+        assert_eq!(err.instruction_index, 2);
     }
 
     #[test]
@@ -1036,13 +2545,7 @@ mod tests {
         // 5: Return
 
         let instructions = vec![
-            Instruction::DefineFunction {
-                name_index: 0,
-                param_count: 0,
-                body_start: 4,
-                body_len: 2,
-                max_register_used: 0,
-            },
+            Instruction::DefineFunction { chunk_index: 0 },
             Instruction::Call {
                 name_index: 0,
                 arg_count: 0,
@@ -1051,24 +2554,33 @@ mod tests {
             },
             Instruction::SetResult { src_reg: 5 },
             Instruction::Halt,
-            Instruction::LoadConst {
-                dest_reg: 10,
-                const_index: 0,
-            },
-            Instruction::Return {
-                has_value: true,
-                src_reg: Some(10),
-            },
         ];
+        let functions = vec![chunk(
+            0,
+            0,
+            0,
+            vec![
+                Instruction::LoadConst {
+                    dest_reg: 10,
+                    const_index: 0,
+                },
+                Instruction::Return {
+                    has_value: true,
+                    src_reg: Some(10),
+                },
+            ],
+        )];
 
         let bytecode = Bytecode {
             instructions,
+            functions,
             constants: vec![42],
             var_names: vec!["foo".to_string()],
             var_ids: vec![1],
             metadata: crate::bytecode::CompilerMetadata {
                 max_register_used: 255,
             },
+            line_table: Vec::new(),
         };
 
         let mut vm = VM::new();
@@ -1083,13 +2595,7 @@ mod tests {
         // result = double(21)
 
         let instructions = vec![
-            Instruction::DefineFunction {
-                name_index: 0,
-                param_count: 1,
-                body_start: 5,
-                body_len: 4,
-                max_register_used: 2,
-            },
+            Instruction::DefineFunction { chunk_index: 0 },
             Instruction::LoadConst {
                 dest_reg: 0,
                 const_index: 0,
@@ -1102,35 +2608,43 @@ mod tests {
             },
             Instruction::SetResult { src_reg: 5 },
             Instruction::Halt,
-            Instruction::LoadVar {
-                dest_reg: 10,
-                var_name_index: 1,
-                var_id: 2,
-            },
-            Instruction::LoadConst {
-                dest_reg: 11,
-                const_index: 1,
-            },
-            Instruction::BinaryOp {
-                dest_reg: 12,
-                left_reg: 10,
-                op: BinaryOperator::Mul,
-                right_reg: 11,
-            },
-            Instruction::Return {
-                has_value: true,
-                src_reg: Some(12),
-            },
         ];
+        let functions = vec![chunk(
+            0,
+            1,
+            2,
+            vec![
+                Instruction::Move {
+                    dest_reg: 10,
+                    src_reg: 0,
+                },
+                Instruction::LoadConst {
+                    dest_reg: 11,
+                    const_index: 1,
+                },
+                Instruction::BinaryOp {
+                    dest_reg: 12,
+                    left_reg: 10,
+                    op: BinaryOperator::Mul,
+                    right_reg: 11,
+                },
+                Instruction::Return {
+                    has_value: true,
+                    src_reg: Some(12),
+                },
+            ],
+        )];
 
         let bytecode = Bytecode {
             instructions,
+            functions,
             constants: vec![21, 2],
-            var_names: vec!["double".to_string(), "param_0".to_string()],
-            var_ids: vec![1, 2],
+            var_names: vec!["double".to_string()],
+            var_ids: vec![1],
             metadata: crate::bytecode::CompilerMetadata {
                 max_register_used: 255,
             },
+            line_table: Vec::new(),
         };
 
         let mut vm = VM::new();
@@ -1145,13 +2659,7 @@ mod tests {
         // result = add(10, 20)
 
         let instructions = vec![
-            Instruction::DefineFunction {
-                name_index: 0,
-                param_count: 2,
-                body_start: 6,
-                body_len: 4,
-                max_register_used: 3,
-            },
+            Instruction::DefineFunction { chunk_index: 0 },
             Instruction::LoadConst {
                 dest_reg: 0,
                 const_index: 0,
@@ -1168,40 +2676,43 @@ mod tests {
             },
             Instruction::SetResult { src_reg: 5 },
             Instruction::Halt,
-            Instruction::LoadVar {
-                dest_reg: 10,
-                var_name_index: 1,
-                var_id: 2,
-            },
-            Instruction::LoadVar {
-                dest_reg: 11,
-                var_name_index: 2,
-                var_id: 3,
-            },
-            Instruction::BinaryOp {
-                dest_reg: 12,
-                left_reg: 10,
-                op: BinaryOperator::Add,
-                right_reg: 11,
-            },
-            Instruction::Return {
-                has_value: true,
-                src_reg: Some(12),
-            },
         ];
+        let functions = vec![chunk(
+            0,
+            2,
+            3,
+            vec![
+                Instruction::Move {
+                    dest_reg: 10,
+                    src_reg: 0,
+                },
+                Instruction::Move {
+                    dest_reg: 11,
+                    src_reg: 1,
+                },
+                Instruction::BinaryOp {
+                    dest_reg: 12,
+                    left_reg: 10,
+                    op: BinaryOperator::Add,
+                    right_reg: 11,
+                },
+                Instruction::Return {
+                    has_value: true,
+                    src_reg: Some(12),
+                },
+            ],
+        )];
 
         let bytecode = Bytecode {
             instructions,
+            functions,
             constants: vec![10, 20],
-            var_names: vec![
-                "add".to_string(),
-                "param_0".to_string(),
-                "param_1".to_string(),
-            ],
-            var_ids: vec![1, 2, 3],
+            var_names: vec!["add".to_string()],
+            var_ids: vec![1],
             metadata: crate::bytecode::CompilerMetadata {
                 max_register_used: 255,
             },
+            line_table: Vec::new(),
         };
 
         let mut vm = VM::new();
@@ -1216,13 +2727,7 @@ mod tests {
         // result = no_return()
 
         let instructions = vec![
-            Instruction::DefineFunction {
-                name_index: 0,
-                param_count: 0,
-                body_start: 4,
-                body_len: 1,
-                max_register_used: 0,
-            },
+            Instruction::DefineFunction { chunk_index: 0 },
             Instruction::Call {
                 name_index: 0,
                 arg_count: 0,
@@ -1231,20 +2736,27 @@ mod tests {
             },
             Instruction::SetResult { src_reg: 5 },
             Instruction::Halt,
-            Instruction::Return {
+        ];
+        let functions = vec![chunk(
+            0,
+            0,
+            0,
+            vec![Instruction::Return {
                 has_value: false,
                 src_reg: None,
-            },
-        ];
+            }],
+        )];
 
         let bytecode = Bytecode {
             instructions,
+            functions,
             constants: vec![],
             var_names: vec!["no_return".to_string()],
             var_ids: vec![1],
             metadata: crate::bytecode::CompilerMetadata {
                 max_register_used: 255,
             },
+            line_table: Vec::new(),
         };
 
         let mut vm = VM::new();
@@ -1271,14 +2783,8 @@ mod tests {
                 var_id: 1,
                 src_reg: 0,
             },
-            Instruction::DefineFunction {
-                name_index: 1,
-                param_count: 0,
-                body_start: 6,
-                body_len: 4,
-                max_register_used: 2,
-            },
-            Instruction::Call {
+            Instruction::DefineFunction { chunk_index: 0 },
+            Instruction::Call {
                 name_index: 1,
                 arg_count: 0,
                 first_arg_reg: 0,
@@ -1286,34 +2792,43 @@ mod tests {
             },
             Instruction::SetResult { src_reg: 5 },
             Instruction::Halt,
-            Instruction::LoadConst {
-                dest_reg: 10,
-                const_index: 1,
-            },
-            Instruction::StoreVar {
-                var_name_index: 0,
-                var_id: 1,
-                src_reg: 10,
-            },
-            Instruction::LoadVar {
-                dest_reg: 11,
-                var_name_index: 0,
-                var_id: 1,
-            },
-            Instruction::Return {
-                has_value: true,
-                src_reg: Some(11),
-            },
         ];
+        let functions = vec![chunk(
+            1,
+            0,
+            2,
+            vec![
+                Instruction::LoadConst {
+                    dest_reg: 10,
+                    const_index: 1,
+                },
+                Instruction::StoreVar {
+                    var_name_index: 0,
+                    var_id: 1,
+                    src_reg: 10,
+                },
+                Instruction::LoadVar {
+                    dest_reg: 11,
+                    var_name_index: 0,
+                    var_id: 1,
+                },
+                Instruction::Return {
+                    has_value: true,
+                    src_reg: Some(11),
+                },
+            ],
+        )];
 
         let bytecode = Bytecode {
             instructions,
+            functions,
             constants: vec![5, 10],
             var_names: vec!["x".to_string(), "foo".to_string()],
             var_ids: vec![1, 2],
             metadata: crate::bytecode::CompilerMetadata {
                 max_register_used: 255,
             },
+            line_table: Vec::new(),
         };
 
         let mut vm = VM::new();
@@ -1332,20 +2847,8 @@ mod tests {
         // result = outer()
 
         let instructions = vec![
-            Instruction::DefineFunction {
-                name_index: 0,
-                param_count: 0,
-                body_start: 5,
-                body_len: 2,
-                max_register_used: 0,
-            },
-            Instruction::DefineFunction {
-                name_index: 1,
-                param_count: 0,
-                body_start: 7,
-                body_len: 4,
-                max_register_used: 1,
-            },
+            Instruction::DefineFunction { chunk_index: 0 },
+            Instruction::DefineFunction { chunk_index: 1 },
             Instruction::Call {
                 name_index: 1,
                 arg_count: 0,
@@ -1354,44 +2857,62 @@ mod tests {
             },
             Instruction::SetResult { src_reg: 5 },
             Instruction::Halt,
-            Instruction::LoadConst {
-                dest_reg: 10,
-                const_index: 0,
-            },
-            Instruction::Return {
-                has_value: true,
-                src_reg: Some(10),
-            },
-            Instruction::Call {
-                name_index: 0,
-                arg_count: 0,
-                first_arg_reg: 0,
-                dest_reg: 15,
-            },
-            Instruction::LoadConst {
-                dest_reg: 16,
-                const_index: 1,
-            },
-            Instruction::BinaryOp {
-                dest_reg: 17,
-                left_reg: 15,
-                op: BinaryOperator::Add,
-                right_reg: 16,
-            },
-            Instruction::Return {
-                has_value: true,
-                src_reg: Some(17),
-            },
+        ];
+        let functions = vec![
+            chunk(
+                0,
+                0,
+                0,
+                vec![
+                    Instruction::LoadConst {
+                        dest_reg: 10,
+                        const_index: 0,
+                    },
+                    Instruction::Return {
+                        has_value: true,
+                        src_reg: Some(10),
+                    },
+                ],
+            ),
+            chunk(
+                1,
+                0,
+                1,
+                vec![
+                    Instruction::Call {
+                        name_index: 0,
+                        arg_count: 0,
+                        first_arg_reg: 0,
+                        dest_reg: 15,
+                    },
+                    Instruction::LoadConst {
+                        dest_reg: 16,
+                        const_index: 1,
+                    },
+                    Instruction::BinaryOp {
+                        dest_reg: 17,
+                        left_reg: 15,
+                        op: BinaryOperator::Add,
+                        right_reg: 16,
+                    },
+                    Instruction::Return {
+                        has_value: true,
+                        src_reg: Some(17),
+                    },
+                ],
+            ),
         ];
 
         let bytecode = Bytecode {
             instructions,
+            functions,
             constants: vec![10, 5],
             var_names: vec!["inner".to_string(), "outer".to_string()],
             var_ids: vec![1, 2],
             metadata: crate::bytecode::CompilerMetadata {
                 max_register_used: 255,
             },
+            line_table: Vec::new(),
         };
 
         let mut vm = VM::new();
@@ -1406,13 +2927,7 @@ mod tests {
         // result = countdown(3)
 
         let instructions = vec![
-            Instruction::DefineFunction {
-                name_index: 0,
-                param_count: 1,
-                body_start: 5,
-                body_len: 2,
-                max_register_used: 1,
-            },
+            Instruction::DefineFunction { chunk_index: 0 },
             Instruction::LoadConst {
                 dest_reg: 0,
                 const_index: 0,
@@ -1425,25 +2940,33 @@ mod tests {
             },
             Instruction::SetResult { src_reg: 5 },
             Instruction::Halt,
-            Instruction::LoadVar {
-                dest_reg: 10,
-                var_name_index: 1,
-                var_id: 2,
-            },
-            Instruction::Return {
-                has_value: true,
-                src_reg: Some(10),
-            },
         ];
+        let functions = vec![chunk(
+            0,
+            1,
+            1,
+            vec![
+                Instruction::Move {
+                    dest_reg: 10,
+                    src_reg: 0,
+                },
+                Instruction::Return {
+                    has_value: true,
+                    src_reg: Some(10),
+                },
+            ],
+        )];
 
         let bytecode = Bytecode {
             instructions,
+            functions,
             constants: vec![3],
-            var_names: vec!["countdown".to_string(), "param_0".to_string()],
-            var_ids: vec![1, 2],
+            var_names: vec!["countdown".to_string()],
+            var_ids: vec![1],
             metadata: crate::bytecode::CompilerMetadata {
                 max_register_used: 255,
             },
+            line_table: Vec::new(),
         };
 
         let mut vm = VM::new();
@@ -1472,13 +2995,7 @@ mod tests {
     #[test]
     fn test_wrong_argument_count_error() {
         let instructions = vec![
-            Instruction::DefineFunction {
-                name_index: 0,
-                param_count: 2,
-                body_start: 4,
-                body_len: 1,
-                max_register_used: 1,
-            },
+            Instruction::DefineFunction { chunk_index: 0 },
             Instruction::LoadConst {
                 dest_reg: 0,
                 const_index: 0,
@@ -1490,20 +3007,27 @@ mod tests {
                 dest_reg: 5,
             },
             Instruction::Halt,
-            Instruction::Return {
+        ];
+        let functions = vec![chunk(
+            0,
+            2,
+            1,
+            vec![Instruction::Return {
                 has_value: false,
                 src_reg: None,
-            },
-        ];
+            }],
+        )];
 
         let bytecode = Bytecode {
             instructions,
+            functions,
             constants: vec![10],
             var_names: vec!["add".to_string()],
             var_ids: vec![1],
             metadata: crate::bytecode::CompilerMetadata {
                 max_register_used: 255,
             },
+            line_table: Vec::new(),
         };
 
         let mut vm = VM::new();
@@ -1547,13 +3071,7 @@ mod tests {
                 var_id: 1,
                 src_reg: 0,
             },
-            Instruction::DefineFunction {
-                name_index: 1,
-                param_count: 0,
-                body_start: 6,
-                body_len: 2,
-                max_register_used: 0,
-            },
+            Instruction::DefineFunction { chunk_index: 0 },
             Instruction::Call {
                 name_index: 1,
                 arg_count: 0,
@@ -1562,25 +3080,34 @@ mod tests {
             },
             Instruction::SetResult { src_reg: 5 },
             Instruction::Halt,
-            Instruction::LoadVar {
-                dest_reg: 10,
-                var_name_index: 0,
-                var_id: 1,
-            },
-            Instruction::Return {
-                has_value: true,
-                src_reg: Some(10),
-            },
         ];
+        let functions = vec![chunk(
+            1,
+            0,
+            0,
+            vec![
+                Instruction::LoadVar {
+                    dest_reg: 10,
+                    var_name_index: 0,
+                    var_id: 1,
+                },
+                Instruction::Return {
+                    has_value: true,
+                    src_reg: Some(10),
+                },
+            ],
+        )];
 
         let bytecode = Bytecode {
             instructions,
+            functions,
             constants: vec![100],
             var_names: vec!["global_var".to_string(), "foo".to_string()],
             var_ids: vec![1, 2],
             metadata: crate::bytecode::CompilerMetadata {
                 max_register_used: 255,
             },
+            line_table: Vec::new(),
         };
 
         let mut vm = VM::new();
@@ -1605,13 +3132,7 @@ mod tests {
                 var_id: 1,
                 src_reg: 0,
             },
-            Instruction::DefineFunction {
-                name_index: 1,
-                param_count: 0,
-                body_start: 5,
-                body_len: 4,
-                max_register_used: 2,
-            },
+            Instruction::DefineFunction { chunk_index: 0 },
             Instruction::Call {
                 name_index: 1,
                 arg_count: 0,
@@ -1619,34 +3140,43 @@ mod tests {
                 dest_reg: 5,
             },
             Instruction::Halt,
-            Instruction::LoadConst {
-                dest_reg: 10,
-                const_index: 1,
-            },
-            Instruction::StoreVar {
-                var_name_index: 0,
-                var_id: 1,
-                src_reg: 10,
-            },
-            Instruction::LoadVar {
-                dest_reg: 11,
-                var_name_index: 0,
-                var_id: 1,
-            },
-            Instruction::Return {
-                has_value: true,
-                src_reg: Some(11),
-            },
         ];
+        let functions = vec![chunk(
+            1,
+            0,
+            2,
+            vec![
+                Instruction::LoadConst {
+                    dest_reg: 10,
+                    const_index: 1,
+                },
+                Instruction::StoreVar {
+                    var_name_index: 0,
+                    var_id: 1,
+                    src_reg: 10,
+                },
+                Instruction::LoadVar {
+                    dest_reg: 11,
+                    var_name_index: 0,
+                    var_id: 1,
+                },
+                Instruction::Return {
+                    has_value: true,
+                    src_reg: Some(11),
+                },
+            ],
+        )];
 
         let bytecode = Bytecode {
             instructions,
+            functions,
             constants: vec![5, 10],
             var_names: vec!["x".to_string(), "foo".to_string()],
             var_ids: vec![1, 2],
             metadata: crate::bytecode::CompilerMetadata {
                 max_register_used: 255,
             },
+            line_table: Vec::new(),
         };
 
         let mut vm = VM::new();
@@ -1659,44 +3189,44 @@ mod tests {
     #[test]
     fn test_multiple_function_definitions() {
         let instructions = vec![
-            Instruction::DefineFunction {
-                name_index: 0,
-                param_count: 0,
-                body_start: 4,
-                body_len: 1,
-                max_register_used: 0,
-            },
-            Instruction::DefineFunction {
-                name_index: 1,
-                param_count: 1,
-                body_start: 5,
-                body_len: 1,
-                max_register_used: 0,
-            },
-            Instruction::DefineFunction {
-                name_index: 2,
-                param_count: 2,
-                body_start: 6,
-                body_len: 1,
-                max_register_used: 1,
-            },
+            Instruction::DefineFunction { chunk_index: 0 },
+            Instruction::DefineFunction { chunk_index: 1 },
+            Instruction::DefineFunction { chunk_index: 2 },
             Instruction::Halt,
-            Instruction::Return {
-                has_value: false,
-                src_reg: None,
-            },
-            Instruction::Return {
-                has_value: false,
-                src_reg: None,
-            },
-            Instruction::Return {
-                has_value: false,
-                src_reg: None,
-            },
+        ];
+        let functions = vec![
+            chunk(
+                0,
+                0,
+                0,
+                vec![Instruction::Return {
+                    has_value: false,
+                    src_reg: None,
+                }],
+            ),
+            chunk(
+                1,
+                1,
+                0,
+                vec![Instruction::Return {
+                    has_value: false,
+                    src_reg: None,
+                }],
+            ),
+            chunk(
+                2,
+                2,
+                1,
+                vec![Instruction::Return {
+                    has_value: false,
+                    src_reg: None,
+                }],
+            ),
         ];
 
         let bytecode = Bytecode {
             instructions,
+            functions,
             constants: vec![],
             var_names: vec![
                 "func1".to_string(),
@@ -1707,6 +3237,7 @@ mod tests {
             metadata: crate::bytecode::CompilerMetadata {
                 max_register_used: 255,
             },
+            line_table: Vec::new(),
         };
 
         let mut vm = VM::new();
@@ -1724,13 +3255,7 @@ mod tests {
         // result = calc()
 
         let instructions = vec![
-            Instruction::DefineFunction {
-                name_index: 0,
-                param_count: 0,
-                body_start: 4,
-                body_len: 6,
-                max_register_used: 6,
-            },
+            Instruction::DefineFunction { chunk_index: 0 },
             Instruction::Call {
                 name_index: 0,
                 arg_count: 0,
@@ -1739,44 +3264,53 @@ mod tests {
             },
             Instruction::SetResult { src_reg: 5 },
             Instruction::Halt,
-            Instruction::LoadConst {
-                dest_reg: 10,
-                const_index: 0,
-            },
-            Instruction::LoadConst {
-                dest_reg: 11,
-                const_index: 1,
-            },
-            Instruction::LoadConst {
-                dest_reg: 12,
-                const_index: 2,
-            },
-            Instruction::BinaryOp {
-                dest_reg: 13,
-                left_reg: 11,
-                op: BinaryOperator::Mul,
-                right_reg: 12,
-            },
-            Instruction::BinaryOp {
-                dest_reg: 14,
-                left_reg: 10,
-                op: BinaryOperator::Add,
-                right_reg: 13,
-            },
-            Instruction::Return {
-                has_value: true,
-                src_reg: Some(14),
-            },
         ];
+        let functions = vec![chunk(
+            0,
+            0,
+            6,
+            vec![
+                Instruction::LoadConst {
+                    dest_reg: 10,
+                    const_index: 0,
+                },
+                Instruction::LoadConst {
+                    dest_reg: 11,
+                    const_index: 1,
+                },
+                Instruction::LoadConst {
+                    dest_reg: 12,
+                    const_index: 2,
+                },
+                Instruction::BinaryOp {
+                    dest_reg: 13,
+                    left_reg: 11,
+                    op: BinaryOperator::Mul,
+                    right_reg: 12,
+                },
+                Instruction::BinaryOp {
+                    dest_reg: 14,
+                    left_reg: 10,
+                    op: BinaryOperator::Add,
+                    right_reg: 13,
+                },
+                Instruction::Return {
+                    has_value: true,
+                    src_reg: Some(14),
+                },
+            ],
+        )];
 
         let bytecode = Bytecode {
             instructions,
+            functions,
             constants: vec![10, 20, 2],
             var_names: vec!["calc".to_string()],
             var_ids: vec![1],
             metadata: crate::bytecode::CompilerMetadata {
                 max_register_used: 255,
             },
+            line_table: Vec::new(),
         };
 
         let mut vm = VM::new();
@@ -1791,13 +3325,7 @@ mod tests {
         // greet()
 
         let instructions = vec![
-            Instruction::DefineFunction {
-                name_index: 0,
-                param_count: 0,
-                body_start: 3,
-                body_len: 3,
-                max_register_used: 0,
-            },
+            Instruction::DefineFunction { chunk_index: 0 },
             Instruction::Call {
                 name_index: 0,
                 arg_count: 0,
@@ -1805,25 +3333,34 @@ mod tests {
                 dest_reg: 5,
             },
             Instruction::Halt,
-            Instruction::LoadConst {
-                dest_reg: 10,
-                const_index: 0,
-            },
-            Instruction::Print { src_reg: 10 },
-            Instruction::Return {
-                has_value: false,
-                src_reg: None,
-            },
         ];
+        let functions = vec![chunk(
+            0,
+            0,
+            0,
+            vec![
+                Instruction::LoadConst {
+                    dest_reg: 10,
+                    const_index: 0,
+                },
+                Instruction::Print { src_reg: 10 },
+                Instruction::Return {
+                    has_value: false,
+                    src_reg: None,
+                },
+            ],
+        )];
 
         let bytecode = Bytecode {
             instructions,
+            functions,
             constants: vec![42],
             var_names: vec!["greet".to_string()],
             var_ids: vec![1],
             metadata: crate::bytecode::CompilerMetadata {
                 max_register_used: 255,
             },
+            line_table: Vec::new(),
         };
 
         let mut vm = VM::new();
@@ -1839,13 +3376,7 @@ mod tests {
         // b = get_ten()
 
         let instructions = vec![
-            Instruction::DefineFunction {
-                name_index: 0,
-                param_count: 0,
-                body_start: 6,
-                body_len: 2,
-                max_register_used: 1,
-            },
+            Instruction::DefineFunction { chunk_index: 0 },
             Instruction::Call {
                 name_index: 0,
                 arg_count: 0,
@@ -1869,24 +3400,33 @@ mod tests {
                 src_reg: 6,
             },
             Instruction::Halt,
-            Instruction::LoadConst {
-                dest_reg: 10,
-                const_index: 0,
-            },
-            Instruction::Return {
-                has_value: true,
-                src_reg: Some(10),
-            },
         ];
+        let functions = vec![chunk(
+            0,
+            0,
+            1,
+            vec![
+                Instruction::LoadConst {
+                    dest_reg: 10,
+                    const_index: 0,
+                },
+                Instruction::Return {
+                    has_value: true,
+                    src_reg: Some(10),
+                },
+            ],
+        )];
 
         let bytecode = Bytecode {
             instructions,
+            functions,
             constants: vec![10],
             var_names: vec!["get_ten".to_string(), "a".to_string(), "b".to_string()],
             var_ids: vec![1, 2, 3],
             metadata: crate::bytecode::CompilerMetadata {
                 max_register_used: 255,
             },
+            line_table: Vec::new(),
         };
 
         let mut vm = VM::new();
@@ -1898,55 +3438,13 @@ mod tests {
 
     #[test]
     fn test_function_parameter_names_dont_leak() {
-        // def foo(x): return x
-        // foo(42)
-        // param_0 should not be in global scope
-
-        let instructions = vec![
-            Instruction::DefineFunction {
-                name_index: 0,
-                param_count: 1,
-                body_start: 4,
-                body_len: 2,
-                max_register_used: 0,
-            },
-            Instruction::LoadConst {
-                dest_reg: 0,
-                const_index: 0,
-            },
-            Instruction::Call {
-                name_index: 0,
-                arg_count: 1,
-                first_arg_reg: 0,
-                dest_reg: 5,
-            },
-            Instruction::Halt,
-            Instruction::LoadVar {
-                dest_reg: 10,
-                var_name_index: 1,
-                var_id: 2,
-            },
-            Instruction::Return {
-                has_value: true,
-                src_reg: Some(10),
-            },
-        ];
-
-        let bytecode = Bytecode {
-            instructions,
-            constants: vec![42],
-            var_names: vec!["foo".to_string(), "param_0".to_string()],
-            var_ids: vec![1, 2],
-            metadata: crate::bytecode::CompilerMetadata {
-                max_register_used: 255,
-            },
-        };
+        // Parameters live in the callee's registers, even when reassigned
+        let bytecode = compile_source("def foo(x):\n    x = x + 1\n    return x\nfoo(42)");
+        assert!(!bytecode.var_names.iter().any(|name| name == "x"));
 
         let mut vm = VM::new();
-        vm.execute(&bytecode).unwrap();
-
-        // param_0 should not exist in global scope
-        assert!(!vm.variables.contains_key(&2));
+        assert_eq!(vm.execute(&bytecode).unwrap(), Some(Value::Integer(43)));
+        assert!(vm.variables.is_empty());
     }
 
     #[test]
@@ -1958,13 +3456,7 @@ mod tests {
                 dest_reg: 0,
                 const_index: 0,
             },
-            Instruction::DefineFunction {
-                name_index: 0,
-                param_count: 0,
-                body_start: 4,
-                body_len: 2,
-                max_register_used: 0,
-            },
+            Instruction::DefineFunction { chunk_index: 0 },
             Instruction::Call {
                 name_index: 0,
                 arg_count: 0,
@@ -1972,33 +3464,42 @@ mod tests {
                 dest_reg: 5,
             },
             Instruction::Halt,
-            Instruction::LoadConst {
-                dest_reg: 0,
-                const_index: 1,
-            },
-            Instruction::Return {
-                has_value: true,
-                src_reg: Some(0),
-            },
         ];
+        let functions = vec![chunk(
+            0,
+            0,
+            0,
+            vec![
+                Instruction::LoadConst {
+                    dest_reg: 0,
+                    const_index: 1,
+                },
+                Instruction::Return {
+                    has_value: true,
+                    src_reg: Some(0),
+                },
+            ],
+        )];
 
         let bytecode = Bytecode {
             instructions,
+            functions,
             constants: vec![999, 42],
             var_names: vec!["foo".to_string()],
             var_ids: vec![1],
             metadata: crate::bytecode::CompilerMetadata {
                 max_register_used: 255,
             },
+            line_table: Vec::new(),
         };
 
         let mut vm = VM::new();
         vm.execute(&bytecode).unwrap();
 
         // Register 0 should be restored to 999
-        assert_eq!(vm.registers[0], Value::Integer(999));
+        assert_eq!(vm.registers.get(0), Value::Integer(999));
         // Register 5 should have the return value
-        assert_eq!(vm.registers[5], Value::Integer(42));
+        assert_eq!(vm.registers.get(5), Value::Integer(42));
     }
 
     #[test]
@@ -2007,13 +3508,7 @@ mod tests {
         // result = sum3(10, 20, 30)
 
         let instructions = vec![
-            Instruction::DefineFunction {
-                name_index: 0,
-                param_count: 3,
-                body_start: 7,
-                body_len: 6,
-                max_register_used: 5,
-            },
+            Instruction::DefineFunction { chunk_index: 0 },
             Instruction::LoadConst {
                 dest_reg: 0,
                 const_index: 0,
@@ -2034,52 +3529,53 @@ mod tests {
             },
             Instruction::SetResult { src_reg: 5 },
             Instruction::Halt,
-            Instruction::LoadVar {
-                dest_reg: 10,
-                var_name_index: 1,
-                var_id: 2,
-            },
-            Instruction::LoadVar {
-                dest_reg: 11,
-                var_name_index: 2,
-                var_id: 3,
-            },
-            Instruction::LoadVar {
-                dest_reg: 12,
-                var_name_index: 3,
-                var_id: 4,
-            },
-            Instruction::BinaryOp {
-                dest_reg: 13,
-                left_reg: 10,
-                op: BinaryOperator::Add,
-                right_reg: 11,
-            },
-            Instruction::BinaryOp {
-                dest_reg: 14,
-                left_reg: 13,
-                op: BinaryOperator::Add,
-                right_reg: 12,
-            },
-            Instruction::Return {
-                has_value: true,
-                src_reg: Some(14),
-            },
         ];
+        let functions = vec![chunk(
+            0,
+            3,
+            5,
+            vec![
+                Instruction::Move {
+                    dest_reg: 10,
+                    src_reg: 0,
+                },
+                Instruction::Move {
+                    dest_reg: 11,
+                    src_reg: 1,
+                },
+                Instruction::Move {
+                    dest_reg: 12,
+                    src_reg: 2,
+                },
+                Instruction::BinaryOp {
+                    dest_reg: 13,
+                    left_reg: 10,
+                    op: BinaryOperator::Add,
+                    right_reg: 11,
+                },
+                Instruction::BinaryOp {
+                    dest_reg: 14,
+                    left_reg: 13,
+                    op: BinaryOperator::Add,
+                    right_reg: 12,
+                },
+                Instruction::Return {
+                    has_value: true,
+                    src_reg: Some(14),
+                },
+            ],
+        )];
 
         let bytecode = Bytecode {
             instructions,
+            functions,
             constants: vec![10, 20, 30],
-            var_names: vec![
-                "sum3".to_string(),
-                "param_0".to_string(),
-                "param_1".to_string(),
-                "param_2".to_string(),
-            ],
-            var_ids: vec![1, 2, 3, 4],
+            var_names: vec!["sum3".to_string()],
+            var_ids: vec![1],
             metadata: crate::bytecode::CompilerMetadata {
                 max_register_used: 255,
             },
+            line_table: Vec::new(),
         };
 
         let mut vm = VM::new();
@@ -2094,13 +3590,7 @@ mod tests {
         // result = empty()
 
         let instructions = vec![
-            Instruction::DefineFunction {
-                name_index: 0,
-                param_count: 0,
-                body_start: 4,
-                body_len: 1,
-                max_register_used: 0,
-            },
+            Instruction::DefineFunction { chunk_index: 0 },
             Instruction::Call {
                 name_index: 0,
                 arg_count: 0,
@@ -2109,20 +3599,27 @@ mod tests {
             },
             Instruction::SetResult { src_reg: 5 },
             Instruction::Halt,
-            Instruction::Return {
+        ];
+        let functions = vec![chunk(
+            0,
+            0,
+            0,
+            vec![Instruction::Return {
                 has_value: false,
                 src_reg: None,
-            },
-        ];
+            }],
+        )];
 
         let bytecode = Bytecode {
             instructions,
+            functions,
             constants: vec![],
             var_names: vec!["empty".to_string()],
             var_ids: vec![1],
             metadata: crate::bytecode::CompilerMetadata {
                 max_register_used: 255,
             },
+            line_table: Vec::new(),
         };
 
         let mut vm = VM::new();
@@ -2137,13 +3634,7 @@ mod tests {
         // result = get_five() + 10
 
         let instructions = vec![
-            Instruction::DefineFunction {
-                name_index: 0,
-                param_count: 0,
-                body_start: 6,
-                body_len: 2,
-                max_register_used: 1,
-            },
+            Instruction::DefineFunction { chunk_index: 0 },
             Instruction::Call {
                 name_index: 0,
                 arg_count: 0,
@@ -2162,24 +3653,33 @@ mod tests {
             },
             Instruction::SetResult { src_reg: 7 },
             Instruction::Halt,
-            Instruction::LoadConst {
-                dest_reg: 10,
-                const_index: 0,
-            },
-            Instruction::Return {
-                has_value: true,
-                src_reg: Some(10),
-            },
         ];
+        let functions = vec![chunk(
+            0,
+            0,
+            1,
+            vec![
+                Instruction::LoadConst {
+                    dest_reg: 10,
+                    const_index: 0,
+                },
+                Instruction::Return {
+                    has_value: true,
+                    src_reg: Some(10),
+                },
+            ],
+        )];
 
         let bytecode = Bytecode {
             instructions,
+            functions,
             constants: vec![5, 10],
             var_names: vec!["get_five".to_string()],
             var_ids: vec![1],
             metadata: crate::bytecode::CompilerMetadata {
                 max_register_used: 255,
             },
+            line_table: Vec::new(),
         };
 
         let mut vm = VM::new();
@@ -2193,39 +3693,41 @@ mod tests {
         // Define function twice, second definition should win
 
         let instructions = vec![
-            Instruction::DefineFunction {
-                name_index: 0,
-                param_count: 0,
-                body_start: 3,
-                body_len: 1,
-                max_register_used: 0,
-            },
-            Instruction::DefineFunction {
-                name_index: 0,
-                param_count: 1,
-                body_start: 4,
-                body_len: 1,
-                max_register_used: 0,
-            },
+            Instruction::DefineFunction { chunk_index: 0 },
+            Instruction::DefineFunction { chunk_index: 1 },
             Instruction::Halt,
-            Instruction::Return {
-                has_value: false,
-                src_reg: None,
-            },
-            Instruction::Return {
-                has_value: false,
-                src_reg: None,
-            },
+        ];
+        let functions = vec![
+            chunk(
+                0,
+                0,
+                0,
+                vec![Instruction::Return {
+                    has_value: false,
+                    src_reg: None,
+                }],
+            ),
+            chunk(
+                0,
+                1,
+                0,
+                vec![Instruction::Return {
+                    has_value: false,
+                    src_reg: None,
+                }],
+            ),
         ];
 
         let bytecode = Bytecode {
             instructions,
+            functions,
             constants: vec![],
             var_names: vec!["foo".to_string()],
             var_ids: vec![1],
             metadata: crate::bytecode::CompilerMetadata {
                 max_register_used: 255,
             },
+            line_table: Vec::new(),
         };
 
         let mut vm = VM::new();
@@ -2233,7 +3735,7 @@ mod tests {
 
         let func = &vm.functions["foo"];
         assert_eq!(func.param_count, 1);
-        assert_eq!(func.body_start, 4);
+        assert_eq!(func.start, 4);
     }
 
     #[test]
@@ -2244,27 +3746,9 @@ mod tests {
         // result = f3()  # Should be 3
 
         let instructions = vec![
-            Instruction::DefineFunction {
-                name_index: 0,
-                param_count: 0,
-                body_start: 6,
-                body_len: 2,
-                max_register_used: 1,
-            },
-            Instruction::DefineFunction {
-                name_index: 1,
-                param_count: 0,
-                body_start: 8,
-                body_len: 4,
-                max_register_used: 1,
-            },
-            Instruction::DefineFunction {
-                name_index: 2,
-                param_count: 0,
-                body_start: 12,
-                body_len: 4,
-                max_register_used: 1,
-            },
+            Instruction::DefineFunction { chunk_index: 0 },
+            Instruction::DefineFunction { chunk_index: 1 },
+            Instruction::DefineFunction { chunk_index: 2 },
             Instruction::Call {
                 name_index: 2,
                 arg_count: 0,
@@ -2273,64 +3757,89 @@ mod tests {
             },
             Instruction::SetResult { src_reg: 5 },
             Instruction::Halt,
-            Instruction::LoadConst {
-                dest_reg: 10,
-                const_index: 0,
-            },
-            Instruction::Return {
-                has_value: true,
-                src_reg: Some(10),
-            },
-            Instruction::Call {
-                name_index: 0,
-                arg_count: 0,
-                first_arg_reg: 0,
-                dest_reg: 15,
-            },
-            Instruction::LoadConst {
-                dest_reg: 16,
-                const_index: 0,
-            },
-            Instruction::BinaryOp {
-                dest_reg: 17,
-                left_reg: 15,
-                op: BinaryOperator::Add,
-                right_reg: 16,
-            },
-            Instruction::Return {
-                has_value: true,
-                src_reg: Some(17),
-            },
-            Instruction::Call {
-                name_index: 1,
-                arg_count: 0,
-                first_arg_reg: 0,
-                dest_reg: 20,
-            },
-            Instruction::LoadConst {
-                dest_reg: 21,
-                const_index: 0,
-            },
-            Instruction::BinaryOp {
-                dest_reg: 22,
-                left_reg: 20,
-                op: BinaryOperator::Add,
-                right_reg: 21,
-            },
-            Instruction::Return {
-                has_value: true,
-                src_reg: Some(22),
-            },
+        ];
+        let functions = vec![
+            chunk(
+                0,
+                0,
+                1,
+                vec![
+                    Instruction::LoadConst {
+                        dest_reg: 10,
+                        const_index: 0,
+                    },
+                    Instruction::Return {
+                        has_value: true,
+                        src_reg: Some(10),
+                    },
+                ],
+            ),
+            chunk(
+                1,
+                0,
+                1,
+                vec![
+                    Instruction::Call {
+                        name_index: 0,
+                        arg_count: 0,
+                        first_arg_reg: 0,
+                        dest_reg: 15,
+                    },
+                    Instruction::LoadConst {
+                        dest_reg: 16,
+                        const_index: 0,
+                    },
+                    Instruction::BinaryOp {
+                        dest_reg: 17,
+                        left_reg: 15,
+                        op: BinaryOperator::Add,
+                        right_reg: 16,
+                    },
+                    Instruction::Return {
+                        has_value: true,
+                        src_reg: Some(17),
+                    },
+                ],
+            ),
+            chunk(
+                2,
+                0,
+                1,
+                vec![
+                    Instruction::Call {
+                        name_index: 1,
+                        arg_count: 0,
+                        first_arg_reg: 0,
+                        dest_reg: 20,
+                    },
+                    Instruction::LoadConst {
+                        dest_reg: 21,
+                        const_index: 0,
+                    },
+                    Instruction::BinaryOp {
+                        dest_reg: 22,
+                        left_reg: 20,
+                        op: BinaryOperator::Add,
+                        right_reg: 21,
+                    },
+                    Instruction::Return {
+                        has_value: true,
+                        src_reg: Some(22),
+                    },
+                ],
+            ),
         ];
 
         let bytecode = Bytecode {
             instructions,
+            functions,
             constants: vec![1],
             var_names: vec!["f1".to_string(), "f2".to_string(), "f3".to_string()],
             var_ids: vec![1, 2, 3],
             metadata: crate::bytecode::CompilerMetadata {
                 max_register_used: 255,
             },
+            line_table: Vec::new(),
         };
 
         let mut vm = VM::new();
@@ -2346,13 +3855,7 @@ mod tests {
         // Testing that None value is properly stored
 
         let instructions = vec![
-            Instruction::DefineFunction {
-                name_index: 0,
-                param_count: 0,
-                body_start: 4,
-                body_len: 1,
-                max_register_used: 0,
-            },
+            Instruction::DefineFunction { chunk_index: 0 },
             Instruction::Call {
                 name_index: 0,
                 arg_count: 0,
@@ -2365,20 +3868,27 @@ mod tests {
                 src_reg: 5,
             },
             Instruction::Halt,
-            Instruction::Return {
+        ];
+        let functions = vec![chunk(
+            0,
+            0,
+            0,
+            vec![Instruction::Return {
                 has_value: false,
                 src_reg: None,
-            },
-        ];
+            }],
+        )];
 
         let bytecode = Bytecode {
             instructions,
+            functions,
             constants: vec![],
             var_names: vec!["empty".to_string(), "x".to_string()],
             var_ids: vec![1, 2],
             metadata: crate::bytecode::CompilerMetadata {
                 max_register_used: 255,
             },
+            line_table: Vec::new(),
         };
 
         let mut vm = VM::new();
@@ -2397,20 +3907,8 @@ mod tests {
         // result = a + b
 
         let instructions = vec![
-            Instruction::DefineFunction {
-                name_index: 0,
-                param_count: 0,
-                body_start: 11,
-                body_len: 2,
-                max_register_used: 0,
-            },
-            Instruction::DefineFunction {
-                name_index: 1,
-                param_count: 0,
-                body_start: 13,
-                body_len: 2,
-                max_register_used: 0,
-            },
+            Instruction::DefineFunction { chunk_index: 0 },
+            Instruction::DefineFunction { chunk_index: 1 },
             Instruction::Call {
                 name_index: 0,
                 arg_count: 0,
@@ -2451,26 +3949,43 @@ mod tests {
             },
             Instruction::SetResult { src_reg: 9 },
             Instruction::Halt,
-            Instruction::LoadConst {
-                dest_reg: 10,
-                const_index: 0,
-            },
-            Instruction::Return {
-                has_value: true,
-                src_reg: Some(10),
-            },
-            Instruction::LoadConst {
-                dest_reg: 11,
-                const_index: 1,
-            },
-            Instruction::Return {
-                has_value: true,
-                src_reg: Some(11),
-            },
+        ];
+        let functions = vec![
+            chunk(
+                0,
+                0,
+                0,
+                vec![
+                    Instruction::LoadConst {
+                        dest_reg: 10,
+                        const_index: 0,
+                    },
+                    Instruction::Return {
+                        has_value: true,
+                        src_reg: Some(10),
+                    },
+                ],
+            ),
+            chunk(
+                1,
+                0,
+                0,
+                vec![
+                    Instruction::LoadConst {
+                        dest_reg: 11,
+                        const_index: 1,
+                    },
+                    Instruction::Return {
+                        has_value: true,
+                        src_reg: Some(11),
+                    },
+                ],
+            ),
         ];
 
         let bytecode = Bytecode {
             instructions,
+            functions,
             constants: vec![1, 2],
             var_names: vec![
                 "get_one".to_string(),
@@ -2482,6 +3997,7 @@ mod tests {
             metadata: crate::bytecode::CompilerMetadata {
                 max_register_used: 255,
             },
+            line_table: Vec::new(),
         };
 
         let mut vm = VM::new();
@@ -2498,13 +4014,7 @@ mod tests {
         // result = complex_calc(2, 3, 4)  # Should be 20
 
         let instructions = vec![
-            Instruction::DefineFunction {
-                name_index: 0,
-                param_count: 3,
-                body_start: 7,
-                body_len: 6,
-                max_register_used: 5,
-            },
+            Instruction::DefineFunction { chunk_index: 0 },
             Instruction::LoadConst {
                 dest_reg: 0,
                 const_index: 0,
@@ -2525,52 +4035,53 @@ mod tests {
             },
             Instruction::SetResult { src_reg: 5 },
             Instruction::Halt,
-            Instruction::LoadVar {
-                dest_reg: 10,
-                var_name_index: 1,
-                var_id: 2,
-            },
-            Instruction::LoadVar {
-                dest_reg: 11,
-                var_name_index: 2,
-                var_id: 3,
-            },
-            Instruction::BinaryOp {
-                dest_reg: 12,
-                left_reg: 10,
-                op: BinaryOperator::Add,
-                right_reg: 11,
-            },
-            Instruction::LoadVar {
-                dest_reg: 13,
-                var_name_index: 3,
-                var_id: 4,
-            },
-            Instruction::BinaryOp {
-                dest_reg: 14,
-                left_reg: 12,
-                op: BinaryOperator::Mul,
-                right_reg: 13,
-            },
-            Instruction::Return {
-                has_value: true,
-                src_reg: Some(14),
-            },
         ];
+        let functions = vec![chunk(
+            0,
+            3,
+            5,
+            vec![
+                Instruction::Move {
+                    dest_reg: 10,
+                    src_reg: 0,
+                },
+                Instruction::Move {
+                    dest_reg: 11,
+                    src_reg: 1,
+                },
+                Instruction::BinaryOp {
+                    dest_reg: 12,
+                    left_reg: 10,
+                    op: BinaryOperator::Add,
+                    right_reg: 11,
+                },
+                Instruction::Move {
+                    dest_reg: 13,
+                    src_reg: 2,
+                },
+                Instruction::BinaryOp {
+                    dest_reg: 14,
+                    left_reg: 12,
+                    op: BinaryOperator::Mul,
+                    right_reg: 13,
+                },
+                Instruction::Return {
+                    has_value: true,
+                    src_reg: Some(14),
+                },
+            ],
+        )];
 
         let bytecode = Bytecode {
             instructions,
+            functions,
             constants: vec![2, 3, 4],
-            var_names: vec![
-                "complex_calc".to_string(),
-                "param_0".to_string(),
-                "param_1".to_string(),
-                "param_2".to_string(),
-            ],
-            var_ids: vec![1, 2, 3, 4],
+            var_names: vec!["complex_calc".to_string()],
+            var_ids: vec![1],
             metadata: crate::bytecode::CompilerMetadata {
                 max_register_used: 255,
             },
+            line_table: Vec::new(),
         };
 
         let mut vm = VM::new();
@@ -2585,13 +4096,7 @@ mod tests {
         // result = subtract(-10, -5)  # Should be -5
 
         let instructions = vec![
-            Instruction::DefineFunction {
-                name_index: 0,
-                param_count: 2,
-                body_start: 6,
-                body_len: 4,
-                max_register_used: 3,
-            },
+            Instruction::DefineFunction { chunk_index: 0 },
             Instruction::LoadConst {
                 dest_reg: 0,
                 const_index: 0,
@@ -2608,40 +4113,43 @@ mod tests {
             },
             Instruction::SetResult { src_reg: 5 },
             Instruction::Halt,
-            Instruction::LoadVar {
-                dest_reg: 10,
-                var_name_index: 1,
-                var_id: 2,
-            },
-            Instruction::LoadVar {
-                dest_reg: 11,
-                var_name_index: 2,
-                var_id: 3,
-            },
-            Instruction::BinaryOp {
-                dest_reg: 12,
-                left_reg: 10,
-                op: BinaryOperator::Sub,
-                right_reg: 11,
-            },
-            Instruction::Return {
-                has_value: true,
-                src_reg: Some(12),
-            },
         ];
+        let functions = vec![chunk(
+            0,
+            2,
+            3,
+            vec![
+                Instruction::Move {
+                    dest_reg: 10,
+                    src_reg: 0,
+                },
+                Instruction::Move {
+                    dest_reg: 11,
+                    src_reg: 1,
+                },
+                Instruction::BinaryOp {
+                    dest_reg: 12,
+                    left_reg: 10,
+                    op: BinaryOperator::Sub,
+                    right_reg: 11,
+                },
+                Instruction::Return {
+                    has_value: true,
+                    src_reg: Some(12),
+                },
+            ],
+        )];
 
         let bytecode = Bytecode {
             instructions,
+            functions,
             constants: vec![-10, -5],
-            var_names: vec![
-                "subtract".to_string(),
-                "param_0".to_string(),
-                "param_1".to_string(),
-            ],
-            var_ids: vec![1, 2, 3],
+            var_names: vec!["subtract".to_string()],
+            var_ids: vec![1],
             metadata: crate::bytecode::CompilerMetadata {
                 max_register_used: 255,
             },
+            line_table: Vec::new(),
         };
 
         let mut vm = VM::new();
@@ -2661,41 +4169,11 @@ mod tests {
         // result = level5()  # Should be 14
 
         let instructions = vec![
-            Instruction::DefineFunction {
-                name_index: 0,
-                param_count: 0,
-                body_start: 8,
-                body_len: 2,
-                max_register_used: 0,
-            },
-            Instruction::DefineFunction {
-                name_index: 1,
-                param_count: 0,
-                body_start: 10,
-                body_len: 4,
-                max_register_used: 1,
-            },
-            Instruction::DefineFunction {
-                name_index: 2,
-                param_count: 0,
-                body_start: 14,
-                body_len: 4,
-                max_register_used: 1,
-            },
-            Instruction::DefineFunction {
-                name_index: 3,
-                param_count: 0,
-                body_start: 18,
-                body_len: 4,
-                max_register_used: 1,
-            },
-            Instruction::DefineFunction {
-                name_index: 4,
-                param_count: 0,
-                body_start: 22,
-                body_len: 4,
-                max_register_used: 0,
-            },
+            Instruction::DefineFunction { chunk_index: 0 },
+            Instruction::DefineFunction { chunk_index: 1 },
+            Instruction::DefineFunction { chunk_index: 2 },
+            Instruction::DefineFunction { chunk_index: 3 },
+            Instruction::DefineFunction { chunk_index: 4 },
             Instruction::Call {
                 name_index: 4,
                 arg_count: 0,
@@ -2704,98 +4182,136 @@ mod tests {
             },
             Instruction::SetResult { src_reg: 5 },
             Instruction::Halt,
-            Instruction::LoadConst {
-                dest_reg: 10,
-                const_index: 0,
-            },
-            Instruction::Return {
-                has_value: true,
-                src_reg: Some(10),
-            },
-            Instruction::Call {
-                name_index: 0,
-                arg_count: 0,
-                first_arg_reg: 0,
-                dest_reg: 15,
-            },
-            Instruction::LoadConst {
-                dest_reg: 16,
-                const_index: 1,
-            },
-            Instruction::BinaryOp {
-                dest_reg: 17,
-                left_reg: 15,
-                op: BinaryOperator::Add,
-                right_reg: 16,
-            },
-            Instruction::Return {
-                has_value: true,
-                src_reg: Some(17),
-            },
-            Instruction::Call {
-                name_index: 1,
-                arg_count: 0,
-                first_arg_reg: 0,
-                dest_reg: 20,
-            },
-            Instruction::LoadConst {
-                dest_reg: 21,
-                const_index: 1,
-            },
-            Instruction::BinaryOp {
-                dest_reg: 22,
-                left_reg: 20,
-                op: BinaryOperator::Add,
-                right_reg: 21,
-            },
-            Instruction::Return {
-                has_value: true,
-                src_reg: Some(22),
-            },
-            Instruction::Call {
-                name_index: 2,
-                arg_count: 0,
-                first_arg_reg: 0,
-                dest_reg: 25,
-            },
-            Instruction::LoadConst {
-                dest_reg: 26,
-                const_index: 1,
-            },
-            Instruction::BinaryOp {
-                dest_reg: 27,
-                left_reg: 25,
-                op: BinaryOperator::Add,
-                right_reg: 26,
-            },
-            Instruction::Return {
-                has_value: true,
-                src_reg: Some(27),
-            },
-            Instruction::Call {
-                name_index: 3,
-                arg_count: 0,
-                first_arg_reg: 0,
-                dest_reg: 30,
-            },
-            Instruction::LoadConst {
-                dest_reg: 31,
-                const_index: 1,
-            },
-            Instruction::BinaryOp {
-                dest_reg: 32,
-                left_reg: 30,
-                op: BinaryOperator::Add,
-                right_reg: 31,
-            },
-            Instruction::Return {
-                has_value: true,
-                src_reg: Some(32),
-            },
+        ];
+        let functions = vec![
+            chunk(
+                0,
+                0,
+                0,
+                vec![
+                    Instruction::LoadConst {
+                        dest_reg: 10,
+                        const_index: 0,
+                    },
+                    Instruction::Return {
+                        has_value: true,
+                        src_reg: Some(10),
+                    },
+                ],
+            ),
+            chunk(
+                1,
+                0,
+                1,
+                vec![
+                    Instruction::Call {
+                        name_index: 0,
+                        arg_count: 0,
+                        first_arg_reg: 0,
+                        dest_reg: 15,
+                    },
+                    Instruction::LoadConst {
+                        dest_reg: 16,
+                        const_index: 1,
+                    },
+                    Instruction::BinaryOp {
+                        dest_reg: 17,
+                        left_reg: 15,
+                        op: BinaryOperator::Add,
+                        right_reg: 16,
+                    },
+                    Instruction::Return {
+                        has_value: true,
+                        src_reg: Some(17),
+                    },
+                ],
+            ),
+            chunk(
+                2,
+                0,
+                1,
+                vec![
+                    Instruction::Call {
+                        name_index: 1,
+                        arg_count: 0,
+                        first_arg_reg: 0,
+                        dest_reg: 20,
+                    },
+                    Instruction::LoadConst {
+                        dest_reg: 21,
+                        const_index: 1,
+                    },
+                    Instruction::BinaryOp {
+                        dest_reg: 22,
+                        left_reg: 20,
+                        op: BinaryOperator::Add,
+                        right_reg: 21,
+                    },
+                    Instruction::Return {
+                        has_value: true,
+                        src_reg: Some(22),
+                    },
+                ],
+            ),
+            chunk(
+                3,
+                0,
+                1,
+                vec![
+                    Instruction::Call {
+                        name_index: 2,
+                        arg_count: 0,
+                        first_arg_reg: 0,
+                        dest_reg: 25,
+                    },
+                    Instruction::LoadConst {
+                        dest_reg: 26,
+                        const_index: 1,
+                    },
+                    Instruction::BinaryOp {
+                        dest_reg: 27,
+                        left_reg: 25,
+                        op: BinaryOperator::Add,
+                        right_reg: 26,
+                    },
+                    Instruction::Return {
+                        has_value: true,
+                        src_reg: Some(27),
+                    },
+                ],
+            ),
+            chunk(
+                4,
+                0,
+                0,
+                vec![
+                    Instruction::Call {
+                        name_index: 3,
+                        arg_count: 0,
+                        first_arg_reg: 0,
+                        dest_reg: 30,
+                    },
+                    Instruction::LoadConst {
+                        dest_reg: 31,
+                        const_index: 1,
+                    },
+                    Instruction::BinaryOp {
+                        dest_reg: 32,
+                        left_reg: 30,
+                        op: BinaryOperator::Add,
+                        right_reg: 31,
+                    },
+                    Instruction::Return {
+                        has_value: true,
+                        src_reg: Some(32),
+                    },
+                ],
+            ),
         ];
 
         let bytecode = Bytecode {
             instructions,
+            functions,
             constants: vec![10, 1],
             var_names: vec![
                 "level1".to_string(),
@@ -2808,6 +4324,7 @@ mod tests {
             metadata: crate::bytecode::CompilerMetadata {
                 max_register_used: 255,
             },
+            line_table: Vec::new(),
         };
 
         let mut vm = VM::new();
@@ -2823,13 +4340,7 @@ mod tests {
         // Testing that zero is properly distinguished from None
 
         let instructions = vec![
-            Instruction::DefineFunction {
-                name_index: 0,
-                param_count: 0,
-                body_start: 4,
-                body_len: 2,
-                max_register_used: 0,
-            },
+            Instruction::DefineFunction { chunk_index: 0 },
             Instruction::Call {
                 name_index: 0,
                 arg_count: 0,
@@ -2838,24 +4349,33 @@ mod tests {
             },
             Instruction::SetResult { src_reg: 5 },
             Instruction::Halt,
-            Instruction::LoadConst {
-                dest_reg: 10,
-                const_index: 0,
-            },
-            Instruction::Return {
-                has_value: true,
-                src_reg: Some(10),
-            },
         ];
+        let functions = vec![chunk(
+            0,
+            0,
+            0,
+            vec![
+                Instruction::LoadConst {
+                    dest_reg: 10,
+                    const_index: 0,
+                },
+                Instruction::Return {
+                    has_value: true,
+                    src_reg: Some(10),
+                },
+            ],
+        )];
 
         let bytecode = Bytecode {
             instructions,
+            functions,
             constants: vec![0],
             var_names: vec!["return_zero".to_string()],
             var_ids: vec![1],
             metadata: crate::bytecode::CompilerMetadata {
                 max_register_used: 255,
             },
+            line_table: Vec::new(),
         };
 
         let mut vm = VM::new();
@@ -3202,4 +4722,766 @@ mod tests {
         assert_eq!(err.message, "Register 42 is empty");
         assert_eq!(err.instruction_index, 0); // IP is 0 initially
     }
+
+    #[test]
+    fn test_jump_if_true_loop() {
+        // n = 3; loop: print(n); n = n - 1; if n: goto loop
+        let mut builder = BytecodeBuilder::new();
+        let top = builder.new_label();
+        builder.emit_load_const(0, 3);
+        builder.emit_store_var("n", 1, 0);
+        builder.bind_label(top);
+        builder.emit_load_var(0, "n", 1);
+        builder.emit_print(0);
+        builder.emit_load_const(1, 1);
+        builder.emit_binary_op(0, 0, BinaryOperator::Sub, 1);
+        builder.emit_store_var("n", 1, 0);
+        builder.emit_jump_if_true(0, top);
+        let bytecode = builder.build();
+
+        let mut vm = VM::new();
+        vm.execute(&bytecode).unwrap();
+        assert_eq!(vm.stdout.as_str(), "3\n2\n1\n");
+    }
+
+    #[test]
+    fn test_jump_if_false_and_jump() {
+        // if 0: print(1) else: print(2)
+        let mut builder = BytecodeBuilder::new();
+        let else_branch = builder.new_label();
+        let end = builder.new_label();
+        builder.emit_load_const(0, 0);
+        builder.emit_jump_if_false(0, else_branch);
+        builder.emit_load_const(1, 1);
+        builder.emit_print(1);
+        builder.emit_jump(end);
+        builder.bind_label(else_branch);
+        builder.emit_load_const(1, 2);
+        builder.emit_print(1);
+        builder.bind_label(end);
+        let bytecode = builder.build();
+
+        let mut vm = VM::new();
+        vm.execute(&bytecode).unwrap();
+        assert_eq!(vm.stdout.as_str(), "2\n");
+    }
+
+    #[test]
+    fn test_jump_on_none_condition_is_falsy() {
+        let bytecode = Bytecode {
+            instructions: vec![
+                Instruction::JumpIfTrue {
+                    cond_reg: 0,
+                    target: 3,
+                },
+                Instruction::LoadConst {
+                    dest_reg: 1,
+                    const_index: 0,
+                },
+                Instruction::SetResult { src_reg: 1 },
+                Instruction::Halt,
+            ],
+            functions: Vec::new(),
+            constants: vec![7],
+            var_names: vec![],
+            var_ids: vec![],
+            metadata: crate::bytecode::CompilerMetadata {
+                max_register_used: 1,
+            },
+            line_table: Vec::new(),
+        };
+
+        let mut vm = VM::new();
+        vm.set_register(0, Value::None);
+        assert_eq!(vm.execute(&bytecode).unwrap(), Some(Value::Integer(7)));
+    }
+
+    #[test]
+    fn test_instruction_limit_stops_infinite_loop() {
+        let mut builder = BytecodeBuilder::new();
+        let top = builder.new_label();
+        builder.bind_label(top);
+        builder.emit_jump(top);
+        let bytecode = builder.build();
+
+        let mut vm = VM::new();
+        vm.set_instruction_limit(Some(1000));
+        let err = vm.execute(&bytecode).unwrap_err();
+        assert!(err.message.contains("execution budget exceeded"));
+        assert_eq!(err.kind, ExceptionKind::RuntimeError);
+        assert_eq!(vm.instructions_executed(), 1000);
+    }
+
+    #[test]
+    fn test_execution_stats_report_usage() {
+        let bytecode = compile_source(
+            "def inner(a):\n    return a + 1\n\
+             def outer(a):\n    return inner(a) * 2\n\
+             print(outer(1))\nprint(outer(2))",
+        );
+
+        let mut vm = VM::new();
+        let (result, stats) = vm.execute_with_stats(&bytecode).unwrap();
+        assert_eq!(result, None);
+        assert_eq!(stats.instructions_executed, vm.instructions_executed());
+        assert_eq!(stats.max_call_depth, 2);
+        assert_eq!(stats.stdout_bytes, "4\n6\n".len());
+        // Two frames deep on the first call; the second call reuses their maps
+        assert_eq!(stats.allocations, 2);
+        // Three frames packed on top of each other, far from a full window
+        assert!(stats.peak_registers >= 3);
+        assert!(stats.peak_registers < REGISTER_WINDOW);
+
+        // Counting restarts with each execution
+        let (_, again) = vm.execute_with_stats(&bytecode).unwrap();
+        assert_eq!(again.stdout_bytes, stats.stdout_bytes);
+        assert_eq!(again.allocations, 0);
+    }
+
+    #[test]
+    fn test_execution_stats_available_after_error() {
+        let bytecode = compile_source("x = 1\nprint(x)\ny");
+        let mut vm = VM::new();
+        assert!(vm.execute(&bytecode).is_err());
+
+        let stats = vm.stats();
+        assert_eq!(stats.stdout_bytes, 2);
+        assert_eq!(stats.max_call_depth, 0);
+        assert!(stats.peak_registers >= 1);
+    }
+
+    #[test]
+    fn test_execute_many_isolates_programs() {
+        let programs = [
+            "def f(a):\n    return a * 2\nx = 5\nprint(f(x))",
+            "f(1)",
+            "print(1)\n1 // 0",
+            "x = 7\nx",
+        ]
+        .map(|source| Arc::new(compile_source(source)));
+
+        let mut vm = VM::new();
+        let results = vm.execute_many(&programs);
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0].as_deref().unwrap(), "10\n");
+        // Functions from the first program are gone
+        assert!(results[1]
+            .as_ref()
+            .unwrap_err()
+            .message
+            .contains("Undefined function"));
+        assert_eq!(
+            results[2].as_ref().unwrap_err().kind,
+            ExceptionKind::ZeroDivisionError
+        );
+        // Output printed by a failed program does not leak into the next one
+        assert_eq!(results[3].as_deref().unwrap(), "7");
+    }
+
+    #[test]
+    fn test_cancellation_token_stops_infinite_loop() {
+        let mut builder = BytecodeBuilder::new();
+        let top = builder.new_label();
+        builder.bind_label(top);
+        builder.emit_jump(top);
+        let bytecode = builder.build();
+
+        let token = CancellationToken::new();
+        let canceller = {
+            let token = token.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(std::time::Duration::from_millis(20));
+                token.cancel();
+            })
+        };
+
+        let mut vm = VM::new();
+        vm.set_cancellation_token(token);
+        let err = vm.execute(&bytecode).unwrap_err();
+        canceller.join().unwrap();
+        assert_eq!(err.message, CANCELLED_MESSAGE);
+        assert_eq!(err.kind, ExceptionKind::RuntimeError);
+    }
+
+    #[test]
+    fn test_cancelled_token_stops_before_first_instruction() {
+        let mut builder = BytecodeBuilder::new();
+        builder.emit_load_const(0, 1);
+        builder.emit_set_result(0);
+        let bytecode = builder.build();
+
+        let token = CancellationToken::new();
+        token.cancel();
+        let mut vm = VM::new();
+        vm.set_cancellation_token(token);
+        assert!(vm.execute(&bytecode).is_err());
+        assert_eq!(vm.instructions_executed(), 0);
+
+        // The VM runs normally again once the token is detached
+        vm.clear_cancellation_token();
+        assert_eq!(vm.execute(&bytecode).unwrap(), Some(Value::Integer(1)));
+    }
+
+    #[test]
+    fn test_watchdog_sees_progress_and_can_abort() {
+        use std::sync::Mutex;
+
+        let bytecode = compile_source(
+            "def inc(n):\n    return n + 1\n\
+             x = inc(1)\nx = inc(x)\nx = inc(x)\nx = inc(x)\nx",
+        );
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&events);
+
+        let mut vm = VM::new();
+        vm.set_watchdog(3, move |event| {
+            seen.lock().unwrap().push(*event);
+            WatchdogAction::Continue
+        });
+        assert_eq!(vm.execute(&bytecode).unwrap(), Some(Value::Integer(5)));
+
+        let events = events.lock().unwrap();
+        let total = vm.instructions_executed();
+        assert_eq!(events.len() as u64, (total - 1) / 3);
+        for (i, event) in events.iter().enumerate() {
+            assert_eq!(event.stats.instructions_executed, 3 * (i as u64 + 1));
+        }
+        assert!(events.iter().any(|event| event.call_depth == 1));
+
+        // Aborting stops the run where the hook was consulted
+        vm.set_watchdog(5, |event| {
+            if event.call_depth > 0 {
+                WatchdogAction::Abort(format!("call at {}", event.ip))
+            } else {
+                WatchdogAction::Continue
+            }
+        });
+        let err = vm.execute(&bytecode).unwrap_err();
+        assert!(err
+            .message
+            .starts_with("execution aborted by watchdog: call at"));
+        assert_eq!(err.kind, ExceptionKind::RuntimeError);
+
+        vm.clear_watchdog();
+        assert_eq!(vm.execute(&bytecode).unwrap(), Some(Value::Integer(5)));
+    }
+
+    #[test]
+    fn test_instruction_limit_allows_program_within_budget() {
+        let mut builder = BytecodeBuilder::new();
+        builder.emit_load_const(0, 42);
+        builder.emit_set_result(0);
+        let bytecode = builder.build();
+
+        // LoadConst, SetResult, Halt
+        let mut vm = VM::new();
+        vm.set_instruction_limit(Some(3));
+        assert_eq!(vm.execute(&bytecode).unwrap(), Some(Value::Integer(42)));
+        assert_eq!(vm.instructions_executed(), 3);
+
+        vm.set_instruction_limit(Some(2));
+        assert!(vm.execute(&bytecode).is_err());
+    }
+
+    #[test]
+    fn test_stdout_sink_receives_each_print() {
+        use std::sync::{Arc, Mutex};
+
+        let mut builder = BytecodeBuilder::new();
+        builder.emit_load_const(0, 1);
+        builder.emit_print(0);
+        builder.emit_load_const(0, 2);
+        builder.emit_print(0);
+        builder.emit_set_result(0);
+        let bytecode = builder.build();
+
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let captured = Arc::clone(&lines);
+        let mut vm = VM::new();
+        vm.set_stdout_sink(move |line| captured.lock().unwrap().push(line.to_string()));
+        let result = vm.execute(&bytecode).unwrap();
+
+        assert_eq!(*lines.lock().unwrap(), vec!["1\n", "2\n"]);
+        assert!(vm.stdout.is_empty());
+        assert_eq!(vm.format_output(result), "2");
+
+        vm.clear_stdout_sink();
+        vm.execute(&bytecode).unwrap();
+        assert_eq!(vm.stdout.as_str(), "1\n2\n");
+    }
+
+    #[test]
+    fn test_read_input_line() {
+        let mut vm = VM::new();
+        let err = vm.read_input_line().unwrap_err();
+        assert_eq!(err.kind, ExceptionKind::EOFError);
+
+        vm.set_stdin("first\nsecond");
+        assert_eq!(vm.read_input_line().unwrap(), "first");
+
+        let mut rest = vm.take_stdin().unwrap();
+        assert_eq!(rest.read_line().unwrap(), Some("second".to_string()));
+        assert_eq!(
+            vm.read_input_line().unwrap_err().message,
+            "EOF when reading a line"
+        );
+    }
+
+    #[test]
+    fn test_argv_builtins() {
+        let mut vm = VM::new();
+        vm.set_args(vec!["7".to_string(), "-2".to_string(), "x".to_string()]);
+        let bytecode = compile_source("argv(0) * argv(-2) + argc()");
+        assert_eq!(vm.execute(&bytecode).unwrap(), Some(Value::Integer(-11)));
+
+        let err = vm.execute(&compile_source("argv(3)")).unwrap_err();
+        assert_eq!(err.kind, ExceptionKind::IndexError);
+        assert_eq!(err.message, "argv index 3 out of range");
+        let err = vm.execute(&compile_source("argv(2)")).unwrap_err();
+        assert_eq!(err.kind, ExceptionKind::ValueError);
+        let err = vm.execute(&compile_source("argc(1)")).unwrap_err();
+        assert_eq!(err.kind, ExceptionKind::TypeError);
+
+        // A function of the same name takes precedence, and arguments
+        // survive a reset
+        vm.reset();
+        assert_eq!(vm.args().len(), 3);
+        let bytecode = compile_source("def argc():\n    return 42\nargc()");
+        assert_eq!(vm.execute(&bytecode).unwrap(), Some(Value::Integer(42)));
+    }
+
+    #[test]
+    fn test_exit_builtin_raises_system_exit() {
+        let mut vm = VM::new();
+        let err = vm
+            .execute(&compile_source("print(1)\nexit(3)\nprint(2)"))
+            .unwrap_err();
+        assert_eq!(err.kind, ExceptionKind::SystemExit);
+        assert_eq!(err.exit_code(), Some(3));
+        // Output up to the exit is kept
+        assert_eq!(vm.format_output(None), "1\n");
+
+        let err = vm.execute(&compile_source("exit()")).unwrap_err();
+        assert_eq!(err.exit_code(), Some(0));
+        let err = vm.execute(&compile_source("exit(1, 2)")).unwrap_err();
+        assert_eq!(err.message, "Function exit expects 0 to 1 arguments, got 2");
+        assert_eq!(err.exit_code(), None);
+    }
+
+    #[test]
+    fn test_trace_hook_reports_written_registers() {
+        use std::sync::{Arc, Mutex};
+
+        let mut builder = BytecodeBuilder::new();
+        builder.emit_load_const(0, 6);
+        builder.emit_load_const(1, 7);
+        builder.emit_binary_op(2, 0, BinaryOperator::Mul, 1);
+        builder.emit_set_result(2);
+        let bytecode = builder.build();
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let captured = Arc::clone(&events);
+        let mut vm = VM::new();
+        vm.set_trace_hook(move |event| {
+            captured
+                .lock()
+                .unwrap()
+                .push((event.ip, event.written, event.to_string()));
+        });
+        vm.execute(&bytecode).unwrap();
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 5);
+        assert_eq!(events[2].0, 2);
+        assert_eq!(events[2].1, Some((2, Value::Integer(42))));
+        assert_eq!(events[2].2, "0002 BinaryOp r2, r0 * r1  ; r2 = 42");
+        assert_eq!(events[3].1, None);
+        assert_eq!(events[4].2, "0004 Halt");
+    }
+
+    #[test]
+    fn test_trace_hook_reports_return_into_caller_register() {
+        use crate::compiler::compile;
+        use crate::{lexer, parser};
+        use std::sync::{Arc, Mutex};
+
+        let program = parser::parse(lexer::lex("def f():\n    return 5\nf()").unwrap()).unwrap();
+        let bytecode = compile(&program).unwrap();
+
+        let returns = Arc::new(Mutex::new(Vec::new()));
+        let captured = Arc::clone(&returns);
+        let mut vm = VM::new();
+        vm.set_trace_hook(move |event| {
+            if let Instruction::Return { .. } = event.instruction {
+                captured
+                    .lock()
+                    .unwrap()
+                    .push((event.call_depth, event.written));
+            }
+        });
+        vm.execute(&bytecode).unwrap();
+
+        let returns = returns.lock().unwrap();
+        assert_eq!(returns.len(), 1);
+        assert_eq!(returns[0].0, 0);
+        assert_eq!(returns[0].1.map(|(_, v)| v), Some(Value::Integer(5)));
+    }
+
+    #[test]
+    fn test_step_executes_one_instruction_at_a_time() {
+        let mut builder = BytecodeBuilder::new();
+        builder.emit_load_const(0, 5);
+        builder.emit_print(0);
+        builder.emit_set_result(0);
+        let bytecode = builder.build();
+
+        let mut vm = VM::new();
+        assert_eq!(vm.ip(), 0);
+        assert_eq!(vm.step(&bytecode).unwrap(), StepResult::Running { ip: 1 });
+        assert!(vm.stdout.is_empty());
+        assert_eq!(vm.step(&bytecode).unwrap(), StepResult::Running { ip: 2 });
+        assert_eq!(vm.stdout.as_str(), "5\n");
+        assert_eq!(vm.step(&bytecode).unwrap(), StepResult::Running { ip: 3 });
+        assert_eq!(
+            vm.step(&bytecode).unwrap(),
+            StepResult::Halted(Some(Value::Integer(5)))
+        );
+        assert_eq!(
+            vm.step(&bytecode).unwrap(),
+            StepResult::Halted(Some(Value::Integer(5)))
+        );
+        assert_eq!(vm.instructions_executed(), 4);
+
+        vm.restart();
+        assert_eq!(vm.ip(), 0);
+        assert_eq!(vm.instructions_executed(), 0);
+    }
+
+    #[test]
+    fn test_step_follows_calls_and_jumps() {
+        use crate::compiler::compile;
+        use crate::{lexer, parser};
+
+        let program = parser::parse(lexer::lex("def f():\n    return 9\nf()").unwrap()).unwrap();
+        let bytecode = compile(&program).unwrap();
+
+        let mut vm = VM::new();
+        let mut ips = vec![vm.ip()];
+        let result = loop {
+            match vm.step(&bytecode).unwrap() {
+                StepResult::Running { ip } => ips.push(ip),
+                StepResult::Halted(result) => break result,
+            }
+        };
+
+        // DefineFunction, Call -> body (LoadConst, Return) -> SetResult, Halt
+        assert_eq!(ips, vec![0, 1, 4, 5, 2, 3]);
+        assert_eq!(result, Some(Value::Integer(9)));
+    }
+
+    #[test]
+    fn test_step_respects_instruction_limit() {
+        let mut builder = BytecodeBuilder::new();
+        builder.emit_load_const(0, 1);
+        let bytecode = builder.build();
+
+        let mut vm = VM::new();
+        vm.set_instruction_limit(Some(1));
+        assert!(vm.step(&bytecode).is_ok());
+        assert!(vm.step(&bytecode).is_err());
+    }
+
+    #[test]
+    fn test_callee_window_starts_empty() {
+        // Caller writes r3, callee reads r3 from its own (empty) window
+        let instructions = vec![
+            Instruction::DefineFunction { chunk_index: 0 },
+            Instruction::LoadConst {
+                dest_reg: 3,
+                const_index: 0,
+            },
+            Instruction::Call {
+                name_index: 0,
+                arg_count: 0,
+                first_arg_reg: 0,
+                dest_reg: 4,
+            },
+            Instruction::Halt,
+        ];
+        let functions = vec![chunk(
+            0,
+            0,
+            3,
+            vec![Instruction::Return {
+                has_value: true,
+                src_reg: Some(3),
+            }],
+        )];
+        let bytecode = Bytecode {
+            instructions,
+            functions,
+            constants: vec![1],
+            var_names: vec!["f".to_string()],
+            var_ids: vec![1],
+            metadata: crate::bytecode::CompilerMetadata {
+                max_register_used: 4,
+            },
+            line_table: Vec::new(),
+        };
+
+        let mut vm = VM::new();
+        let err = vm.execute(&bytecode).unwrap_err();
+        assert_eq!(err.message, "Register 3 is empty");
+        assert_eq!(err.instruction_index, 4);
+    }
+
+    #[test]
+    fn test_call_depth_and_memory_limits() {
+        use crate::compiler::compile;
+        use crate::{lexer, parser};
+
+        // f0() calls f1() and so on, 50 calls deep
+        let mut source = String::from("def f50():\n    return 0\n");
+        for i in 0..50 {
+            source.push_str(&format!("def f{}():\n    return f{}() + 1\n", i, i + 1));
+        }
+        source.push_str("f0()");
+        let program = parser::parse(lexer::lex(&source).unwrap()).unwrap();
+        let bytecode = compile(&program).unwrap();
+
+        let mut vm = VM::new();
+        vm.set_call_depth_limit(Some(51));
+        assert_eq!(vm.execute(&bytecode).unwrap(), Some(Value::Integer(50)));
+        vm.set_call_depth_limit(Some(50));
+        let err = vm.execute(&bytecode).unwrap_err();
+        assert_eq!(err.message, "call depth limit exceeded (50 calls)");
+        assert_eq!(err.kind, ExceptionKind::RecursionError);
+
+        vm.set_call_depth_limit(None);
+        vm.set_memory_limit(Some(1024));
+        let err = vm.execute(&bytecode).unwrap_err();
+        assert_eq!(err.message, "memory limit exceeded (1024 bytes)");
+        vm.set_memory_limit(Some(1 << 20));
+        assert_eq!(vm.execute(&bytecode).unwrap(), Some(Value::Integer(50)));
+    }
+
+    #[test]
+    fn test_register_windows_grow_with_call_depth() {
+        use crate::compiler::compile;
+        use crate::{lexer, parser};
+
+        let source = "def depth(n):\n    return depth(n - 1) + 1\ndepth(0)";
+        let program = parser::parse(lexer::lex(source).unwrap()).unwrap();
+        let bytecode = compile(&program).unwrap();
+
+        let mut vm = VM::new();
+        let err = vm.execute(&bytecode).unwrap_err();
+        assert_eq!(err.kind, ExceptionKind::RecursionError);
+        // Each frame holds only the few registers its body writes
+        assert!(vm.registers.len() < (MAX_CALL_DEPTH + 1) * 8);
+
+        // A failed run leaves frames behind; the next run starts at the top window
+        let program = parser::parse(lexer::lex("x = 5\nx").unwrap()).unwrap();
+        let bytecode = compile(&program).unwrap();
+        assert_eq!(vm.execute(&bytecode).unwrap(), Some(Value::Integer(5)));
+        assert_eq!(vm.base, 0);
+        assert!(vm.call_stack.is_empty());
+    }
+
+    #[test]
+    fn test_global_slots_dense_and_sparse() {
+        let mut globals = GlobalSlots::default();
+        assert!(globals.is_empty());
+
+        assert_eq!(globals.insert(3, Value::Integer(1)), None);
+        assert_eq!(
+            globals.insert(3, Value::Integer(2)),
+            Some(Value::Integer(1))
+        );
+        assert_eq!(globals.insert(DENSE_GLOBAL_LIMIT + 5, Value::None), None);
+
+        assert_eq!(globals.get(&3), Some(&Value::Integer(2)));
+        assert_eq!(globals.get(&(DENSE_GLOBAL_LIMIT + 5)), Some(&Value::None));
+        assert!(!globals.contains_key(&4));
+        assert_eq!(globals.len, 2);
+        assert_eq!(globals.dense.len(), 32);
+    }
+
+    fn compile_source(source: &str) -> Bytecode {
+        let program = crate::parser::parse(crate::lexer::lex(source).unwrap()).unwrap();
+        crate::compiler::compile(&program).unwrap()
+    }
+
+    #[test]
+    fn test_call_site_cache_filled_on_first_call() {
+        let bytecode = compile_source("def inc(n):\n    return n + 1\ninc(inc(1))");
+        let mut vm = VM::new();
+        assert_eq!(vm.execute(&bytecode).unwrap(), Some(Value::Integer(3)));
+
+        let cached: Vec<usize> = (0..bytecode.instructions.len())
+            .filter(|&ip| matches!(vm.call_sites.get(ip), Some(Some(_))))
+            .collect();
+        let calls: Vec<usize> = bytecode
+            .instructions
+            .iter()
+            .enumerate()
+            .filter(|(_, instruction)| matches!(instruction, Instruction::Call { .. }))
+            .map(|(ip, _)| ip)
+            .collect();
+        assert_eq!(cached, calls);
+    }
+
+    #[test]
+    fn test_call_site_cache_does_not_leak_across_programs() {
+        // Both programs place their only Call at the same instruction index
+        let first = compile_source("def f():\n    return 1\nf()");
+        let second = compile_source("def g():\n    return 2\ng()");
+
+        let mut vm = VM::new();
+        assert_eq!(vm.execute(&first).unwrap(), Some(Value::Integer(1)));
+        assert_eq!(vm.execute(&second).unwrap(), Some(Value::Integer(2)));
+    }
+
+    #[test]
+    fn test_call_site_cache_sees_redefinition() {
+        let bytecode = compile_source("def f():\n    return 1\nf()");
+        let redefined = compile_source("def f():\n    return 2\nf()");
+
+        let mut vm = VM::new();
+        assert_eq!(vm.execute(&bytecode).unwrap(), Some(Value::Integer(1)));
+        assert_eq!(vm.execute(&redefined).unwrap(), Some(Value::Integer(2)));
+        assert_eq!(vm.execute(&bytecode).unwrap(), Some(Value::Integer(1)));
+    }
+
+    #[test]
+    fn test_locals_maps_are_recycled_across_calls() {
+        let bytecode = compile_source("def id(n):\n    return n\nid(1)\nid(2)\nid(3)");
+        let mut vm = VM::new();
+        assert_eq!(vm.execute(&bytecode).unwrap(), Some(Value::Integer(3)));
+        // Sequential calls share one map
+        assert_eq!(vm.locals_pool.len(), 1);
+
+        let nested = compile_source(
+            "def double(x):\n    return x * 2\ndef quad(x):\n    return double(double(x))\nquad(5)",
+        );
+        assert_eq!(vm.execute(&nested).unwrap(), Some(Value::Integer(20)));
+        assert_eq!(vm.locals_pool.len(), 2);
+        assert!(vm.locals_pool.iter().all(HashMap::is_empty));
+    }
+
+    #[test]
+    fn test_abandoned_frames_return_locals_to_pool() {
+        let bytecode = compile_source("def f(n):\n    return n / 0\nf(1)");
+        let mut vm = VM::new();
+        assert!(vm.execute(&bytecode).is_err());
+        assert_eq!(vm.call_stack.len(), 1);
+
+        vm.reset_execution_state();
+        assert!(vm.call_stack.is_empty());
+        assert_eq!(vm.locals_pool.len(), 1);
+        assert!(vm.locals_pool[0].is_empty());
+    }
+
+    #[test]
+    fn test_reset_matches_fresh_vm() {
+        let bytecode = compile_source("def f(n):\n    return n + 1\nx = f(1)\nprint(x)\nx");
+        let mut vm = VM::new();
+        vm.set_instruction_limit(Some(1_000));
+        assert_eq!(vm.execute(&bytecode).unwrap(), Some(Value::Integer(2)));
+
+        vm.reset();
+        assert!(vm.variables.is_empty());
+        assert!(vm.functions.is_empty());
+        assert!(vm.stdout.is_empty());
+        assert!(vm.result.is_none());
+        assert_eq!(vm.register_valid, [0; 4]);
+        assert_eq!(vm.instructions_executed(), 0);
+        // Configuration is kept
+        assert_eq!(vm.instruction_limit, Some(1_000));
+
+        // Functions from the previous program are gone
+        let call_only = compile_source("f(1)");
+        let err = vm.execute(&call_only).unwrap_err();
+        assert_eq!(err.kind, ExceptionKind::NameError);
+
+        vm.reset();
+        assert_eq!(vm.execute(&bytecode).unwrap(), Some(Value::Integer(2)));
+        assert_eq!(vm.format_output(Some(Value::Integer(2))), "2\n2");
+    }
+
+    #[test]
+    fn test_reset_releases_deep_register_stack() {
+        let mut vm = VM::new();
+        for _ in 0..(RETAINED_FRAMES + 4) {
+            vm.set_register(255, Value::Integer(1));
+            vm.enter_window(REGISTER_WINDOW);
+        }
+        vm.locals_pool = vec![HashMap::new(); RETAINED_FRAMES + 4];
+
+        vm.reset();
+        assert_eq!(vm.base, 0);
+        assert_eq!(vm.registers.len(), RETAINED_FRAMES * REGISTER_WINDOW);
+        assert_eq!(vm.locals_pool.len(), RETAINED_FRAMES);
+    }
+
+    #[test]
+    fn test_output_limit_truncates_with_marker() {
+        let bytecode = compile_source("print(1)\nprint(22)\nprint(333)\nprint(4)\n5");
+        let mut vm = VM::new();
+        vm.set_output_limit(Some(OutputLimit {
+            max_bytes: 6,
+            on_overflow: OutputOverflow::Truncate,
+        }));
+
+        let result = vm.execute(&bytecode).unwrap();
+        assert_eq!(
+            vm.format_output(result),
+            "1\n22\n[output truncated after 5 bytes]\n5"
+        );
+        assert_eq!(vm.output_bytes(), 5);
+    }
+
+    #[test]
+    fn test_output_limit_error_mode() {
+        let bytecode = compile_source("print(1)\nprint(22)\nprint(333)");
+        let mut vm = VM::new();
+        vm.set_output_limit(Some(OutputLimit {
+            max_bytes: 5,
+            on_overflow: OutputOverflow::Error,
+        }));
+
+        let err = vm.execute(&bytecode).unwrap_err();
+        assert_eq!(err.kind, ExceptionKind::RuntimeError);
+        assert_eq!(err.message, "output limit exceeded (5 bytes)");
+        assert!(matches!(
+            bytecode.instructions[err.instruction_index],
+            Instruction::Print { .. }
+        ));
+        assert_eq!(vm.format_output(None), "1\n22\n");
+    }
+
+    #[test]
+    fn test_output_limit_counts_streamed_output_and_resets() {
+        let bytecode = compile_source("print(1)\nprint(2)");
+        let lines = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let captured = std::sync::Arc::clone(&lines);
+        let mut vm = VM::new();
+        vm.set_stdout_sink(move |line| captured.lock().unwrap().push(line.to_string()));
+        vm.set_output_limit(Some(OutputLimit {
+            max_bytes: 2,
+            on_overflow: OutputOverflow::Truncate,
+        }));
+
+        vm.execute(&bytecode).unwrap();
+        assert_eq!(
+            *lines.lock().unwrap(),
+            vec!["1\n", "[output truncated after 2 bytes]\n"]
+        );
+
+        // The budget starts over once execution state is reset
+        vm.reset_execution_state();
+        lines.lock().unwrap().clear();
+        vm.execute(&compile_source("print(3)")).unwrap();
+        assert_eq!(*lines.lock().unwrap(), vec!["3\n"]);
+    }
 }
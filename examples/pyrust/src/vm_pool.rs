@@ -0,0 +1,238 @@
+//! Pool of reusable, pre-initialized VMs
+//!
+//! A [`VmPool`] hands out VMs with [`VmPool::checkout`]. When the returned
+//! [`PooledVm`] guard is dropped, the VM is reset (globals, functions, output,
+//! and per-request attachments such as stdout sinks, script arguments, trace
+//! hooks, watchdogs, or cancellation tokens are cleared) and put back for the next request, so
+//! callers never pay VM construction on the hot path.
+//!
+//! Every VM is built by the pool's factory, which is where per-VM limits are
+//! configured once for all requests. Checkouts never block: if every pooled
+//! VM is busy, a fresh one is built, and it is dropped on return if the pool is
+//! already full.
+//!
+//! # Example
+//!
+//! ```
+//! use pyrust::vm::VM;
+//! use pyrust::vm_pool::VmPool;
+//! use pyrust::{compiler, lexer, parser};
+//!
+//! let pool = VmPool::with_factory(2, || {
+//!     let mut vm = VM::new();
+//!     vm.set_instruction_limit(Some(10_000));
+//!     vm
+//! });
+//!
+//! let program = parser::parse(lexer::lex("x = 20\nx + 22").unwrap()).unwrap();
+//! let bytecode = compiler::compile(&program).unwrap();
+//!
+//! let mut vm = pool.checkout();
+//! let result = vm.execute(&bytecode).unwrap();
+//! assert_eq!(vm.format_output(result), "42");
+//! ```
+
+use crate::vm::VM;
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+
+/// Builds the VMs a pool hands out
+type VmFactory = Box<dyn Fn() -> VM + Send + Sync>;
+
+/// Thread-safe pool of VMs that are reset when returned
+pub struct VmPool {
+    idle: Mutex<Vec<VM>>,
+    factory: VmFactory,
+    capacity: usize,
+}
+
+impl VmPool {
+    /// Create a pool of `capacity` VMs using the process-wide execution limits
+    pub fn new(capacity: usize) -> Self {
+        Self::with_factory(capacity, crate::new_vm)
+    }
+
+    /// Create a pool of `capacity` VMs built by `factory`
+    ///
+    /// All VMs are constructed up front.
+    pub fn with_factory(capacity: usize, factory: impl Fn() -> VM + Send + Sync + 'static) -> Self {
+        let idle = (0..capacity).map(|_| factory()).collect();
+        Self {
+            idle: Mutex::new(idle),
+            factory: Box::new(factory),
+            capacity,
+        }
+    }
+
+    /// Take a VM from the pool, building a new one if none is idle
+    pub fn checkout(&self) -> PooledVm<'_> {
+        let vm = self
+            .idle
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .pop()
+            .unwrap_or_else(|| (self.factory)());
+        PooledVm {
+            vm: Some(vm),
+            pool: self,
+        }
+    }
+
+    /// Number of VMs currently waiting in the pool
+    pub fn idle_count(&self) -> usize {
+        self.idle
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .len()
+    }
+
+    /// Maximum number of idle VMs the pool keeps
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Reset `vm` and keep it if the pool has room
+    fn check_in(&self, mut vm: VM) {
+        vm.reset();
+        vm.clear_stdout_sink();
+        vm.clear_trace_hook();
+        vm.clear_watchdog();
+        vm.take_debugger();
+        vm.take_stdin();
+        vm.set_args(Vec::new());
+        vm.clear_cancellation_token();
+
+        let mut idle = self
+            .idle
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if idle.len() < self.capacity {
+            idle.push(vm);
+        }
+    }
+}
+
+/// A VM checked out of a [`VmPool`], returned to it on drop
+pub struct PooledVm<'a> {
+    vm: Option<VM>,
+    pool: &'a VmPool,
+}
+
+impl Deref for PooledVm<'_> {
+    type Target = VM;
+
+    fn deref(&self) -> &VM {
+        self.vm.as_ref().expect("pooled VM already returned")
+    }
+}
+
+impl DerefMut for PooledVm<'_> {
+    fn deref_mut(&mut self) -> &mut VM {
+        self.vm.as_mut().expect("pooled VM already returned")
+    }
+}
+
+impl Drop for PooledVm<'_> {
+    fn drop(&mut self) {
+        if let Some(vm) = self.vm.take() {
+            self.pool.check_in(vm);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compiler, lexer, parser};
+
+    fn run(vm: &mut VM, source: &str) -> Result<String, String> {
+        let program = parser::parse(lexer::lex(source).unwrap()).unwrap();
+        let bytecode = compiler::compile(&program).unwrap();
+        vm.execute(&bytecode)
+            .map(|result| vm.format_output(result))
+            .map_err(|e| e.message)
+    }
+
+    #[test]
+    fn test_pool_is_prefilled() {
+        let pool = VmPool::new(3);
+        assert_eq!(pool.idle_count(), 3);
+        assert_eq!(pool.capacity(), 3);
+
+        let vm = pool.checkout();
+        assert_eq!(pool.idle_count(), 2);
+        drop(vm);
+        assert_eq!(pool.idle_count(), 3);
+    }
+
+    #[test]
+    fn test_returned_vm_is_reset() {
+        let pool = VmPool::new(1);
+        {
+            let mut vm = pool.checkout();
+            assert_eq!(run(&mut vm, "x = 1\nprint(x)").unwrap(), "1\n");
+        }
+        let mut vm = pool.checkout();
+        assert!(run(&mut vm, "x").is_err());
+    }
+
+    #[test]
+    fn test_factory_configuration_survives_reuse() {
+        let pool = VmPool::with_factory(1, || {
+            let mut vm = VM::new();
+            vm.set_instruction_limit(Some(3));
+            vm
+        });
+        for _ in 0..2 {
+            let mut vm = pool.checkout();
+            let err = run(&mut vm, "1 + 2 + 3").unwrap_err();
+            assert!(err.contains("execution budget exceeded"));
+        }
+    }
+
+    #[test]
+    fn test_per_request_attachments_are_cleared() {
+        let pool = VmPool::new(1);
+        {
+            let mut vm = pool.checkout();
+            vm.set_stdout_sink(|_| {});
+            vm.set_args(vec!["1".to_string()]);
+        }
+        let mut vm = pool.checkout();
+        // Without a sink, print output is buffered again
+        assert_eq!(run(&mut vm, "print(5)").unwrap(), "5\n");
+        assert!(vm.args().is_empty());
+    }
+
+    #[test]
+    fn test_exhausted_pool_builds_extra_vms() {
+        let pool = VmPool::new(1);
+        let mut first = pool.checkout();
+        let mut second = pool.checkout();
+        assert_eq!(pool.idle_count(), 0);
+        assert_eq!(run(&mut first, "1").unwrap(), "1");
+        assert_eq!(run(&mut second, "2").unwrap(), "2");
+
+        drop(first);
+        drop(second);
+        // The surplus VM is dropped rather than growing the pool
+        assert_eq!(pool.idle_count(), 1);
+    }
+
+    #[test]
+    fn test_pool_shared_across_threads() {
+        let pool = std::sync::Arc::new(VmPool::new(2));
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let pool = std::sync::Arc::clone(&pool);
+                std::thread::spawn(move || run(&mut pool.checkout(), &format!("{} * 2", i)))
+            })
+            .collect();
+        let results: Vec<String> = handles
+            .into_iter()
+            .map(|h| h.join().unwrap().unwrap())
+            .collect();
+        assert_eq!(results, vec!["0", "2", "4", "6"]);
+        assert_eq!(pool.idle_count(), 2);
+    }
+}
@@ -0,0 +1,569 @@
+//! Non-fatal diagnostics for suspicious but valid programs
+//!
+//! Each stage of compilation can report code that compiles and may even
+//! run, but is probably not what the author meant. The lexer warns about an
+//! indent that mixes tabs and spaces (see [`crate::lexer::lex_with_warnings`])
+//! and the parser about statements after a `return`, which never run (see
+//! [`crate::parser::parse_with_warnings`]). [`check`] then walks the parsed
+//! program for:
+//!
+//! - a local variable that is assigned but never read
+//! - a variable read before it is assigned (in a function this silently reads
+//!   the global of the same name instead)
+//! - a parameter that shadows a global variable or function
+//! - a function that is never called
+//! - a division or modulo whose divisor is a constant zero, such as `x / 0`
+//!   or `x % (2 - 2)`, which always raises at runtime
+//!
+//! Top-level variables are never reported as unused, since they are the
+//! program's visible state. [`crate::compiler::compile_with_warnings`] returns
+//! these warnings alongside the bytecode, and
+//! [`crate::compile_python_with_warnings`] those of every stage; the CLI
+//! prints them with `--warnings`. Constant division by zero can instead be
+//! made a compile error with
+//! [`crate::compiler::CompileOptions::division_by_zero`], and every warning
+//! with [`crate::compiler::CompileOptions::warnings`] (`-W error`).
+//!
+//! # Example
+//!
+//! ```
+//! use pyrust::warnings::{check, WarningKind};
+//! use pyrust::{lexer, parser};
+//!
+//! let source = "def f(a):\n    b = 1\n    return a\nprint(y)";
+//! let (program, positions) = parser::parse_with_positions(lexer::lex(source).unwrap()).unwrap();
+//!
+//! let kinds: Vec<_> = check(&program, &positions).iter().map(|w| w.kind).collect();
+//! assert_eq!(
+//!     kinds,
+//!     vec![WarningKind::UnusedFunction, WarningKind::UnusedVariable, WarningKind::UseBeforeAssignment]
+//! );
+//! ```
+
+use crate::ast::simplify;
+use crate::ast::{ExprSpan, Expression, Program, SourcePos, Statement, StatementPos};
+use crate::error::CompileError;
+use crate::error_code::ErrorCode;
+use std::collections::HashSet;
+use std::fmt;
+
+/// What a [`CompileWarning`] is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WarningKind {
+    /// A local variable is assigned but never read
+    UnusedVariable,
+    /// A variable is read before any assignment to it
+    UseBeforeAssignment,
+    /// A parameter has the name of a global variable or function
+    ShadowedParameter,
+    /// A function is defined but never called
+    UnusedFunction,
+    /// A division or modulo has a divisor that is always zero
+    DivisionByZero,
+    /// A line is indented with both tabs and spaces, reported by the lexer
+    MixedIndentation,
+    /// A statement follows a `return` in the same block, reported by the
+    /// parser
+    UnreachableCode,
+}
+
+/// A non-fatal diagnostic produced during compilation
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompileWarning {
+    pub kind: WarningKind,
+    pub message: String,
+    /// Where the warning applies, when the program was parsed with positions
+    pub pos: Option<SourcePos>,
+    /// Where the offending expression ends (exclusive), when known
+    pub end: Option<SourcePos>,
+}
+
+impl WarningKind {
+    /// Stable code of the warning
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            WarningKind::UnusedVariable => ErrorCode::UnusedVariable,
+            WarningKind::UseBeforeAssignment => ErrorCode::UseBeforeAssignment,
+            WarningKind::ShadowedParameter => ErrorCode::ShadowedParameter,
+            WarningKind::UnusedFunction => ErrorCode::UnusedFunction,
+            WarningKind::DivisionByZero => ErrorCode::ConstantZeroDivisor,
+            WarningKind::MixedIndentation => ErrorCode::MixedIndentation,
+            WarningKind::UnreachableCode => ErrorCode::UnreachableCode,
+        }
+    }
+}
+
+impl CompileWarning {
+    /// The compile error this warning becomes when warnings are errors
+    ///
+    /// The error keeps the warning's code, message and position.
+    pub fn into_error(self) -> CompileError {
+        CompileError {
+            code: self.kind.code(),
+            message: self.message,
+            location: self.pos,
+            end: self.end,
+        }
+    }
+}
+
+impl fmt::Display for CompileWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.pos {
+            Some(pos) => write!(
+                f,
+                "Warning[{}] at {}:{}: {}",
+                self.kind.code(),
+                pos.line,
+                pos.column,
+                self.message
+            ),
+            None => write!(f, "Warning[{}]: {}", self.kind.code(), self.message),
+        }
+    }
+}
+
+/// Collect warnings for `program`, ordered by source position
+///
+/// `positions` is the list returned by [`crate::parser::parse_with_positions`]
+/// and may be empty, in which case the warnings have no positions.
+pub fn check(program: &Program, positions: &[StatementPos]) -> Vec<CompileWarning> {
+    let mut checker = Checker::default();
+    for stmt in &program.statements {
+        match stmt {
+            Statement::Assignment { name, .. } => {
+                checker.globals.insert(name);
+            }
+            Statement::MultiAssignment { targets, .. } => {
+                checker.globals.extend(targets.iter().map(String::as_str))
+            }
+            Statement::FunctionDef { name, .. } => {
+                checker.function_names.insert(name);
+            }
+            _ => {}
+        }
+    }
+
+    let mut assigned = HashSet::new();
+    for (index, stmt) in program.statements.iter().enumerate() {
+        let pos = positions.get(index);
+        if let Statement::FunctionDef { name, params, body } = stmt {
+            let body_positions = pos.map_or(&[][..], |pos| &pos.body[..]);
+            checker.check_function(name, params, body, pos.map(|pos| pos.pos), body_positions);
+            continue;
+        }
+        let mut reads = Vec::new();
+        if let Some(value) = statement_value(stmt) {
+            collect_reads(
+                value,
+                span_of(pos),
+                pos.map(|pos| pos.pos),
+                &mut reads,
+                &mut checker.calls,
+            );
+        }
+        for (name, read_pos) in reads {
+            if !assigned.contains(name) {
+                checker.warn(
+                    WarningKind::UseBeforeAssignment,
+                    format!("'{}' is read before it is assigned", name),
+                    read_pos,
+                );
+            }
+        }
+        assigned.extend(assigned_names(stmt));
+    }
+
+    for (name, pos) in std::mem::take(&mut checker.functions) {
+        if !checker.calls.contains(name) {
+            let message = format!("function '{}' is never called", name);
+            checker.warn(WarningKind::UnusedFunction, message, pos);
+        }
+    }
+
+    let mut warnings = checker.warnings;
+    warnings.extend(division_by_zero(program, positions));
+    warnings.sort_by_key(|warning| {
+        warning
+            .pos
+            .map_or((usize::MAX, usize::MAX), |pos| (pos.line, pos.column))
+    });
+    warnings
+}
+
+/// Report every division or modulo by a constant zero, in source order
+///
+/// The divisor counts as constant when it is built only from integer
+/// literals; each report is positioned at the divisor. The compiler uses
+/// this to reject such programs when the diagnostic is configured as an
+/// error.
+pub fn division_by_zero(program: &Program, positions: &[StatementPos]) -> Vec<CompileWarning> {
+    let mut warnings = Vec::new();
+    for (index, stmt) in program.statements.iter().enumerate() {
+        let pos = positions.get(index);
+        let fallback = pos.map(|pos| pos.pos);
+        if let Statement::FunctionDef { body, .. } = stmt {
+            let body_positions = pos.map_or(&[][..], |pos| &pos.body[..]);
+            for (index, stmt) in body.iter().enumerate() {
+                let stmt_pos = body_positions.get(index);
+                if let Some(value) = statement_value(stmt) {
+                    let fallback = stmt_pos.map(|p| p.pos).or(fallback);
+                    find_zero_divisors(value, span_of(stmt_pos), fallback, &mut warnings);
+                }
+            }
+        } else if let Some(value) = statement_value(stmt) {
+            find_zero_divisors(value, span_of(pos), fallback, &mut warnings);
+        }
+    }
+    warnings
+}
+
+#[derive(Default)]
+struct Checker<'a> {
+    /// Names assigned anywhere at the top level
+    globals: HashSet<&'a str>,
+    /// Names of all defined functions
+    function_names: HashSet<&'a str>,
+    /// Checked functions and where their definitions start
+    functions: Vec<(&'a str, Option<SourcePos>)>,
+    /// Functions called anywhere except from their own body
+    calls: HashSet<&'a str>,
+    warnings: Vec<CompileWarning>,
+}
+
+impl<'a> Checker<'a> {
+    fn warn(&mut self, kind: WarningKind, message: String, pos: Option<SourcePos>) {
+        self.warnings.push(CompileWarning {
+            kind,
+            message,
+            pos,
+            end: None,
+        });
+    }
+
+    fn check_function(
+        &mut self,
+        name: &'a str,
+        params: &'a [String],
+        body: &'a [Statement],
+        pos: Option<SourcePos>,
+        body_positions: &[StatementPos],
+    ) {
+        self.functions.push((name, pos));
+        for param in params {
+            if self.globals.contains(param.as_str()) || self.function_names.contains(param.as_str())
+            {
+                let message = format!("parameter '{}' of '{}' shadows a global name", param, name);
+                self.warn(WarningKind::ShadowedParameter, message, pos);
+            }
+        }
+
+        let locals: HashSet<&str> = body.iter().flat_map(assigned_names).collect();
+        let mut assigned: HashSet<&str> = params.iter().map(String::as_str).collect();
+        // Locals in order of first assignment, with where that happened
+        let mut first_assignments: Vec<(&str, Option<SourcePos>)> = Vec::new();
+        let mut read: HashSet<&str> = HashSet::new();
+        let mut calls = HashSet::new();
+
+        for (index, stmt) in body.iter().enumerate() {
+            let stmt_pos = body_positions.get(index);
+            let mut reads = Vec::new();
+            if let Some(value) = statement_value(stmt) {
+                let fallback = stmt_pos.map(|p| p.pos).or(pos);
+                collect_reads(value, span_of(stmt_pos), fallback, &mut reads, &mut calls);
+            }
+            for (var, read_pos) in reads {
+                read.insert(var);
+                if assigned.contains(var) {
+                    continue;
+                }
+                if locals.contains(var) {
+                    let message = format!(
+                        "'{}' is read before it is assigned in '{}', so the global is used",
+                        var, name
+                    );
+                    self.warn(WarningKind::UseBeforeAssignment, message, read_pos);
+                } else if !self.globals.contains(var) {
+                    let message = format!("'{}' is read but never assigned", var);
+                    self.warn(WarningKind::UseBeforeAssignment, message, read_pos);
+                }
+            }
+            for var in assigned_names(stmt) {
+                if !assigned.contains(var) && !params.iter().any(|p| p == var) {
+                    first_assignments.push((var, stmt_pos.map(|p| p.pos).or(pos)));
+                }
+                assigned.insert(var);
+            }
+        }
+
+        for (var, assign_pos) in first_assignments {
+            if !read.contains(var) {
+                let message = format!("local variable '{}' in '{}' is never read", var, name);
+                self.warn(WarningKind::UnusedVariable, message, assign_pos);
+            }
+        }
+
+        calls.remove(name);
+        self.calls.extend(calls);
+    }
+}
+
+/// The expression a statement evaluates, if any
+fn statement_value(stmt: &Statement) -> Option<&Expression> {
+    match stmt {
+        Statement::Assignment { value, .. }
+        | Statement::MultiAssignment { value, .. }
+        | Statement::Print { value }
+        | Statement::Expression { value } => Some(value),
+        Statement::Return { value } => value.as_ref(),
+        Statement::FunctionDef { .. } => None,
+    }
+}
+
+/// Variables a statement assigns
+fn assigned_names(stmt: &Statement) -> Vec<&str> {
+    match stmt {
+        Statement::Assignment { name, .. } => vec![name.as_str()],
+        Statement::MultiAssignment { targets, .. } => targets.iter().map(String::as_str).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn span_of(pos: Option<&StatementPos>) -> Option<&ExprSpan> {
+    pos.and_then(|pos| pos.value.as_ref())
+}
+
+/// Record every variable `expr` reads and every function it calls
+///
+/// Reads are positioned at their own span when known, else at `fallback`.
+fn collect_reads<'a>(
+    expr: &'a Expression,
+    span: Option<&ExprSpan>,
+    fallback: Option<SourcePos>,
+    reads: &mut Vec<(&'a str, Option<SourcePos>)>,
+    calls: &mut HashSet<&'a str>,
+) {
+    let child = |index: usize| span.and_then(|span| span.children.get(index));
+    match expr {
+        Expression::Integer(_) => {}
+        Expression::Variable(name) => {
+            reads.push((name, span.map(|span| span.start).or(fallback)));
+        }
+        Expression::BinaryOp { left, right, .. } => {
+            collect_reads(left, child(0), fallback, reads, calls);
+            collect_reads(right, child(1), fallback, reads, calls);
+        }
+        Expression::UnaryOp { operand, .. } => {
+            collect_reads(operand, child(0), fallback, reads, calls);
+        }
+        Expression::Call { name, args } => {
+            calls.insert(name);
+            for (index, arg) in args.iter().enumerate() {
+                collect_reads(arg, child(index), fallback, reads, calls);
+            }
+        }
+    }
+}
+
+/// Record every division or modulo in `expr` whose divisor folds to zero
+fn find_zero_divisors(
+    expr: &Expression,
+    span: Option<&ExprSpan>,
+    fallback: Option<SourcePos>,
+    warnings: &mut Vec<CompileWarning>,
+) {
+    let child = |index: usize| span.and_then(|span| span.children.get(index));
+    match expr {
+        Expression::Integer(_) | Expression::Variable(_) => {}
+        Expression::BinaryOp { left, op, right } => {
+            find_zero_divisors(left, child(0), fallback, warnings);
+            find_zero_divisors(right, child(1), fallback, warnings);
+            if simplify::is_division(*op) && simplify::literal_value(right) == Some(0) {
+                warnings.push(CompileWarning {
+                    kind: WarningKind::DivisionByZero,
+                    message: "division by zero will always raise".to_string(),
+                    pos: child(1).map(|span| span.start).or(fallback),
+                    end: child(1).map(|span| span.end),
+                });
+            }
+        }
+        Expression::UnaryOp { operand, .. } => {
+            find_zero_divisors(operand, child(0), fallback, warnings);
+        }
+        Expression::Call { args, .. } => {
+            for (index, arg) in args.iter().enumerate() {
+                find_zero_divisors(arg, child(index), fallback, warnings);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer, parser};
+
+    fn warnings_for(source: &str) -> Vec<(WarningKind, String, (usize, usize))> {
+        let (program, positions) =
+            parser::parse_with_positions(lexer::lex(source).unwrap()).unwrap();
+        check(&program, &positions)
+            .into_iter()
+            .map(|w| {
+                let pos = w.pos.unwrap();
+                (w.kind, w.message, (pos.line, pos.column))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_clean_program_has_no_warnings() {
+        let source =
+            "base = 10\ndef scale(n):\n    factor = base\n    return n * factor\nprint(scale(2))";
+        assert!(warnings_for(source).is_empty());
+    }
+
+    #[test]
+    fn test_unused_local_and_function() {
+        let source =
+            "def helper():\n    return 1\ndef f(a):\n    tmp = a\n    tmp = 2\n    return a\nf(1)";
+        assert_eq!(
+            warnings_for(source),
+            vec![
+                (
+                    WarningKind::UnusedFunction,
+                    "function 'helper' is never called".to_string(),
+                    (1, 1)
+                ),
+                (
+                    WarningKind::UnusedVariable,
+                    "local variable 'tmp' in 'f' is never read".to_string(),
+                    (4, 5)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_recursion_alone_does_not_count_as_a_call() {
+        let source = "def f(n):\n    return f(n - 1)";
+        let kinds: Vec<_> = warnings_for(source).into_iter().map(|w| w.0).collect();
+        assert_eq!(kinds, vec![WarningKind::UnusedFunction]);
+    }
+
+    #[test]
+    fn test_reads_before_assignment() {
+        let source =
+            "x = 1\nprint(y + x)\ny = 2\ndef f():\n    z = x + w\n    x = 3\n    return z + x\nf()";
+        assert_eq!(
+            warnings_for(source),
+            vec![
+                (
+                    WarningKind::UseBeforeAssignment,
+                    "'y' is read before it is assigned".to_string(),
+                    (2, 7)
+                ),
+                (
+                    WarningKind::UseBeforeAssignment,
+                    "'x' is read before it is assigned in 'f', so the global is used".to_string(),
+                    (5, 9)
+                ),
+                (
+                    WarningKind::UseBeforeAssignment,
+                    "'w' is read but never assigned".to_string(),
+                    (5, 13)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parameter_shadowing_globals() {
+        let source = "limit = 5\ndef g():\n    return 0\ndef f(limit, g):\n    return limit + g\nf(1, 2)\ng()";
+        let shadowed: Vec<_> = warnings_for(source)
+            .into_iter()
+            .filter(|w| w.0 == WarningKind::ShadowedParameter)
+            .map(|w| (w.1, w.2))
+            .collect();
+        assert_eq!(
+            shadowed,
+            vec![
+                (
+                    "parameter 'limit' of 'f' shadows a global name".to_string(),
+                    (4, 1)
+                ),
+                (
+                    "parameter 'g' of 'f' shadows a global name".to_string(),
+                    (4, 1)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_constant_division_by_zero() {
+        let source = "x = 10 / 0\ndef f(n):\n    return n % (3 - 3)\nprint(f(x) // 2 + x // -0)\nprint(x / (0 / 1 + y))";
+        let found: Vec<_> = warnings_for(source)
+            .into_iter()
+            .filter(|w| w.0 == WarningKind::DivisionByZero)
+            .map(|w| w.2)
+            .collect();
+        // `0 / 1 + y` is not constant, so only the first three are reported
+        assert_eq!(found, vec![(1, 10), (3, 17), (4, 24)]);
+    }
+
+    #[test]
+    fn test_warnings_without_positions() {
+        let program = parser::parse(lexer::lex("print(x)").unwrap()).unwrap();
+        let warnings = check(&program, &[]);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].pos, None);
+        assert_eq!(
+            warnings[0].to_string(),
+            "Warning[W0002]: 'x' is read before it is assigned"
+        );
+    }
+
+    #[test]
+    fn test_warnings_as_errors() {
+        use crate::compiler::{CompileOptions, Severity};
+
+        let options = CompileOptions {
+            warnings: Severity::Error,
+            ..Default::default()
+        };
+        // Every stage's first warning fails compilation, keeping its code
+        for (source, code) in [
+            (
+                "def f():\n\t return 1\nprint(f())",
+                ErrorCode::MixedIndentation,
+            ),
+            (
+                "def f():\n    return 1\n    print(2)\nprint(f())",
+                ErrorCode::UnreachableCode,
+            ),
+            ("def f():\n    return 1", ErrorCode::UnusedFunction),
+        ] {
+            assert!(
+                crate::compile_python_with_warnings(source, &CompileOptions::default()).is_ok()
+            );
+            let err = crate::compile_python_with_warnings(source, &options).unwrap_err();
+            assert_eq!(err.code(), code, "{}", source);
+            // Compiling a parsed program only sees the compiler's warnings
+            let compiled = crate::compile_python_with_options(source, &options);
+            assert_eq!(compiled.is_err(), code == ErrorCode::UnusedFunction);
+        }
+
+        let error = CompileWarning {
+            kind: WarningKind::UnusedFunction,
+            message: "function 'f' is never called".to_string(),
+            pos: Some(SourcePos { line: 1, column: 1 }),
+            end: None,
+        }
+        .into_error();
+        assert_eq!(error.code, ErrorCode::UnusedFunction);
+        assert_eq!(
+            crate::error::PyRustError::from(error).to_string(),
+            "CompileError[W0004] at 1:1: function 'f' is never called"
+        );
+    }
+}
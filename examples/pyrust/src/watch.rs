@@ -0,0 +1,137 @@
+//! Waiting for a script to change, for `pyrust --watch`
+//!
+//! A [`FileWatcher`] polls a file's modification time and size rather than
+//! subscribing to file system events, so it works the same everywhere,
+//! including on network mounts. Editors often save in several steps (truncate
+//! then write, or write a temporary file and rename it over the original),
+//! so a change is only reported once the file has stayed the same for the
+//! watcher's debounce interval. A file that disappears is waited for until
+//! it comes back.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use pyrust::watch::FileWatcher;
+//!
+//! let mut watcher = FileWatcher::new("script.py");
+//! loop {
+//!     watcher.wait_for_change();
+//!     println!("script.py changed");
+//! }
+//! ```
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// How often the file is checked
+pub const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long the file must stay unchanged before a change is reported
+pub const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// Watches one file for changes
+pub struct FileWatcher {
+    path: PathBuf,
+    debounce: Duration,
+    /// The file as it was when last reported
+    seen: Option<Stamp>,
+}
+
+/// What a change to a file shows up in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stamp {
+    modified: SystemTime,
+    len: u64,
+}
+
+impl FileWatcher {
+    /// Watch `path`, as it is now
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        Self {
+            seen: stamp(&path),
+            path,
+            debounce: DEBOUNCE,
+        }
+    }
+
+    /// Report a change only once the file has been still for `debounce`
+    pub fn with_debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    /// The watched file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Block until the file has changed and settled
+    pub fn wait_for_change(&mut self) {
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            if self.poll() {
+                return;
+            }
+        }
+    }
+
+    /// Whether the file has changed and settled since it was last reported;
+    /// waits out the debounce interval if it has changed
+    pub fn poll(&mut self) -> bool {
+        let mut current = stamp(&self.path);
+        if current == self.seen || current.is_none() {
+            return false;
+        }
+        // Wait for the writer to finish
+        loop {
+            thread::sleep(self.debounce);
+            let settled = stamp(&self.path);
+            if settled == current {
+                break;
+            }
+            current = settled;
+        }
+        if current.is_none() || current == self.seen {
+            return false;
+        }
+        self.seen = current;
+        true
+    }
+}
+
+/// The file's stamp, or None if it cannot be read
+fn stamp(path: &Path) -> Option<Stamp> {
+    let metadata = fs::metadata(path).ok()?;
+    Some(Stamp {
+        modified: metadata.modified().ok()?,
+        len: metadata.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_poll_reports_each_change_once() {
+        let path = std::env::temp_dir().join(format!("pyrust-watch-{}.py", std::process::id()));
+        fs::write(&path, "print(1)\n").unwrap();
+        let mut watcher = FileWatcher::new(&path).with_debounce(Duration::from_millis(10));
+        assert!(!watcher.poll());
+
+        // Longer, so the change shows even on coarse timestamps
+        fs::write(&path, "print(12)\n").unwrap();
+        assert!(watcher.poll());
+        assert!(!watcher.poll());
+
+        // A file that is gone is waited for
+        fs::remove_file(&path).unwrap();
+        assert!(!watcher.poll());
+        fs::write(&path, "print(123)\n").unwrap();
+        assert!(watcher.poll());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -4,7 +4,8 @@
 //! during the merge of issue/error-module and issue/ast-module branches.
 
 use pyrust::ast::{BinaryOperator, Expression, Program, Statement};
-use pyrust::error::{LexError, ParseError, PyRustError, RuntimeError};
+use pyrust::error::{ExceptionKind, LexError, ParseError, PyRustError, RuntimeError};
+use pyrust::error_code::ErrorCode;
 
 /// CONFLICT RESOLUTION TEST: src/lib.rs
 /// Verifies that both `pub mod error;` and `pub mod ast;` exports work together
@@ -14,9 +15,11 @@ fn test_lib_rs_conflict_resolution() {
 
     // From error module (first branch merged)
     let lex_error = LexError {
+        code: ErrorCode::UnexpectedCharacter,
         message: "test".to_string(),
         line: 1,
         column: 1,
+        end_column: 2,
     };
     let _: PyRustError = lex_error.into();
 
@@ -29,9 +32,11 @@ fn test_lib_rs_conflict_resolution() {
 
     // Both modules should be usable together without conflicts
     let parse_err = ParseError {
+        code: ErrorCode::ExpectedExpression,
         message: "AST parsing failed".to_string(),
         line: 1,
         column: 1,
+        end_column: 2,
         found_token: "EOF".to_string(),
         expected_tokens: vec!["expression".to_string()],
     };
@@ -70,7 +75,10 @@ fn test_cargo_toml_conflict_resolution() {
     use pyrust::error::CompileError;
 
     let _compile_err = CompileError {
+        code: ErrorCode::CompileFailed,
         message: "Testing Cargo.toml merge".to_string(),
+        location: None,
+        end: None,
     };
 
     let _unary_op = UnaryOperator::Neg;
@@ -100,17 +108,20 @@ fn test_cross_module_type_integration() {
 
     // Create errors that reference the AST
     let parse_error = ParseError {
+        code: ErrorCode::ExpectedExpression,
         message: "Failed to parse statement 2".to_string(),
         line: 2,
         column: 1,
+        end_column: 2,
         found_token: "undefined".to_string(),
         expected_tokens: vec!["defined_variable".to_string()],
     };
 
-    let runtime_error = RuntimeError {
-        message: "Variable 'undefined' not found in scope".to_string(),
-        instruction_index: 1,
-    };
+    let runtime_error = RuntimeError::new(
+        ExceptionKind::NameError,
+        "Variable 'undefined' not found in scope",
+        1,
+    );
 
     // Verify both types work together
     assert_eq!(program.statements.len(), 2);
@@ -135,9 +146,11 @@ fn test_lib_documentation_preserved() {
     // If both modules are accessible, the documentation merge succeeded
     let _: Expression = Expression::Integer(1);
     let _: PyRustError = LexError {
+        code: ErrorCode::UnexpectedCharacter,
         message: "test".to_string(),
         line: 1,
         column: 1,
+        end_column: 2,
     }
     .into();
 }
@@ -148,27 +161,32 @@ fn test_lib_documentation_preserved() {
 fn test_all_error_types_with_ast() {
     // LexError - would occur before AST construction
     let lex_err = LexError {
+        code: ErrorCode::UnexpectedCharacter,
         message: "Invalid character in source".to_string(),
         line: 1,
         column: 5,
+        end_column: 6,
     };
     assert!(format!("{}", PyRustError::from(lex_err)).contains("Invalid character"));
 
     // ParseError - occurs during AST construction
     let parse_err = ParseError {
+        code: ErrorCode::ExpectedExpression,
         message: "Cannot build AST node".to_string(),
         line: 2,
         column: 10,
+        end_column: 11,
         found_token: "invalid".to_string(),
         expected_tokens: vec!["expression".to_string()],
     };
     assert!(format!("{}", PyRustError::from(parse_err)).contains("Cannot build AST"));
 
     // RuntimeError - occurs during AST evaluation
-    let runtime_err = RuntimeError {
-        message: "Error evaluating AST expression".to_string(),
-        instruction_index: 5,
-    };
+    let runtime_err = RuntimeError::new(
+        ExceptionKind::RuntimeError,
+        "Error evaluating AST expression",
+        5,
+    );
     assert!(format!("{}", PyRustError::from(runtime_err)).contains("evaluating AST"));
 
     // All error types successfully integrate with AST workflow
@@ -205,9 +223,11 @@ fn test_precedence_integration_with_errors() {
 
     // Create error that might occur with precedence issues
     let parse_err = ParseError {
+        code: ErrorCode::ExpectedExpression,
         message: "Precedence error in expression".to_string(),
         line: 1,
         column: 1,
+        end_column: 2,
         found_token: "*".to_string(),
         expected_tokens: vec!["operand".to_string()],
     };
@@ -236,28 +256,33 @@ fn test_complete_integration_scenario() {
 
     // Step 2: Simulate lex error (error module)
     let lex_error = PyRustError::LexError(LexError {
+        code: ErrorCode::UnexpectedCharacter,
         message: "Unexpected '@' in source".to_string(),
         line: 1,
         column: 8,
+        end_column: 9,
     });
-    assert!(format!("{}", lex_error).contains("LexError at 1:8"));
+    assert!(format!("{}", lex_error).contains("LexError[E0001] at 1:8"));
 
     // Step 3: Simulate parse error (error module with AST context)
     let parse_error = PyRustError::ParseError(ParseError {
+        code: ErrorCode::ExpectedExpression,
         message: "Expected expression after operator".to_string(),
         line: 1,
         column: 15,
+        end_column: 16,
         found_token: ";".to_string(),
         expected_tokens: vec!["integer".to_string(), "identifier".to_string()],
     });
-    assert!(format!("{}", parse_error).contains("ParseError at 1:15"));
+    assert!(format!("{}", parse_error).contains("ParseError[E0007] at 1:15"));
 
     // Step 4: Simulate runtime error during execution (error module)
-    let runtime_error = PyRustError::RuntimeError(RuntimeError {
-        message: "Division by zero".to_string(),
-        instruction_index: 10,
-    });
-    assert!(format!("{}", runtime_error).contains("RuntimeError at instruction 10"));
+    let runtime_error = PyRustError::RuntimeError(RuntimeError::new(
+        ExceptionKind::ZeroDivisionError,
+        "Division by zero",
+        10,
+    ));
+    assert!(format!("{}", runtime_error).contains("RuntimeError[E2001] at instruction 10"));
 
     // All components work together seamlessly
 }
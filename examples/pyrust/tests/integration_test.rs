@@ -4,7 +4,8 @@
 //! after being merged into the integration branch.
 
 use pyrust::ast::{BinaryOperator, Expression, Program, Statement, UnaryOperator};
-use pyrust::error::{CompileError, LexError, ParseError, PyRustError, RuntimeError};
+use pyrust::error::{CompileError, ExceptionKind, LexError, ParseError, PyRustError, RuntimeError};
+use pyrust::error_code::ErrorCode;
 
 /// Test that error module and ast module can be imported together
 /// This tests the conflict resolution in src/lib.rs where both modules are exported
@@ -16,9 +17,11 @@ fn test_module_imports() {
 
     // Should be able to create error types
     let lex_err = LexError {
+        code: ErrorCode::UnexpectedCharacter,
         message: "test error".to_string(),
         line: 1,
         column: 1,
+        end_column: 2,
     };
     assert_eq!(lex_err.message, "test error");
 }
@@ -29,9 +32,11 @@ fn test_module_imports() {
 fn test_parse_error_with_ast_context() {
     // Simulate a parse error that would occur during AST construction
     let parse_err = ParseError {
+        code: ErrorCode::ExpectedExpression,
         message: "Expected expression after binary operator".to_string(),
         line: 1,
         column: 5,
+        end_column: 6,
         found_token: "+".to_string(),
         expected_tokens: vec!["integer".to_string(), "identifier".to_string()],
     };
@@ -40,7 +45,7 @@ fn test_parse_error_with_ast_context() {
     let display = format!("{}", pyrust_err);
 
     // Verify error displays correctly
-    assert!(display.contains("ParseError at 1:5"));
+    assert!(display.contains("ParseError[E0007] at 1:5"));
     assert!(display.contains("Expected expression after binary operator"));
     assert!(display.contains("Found: +"));
 }
@@ -57,15 +62,16 @@ fn test_runtime_error_with_ast_expression() {
     };
 
     // Create a runtime error for division by zero
-    let runtime_err = RuntimeError {
-        message: "Division by zero in binary operation".to_string(),
-        instruction_index: 5,
-    };
+    let runtime_err = RuntimeError::new(
+        ExceptionKind::ZeroDivisionError,
+        "Division by zero in binary operation",
+        5,
+    );
 
     let pyrust_err: PyRustError = runtime_err.into();
     let display = format!("{}", pyrust_err);
 
-    assert!(display.contains("RuntimeError at instruction 5"));
+    assert!(display.contains("RuntimeError[E2001] at instruction 5"));
     assert!(display.contains("Division by zero"));
 }
 
@@ -95,10 +101,11 @@ fn test_complex_ast_with_error_handling() {
     }
 
     // Create an error that might occur during evaluation
-    let err = RuntimeError {
-        message: "Division by zero in complex expression".to_string(),
-        instruction_index: 10,
-    };
+    let err = RuntimeError::new(
+        ExceptionKind::ZeroDivisionError,
+        "Division by zero in complex expression",
+        10,
+    );
 
     assert_eq!(err.message, "Division by zero in complex expression");
 }
@@ -132,7 +139,10 @@ fn test_all_operators_with_error_types() {
 
     // Test that compile errors can reference operators
     let compile_err = CompileError {
+        code: ErrorCode::CompileFailed,
         message: "Failed to compile FloorDiv operator".to_string(),
+        location: None,
+        end: None,
     };
 
     let err: PyRustError = compile_err.into();
@@ -156,10 +166,7 @@ fn test_statements_with_errors() {
     }
 
     // Create an error for undefined variable
-    let runtime_err = RuntimeError {
-        message: "Undefined variable: x".to_string(),
-        instruction_index: 0,
-    };
+    let runtime_err = RuntimeError::new(ExceptionKind::NameError, "Undefined variable: x", 0);
 
     let err: PyRustError = runtime_err.into();
     assert!(format!("{}", err).contains("Undefined variable: x"));
@@ -202,10 +209,11 @@ fn test_program_with_error_scenarios() {
     assert_eq!(program.statements.len(), 3);
 
     // Simulate runtime error during execution
-    let err = RuntimeError {
-        message: "Division by zero at statement 2".to_string(),
-        instruction_index: 15,
-    };
+    let err = RuntimeError::new(
+        ExceptionKind::ZeroDivisionError,
+        "Division by zero at statement 2",
+        15,
+    );
 
     let pyrust_err: PyRustError = err.into();
     assert!(format!("{}", pyrust_err).contains("Division by zero"));
@@ -234,9 +242,11 @@ fn test_unary_operators_with_errors() {
 #[test]
 fn test_error_trait_implementations() {
     let lex_err = LexError {
+        code: ErrorCode::UnexpectedCharacter,
         message: "Invalid token".to_string(),
         line: 1,
         column: 1,
+        end_column: 2,
     };
 
     // Test From trait
@@ -292,9 +302,11 @@ fn test_cross_module_traits() {
 
     // Test error cloning
     let err = LexError {
+        code: ErrorCode::UnexpectedCharacter,
         message: "test".to_string(),
         line: 1,
         column: 1,
+        end_column: 2,
     };
     let cloned_err = err.clone();
     assert_eq!(err, cloned_err);
@@ -309,18 +321,22 @@ fn test_cross_module_traits() {
 fn test_error_location_information() {
     // Test LexError location
     let lex_err = LexError {
+        code: ErrorCode::UnexpectedCharacter,
         message: "Unexpected character '@'".to_string(),
         line: 5,
         column: 10,
+        end_column: 11,
     };
     assert_eq!(lex_err.line, 5);
     assert_eq!(lex_err.column, 10);
 
     // Test ParseError location
     let parse_err = ParseError {
+        code: ErrorCode::ExpectedExpression,
         message: "Expected expression".to_string(),
         line: 3,
         column: 15,
+        end_column: 16,
         found_token: "EOF".to_string(),
         expected_tokens: vec!["integer".to_string()],
     };
@@ -328,10 +344,7 @@ fn test_error_location_information() {
     assert_eq!(parse_err.column, 15);
 
     // Test RuntimeError location
-    let runtime_err = RuntimeError {
-        message: "Stack overflow".to_string(),
-        instruction_index: 42,
-    };
+    let runtime_err = RuntimeError::new(ExceptionKind::RecursionError, "Stack overflow", 42);
     assert_eq!(runtime_err.instruction_index, 42);
 }
 
@@ -340,9 +353,11 @@ fn test_error_location_information() {
 fn test_error_message_quality() {
     // Test ParseError with multiple expected tokens
     let parse_err = ParseError {
+        code: ErrorCode::ExpectedToken,
         message: "Unexpected token".to_string(),
         line: 1,
         column: 5,
+        end_column: 6,
         found_token: "=".to_string(),
         expected_tokens: vec![
             "integer".to_string(),
@@ -352,7 +367,7 @@ fn test_error_message_quality() {
     };
 
     let display = format!("{}", PyRustError::from(parse_err));
-    assert!(display.contains("ParseError at 1:5"));
+    assert!(display.contains("ParseError[E0006] at 1:5"));
     assert!(display.contains("Found: ="));
     assert!(display.contains("Expected: integer | identifier | ("));
 }
@@ -361,9 +376,11 @@ fn test_error_message_quality() {
 #[test]
 fn test_error_conversion_preserves_data() {
     let original = ParseError {
+        code: ErrorCode::ExpectedExpression,
         message: "Test message".to_string(),
         line: 10,
         column: 20,
+        end_column: 21,
         found_token: "test_token".to_string(),
         expected_tokens: vec!["expected1".to_string(), "expected2".to_string()],
     };
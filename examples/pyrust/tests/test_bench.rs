@@ -0,0 +1,53 @@
+//! Integration tests for `pyrust --bench <runs>`
+
+use std::process::{Command, Output};
+
+const BINARY_PATH: &str = "./target/release/pyrust";
+
+fn pyrust(args: &[&str]) -> Output {
+    Command::new(BINARY_PATH)
+        .args(args)
+        .output()
+        .expect("Failed to run pyrust")
+}
+
+#[test]
+fn test_bench_reports_each_phase() {
+    for (args, heading) in [
+        (&["--bench", "25"][..], "25 runs, compiled every run\n"),
+        (
+            &["--bench", "25", "--warm"][..],
+            "25 runs, cached before timing\n",
+        ),
+    ] {
+        let mut args = args.to_vec();
+        args.extend(["-c", "print(6 * 7)"]);
+        let output = pyrust(&args);
+        assert!(output.status.success(), "{:?}", output);
+
+        // The program's output is not shown, only the table
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        let lines: Vec<&str> = stdout.lines().collect();
+        assert!(stdout.starts_with(heading), "{}", stdout);
+        assert_eq!(lines.len(), 5, "{}", stdout);
+        assert_eq!(
+            lines[1].split_whitespace().collect::<Vec<_>>(),
+            ["min", "median", "p95"]
+        );
+        for (line, phase) in lines[2..].iter().zip(["compile", "execute", "total"]) {
+            let columns: Vec<&str> = line.split_whitespace().collect();
+            assert_eq!(columns[0], phase);
+            assert_eq!(columns.len(), 4, "{}", line);
+        }
+    }
+}
+
+#[test]
+fn test_bench_rejects_bad_input() {
+    let output = pyrust(&["--bench", "0", "-c", "1"]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Invalid number of runs"));
+
+    let output = pyrust(&["--bench", "3", "-c", "1 / 0"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("ZeroDivisionError"));
+}
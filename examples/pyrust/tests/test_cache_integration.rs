@@ -426,6 +426,18 @@ fn test_cache_whitespace_sensitivity() {
     assert_eq!(stats.size, 2);
 }
 
+#[test]
+fn test_cached_errors_point_into_the_source_that_ran() {
+    clear_thread_local_cache();
+
+    let first = execute_python("x = 1/0").unwrap_err();
+    let second = execute_python("x = 1 / 0").unwrap_err();
+    assert!(first.to_string().contains("column 5"), "{}", first);
+    assert!(second
+        .render(None)
+        .ends_with("\n    x = 1 / 0\n        ^^^^^"));
+}
+
 #[test]
 fn test_cache_unicode_code() {
     clear_thread_local_cache();
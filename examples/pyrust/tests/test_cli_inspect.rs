@@ -0,0 +1,186 @@
+//! Integration tests for the CLI flags that inspect or check a program without
+//! running it
+
+use std::process::{Command, Output};
+
+const BINARY_PATH: &str = "./target/release/pyrust";
+
+fn pyrust(args: &[&str]) -> Output {
+    Command::new(BINARY_PATH)
+        .args(args)
+        .output()
+        .expect("Failed to run pyrust")
+}
+
+#[test]
+fn test_ast_prints_tree() {
+    let output = pyrust(&["--ast", "-c", "x = 1 - 2 - 3\nprint(x)"]);
+    assert!(output.status.success());
+    // Subtraction groups to the left
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "Program\n  Assignment x\n    BinaryOp -\n      BinaryOp -\n        Integer 1\n        Integer 2\n      Integer 3\n  Print\n    Variable x\n"
+    );
+}
+
+#[test]
+fn test_ast_reads_file() {
+    let path = std::env::temp_dir().join(format!("pyrust-ast-{}.py", std::process::id()));
+    std::fs::write(&path, "print(7)\n").unwrap();
+    let output = pyrust(&["--ast", path.to_str().unwrap()]);
+    std::fs::remove_file(&path).unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "Program\n  Print\n    Integer 7\n"
+    );
+}
+
+#[test]
+fn test_ast_reports_parse_errors() {
+    let output = pyrust(&["--ast", "-c", "1 +"]);
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("ParseError"));
+
+    let output = pyrust(&["--ast"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Usage: pyrust --ast"));
+}
+
+#[test]
+fn test_dis_prints_bytecode_without_running() {
+    let output = pyrust(&["--dis", "-c", "x = 6\nprint(x * 7)"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("Constants:\n"), "{}", stdout);
+    assert!(stdout.contains("  #0    x (id 0)\n"), "{}", stdout);
+    assert!(stdout.contains("StoreVar x, r0"), "{}", stdout);
+    assert!(stdout.contains("Print r"), "{}", stdout);
+    assert!(stdout.ends_with("Halt\n"), "{}", stdout);
+    // Nothing was printed by the program itself
+    assert!(!stdout.contains("42"), "{}", stdout);
+}
+
+#[test]
+fn test_dis_lists_functions() {
+    let path = std::env::temp_dir().join(format!("pyrust-dis-{}.py", std::process::id()));
+    std::fs::write(
+        &path,
+        "def double(n):\n    return n + n\nprint(double(4))\n",
+    )
+    .unwrap();
+    let output = pyrust(&["--dis", path.to_str().unwrap()]);
+    std::fs::remove_file(&path).unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("DefineFunction double/1, chunk 0"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("Function double/1 (chunk 0,"), "{}", stdout);
+    assert!(stdout.contains("Call r"), "{}", stdout);
+}
+
+#[test]
+fn test_dis_reports_errors() {
+    let output = pyrust(&["--dis", "-c", "print(1 +)"]);
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("ParseError"));
+
+    let output = pyrust(&["--dis", "-c"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Usage: pyrust --dis"));
+}
+
+#[test]
+fn test_check_reports_warnings_without_running() {
+    let output = pyrust(&["--check", "-c", "def f(n):\n    return n\nprint(7)"]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "<string>: Warning[W0004] at 1:1: function 'f' is never called\n"
+    );
+
+    let output = pyrust(&[
+        "--check",
+        "--deny-warnings",
+        "-c",
+        "def f(n):\n    return n\nprint(7)",
+    ]);
+    assert!(!output.status.success());
+
+    // A runtime error is not found by checking
+    let output = pyrust(&["--check", "-c", "print(g(1))"]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn test_check_reports_every_lexical_error() {
+    let output = pyrust(&["--check", "-c", "x = 1 $ 2\nprint(x ? 3)"]);
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<_> = stdout.lines().collect();
+    assert_eq!(lines.len(), 2, "{}", stdout);
+    assert!(
+        lines[0].starts_with("<string>: LexError[E0001] at 1:7"),
+        "{}",
+        stdout
+    );
+    assert!(
+        lines[1].starts_with("<string>: LexError[E0001] at 2:9"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn test_check_reports_every_file() {
+    let dir = std::env::temp_dir().join(format!("pyrust-check-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let good = dir.join("good.py");
+    let bad = dir.join("bad.py");
+    let zero = dir.join("zero.py");
+    std::fs::write(&good, "print(1)\n").unwrap();
+    std::fs::write(&bad, "x = (1\n").unwrap();
+    std::fs::write(&zero, "print(1 / 0)\nprint(2 % 0)\n").unwrap();
+    let paths: Vec<&str> = [&good, &bad, &zero]
+        .iter()
+        .map(|path| path.to_str().unwrap())
+        .collect();
+
+    let output = pyrust(&["--check", paths[0], paths[1], paths[2]]);
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!stdout.contains(paths[0]), "{}", stdout);
+    assert!(
+        stdout.contains(&format!("{}: ParseError[", paths[1])),
+        "{}",
+        stdout
+    );
+    assert_eq!(
+        stdout.matches("division by zero will always raise").count(),
+        2,
+        "{}",
+        stdout
+    );
+
+    // The first denied division is the error; the rest are still listed
+    let output = pyrust(&["--check", "--deny-division-by-zero", paths[2]]);
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!(
+            "{0}: CompileError[W0005] at 1:11: Division by zero\n{0}: Warning[W0005] at 2:11: division by zero will always raise\n",
+            paths[2]
+        )
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let output = pyrust(&["--check"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Usage: pyrust --check"));
+}
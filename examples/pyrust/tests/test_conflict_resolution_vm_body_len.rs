@@ -12,7 +12,7 @@
 //! - Function calls and execution still work correctly
 //! - Multi-line formatting in function definition blocks
 
-use pyrust::bytecode::{Bytecode, CompilerMetadata, Instruction};
+use pyrust::bytecode::{Bytecode, CompilerMetadata, FunctionChunk, Instruction};
 use pyrust::execute_python;
 use pyrust::vm::VM;
 
@@ -144,68 +144,71 @@ f()  # Second definition
 #[test]
 fn test_vm_bytecode_define_function_pattern_match() {
     // Test direct bytecode execution with DefineFunction instruction
-    // This validates the body_len: _ pattern matching in the conflict resolution
+    // The body lives in its own chunk, so its length is the chunk's length
 
     let var_names = vec!["test_func".to_string()];
     let instructions = vec![
-        Instruction::DefineFunction {
-            name_index: 0,
-            param_count: 0,
-            body_start: 2,
-            body_len: 1, // This value should be ignored by VM
-            max_register_used: 0,
-        },
+        Instruction::DefineFunction { chunk_index: 0 },
         Instruction::Halt,
-        Instruction::Return {
+    ];
+    let functions = vec![FunctionChunk {
+        name_index: 0,
+        param_count: 0,
+        max_register_used: 0,
+        instructions: vec![Instruction::Return {
             has_value: false,
             src_reg: None,
-        },
-    ];
+        }],
+        line_table: Vec::new(),
+    }];
 
     let bytecode = Bytecode {
         instructions,
+        functions,
         constants: vec![],
         var_names,
         var_ids: vec![0],
         metadata: CompilerMetadata {
             max_register_used: 0,
         },
+        line_table: Vec::new(),
     };
 
     let mut vm = VM::new();
     let result = vm.execute(&bytecode);
 
-    // Should succeed even though body_len is provided but ignored
-    assert!(result.is_ok(), "DefineFunction should ignore body_len");
+    assert!(result.is_ok(), "DefineFunction should register the chunk");
 }
 
 #[test]
-fn test_vm_function_call_with_body_len_ignored() {
-    // Test that function calls work when body_len is in bytecode but ignored
+fn test_vm_registers_chunk_with_params() {
+    // Test that a function chunk with parameters registers cleanly
     let var_names = vec!["add".to_string()];
     let instructions = vec![
-        Instruction::DefineFunction {
-            name_index: 0,
-            param_count: 2,
-            body_start: 2,
-            body_len: 1, // Ignored by VM
-            max_register_used: 1,
-        },
+        Instruction::DefineFunction { chunk_index: 0 },
         Instruction::Halt,
-        Instruction::Return {
+    ];
+    let functions = vec![FunctionChunk {
+        name_index: 0,
+        param_count: 2,
+        max_register_used: 1,
+        instructions: vec![Instruction::Return {
             has_value: false,
             src_reg: None,
-        },
-    ];
+        }],
+        line_table: Vec::new(),
+    }];
 
     let bytecode = Bytecode {
         instructions,
+        functions,
         constants: vec![],
         var_names,
         var_ids: vec![0],
         metadata: CompilerMetadata {
             max_register_used: 1,
         },
+        line_table: Vec::new(),
     };
 
     let mut vm = VM::new();
@@ -265,24 +268,27 @@ fn test_vm_function_name_index_validation() {
     // Test that name_index validation still works without body_len
     let var_names = vec![];
     let instructions = vec![
-        Instruction::DefineFunction {
-            name_index: 99, // Invalid index
-            param_count: 0,
-            body_start: 2,
-            body_len: 1,
-            max_register_used: 0,
-        },
+        Instruction::DefineFunction { chunk_index: 0 },
         Instruction::Halt,
     ];
+    let functions = vec![FunctionChunk {
+        name_index: 99, // Invalid index
+        param_count: 0,
+        max_register_used: 0,
+        instructions: Vec::new(),
+        line_table: Vec::new(),
+    }];
 
     let bytecode = Bytecode {
         instructions,
+        functions,
         constants: vec![],
         var_names,
         var_ids: vec![],
         metadata: CompilerMetadata {
             max_register_used: 0,
         },
+        line_table: Vec::new(),
     };
 
     let mut vm = VM::new();
@@ -0,0 +1,88 @@
+//! Integration tests for systemd socket activation
+//!
+//! The test plays systemd: it binds the socket, then starts `pyrust
+//! --daemon` with the socket as fd 3 and `LISTEN_PID`/`LISTEN_FDS` naming
+//! the daemon, as `systemd-socket-activate` does.
+
+#![cfg(unix)]
+
+use std::fs;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixListener;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const BINARY_PATH: &str = "./target/release/pyrust";
+const SOCKET_PATH: &str = "/tmp/pyrust-sdtest.sock";
+const PID_FILE_PATH: &str = "/tmp/pyrust-sdtest.pid";
+
+fn pyrust(args: &[&str]) -> Output {
+    Command::new(BINARY_PATH)
+        .args(["--name", "sdtest"])
+        .args(args)
+        .output()
+        .expect("Failed to run pyrust")
+}
+
+#[test]
+fn test_daemon_serves_inherited_socket() {
+    let _ = fs::remove_file(SOCKET_PATH);
+    let _ = fs::remove_file(PID_FILE_PATH);
+    let listener = UnixListener::bind(SOCKET_PATH).unwrap();
+    let fd = listener.as_raw_fd();
+
+    // The shell's PID is the daemon's once it execs
+    let mut command = Command::new("sh");
+    command
+        .args([
+            "-c",
+            "LISTEN_PID=$$ LISTEN_FDS=1 exec \"$0\" --daemon --name sdtest",
+            BINARY_PATH,
+        ])
+        .stdout(Stdio::null());
+    unsafe {
+        command.pre_exec(move || {
+            // dup2 onto itself would leave close-on-exec set
+            let moved = if fd == 3 {
+                libc::fcntl(fd, libc::F_SETFD, 0)
+            } else {
+                libc::dup2(fd, 3)
+            };
+            if moved < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let mut daemon = command.spawn().expect("Failed to start daemon");
+
+    // It serves in the foreground, so the PID file is its own
+    let started = Instant::now();
+    while !Path::new(PID_FILE_PATH).exists() {
+        assert!(started.elapsed() < Duration::from_secs(2), "Daemon not up");
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(
+        fs::read_to_string(PID_FILE_PATH).unwrap().trim(),
+        daemon.id().to_string()
+    );
+
+    let output = pyrust(&["-c", "print(7)"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "7\n");
+    let status = pyrust(&["--daemon-status"]);
+    assert!(String::from_utf8_lossy(&status.stdout).contains("Requests: 1 "));
+
+    // Stopped the way systemd stops it, it leaves systemd's socket alone
+    unsafe {
+        libc::kill(daemon.id() as i32, libc::SIGTERM);
+    }
+    assert!(daemon.wait().unwrap().success());
+    assert!(Path::new(SOCKET_PATH).exists(), "Socket was removed");
+    assert!(!Path::new(PID_FILE_PATH).exists(), "PID file was left");
+
+    drop(listener);
+    fs::remove_file(SOCKET_PATH).unwrap();
+}
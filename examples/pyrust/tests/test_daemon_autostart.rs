@@ -0,0 +1,60 @@
+//! Integration tests for starting the daemon on first use
+//!
+//! With PYRUST_DAEMON_AUTOSTART=1, running a script when no daemon is up
+//! starts one in the background, which later runs then reuse.
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+const BINARY_PATH: &str = "./target/release/pyrust";
+const SOCKET_PATH: &str = "/tmp/pyrust.sock";
+const PID_FILE_PATH: &str = "/tmp/pyrust.pid";
+
+/// Helper to cleanup daemon artifacts
+fn cleanup_daemon() {
+    let _ = Command::new(BINARY_PATH).arg("--stop-daemon").output();
+    let _ = fs::remove_file(SOCKET_PATH);
+    let _ = fs::remove_file(PID_FILE_PATH);
+    thread::sleep(Duration::from_millis(100));
+}
+
+fn run(code: &str, autostart: bool) -> String {
+    let mut command = Command::new(BINARY_PATH);
+    command.args(["-c", code]);
+    if autostart {
+        command.env("PYRUST_DAEMON_AUTOSTART", "1");
+    } else {
+        command.env_remove("PYRUST_DAEMON_AUTOSTART");
+    }
+    let output = command.output().expect("Failed to execute code");
+    assert!(output.status.success(), "Execution failed");
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn test_autostart_starts_daemon_on_first_use() {
+    cleanup_daemon();
+
+    // Without the opt-in, nothing is started
+    assert_eq!(run("2+3", false), "5");
+    assert!(!Path::new(SOCKET_PATH).exists(), "Daemon should not start");
+
+    // The first run starts the daemon and is served by it
+    assert_eq!(run("print(7)", true), "7\n");
+    assert!(Path::new(SOCKET_PATH).exists(), "Daemon was not started");
+    let pid = fs::read_to_string(PID_FILE_PATH).expect("Failed to read PID");
+
+    // Later runs reuse it
+    assert_eq!(run("10*5", true), "50");
+    assert_eq!(fs::read_to_string(PID_FILE_PATH).unwrap(), pid);
+    let status = Command::new(BINARY_PATH)
+        .arg("--daemon-status")
+        .output()
+        .expect("Failed to query status");
+    assert!(String::from_utf8_lossy(&status.stdout).contains("Requests: 2 "));
+
+    cleanup_daemon();
+}
@@ -0,0 +1,69 @@
+//! Integration tests for administering the daemon's cache from the CLI
+//!
+//! `--clear-cache` must reach the running daemon, not just the caches of
+//! the process it runs in.
+
+use std::fs;
+use std::process::{Command, Output};
+use std::thread;
+use std::time::Duration;
+
+const BINARY_PATH: &str = "./target/release/pyrust";
+const SOCKET_PATH: &str = "/tmp/pyrust-cacheadmin.sock";
+const PID_FILE_PATH: &str = "/tmp/pyrust-cacheadmin.pid";
+
+fn pyrust(args: &[&str]) -> Output {
+    Command::new(BINARY_PATH)
+        .args(["--name", "cacheadmin"])
+        .args(args)
+        .output()
+        .expect("Failed to run pyrust")
+}
+
+fn cleanup() {
+    pyrust(&["--stop-daemon"]);
+    let _ = fs::remove_file(SOCKET_PATH);
+    let _ = fs::remove_file(PID_FILE_PATH);
+    thread::sleep(Duration::from_millis(100));
+}
+
+#[test]
+fn test_clear_cache_clears_daemon_cache() {
+    cleanup();
+    assert!(pyrust(&["--daemon"]).status.success());
+    assert!(pyrust(&["-c", "1 + 1"]).status.success());
+    assert!(pyrust(&["-c", "2 + 2"]).status.success());
+
+    let output = pyrust(&["--cache-stats"]);
+    assert!(output.status.success());
+    let stats = String::from_utf8_lossy(&output.stdout);
+    assert!(stats.contains("\nsize 2\n"), "{}", stats);
+    assert!(stats.contains("\nmisses 2\n"), "{}", stats);
+
+    let output = pyrust(&["--clear-cache"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Cleared 2 programs from the daemon's cache"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("Cache cleared successfully"));
+
+    let stats = String::from_utf8_lossy(&pyrust(&["--cache-stats"]).stdout).to_string();
+    assert!(stats.contains("\nsize 0\n"), "{}", stats);
+
+    cleanup();
+}
+
+#[test]
+fn test_cache_stats_without_daemon_fails() {
+    let output = Command::new(BINARY_PATH)
+        .args(["--name", "cacheadmin-none", "--cache-stats"])
+        .output()
+        .expect("Failed to run pyrust");
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("Cannot read the daemon's cache stats")
+    );
+}
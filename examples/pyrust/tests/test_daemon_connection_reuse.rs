@@ -0,0 +1,58 @@
+//! Integration tests for reusing and pipelining daemon connections
+//!
+//! The daemon here is a named instance so that the client's environment can
+//! point at it without touching the default daemon.
+
+#![cfg(unix)]
+
+use pyrust::daemon::{instance_paths, DaemonServer, DAEMON_NAME_ENV};
+use pyrust::daemon_client::{DaemonClient, DaemonClientError, DaemonConnection, POOL_SIZE_ENV};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[test]
+fn test_pooled_and_pipelined_requests() {
+    std::env::set_var(DAEMON_NAME_ENV, "pooltest");
+    std::env::set_var(POOL_SIZE_ENV, "1");
+    let (socket_path, pid_path) = instance_paths(Some("pooltest"));
+    let _ = std::fs::remove_file(&socket_path);
+    let daemon = DaemonServer::with_paths(socket_path.clone(), pid_path).unwrap();
+    let server = thread::spawn(move || daemon.run());
+    let started = Instant::now();
+    while !Path::new(&socket_path).exists() {
+        assert!(started.elapsed() < Duration::from_secs(2), "Daemon not up");
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    // The sync daemon serves one connection at a time, so these only all
+    // get answered if they share the pooled one
+    for _ in 0..3 {
+        assert_eq!(DaemonClient::execute_or_fallback("2+3").unwrap(), "5");
+    }
+    assert_eq!(DaemonClient::stats().unwrap().requests, 3);
+
+    let mut connection = DaemonConnection::open().unwrap();
+    for code in ["1+1", "1/0", "2*3"] {
+        connection.send(code).unwrap();
+    }
+    assert_eq!(connection.receive().unwrap(), "2");
+    assert!(matches!(
+        connection.receive(),
+        Err(DaemonClientError::ExecutionError(_))
+    ));
+    assert_eq!(connection.receive().unwrap(), "6");
+    assert!(connection.receive().is_err(), "Nothing left to receive");
+    assert_eq!(connection.execute("7").unwrap(), "7");
+    drop(connection);
+    assert_eq!(DaemonClient::stats().unwrap().requests, 7);
+
+    // With pooling off, a connection is closed once done with
+    std::env::set_var(POOL_SIZE_ENV, "0");
+    drop(DaemonConnection::open().unwrap());
+    unsafe {
+        libc::kill(std::process::id() as i32, libc::SIGTERM);
+    }
+    server.join().unwrap().unwrap();
+    assert!(!Path::new(&socket_path).exists());
+}
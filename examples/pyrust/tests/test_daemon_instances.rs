@@ -0,0 +1,72 @@
+//! Integration tests for named daemon instances
+//!
+//! `--name <id>` gives a daemon its own socket and PID file, so it runs and
+//! stops independently of the default daemon and of other names.
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+use std::thread;
+use std::time::Duration;
+
+const BINARY_PATH: &str = "./target/release/pyrust";
+const SOCKET_PATH: &str = "/tmp/pyrust.sock";
+const NAMED_SOCKET_PATH: &str = "/tmp/pyrust-itest.sock";
+const NAMED_PID_FILE_PATH: &str = "/tmp/pyrust-itest.pid";
+
+fn pyrust(args: &[&str]) -> Output {
+    Command::new(BINARY_PATH)
+        .args(args)
+        .env_remove("PYRUST_DAEMON_NAME")
+        .output()
+        .expect("Failed to run pyrust")
+}
+
+/// Helper to cleanup daemon artifacts
+fn cleanup_daemons() {
+    pyrust(&["--stop-daemon"]);
+    pyrust(&["--stop-daemon", "--name", "itest"]);
+    let _ = fs::remove_file(SOCKET_PATH);
+    let _ = fs::remove_file(NAMED_SOCKET_PATH);
+    let _ = fs::remove_file(NAMED_PID_FILE_PATH);
+    thread::sleep(Duration::from_millis(100));
+}
+
+#[test]
+fn test_named_daemon_is_isolated() {
+    cleanup_daemons();
+
+    assert!(pyrust(&["--daemon", "--name", "itest"]).status.success());
+    assert!(Path::new(NAMED_SOCKET_PATH).exists());
+    assert!(Path::new(NAMED_PID_FILE_PATH).exists());
+    assert!(!Path::new(SOCKET_PATH).exists(), "Default daemon started");
+
+    // Only clients selecting the name see it
+    assert!(pyrust(&["--daemon-health", "--name", "itest"])
+        .status
+        .success());
+    assert!(!pyrust(&["--daemon-health"]).status.success());
+    let output = pyrust(&["--name", "itest", "-c", "print(7)"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "7\n");
+    let status = Command::new(BINARY_PATH)
+        .arg("--daemon-status")
+        .env("PYRUST_DAEMON_NAME", "itest")
+        .output()
+        .unwrap();
+    assert!(String::from_utf8_lossy(&status.stdout).contains("Requests: 1 "));
+
+    // The default daemon runs alongside and stops on its own
+    assert!(pyrust(&["--daemon"]).status.success());
+    assert!(pyrust(&["--stop-daemon"]).status.success());
+    assert!(Path::new(NAMED_SOCKET_PATH).exists());
+
+    assert!(pyrust(&["--stop-daemon", "--name", "itest"])
+        .status
+        .success());
+    assert!(!Path::new(NAMED_SOCKET_PATH).exists());
+
+    // Names end up in paths, so they are checked
+    assert!(!pyrust(&["--name", "../x", "-c", "1"]).status.success());
+
+    cleanup_daemons();
+}
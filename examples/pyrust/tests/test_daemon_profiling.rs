@@ -0,0 +1,68 @@
+//! Integration tests for `--profile` through the daemon
+//!
+//! With a daemon running, the profile is taken there, so a repeated run
+//! shows the warm cache. Without one, the CLI profiles directly.
+
+use std::fs;
+use std::process::{Command, Output};
+use std::thread;
+use std::time::Duration;
+
+const BINARY_PATH: &str = "./target/release/pyrust";
+const SOCKET_PATH: &str = "/tmp/pyrust-proftest.sock";
+const PID_FILE_PATH: &str = "/tmp/pyrust-proftest.pid";
+
+fn pyrust(args: &[&str]) -> Output {
+    Command::new(BINARY_PATH)
+        .args(["--name", "proftest"])
+        .args(args)
+        .output()
+        .expect("Failed to run pyrust")
+}
+
+fn cleanup() {
+    pyrust(&["--stop-daemon"]);
+    let _ = fs::remove_file(SOCKET_PATH);
+    let _ = fs::remove_file(PID_FILE_PATH);
+    thread::sleep(Duration::from_millis(100));
+}
+
+#[test]
+fn test_profile_shows_daemon_cache() {
+    cleanup();
+    let code = "x = 6\nprint(x)\nx * 7";
+
+    // No daemon: profiled directly
+    let output = pyrust(&["-c", code, "--profile"]);
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Stage Breakdown"));
+    assert!(!stderr.contains("Profiled in daemon"));
+
+    assert!(pyrust(&["--daemon"]).status.success());
+    let output = pyrust(&["-c", code, "--profile"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "6\n42");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Stage Breakdown"));
+    assert!(
+        stderr.contains("Profiled in daemon (cache miss)"),
+        "{}",
+        stderr
+    );
+
+    let output = pyrust(&["-c", code, "--profile"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Profiled in daemon (cache hit)"),
+        "{}",
+        stderr
+    );
+
+    // The JSON stays JSON; lexing was skipped for the cached program
+    let output = pyrust(&["-c", code, "--profile-json"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.trim().starts_with('{') && stderr.trim().ends_with('}'));
+    assert!(stderr.contains("\"lex_ns\": 0,"), "{}", stderr);
+
+    cleanup();
+}
@@ -0,0 +1,100 @@
+//! Integration tests for `--daemon --supervise`
+//!
+//! The daemon is killed out from under its supervisor, which must start a
+//! new one on the same socket and write a crash report.
+
+#![cfg(unix)]
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const BINARY_PATH: &str = "./target/release/pyrust";
+const SOCKET_PATH: &str = "/tmp/pyrust-suptest.sock";
+const PID_FILE_PATH: &str = "/tmp/pyrust-suptest.pid";
+const CRASH_DIR: &str = "/tmp/pyrust-suptest-crash-reports";
+
+fn pyrust(args: &[&str]) -> Output {
+    Command::new(BINARY_PATH)
+        .args(["--name", "suptest"])
+        .args(args)
+        .env("PYRUST_DAEMON_CRASH_DIR", CRASH_DIR)
+        .output()
+        .expect("Failed to run pyrust")
+}
+
+fn cleanup() {
+    pyrust(&["--stop-daemon"]);
+    let _ = fs::remove_file(SOCKET_PATH);
+    let _ = fs::remove_file(PID_FILE_PATH);
+    let _ = fs::remove_dir_all(CRASH_DIR);
+    thread::sleep(Duration::from_millis(100));
+}
+
+fn daemon_pid() -> Option<i32> {
+    fs::read_to_string(PID_FILE_PATH).ok()?.trim().parse().ok()
+}
+
+/// Whether `pid` is still running (zombies count as gone)
+fn alive(pid: i32) -> bool {
+    fs::read_to_string(format!("/proc/{}/stat", pid)).is_ok_and(|stat| {
+        stat.rsplit(") ")
+            .next()
+            .is_some_and(|rest| !rest.starts_with('Z'))
+    })
+}
+
+fn wait_until(what: &str, mut condition: impl FnMut() -> bool) {
+    let started = Instant::now();
+    while !condition() {
+        assert!(started.elapsed() < Duration::from_secs(5), "{}", what);
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[test]
+fn test_supervisor_restarts_crashed_daemon() {
+    cleanup();
+    let output = pyrust(&["--daemon", "--supervise"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let supervisor: i32 = stdout
+        .trim()
+        .strip_prefix("Daemon started with PID ")
+        .and_then(|pid| pid.parse().ok())
+        .expect("supervisor PID");
+    let first = daemon_pid().expect("daemon PID file");
+    assert_ne!(first, supervisor);
+    assert_eq!(String::from_utf8_lossy(&pyrust(&["-c", "2+3"]).stdout), "5");
+
+    unsafe {
+        libc::kill(first, libc::SIGKILL);
+    }
+    wait_until("daemon not restarted", || {
+        daemon_pid().is_some_and(|pid| pid != first) && Path::new(SOCKET_PATH).exists()
+    });
+    assert_eq!(String::from_utf8_lossy(&pyrust(&["-c", "2+3"]).stdout), "5");
+    assert!(alive(supervisor));
+
+    let reports: Vec<_> = fs::read_dir(CRASH_DIR)
+        .expect("crash report directory")
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    assert_eq!(reports.len(), 1);
+    let report = fs::read_to_string(&reports[0]).unwrap();
+    assert!(
+        report.starts_with(&format!("crash pid {} killed by signal 9", first)),
+        "{}",
+        report
+    );
+    assert!(report.contains("\nrestarts 0\n"));
+
+    // A clean stop ends the supervisor too
+    assert!(pyrust(&["--stop-daemon"]).status.success());
+    wait_until("supervisor still running", || !alive(supervisor));
+    assert_eq!(fs::read_dir(CRASH_DIR).unwrap().count(), 1);
+
+    cleanup();
+}
@@ -0,0 +1,233 @@
+//! Integration tests for how the CLI shows errors on stderr
+
+use std::process::{Command, Output};
+
+const BINARY_PATH: &str = "./target/release/pyrust";
+
+fn pyrust(args: &[&str]) -> Output {
+    Command::new(BINARY_PATH)
+        .args(["--name", "diagnostics-none"])
+        .args(args)
+        .env_remove("NO_COLOR")
+        .output()
+        .expect("Failed to run pyrust")
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).to_string()
+}
+
+#[test]
+fn test_color_always_marks_the_span() {
+    let output = pyrust(&["--color=always", "-c", "x = 1\nprint(x / 0)"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        stderr(&output),
+        "\x1b[1;31mRuntimeError[E2001] at line 2, column 7:\x1b[0m \x1b[1mZeroDivisionError: Division by zero\x1b[0m\n    print(x / 0)\n          \x1b[31m^^^^^\x1b[0m\n"
+    );
+
+    // An explicit choice wins over NO_COLOR; compile errors get the line too
+    let output = Command::new(BINARY_PATH)
+        .args(["--color", "always", "--dis", "-c", "print(1 +)"])
+        .env("NO_COLOR", "1")
+        .output()
+        .unwrap();
+    let text = stderr(&output);
+    assert!(
+        text.starts_with("\x1b[1;31mParseError[E0007] at 1:10:"),
+        "{}",
+        text
+    );
+    assert!(text.contains("\n    print(1 +)\n             \x1b[31m^\x1b[0m\n  Found: )"));
+}
+
+#[test]
+fn test_every_error_kind_underlines_its_span() {
+    for (code, marked) in [
+        ("x = 1 $ 2", "    x = 1 $ 2\n          ^\n"),
+        (
+            "x = 99999999999999999999",
+            "    x = 99999999999999999999\n        ^^^^^^^^^^^^^^^^^^^^\n",
+        ),
+        ("print(1 + def)", "    print(1 + def)\n              ^^^\n"),
+        (
+            "def f(a):\n    return a\ny = f(1, 2)",
+            "    y = f(1, 2)\n        ^^^^^^^\n",
+        ),
+        (
+            "x = 2\ny = 1 + missing * x",
+            "    y = 1 + missing * x\n            ^^^^^^^\n",
+        ),
+    ] {
+        let text = stderr(&pyrust(&["--color=always", "-c", code, "--no-daemon"]));
+        let text = text.replace("\x1b[31m", "").replace("\x1b[0m", "");
+        assert!(text.contains(marked), "{}", text);
+    }
+}
+
+#[test]
+fn test_piped_errors_stay_plain() {
+    let plain =
+        "RuntimeError[E2001] at line 1, column 7: ZeroDivisionError: Division by zero\n    print(1 / 0)\n";
+    for args in [
+        &["-c", "print(1 / 0)"][..],
+        &["--color=never", "-c", "print(1 / 0)"],
+    ] {
+        assert_eq!(stderr(&pyrust(args)), plain, "{:?}", args);
+    }
+
+    let output = pyrust(&["--color=sometimes", "-c", "1"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("--color=(auto | always | never)"));
+}
+
+#[test]
+fn test_verbosity_levels() {
+    let code = "x = 0\nprint(1 / x)";
+    let output = pyrust(&["--verbosity=short", "-c", code, "--no-daemon"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        stderr(&output),
+        "RuntimeError[E2001] at line 2, column 7: ZeroDivisionError: Division by zero\n"
+    );
+
+    let output = pyrust(&["--verbosity", "verbose", "-c", code, "--no-daemon"]);
+    assert_eq!(output.status.code(), Some(1));
+    let text = stderr(&output);
+    assert!(
+        text.starts_with(
+            "RuntimeError[E2001] at line 2, column 7: ZeroDivisionError: Division by zero\n    print(1 / x)\n          ^^^^^\n  hint: "
+        ),
+        "{}",
+        text
+    );
+    assert!(
+        text.contains("\n  bytecode:\n         2  LoadConst r0, 1\n         3  LoadVar r1, x\n    >    4  BinaryOp r0, r0 / r1\n"),
+        "{}",
+        text
+    );
+
+    // Compile errors have no bytecode
+    let output = pyrust(&["--verbosity=verbose", "-c", "print(1 +)"]);
+    assert!(stderr(&output).contains("\n  Expected: integer | identifier | '('\n  hint: "));
+    assert!(!stderr(&output).contains("bytecode:"));
+
+    let output = pyrust(&["--verbosity=loud", "-c", code]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("--verbosity=(short | normal | verbose)"));
+}
+
+#[test]
+fn test_error_format_json() {
+    let output = pyrust(&["--error-format=json", "-c", "print(1 / 0)", "--no-daemon"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        stderr(&output),
+        "{\"stage\":\"runtime\",\"severity\":\"error\",\"code\":\"E2001\",\"message\":\"ZeroDivisionError: Division by zero\",\"span\":{\"line\":1,\"column\":7,\"end_line\":1,\"end_column\":12},\"notes\":[]}\n"
+    );
+
+    // Only --verbosity=verbose adds the bytecode listing
+    let output = pyrust(&[
+        "--error-format=json",
+        "--verbosity=verbose",
+        "-c",
+        "print(1 / 0)",
+        "--no-daemon",
+    ]);
+    assert!(stderr(&output).ends_with(
+        "\"notes\":[],\"bytecode\":[\"     0  LoadConst r0, 1\",\"     1  LoadConst r1, 0\",\">    2  BinaryOp r0, r0 / r1\",\"     3  Print r0\",\"     4  Halt\"]}\n"
+    ));
+
+    // --check prints one object per diagnostic, naming the file
+    let output = pyrust(&["--error-format", "json", "--check", "-c", "x = 2 / 0"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "{\"file\":\"<string>\",\"stage\":\"compile\",\"severity\":\"warning\",\"code\":\"W0005\",\"message\":\"division by zero will always raise\",\"span\":{\"line\":1,\"column\":9,\"end_line\":1,\"end_column\":10},\"notes\":[]}\n"
+    );
+
+    let output = pyrust(&["--error-format=xml", "-c", "1"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).starts_with("Usage: pyrust --error-format="));
+}
+
+#[test]
+fn test_explain_prints_the_code() {
+    let output = pyrust(&["explain", "e1003"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.starts_with("E1003: undefined variable\n\n"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("NameError"));
+
+    // The code in a message is the one to look up
+    let output = pyrust(&["-c", "print(missing)", "--no-daemon"]);
+    assert!(stderr(&output).starts_with("RuntimeError[E1003] at "));
+
+    let output = pyrust(&["explain"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout
+        .lines()
+        .any(|line| line == "E0001  unexpected character"));
+
+    let output = pyrust(&["explain", "E9999"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).starts_with("Unknown error code: E9999"));
+}
+
+#[test]
+fn test_nested_calls_show_a_traceback() {
+    let code = "def f(d):\n    return 10 / d\ndef g(n):\n    return f(n)\nprint(g(0))";
+    let output = pyrust(&["-c", code, "--no-daemon"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        stderr(&output),
+        "RuntimeError[E2001] at line 2, column 12: ZeroDivisionError: Division by zero\n\
+         Traceback (most recent call last):\n  line 5, in <module>\n    print(g(0))\n  \
+         line 4, in g\n    return f(n)\n  line 2, in f\n    return 10 / d\n"
+    );
+
+    // JSON output lists the frames as notes
+    let output = pyrust(&["--error-format=json", "-c", code, "--no-daemon"]);
+    assert!(stderr(&output).contains(
+        "\"notes\":[\"line 5, in <module>: print(g(0))\",\"line 4, in g: return f(n)\",\"line 2, in f: return 10 / d\"]"
+    ));
+}
+
+#[test]
+fn test_warnings_as_errors() {
+    let code = "def f(x):\n    return x\n    print(x)\nprint(f(3))";
+    let output = pyrust(&["-c", code, "--warnings", "--no-daemon"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "3\n");
+    assert_eq!(
+        stderr(&output),
+        "Warning[W0007] at 3:5: code after `return` is never run\n"
+    );
+
+    let output = pyrust(&["-W", "error", "-c", code]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        stderr(&output),
+        "CompileError[W0007] at 3:5: code after `return` is never run\n"
+    );
+
+    // --check reports lexer warnings too, and fails on them with -W error
+    let mixed = "def f():\n\t return 1\nprint(f())";
+    let output = pyrust(&["--check", "-c", mixed]);
+    assert_eq!(output.status.code(), Some(0));
+    let output = pyrust(&["-W=error", "--check", "-c", mixed]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "<string>: Warning[W0006] at 2:1: indentation mixes tabs and spaces\n"
+    );
+
+    let output = pyrust(&["-W", "all", "-c", "print(1)"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stderr(&output), "Usage: pyrust -W error ...\n");
+}
@@ -0,0 +1,86 @@
+//! Integration tests for the exit status of `pyrust` after `exit(n)`
+//!
+//! A program's `exit(n)` must end the CLI with status `n` whether the daemon
+//! or the CLI process runs it, after the output printed before it.
+
+use std::fs;
+use std::process::{Command, Output};
+use std::thread;
+use std::time::Duration;
+
+const BINARY_PATH: &str = "./target/release/pyrust";
+const SOCKET_PATH: &str = "/tmp/pyrust-exitcode.sock";
+const PID_FILE_PATH: &str = "/tmp/pyrust-exitcode.pid";
+
+fn pyrust(name: &str, args: &[&str]) -> Output {
+    Command::new(BINARY_PATH)
+        .args(["--name", name])
+        .args(args)
+        .output()
+        .expect("Failed to run pyrust")
+}
+
+fn cleanup() {
+    pyrust("exitcode", &["--stop-daemon"]);
+    let _ = fs::remove_file(SOCKET_PATH);
+    let _ = fs::remove_file(PID_FILE_PATH);
+    thread::sleep(Duration::from_millis(100));
+}
+
+/// Run the same scripts against `name`, which may or may not have a daemon
+fn check_exit_codes(name: &str) {
+    let output = pyrust(name, &["-c", "print(1)\nexit(3)\nprint(2)"]);
+    assert_eq!(output.status.code(), Some(3), "{:?}", output);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n");
+    assert!(output.stderr.is_empty(), "{:?}", output);
+
+    for code in ["exit()", "exit(0)"] {
+        let output = pyrust(name, &["-c", code]);
+        assert_eq!(output.status.code(), Some(0), "{}: {:?}", code, output);
+    }
+
+    // An uncaught exception still fails with 1
+    let output = pyrust(name, &["-c", "1 / 0"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("ZeroDivisionError"));
+}
+
+#[test]
+fn test_exit_code_without_daemon() {
+    check_exit_codes("exitcode-none");
+}
+
+#[test]
+fn test_exit_code_through_daemon() {
+    cleanup();
+    assert!(pyrust("exitcode", &["--daemon"]).status.success());
+    check_exit_codes("exitcode");
+
+    // The scripts ran in the daemon, each only once
+    let stats = pyrust("exitcode", &["--cache-stats"]);
+    let stats = String::from_utf8_lossy(&stats.stdout).to_string();
+    assert!(stats.contains("\nmisses 4\n"), "{}", stats);
+    cleanup();
+}
+
+#[test]
+fn test_exit_code_of_bytecode_file() {
+    let dir = std::env::temp_dir();
+    let source = dir.join(format!("pyrust-exit-{}.py", std::process::id()));
+    let compiled = source.with_extension("pybc");
+    fs::write(&source, "exit(4)\n").unwrap();
+    let output = pyrust(
+        "exitcode-none",
+        &[
+            "--compile",
+            source.to_str().unwrap(),
+            "-o",
+            compiled.to_str().unwrap(),
+        ],
+    );
+    assert!(output.status.success(), "{:?}", output);
+    let output = pyrust("exitcode-none", &["run", compiled.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(4), "{:?}", output);
+    let _ = fs::remove_file(&source);
+    let _ = fs::remove_file(&compiled);
+}
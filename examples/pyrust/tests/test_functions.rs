@@ -826,6 +826,32 @@ def foo():
     assert!(result.is_err());
 }
 
+#[test]
+fn test_function_calling_function_defined_later() {
+    let code = r#"
+def area(w, h):
+    return double(w * h) / 2
+def double(n):
+    return n * 2
+print(area(3, 4))
+"#;
+    assert_eq!(execute_python(code).unwrap(), "12\n");
+}
+
+#[test]
+fn test_mutually_recursive_functions_compile() {
+    // Without conditionals the recursion never ends, so it must stop at the VM's depth limit
+    let code = r#"
+def ping(n):
+    return pong(n + 1)
+def pong(n):
+    return ping(n + 1)
+ping(0)
+"#;
+    let err = execute_python(code).unwrap_err();
+    assert!(err.to_string().contains("RecursionError"));
+}
+
 #[test]
 fn test_deeply_nested_arithmetic_in_function() {
     let code = r#"
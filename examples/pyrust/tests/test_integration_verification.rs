@@ -84,7 +84,7 @@ mod integration_verification_tests {
     fn test_variable_interning_integration() {
         use pyrust::execute_python;
 
-        // Use common variable names that should be pre-interned
+        // Names are interned on first use and reused afterwards
         let result = execute_python("a = 1\nb = 2\nc = 3\nx = a + b\ny = x + c\nz = x + y");
         assert!(
             result.is_ok(),
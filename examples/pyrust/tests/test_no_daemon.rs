@@ -0,0 +1,68 @@
+//! Integration tests for `pyrust --no-daemon` and `pyrust --no-cache`
+//!
+//! `--no-daemon` must run a script in the CLI process even while a daemon is
+//! up, and `--no-cache` must run it in the daemon without touching its cache.
+
+use std::fs;
+use std::process::{Command, Output};
+use std::thread;
+use std::time::Duration;
+
+const BINARY_PATH: &str = "./target/release/pyrust";
+const SOCKET_PATH: &str = "/tmp/pyrust-nodaemon.sock";
+const PID_FILE_PATH: &str = "/tmp/pyrust-nodaemon.pid";
+
+fn pyrust(args: &[&str]) -> Output {
+    Command::new(BINARY_PATH)
+        .args(["--name", "nodaemon"])
+        .args(args)
+        .output()
+        .expect("Failed to run pyrust")
+}
+
+fn cleanup() {
+    pyrust(&["--stop-daemon"]);
+    let _ = fs::remove_file(SOCKET_PATH);
+    let _ = fs::remove_file(PID_FILE_PATH);
+    thread::sleep(Duration::from_millis(100));
+}
+
+fn cache_stats() -> String {
+    String::from_utf8_lossy(&pyrust(&["--cache-stats"]).stdout).to_string()
+}
+
+#[test]
+fn test_no_daemon_and_no_cache_leave_the_cache_alone() {
+    cleanup();
+    assert!(pyrust(&["--daemon"]).status.success());
+
+    let script = "x = argc()\nprint(x)\nexit(x)";
+    for flag in ["--no-daemon", "--no-cache"] {
+        let output = pyrust(&["-c", script, flag, "--", "a", "b"]);
+        assert_eq!(output.status.code(), Some(2), "{:?}", output);
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "2\n");
+    }
+    let stats = cache_stats();
+    assert!(stats.contains("\nmisses 0\n"), "{}", stats);
+    assert!(stats.contains("hits 0\n"), "{}", stats);
+
+    // Profiling without the cache still goes through the daemon
+    let output = pyrust(&["-c", "1 + 2", "--profile", "--no-cache"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("cache"));
+    assert!(cache_stats().contains("\nmisses 0\n"));
+
+    // Without either flag the script is cached as usual
+    assert!(pyrust(&["-c", script]).status.success());
+    assert!(cache_stats().contains("\nmisses 1\n"));
+    cleanup();
+}
+
+#[test]
+fn test_no_cache_is_rejected_with_trace() {
+    let output = pyrust(&["-c", "1", "--trace", "--no-cache"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("--no-cache cannot be used with --trace")
+    );
+}
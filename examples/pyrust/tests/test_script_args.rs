@@ -0,0 +1,81 @@
+//! Integration tests for passing script arguments with `pyrust ... -- <args>`
+//!
+//! The arguments must reach the program the same way whether the daemon or
+//! the CLI process runs it.
+
+use std::fs;
+use std::process::{Command, Output};
+use std::thread;
+use std::time::Duration;
+
+const BINARY_PATH: &str = "./target/release/pyrust";
+const SOCKET_PATH: &str = "/tmp/pyrust-scriptargs.sock";
+const PID_FILE_PATH: &str = "/tmp/pyrust-scriptargs.pid";
+
+fn pyrust(name: &str, args: &[&str]) -> Output {
+    Command::new(BINARY_PATH)
+        .args(["--name", name])
+        .args(args)
+        .output()
+        .expect("Failed to run pyrust")
+}
+
+fn cleanup() {
+    pyrust("scriptargs", &["--stop-daemon"]);
+    let _ = fs::remove_file(SOCKET_PATH);
+    let _ = fs::remove_file(PID_FILE_PATH);
+    thread::sleep(Duration::from_millis(100));
+}
+
+/// Run the same scripts against `name`, which may or may not have a daemon
+fn check_arguments(name: &str) {
+    let output = pyrust(
+        name,
+        &[
+            "-c",
+            "print(argc())\nargv(0) * argv(-1)",
+            "--",
+            "6",
+            "--name",
+            "7",
+        ],
+    );
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "3\n42");
+
+    let path = std::env::temp_dir().join(format!("pyrust-args-{}-{}.py", name, std::process::id()));
+    fs::write(&path, "print(argv(0) + 1)\n").unwrap();
+    let output = pyrust(name, &[path.to_str().unwrap(), "--", "41"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "42\n");
+    let output = pyrust(name, &[path.to_str().unwrap(), "--", "x"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("ValueError"));
+    let output = pyrust(name, &[path.to_str().unwrap()]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("IndexError"));
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_script_arguments_without_daemon() {
+    check_arguments("scriptargs-none");
+}
+
+#[test]
+fn test_script_arguments_through_daemon() {
+    cleanup();
+    assert!(pyrust("scriptargs", &["--daemon"]).status.success());
+    check_arguments("scriptargs");
+
+    // The scripts ran in the daemon
+    let stats = pyrust("scriptargs", &["--cache-stats"]);
+    let stats = String::from_utf8_lossy(&stats.stdout).to_string();
+    assert!(stats.contains("\nmisses 2\n"), "{}", stats);
+    cleanup();
+}
+
+#[test]
+fn test_script_arguments_rejected_with_profile() {
+    let output = pyrust("scriptargs-none", &["-c", "argc()", "--profile", "--", "1"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--profile"));
+}
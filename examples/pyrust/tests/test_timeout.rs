@@ -0,0 +1,87 @@
+//! Integration tests for `pyrust --timeout <duration>`
+//!
+//! A script still running when its time runs out must be stopped with exit
+//! status 124, whether the daemon or the CLI process runs it.
+
+use std::fs;
+use std::process::{Command, Output};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const BINARY_PATH: &str = "./target/release/pyrust";
+const SOCKET_PATH: &str = "/tmp/pyrust-timeout.sock";
+const PID_FILE_PATH: &str = "/tmp/pyrust-timeout.pid";
+
+fn pyrust(name: &str, args: &[&str]) -> Output {
+    Command::new(BINARY_PATH)
+        .args(["--name", name])
+        .args(args)
+        .output()
+        .expect("Failed to run pyrust")
+}
+
+fn cleanup() {
+    pyrust("timeout", &["--stop-daemon"]);
+    let _ = fs::remove_file(SOCKET_PATH);
+    let _ = fs::remove_file(PID_FILE_PATH);
+    thread::sleep(Duration::from_millis(100));
+}
+
+/// A program that prints 1, then runs for far longer than any test waits
+fn slow_program() -> String {
+    let mut source = "print(1)\ndef f40():\n    return 1\n".to_string();
+    for i in (0..40).rev() {
+        source.push_str(&format!(
+            "def f{}():\n    return f{}() + f{}()\n",
+            i,
+            i + 1,
+            i + 1
+        ));
+    }
+    source.push_str("f0()\n");
+    source
+}
+
+/// Run the same scripts against `name`, which may or may not have a daemon
+fn check_timeout(name: &str) {
+    let started = Instant::now();
+    let output = pyrust(name, &["--timeout", "200ms", "-c", &slow_program()]);
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert_eq!(output.status.code(), Some(124), "{:?}", output);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("imed out after 0.2s"));
+
+    // A script that finishes in time is unaffected
+    let output = pyrust(name, &["--timeout", "5s", "-c", "print(argc())", "--", "x"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n");
+}
+
+#[test]
+fn test_timeout_without_daemon() {
+    check_timeout("timeout-none");
+}
+
+#[test]
+fn test_timeout_through_daemon() {
+    cleanup();
+    assert!(pyrust("timeout", &["--daemon"]).status.success());
+    check_timeout("timeout");
+
+    // Both scripts ran in the daemon, the slow one only once
+    let stats = pyrust("timeout", &["--cache-stats"]);
+    let stats = String::from_utf8_lossy(&stats.stdout).to_string();
+    assert!(stats.contains("\nmisses 2\n"), "{}", stats);
+    cleanup();
+}
+
+#[test]
+fn test_timeout_rejects_invalid_durations() {
+    for timeout in ["soon", "-1s", "1h"] {
+        let output = pyrust("timeout-none", &["--timeout", timeout, "-c", "1"]);
+        assert_eq!(output.status.code(), Some(1), "{}", timeout);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("Invalid timeout"));
+    }
+    let output = pyrust("timeout-none", &["--timeout", "1s", "--trace", "-c", "1"]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--timeout"));
+}
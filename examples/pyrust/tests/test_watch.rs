@@ -0,0 +1,75 @@
+//! Integration tests for `pyrust --watch`
+
+use std::fs;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const BINARY_PATH: &str = "./target/release/pyrust";
+
+/// Wait until `path` holds at least `runs` run separators
+fn wait_for_runs(path: &std::path::Path, runs: usize) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while fs::read_to_string(path)
+        .unwrap_or_default()
+        .matches("--- run ")
+        .count()
+        < runs
+    {
+        assert!(Instant::now() < deadline, "run {} never started", runs);
+        thread::sleep(Duration::from_millis(20));
+    }
+    // Let the run finish
+    thread::sleep(Duration::from_millis(100));
+}
+
+#[test]
+fn test_watch_reruns_script_on_change() {
+    let dir = std::env::temp_dir().join(format!("pyrust-watch-cli-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("script.py");
+    let stdout_path = dir.join("stdout");
+    let stderr_path = dir.join("stderr");
+    fs::write(&script, "print(1)\n2\n").unwrap();
+
+    let mut child = Command::new(BINARY_PATH)
+        .arg("--watch")
+        .arg(&script)
+        .stdout(Stdio::from(fs::File::create(&stdout_path).unwrap()))
+        .stderr(Stdio::from(fs::File::create(&stderr_path).unwrap()))
+        .spawn()
+        .expect("Failed to run pyrust");
+
+    wait_for_runs(&stderr_path, 1);
+    // An error is reported, and the watch goes on
+    fs::write(&script, "print(10)\n1 / 0\n").unwrap();
+    wait_for_runs(&stderr_path, 2);
+    fs::write(&script, "print(100)\n").unwrap();
+    wait_for_runs(&stderr_path, 3);
+
+    // Still watching
+    assert!(child.try_wait().unwrap().is_none());
+    child.kill().unwrap();
+    child.wait().unwrap();
+
+    let stdout = fs::read_to_string(&stdout_path).unwrap();
+    assert_eq!(stdout, "1\n2\n10\n100\n");
+    let stderr = fs::read_to_string(&stderr_path).unwrap();
+    assert!(stderr.starts_with("Watching "), "{}", stderr);
+    assert!(stderr.contains("--- run 3: "), "{}", stderr);
+    assert!(stderr.contains("Division by zero"), "{}", stderr);
+    assert_eq!(stderr.matches("--- ok in ").count(), 2, "{}", stderr);
+    assert_eq!(stderr.matches("--- failed in ").count(), 1, "{}", stderr);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_watch_usage() {
+    let output = Command::new(BINARY_PATH)
+        .arg("--watch")
+        .output()
+        .expect("Failed to run pyrust");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Usage: pyrust --watch <file.py>"));
+}